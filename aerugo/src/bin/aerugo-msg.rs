@@ -0,0 +1,104 @@
+//! Send commands to a running display server over its IPC socket.
+
+use std::{env, io, path::PathBuf, process};
+
+use aerugo_comp::ipc::{self, Client, Command};
+use clap::{Parser, Subcommand};
+
+/// Control a running Aerugo display server
+#[deny(missing_docs)]
+#[derive(Parser, Debug)]
+#[clap(about = "Control a running Aerugo display server", author, version)]
+struct MsgArgs {
+    /// The token granting the configure or privileged tier
+    ///
+    /// Without a token, only commands of the read tier are allowed if the configuration sets a configure token.
+    #[clap(long, value_name = "TOKEN")]
+    token: Option<String>,
+
+    #[clap(subcommand)]
+    command: MsgCommand,
+}
+
+#[deny(missing_docs)]
+#[derive(Subcommand, Debug)]
+enum MsgCommand {
+    /// Collect information for bug reports
+    #[clap(subcommand)]
+    Debug(DebugCommand),
+
+    /// List every key and pointer binding of the wm with its owner
+    ListBindings,
+
+    /// Print every workspace and binding mode change until the display server exits
+    Subscribe,
+}
+
+#[deny(missing_docs)]
+#[derive(Subcommand, Debug)]
+enum DebugCommand {
+    /// Write a debug report to attach to an issue
+    ///
+    /// The report is a tarball with the version, outputs, GPU and driver, frame statistics, recent logs and the
+    /// state of the toplevels. The home directory and user name are removed from the report.
+    Report {
+        /// Where the report is written
+        #[clap(value_name = "PATH", default_value = "aerugo-report.tar")]
+        path: PathBuf,
+    },
+}
+
+fn main() {
+    let args = MsgArgs::parse();
+
+    let Some(path) = ipc::running_socket_path() else {
+        eprintln!("error: no running display server, since neither AERUGO_SOCKET nor WAYLAND_DISPLAY is set");
+        process::exit(1);
+    };
+
+    let result = Client::connect(&path, args.token.as_deref()).and_then(|mut client| match args.command {
+        MsgCommand::Debug(DebugCommand::Report { path }) => debug_report(&mut client, path),
+        MsgCommand::ListBindings => list_bindings(&mut client),
+        MsgCommand::Subscribe => subscribe(&mut client),
+    });
+
+    if let Err(err) = result {
+        eprintln!("error: {err}");
+        process::exit(1);
+    }
+}
+
+fn debug_report(client: &mut Client, path: PathBuf) -> io::Result<()> {
+    // The display server writes the report, so the path must not depend on its working directory.
+    let path = env::current_dir()?.join(path);
+    client.request(&Command::DebugReport(path.clone()))?;
+    println!("Debug report written to {}", path.display());
+    Ok(())
+}
+
+fn list_bindings(client: &mut Client) -> io::Result<()> {
+    let reply = client.request(&Command::ListBindings)?;
+    let count = reply
+        .strip_prefix("bindings ")
+        .and_then(|count| count.parse::<usize>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, reply.clone()))?;
+
+    for _ in 0..count {
+        match client.read_line()? {
+            Some(line) => println!("{line}"),
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+fn subscribe(client: &mut Client) -> io::Result<()> {
+    client.request(&Command::Subscribe)?;
+
+    while let Some(line) = client.read_line()? {
+        println!("{}", line.strip_prefix("event ").unwrap_or(&line));
+    }
+
+    Ok(())
+}
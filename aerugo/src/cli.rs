@@ -0,0 +1,178 @@
+//! Command line argument parsing using clap.
+
+use std::path::PathBuf;
+
+use aerugo_comp::metrics::MetricsEndpoint;
+use clap::{Parser, ValueEnum};
+
+/// The Aerugo wayland compositor
+#[deny(missing_docs)]
+#[derive(Parser, Debug)]
+#[clap(
+    about = "A Wayland compositor written in Rust",
+    author,
+    version,
+    disable_version_flag = true
+)]
+pub struct AerugoArgs {
+    /// Backend selection
+    ///
+    /// By default the backend will be selected depending on the environment (`auto`).
+    ///
+    /// There are three primary backends that may be chosen:
+    ///
+    /// `kms` (or `drm`): Used when the compositor is run under a session. This backend is used when aerugo is your
+    /// primary display server.
+    ///
+    /// `windowed` (or `winit`): The compositor is run inside a window as an X11 or Wayland client. The windowed backend
+    /// is useful for testing purposes.
+    ///
+    /// `headless`: The compositor runs without any outputs or input devices, such as for running tests.
+    ///
+    /// The `x11` and `wayland` options both act like `windowed`, but allow specifying whether aerugo is run as an X11
+    /// or Wayland client.
+    #[clap(value_enum, default_value_t, short, long)]
+    pub backend: Backend,
+
+    /// Renderer selection
+    ///
+    /// This allows overriding the renderer to use at runtime. This may be useful in case of driver bugs.
+    ///
+    /// Right now only the OpenGL ES renderer is supported. In the future a Vulkan renderer will be available.
+    #[clap(value_enum, default_value_t, long)]
+    pub renderer: Renderer,
+
+    /// Serve metrics in the OpenMetrics format
+    ///
+    /// The endpoint is a TCP address such as `127.0.0.1:9100` or a Unix socket such as `unix:/run/aerugo-metrics`.
+    /// Frame times, missed frames, client counts, memory usage and input latency are only collected if an endpoint
+    /// is set.
+    #[clap(long, value_name = "ENDPOINT")]
+    pub metrics: Option<MetricsEndpoint>,
+
+    /// The configuration file
+    ///
+    /// Defaults to `$XDG_CONFIG_HOME/aerugo/config.toml`, falling back to `~/.config/aerugo/config.toml`.
+    #[clap(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Check the configuration file and exit
+    ///
+    /// Every problem is printed with its line number. The exit status is non-zero if the configuration has errors,
+    /// so scripts can check the configuration before reloading it.
+    #[clap(long)]
+    pub check_config: bool,
+
+    /// The wm component to load
+    ///
+    /// Overrides the wm set in the configuration file.
+    #[clap(long, value_name = "PATH")]
+    pub wm: Option<PathBuf>,
+
+    /// Enable debug logging for categories, separated by commas
+    ///
+    /// `RUST_LOG` may be used for finer control over logging.
+    #[clap(value_enum, long, value_name = "CATEGORIES", value_delimiter = ',')]
+    pub debug: Vec<DebugCategory>,
+
+    /// Replace the display server which is already running
+    ///
+    /// The running display server is asked to exit over its IPC socket, and this display server starts once it
+    /// has exited.
+    #[clap(long)]
+    pub replace: bool,
+
+    /// Print the version and exit
+    #[clap(short = 'V', long)]
+    pub version: bool,
+
+    /// Print the capabilities compiled into the display server along with the version
+    #[clap(long, requires = "version")]
+    pub features: bool,
+    // TODO: How should the WM spawn privileged clients?
+}
+
+/// Enum containing all possible backend selections.
+#[deny(missing_docs)]
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Automatically choose the backend depending on the environment.
+    #[default]
+    Auto,
+
+    /// Launch the compositor using kernel mode setting.
+    ///
+    /// This should be used if you launch the compositor from a TTY.
+    #[clap(alias("tty"))]
+    #[clap(alias("drm"))]
+    Kms,
+
+    /// Launch the compositor inside a window.
+    ///
+    /// This will select Wayland or X11 as appropriate.
+    #[clap(alias("winit"))]
+    Windowed,
+
+    /// Launch the compositor inside a window as a Wayland client.
+    #[clap(alias("wl"))]
+    Wayland,
+
+    /// Launch the compositor inside a window as an X11 client.
+    #[clap(alias("x"))]
+    X11,
+
+    /// Launch the compositor without any outputs or input devices.
+    Headless,
+}
+
+/// Enum containing all possible renderer backends
+#[deny(missing_docs)]
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    /// Select the most optimal, supported renderer.
+    #[default]
+    Default,
+
+    /// Use the OpenGL ES renderer.
+    #[clap(alias("egl"))]
+    #[clap(alias("gl"))]
+    #[clap(alias("gles"))]
+    Gles,
+    // #[clap(alias("vk"))]
+    // Vulkan, // TODO
+}
+
+/// Enum containing the categories of debug logging
+#[deny(missing_docs)]
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCategory {
+    /// Log every Wayland message, like `WAYLAND_DEBUG=server`.
+    Protocol,
+
+    /// Log keyboard, pointer and touch input.
+    Input,
+
+    /// Log rendering and output configuration.
+    Render,
+
+    /// Log the events sent to and the requests from the wm.
+    Wm,
+}
+
+impl DebugCategory {
+    /// The tracing directives which enable the logs of the category.
+    ///
+    /// Wayland messages are logged by `wayland-server` rather than with tracing.
+    pub fn directives(self) -> &'static [&'static str] {
+        match self {
+            Self::Protocol => &[],
+            Self::Input => &[
+                "aerugo_comp::input=trace",
+                "aerugo_comp::keyboard=trace",
+                "aerugo_comp::cursor=trace",
+            ],
+            Self::Render => &["aerugo_comp::backend=trace", "aerugo_comp::scene=trace"],
+            Self::Wm => &["aerugo_wm_runtime=trace", "aerugo_comp::wm_scene=trace"],
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::{env, io, panic, path::Path, process};
+
+use aerugo_comp::{
+    backend,
+    config::{self, Config, Severity},
+    handoff::Handoff,
+    ipc::{self, IpcAuth},
+    report::RecentLogs,
+    Configuration,
+};
+use clap::{Parser, ValueEnum};
+use cli::{Backend, DebugCategory};
+use tracing::metadata::LevelFilter;
+use tracing_subscriber::{fmt::writer::MakeWriterExt, EnvFilter, FmtSubscriber};
+
+mod cli;
+
+/// The capabilities compiled into the display server, printed by `--version --features`.
+const FEATURES: &[(&str, &str)] = &[
+    ("backends", "x11"),
+    ("renderers", "gles"),
+    ("wm runtime", "wasm components"),
+    ("image formats", "png, jpeg"),
+    ("metrics", "openmetrics"),
+];
+
+fn main() {
+    let args = cli::AerugoArgs::parse();
+
+    if args.version {
+        print_version(args.features);
+        return;
+    }
+
+    let config_path = args.config.clone().or_else(Config::default_path);
+
+    if args.check_config {
+        process::exit(check_config(config_path.as_deref()));
+    }
+
+    if let Some(wm) = &args.wm {
+        if let Err(err) = config::check_component(wm) {
+            eprintln!("error: {err}");
+            process::exit(1);
+        }
+    }
+
+    // The X11 backend is the only backend, which the windowed and automatic backends select.
+    if !matches!(args.backend, Backend::Auto | Backend::Windowed | Backend::X11) {
+        let name = args.backend.to_possible_value().expect("backend has a name");
+        eprintln!("error: the {} backend is not available yet", name.get_name());
+        process::exit(1);
+    }
+
+    if args.debug.contains(&DebugCategory::Protocol) {
+        // wayland-server logs every message of a display created while WAYLAND_DEBUG is set.
+        env::set_var("WAYLAND_DEBUG", "server");
+    }
+
+    let mut env_filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::DEBUG.into())
+        .from_env()
+        .unwrap();
+
+    for directive in args.debug.iter().flat_map(|category| category.directives()) {
+        env_filter = env_filter.add_directive(directive.parse().expect("invalid debug directive"));
+    }
+
+    // Debug reports include the recent lines of the log.
+    let recent_logs = RecentLogs::new();
+    let writer = recent_logs.clone();
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(env_filter)
+        .with_writer(io::stdout.and(move || writer.clone()))
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let mut config = config_path.as_deref().map(load_config).unwrap_or_default();
+    let mut metrics = config.metrics;
+
+    if args.metrics.is_some() {
+        metrics.endpoint = args.metrics;
+    }
+
+    if args.wm.is_some() {
+        config.wm = args.wm;
+    }
+
+    if args.replace {
+        replace();
+    }
+
+    let mut configuration = Configuration::new(backend::default_backend)
+        .with_outputs(config.outputs)
+        .with_rules(config.rules)
+        .with_placeholders(config.placeholders)
+        .with_emergency_chords(config.emergency)
+        .with_spawn_bindings(config.bindings)
+        .with_ipc_auth(IpcAuth::current_user().with_tokens(config.ipc))
+        .with_global_policy(config.policy)
+        .with_environment(config.environment)
+        .with_cursor_hiding(config.cursor_hiding)
+        .with_initial_locks(config.locks)
+        .with_key_repeat(config.key_repeat)
+        .with_recent_logs(recent_logs)
+        .with_metrics(metrics);
+
+    if let Some(wm) = config.wm {
+        configuration = configuration.with_wm(wm);
+    }
+
+    // After a restart in place, the previous process passed the listening socket in the environment.
+    if let Some(handoff) = Handoff::from_env() {
+        // SAFETY: The file descriptors were passed by the previous process and nothing else in this process owns
+        // them yet.
+        match unsafe { handoff.adopt() } {
+            Ok(inherited) => configuration = configuration.with_handoff(inherited),
+            Err(err) => tracing::error!(%err, "Ignoring the handoff of the previous process"),
+        }
+    }
+
+    if let Some(idle) = config.idle {
+        configuration = configuration.with_idle(idle);
+    }
+
+    if let Some(policy) = config.modifier_policy {
+        configuration = configuration.with_modifier_policy(policy);
+    }
+
+    if let Some((theme, size)) = config.cursor_theme {
+        configuration = configuration.with_cursor_theme(theme, size);
+    }
+
+    let executor = configuration.create_server().expect("Failed to create server");
+
+    if let Err(err) = executor.join() {
+        panic::resume_unwind(err)
+    }
+}
+
+/// Ask the display server running in this session to exit, exiting with an error if that fails.
+///
+/// The display server is found by the IPC socket exported to its child processes, or else by `WAYLAND_DISPLAY`.
+fn replace() {
+    let Some(path) = ipc::running_socket_path() else {
+        eprintln!(
+            "error: no running display server to replace, since neither AERUGO_SOCKET nor WAYLAND_DISPLAY is set"
+        );
+        process::exit(1);
+    };
+
+    if let Err(err) = ipc::replace(&path) {
+        eprintln!(
+            "error: failed to replace the display server at {}: {err}",
+            path.display()
+        );
+        process::exit(1);
+    }
+
+    tracing::info!(?path, "The running display server exited");
+}
+
+fn print_version(features: bool) {
+    println!("aerugo {}", env!("CARGO_PKG_VERSION"));
+
+    if features {
+        for (name, value) in FEATURES {
+            println!("{name}: {value}");
+        }
+    }
+}
+
+/// Load the configuration, logging every problem.
+///
+/// Settings with errors are skipped, so a mistake in the configuration never prevents the session from starting.
+fn load_config(path: &Path) -> Config {
+    let (config, diagnostics) = match Config::load(path) {
+        Ok(loaded) => loaded,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Config::default(),
+        Err(err) => {
+            tracing::error!(%err, ?path, "Failed to read the configuration");
+            return Config::default();
+        }
+    };
+
+    for diagnostic in diagnostics {
+        match diagnostic.severity {
+            Severity::Warning => tracing::warn!(?path, line = diagnostic.line, "{}", diagnostic.message),
+            Severity::Error => tracing::error!(?path, line = diagnostic.line, "{}", diagnostic.message),
+        }
+    }
+
+    config
+}
+
+/// Print every problem in the configuration, returning the exit status.
+fn check_config(path: Option<&Path>) -> i32 {
+    let Some(path) = path else {
+        eprintln!("error: no configuration file, pass --config");
+        return 1;
+    };
+
+    let diagnostics = match Config::load(path) {
+        Ok((_, diagnostics)) => diagnostics,
+        Err(err) => {
+            eprintln!("{}: error: {err}", path.display());
+            return 1;
+        }
+    };
+
+    for diagnostic in &diagnostics {
+        eprintln!("{}:{diagnostic}", path.display());
+    }
+
+    if config::has_errors(&diagnostics) {
+        return 1;
+    }
+
+    println!("{}: ok", path.display());
+    0
+}
@@ -0,0 +1,238 @@
+//! Activation tokens
+//!
+//! A client asks for one of its toplevels to be activated with a token, using `xdg_activation_v1`. Tokens are
+//! created by clients, usually in response to user input so a launcher or a browser can hand the token to the app
+//! it opens, and by the wm for the processes it spawns.
+//!
+//! The compositor only records where a token came from and drops tokens once they expire. Whether the toplevel is
+//! focused is decided by the wm.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io,
+    time::{Duration, Instant},
+};
+
+use rustix::rand::{getrandom, GetRandomFlags};
+use wm_runtime::WmEvent;
+
+use crate::{shell::ToplevelId, wm, Aerugo};
+
+/// How long a token created by a client may be used.
+pub const CLIENT_TOKEN_LIFETIME: Duration = Duration::from_secs(10);
+
+/// How long a token created by the wm may be used.
+///
+/// This is longer than for clients, since an app spawned by the wm may take a while to start.
+pub const WM_TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// The most tokens which may exist at once.
+///
+/// The oldest token is dropped when a token is created, so a client creating tokens in a loop cannot grow the
+/// tokens without bound.
+const MAX_TOKENS: usize = 256;
+
+/// Who created an activation token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenOrigin {
+    /// The wm created the token for a process it spawns.
+    Wm,
+
+    Client {
+        /// Whether the client passed the serial of an input event it received since the keyboard focus last
+        /// changed.
+        from_input: bool,
+
+        /// Whether the surface of the client which created the token had keyboard focus.
+        focused: bool,
+    },
+}
+
+/// What is known about an activation token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenData {
+    /// The app id of the app the token is intended for.
+    pub app_id: Option<String>,
+
+    pub origin: TokenOrigin,
+
+    pub created: Instant,
+}
+
+impl TokenData {
+    fn lifetime(&self) -> Duration {
+        match self.origin {
+            TokenOrigin::Wm => WM_TOKEN_LIFETIME,
+            TokenOrigin::Client { .. } => CLIENT_TOKEN_LIFETIME,
+        }
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.created) > self.lifetime()
+    }
+}
+
+/// The activation tokens which may be used.
+#[derive(Debug, Default)]
+pub struct ActivationTokens {
+    tokens: HashMap<String, TokenData>,
+}
+
+impl ActivationTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new token.
+    pub fn create(&mut self, data: TokenData) -> io::Result<String> {
+        let token = random_token()?;
+        self.insert(token.clone(), data);
+        Ok(token)
+    }
+
+    /// Accept a token created by the wm.
+    pub fn insert(&mut self, token: String, data: TokenData) {
+        let now = data.created;
+        self.tokens.retain(|_, data| !data.expired(now));
+
+        if self.tokens.len() >= MAX_TOKENS {
+            let oldest = self
+                .tokens
+                .iter()
+                .min_by_key(|(_, data)| data.created)
+                .map(|(token, _)| token.clone());
+
+            if let Some(oldest) = oldest {
+                self.tokens.remove(&oldest);
+            }
+        }
+
+        self.tokens.insert(token, data);
+    }
+
+    /// Use the token.
+    ///
+    /// A token may only be used once. Returns [`None`] if the token is unknown or expired.
+    pub fn take(&mut self, token: &str, now: Instant) -> Option<TokenData> {
+        self.tokens.remove(token).filter(|data| !data.expired(now))
+    }
+}
+
+/// A token which cannot be guessed by other clients.
+fn random_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    let mut filled = 0;
+
+    while filled < bytes.len() {
+        match getrandom(&mut bytes[filled..], GetRandomFlags::empty()) {
+            Ok(read) => filled += read,
+            Err(rustix::io::Errno::INTR) => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let mut token = String::with_capacity(bytes.len() * 2);
+
+    for byte in bytes {
+        let _ = write!(token, "{byte:02x}");
+    }
+
+    Ok(token)
+}
+
+impl Aerugo {
+    /// Accept an activation token the wm created for a process it spawns.
+    pub fn add_wm_activation_token(&mut self, token: String, app_id: Option<String>) {
+        let data = TokenData {
+            app_id,
+            origin: TokenOrigin::Wm,
+            created: Instant::now(),
+        };
+
+        self.activation_tokens.insert(token, data);
+    }
+
+    /// A client requested the toplevel be activated with a valid token.
+    pub fn activation_requested(&mut self, toplevel: ToplevelId, token: TokenData) {
+        tracing::debug!(toplevel, ?token, "Toplevel requested activation");
+
+        if let Some(id) = self.wm.toplevel_id(toplevel) {
+            self.send_wm(WmEvent::ActivationRequested {
+                toplevel: id,
+                token: wm::activation_token_info(&token, Instant::now()),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{ActivationTokens, TokenData, TokenOrigin, CLIENT_TOKEN_LIFETIME, MAX_TOKENS, WM_TOKEN_LIFETIME};
+
+    fn client_token(created: Instant) -> TokenData {
+        TokenData {
+            app_id: Some("org.example.Browser".into()),
+            origin: TokenOrigin::Client {
+                from_input: true,
+                focused: true,
+            },
+            created,
+        }
+    }
+
+    #[test]
+    fn single_use() {
+        let start = Instant::now();
+        let mut tokens = ActivationTokens::new();
+        let token = tokens.create(client_token(start)).unwrap();
+        let other = tokens.create(client_token(start)).unwrap();
+
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, other);
+
+        assert_eq!(tokens.take(&token, start), Some(client_token(start)));
+        assert_eq!(tokens.take(&token, start), None);
+        assert_eq!(tokens.take("unknown", start), None);
+    }
+
+    #[test]
+    fn expiry() {
+        let start = Instant::now();
+        let mut tokens = ActivationTokens::new();
+        let client = tokens.create(client_token(start)).unwrap();
+
+        let wm = TokenData {
+            app_id: None,
+            origin: TokenOrigin::Wm,
+            created: start,
+        };
+        tokens.insert("wm".into(), wm.clone());
+
+        let later = start + CLIENT_TOKEN_LIFETIME + Duration::from_secs(1);
+        assert_eq!(tokens.take(&client, later), None);
+        assert_eq!(tokens.take("wm", later), Some(wm.clone()));
+
+        tokens.insert("wm".into(), wm);
+        assert_eq!(
+            tokens.take("wm", start + WM_TOKEN_LIFETIME + Duration::from_secs(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn oldest_dropped() {
+        let start = Instant::now();
+        let mut tokens = ActivationTokens::new();
+
+        for i in 0..=MAX_TOKENS {
+            tokens.insert(i.to_string(), client_token(start + Duration::from_millis(i as u64)));
+        }
+
+        assert_eq!(tokens.tokens.len(), MAX_TOKENS);
+        assert!(tokens.take("0", start).is_none());
+        assert!(tokens.take("1", start).is_some());
+    }
+}
@@ -0,0 +1,304 @@
+//! Buffer alpha modes
+//!
+//! Wayland buffers with an alpha channel are premultiplied by convention, both for `wl_shm` and dmabuf. The
+//! color-representation protocol allows a client to declare a buffer uses straight (non-premultiplied) alpha
+//! instead. Blending a straight alpha buffer as if it were premultiplied leaves dark fringes around antialiased
+//! edges (such as text), so the alpha mode of every surface is tracked and surfaces with straight alpha are drawn
+//! by a [`StraightAlphaElement`], which premultiplies the color in the shader.
+//!
+//! Buffers without an alpha channel are opaque. Opaque surfaces are reported as opaque regions so the surfaces
+//! below do not need to be drawn.
+
+use std::sync::Mutex;
+
+use smithay::{
+    backend::{
+        allocator::{Buffer, Fourcc},
+        renderer::{
+            element::{Element, Id, RenderElement, UnderlyingStorage},
+            gles::{GlesError, GlesFrame, GlesRenderer, GlesTexProgram},
+            utils::CommitCounter,
+        },
+    },
+    utils::{Buffer as BufferCoords, Physical, Rectangle, Scale},
+    wayland::{
+        compositor::{self, SurfaceData},
+        dmabuf::get_dmabuf,
+        shm::{self, shm_format_to_fourcc},
+    },
+};
+use wayland_server::protocol::{wl_buffer::WlBuffer, wl_surface::WlSurface};
+
+/// Fragment shader for GLES which premultiplies a straight alpha texture before blending.
+pub const STRAIGHT_ALPHA_SHADER: &str = r#"
+#version 100
+//_DEFINES_
+
+#if defined(EXTERNAL)
+#extension GL_OES_EGL_image_external : require
+#endif
+
+precision mediump float;
+#if defined(EXTERNAL)
+uniform samplerExternalOES tex;
+#else
+uniform sampler2D tex;
+#endif
+
+uniform float alpha;
+varying vec2 v_coords;
+
+void main() {
+    vec4 color = texture2D(tex, v_coords);
+#if defined(NO_ALPHA)
+    color.a = 1.0;
+#else
+    color.rgb *= color.a;
+#endif
+    gl_FragColor = color * alpha;
+}
+"#;
+
+/// How the alpha channel of a buffer is interpreted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// The color channels are already multiplied by the alpha channel.
+    #[default]
+    Premultiplied,
+
+    /// The color channels are not multiplied by the alpha channel.
+    Straight,
+
+    /// The buffer has no alpha channel, or the alpha channel is ignored.
+    Opaque,
+}
+
+impl AlphaMode {
+    /// The alpha mode of a buffer with the format, following the Wayland convention.
+    pub fn from_fourcc(format: Fourcc) -> Self {
+        match has_alpha(format) {
+            true => AlphaMode::Premultiplied,
+            false => AlphaMode::Opaque,
+        }
+    }
+
+    /// The alpha mode of a client buffer, following the Wayland convention.
+    ///
+    /// Returns [`None`] if the format of the buffer is not known.
+    pub fn of_buffer(buffer: &WlBuffer) -> Option<Self> {
+        let format = match get_dmabuf(buffer) {
+            Ok(dmabuf) => Some(dmabuf.format().code),
+            Err(_) => shm::with_buffer_contents(buffer, |_, _, data| shm_format_to_fourcc(data.format))
+                .ok()
+                .flatten(),
+        };
+
+        format.map(Self::from_fourcc)
+    }
+
+    /// Whether the color must be premultiplied before blending.
+    pub fn needs_premultiply(self) -> bool {
+        self == AlphaMode::Straight
+    }
+}
+
+/// Compile [`STRAIGHT_ALPHA_SHADER`] for the renderer.
+pub fn compile_straight_alpha_shader(renderer: &mut GlesRenderer) -> Result<GlesTexProgram, GlesError> {
+    renderer.compile_custom_texture_shader(STRAIGHT_ALPHA_SHADER, &[])
+}
+
+/// An element whose texture has straight alpha, drawn with [`STRAIGHT_ALPHA_SHADER`].
+#[derive(Debug)]
+pub struct StraightAlphaElement<E> {
+    inner: E,
+    program: GlesTexProgram,
+}
+
+impl<E> StraightAlphaElement<E> {
+    /// Draw the element with the program compiled by [`compile_straight_alpha_shader`].
+    pub fn new(inner: E, program: GlesTexProgram) -> Self {
+        Self { inner, program }
+    }
+}
+
+impl<E: Element> Element for StraightAlphaElement<E> {
+    fn id(&self) -> &Id {
+        self.inner.id()
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.inner.current_commit()
+    }
+
+    fn src(&self) -> Rectangle<f64, BufferCoords> {
+        self.inner.src()
+    }
+
+    fn geometry(&self, scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.inner.geometry(scale)
+    }
+
+    fn damage_since(&self, scale: Scale<f64>, commit: Option<CommitCounter>) -> Vec<Rectangle<i32, Physical>> {
+        self.inner.damage_since(scale, commit)
+    }
+
+    fn opaque_regions(&self, scale: Scale<f64>) -> Vec<Rectangle<i32, Physical>> {
+        self.inner.opaque_regions(scale)
+    }
+}
+
+impl<E: RenderElement<GlesRenderer>> RenderElement<GlesRenderer> for StraightAlphaElement<E> {
+    fn draw<'a>(
+        &self,
+        frame: &mut GlesFrame<'a>,
+        src: Rectangle<f64, BufferCoords>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<(), GlesError> {
+        frame.override_default_tex_program(self.program.clone(), Vec::new());
+        let result = self.inner.draw(frame, src, dst, damage);
+        frame.clear_tex_program_override();
+        result
+    }
+
+    fn underlying_storage(&self, _renderer: &mut GlesRenderer) -> Option<UnderlyingStorage> {
+        // The buffer cannot be scanned out, since the display controller would blend it as premultiplied.
+        None
+    }
+}
+
+/// Alpha mode declared by the client for a surface.
+#[derive(Debug, Default)]
+struct SurfaceAlphaMode(Mutex<Option<AlphaMode>>);
+
+/// Alpha mode declared by the client since the last commit of the surface.
+#[derive(Debug, Default)]
+struct PendingAlphaMode {
+    /// The declared alpha mode, or [`None`] if the alpha mode was not changed since the last commit.
+    pending: Mutex<Option<Option<AlphaMode>>>,
+
+    /// Whether a color representation object exists for the surface.
+    claimed: Mutex<bool>,
+}
+
+/// Mark the surface as having a color representation object.
+///
+/// Returns [`false`] if the surface already has one.
+pub fn claim_alpha_mode(surface: &WlSurface) -> bool {
+    compositor::with_states(surface, |states| {
+        states.data_map.insert_if_missing_threadsafe(PendingAlphaMode::default);
+        let mut claimed = states
+            .data_map
+            .get::<PendingAlphaMode>()
+            .unwrap()
+            .claimed
+            .lock()
+            .unwrap();
+        !std::mem::replace(&mut *claimed, true)
+    })
+}
+
+/// The color representation object of the surface was destroyed.
+pub fn release_alpha_mode(surface: &WlSurface) {
+    compositor::with_states(surface, |states| {
+        if let Some(pending) = states.data_map.get::<PendingAlphaMode>() {
+            *pending.claimed.lock().unwrap() = false;
+        }
+    });
+}
+
+/// Declare the alpha mode of the surface's buffers, or use the convention of the buffer format if [`None`].
+///
+/// The alpha mode is applied by [`commit_alpha_mode`] when the surface is committed.
+pub fn set_pending_alpha_mode(surface: &WlSurface, mode: Option<AlphaMode>) {
+    compositor::with_states(surface, |states| {
+        states.data_map.insert_if_missing_threadsafe(PendingAlphaMode::default);
+        *states
+            .data_map
+            .get::<PendingAlphaMode>()
+            .unwrap()
+            .pending
+            .lock()
+            .unwrap() = Some(mode);
+    });
+}
+
+/// Apply the alpha mode declared since the last commit of the surface.
+pub fn commit_alpha_mode(surface: &WlSurface) {
+    let pending = compositor::with_states(surface, |states| {
+        states
+            .data_map
+            .get::<PendingAlphaMode>()
+            .and_then(|pending| pending.pending.lock().unwrap().take())
+    });
+
+    if let Some(mode) = pending {
+        set_surface_alpha_mode(surface, mode);
+    }
+}
+
+/// Override the alpha mode of the surface's buffers, or use the convention of the buffer format if [`None`].
+fn set_surface_alpha_mode(surface: &WlSurface, mode: Option<AlphaMode>) {
+    compositor::with_states(surface, |states| {
+        states.data_map.insert_if_missing_threadsafe(SurfaceAlphaMode::default);
+        *states.data_map.get::<SurfaceAlphaMode>().unwrap().0.lock().unwrap() = mode;
+    });
+}
+
+/// The alpha mode used to draw the current buffer of the surface.
+pub fn surface_alpha_mode(states: &SurfaceData, buffer: Option<&WlBuffer>) -> AlphaMode {
+    let declared = states
+        .data_map
+        .get::<SurfaceAlphaMode>()
+        .and_then(|mode| *mode.0.lock().unwrap());
+    let format = buffer.and_then(AlphaMode::of_buffer);
+
+    match (declared, format) {
+        // A declared alpha mode cannot make a buffer without an alpha channel transparent.
+        (_, Some(AlphaMode::Opaque)) => AlphaMode::Opaque,
+        (Some(mode), _) => mode,
+        (None, format) => format.unwrap_or_default(),
+    }
+}
+
+fn has_alpha(format: Fourcc) -> bool {
+    !matches!(
+        format,
+        Fourcc::Xrgb8888
+            | Fourcc::Xbgr8888
+            | Fourcc::Rgbx8888
+            | Fourcc::Bgrx8888
+            | Fourcc::Rgb888
+            | Fourcc::Bgr888
+            | Fourcc::Rgb565
+            | Fourcc::Bgr565
+            | Fourcc::Xrgb2101010
+            | Fourcc::Xbgr2101010
+            | Fourcc::Rgbx1010102
+            | Fourcc::Bgrx1010102
+            | Fourcc::Xrgb16161616f
+            | Fourcc::Xbgr16161616f
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::backend::allocator::Fourcc;
+
+    use super::AlphaMode;
+
+    #[test]
+    fn formats_with_alpha_are_premultiplied() {
+        assert_eq!(AlphaMode::from_fourcc(Fourcc::Argb8888), AlphaMode::Premultiplied);
+        assert_eq!(AlphaMode::from_fourcc(Fourcc::Abgr2101010), AlphaMode::Premultiplied);
+        assert_eq!(AlphaMode::from_fourcc(Fourcc::Xrgb8888), AlphaMode::Opaque);
+        assert_eq!(AlphaMode::from_fourcc(Fourcc::Rgb565), AlphaMode::Opaque);
+    }
+
+    #[test]
+    fn only_straight_needs_premultiply() {
+        assert!(AlphaMode::Straight.needs_premultiply());
+        assert!(!AlphaMode::Premultiplied.needs_premultiply());
+        assert!(!AlphaMode::Opaque.needs_premultiply());
+    }
+}
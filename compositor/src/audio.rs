@@ -0,0 +1,562 @@
+//! Audio streams of toplevels
+//!
+//! PipeWire knows which process plays each audio stream, but not which window the stream belongs to. Streams are
+//! associated with toplevels by the process of the stream: a stream belongs to a toplevel if the client of the
+//! toplevel is the process which plays the stream, or if both run in the same app scope, since apps such as
+//! browsers play audio from a separate process. Cgroups shared by unrelated processes, such as the login session,
+//! do not associate streams. If several toplevels match, the stream belongs to the focused toplevel, or else to the
+//! toplevel created first.
+//!
+//! The wm is told when a toplevel starts or stops playing audio, so it may raise the toplevel which started playing
+//! or show the toplevel in a volume OSD. The association is also written to the default metadata of PipeWire with
+//! the stream node as subject, so mixers can show which window plays a stream.
+//!
+//! The PipeWire registry is watched on a separate thread, since PipeWire runs its own main loop.
+
+use std::{cell::RefCell, io, rc::Rc, thread};
+
+use calloop::channel::Sender;
+use pipewire::{
+    metadata::Metadata,
+    node::{Node, NodeListener, NodeState},
+    registry::{GlobalObject, Registry},
+    spa::{ForeignDict, ReadableDict},
+    types::ObjectType,
+    Context, MainLoop,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+use wayland_server::Resource;
+use wm_runtime::WmEvent;
+
+use crate::{
+    policy::{self, ClientInfo},
+    shell::ToplevelId,
+    state::ClientData,
+    Aerugo,
+};
+
+/// The stream node property holding the id of the toplevel the stream belongs to.
+pub const TOPLEVEL_PROPERTY: &str = "aerugo.toplevel.id";
+
+/// The stream node property which is `true` if the toplevel the stream belongs to is focused.
+pub const FOCUSED_PROPERTY: &str = "aerugo.toplevel.focused";
+
+/// An audio stream node.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioStream {
+    /// The pid of the process playing the stream, from the `application.process.id` property of the node.
+    pub pid: Option<i32>,
+
+    /// The cgroup of the process playing the stream.
+    pub cgroup: Option<String>,
+
+    /// Whether the stream is running rather than paused or corked.
+    pub playing: bool,
+}
+
+impl AudioStream {
+    pub fn new(pid: Option<i32>) -> Self {
+        Self {
+            pid,
+            cgroup: pid.and_then(policy::process_cgroup),
+            playing: false,
+        }
+    }
+
+    fn matches(&self, client: &ClientInfo) -> bool {
+        if self.pid.is_some() && self.pid == client.pid {
+            return true;
+        }
+
+        match (self.cgroup.as_deref().and_then(app_scope), client.cgroup.as_deref()) {
+            (Some(scope), Some(cgroup)) => app_scope(cgroup) == Some(scope),
+            _ => false,
+        }
+    }
+}
+
+/// The systemd unit a launcher started the app in, such as `app-gnome-firefox-1234.scope`.
+///
+/// Units of apps are named `app-<launcher>-<app id>-<random>` by convention, so other units such as
+/// `session-2.scope` or `user@1000.service` are shared by unrelated processes.
+fn app_scope(cgroup: &str) -> Option<&str> {
+    cgroup
+        .split('/')
+        .rev()
+        .find(|unit| unit.starts_with("app-") && (unit.ends_with(".scope") || unit.ends_with(".service")))
+}
+
+#[derive(Debug)]
+struct Tracked {
+    stream: AudioStream,
+    toplevel: Option<ToplevelId>,
+    /// The properties last written to the metadata of the stream node.
+    published: Vec<(&'static str, String)>,
+}
+
+/// Associates audio streams with toplevels.
+#[derive(Debug, Default)]
+pub struct AudioStreams {
+    /// Streams by the id of their node.
+    streams: FxHashMap<u32, Tracked>,
+
+    /// Toplevels which play at least one stream.
+    playing: FxHashSet<ToplevelId>,
+}
+
+impl AudioStreams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A stream node was added or its properties changed.
+    pub fn insert(&mut self, node: u32, stream: AudioStream) {
+        let (toplevel, published) = self
+            .streams
+            .remove(&node)
+            .map(|tracked| (tracked.toplevel, tracked.published))
+            .unwrap_or_default();
+
+        self.streams.insert(
+            node,
+            Tracked {
+                stream,
+                toplevel,
+                published,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, node: u32) {
+        self.streams.remove(&node);
+    }
+
+    /// The stream started or stopped playing.
+    pub fn set_playing(&mut self, node: u32, playing: bool) {
+        if let Some(tracked) = self.streams.get_mut(&node) {
+            tracked.stream.playing = playing;
+        }
+    }
+
+    /// The toplevel the stream belongs to.
+    pub fn toplevel(&self, node: u32) -> Option<ToplevelId> {
+        self.streams.get(&node)?.toplevel
+    }
+
+    pub fn is_playing(&self, toplevel: ToplevelId) -> bool {
+        self.playing.contains(&toplevel)
+    }
+
+    /// The properties to set on the stream node.
+    pub fn properties(&self, node: u32, focus: Option<ToplevelId>) -> Vec<(&'static str, String)> {
+        let Some(toplevel) = self.toplevel(node) else {
+            return Vec::new();
+        };
+
+        vec![
+            (TOPLEVEL_PROPERTY, toplevel.to_string()),
+            (FOCUSED_PROPERTY, (focus == Some(toplevel)).to_string()),
+        ]
+    }
+
+    /// Associate the streams with the toplevels.
+    ///
+    /// Returns the toplevels which started or stopped playing audio, ordered by id.
+    pub fn update(
+        &mut self,
+        toplevels: &[(ToplevelId, &ClientInfo)],
+        focus: Option<ToplevelId>,
+    ) -> Vec<(ToplevelId, bool)> {
+        let mut playing = FxHashSet::default();
+
+        for tracked in self.streams.values_mut() {
+            let mut matching = toplevels
+                .iter()
+                .filter(|(_, client)| tracked.stream.matches(client))
+                .map(|&(id, _)| id);
+
+            tracked.toplevel = match focus {
+                Some(focus) if matching.clone().any(|id| id == focus) => Some(focus),
+                _ => matching.min(),
+            };
+
+            if tracked.stream.playing {
+                playing.extend(tracked.toplevel);
+            }
+        }
+
+        let mut changes = playing
+            .symmetric_difference(&self.playing)
+            .map(|&id| (id, playing.contains(&id)))
+            .collect::<Vec<_>>();
+        changes.sort_unstable_by_key(|&(id, _)| id);
+
+        self.playing = playing;
+        changes
+    }
+
+    /// The stream nodes whose properties changed since the properties were last taken, with the new properties.
+    ///
+    /// A node without properties no longer belongs to a toplevel.
+    pub fn take_changed_properties(&mut self, focus: Option<ToplevelId>) -> Vec<(u32, Vec<(&'static str, String)>)> {
+        let nodes = self.streams.keys().copied().collect::<Vec<_>>();
+        let mut changed = Vec::new();
+
+        for node in nodes {
+            let properties = self.properties(node, focus);
+            let tracked = self.streams.get_mut(&node).unwrap();
+
+            if tracked.published != properties {
+                tracked.published = properties.clone();
+                changed.push((node, properties));
+            }
+        }
+
+        changed.sort_unstable_by_key(|&(node, _)| node);
+        changed
+    }
+}
+
+/// A change to the audio stream nodes of PipeWire.
+#[derive(Debug)]
+pub enum AudioEvent {
+    /// A stream node was added or its properties changed.
+    Stream {
+        node: u32,
+        stream: AudioStream,
+    },
+
+    /// The stream started or stopped playing.
+    Playing {
+        node: u32,
+        playing: bool,
+    },
+
+    Removed(u32),
+}
+
+/// Writes the properties of stream nodes to PipeWire.
+pub struct AudioMonitor {
+    properties: pipewire::channel::Sender<(u32, Vec<(&'static str, String)>)>,
+}
+
+impl std::fmt::Debug for AudioMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioMonitor").finish_non_exhaustive()
+    }
+}
+
+impl AudioMonitor {
+    /// Watch the audio stream nodes of PipeWire on a separate thread, which sends the changes to the stream nodes.
+    ///
+    /// If PipeWire is not running, the thread logs the error and exits, and no stream is associated with a toplevel.
+    pub fn spawn(events: Sender<AudioEvent>) -> io::Result<Self> {
+        let (properties, receiver) = pipewire::channel::channel();
+
+        thread::Builder::new().name("PipeWire monitor".into()).spawn(move || {
+            if let Err(err) = run_monitor(events, receiver) {
+                tracing::warn!(%err, "Failed to watch the audio streams of PipeWire");
+            }
+        })?;
+
+        Ok(Self { properties })
+    }
+
+    /// Write the properties to the metadata of the stream node.
+    fn publish(&self, node: u32, properties: Vec<(&'static str, String)>) {
+        // The thread exited if PipeWire is not running.
+        let _ = self.properties.send((node, properties));
+    }
+}
+
+/// The objects of the PipeWire registry the monitor listens to.
+#[derive(Default)]
+struct MonitorState {
+    /// The stream nodes and their listeners, which are removed once the proxy is dropped.
+    nodes: FxHashMap<u32, (Node, NodeListener)>,
+
+    /// The default metadata, which mixers read the properties of stream nodes from.
+    metadata: Option<Metadata>,
+}
+
+/// Run the main loop of PipeWire until the connection to PipeWire is lost.
+fn run_monitor(
+    events: Sender<AudioEvent>,
+    properties: pipewire::channel::Receiver<(u32, Vec<(&'static str, String)>)>,
+) -> Result<(), pipewire::Error> {
+    pipewire::init();
+
+    let main_loop = MainLoop::new()?;
+    let context = Context::new(&main_loop)?;
+    let core = context.connect(None)?;
+    let registry = Rc::new(core.get_registry()?);
+    let state = Rc::new(RefCell::new(MonitorState::default()));
+
+    let _properties = properties.attach(&main_loop, {
+        let state = state.clone();
+
+        move |(node, properties)| {
+            let state = state.borrow();
+            let Some(metadata) = &state.metadata else {
+                return;
+            };
+
+            for key in [TOPLEVEL_PROPERTY, FOCUSED_PROPERTY] {
+                let value = properties
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, value)| value.as_str());
+                metadata.set_property(node, key, value.map(|_| "Spa:String"), value);
+            }
+        }
+    });
+
+    let _registry = registry
+        .add_listener_local()
+        .global({
+            let registry = Rc::downgrade(&registry);
+            let state = state.clone();
+            let events = events.clone();
+
+            move |global| {
+                let Some(registry) = registry.upgrade() else {
+                    return;
+                };
+
+                if let Err(err) = global_added(&registry, &mut state.borrow_mut(), &events, global) {
+                    tracing::warn!(%err, id = global.id, "Failed to bind PipeWire object");
+                }
+            }
+        })
+        .global_remove({
+            let state = state.clone();
+
+            move |id| {
+                if state.borrow_mut().nodes.remove(&id).is_some() {
+                    let _ = events.send(AudioEvent::Removed(id));
+                }
+            }
+        })
+        .register();
+
+    let _error = core
+        .add_listener_local()
+        .error({
+            let main_loop = main_loop.clone();
+
+            move |id, _, _, message| {
+                tracing::warn!(id, message, "PipeWire error");
+
+                // Errors of the core mean the connection to PipeWire was lost.
+                if id == pipewire::core::PW_ID_CORE {
+                    main_loop.quit();
+                }
+            }
+        })
+        .register();
+
+    main_loop.run();
+    Ok(())
+}
+
+/// Bind an object which was added to the registry if the object is an audio stream node or the default metadata.
+fn global_added(
+    registry: &Registry,
+    state: &mut MonitorState,
+    events: &Sender<AudioEvent>,
+    global: &GlobalObject<ForeignDict>,
+) -> Result<(), pipewire::Error> {
+    let Some(props) = &global.props else {
+        return Ok(());
+    };
+
+    match global.type_ {
+        ObjectType::Node if props.get("media.class") == Some("Stream/Output/Audio") => {
+            let pid = props.get("application.process.id").and_then(|pid| pid.parse().ok());
+            let node_id = global.id;
+            let _ = events.send(AudioEvent::Stream {
+                node: node_id,
+                stream: AudioStream::new(pid),
+            });
+
+            let node: Node = registry.bind(global)?;
+            let listener = node
+                .add_listener_local()
+                .info({
+                    let events = events.clone();
+
+                    move |info| {
+                        let _ = events.send(AudioEvent::Playing {
+                            node: node_id,
+                            playing: matches!(info.state(), NodeState::Running),
+                        });
+                    }
+                })
+                .register();
+
+            state.nodes.insert(node_id, (node, listener));
+        }
+
+        ObjectType::Metadata if props.get("metadata.name") == Some("default") => {
+            state.metadata = Some(registry.bind(global)?);
+        }
+
+        _ => (),
+    }
+
+    Ok(())
+}
+
+impl Aerugo {
+    /// A stream node of PipeWire changed.
+    pub fn audio_event(&mut self, event: AudioEvent) {
+        match event {
+            AudioEvent::Stream { node, stream } => self.audio_streams.insert(node, stream),
+            AudioEvent::Playing { node, playing } => self.audio_streams.set_playing(node, playing),
+            AudioEvent::Removed(node) => self.audio_streams.remove(node),
+        }
+
+        self.update_audio_streams();
+    }
+
+    /// Associate the audio streams with the toplevels after streams or toplevels changed or the focus moved.
+    ///
+    /// The wm is told which toplevels started or stopped playing audio.
+    pub fn update_audio_streams(&mut self) {
+        let clients = self
+            .shell
+            .toplevels
+            .iter()
+            .filter_map(|(&id, toplevel)| Some((id, toplevel.wl_surface()?.client()?)))
+            .collect::<Vec<_>>();
+
+        let toplevels = clients
+            .iter()
+            .filter_map(|(id, client)| Some((*id, &ClientData::get_data(client)?.info)))
+            .collect::<Vec<_>>();
+
+        let changes = self.audio_streams.update(&toplevels, self.keyboard_focus);
+
+        for (id, playing) in changes {
+            if let Some(toplevel) = self.wm.toplevel_id(id) {
+                self.send_wm(WmEvent::ToplevelAudio { toplevel, playing });
+            }
+        }
+
+        let properties = self.audio_streams.take_changed_properties(self.keyboard_focus);
+
+        if let Some(monitor) = &self.audio_monitor {
+            for (node, properties) in properties {
+                monitor.publish(node, properties);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{policy::ClientInfo, test_util::toplevel};
+
+    use super::{app_scope, AudioStream, AudioStreams, FOCUSED_PROPERTY, TOPLEVEL_PROPERTY};
+
+    fn client(pid: i32, cgroup: &str) -> ClientInfo {
+        ClientInfo {
+            pid: Some(pid),
+            cgroup: Some(cgroup.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn stream(pid: i32, cgroup: &str) -> AudioStream {
+        AudioStream {
+            pid: Some(pid),
+            cgroup: Some(cgroup.to_owned()),
+            playing: false,
+        }
+    }
+
+    #[test]
+    fn match_by_pid_and_cgroup() {
+        let player = client(10, "/app.slice/app-player.scope");
+        let browser = client(20, "/app.slice/app-browser.scope");
+        let toplevels = [(toplevel(1), &player), (toplevel(2), &browser)];
+        let mut streams = AudioStreams::new();
+
+        streams.insert(100, stream(10, "/app.slice/app-player.scope"));
+        // The audio process of the browser is a child of the browser.
+        streams.insert(101, stream(21, "/app.slice/app-browser.scope"));
+        streams.insert(102, stream(30, "/app.slice/app-other.scope"));
+
+        assert!(streams.update(&toplevels, None).is_empty());
+        assert_eq!(streams.toplevel(100), Some(toplevel(1)));
+        assert_eq!(streams.toplevel(101), Some(toplevel(2)));
+        assert_eq!(streams.toplevel(102), None);
+    }
+
+    #[test]
+    fn shared_cgroup_does_not_match() {
+        let terminal = client(10, "/user.slice/user-1000.slice/session-2.scope");
+        let toplevels = [(toplevel(1), &terminal)];
+        let mut streams = AudioStreams::new();
+
+        // A player started from the login session shares the cgroup with every other process of the session.
+        streams.insert(100, stream(11, "/user.slice/user-1000.slice/session-2.scope"));
+
+        streams.update(&toplevels, None);
+        assert_eq!(streams.toplevel(100), None);
+    }
+
+    #[test]
+    fn app_scopes() {
+        assert_eq!(
+            app_scope("/user.slice/user-1000.slice/user@1000.service/app.slice/app-gnome-firefox-1234.scope"),
+            Some("app-gnome-firefox-1234.scope")
+        );
+        assert_eq!(
+            app_scope("/user.slice/user-1000.slice/user@1000.service/app.slice"),
+            None
+        );
+        assert_eq!(app_scope("/user.slice/user-1000.slice/session-2.scope"), None);
+    }
+
+    #[test]
+    fn playing_changes() {
+        let player = client(10, "/app.slice/app-player.scope");
+        let toplevels = [(toplevel(1), &player), (toplevel(2), &player)];
+        let mut streams = AudioStreams::new();
+
+        streams.insert(100, stream(10, "/app.slice/app-player.scope"));
+        streams.set_playing(100, true);
+        assert_eq!(streams.update(&toplevels, None), [(toplevel(1), true)]);
+        assert!(streams.update(&toplevels, None).is_empty());
+
+        // The stream belongs to the focused toplevel of the client.
+        assert_eq!(
+            streams.update(&toplevels, Some(toplevel(2))),
+            [(toplevel(1), false), (toplevel(2), true)]
+        );
+        assert_eq!(
+            streams.properties(100, Some(toplevel(2))),
+            [
+                (TOPLEVEL_PROPERTY, "2".to_owned()),
+                (FOCUSED_PROPERTY, "true".to_owned())
+            ]
+        );
+
+        assert_eq!(
+            streams.take_changed_properties(Some(toplevel(2))),
+            [(
+                100,
+                vec![
+                    (TOPLEVEL_PROPERTY, "2".to_owned()),
+                    (FOCUSED_PROPERTY, "true".to_owned())
+                ]
+            )]
+        );
+        assert!(streams.take_changed_properties(Some(toplevel(2))).is_empty());
+
+        streams.remove(100);
+        assert_eq!(streams.update(&toplevels, Some(toplevel(2))), [(toplevel(2), false)]);
+        assert!(!streams.is_playing(toplevel(2)));
+    }
+}
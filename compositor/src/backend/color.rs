@@ -0,0 +1,441 @@
+//! Output color transforms
+//!
+//! Night light, brightness adjustments and simple accessibility filters change the color of everything shown on
+//! an output. Where the CRTC has the `CTM`, `DEGAMMA_LUT` and `GAMMA_LUT` properties, the transform is applied by
+//! the display hardware during scanout, which costs nothing compared to a shader pass over every frame. If the
+//! CRTC lacks a property the transform needs, the renderer applies the whole transform with a shader instead.
+//!
+//! The hardware applies the degamma LUT, then the CTM and then the gamma LUT. The color temperature and
+//! grayscale filter are applied by the CTM in linear light if the CRTC has a degamma LUT, while the brightness
+//! and inversion are applied by the gamma LUT since inverting colors is not a linear transform.
+//!
+//! The shader is [`COLOR_TRANSFORM_SHADER`], which draws the composited frame to the output in one more pass. It
+//! applies the matrix to the encoded colors like a CRTC without a degamma LUT.
+
+use std::fmt;
+
+use smithay::backend::renderer::gles::{
+    GlesError, GlesRenderer, GlesTexProgram, Uniform, UniformName, UniformType, UniformValue,
+};
+
+/// Fragment shader for GLES which applies a [`ColorTransform`] to a premultiplied texture.
+pub const COLOR_TRANSFORM_SHADER: &str = r#"
+#version 100
+//_DEFINES_
+
+#if defined(EXTERNAL)
+#extension GL_OES_EGL_image_external : require
+#endif
+
+precision mediump float;
+#if defined(EXTERNAL)
+uniform samplerExternalOES tex;
+#else
+uniform sampler2D tex;
+#endif
+
+uniform float alpha;
+uniform mat3 matrix;
+uniform float brightness;
+uniform float invert;
+varying vec2 v_coords;
+
+void main() {
+    vec4 color = texture2D(tex, v_coords);
+#if defined(NO_ALPHA)
+    color.a = 1.0;
+#endif
+
+    vec3 rgb = color.a > 0.0 ? color.rgb / color.a : vec3(0.0);
+    rgb = clamp(matrix * rgb, 0.0, 1.0);
+    rgb = mix(rgb, 1.0 - rgb, invert) * brightness;
+    gl_FragColor = vec4(rgb * color.a, color.a) * alpha;
+}
+"#;
+
+/// The color temperature which leaves colors unchanged, in kelvin.
+pub const NEUTRAL_TEMPERATURE: u32 = 6500;
+
+/// The warmest supported color temperature, in kelvin.
+pub const MIN_TEMPERATURE: u32 = 1000;
+
+const IDENTITY_MATRIX: [f64; 9] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+/// Luma coefficients of BT.709.
+const LUMA: [f64; 3] = [0.2126, 0.7152, 0.0722];
+
+/// An accessibility filter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorFilter {
+    #[default]
+    None,
+
+    /// Remove all color.
+    Grayscale,
+
+    /// Invert every color.
+    Invert,
+}
+
+impl ColorFilter {
+    /// Parse the name of a filter, as written by [`Display`](fmt::Display).
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Self::None),
+            "grayscale" => Some(Self::Grayscale),
+            "invert" => Some(Self::Invert),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ColorFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Grayscale => "grayscale",
+            Self::Invert => "invert",
+        })
+    }
+}
+
+/// A transform applied to the colors of an output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorTransform {
+    /// The color temperature in kelvin, between [`MIN_TEMPERATURE`] and [`NEUTRAL_TEMPERATURE`].
+    pub temperature: u32,
+
+    /// The brightness between 0.0 (black) and 1.0.
+    pub brightness: f64,
+
+    pub filter: ColorFilter,
+}
+
+impl ColorTransform {
+    /// The transform which leaves colors unchanged.
+    pub const IDENTITY: Self = Self {
+        temperature: NEUTRAL_TEMPERATURE,
+        brightness: 1.0,
+        filter: ColorFilter::None,
+    };
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    /// Parse the color temperature, brightness and filter, as written by [`Display`](fmt::Display).
+    ///
+    /// Returns [`None`] if a value is out of range.
+    pub fn parse(temperature: &str, brightness: &str, filter: &str) -> Option<Self> {
+        let temperature = temperature
+            .parse()
+            .ok()
+            .filter(|temperature| (MIN_TEMPERATURE..=NEUTRAL_TEMPERATURE).contains(temperature))?;
+        let brightness = brightness
+            .parse()
+            .ok()
+            .filter(|brightness| (0.0..=1.0).contains(brightness))?;
+
+        Some(Self {
+            temperature,
+            brightness,
+            filter: ColorFilter::parse(filter)?,
+        })
+    }
+
+    /// The matrix applying the color temperature and grayscale filter, in row major order.
+    pub fn matrix(&self) -> [f64; 9] {
+        let white = temperature_rgb(self.temperature);
+
+        std::array::from_fn(|index| {
+            let column = index % 3;
+            let value = match self.filter {
+                ColorFilter::Grayscale => LUMA[column],
+                _ => IDENTITY_MATRIX[index],
+            };
+
+            value * white[column]
+        })
+    }
+
+    /// Whether the gamma LUT must change the colors after the matrix is applied.
+    fn needs_gamma(&self) -> bool {
+        self.brightness != 1.0 || self.filter == ColorFilter::Invert
+    }
+
+    /// Apply the brightness and inversion to a color channel between 0.0 and 1.0.
+    fn gamma(&self, value: f64) -> f64 {
+        let value = match self.filter {
+            ColorFilter::Invert => 1.0 - value,
+            _ => value,
+        };
+
+        value * self.brightness.clamp(0.0, 1.0)
+    }
+}
+
+impl Default for ColorTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl fmt::Display for ColorTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.temperature, self.brightness, self.filter)
+    }
+}
+
+/// The color management properties of a CRTC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CrtcColorCaps {
+    /// Whether the CRTC has the `CTM` property.
+    pub ctm: bool,
+
+    /// The value of `DEGAMMA_LUT_SIZE`, or 0 if the CRTC has no degamma LUT.
+    pub degamma_lut_size: usize,
+
+    /// The value of `GAMMA_LUT_SIZE`, or 0 if the CRTC has no gamma LUT.
+    pub gamma_lut_size: usize,
+}
+
+/// The color management properties to set on a CRTC.
+///
+/// Properties which are [`None`] are cleared, so the default value leaves colors unchanged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KmsColor {
+    /// The `CTM` blob in S31.32 sign-magnitude format.
+    pub ctm: Option<[u64; 9]>,
+
+    /// The red, green and blue values of each entry of the `DEGAMMA_LUT` blob.
+    pub degamma_lut: Option<Vec<[u16; 3]>>,
+
+    /// The red, green and blue values of each entry of the `GAMMA_LUT` blob.
+    pub gamma_lut: Option<Vec<[u16; 3]>>,
+}
+
+/// Compute the properties which apply the transform on the CRTC.
+///
+/// Returns [`None`] if the CRTC lacks a property the transform needs, in which case the transform must be applied
+/// with a shader.
+pub fn offload(transform: &ColorTransform, caps: &CrtcColorCaps) -> Option<KmsColor> {
+    let matrix = transform.matrix();
+    let needs_ctm = matrix != IDENTITY_MATRIX;
+    let needs_gamma = transform.needs_gamma();
+
+    if (needs_ctm && !caps.ctm) || (needs_gamma && caps.gamma_lut_size == 0) {
+        return None;
+    }
+
+    // Without a degamma LUT the matrix is applied to the encoded colors, which is close enough for a night light.
+    let linear = needs_ctm && caps.degamma_lut_size > 0 && caps.gamma_lut_size > 0;
+
+    Some(KmsColor {
+        ctm: needs_ctm.then(|| matrix.map(s31_32)),
+        degamma_lut: linear.then(|| lut(caps.degamma_lut_size, srgb_to_linear)),
+        gamma_lut: (needs_gamma || linear).then(|| {
+            lut(caps.gamma_lut_size, |value| {
+                let value = if linear { linear_to_srgb(value) } else { value };
+                transform.gamma(value)
+            })
+        }),
+    })
+}
+
+/// The white point of a color temperature as red, green and blue multipliers.
+///
+/// This uses Tanner Helland's approximation of the blackbody color, scaled so that [`NEUTRAL_TEMPERATURE`] is
+/// white.
+fn temperature_rgb(temperature: u32) -> [f64; 3] {
+    fn blackbody(temperature: f64) -> [f64; 3] {
+        let t = temperature / 100.0;
+
+        let red = if t <= 66.0 {
+            255.0
+        } else {
+            329.698727446 * (t - 60.0).powf(-0.1332047592)
+        };
+        let green = if t <= 66.0 {
+            99.4708025861 * t.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (t - 60.0).powf(-0.0755148492)
+        };
+        let blue = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (t - 10.0).ln() - 305.0447927307
+        };
+
+        [red, green, blue]
+    }
+
+    let temperature = temperature.clamp(MIN_TEMPERATURE, NEUTRAL_TEMPERATURE) as f64;
+    let color = blackbody(temperature);
+    let white = blackbody(NEUTRAL_TEMPERATURE as f64);
+
+    [0, 1, 2].map(|channel| (color[channel] / white[channel]).clamp(0.0, 1.0))
+}
+
+/// Compile [`COLOR_TRANSFORM_SHADER`] for the renderer.
+pub fn compile_color_transform_shader(renderer: &mut GlesRenderer) -> Result<GlesTexProgram, GlesError> {
+    renderer.compile_custom_texture_shader(
+        COLOR_TRANSFORM_SHADER,
+        &[
+            UniformName::new("matrix", UniformType::Matrix3x3),
+            UniformName::new("brightness", UniformType::_1f),
+            UniformName::new("invert", UniformType::_1f),
+        ],
+    )
+}
+
+/// The uniforms of [`COLOR_TRANSFORM_SHADER`] which apply the transform.
+pub fn shader_uniforms(transform: &ColorTransform) -> Vec<Uniform<'static>> {
+    let invert = match transform.filter {
+        ColorFilter::Invert => 1.0,
+        _ => 0.0,
+    };
+
+    vec![
+        Uniform::new(
+            "matrix",
+            UniformValue::Matrix3x3 {
+                matrices: vec![column_major(transform.matrix())],
+                transpose: false,
+            },
+        ),
+        Uniform::new("brightness", transform.brightness.clamp(0.0, 1.0) as f32),
+        Uniform::new("invert", invert),
+    ]
+}
+
+/// Convert a row major matrix to column major order, which is the only order GLES accepts.
+fn column_major(matrix: [f64; 9]) -> [f32; 9] {
+    std::array::from_fn(|index| matrix[(index % 3) * 3 + index / 3] as f32)
+}
+
+/// Convert a value to the S31.32 sign-magnitude format used by the `CTM` property.
+fn s31_32(value: f64) -> u64 {
+    let magnitude = ((value.abs() * (1u64 << 32) as f64).round() as u64) & !(1 << 63);
+
+    if value.is_sign_negative() && magnitude != 0 {
+        magnitude | (1 << 63)
+    } else {
+        magnitude
+    }
+}
+
+/// Create a LUT with the same curve on every channel.
+fn lut(size: usize, curve: impl Fn(f64) -> f64) -> Vec<[u16; 3]> {
+    let last = size.saturating_sub(1).max(1) as f64;
+
+    (0..size)
+        .map(|index| {
+            let value = (curve(index as f64 / last).clamp(0.0, 1.0) * u16::MAX as f64).round() as u16;
+            [value; 3]
+        })
+        .collect()
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{column_major, offload, s31_32, ColorFilter, ColorTransform, CrtcColorCaps, KmsColor};
+
+    const FULL_CAPS: CrtcColorCaps = CrtcColorCaps {
+        ctm: true,
+        degamma_lut_size: 256,
+        gamma_lut_size: 256,
+    };
+
+    #[test]
+    fn night_light_warms_colors() {
+        let transform = ColorTransform {
+            temperature: 3000,
+            ..ColorTransform::IDENTITY
+        };
+        let matrix = transform.matrix();
+
+        assert_eq!(matrix[0], 1.0);
+        assert!(matrix[8] < matrix[4] && matrix[4] < 1.0);
+        assert_eq!(ColorTransform::IDENTITY.matrix(), super::IDENTITY_MATRIX);
+
+        let color = offload(&transform, &FULL_CAPS).unwrap();
+        assert!(color.ctm.is_some());
+        assert_eq!(color.degamma_lut.unwrap().len(), 256);
+        assert_eq!(color.gamma_lut.unwrap().len(), 256);
+
+        // The identity transform clears every property.
+        assert_eq!(
+            offload(&ColorTransform::IDENTITY, &FULL_CAPS),
+            Some(KmsColor::default())
+        );
+    }
+
+    #[test]
+    fn falls_back_to_shader() {
+        let night_light = ColorTransform {
+            temperature: 4000,
+            ..ColorTransform::IDENTITY
+        };
+        let dimmed = ColorTransform {
+            brightness: 0.5,
+            ..ColorTransform::IDENTITY
+        };
+        let gamma_only = CrtcColorCaps {
+            gamma_lut_size: 256,
+            ..CrtcColorCaps::default()
+        };
+
+        assert_eq!(offload(&night_light, &gamma_only), None);
+        assert_eq!(offload(&dimmed, &CrtcColorCaps::default()), None);
+
+        // Brightness only needs the gamma LUT.
+        let color = offload(&dimmed, &gamma_only).unwrap();
+        let lut = color.gamma_lut.unwrap();
+        assert_eq!(color.ctm, None);
+        assert_eq!(lut[0], [0; 3]);
+        assert_eq!(lut[255], [32768; 3]);
+    }
+
+    #[test]
+    fn shader_matrix_is_column_major() {
+        let matrix = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0];
+        assert_eq!(column_major(matrix), [1.0, 4.0, 7.0, 2.0, 5.0, 8.0, 3.0, 6.0, 9.0]);
+    }
+
+    #[test]
+    fn invert_uses_gamma_lut() {
+        let transform = ColorTransform {
+            filter: ColorFilter::Invert,
+            ..ColorTransform::IDENTITY
+        };
+        let lut = offload(&transform, &FULL_CAPS).unwrap().gamma_lut.unwrap();
+
+        assert_eq!(lut[0], [u16::MAX; 3]);
+        assert_eq!(lut[255], [0; 3]);
+    }
+
+    #[test]
+    fn ctm_format() {
+        assert_eq!(s31_32(1.0), 1 << 32);
+        assert_eq!(s31_32(-0.5), (1 << 63) | (1 << 31));
+        assert_eq!(s31_32(0.0), 0);
+    }
+}
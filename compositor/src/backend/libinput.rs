@@ -0,0 +1,59 @@
+//! libinput input source
+//!
+//! Windowed backends only receive the keyboard and pointer of the host, so the switches, touchpad gestures and
+//! touchscreens of the machine are read with libinput. Keys and pointer events from libinput are ignored, since the
+//! host already sends them to the window.
+
+use std::{io, os::fd::OwnedFd, path::Path};
+
+use calloop::{LoopHandle, RegistrationToken};
+use rustix::fs::{self, Mode, OFlags};
+use smithay::{
+    backend::{input::InputEvent, libinput::LibinputInputBackend},
+    reexports::input::{Libinput, LibinputInterface},
+};
+
+use crate::Loop;
+
+/// Opens input devices directly, which requires read access to the devices, such as by being in the `input` group.
+#[derive(Debug)]
+struct DirectInterface;
+
+impl LibinputInterface for DirectInterface {
+    fn open_restricted(&mut self, path: &Path, flags: i32) -> Result<OwnedFd, i32> {
+        fs::open(path, OFlags::from_bits_retain(flags as u32), Mode::empty()).map_err(|err| err.raw_os_error())
+    }
+
+    fn close_restricted(&mut self, fd: OwnedFd) {
+        drop(fd);
+    }
+}
+
+/// Read the switches, touchpad gestures and touchscreens of the seat with libinput.
+pub fn insert_source(r#loop: &LoopHandle<'static, Loop>, seat: &str) -> io::Result<RegistrationToken> {
+    let mut libinput = Libinput::new_with_udev(DirectInterface);
+
+    if libinput.udev_assign_seat(seat).is_err() {
+        return Err(io::Error::new(io::ErrorKind::Other, "Failed to assign the udev seat"));
+    }
+
+    r#loop
+        .insert_source(LibinputInputBackend::new(libinput), |event, _, state| match event {
+            InputEvent::SwitchToggle { .. }
+            | InputEvent::GestureSwipeBegin { .. }
+            | InputEvent::GestureSwipeUpdate { .. }
+            | InputEvent::GestureSwipeEnd { .. }
+            | InputEvent::GesturePinchBegin { .. }
+            | InputEvent::GesturePinchUpdate { .. }
+            | InputEvent::GesturePinchEnd { .. }
+            | InputEvent::GestureHoldBegin { .. }
+            | InputEvent::GestureHoldEnd { .. }
+            | InputEvent::TouchDown { .. }
+            | InputEvent::TouchMotion { .. }
+            | InputEvent::TouchUp { .. }
+            | InputEvent::TouchCancel { .. } => state.input_event(event),
+
+            _ => (),
+        })
+        .map_err(|err| err.error.into())
+}
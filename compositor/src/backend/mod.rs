@@ -1,19 +1,36 @@
+pub mod color;
+mod libinput;
+pub(crate) mod modeset;
+pub mod modifiers;
+pub mod render_scale;
 mod x11;
 
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, os::fd::BorrowedFd, path::PathBuf};
 
 use calloop::LoopHandle;
 use downcast_rs::{impl_downcast, Downcast};
 use smithay::{
     backend::allocator::dmabuf::Dmabuf,
+    output::{Mode, Output},
+    utils::{Physical, Size},
     wayland::{
         dmabuf::{DmabufGlobal, DmabufState, ImportError},
         shm::ShmState,
     },
 };
-use wayland_server::DisplayHandle;
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle};
 
-use crate::Loop;
+use crate::{
+    backend::{
+        color::{CrtcColorCaps, KmsColor},
+        modifiers::ModifierPolicy,
+    },
+    cursor::{CursorImage, CursorPresentation},
+    keyboard::Leds,
+    scene::Scene,
+    thumbnail::Thumbnail,
+    Loop,
+};
 
 pub trait Backend: fmt::Debug + Downcast {
     fn shm_state(&self) -> &ShmState;
@@ -26,6 +43,100 @@ pub trait Backend: fmt::Debug + Downcast {
     /// Import a client's dmabuf buffer into the backend.
     fn dmabuf_imported(&mut self, _global: &DmabufGlobal, _dmabuf: Dmabuf) -> Result<(), ImportError>;
 
+    /// Request that a new frame is drawn.
+    ///
+    /// The backend may defer drawing until the previous frame has been presented.
+    fn schedule_render(&mut self) {}
+
+    /// Set the mode of the output.
+    ///
+    /// DRM backends should test the mode with an atomic test commit before committing it, so a failed modeset
+    /// leaves the output unchanged. Returns [`false`] if the mode could not be set, such as when the backend
+    /// cannot change modes.
+    fn set_output_mode(&mut self, _output: &Output, _mode: Mode) -> bool {
+        false
+    }
+
+    /// Enable or disable the output.
+    ///
+    /// DRM backends should release the CRTC of a disabled output so it may be used by another connector, and
+    /// assign a CRTC again when the output is enabled. Returns [`false`] if the output could not be enabled or
+    /// disabled.
+    fn set_output_enabled(&mut self, _output: &Output, _enabled: bool) -> bool {
+        false
+    }
+
+    /// Turn the display of the output on or off.
+    ///
+    /// DRM backends should set the `ACTIVE` property of the CRTC while keeping the CRTC assigned to the output, so
+    /// the display can be turned back on without a full modeset. Returns [`false`] if the power state could not
+    /// be changed.
+    fn set_output_dpms(&mut self, _output: &Output, _on: bool) -> bool {
+        false
+    }
+
+    /// Whether the display connected to the output supports variable refresh.
+    ///
+    /// DRM backends should check the `vrr_capable` property of the connector.
+    fn output_supports_vrr(&self, _output: &Output) -> bool {
+        false
+    }
+
+    /// Enable or disable variable refresh on the output.
+    ///
+    /// DRM backends should set the `VRR_ENABLED` property of the CRTC. Returns [`false`] if variable refresh could
+    /// not be changed.
+    fn set_output_vrr(&mut self, _output: &Output, _enabled: bool) -> bool {
+        false
+    }
+
+    /// The color management properties of the CRTC driving the output.
+    ///
+    /// Backends without KMS return no properties, so color transforms are always applied by the renderer.
+    fn crtc_color_caps(&self, _output: &Output) -> CrtcColorCaps {
+        CrtcColorCaps::default()
+    }
+
+    /// Set the `CTM`, `DEGAMMA_LUT` and `GAMMA_LUT` properties of the CRTC driving the output.
+    ///
+    /// DRM backends should test the properties with an atomic test commit first. Returns [`false`] if the
+    /// properties could not be set, in which case the renderer applies the color transform.
+    fn set_output_color(&mut self, _output: &Output, _color: &KmsColor) -> bool {
+        false
+    }
+
+    /// Change how the cursor is presented.
+    ///
+    /// DRM backends should disable the cursor plane while the cursor is hidden rather than scanning out a
+    /// transparent image. Cursor planes cannot blend with an alpha, so a fading cursor must be drawn by the
+    /// renderer and is moved back to the cursor plane once it is fully visible again.
+    fn set_cursor_presentation(&mut self, _presentation: CursorPresentation) {}
+
+    /// Set the LEDs of every keyboard.
+    ///
+    /// Backends using libinput should update the LEDs of every keyboard device, and set the LEDs of a keyboard
+    /// when it is added.
+    fn set_keyboard_leds(&mut self, _leds: Leds) {}
+
+    /// Set the image of the cursor, or [`None`] to show no cursor.
+    ///
+    /// DRM backends should place the image on the cursor plane if the image fits the plane, and otherwise draw
+    /// the cursor with the renderer.
+    fn set_cursor_image(&mut self, _image: Option<&CursorImage>) {}
+
+    /// Render the surface tree of a toplevel in the scene into a thumbnail.
+    ///
+    /// The thumbnail should be scaled down to fit the maximum size using [`crate::thumbnail::thumbnail_size`]
+    /// and read back into memory. Returns [`None`] if the backend cannot render thumbnails.
+    fn render_thumbnail(
+        &mut self,
+        _scene: &Scene,
+        _surface: &WlSurface,
+        _max_size: Size<i32, Physical>,
+    ) -> Option<Thumbnail> {
+        None
+    }
+
     /// Check if the backend is asking the compositor to shutdown.
     ///
     /// Outside of the windowed test backends, this should return [`false`]
@@ -33,6 +144,23 @@ pub trait Backend: fmt::Debug + Downcast {
         false
     }
 
+    /// Details of the GPU and driver for debug reports.
+    fn debug_info(&self) -> String {
+        String::new()
+    }
+
+    /// The modifier policy changed.
+    ///
+    /// The policy applies the next time the backend allocates buffers for an output.
+    fn set_modifier_policy(&mut self, _policy: &ModifierPolicy) {}
+
+    /// The open DRM devices and their paths.
+    ///
+    /// The devices are inherited when the display server restarts in place, so the session keeps DRM master.
+    fn drm_devices(&self) -> Vec<(PathBuf, BorrowedFd<'_>)> {
+        Vec::new()
+    }
+
     // TODO: Outputs?
     // TODO: Seat?
 }
@@ -42,6 +170,11 @@ pub fn default_backend(
     r#loop: LoopHandle<'static, Loop>,
     display: DisplayHandle,
 ) -> Result<Box<dyn Backend>, Box<dyn Error>> {
+    // The X11 window only receives the keyboard and pointer, so other input comes from libinput.
+    if let Err(err) = libinput::insert_source(&r#loop, "seat0") {
+        tracing::warn!(%err, "Failed to read input devices with libinput");
+    }
+
     // TODO: X11 backend only exists right now, so the backend selection is ignored.
     Ok(Box::new(x11::Backend::new(r#loop, display).expect("TODO: Error type")))
 }
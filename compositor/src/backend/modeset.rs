@@ -0,0 +1,125 @@
+//! Mode setting helpers
+//!
+//! A modeset may fail even if the mode is advertised by the connector, such as if a DP-MST dock does not have
+//! enough bandwidth or the cable is flaky. Instead of leaving the output black, [`Aerugo::configure_output`] tries
+//! the modes in the fallback cascade in order until a modeset succeeds:
+//!
+//! 1. The same resolution at a lower refresh rate.
+//! 2. A lower resolution, preferring the highest refresh rate.
+//! 3. Disable the output.
+//!
+//! The wm is told whether the configuration was applied and sees the chosen mode in the update of the output.
+//!
+//! [`Aerugo::configure_output`]: crate::Aerugo::configure_output
+
+use std::cmp::Reverse;
+
+use smithay::output::Mode;
+
+/// A step in the fallback cascade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// Try to set this mode.
+    Mode(Mode),
+
+    /// No mode could be set, the output should be disabled.
+    Disable,
+}
+
+/// The result of setting a mode on an output.
+///
+/// This is used to notify the wm which mode was chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModesetOutcome {
+    /// The requested mode was set.
+    Requested(Mode),
+
+    /// The requested mode failed and a fallback mode was set instead.
+    Fallback { requested: Mode, chosen: Mode },
+
+    /// No mode could be set and the output was disabled.
+    Disabled { requested: Mode },
+}
+
+/// Create the fallback cascade after a modeset with the `failed` mode.
+///
+/// The last step in the cascade is always [`Fallback::Disable`].
+pub fn fallback_cascade(failed: Mode, modes: &[Mode]) -> Vec<Fallback> {
+    let area = |mode: &Mode| mode.size.w as i64 * mode.size.h as i64;
+
+    let mut lower_refresh = modes
+        .iter()
+        .filter(|mode| mode.size == failed.size && mode.refresh < failed.refresh)
+        .copied()
+        .collect::<Vec<_>>();
+    lower_refresh.sort_by_key(|mode| Reverse(mode.refresh));
+
+    let mut lower_resolution = modes
+        .iter()
+        .filter(|mode| area(mode) < area(&failed))
+        .copied()
+        .collect::<Vec<_>>();
+    lower_resolution.sort_by_key(|mode| (Reverse(area(mode)), Reverse(mode.refresh)));
+
+    let mut cascade = Vec::with_capacity(lower_refresh.len() + lower_resolution.len() + 1);
+
+    for mode in lower_refresh.into_iter().chain(lower_resolution) {
+        // Connectors may advertise the same mode more than once with different flags.
+        if !cascade.contains(&Fallback::Mode(mode)) {
+            cascade.push(Fallback::Mode(mode));
+        }
+    }
+
+    cascade.push(Fallback::Disable);
+    cascade
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fallback_cascade, Fallback};
+    use crate::test_util::mode;
+
+    #[test]
+    fn no_modes() {
+        assert_eq!(fallback_cascade(mode(1920, 1080, 60000), &[]), vec![Fallback::Disable]);
+    }
+
+    #[test]
+    fn lower_refresh_first() {
+        let modes = [
+            mode(3840, 2160, 144000),
+            mode(3840, 2160, 60000),
+            mode(3840, 2160, 120000),
+            mode(2560, 1440, 144000),
+            mode(2560, 1440, 60000),
+            mode(1920, 1080, 60000),
+        ];
+
+        assert_eq!(
+            fallback_cascade(mode(3840, 2160, 144000), &modes),
+            vec![
+                Fallback::Mode(mode(3840, 2160, 120000)),
+                Fallback::Mode(mode(3840, 2160, 60000)),
+                Fallback::Mode(mode(2560, 1440, 144000)),
+                Fallback::Mode(mode(2560, 1440, 60000)),
+                Fallback::Mode(mode(1920, 1080, 60000)),
+                Fallback::Disable,
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_duplicates_and_higher_modes() {
+        let modes = [
+            mode(3840, 2160, 60000),
+            mode(1920, 1080, 60000),
+            mode(1920, 1080, 60000),
+            mode(1280, 720, 60000),
+        ];
+
+        assert_eq!(
+            fallback_cascade(mode(1920, 1080, 60000), &modes),
+            vec![Fallback::Mode(mode(1280, 720, 60000)), Fallback::Disable]
+        );
+    }
+}
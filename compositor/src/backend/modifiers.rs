@@ -0,0 +1,223 @@
+//! Modifier preference
+//!
+//! The allocator picks the modifier of a buffer from the modifiers it is offered, and usually picks a tiled
+//! modifier without compression even when the GPU and the display both support compressed modifiers (such as
+//! Intel CCS, AMD DCC, ARM AFBC or NVIDIA compression). Compression saves a lot of memory bandwidth when the
+//! composited frame is scanned out, so the [`ModifierPolicy`] offers the compressed modifiers on their own first
+//! and only offers every modifier if no compressed buffer could be allocated.
+//!
+//! Some drivers advertise compressed modifiers which are broken on some hardware, so compressed modifiers may be
+//! denied per driver. The policy may be changed while the display server is running, and applies the next time
+//! buffers are allocated.
+
+use rustc_hash::FxHashMap;
+use smithay::backend::allocator::Modifier;
+
+/// The vendor bits of a modifier.
+const VENDOR_SHIFT: u64 = 56;
+
+const VENDOR_INTEL: u64 = 0x01;
+const VENDOR_AMD: u64 = 0x02;
+const VENDOR_NVIDIA: u64 = 0x03;
+const VENDOR_ARM: u64 = 0x08;
+
+/// The Intel modifiers with a compression control surface.
+const INTEL_CCS: &[u64] = &[0x04, 0x05, 0x06, 0x07, 0x08, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f];
+
+/// Whether the buffer uses Delta Color Compression.
+const AMD_DCC: u64 = 1 << 13;
+
+/// Whether the modifier is a 2D block linear layout.
+const NVIDIA_BLOCK_LINEAR: u64 = 0x10;
+
+/// The compression type of a block linear layout.
+const NVIDIA_COMPRESSION_SHIFT: u64 = 23;
+const NVIDIA_COMPRESSION_MASK: u64 = 0x7;
+
+/// The type bits of an ARM modifier, which are 0 for AFBC.
+const ARM_TYPE_SHIFT: u64 = 52;
+const ARM_TYPE_MASK: u64 = 0xf;
+
+/// Whether buffers with the modifier are compressed.
+pub fn is_compressed(modifier: Modifier) -> bool {
+    let modifier = u64::from(modifier);
+    let value = modifier & ((1 << VENDOR_SHIFT) - 1);
+
+    match modifier >> VENDOR_SHIFT {
+        VENDOR_INTEL => INTEL_CCS.contains(&value),
+        VENDOR_AMD => value & AMD_DCC != 0,
+        VENDOR_NVIDIA => {
+            value & NVIDIA_BLOCK_LINEAR != 0 && (value >> NVIDIA_COMPRESSION_SHIFT) & NVIDIA_COMPRESSION_MASK != 0
+        }
+        VENDOR_ARM => (value >> ARM_TYPE_SHIFT) & ARM_TYPE_MASK == 0 && value != 0,
+        _ => false,
+    }
+}
+
+/// Which compressed modifiers of a driver are denied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denied {
+    /// Every compressed modifier.
+    Compressed,
+
+    /// Only the listed modifiers.
+    Modifiers(Vec<Modifier>),
+}
+
+/// Decides which modifiers are offered to the allocator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModifierPolicy {
+    /// Whether compressed modifiers are offered first.
+    pub prefer_compressed: bool,
+
+    /// Denied modifiers by the name of the kernel driver, such as `i915` or `amdgpu`.
+    denied: FxHashMap<String, Denied>,
+}
+
+impl Default for ModifierPolicy {
+    fn default() -> Self {
+        Self {
+            prefer_compressed: true,
+            denied: FxHashMap::default(),
+        }
+    }
+}
+
+impl ModifierPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deny modifiers of the driver, replacing the modifiers denied before.
+    pub fn deny(&mut self, driver: impl Into<String>, denied: Denied) {
+        self.denied.insert(driver.into(), denied);
+    }
+
+    /// Allow every modifier of the driver again.
+    pub fn allow(&mut self, driver: &str) {
+        self.denied.remove(driver);
+    }
+
+    fn is_denied(&self, driver: Option<&str>, modifier: Modifier) -> bool {
+        match driver.and_then(|driver| self.denied.get(driver)) {
+            Some(Denied::Compressed) => is_compressed(modifier),
+            Some(Denied::Modifiers(modifiers)) => modifiers.contains(&modifier),
+            None => false,
+        }
+    }
+
+    /// The lists of modifiers to offer to the allocator for a buffer which is rendered and scanned out.
+    ///
+    /// The modifiers are those supported by both the renderer and the display. The allocation should be tried with
+    /// each list in order until a buffer is allocated. Denied modifiers are never offered, unless every modifier
+    /// is denied. The driver is [`None`] if its name is not known, in which case nothing is denied.
+    pub fn candidates(&self, driver: Option<&str>, modifiers: &[Modifier]) -> Vec<Vec<Modifier>> {
+        let mut allowed = modifiers
+            .iter()
+            .copied()
+            .filter(|&modifier| !self.is_denied(driver, modifier))
+            .collect::<Vec<_>>();
+
+        if allowed.is_empty() {
+            tracing::warn!(?driver, "Every modifier is denied, ignoring the denylist");
+            allowed = modifiers.to_vec();
+        }
+
+        let compressed = allowed
+            .iter()
+            .copied()
+            .filter(|&modifier| is_compressed(modifier))
+            .collect::<Vec<_>>();
+
+        if !self.prefer_compressed || compressed.is_empty() || compressed.len() == allowed.len() {
+            return vec![allowed];
+        }
+
+        vec![compressed, allowed]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::backend::allocator::Modifier;
+
+    use super::{is_compressed, Denied, ModifierPolicy};
+
+    const INTEL_Y_TILED: u64 = 0x0100_0000_0000_0002;
+    const INTEL_Y_TILED_CCS: u64 = 0x0100_0000_0000_0004;
+    const INTEL_GEN12_RC_CCS: u64 = 0x0100_0000_0000_0006;
+    /// GFX9 64K_S_X tiling with DCC.
+    const AMD_DCC: u64 = 0x0200_0000_0000_2019;
+    const AMD_TILED: u64 = 0x0200_0000_0000_0019;
+    const ARM_AFBC: u64 = 0x0800_0000_0000_0001;
+    const ARM_MISC: u64 = 0x0820_0000_0000_0001;
+    /// 2D block linear with compression type 1.
+    const NVIDIA_COMPRESSED: u64 = 0x0300_0000_0080_0014;
+    const NVIDIA_UNCOMPRESSED: u64 = 0x0300_0000_0000_0014;
+
+    fn modifier(value: u64) -> Modifier {
+        Modifier::from(value)
+    }
+
+    #[test]
+    fn compressed_modifiers() {
+        for value in [
+            INTEL_Y_TILED_CCS,
+            INTEL_GEN12_RC_CCS,
+            AMD_DCC,
+            ARM_AFBC,
+            NVIDIA_COMPRESSED,
+        ] {
+            assert!(is_compressed(modifier(value)), "{value:#x} is compressed");
+        }
+
+        for value in [INTEL_Y_TILED, AMD_TILED, ARM_MISC, NVIDIA_UNCOMPRESSED] {
+            assert!(!is_compressed(modifier(value)), "{value:#x} is not compressed");
+        }
+
+        assert!(!is_compressed(Modifier::Linear));
+        assert!(!is_compressed(Modifier::Invalid));
+    }
+
+    #[test]
+    fn compressed_first() {
+        let modifiers = [Modifier::Linear, modifier(INTEL_Y_TILED), modifier(INTEL_Y_TILED_CCS)];
+        let mut policy = ModifierPolicy::new();
+
+        assert_eq!(
+            policy.candidates(Some("i915"), &modifiers),
+            [vec![modifier(INTEL_Y_TILED_CCS)], modifiers.to_vec()]
+        );
+
+        // Without compressed modifiers there is nothing to prefer.
+        assert_eq!(
+            policy.candidates(Some("i915"), &modifiers[..2]),
+            [modifiers[..2].to_vec()]
+        );
+
+        policy.prefer_compressed = false;
+        assert_eq!(policy.candidates(Some("i915"), &modifiers), [modifiers.to_vec()]);
+    }
+
+    #[test]
+    fn denylist() {
+        let modifiers = [modifier(AMD_TILED), modifier(AMD_DCC)];
+        let mut policy = ModifierPolicy::new();
+        policy.deny("amdgpu", Denied::Compressed);
+
+        assert_eq!(
+            policy.candidates(Some("amdgpu"), &modifiers),
+            [vec![modifier(AMD_TILED)]]
+        );
+        // Other drivers and unknown drivers are not affected.
+        assert_eq!(policy.candidates(Some("radeon"), &modifiers).len(), 2);
+        assert_eq!(policy.candidates(None, &modifiers).len(), 2);
+
+        // A buffer is still allocated if every modifier is denied.
+        policy.deny("amdgpu", Denied::Modifiers(modifiers.to_vec()));
+        assert_eq!(policy.candidates(Some("amdgpu"), &modifiers).len(), 2);
+
+        policy.allow("amdgpu");
+        assert_eq!(policy.candidates(Some("amdgpu"), &modifiers).len(), 2);
+    }
+}
@@ -0,0 +1,151 @@
+//! Per-output render scale
+//!
+//! The scene may be composited at a different resolution than the output mode and then scaled to the output.
+//! Rendering below 1.0 reduces the load on weak GPUs at the cost of sharpness, while rendering above 1.0
+//! supersamples the scene, which improves the quality of screen captures.
+//!
+//! The render scale is separate from the output scale advertised to clients. Clients are not told about the
+//! render scale and keep rendering at the output scale.
+//!
+//! Anything drawn after the scene is scaled to the output (such as the cursor) is drawn in output space, so
+//! positions and damage must be converted between render space and output space.
+
+use std::cell::Cell;
+
+use smithay::{
+    output::Output,
+    utils::{Physical, Point, Rectangle, Size},
+};
+
+/// The render scale of an output.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct RenderScale(f64);
+
+impl RenderScale {
+    /// Render at the resolution of the output.
+    pub const ONE: Self = Self(1.0);
+
+    /// The smallest supported render scale.
+    pub const MIN: f64 = 0.25;
+
+    /// The largest supported render scale.
+    ///
+    /// Larger scales quickly exceed the maximum texture size of most GPUs.
+    pub const MAX: f64 = 4.0;
+
+    /// Create a render scale.
+    ///
+    /// Returns [`None`] if the scale is not between [`RenderScale::MIN`] and [`RenderScale::MAX`].
+    pub fn new(scale: f64) -> Option<Self> {
+        (Self::MIN..=Self::MAX).contains(&scale).then_some(Self(scale))
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    /// The size of the buffer the scene is composited into for an output of the specified size.
+    pub fn buffer_size(self, output: Size<i32, Physical>) -> Size<i32, Physical> {
+        (
+            (output.w as f64 * self.0).ceil() as i32,
+            (output.h as f64 * self.0).ceil() as i32,
+        )
+            .into()
+    }
+
+    /// Convert damage in render space to output space.
+    ///
+    /// The damage is rounded outward so that no damaged pixel is missed after filtering.
+    pub fn damage_to_output(self, damage: Rectangle<i32, Physical>) -> Rectangle<i32, Physical> {
+        scale_outward(damage, 1.0 / self.0)
+    }
+
+    /// Convert damage in output space to render space.
+    ///
+    /// The damage is rounded outward so that no damaged pixel is missed after filtering.
+    pub fn damage_to_render(self, damage: Rectangle<i32, Physical>) -> Rectangle<i32, Physical> {
+        scale_outward(damage, self.0)
+    }
+
+    /// Convert a position in output space to render space, such as the position of the cursor.
+    pub fn position_to_render(self, position: Point<f64, Physical>) -> Point<f64, Physical> {
+        (position.x * self.0, position.y * self.0).into()
+    }
+}
+
+impl Default for RenderScale {
+    fn default() -> Self {
+        Self::ONE
+    }
+}
+
+fn scale_outward(rect: Rectangle<i32, Physical>, scale: f64) -> Rectangle<i32, Physical> {
+    let x1 = (rect.loc.x as f64 * scale).floor() as i32;
+    let y1 = (rect.loc.y as f64 * scale).floor() as i32;
+    let x2 = ((rect.loc.x + rect.size.w) as f64 * scale).ceil() as i32;
+    let y2 = ((rect.loc.y + rect.size.h) as f64 * scale).ceil() as i32;
+
+    Rectangle::from_extemities((x1, y1), (x2, y2))
+}
+
+/// Get the render scale of the output.
+pub fn render_scale(output: &Output) -> RenderScale {
+    output
+        .user_data()
+        .get::<Cell<RenderScale>>()
+        .map(Cell::get)
+        .unwrap_or_default()
+}
+
+/// Set the render scale of the output.
+///
+/// Returns [`true`] if the render scale changed.
+pub(crate) fn set_render_scale(output: &Output, scale: RenderScale) -> bool {
+    output.user_data().insert_if_missing(|| Cell::new(RenderScale::ONE));
+    let cell = output.user_data().get::<Cell<RenderScale>>().unwrap();
+    cell.replace(scale) != scale
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::{Physical, Rectangle, Size};
+
+    use super::RenderScale;
+
+    #[test]
+    fn out_of_range() {
+        assert!(RenderScale::new(0.0).is_none());
+        assert!(RenderScale::new(-1.0).is_none());
+        assert!(RenderScale::new(8.0).is_none());
+        assert!(RenderScale::new(f64::NAN).is_none());
+        assert!(RenderScale::new(0.75).is_some());
+    }
+
+    #[test]
+    fn buffer_size() {
+        let output = Size::<i32, Physical>::from((1920, 1080));
+
+        assert_eq!(RenderScale::ONE.buffer_size(output), output);
+        assert_eq!(RenderScale::new(2.0).unwrap().buffer_size(output), (3840, 2160).into());
+        // 1080 * 0.75 = 810, 1920 * 0.75 = 1440
+        assert_eq!(RenderScale::new(0.75).unwrap().buffer_size(output), (1440, 810).into());
+    }
+
+    #[test]
+    fn damage_rounds_outward() {
+        let scale = RenderScale::new(0.75).unwrap();
+        let damage = Rectangle::<i32, Physical>::from_loc_and_size((1, 1), (3, 3));
+
+        // (1, 1)..(4, 4) * 0.75 = (0.75, 0.75)..(3, 3)
+        assert_eq!(
+            scale.damage_to_render(damage),
+            Rectangle::from_loc_and_size((0, 0), (3, 3))
+        );
+
+        // (1, 1)..(4, 4) / 0.75 = (1.33, 1.33)..(5.33, 5.33)
+        assert_eq!(
+            scale.damage_to_output(damage),
+            Rectangle::from_loc_and_size((1, 1), (5, 5))
+        );
+    }
+}
@@ -1,26 +1,61 @@
 //! X11 input and output backend
 
-use calloop::LoopHandle;
+use std::{
+    ffi::{c_char, CStr},
+    fmt::Write as _,
+    time::Instant,
+};
+
+use calloop::{
+    timer::{TimeoutAction, Timer},
+    LoopHandle, RegistrationToken,
+};
 use smithay::{
     backend::{
         allocator::{
             dmabuf::{Dmabuf, DmabufAllocator},
             gbm::GbmAllocator,
+            Fourcc, Modifier,
         },
+        drm::DrmDeviceFd,
         egl::{EGLContext, EGLDisplay},
-        renderer::{element::AsRenderElements, gles::GlesRenderer, utils::draw_render_elements, Bind, Frame, Renderer},
-        x11::{Window, WindowBuilder, X11Backend, X11Event, X11Handle, X11Surface},
+        renderer::{
+            element::{
+                utils::{Relocate, RelocateRenderElement},
+                AsRenderElements,
+            },
+            gles::{ffi, GlesError, GlesRenderer, GlesTexProgram, GlesTexture, Uniform},
+            utils::draw_render_elements,
+            Bind, ExportMem, Frame, ImportDma, ImportMem, Offscreen, Renderer, Texture, TextureMapping,
+        },
+        x11::{AllocateBuffersError, Window, WindowBuilder, X11Backend, X11Error, X11Event, X11Handle, X11Surface},
+    },
+    output::{Mode, Output},
+    reexports::{
+        drm::Device as _,
+        gbm::{self, BufferObjectFlags},
     },
-    reexports::gbm::{self, BufferObjectFlags},
-    utils::{DeviceFd, Rectangle, Transform},
+    utils::{Buffer, DeviceFd, Physical, Point, Rectangle, Scale, Size, Transform},
     wayland::{
         dmabuf::{DmabufGlobal, DmabufState, ImportError},
         shm::ShmState,
     },
 };
-use wayland_server::DisplayHandle;
+use wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle};
 
-use crate::{scene::SceneGraphElement, Aerugo, Loop};
+use crate::{
+    alpha::{self, StraightAlphaElement},
+    backend::{
+        color,
+        modifiers::ModifierPolicy,
+        render_scale::{render_scale, RenderScale},
+    },
+    cursor::{CursorImage, CursorPresentation},
+    rounded::{self, RoundedElement},
+    scene::{Hierarchy, NodeIndex, Scene, SceneElement},
+    thumbnail::{thumbnail_size, Thumbnail},
+    Aerugo, Loop,
+};
 
 #[derive(Debug)]
 pub struct Backend {
@@ -28,10 +63,57 @@ pub struct Backend {
     window: Window,
     renderer: GlesRenderer,
     surface: X11Surface,
+    /// The device buffers of the surface are allocated from.
+    device: gbm::Device<DeviceFd>,
+    /// The name of the kernel driver of the device, such as `i915`.
+    driver: Option<String>,
+    /// The modifiers the renderer can render to.
+    modifiers: Vec<Modifier>,
+    /// The vendor, renderer and version strings of OpenGL.
+    gl_strings: [String; 3],
+    dmabuf_state: DmabufState,
+    _dmabuf_global: DmabufGlobal,
     r#loop: LoopHandle<'static, Loop>,
     display: DisplayHandle,
     shm_state: ShmState,
     shutdown: bool,
+    /// Whether a frame is being drawn or waiting to be presented.
+    frame_pending: bool,
+    /// Whether the output is enabled. The window is unmapped while the output is disabled.
+    enabled: bool,
+    /// Whether the display of the output is on.
+    ///
+    /// The window stays mapped and is drawn black while the display is off, so input still reaches the display
+    /// server to turn the display on again.
+    dpms_on: bool,
+    /// Whether the window was drawn black since the display was turned off.
+    blanked: bool,
+    /// The start of the clock of frame times, which are in milliseconds.
+    epoch: Instant,
+    /// The time the previous frame was presented.
+    presented: u32,
+    /// Timer which draws once the committed transactions time out.
+    transaction_timer: Option<RegistrationToken>,
+    /// Offscreen buffer the scene is composited into if the render scale is not 1, the output is zoomed or the colors
+    /// are transformed.
+    scaled_buffer: Option<GlesTexture>,
+    /// Draws surfaces with straight alpha.
+    straight_alpha: GlesTexProgram,
+    /// Draws surfaces with rounded corners.
+    rounded_corners: GlesTexProgram,
+    /// Draws the composited frame with the color transform, since X11 cannot offload it to the display hardware.
+    color_transform: GlesTexProgram,
+    /// The cursor image drawn above the scene, with its texture once uploaded.
+    cursor: Option<(CursorImage, Option<GlesTexture>)>,
+    cursor_presentation: CursorPresentation,
+}
+
+// An element drawn by the X11 backend.
+smithay::render_elements! {
+    OutputElement<=GlesRenderer>;
+    Scene=SceneElement,
+    StraightAlpha=StraightAlphaElement<SceneElement>,
+    Rounded=RoundedElement<SceneElement>,
 }
 
 impl dyn super::Backend {
@@ -54,6 +136,8 @@ impl Backend {
         //   available. This will however require a way to enumerate what formats the window could be created
         //   with.
         let window = WindowBuilder::new().title("Aerugo").build(&x11).unwrap();
+        // The display server draws the cursor, so the cursor of the X server is hidden over the window.
+        window.set_cursor_visible(false);
         window.map();
 
         // Get the drm node for buffer allocation and initializing EGL.
@@ -62,19 +146,44 @@ impl Backend {
         // - This should return just the path to the drm device. For the legacy DRI3 fallback, there should be
         //   a separate function to get the DRM file descriptor in that case.
         let (_, fd) = x11.drm_node().expect("Failed to get DRM node used by X server");
-        let device = gbm::Device::new(DeviceFd::from(fd)).unwrap();
+        let fd = DeviceFd::from(fd);
+        let driver = driver_name(&fd);
+        let device = gbm::Device::new(fd).unwrap();
         let egl = EGLDisplay::new(device.clone()).unwrap();
         let context = EGLContext::new(&egl).unwrap();
 
-        let surface = x11
-            .create_surface(
-                &window,
-                DmabufAllocator(GbmAllocator::new(device.clone(), BufferObjectFlags::RENDERING)),
-                context.dmabuf_render_formats().iter().map(|format| format.modifier),
-            )
-            .unwrap();
+        let mut modifiers = context
+            .dmabuf_render_formats()
+            .iter()
+            .map(|format| format.modifier)
+            .collect::<Vec<_>>();
+        modifiers.sort_unstable_by_key(|&modifier| u64::from(modifier));
+        modifiers.dedup();
 
-        let renderer = unsafe { GlesRenderer::new(context) }.unwrap();
+        // The surface is created again if the configured policy differs from the default.
+        let surface = create_surface(
+            &x11,
+            &window,
+            &device,
+            driver.as_deref(),
+            &modifiers,
+            &ModifierPolicy::default(),
+        )
+        .expect("Failed to create X11 surface");
+
+        let mut renderer = unsafe { GlesRenderer::new(context) }.unwrap();
+        let straight_alpha =
+            alpha::compile_straight_alpha_shader(&mut renderer).expect("Failed to compile the straight alpha shader");
+        let rounded_corners = rounded::compile_rounded_corners_shader(&mut renderer)
+            .expect("Failed to compile the rounded corners shader");
+        let color_transform =
+            color::compile_color_transform_shader(&mut renderer).expect("Failed to compile the color transform shader");
+
+        let gl_strings = renderer.with_context(gl_strings).unwrap_or_default();
+
+        let mut dmabuf_state = DmabufState::new();
+        let dmabuf_global =
+            dmabuf_state.create_global::<Aerugo>(&display, renderer.dmabuf_formats().collect::<Vec<_>>());
 
         r#loop.insert_source(backend, dispatch_x11_event).unwrap();
 
@@ -86,21 +195,288 @@ impl Backend {
             // TODO: Additional renderer shm formats
             shm_state: ShmState::new::<Aerugo>(&display, Vec::with_capacity(2)),
             shutdown: false,
+            frame_pending: false,
+            enabled: true,
+            dpms_on: true,
+            blanked: false,
+            epoch: Instant::now(),
+            presented: 0,
+            transaction_timer: None,
+            scaled_buffer: None,
+            straight_alpha,
+            rounded_corners,
+            color_transform,
+            cursor: None,
+            cursor_presentation: CursorPresentation::Visible,
             renderer,
             surface,
+            device,
+            driver,
+            modifiers,
+            gl_strings,
+            dmabuf_state,
+            _dmabuf_global: dmabuf_global,
         })
     }
 }
 
+impl Backend {
+    /// The time in milliseconds since the backend was created.
+    fn time(&self) -> u32 {
+        self.epoch.elapsed().as_millis() as u32
+    }
+
+    /// Whether the window is mapped, so frames are drawn.
+    fn is_visible(&self) -> bool {
+        self.enabled
+    }
+
+    fn update_visibility(&mut self) {
+        if self.is_visible() {
+            self.window.map();
+        } else {
+            self.window.unmap();
+        }
+    }
+
+    /// Wrap an element of the scene in the element drawing it with the right shader.
+    fn output_element(&self, element: SceneElement) -> OutputElement {
+        if let Some(mask) = element.corner_mask() {
+            return RoundedElement::new(element, self.rounded_corners.clone(), mask).into();
+        }
+
+        match element.needs_premultiply() {
+            true => StraightAlphaElement::new(element, self.straight_alpha.clone()).into(),
+            false => OutputElement::from(element),
+        }
+    }
+
+    /// Render the graph at the scale into an offscreen buffer of the size and read back the premultiplied
+    /// ARGB8888 pixels, row by row. The graph is moved by the offset, so the offset is drawn at the origin.
+    fn render_offscreen(
+        &mut self,
+        hir: Hierarchy<'_>,
+        size: Size<i32, Physical>,
+        scale: f64,
+        offset: Point<i32, Physical>,
+    ) -> Result<Vec<u8>, GlesError> {
+        let buffer_size = size.to_logical(1).to_buffer(1, Transform::Normal);
+        let buffer = Offscreen::<GlesTexture>::create_buffer(&mut self.renderer, Fourcc::Abgr8888, buffer_size)?;
+        self.renderer.bind(buffer)?;
+
+        let elems: Vec<SceneElement> = hir.render_elements(&mut self.renderer, (0, 0).into(), Scale::from(scale), 1.0);
+        let elems = elems
+            .into_iter()
+            .map(|element| {
+                let element = self.output_element(element);
+                RelocateRenderElement::from_element(element, (-offset.x, -offset.y), Relocate::Relative)
+            })
+            .collect::<Vec<_>>();
+
+        let damage = [Rectangle::from_loc_and_size((0, 0), size)];
+
+        {
+            let mut frame = self.renderer.render(size, Transform::Normal)?;
+            frame.clear([0.0, 0.0, 0.0, 0.0], &damage)?;
+            draw_render_elements::<GlesRenderer, _, _>(&mut frame, scale, &elems, &damage)?;
+            frame.finish()?;
+        }
+
+        let mapping = self
+            .renderer
+            .copy_framebuffer(Rectangle::from_loc_and_size((0, 0), buffer_size), Fourcc::Argb8888)?;
+        let flipped = mapping.flipped();
+        let pixels = self.renderer.map_texture(&mapping)?;
+
+        // OpenGL reads the framebuffer from the bottom row up.
+        let stride = size.w as usize * 4;
+        Ok(match flipped {
+            true => pixels.chunks_exact(stride).rev().flatten().copied().collect(),
+            false => pixels.to_vec(),
+        })
+    }
+}
+
+/// An error while drawing a frame, after which the frame is skipped.
+#[derive(Debug, thiserror::Error)]
+enum DrawError {
+    #[error(transparent)]
+    Render(#[from] GlesError),
+
+    #[error("failed to allocate a buffer: {0}")]
+    Allocate(#[from] AllocateBuffersError),
+
+    #[error("failed to present the buffer: {0}")]
+    Present(#[from] X11Error),
+}
+
+impl Backend {
+    /// Draw the elements and the cursor image to the window, then present the frame.
+    ///
+    /// The scene is composited into an offscreen buffer if it is rendered at a different scale than the window, only
+    /// part of the scene is magnified to fill the window, or the colors are transformed.
+    fn render_frame(
+        &mut self,
+        elems: &[OutputElement],
+        cursor: Option<&(GlesTexture, Point<i32, Physical>, f32)>,
+        color_uniforms: Option<Vec<Uniform<'static>>>,
+        zoom: Option<Rectangle<f64, Buffer>>,
+        scale: RenderScale,
+        output_size: Size<i32, Physical>,
+    ) -> Result<(), DrawError> {
+        let render_size = scale.buffer_size(output_size);
+        let buffer_size = render_size.to_logical(1).to_buffer(1, Transform::Normal);
+
+        if scale != RenderScale::ONE || zoom.is_some() || color_uniforms.is_some() {
+            let buffer = match self.scaled_buffer.take() {
+                Some(buffer) if buffer.size() == buffer_size => buffer,
+                _ => Offscreen::<GlesTexture>::create_buffer(&mut self.renderer, Fourcc::Abgr8888, buffer_size)?,
+            };
+
+            self.renderer.bind(buffer.clone())?;
+            self.scaled_buffer = Some(buffer);
+        } else {
+            self.scaled_buffer = None;
+            let (buffer, _age) = self.surface.buffer()?;
+            self.renderer.bind(buffer)?;
+        }
+
+        {
+            let mut frame = self.renderer.render(render_size, Transform::Normal)?;
+            frame.clear(
+                [0.8, 0.8, 0.8, 1.0],
+                &[Rectangle::from_loc_and_size((0, 0), render_size)],
+            )?;
+            draw_render_elements::<GlesRenderer, _, _>(
+                &mut frame,
+                scale.get(),
+                elems,
+                &[Rectangle::from_loc_and_size((0, 0), (i32::MAX, i32::MAX))],
+            )?;
+
+            // The scene is drawn at the size of the window, so the cursor is drawn right away.
+            if let (None, Some(cursor)) = (&self.scaled_buffer, cursor) {
+                draw_cursor(&mut frame, cursor, render_size)?;
+            }
+
+            frame.finish()?;
+        }
+
+        // Scale the offscreen buffer, or the zoomed part of it, to the window.
+        if let Some(texture) = self.scaled_buffer.clone() {
+            let (buffer, _age) = self.surface.buffer()?;
+            self.renderer.bind(buffer)?;
+
+            // The scene is composited into the offscreen buffer every frame, so the whole window is drawn.
+            let damage = [Rectangle::from_loc_and_size((0, 0), output_size)];
+            let mut frame = self.renderer.render(output_size, Transform::Normal)?;
+
+            // The cursor is transformed too, so it fades out with the output.
+            if let Some(uniforms) = color_uniforms {
+                frame.override_default_tex_program(self.color_transform.clone(), uniforms);
+            }
+
+            frame.render_texture_from_to(
+                &texture,
+                zoom.unwrap_or_else(|| Rectangle::from_loc_and_size((0., 0.), buffer_size.to_f64())),
+                damage[0],
+                &damage,
+                Transform::Normal,
+                1.0,
+            )?;
+
+            if let Some(cursor) = cursor {
+                draw_cursor(&mut frame, cursor, output_size)?;
+            }
+
+            frame.clear_tex_program_override();
+            frame.finish()?;
+        }
+
+        self.surface.submit()?;
+        Ok(())
+    }
+
+    /// Draw the window black while the display is off.
+    fn draw_black(&mut self) -> Result<(), DrawError> {
+        let size = Size::<i32, Physical>::from((self.window.size().w as i32, self.window.size().h as i32));
+        let (buffer, _age) = self.surface.buffer()?;
+        self.renderer.bind(buffer)?;
+
+        let mut frame = self.renderer.render(size, Transform::Normal)?;
+        frame.clear([0.0, 0.0, 0.0, 1.0], &[Rectangle::from_loc_and_size((0, 0), size)])?;
+        frame.finish()?;
+
+        self.surface.submit()?;
+        self.frame_pending = true;
+        Ok(())
+    }
+}
+
+/// Create the surface of the window, allocating buffers with the modifiers offered by the policy.
+///
+/// The X server only presents buffers with the modifiers it supports, so creating the surface with only the
+/// compressed modifiers may fail, in which case every modifier is offered.
+fn create_surface(
+    x11: &X11Handle,
+    window: &Window,
+    device: &gbm::Device<DeviceFd>,
+    driver: Option<&str>,
+    modifiers: &[Modifier],
+    policy: &ModifierPolicy,
+) -> Option<X11Surface> {
+    policy.candidates(driver, modifiers).into_iter().find_map(|modifiers| {
+        x11.create_surface(
+            window,
+            DmabufAllocator(GbmAllocator::new(device.clone(), BufferObjectFlags::RENDERING)),
+            modifiers.into_iter(),
+        )
+        .ok()
+    })
+}
+
+/// The vendor, renderer and version strings of the current OpenGL context.
+fn gl_strings(gl: &ffi::Gles2) -> [String; 3] {
+    [ffi::VENDOR, ffi::RENDERER, ffi::VERSION].map(|name| {
+        let string = unsafe { gl.GetString(name) };
+
+        match string.is_null() {
+            true => String::new(),
+            false => unsafe { CStr::from_ptr(string as *const c_char) }
+                .to_string_lossy()
+                .into_owned(),
+        }
+    })
+}
+
+/// The name of the kernel driver of the DRM device, such as `i915`.
+fn driver_name(fd: &DeviceFd) -> Option<String> {
+    let driver = DrmDeviceFd::new(fd.clone()).get_driver().ok()?;
+    Some(driver.name().to_string_lossy().into_owned())
+}
+
 fn dispatch_x11_event(event: X11Event, _: &mut (), aerugo: &mut Loop) {
     match event {
         X11Event::Refresh { window_id: _ } => draw(aerugo),
-        X11Event::Input(_) => {}
+        X11Event::Input(event) => aerugo.input_event(event),
         X11Event::Resized {
             new_size: _,
             window_id: _,
         } => draw(aerugo),
-        X11Event::PresentCompleted { window_id: _ } => draw(aerugo),
+        X11Event::PresentCompleted { window_id: _ } => {
+            let backend = aerugo.comp.backend.x11_mut();
+            backend.frame_pending = false;
+            backend.presented = backend.time();
+            // The display was turned off while a frame was pending.
+            let blank = !backend.dpms_on && !backend.blanked;
+            aerugo.comp.session_lock_frame_presented();
+
+            // Only draw if something changed or the wm is animating. Otherwise the compositor would wake up every
+            // frame while idle.
+            if blank || aerugo.comp.damaged || aerugo.comp.frame_requested {
+                draw(aerugo)
+            }
+        }
         X11Event::CloseRequested { window_id: _ } => {
             // TODO: shutdown based on output counts
             let backend: &mut Backend = &mut aerugo.comp.backend.downcast_mut().unwrap();
@@ -110,16 +486,187 @@ fn dispatch_x11_event(event: X11Event, _: &mut (), aerugo: &mut Loop) {
     }
 }
 
+/// The mode of the output, which is the size of the window.
+///
+/// X11 does not tell the refresh rate of the window, so 60Hz is assumed.
+fn window_mode(window: &Window) -> Mode {
+    let size = window.size();
+
+    Mode {
+        size: (size.w as i32, size.h as i32).into(),
+        refresh: 60_000,
+    }
+}
+
+/// Match the mode of the output to the size of the window, which may be resized at any time.
+fn update_output_mode(aerugo: &mut Loop) {
+    let mode = window_mode(&aerugo.comp.backend.x11_mut().window);
+    let output = aerugo.comp.output.clone();
+    let current = output.current_mode();
+
+    if current == Some(mode) {
+        return;
+    }
+
+    if let Some(current) = current {
+        output.delete_mode(current);
+    }
+
+    output.change_current_state(Some(mode), None, None, None);
+    output.set_preferred(mode);
+    aerugo.comp.output_changed(&output);
+}
+
 fn draw(aerugo: &mut Loop) {
+    let started = Instant::now();
+    update_output_mode(aerugo);
+
+    // Nothing is shown while the window is unmapped. A frame is drawn once the window is mapped again.
     let backend = aerugo.comp.backend.x11_mut();
-    let (buffer, _age) = backend.surface.buffer().unwrap();
-    backend.renderer.bind(buffer).unwrap();
+    if !backend.is_visible() {
+        backend.frame_pending = false;
+
+        // Nothing is visible, so the screen locker does not need to wait for a frame.
+        aerugo.comp.session_lock_frame_drawn();
+        aerugo.comp.session_lock_frame_presented();
+        return;
+    }
+
+    // The scene is not drawn while the display is off, and the screen locker does not need to wait for a frame.
+    if !backend.dpms_on {
+        backend.frame_pending = false;
+
+        if !backend.blanked {
+            match backend.draw_black() {
+                Ok(()) => backend.blanked = true,
+                Err(err) => tracing::warn!(%err, "Failed to draw the window black"),
+            }
+        }
+
+        aerugo.comp.session_lock_frame_drawn();
+        aerugo.comp.session_lock_frame_presented();
+        return;
+    }
+
+    backend.blanked = false;
+
+    // Keep showing the previous frame until every toplevel of the committed transactions has committed. The frame
+    // is scheduled again once the transactions finish, or by the timer once they time out.
+    if aerugo.comp.transactions.is_blocking(started) {
+        let deadline = aerugo.comp.transactions.next_deadline();
+        let backend = aerugo.comp.backend.x11_mut();
+        backend.frame_pending = false;
+
+        if let (None, Some(deadline)) = (backend.transaction_timer, deadline) {
+            let timer = Timer::from_deadline(deadline);
+            let token = backend
+                .r#loop
+                .insert_source(timer, |_, _, aerugo| {
+                    aerugo.comp.backend.x11_mut().transaction_timer = None;
+                    aerugo.comp.backend.schedule_render();
+                    TimeoutAction::Drop
+                })
+                .expect("Failed to insert timer");
+            backend.transaction_timer = Some(token);
+        }
+
+        return;
+    }
+
+    let output = aerugo.comp.output.clone();
+    let presented = aerugo.comp.backend.x11_mut().presented;
+    aerugo.comp.frame_started(&output, presented);
+    aerugo.comp.damaged = false;
+    let scale = render_scale(&aerugo.comp.output);
+    // A cursor drawn by a client is a surface placed at the pointer, drawn above the scene.
+    let cursor_surface = aerugo
+        .comp
+        .cursor_surface()
+        .map(|(surface, hotspot)| (surface.clone(), hotspot));
+    // The color transform, such as the idle fade, is applied while the composited frame is drawn to the window.
+    let color_uniforms = (!aerugo.comp.color_offloaded && !aerugo.comp.applied_color_transform.is_identity())
+        .then(|| color::shader_uniforms(&aerugo.comp.applied_color_transform));
+    let backend = aerugo.comp.backend.x11_mut();
+    backend.frame_pending = true;
+
+    let output_size = Size::<i32, Physical>::from((backend.window.size().w as i32, backend.window.size().h as i32));
+    let buffer_size = scale
+        .buffer_size(output_size)
+        .to_logical(1)
+        .to_buffer(1, Transform::Normal);
+    let zoom = aerugo.comp.zoom.source(buffer_size.to_f64());
+
+    let cursor_alpha = match backend.cursor_presentation {
+        CursorPresentation::Visible => Some(1.0),
+        CursorPresentation::Fading(alpha) => Some(alpha),
+        CursorPresentation::Hidden => None,
+    };
+
+    // The position of the pointer in output space.
+    let output_scale = aerugo.comp.output.current_scale().integer_scale() as f64;
+    let pointer =
+        (aerugo.comp.pointer_location - aerugo.comp.output.current_location().to_f64()).to_physical(output_scale);
+
+    let cursor_surface = cursor_surface.filter(|_| cursor_alpha.is_some());
+
+    if let Some((surface, hotspot)) = &cursor_surface {
+        let location = pointer - hotspot.to_f64().to_physical(output_scale);
+
+        if let Some(index) = aerugo.comp.scene.get_surface_tree_index(surface.clone()) {
+            aerugo
+                .comp
+                .scene
+                .set_node_offset(NodeIndex::SurfaceTree(index), location.to_i32_round());
+        }
+    }
+
+    // Upload the cursor image from the theme or the wm, which is drawn in window space so it stays sharp at any
+    // render scale.
+    let cursor = match (cursor_alpha, &mut backend.cursor) {
+        (Some(alpha), Some((image, texture))) => {
+            if texture.is_none() {
+                *texture = backend
+                    .renderer
+                    .import_memory(
+                        &image.pixels,
+                        Fourcc::Argb8888,
+                        (image.width as i32, image.height as i32).into(),
+                        false,
+                    )
+                    .map_err(|err| tracing::warn!(?err, "Failed to upload the cursor image"))
+                    .ok();
+            }
+
+            let position = scene_to_window(pointer, scale, buffer_size, output_size, zoom);
+            let hotspot = Point::<f64, Physical>::from((image.xhot as f64, image.yhot as f64));
+            texture
+                .clone()
+                .map(|texture| (texture, (position - hotspot).to_i32_round(), alpha))
+        }
+        _ => None,
+    };
+
+    let cursor_elems: Vec<SceneElement> = cursor_surface
+        .and_then(|(surface, _)| aerugo.comp.scene.get_surface_graph(&surface))
+        .map(|hir| hir.render_elements(&mut backend.renderer, (0, 0).into(), Scale::from(scale.get()), 1.0))
+        .unwrap_or_default();
+
+    // Toplevels must not be visible while the session is locked, so only the lock surface is drawn.
+    let hir = match &aerugo.comp.session_lock {
+        Some(session_lock) => session_lock
+            .surface()
+            .and_then(|surface| aerugo.comp.scene.get_surface_graph(surface)),
+        None => aerugo.comp.scene.get_graph(&aerugo.comp.output),
+    };
 
-    let elems: Vec<SceneGraphElement> = if let Some(hir) = aerugo.comp.scene.get_graph(&aerugo.comp.output) {
+    let elems: Vec<SceneElement> = if let Some(hir) = hir {
         hir.render_elements(
             &mut backend.renderer,
             (0, 0).into(),
-            smithay::utils::Scale { x: 1., y: 1. },
+            Scale {
+                x: scale.get(),
+                y: scale.get(),
+            },
             1.0,
         )
         .into()
@@ -127,37 +674,61 @@ fn draw(aerugo: &mut Loop) {
         Vec::new()
     };
 
-    {
-        let mut frame = backend
-            .renderer
-            .render(
-                (backend.window.size().w as i32, backend.window.size().h as i32).into(),
-                Transform::Normal,
-            )
-            .unwrap();
-
-        frame
-            .clear(
-                [0.8, 0.8, 0.8, 1.0],
-                &[Rectangle::from_loc_and_size(
-                    (0, 0),
-                    (backend.window.size().w as i32, backend.window.size().h as i32),
-                )],
-            )
-            .unwrap();
-
-        draw_render_elements::<GlesRenderer, _, _>(
-            &mut frame,
-            1.0,
-            &elems,
-            &[Rectangle::from_loc_and_size((0, 0), (i32::MAX, i32::MAX))],
-        )
-        .unwrap();
+    let elems = cursor_elems
+        .into_iter()
+        .chain(elems)
+        .map(|element| backend.output_element(element))
+        .collect::<Vec<_>>();
 
-        frame.finish().unwrap();
+    if let Err(err) = backend.render_frame(&elems, cursor.as_ref(), color_uniforms, zoom, scale, output_size) {
+        tracing::warn!(%err, "Failed to draw a frame");
+        backend.frame_pending = false;
+        aerugo.comp.damaged = true;
+        return;
     }
 
-    backend.surface.submit().unwrap();
+    let time = backend.time();
+    aerugo.comp.session_lock_frame_drawn();
+    aerugo.comp.frame_drawn(&output, started.elapsed());
+    aerugo.comp.send_frame_callbacks(time);
+}
+
+/// Map a position in output space to the window, through the render scale and the zoomed part of the buffer the
+/// scene is composited into.
+fn scene_to_window(
+    position: Point<f64, Physical>,
+    scale: RenderScale,
+    buffer_size: Size<i32, Buffer>,
+    window_size: Size<i32, Physical>,
+    zoom: Option<Rectangle<f64, Buffer>>,
+) -> Point<f64, Physical> {
+    let position = scale.position_to_render(position);
+    let source = zoom.unwrap_or_else(|| Rectangle::from_loc_and_size((0., 0.), buffer_size.to_f64()));
+
+    (
+        (position.x - source.loc.x) * window_size.w as f64 / source.size.w.max(1.0),
+        (position.y - source.loc.y) * window_size.h as f64 / source.size.h.max(1.0),
+    )
+        .into()
+}
+
+/// Draw the texture of the cursor image at its location in the window with the alpha.
+fn draw_cursor<F: Frame<TextureId = GlesTexture>>(
+    frame: &mut F,
+    (texture, location, alpha): &(GlesTexture, Point<i32, Physical>, f32),
+    size: Size<i32, Physical>,
+) -> Result<(), F::Error> {
+    let texture_size = texture.size();
+    let destination = Rectangle::from_loc_and_size(*location, (texture_size.w, texture_size.h));
+
+    frame.render_texture_from_to(
+        texture,
+        Rectangle::from_loc_and_size((0., 0.), texture_size.to_f64()),
+        destination,
+        &[Rectangle::from_loc_and_size((0, 0), size)],
+        Transform::Normal,
+        *alpha,
+    )
 }
 
 impl crate::backend::Backend for Backend {
@@ -166,14 +737,117 @@ impl crate::backend::Backend for Backend {
     }
 
     fn dmabuf_state(&mut self) -> &mut DmabufState {
-        todo!("X11 does not initialize the dmabuf global yet")
+        &mut self.dmabuf_state
+    }
+
+    fn dmabuf_imported(&mut self, _global: &DmabufGlobal, dmabuf: Dmabuf) -> Result<(), ImportError> {
+        match self.renderer.import_dmabuf(&dmabuf, None) {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                tracing::debug!(%err, "Failed to import a client dmabuf");
+                Err(ImportError::Failed)
+            }
+        }
     }
 
-    fn dmabuf_imported(&mut self, _global: &DmabufGlobal, _dmabuf: Dmabuf) -> Result<(), ImportError> {
-        todo!("X11 does not initialize the dmabuf global yet")
+    fn schedule_render(&mut self) {
+        // The next frame will be drawn when the pending frame is presented.
+        if self.frame_pending {
+            return;
+        }
+
+        self.frame_pending = true;
+        self.r#loop.insert_idle(draw);
     }
 
     fn should_shutdown(&self) -> bool {
         self.shutdown
     }
+
+    fn set_output_mode(&mut self, _output: &Output, mode: Mode) -> bool {
+        // The window is sized by the X server, so only the current size can be set.
+        mode == window_mode(&self.window)
+    }
+
+    fn set_output_enabled(&mut self, _output: &Output, enabled: bool) -> bool {
+        self.enabled = enabled;
+        self.update_visibility();
+        true
+    }
+
+    fn set_output_dpms(&mut self, _output: &Output, on: bool) -> bool {
+        self.dpms_on = on;
+        self.blanked = false;
+        self.schedule_render();
+        true
+    }
+
+    fn set_cursor_presentation(&mut self, presentation: CursorPresentation) {
+        self.cursor_presentation = presentation;
+    }
+
+    fn set_cursor_image(&mut self, image: Option<&CursorImage>) {
+        // The texture is uploaded again when the next frame is drawn.
+        if self.cursor.as_ref().map(|(current, _)| current) != image {
+            self.cursor = image.map(|image| (image.clone(), None));
+        }
+    }
+
+    fn render_thumbnail(
+        &mut self,
+        scene: &Scene,
+        surface: &WlSurface,
+        max_size: Size<i32, Physical>,
+    ) -> Option<Thumbnail> {
+        let hir = scene.get_surface_graph(surface)?;
+        let geometry = scene.surface_tree_geometry(scene.get_surface_tree_index(surface.clone())?)?;
+        let size = thumbnail_size(geometry.size, max_size);
+        let scale = size.w as f64 / geometry.size.w.max(1) as f64;
+        let offset = geometry.loc.to_f64().upscale(scale).to_i32_round();
+
+        let pixels = self
+            .render_offscreen(hir, size, scale, offset)
+            .map_err(|err| tracing::warn!(?err, "Failed to render a thumbnail"))
+            .ok()?;
+
+        Some(Thumbnail {
+            size,
+            pixels: pixels.into(),
+        })
+    }
+
+    fn debug_info(&self) -> String {
+        let [vendor, renderer, version] = &self.gl_strings;
+        let modifiers = self
+            .modifiers
+            .iter()
+            .map(|&modifier| format!("{:#x}", u64::from(modifier)))
+            .collect::<Vec<_>>();
+
+        let mut info = String::from("backend: x11\n");
+        let _ = writeln!(info, "driver: {}", self.driver.as_deref().unwrap_or("unknown"));
+        let _ = writeln!(info, "GL vendor: {vendor}");
+        let _ = writeln!(info, "GL renderer: {renderer}");
+        let _ = writeln!(info, "GL version: {version}");
+        let _ = writeln!(info, "render modifiers: {}", modifiers.join(", "));
+        info
+    }
+
+    fn set_modifier_policy(&mut self, policy: &ModifierPolicy) {
+        // The buffers of the surface are allocated when it is created, so the surface is replaced.
+        let Some(surface) = create_surface(
+            &self.x11,
+            &self.window,
+            &self.device,
+            self.driver.as_deref(),
+            &self.modifiers,
+            policy,
+        ) else {
+            tracing::warn!("Failed to create the X11 surface with the modifier policy, keeping the previous surface");
+            return;
+        };
+
+        self.surface = surface;
+        self.schedule_render();
+    }
 }
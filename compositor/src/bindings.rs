@@ -0,0 +1,99 @@
+//! Bindings from the configuration
+//!
+//! Most bindings are owned by the wm, but the configuration may bind chords to commands, such as a launcher or a
+//! screenshot tool, so they work the same with every wm. The chords are written like the emergency chords and are
+//! matched after them, before keys reach the wm, so a wm binding of the same chord never triggers. They are not
+//! matched while the session is locked, since the command would be shown over the lock screen.
+
+use std::{process::Command, thread};
+
+use smithay::input::keyboard::ModifiersState;
+
+use crate::{
+    emergency::{Chord, ChordModifiers},
+    Loop,
+};
+
+/// Chords which spawn a command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpawnBindings {
+    bindings: Vec<(Chord, Vec<String>)>,
+}
+
+impl SpawnBindings {
+    /// Bind each chord to a command and its arguments.
+    pub fn new(bindings: Vec<(Chord, Vec<String>)>) -> Self {
+        Self { bindings }
+    }
+
+    /// The command of the chord, if the key completes a chord.
+    pub fn command(&self, modifiers: ChordModifiers, sym: u32) -> Option<&[String]> {
+        self.bindings
+            .iter()
+            .find(|(chord, _)| chord.sym == sym && chord.modifiers == modifiers)
+            .map(|(_, command)| command.as_slice())
+    }
+}
+
+impl Loop {
+    /// The command bound to the key by the configuration, unless the session is locked.
+    pub(crate) fn spawn_binding(&self, modifiers: &ModifiersState, sym: u32) -> Option<Vec<String>> {
+        if self.comp.is_session_locked() {
+            return None;
+        }
+
+        let command = self.bindings.command(ChordModifiers::from_state(modifiers), sym)?;
+        Some(command.to_vec())
+    }
+
+    /// Spawn a command bound by the configuration.
+    pub(crate) fn spawn_bound_command(&mut self, command: &[String]) {
+        let Some((program, args)) = command.split_first() else {
+            return;
+        };
+
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        self.environment.apply(&mut cmd);
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                tracing::warn!(%err, program, "Failed to spawn the command of a binding");
+                return;
+            }
+        };
+
+        tracing::debug!(program, pid = child.id(), "Spawned the command of a binding");
+
+        // Wait for the command on another thread so the process is reaped when it exits.
+        let waited = thread::Builder::new().name("Bound command".into()).spawn(move || {
+            let _ = child.wait();
+        });
+
+        if let Err(err) = waited {
+            tracing::warn!(%err, "Failed to wait for the command of a binding");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpawnBindings;
+    use crate::emergency::{parse_chord, ChordModifiers};
+
+    #[test]
+    fn command_of_chord() {
+        let launcher = parse_chord("logo+d").unwrap();
+        let bindings = SpawnBindings::new(vec![(launcher, vec!["fuzzel".into()])]);
+
+        assert_eq!(
+            bindings.command(ChordModifiers::LOGO, launcher.sym),
+            Some(["fuzzel".to_owned()].as_slice())
+        );
+        assert_eq!(
+            bindings.command(ChordModifiers::LOGO | ChordModifiers::SHIFT, launcher.sym),
+            None
+        );
+    }
+}
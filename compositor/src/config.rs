@@ -0,0 +1,1260 @@
+//! Configuration file
+//!
+//! The configuration is a TOML file read from `$XDG_CONFIG_HOME/aerugo/config.toml`, falling back to
+//! `~/.config/aerugo/config.toml`:
+//!
+//! ```toml
+//! wm = "~/.local/share/aerugo/tiling.wasm"
+//! terminal = "foot --server"
+//! metrics = "unix:/run/user/1000/aerugo-metrics"
+//! # Milliseconds, or 0 to never draw placeholders.
+//! placeholder-timeout = 500
+//!
+//! [output.DP-1]
+//! scale = 1.5
+//! position = [1920, 0]
+//! transform = "90"
+//! subpixel = "rgb"
+//!
+//! [[rule]]
+//! app-id = '^org\.mozilla\.firefox$'
+//! workspace = "web"
+//! size = [1280, 720]
+//!
+//! [cursor]
+//! theme = "Adwaita"
+//! size = 32
+//! hide-while-typing = true
+//! # Milliseconds without pointer motion before the cursor fades out, or 0 to never fade out.
+//! idle-timeout = 5000
+//! fade-duration = 300
+//!
+//! # Dim and then turn off the outputs when there is no input.
+//! [idle]
+//! # Seconds, or 0 to never dim the outputs.
+//! dim-timeout = 300
+//! # Milliseconds.
+//! fade-duration = 5000
+//!
+//! # Compressed modifiers save memory bandwidth, but are broken with some drivers.
+//! [modifiers]
+//! prefer-compressed = true
+//! deny-compressed = ["i915"]
+//!
+//! # Locked when the display server starts.
+//! [keyboard]
+//! numlock = true
+//! capslock = false
+//! # Repeats per second and milliseconds before a held key repeats.
+//! repeat-rate = 25
+//! repeat-delay = 200
+//!
+//! # Spawned by the display server before keys reach the wm. Arguments are split at whitespace, without a shell.
+//! [bindings]
+//! "logo+d" = "fuzzel"
+//! "Print" = "grim -t png"
+//!
+//! [emergency]
+//! exit = "ctrl+alt+shift+Escape"
+//! reload-wm = "none"
+//!
+//! # Exported to every client the display server spawns.
+//! [environment]
+//! toolkit-hints = true
+//! update-activation-environment = true
+//!
+//! [environment.vars]
+//! QT_QPA_PLATFORMTHEME = "gtk3"
+//!
+//! # Without the token, IPC clients may only query state and subscribe to events.
+//! [ipc]
+//! configure-token = "correct horse battery staple"
+//!
+//! # Privileged globals every client can see.
+//! [policy]
+//! default = ["foreign-toplevel-list"]
+//!
+//! [[policy.allow]]
+//! executable = "/usr/bin/waybar"
+//! globals = ["layer-shell"]
+//!
+//! [[policy.deny]]
+//! sandbox-engine = "org.flatpak"
+//! globals = ["foreign-toplevel-list"]
+//! ```
+//!
+//! A file which is not valid TOML or has a setting of the wrong type is rejected as a whole. Otherwise the values
+//! are validated without stopping at the first mistake: every problem is reported as a [`Diagnostic`] with the line
+//! it was found on and the setting is skipped, so `aerugo --check-config` can list every problem at once before
+//! the configuration is reloaded. A relative wm path is relative to the directory of the configuration file.
+
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use smithay::{
+    output::Subpixel,
+    utils::{Logical, Point, Size, Transform},
+};
+use toml::Spanned;
+
+use crate::{
+    backend::modifiers::{Denied, ModifierPolicy},
+    bindings::SpawnBindings,
+    cursor::{self, CursorHideConfig},
+    emergency::{self, Chord, EmergencyAction, EmergencyConfig},
+    environment::Environment,
+    idle::IdleConfig,
+    ipc::IpcTokens,
+    keyboard::{KeyRepeat, LockConfig},
+    metrics::{MetricsConfig, MetricsEndpoint},
+    output_config::{self, OutputConfig, MAX_SCALE},
+    placeholder::PlaceholderConfig,
+    policy::{self, GlobalPolicy, Match},
+    rules::{self, Rule, RuleActions, Rules},
+    state::PrivilegedGlobals,
+};
+
+/// Connector types which output names start with, as named by the kernel and the windowed backends.
+const CONNECTOR_TYPES: &[&str] = &[
+    "Component",
+    "Composite",
+    "DIN",
+    "DP",
+    "DPI",
+    "DSI",
+    "DVI-A",
+    "DVI-D",
+    "DVI-I",
+    "eDP",
+    "HDMI-A",
+    "HDMI-B",
+    "LVDS",
+    "SPI",
+    "SVIDEO",
+    "TV",
+    "Unknown",
+    "USB",
+    "VGA",
+    "Virtual",
+    "WL",
+    "Writeback",
+    "X11",
+];
+
+/// How bad a problem in the configuration is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The setting is used, but is probably not what was intended.
+    Warning,
+
+    /// The setting is ignored.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A problem found in the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The line of the problem, starting at 1.
+    pub line: usize,
+
+    pub severity: Severity,
+
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.line, self.severity, self.message)
+    }
+}
+
+/// The settings of an output, applied when an output with the name is connected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSettings {
+    /// The connector name, such as `DP-1`.
+    pub name: String,
+
+    pub config: OutputConfig,
+
+    pub subpixel: Option<Subpixel>,
+}
+
+/// The parsed configuration.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// The wm component to load.
+    pub wm: Option<PathBuf>,
+
+    pub metrics: MetricsConfig,
+
+    pub placeholders: PlaceholderConfig,
+
+    pub outputs: Vec<OutputSettings>,
+
+    pub rules: Rules,
+
+    pub emergency: EmergencyConfig,
+
+    /// The chords which spawn a command.
+    pub bindings: SpawnBindings,
+
+    /// The cursor theme and the size of the cursor in logical pixels.
+    pub cursor_theme: Option<(String, u32)>,
+
+    /// When the cursor is hidden.
+    pub cursor_hiding: CursorHideConfig,
+
+    /// Whether numlock and capslock are enabled when the display server starts.
+    pub locks: LockConfig,
+
+    /// How held keys repeat.
+    pub key_repeat: KeyRepeat,
+
+    /// Which modifiers are offered when buffers are allocated, or [`None`] to use the default policy.
+    pub modifier_policy: Option<ModifierPolicy>,
+
+    /// When the outputs are dimmed and turned off, or [`None`] to keep them on.
+    pub idle: Option<IdleConfig>,
+
+    /// The environment of child processes.
+    pub environment: Environment,
+
+    /// The tokens of the IPC socket.
+    pub ipc: IpcTokens,
+
+    /// The policy deciding which privileged globals each client can see.
+    pub policy: GlobalPolicy,
+}
+
+impl Config {
+    /// The default location of the configuration file.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(config_home.join("aerugo").join("config.toml"))
+    }
+
+    /// Read and validate the configuration file.
+    pub fn load(path: &Path) -> io::Result<(Self, Vec<Diagnostic>)> {
+        let contents = fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Ok(parse(&contents, dir))
+    }
+}
+
+/// Whether any of the diagnostics is an error.
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+/// The configuration file as written, before the values are validated.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct File {
+    wm: Option<Spanned<String>>,
+    terminal: Option<Spanned<String>>,
+    metrics: Option<Spanned<String>>,
+    placeholder_timeout: Option<u64>,
+    output: BTreeMap<String, OutputSection>,
+    rule: Vec<RuleSection>,
+    cursor: CursorSection,
+    keyboard: KeyboardSection,
+    idle: Option<IdleSection>,
+    modifiers: Option<ModifiersSection>,
+    bindings: BTreeMap<String, Spanned<String>>,
+    emergency: EmergencySection,
+    environment: EnvironmentSection,
+    ipc: IpcSection,
+    policy: PolicySection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct OutputSection {
+    scale: Option<Spanned<f64>>,
+    position: Option<[i32; 2]>,
+    transform: Option<Spanned<String>>,
+    subpixel: Option<Spanned<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct RuleSection {
+    app_id: Option<Spanned<String>>,
+    title: Option<Spanned<String>>,
+    workspace: Option<String>,
+    floating: Option<bool>,
+    opacity: Option<Spanned<f32>>,
+    size: Option<Spanned<[i32; 2]>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct CursorSection {
+    theme: Option<Spanned<String>>,
+    size: Option<Spanned<u32>>,
+    hide_while_typing: bool,
+    idle_timeout: Option<u64>,
+    fade_duration: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct IdleSection {
+    dim_timeout: Option<u64>,
+    fade_duration: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ModifiersSection {
+    prefer_compressed: Option<bool>,
+    deny_compressed: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct KeyboardSection {
+    numlock: bool,
+    capslock: bool,
+    repeat_rate: Option<u32>,
+    repeat_delay: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct EmergencySection {
+    spawn_terminal: Option<Spanned<String>>,
+    reload_wm: Option<Spanned<String>>,
+    exit: Option<Spanned<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct EnvironmentSection {
+    vars: BTreeMap<String, Spanned<String>>,
+    toolkit_hints: bool,
+    update_activation_environment: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct IpcSection {
+    configure_token: Option<Spanned<String>>,
+    privileged_token: Option<Spanned<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PolicySection {
+    default: Option<Spanned<Vec<String>>>,
+    allow: Vec<PolicyRuleSection>,
+    deny: Vec<PolicyRuleSection>,
+}
+
+/// Every condition must match for the rule to apply.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct PolicyRuleSection {
+    uid: Option<u32>,
+    executable: Option<Spanned<String>>,
+    cgroup: Option<String>,
+    sandbox_engine: Option<String>,
+    token: Option<String>,
+    globals: Spanned<Vec<String>>,
+}
+
+struct Validator<'a> {
+    contents: &'a str,
+    dir: &'a Path,
+    config: Config,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    /// The line of a byte offset in the file, starting at 1.
+    fn line(&self, offset: usize) -> usize {
+        self.contents[..offset.min(self.contents.len())].matches('\n').count() + 1
+    }
+
+    /// The line of a table header, such as `[output.DP-1]`, or 1 if the header cannot be found.
+    fn header_line(&self, header: &str) -> usize {
+        self.contents
+            .lines()
+            .position(|line| {
+                let line = line.trim();
+                line.strip_prefix('[')
+                    .and_then(|line| line.split_once(']'))
+                    .is_some_and(|(name, _)| name.trim().replace(['"', '\''], "") == header)
+            })
+            .map_or(1, |index| index + 1)
+    }
+
+    fn push(&mut self, line: usize, severity: Severity, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            line,
+            severity,
+            message: message.into(),
+        });
+    }
+
+    /// Report an error for the value, returning the value if there was no error.
+    fn check<T, U>(&mut self, value: &Spanned<T>, check: impl FnOnce(&T) -> Result<U, String>) -> Option<U> {
+        match check(value.get_ref()) {
+            Ok(checked) => Some(checked),
+            Err(message) => {
+                let line = self.line(value.start());
+                self.push(line, Severity::Error, message);
+                None
+            }
+        }
+    }
+
+    fn top(&mut self, file: &File) {
+        if let Some(wm) = &file.wm {
+            let dir = self.dir;
+            self.config.wm = self.check(wm, |wm| {
+                let path = expand_path(wm, dir);
+                check_component(&path).map(|()| path)
+            });
+        }
+
+        if let Some(terminal) = &file.terminal {
+            let terminal = self.check(terminal, |terminal| {
+                let terminal = terminal.split_whitespace().map(String::from).collect::<Vec<_>>();
+
+                if terminal.is_empty() {
+                    return Err("missing terminal command".into());
+                }
+
+                Ok(terminal)
+            });
+
+            if let Some(terminal) = terminal {
+                self.config.emergency.terminal = terminal;
+            }
+        }
+
+        if let Some(metrics) = &file.metrics {
+            self.config.metrics.endpoint = self.check(metrics, |metrics| metrics.parse::<MetricsEndpoint>());
+        }
+
+        if let Some(timeout) = file.placeholder_timeout {
+            self.config.placeholders.timeout = (timeout > 0).then(|| Duration::from_millis(timeout));
+        }
+    }
+
+    fn output(&mut self, name: &str, section: &OutputSection) {
+        let known = CONNECTOR_TYPES.iter().any(|ty| {
+            name.strip_prefix(ty)
+                .and_then(|index| index.strip_prefix('-'))
+                .is_some_and(|index| !index.is_empty())
+        });
+
+        if !known {
+            let line = self.header_line(&format!("output.{name}"));
+            self.push(
+                line,
+                Severity::Error,
+                format!("{name:?} does not look like a connector name, such as DP-1"),
+            );
+        }
+
+        let mut output = OutputSettings {
+            name: name.into(),
+            config: OutputConfig::default(),
+            subpixel: None,
+        };
+
+        if let Some(scale) = &section.scale {
+            output.config.scale = self.check(scale, |&scale| {
+                if !(scale > 0.0 && scale <= MAX_SCALE) {
+                    return Err(output_config::Error::InvalidScale(scale).to_string());
+                }
+
+                Ok(scale)
+            });
+        }
+
+        output.config.position = section.position.map(|[x, y]| Point::<i32, Logical>::from((x, y)));
+
+        if let Some(transform) = &section.transform {
+            output.config.transform = self.check(transform, |transform| {
+                parse_transform(transform).ok_or_else(|| {
+                    format!("unknown transform {transform:?}, expected normal, 90, 180, 270 or flipped variants")
+                })
+            });
+        }
+
+        if let Some(subpixel) = &section.subpixel {
+            output.subpixel = self.check(subpixel, |subpixel| {
+                output_config::parse_subpixel(subpixel).ok_or_else(|| {
+                    format!("unknown subpixel layout {subpixel:?}, expected rgb, bgr, vrgb, vbgr or none")
+                })
+            });
+        }
+
+        self.config.outputs.push(output);
+    }
+
+    fn rule(&mut self, rule: &RuleSection) {
+        let mut actions = RuleActions {
+            workspace: rule.workspace.clone(),
+            floating: rule.floating,
+            size: None,
+            opacity: rule.opacity.as_ref().map(|opacity| *opacity.get_ref()),
+        };
+
+        if let Some(size) = &rule.size {
+            let size = self.check(size, |&[w, h]| {
+                if w <= 0 || h <= 0 {
+                    return Err(format!("size {w}x{h} must be positive"));
+                }
+
+                Ok(Size::<i32, Logical>::from((w, h)))
+            });
+
+            // A rule with an invalid size would open toplevels at a size which was not intended.
+            let Some(size) = size else {
+                return;
+            };
+
+            actions.size = Some(size);
+        }
+
+        let app_id = rule.app_id.as_ref().map(|app_id| app_id.get_ref().as_str());
+        let title = rule.title.as_ref().map(|title| title.get_ref().as_str());
+
+        let err = match Rule::new(app_id, title, actions) {
+            Ok(created) => {
+                self.config.rules.push(created);
+                return;
+            }
+            Err(err) => err,
+        };
+
+        // Point at the setting which made the rule invalid.
+        let setting = match &err {
+            rules::Error::InvalidOpacity(_) => rule.opacity.as_ref().map(Spanned::start),
+            rules::Error::InvalidPattern(_) => match &rule.app_id {
+                Some(app_id) if Rule::new(Some(app_id.get_ref().as_str()), None, RuleActions::default()).is_err() => {
+                    Some(app_id.start())
+                }
+                _ => rule.title.as_ref().map(Spanned::start),
+            },
+        };
+
+        let line = setting.map_or(1, |start| self.line(start));
+        self.push(line, Severity::Error, format!("invalid rule: {err}"));
+    }
+
+    fn cursor(&mut self, section: &CursorSection) {
+        let size = section
+            .size
+            .as_ref()
+            .and_then(|size| self.check(size, |&size| cursor::check_size(size)))
+            .unwrap_or(cursor::DEFAULT_SIZE);
+
+        let theme = section.theme.as_ref().and_then(|theme| {
+            self.check(theme, |theme| match theme.is_empty() {
+                true => Err("the cursor theme must not be empty".to_owned()),
+                false => Ok(theme.clone()),
+            })
+        });
+
+        // Without a theme the default theme is used at the size.
+        if theme.is_some() || section.size.is_some() {
+            self.config.cursor_theme = Some((theme.unwrap_or_else(|| cursor::DEFAULT_THEME.into()), size));
+        }
+
+        let hiding = &mut self.config.cursor_hiding;
+        hiding.hide_while_typing = section.hide_while_typing;
+
+        if let Some(timeout) = section.idle_timeout {
+            hiding.idle_timeout = (timeout > 0).then(|| Duration::from_millis(timeout));
+        }
+
+        if let Some(duration) = section.fade_duration {
+            hiding.fade_duration = Duration::from_millis(duration);
+        }
+    }
+
+    fn emergency(&mut self, section: &EmergencySection) {
+        let chords = [
+            (&section.spawn_terminal, EmergencyAction::Terminal),
+            (&section.reload_wm, EmergencyAction::ReloadWm),
+            (&section.exit, EmergencyAction::Exit),
+        ];
+
+        for (value, action) in chords {
+            let Some(value) = value else {
+                continue;
+            };
+
+            let chords = self.config.emergency.chords.clone();
+            let chord = self.check(value, |value| {
+                let chord = match value.as_str() {
+                    "none" => None,
+                    value => {
+                        Some(emergency::parse_chord(value).map_err(|err| format!("invalid chord {value:?}: {err}"))?)
+                    }
+                };
+
+                if let Some((_, other)) = chords
+                    .iter()
+                    .find(|&&(existing, other)| Some(existing) == chord && other != action)
+                {
+                    return Err(format!("chord {value:?} is already used by {other:?}"));
+                }
+
+                Ok(chord)
+            });
+
+            if let Some(chord) = chord {
+                // The chord replaces the default chord of the action.
+                let chords = &mut self.config.emergency.chords;
+                chords.retain(|&(_, existing)| existing != action);
+                chords.extend(chord.map(|chord| (chord, action)));
+            }
+        }
+    }
+
+    /// Check the chord and command of each binding. Emergency chords take precedence, so a binding may not use one.
+    fn bindings(&mut self, bindings: &BTreeMap<String, Spanned<String>>) {
+        let mut bound = Vec::<(Chord, Vec<String>)>::new();
+
+        for (chord, command) in bindings {
+            let emergency = self.config.emergency.clone();
+            let checked = self.check(command, |command| {
+                let parsed = emergency::parse_chord(chord).map_err(|err| format!("invalid chord {chord:?}: {err}"))?;
+
+                if let Some(action) = emergency.action(parsed.modifiers, parsed.sym) {
+                    return Err(format!("chord {chord:?} is already used by {action:?}"));
+                }
+
+                if bound.iter().any(|&(existing, _)| existing == parsed) {
+                    return Err(format!("chord {chord:?} is bound more than once"));
+                }
+
+                let command = command.split_whitespace().map(String::from).collect::<Vec<_>>();
+
+                if command.is_empty() {
+                    return Err(format!("missing command for {chord:?}"));
+                }
+
+                Ok((parsed, command))
+            });
+
+            bound.extend(checked);
+        }
+
+        self.config.bindings = SpawnBindings::new(bound);
+    }
+
+    fn environment(&mut self, section: &EnvironmentSection) {
+        let mut environment = Environment::new();
+
+        for (name, value) in &section.vars {
+            let checked = self.check(value, |value| {
+                if name.is_empty() || name.contains(['=', '\0']) {
+                    return Err(format!("invalid variable name {name:?}"));
+                }
+
+                if matches!(name.as_str(), "WAYLAND_DISPLAY" | "DISPLAY") {
+                    return Err(format!("{name} is set by the display server"));
+                }
+
+                if value.contains('\0') {
+                    return Err(format!("the value of {name} must not contain a nul byte"));
+                }
+
+                Ok(value.clone())
+            });
+
+            if let Some(value) = checked {
+                environment.set(name.clone(), value);
+            }
+        }
+
+        // Hints set by the variables above are kept.
+        if section.toolkit_hints {
+            environment.set_toolkit_hints();
+        }
+
+        environment.set_update_activation_environment(section.update_activation_environment);
+        self.config.environment = environment;
+    }
+
+    fn ipc(&mut self, section: &IpcSection) {
+        let non_empty = |token: &String| match token.is_empty() {
+            true => Err("the token must not be empty".to_owned()),
+            false => Ok(token.clone()),
+        };
+
+        if let Some(token) = &section.configure_token {
+            self.config.ipc.configure = self.check(token, non_empty);
+        }
+
+        if let Some(token) = &section.privileged_token {
+            let configure = self.config.ipc.configure.clone();
+            self.config.ipc.privileged = self.check(token, |token| {
+                // The privileged token is tried first, so the configure token would grant the privileged tier.
+                if configure.as_ref() == Some(token) {
+                    return Err("the privileged token must differ from the configure token".into());
+                }
+
+                non_empty(token)
+            });
+        }
+    }
+
+    fn policy(&mut self, section: &PolicySection) {
+        let default = section
+            .default
+            .as_ref()
+            .and_then(|default| self.check(default, |names| policy::parse_globals(names.iter().map(String::as_str))));
+
+        let mut policy = GlobalPolicy::new(default.unwrap_or_else(PrivilegedGlobals::empty));
+
+        for rule in &section.allow {
+            if let Some((conditions, globals)) = self.policy_rule(rule) {
+                policy = policy.allow(conditions, globals);
+            }
+        }
+
+        for rule in &section.deny {
+            if let Some((conditions, globals)) = self.policy_rule(rule) {
+                policy = policy.deny(conditions, globals);
+            }
+        }
+
+        self.config.policy = policy;
+    }
+
+    fn policy_rule(&mut self, rule: &PolicyRuleSection) -> Option<(Vec<Match>, PrivilegedGlobals)> {
+        let executable = match &rule.executable {
+            Some(executable) => {
+                let dir = self.dir;
+                // The executable of a client is always an absolute path.
+                let executable = self.check(executable, |executable| {
+                    if !executable.starts_with("~/") && !Path::new(executable).is_absolute() {
+                        return Err(format!("executable {executable:?} must be an absolute path"));
+                    }
+
+                    Ok(expand_path(executable, dir))
+                })?;
+                Some(Match::Executable(executable))
+            }
+            None => None,
+        };
+
+        let conditions = rule
+            .uid
+            .map(Match::Uid)
+            .into_iter()
+            .chain(executable)
+            .chain(rule.cgroup.clone().map(Match::Cgroup))
+            .chain(rule.sandbox_engine.clone().map(Match::SandboxEngine))
+            .chain(rule.token.clone().map(Match::Token))
+            .collect::<Vec<_>>();
+
+        let globals = self.check(&rule.globals, |names| {
+            // A rule without conditions applies to every client, which is what the default is for.
+            if conditions.is_empty() {
+                return Err("the rule has no conditions, use the default of the policy instead".into());
+            }
+
+            policy::parse_globals(names.iter().map(String::as_str))
+        })?;
+
+        Some((conditions, globals))
+    }
+}
+
+/// Parse the configuration, collecting every problem.
+///
+/// The wm path is checked on the filesystem, relative to `dir`.
+pub fn parse(contents: &str, dir: &Path) -> (Config, Vec<Diagnostic>) {
+    let file = match toml::from_str::<File>(contents) {
+        Ok(file) => file,
+        Err(err) => {
+            let diagnostic = Diagnostic {
+                line: err.line_col().map_or(1, |(line, _)| line + 1),
+                severity: Severity::Error,
+                message: err.to_string(),
+            };
+
+            return (Config::default(), vec![diagnostic]);
+        }
+    };
+
+    let mut validator = Validator {
+        contents,
+        dir,
+        config: Config::default(),
+        diagnostics: Vec::new(),
+    };
+
+    validator.top(&file);
+
+    for (name, section) in &file.output {
+        validator.output(name, section);
+    }
+
+    for rule in &file.rule {
+        validator.rule(rule);
+    }
+
+    validator.cursor(&file.cursor);
+    validator.config.locks = LockConfig {
+        num_lock: file.keyboard.numlock,
+        caps_lock: file.keyboard.capslock,
+    };
+    validator.config.key_repeat = key_repeat(&file.keyboard);
+    validator.config.idle = file.idle.as_ref().and_then(idle);
+    validator.config.modifier_policy = file.modifiers.as_ref().map(modifier_policy);
+    validator.emergency(&file.emergency);
+    validator.bindings(&file.bindings);
+    validator.environment(&file.environment);
+    validator.ipc(&file.ipc);
+    validator.policy(&file.policy);
+
+    validator.diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    (validator.config, validator.diagnostics)
+}
+
+/// The idle stages of the `[idle]` section, or [`None`] if the outputs are never dimmed.
+fn idle(section: &IdleSection) -> Option<IdleConfig> {
+    let mut config = IdleConfig::default();
+
+    if let Some(timeout) = section.dim_timeout {
+        config.dim_timeout = (timeout > 0).then(|| Duration::from_secs(timeout))?;
+    }
+
+    if let Some(duration) = section.fade_duration {
+        config.fade_duration = Duration::from_millis(duration);
+    }
+
+    Some(config)
+}
+
+/// The key repeat of the `[keyboard]` section.
+fn key_repeat(section: &KeyboardSection) -> KeyRepeat {
+    let default = KeyRepeat::default();
+    let clamp = |value: u32| value.min(i32::MAX as u32) as i32;
+
+    KeyRepeat {
+        rate: section.repeat_rate.map_or(default.rate, clamp),
+        delay: section.repeat_delay.map_or(default.delay, clamp),
+    }
+}
+
+/// The modifier policy of the `[modifiers]` section.
+fn modifier_policy(section: &ModifiersSection) -> ModifierPolicy {
+    let mut policy = ModifierPolicy::new();
+
+    if let Some(prefer) = section.prefer_compressed {
+        policy.prefer_compressed = prefer;
+    }
+
+    for driver in &section.deny_compressed {
+        policy.deny(driver.clone(), Denied::Compressed);
+    }
+
+    policy
+}
+
+/// Expand `~/` to the home directory and make relative paths relative to the directory.
+fn expand_path(path: &str, dir: &Path) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join(rest);
+        }
+    }
+
+    dir.join(path)
+}
+
+/// Check the file is a WebAssembly component rather than a core module.
+pub fn check_component(path: &Path) -> Result<(), String> {
+    let mut header = [0; 8];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map_err(|err| format!("cannot read wm {}: {err}", path.display()))?;
+
+    match header {
+        [0x00, b'a', b's', b'm', _, _, 0x01, 0x00] => Ok(()),
+        [0x00, b'a', b's', b'm', ..] => Err(format!(
+            "wm {} is a core WebAssembly module, but the wm must be a component",
+            path.display()
+        )),
+        _ => Err(format!("wm {} is not a WebAssembly component", path.display())),
+    }
+}
+
+fn parse_transform(value: &str) -> Option<Transform> {
+    Some(match value {
+        "normal" => Transform::Normal,
+        "90" => Transform::_90,
+        "180" => Transform::_180,
+        "270" => Transform::_270,
+        "flipped" => Transform::Flipped,
+        "flipped-90" => Transform::Flipped90,
+        "flipped-180" => Transform::Flipped180,
+        "flipped-270" => Transform::Flipped270,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, path::Path, time::Duration};
+
+    use smithay::output::Subpixel;
+
+    use super::{has_errors, parse, Diagnostic, Severity};
+    use crate::{
+        backend::modifiers::{Denied, ModifierPolicy},
+        cursor::CursorHideConfig,
+        emergency::{parse_chord, EmergencyAction, EmergencyConfig},
+        idle::IdleConfig,
+        keyboard::KeyRepeat,
+        policy::{ClientInfo, GlobalPolicy},
+        state::PrivilegedGlobals,
+    };
+
+    #[test]
+    fn valid() {
+        let (config, diagnostics) = parse(
+            "# Laptop with an external monitor\n\
+             terminal = \"foot --server\"\n\
+             placeholder-timeout = 500\n\
+             \n\
+             [output.eDP-1]\n\
+             scale = 1.5\n\
+             subpixel = \"rgb\"\n\
+             \n\
+             [output.DP-2]\n\
+             position = [1920, 0]\n\
+             \n\
+             [[rule]]\n\
+             app-id = '^org\\.mozilla\\.firefox$'\n\
+             workspace = \"web\"\n\
+             size = [1280, 720]\n\
+             \n\
+             [emergency]\n\
+             reload-wm = \"none\"\n",
+            Path::new("/"),
+        );
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(config.emergency.terminal, ["foot", "--server"]);
+        assert_eq!(config.placeholders.timeout, Some(Duration::from_millis(500)));
+        assert_eq!(config.outputs.len(), 2);
+
+        let output = |name: &str| config.outputs.iter().find(|output| output.name == name).unwrap();
+        assert_eq!(output("eDP-1").config.scale, Some(1.5));
+        assert_eq!(output("eDP-1").subpixel, Some(Subpixel::HorizontalRgb));
+        assert_eq!(output("DP-2").config.position, Some((1920, 0).into()));
+        assert_eq!(
+            config
+                .rules
+                .query(Some("org.mozilla.firefox"), None)
+                .workspace
+                .as_deref(),
+            Some("web")
+        );
+
+        // Only the reload chord was removed.
+        let defaults = EmergencyConfig::default().chords.len();
+        assert_eq!(config.emergency.chords.len(), defaults - 1);
+        assert!(config
+            .emergency
+            .chords
+            .iter()
+            .all(|&(_, action)| action != EmergencyAction::ReloadWm));
+    }
+
+    #[test]
+    fn placeholders_disabled() {
+        let (config, diagnostics) = parse("placeholder-timeout = 0\n", Path::new("/"));
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(config.placeholders.timeout, None);
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let (config, diagnostics) = parse(
+            "wm = \"does-not-exist.wasm\"\n\
+             terminal = \" \"\n\
+             [output.monitor]\n\
+             scale = 0.0\n\
+             transform = \"sideways\"\n\
+             [[rule]]\n\
+             title = \"(unclosed\"\n\
+             [emergency]\n\
+             exit = \"hyper+Escape\"\n\
+             spawn-terminal = \"ctrl+alt+shift+BackSpace\"\n",
+            Path::new("/nonexistent"),
+        );
+
+        let lines = diagnostics
+            .iter()
+            .map(|&Diagnostic { line, severity, .. }| (line, severity))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                (1, Severity::Error),
+                (2, Severity::Error),
+                (3, Severity::Error),
+                (4, Severity::Error),
+                (5, Severity::Error),
+                (7, Severity::Error),
+                (9, Severity::Error),
+                (10, Severity::Error),
+            ]
+        );
+        assert!(has_errors(&diagnostics));
+        assert_eq!(diagnostics[1].to_string(), "2: error: missing terminal command");
+
+        // The output is still configured without the invalid settings.
+        assert_eq!(config.outputs.len(), 1);
+        assert_eq!(config.outputs[0].config.scale, None);
+    }
+
+    #[test]
+    fn environment() {
+        let (config, diagnostics) = parse(
+            "[environment]\n\
+             toolkit-hints = true\n\
+             \n\
+             [environment.vars]\n\
+             QT_QPA_PLATFORM = \"xcb\"\n\
+             WAYLAND_DISPLAY = \"wayland-0\"\n",
+            Path::new("/"),
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 6);
+
+        let vars = config.environment.vars().collect::<Vec<_>>();
+        assert!(vars.contains(&("QT_QPA_PLATFORM", OsStr::new("xcb"))));
+        assert!(vars.contains(&("GDK_BACKEND", OsStr::new("wayland,x11"))));
+        assert!(!vars.iter().any(|&(name, _)| name == "WAYLAND_DISPLAY"));
+    }
+
+    #[test]
+    fn cursor_theme() {
+        let (config, diagnostics) = parse("[cursor]\ntheme = \"Adwaita\"\nsize = 32\n", Path::new("/"));
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(config.cursor_theme, Some(("Adwaita".into(), 32)));
+
+        // An invalid size falls back to the default size.
+        let (config, diagnostics) = parse("[cursor]\nsize = 0\n", Path::new("/"));
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(config.cursor_theme, Some(("default".into(), 24)));
+    }
+
+    #[test]
+    fn cursor_hiding() {
+        let (config, diagnostics) = parse(
+            "[cursor]\nhide-while-typing = true\nidle-timeout = 5000\nfade-duration = 0\n",
+            Path::new("/"),
+        );
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(config.cursor_theme, None);
+        assert!(config.cursor_hiding.hide_while_typing);
+        assert_eq!(config.cursor_hiding.idle_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.cursor_hiding.fade_duration, Duration::ZERO);
+
+        let (config, _) = parse("[cursor]\nidle-timeout = 0\n", Path::new("/"));
+        assert_eq!(config.cursor_hiding, CursorHideConfig::default());
+    }
+
+    #[test]
+    fn key_repeat() {
+        let (config, diagnostics) = parse("[keyboard]\nrepeat-rate = 40\n", Path::new("/"));
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(config.key_repeat, KeyRepeat { rate: 40, delay: 200 });
+    }
+
+    #[test]
+    fn keyboard_locks() {
+        let (config, diagnostics) = parse("[keyboard]\nnumlock = true\n", Path::new("/"));
+
+        assert_eq!(diagnostics, []);
+        assert!(config.locks.num_lock);
+        assert!(!config.locks.caps_lock);
+    }
+
+    #[test]
+    fn modifier_policy() {
+        let (config, diagnostics) = parse(
+            "[modifiers]\nprefer-compressed = false\ndeny-compressed = [\"i915\"]\n",
+            Path::new("/"),
+        );
+
+        let mut policy = ModifierPolicy::new();
+        policy.prefer_compressed = false;
+        policy.deny("i915", Denied::Compressed);
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(config.modifier_policy, Some(policy));
+        assert_eq!(parse("", Path::new("/")).0.modifier_policy, None);
+    }
+
+    #[test]
+    fn idle() {
+        let (config, diagnostics) = parse("[idle]\nfade-duration = 0\n", Path::new("/"));
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(
+            config.idle,
+            Some(IdleConfig {
+                dim_timeout: Duration::from_secs(300),
+                fade_duration: Duration::ZERO,
+            })
+        );
+
+        let (config, _) = parse("[idle]\ndim-timeout = 0\n", Path::new("/"));
+        assert_eq!(config.idle, None);
+        assert_eq!(parse("", Path::new("/")).0.idle, None);
+    }
+
+    #[test]
+    fn bindings() {
+        let (config, diagnostics) = parse(
+            "[bindings]\n\
+             \"logo+d\" = \"fuzzel --lines 10\"\n\
+             \"super+d\" = \"wofi\"\n\
+             \"ctrl+alt+shift+Escape\" = \"true\"\n\
+             \"hyper+x\" = \"true\"\n\
+             \"logo+Return\" = \" \"\n",
+            Path::new("/"),
+        );
+
+        let lines = diagnostics.iter().map(|diagnostic| diagnostic.line).collect::<Vec<_>>();
+        assert_eq!(lines, [3, 4, 5, 6]);
+        assert!(has_errors(&diagnostics));
+
+        let chord = parse_chord("logo+d").unwrap();
+        assert_eq!(
+            config.bindings.command(chord.modifiers, chord.sym),
+            Some(["fuzzel".to_owned(), "--lines".into(), "10".into()].as_slice())
+        );
+        assert_eq!(
+            config.bindings.command(chord.modifiers, parse_chord("x").unwrap().sym),
+            None
+        );
+    }
+
+    #[test]
+    fn ipc_tokens() {
+        let (config, diagnostics) = parse(
+            "[ipc]\nconfigure-token = \"config\"\nprivileged-token = \"capture\"\n",
+            Path::new("/"),
+        );
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(config.ipc.configure.as_deref(), Some("config"));
+        assert_eq!(config.ipc.privileged.as_deref(), Some("capture"));
+
+        let (config, diagnostics) = parse(
+            "[ipc]\nconfigure-token = \"same\"\nprivileged-token = \"same\"\n",
+            Path::new("/"),
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(config.ipc.privileged, None);
+    }
+
+    #[test]
+    fn global_policy() {
+        let (config, diagnostics) = parse(
+            "[policy]\n\
+             default = [\"foreign-toplevel-list\"]\n\
+             \n\
+             [[policy.allow]]\n\
+             executable = \"/usr/bin/waybar\"\n\
+             globals = [\"layer-shell\"]\n\
+             \n\
+             [[policy.deny]]\n\
+             sandbox-engine = \"org.flatpak\"\n\
+             globals = [\"foreign-toplevel-list\"]\n",
+            Path::new("/"),
+        );
+
+        assert_eq!(diagnostics, []);
+
+        let bar = ClientInfo {
+            executable: Some("/usr/bin/waybar".into()),
+            ..ClientInfo::default()
+        };
+        let sandboxed = ClientInfo {
+            sandbox_engine: Some("org.flatpak".into()),
+            ..ClientInfo::default()
+        };
+
+        assert_eq!(
+            config.policy.globals_for(&bar),
+            PrivilegedGlobals::FOREIGN_TOPLEVEL_LIST | PrivilegedGlobals::LAYER_SHELL
+        );
+        assert_eq!(config.policy.globals_for(&sandboxed), PrivilegedGlobals::empty());
+
+        // Without a policy no client sees privileged globals.
+        let (config, _) = parse("", Path::new("/"));
+        assert_eq!(config.policy, GlobalPolicy::default());
+
+        let (_, diagnostics) = parse(
+            "[[policy.allow]]\nexecutable = \"waybar\"\nglobals = [\"layer-shell\"]\n\
+             [[policy.allow]]\nglobals = [\"layer-shell\"]\n\
+             [[policy.allow]]\ntoken = \"bar\"\nglobals = [\"xwayland\"]\n",
+            Path::new("/"),
+        );
+
+        let lines = diagnostics.iter().map(|diagnostic| diagnostic.line).collect::<Vec<_>>();
+        assert_eq!(lines, [2, 5, 8]);
+    }
+
+    #[test]
+    fn invalid_toml_is_rejected() {
+        let (config, diagnostics) = parse("wm = \"a.wasm\"\n[output.DP-1]\ncolour = \"blue\"\n", Path::new("/"));
+
+        assert_eq!(config.wm, None);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+
+        let (_, diagnostics) = parse("wm = \n", Path::new("/"));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 1);
+    }
+}
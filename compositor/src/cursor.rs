@@ -0,0 +1,833 @@
+//! Cursor themes
+//!
+//! The cursor is drawn by the display server using images from an XCursor theme. The theme and size are set by
+//! the configuration and may be changed while the display server is running, in which case every cursor image is
+//! loaded again. The theme and size are also exported to clients as `XCURSOR_THEME` and `XCURSOR_SIZE`, so cursors
+//! drawn by clients match the cursor drawn by the display server.
+//!
+//! XCursor files contain images at several nominal sizes. On a HiDPI output the image closest to the cursor size
+//! multiplied by the output scale is used, so the cursor has the same logical size on every output and stays
+//! sharp.
+//!
+//! Clients set the cursor to a surface they draw themselves, or to the default cursor of the theme. The wm may
+//! replace the cursor set by clients with a cursor from the theme, an image of its own or no cursor at all, such
+//! as to show resize arrows during an interactive resize.
+//!
+//! The cursor may be hidden while typing and faded out after the pointer has not moved for some time. Moving the
+//! pointer restores the cursor instantly. The wm may also hide the cursor until the wm shows the cursor again, such
+//! as in a kiosk, in which case neither moving the pointer nor a new cursor shows the cursor.
+
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use smithay::{
+    input::pointer::{CursorImageAttributes, CursorImageStatus},
+    utils::{Logical, Point},
+    wayland::compositor,
+};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use crate::Aerugo;
+
+/// The default cursor size in logical pixels.
+pub const DEFAULT_SIZE: u32 = 24;
+
+/// The theme used if none is configured, which is usually a link to the theme chosen by the distribution.
+pub const DEFAULT_THEME: &str = "default";
+
+/// The largest cursor size in logical pixels.
+pub const MAX_SIZE: u32 = 256;
+
+const XCURSOR_MAGIC: &[u8; 4] = b"Xcur";
+const XCURSOR_IMAGE_TYPE: u32 = 0xfffd0002;
+/// The size of the header of an image chunk.
+const IMAGE_HEADER_SIZE: usize = 36;
+/// Images larger than this are rejected to avoid allocating huge buffers for broken files.
+const MAX_IMAGE_SIZE: u32 = 0x7fff;
+
+/// An error from loading a cursor.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("the file is not an XCursor file")]
+    NotXcursor,
+
+    #[error("the file is truncated")]
+    Truncated,
+
+    #[error("image of size {0}x{1} is too large")]
+    TooLarge(u32, u32),
+}
+
+/// An image of a cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorImage {
+    /// The size the image was designed for.
+    pub nominal_size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub xhot: u32,
+    pub yhot: u32,
+    /// The time to show the image in milliseconds for animated cursors.
+    pub delay: u32,
+    /// Premultiplied ARGB8888 pixels.
+    pub pixels: Vec<u8>,
+}
+
+/// Parse the images of an XCursor file.
+pub fn parse_xcursor(data: &[u8]) -> Result<Vec<CursorImage>, Error> {
+    let read_u32 = |offset: usize| -> Result<u32, Error> {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or(Error::Truncated)
+    };
+
+    if data.get(..4) != Some(XCURSOR_MAGIC.as_slice()) {
+        return Err(Error::NotXcursor);
+    }
+
+    let header_size = read_u32(4)? as usize;
+    let ntoc = read_u32(12)? as usize;
+    let mut images = Vec::new();
+
+    for entry in 0..ntoc {
+        let entry = header_size + entry * 12;
+
+        if read_u32(entry)? != XCURSOR_IMAGE_TYPE {
+            continue;
+        }
+
+        let position = read_u32(entry + 8)? as usize;
+        let width = read_u32(position + 16)?;
+        let height = read_u32(position + 20)?;
+
+        if width > MAX_IMAGE_SIZE || height > MAX_IMAGE_SIZE {
+            return Err(Error::TooLarge(width, height));
+        }
+
+        let start = position + IMAGE_HEADER_SIZE;
+        let len = width as usize * height as usize * 4;
+        let pixels = data.get(start..start + len).ok_or(Error::Truncated)?.to_vec();
+
+        images.push(CursorImage {
+            nominal_size: read_u32(position + 8)?,
+            width,
+            height,
+            xhot: read_u32(position + 24)?,
+            yhot: read_u32(position + 28)?,
+            delay: read_u32(position + 32)?,
+            pixels,
+        });
+    }
+
+    Ok(images)
+}
+
+/// Select the frames of the images with the nominal size closest to the size.
+///
+/// A cursor with a single frame is not animated.
+pub fn select_size(images: &[CursorImage], size: u32) -> Vec<CursorImage> {
+    let Some(nominal_size) = images
+        .iter()
+        .map(|image| image.nominal_size)
+        .min_by_key(|&nominal_size| nominal_size.abs_diff(size))
+    else {
+        return Vec::new();
+    };
+
+    images
+        .iter()
+        .filter(|image| image.nominal_size == nominal_size)
+        .cloned()
+        .collect()
+}
+
+/// Parse the themes a theme inherits from its `index.theme`.
+fn parse_inherits(index: &str) -> Vec<String> {
+    index
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Inherits")?.trim_start().strip_prefix('='))
+        .flat_map(|themes| themes.split([',', ';']))
+        .map(str::trim)
+        .filter(|theme| !theme.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// The directories searched for cursor themes.
+///
+/// This is `XCURSOR_PATH` if set, otherwise the same directories libXcursor searches.
+pub fn search_path() -> Vec<PathBuf> {
+    if let Some(path) = env::var_os("XCURSOR_PATH") {
+        return env::split_paths(&path).collect();
+    }
+
+    let mut path = Vec::new();
+
+    if let Some(home) = env::var_os("HOME").map(PathBuf::from) {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".local/share"));
+        path.push(data_home.join("icons"));
+        path.push(home.join(".icons"));
+    }
+
+    path.extend(["/usr/share/icons", "/usr/share/pixmaps", "/usr/X11R6/lib/X11/icons"].map(PathBuf::from));
+    path
+}
+
+/// A cursor theme and the cursors loaded from it.
+#[derive(Debug)]
+pub struct CursorTheme {
+    name: String,
+    size: u32,
+    search_path: Vec<PathBuf>,
+    /// Loaded cursors by name and scale.
+    cache: HashMap<(String, u32), Arc<[CursorImage]>>,
+}
+
+impl CursorTheme {
+    pub fn new(name: impl Into<String>, size: u32, search_path: Vec<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            size,
+            search_path,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of the cursor in logical pixels.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Change the theme and size.
+    ///
+    /// Cursors are loaded again the next time they are used.
+    pub fn set_theme(&mut self, name: impl Into<String>, size: u32) {
+        self.name = name.into();
+        self.size = size;
+        self.cache.clear();
+    }
+
+    /// Load a cursor, such as `default` or `text`, for an output with the integer scale.
+    ///
+    /// Returns [`None`] if neither the theme nor the themes it inherits provide the cursor.
+    pub fn load(&mut self, cursor: &str, scale: u32) -> Option<Arc<[CursorImage]>> {
+        let key = (cursor.to_owned(), scale);
+
+        if let Some(images) = self.cache.get(&key) {
+            return Some(images.clone());
+        }
+
+        let path = self.find(&self.name, cursor, &mut Vec::new())?;
+        let images = fs::read(&path)
+            .map_err(Error::from)
+            .and_then(|data| parse_xcursor(&data))
+            .map_err(|err| tracing::warn!(?path, %err, "Failed to load cursor"))
+            .ok()?;
+
+        let images: Arc<[CursorImage]> = select_size(&images, self.size * scale.max(1)).into();
+        self.cache.insert(key, images.clone());
+        Some(images)
+    }
+
+    fn find(&self, theme: &str, cursor: &str, visited: &mut Vec<String>) -> Option<PathBuf> {
+        // Themes may inherit each other in a cycle.
+        if visited.iter().any(|visited| visited == theme) {
+            return None;
+        }
+
+        visited.push(theme.to_owned());
+
+        let dirs = self.search_path.iter().map(|dir| dir.join(theme));
+
+        for dir in dirs.clone() {
+            let path = dir.join("cursors").join(cursor);
+
+            if path.is_file() {
+                return Some(path);
+            }
+        }
+
+        dirs.filter_map(|dir| read_index(&dir))
+            .flat_map(|index| parse_inherits(&index))
+            .find_map(|inherited| self.find(&inherited, cursor, visited))
+    }
+}
+
+impl Default for CursorTheme {
+    fn default() -> Self {
+        Self::new(DEFAULT_THEME, DEFAULT_SIZE, search_path())
+    }
+}
+
+/// Check a cursor size in logical pixels is between 1 and [`MAX_SIZE`].
+pub fn check_size(size: u32) -> Result<u32, String> {
+    match size {
+        1..=MAX_SIZE => Ok(size),
+        _ => Err(format!("cursor size {size} must be between 1 and {MAX_SIZE}")),
+    }
+}
+
+fn read_index(dir: &Path) -> Option<String> {
+    fs::read_to_string(dir.join("index.theme")).ok()
+}
+
+/// A cursor from the cursor theme, named after the CSS cursor property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CursorShape {
+    Default,
+    ContextMenu,
+    Help,
+    Pointer,
+    Progress,
+    Wait,
+    Cell,
+    Crosshair,
+    Text,
+    VerticalText,
+    Alias,
+    Copy,
+    Move,
+    NoDrop,
+    NotAllowed,
+    Grab,
+    Grabbing,
+    EResize,
+    NResize,
+    NeResize,
+    NwResize,
+    SResize,
+    SeResize,
+    SwResize,
+    WResize,
+    EwResize,
+    NsResize,
+    NeswResize,
+    NwseResize,
+    ColResize,
+    RowResize,
+    AllScroll,
+    ZoomIn,
+    ZoomOut,
+}
+
+impl CursorShape {
+    /// The name of the cursor in the theme.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::ContextMenu => "context-menu",
+            Self::Help => "help",
+            Self::Pointer => "pointer",
+            Self::Progress => "progress",
+            Self::Wait => "wait",
+            Self::Cell => "cell",
+            Self::Crosshair => "crosshair",
+            Self::Text => "text",
+            Self::VerticalText => "vertical-text",
+            Self::Alias => "alias",
+            Self::Copy => "copy",
+            Self::Move => "move",
+            Self::NoDrop => "no-drop",
+            Self::NotAllowed => "not-allowed",
+            Self::Grab => "grab",
+            Self::Grabbing => "grabbing",
+            Self::EResize => "e-resize",
+            Self::NResize => "n-resize",
+            Self::NeResize => "ne-resize",
+            Self::NwResize => "nw-resize",
+            Self::SResize => "s-resize",
+            Self::SeResize => "se-resize",
+            Self::SwResize => "sw-resize",
+            Self::WResize => "w-resize",
+            Self::EwResize => "ew-resize",
+            Self::NsResize => "ns-resize",
+            Self::NeswResize => "nesw-resize",
+            Self::NwseResize => "nwse-resize",
+            Self::ColResize => "col-resize",
+            Self::RowResize => "row-resize",
+            Self::AllScroll => "all-scroll",
+            Self::ZoomIn => "zoom-in",
+            Self::ZoomOut => "zoom-out",
+        }
+    }
+
+    /// The name of the cursor in older themes which only provide the X11 cursor names.
+    pub fn legacy_name(self) -> Option<&'static str> {
+        Some(match self {
+            Self::Default => "left_ptr",
+            Self::Help => "question_arrow",
+            Self::Pointer => "hand2",
+            Self::Progress => "left_ptr_watch",
+            Self::Wait => "watch",
+            Self::Crosshair => "crosshair",
+            Self::Text => "xterm",
+            Self::Move | Self::Grabbing => "fleur",
+            Self::NotAllowed | Self::NoDrop => "crossed_circle",
+            Self::Grab => "hand1",
+            Self::EResize => "right_side",
+            Self::NResize => "top_side",
+            Self::NeResize => "top_right_corner",
+            Self::NwResize => "top_left_corner",
+            Self::SResize => "bottom_side",
+            Self::SeResize => "bottom_right_corner",
+            Self::SwResize => "bottom_left_corner",
+            Self::WResize => "left_side",
+            Self::EwResize | Self::ColResize => "sb_h_double_arrow",
+            Self::NsResize | Self::RowResize => "sb_v_double_arrow",
+            _ => return None,
+        })
+    }
+}
+
+/// The cursor set by the wm instead of the cursor set by clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CursorOverride {
+    Shape(CursorShape),
+    Image(CursorImage),
+    Hidden,
+}
+
+/// Configuration of when the cursor is hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorHideConfig {
+    /// Hide the cursor when a key is pressed until the pointer moves.
+    pub hide_while_typing: bool,
+
+    /// How long the pointer must not move before the cursor fades out, or [`None`] to never fade out.
+    pub idle_timeout: Option<Duration>,
+
+    /// How long the fade out takes.
+    pub fade_duration: Duration,
+}
+
+impl Default for CursorHideConfig {
+    fn default() -> Self {
+        Self {
+            hide_while_typing: false,
+            idle_timeout: None,
+            fade_duration: Duration::from_millis(300),
+        }
+    }
+}
+
+/// How the cursor is presented.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CursorPresentation {
+    /// The cursor is fully visible and may be placed on a cursor plane.
+    Visible,
+
+    /// The cursor is fading out and must be drawn with the alpha by the renderer.
+    Fading(f32),
+
+    /// The cursor is not drawn.
+    Hidden,
+}
+
+/// Whether the cursor is hidden by typing or by pointer inactivity.
+#[derive(Debug)]
+pub struct CursorVisibility {
+    config: CursorHideConfig,
+    last_motion: Instant,
+    /// Whether a key was pressed since the pointer last moved.
+    typing: bool,
+    /// Whether the wm hid the cursor.
+    hidden: bool,
+    presentation: CursorPresentation,
+}
+
+impl CursorVisibility {
+    pub fn new(config: CursorHideConfig, now: Instant) -> Self {
+        Self {
+            config,
+            last_motion: now,
+            typing: false,
+            hidden: false,
+            presentation: CursorPresentation::Visible,
+        }
+    }
+
+    pub fn set_config(&mut self, config: CursorHideConfig) {
+        self.config = config;
+    }
+
+    pub fn presentation(&self) -> CursorPresentation {
+        self.presentation
+    }
+
+    /// Notify that the pointer moved.
+    ///
+    /// Returns the new presentation if the cursor was not fully visible.
+    pub fn motion(&mut self, now: Instant) -> Option<CursorPresentation> {
+        self.last_motion = now;
+        self.typing = false;
+
+        if self.hidden {
+            return None;
+        }
+
+        self.set_presentation(CursorPresentation::Visible)
+    }
+
+    /// Hide the cursor until the cursor is shown again, or show the cursor.
+    ///
+    /// Returns the new presentation if it changed.
+    pub fn set_hidden(&mut self, hidden: bool, now: Instant) -> Option<CursorPresentation> {
+        self.hidden = hidden;
+
+        if hidden {
+            return self.set_presentation(CursorPresentation::Hidden);
+        }
+
+        // Showing the cursor restarts the idle timeout.
+        self.motion(now)
+    }
+
+    /// Notify that a key was pressed.
+    ///
+    /// Returns the new presentation if the cursor was hidden.
+    pub fn key_pressed(&mut self) -> Option<CursorPresentation> {
+        if !self.config.hide_while_typing {
+            return None;
+        }
+
+        self.typing = true;
+        self.set_presentation(CursorPresentation::Hidden)
+    }
+
+    /// Advance the fade to the specified time.
+    ///
+    /// Returns the new presentation if it changed.
+    pub fn update(&mut self, now: Instant) -> Option<CursorPresentation> {
+        if self.typing || self.hidden {
+            return None;
+        }
+
+        let fade_at = self.fade_start()?;
+
+        let elapsed = now.saturating_duration_since(fade_at);
+        let presentation = if now < fade_at {
+            CursorPresentation::Visible
+        } else if elapsed >= self.config.fade_duration {
+            CursorPresentation::Hidden
+        } else {
+            let t = elapsed.as_secs_f32() / self.config.fade_duration.as_secs_f32();
+            CursorPresentation::Fading(1.0 - t)
+        };
+
+        self.set_presentation(presentation)
+    }
+
+    /// The next time [`CursorVisibility::update`] must be called.
+    ///
+    /// While fading this returns [`None`] since the fade should be advanced every frame.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.presentation {
+            CursorPresentation::Visible => self.fade_start(),
+            CursorPresentation::Fading(_) | CursorPresentation::Hidden => None,
+        }
+    }
+
+    fn fade_start(&self) -> Option<Instant> {
+        self.config.idle_timeout.map(|timeout| self.last_motion + timeout)
+    }
+
+    fn set_presentation(&mut self, presentation: CursorPresentation) -> Option<CursorPresentation> {
+        if self.presentation == presentation {
+            return None;
+        }
+
+        self.presentation = presentation;
+        Some(presentation)
+    }
+}
+
+impl Aerugo {
+    /// Restore the cursor when the pointer moves.
+    pub fn cursor_motion(&mut self, now: Instant) {
+        let presentation = self.cursor_visibility.motion(now);
+        self.cursor_presentation_changed(presentation);
+    }
+
+    /// Hide the cursor while typing if enabled.
+    pub fn cursor_key_pressed(&mut self) {
+        let presentation = self.cursor_visibility.key_pressed();
+        self.cursor_presentation_changed(presentation);
+    }
+
+    /// Hide the cursor regardless of the cursor set by clients or the wm, or show the cursor again.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        let presentation = self.cursor_visibility.set_hidden(!visible, Instant::now());
+        self.cursor_presentation_changed(presentation);
+    }
+
+    /// Replace the cursor set by clients, or show the cursor set by clients again if [`None`].
+    pub fn set_cursor_override(&mut self, cursor: Option<CursorOverride>) {
+        self.cursor_override = cursor;
+        self.update_cursor_image();
+    }
+
+    /// The client with pointer focus set the cursor.
+    pub(crate) fn client_cursor_changed(&mut self, status: CursorImageStatus) {
+        let previous = self.client_cursor_surface().cloned();
+        self.cursor_status = Some(status);
+
+        if previous.as_ref() != self.client_cursor_surface() {
+            if let Some(previous) = previous {
+                self.scene.destroy_surface_tree(&previous);
+            }
+
+            // The surface is drawn from the scene like any other surface, but is never placed on an output.
+            if let Some(surface) = self.client_cursor_surface().cloned() {
+                self.scene.create_surface_tree(surface);
+            }
+        }
+
+        self.update_cursor_image();
+    }
+
+    /// The surface a client draws its cursor into was destroyed.
+    pub(crate) fn cursor_surface_destroyed(&mut self, surface: &WlSurface) {
+        if self.client_cursor_surface() == Some(surface) {
+            self.client_cursor_changed(CursorImageStatus::Hidden);
+        }
+    }
+
+    fn client_cursor_surface(&self) -> Option<&WlSurface> {
+        match &self.cursor_status {
+            Some(CursorImageStatus::Surface(surface)) => Some(surface),
+            _ => None,
+        }
+    }
+
+    /// The surface the cursor is drawn from and its hotspot, if the cursor is drawn by a client.
+    pub fn cursor_surface(&self) -> Option<(&WlSurface, Point<i32, Logical>)> {
+        if self.cursor_override.is_some() {
+            return None;
+        }
+
+        let surface = self.client_cursor_surface()?;
+        let hotspot = compositor::with_states(surface, |states| {
+            states
+                .data_map
+                .get::<Mutex<CursorImageAttributes>>()
+                .map(|attributes| attributes.lock().unwrap().hotspot)
+                .unwrap_or_default()
+        });
+
+        Some((surface, hotspot))
+    }
+
+    /// Give the backend the image of the cursor set by the wm or the client, loaded for the scale of the output.
+    pub(crate) fn update_cursor_image(&mut self) {
+        let scale = self.output.current_scale().integer_scale().max(1) as u32;
+
+        let (shape, image) = match self.cursor_override.clone() {
+            Some(CursorOverride::Shape(shape)) => (Some(shape), None),
+            Some(CursorOverride::Image(image)) => (None, Some(Arc::from([image]))),
+            Some(CursorOverride::Hidden) => (None, None),
+            None => match &self.cursor_status {
+                // The surface of the client is drawn instead of an image.
+                Some(CursorImageStatus::Hidden | CursorImageStatus::Surface(_)) => (None, None),
+                _ => (Some(CursorShape::Default), None),
+            },
+        };
+
+        let image = match shape {
+            Some(shape) => {
+                let image = self.load_cursor_shape(shape, scale);
+
+                if image.is_none() {
+                    tracing::warn!(
+                        ?shape,
+                        theme = self.cursor_theme.name(),
+                        "Cursor theme does not provide the cursor"
+                    );
+                }
+
+                image
+            }
+            None => image,
+        };
+
+        self.backend.set_cursor_image(image.as_deref().and_then(<[_]>::first));
+        self.scene_changed();
+    }
+
+    /// Load the cursor of the shape, falling back to the legacy name of the cursor.
+    fn load_cursor_shape(&mut self, shape: CursorShape, scale: u32) -> Option<Arc<[CursorImage]>> {
+        self.cursor_theme.load(shape.name(), scale).or_else(|| {
+            let legacy = shape.legacy_name()?;
+            self.cursor_theme.load(legacy, scale)
+        })
+    }
+
+    /// Advance the idle fade of the cursor.
+    pub fn update_cursor_visibility(&mut self, now: Instant) {
+        let presentation = self.cursor_visibility.update(now);
+        self.cursor_presentation_changed(presentation);
+    }
+
+    fn cursor_presentation_changed(&mut self, presentation: Option<CursorPresentation>) {
+        let Some(presentation) = presentation else {
+            return;
+        };
+
+        self.backend.set_cursor_presentation(presentation);
+        self.scene_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{
+        parse_inherits, parse_xcursor, select_size, CursorHideConfig, CursorPresentation, CursorShape,
+        CursorVisibility, Error, XCURSOR_IMAGE_TYPE,
+    };
+
+    /// Create an XCursor file with a 1x1 image for each nominal size.
+    fn xcursor(sizes: &[u32]) -> Vec<u8> {
+        let words = |data: &mut Vec<u8>, words: &[u32]| {
+            for word in words {
+                data.extend(word.to_le_bytes());
+            }
+        };
+
+        let mut data = b"Xcur".to_vec();
+        words(&mut data, &[16, 0x10000, sizes.len() as u32]);
+
+        let images_start = 16 + sizes.len() * 12;
+        for (index, &size) in sizes.iter().enumerate() {
+            words(
+                &mut data,
+                &[XCURSOR_IMAGE_TYPE, size, (images_start + index * 40) as u32],
+            );
+        }
+
+        for &size in sizes {
+            words(
+                &mut data,
+                &[36, XCURSOR_IMAGE_TYPE, size, 1, 1, 1, 0, 0, 50, 0xff00ff00],
+            );
+        }
+
+        data
+    }
+
+    #[test]
+    fn parse_images() {
+        let images = parse_xcursor(&xcursor(&[24, 48])).unwrap();
+
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[1].nominal_size, 48);
+        assert_eq!(images[1].delay, 50);
+        assert_eq!(images[1].pixels, [0x00, 0xff, 0x00, 0xff]);
+
+        let data = xcursor(&[24]);
+        assert!(matches!(parse_xcursor(&data[..data.len() - 1]), Err(Error::Truncated)));
+        assert!(matches!(parse_xcursor(b"PNG"), Err(Error::NotXcursor)));
+    }
+
+    #[test]
+    fn select_scaled_size() {
+        let images = parse_xcursor(&xcursor(&[24, 32, 48])).unwrap();
+
+        assert_eq!(select_size(&images, 24)[0].nominal_size, 24);
+        // A cursor of size 24 on an output with scale 2.
+        assert_eq!(select_size(&images, 48)[0].nominal_size, 48);
+        assert_eq!(select_size(&images, 64)[0].nominal_size, 48);
+        assert!(select_size(&[], 24).is_empty());
+    }
+
+    #[test]
+    fn inherits() {
+        let index = "[Icon Theme]\nName=Breeze\nInherits = Adwaita, hicolor;\n";
+        assert_eq!(parse_inherits(index), ["Adwaita", "hicolor"]);
+        assert!(parse_inherits("[Icon Theme]\nName=Empty\n").is_empty());
+    }
+
+    #[test]
+    fn shape_names() {
+        assert_eq!(CursorShape::NwseResize.name(), "nwse-resize");
+        assert_eq!(CursorShape::Default.legacy_name(), Some("left_ptr"));
+        assert_eq!(CursorShape::SeResize.legacy_name(), Some("bottom_right_corner"));
+        assert_eq!(CursorShape::ZoomIn.legacy_name(), None);
+    }
+
+    const HIDE_CONFIG: CursorHideConfig = CursorHideConfig {
+        hide_while_typing: true,
+        idle_timeout: Some(Duration::from_secs(5)),
+        fade_duration: Duration::from_secs(1),
+    };
+
+    #[test]
+    fn idle_fade() {
+        let start = Instant::now();
+        let mut cursor = CursorVisibility::new(HIDE_CONFIG, start);
+
+        assert_eq!(cursor.update(start + Duration::from_secs(4)), None);
+        assert_eq!(cursor.next_deadline(), Some(start + Duration::from_secs(5)));
+
+        let Some(CursorPresentation::Fading(alpha)) = cursor.update(start + Duration::from_millis(5500)) else {
+            panic!("expected the cursor to fade");
+        };
+        assert!(alpha > 0.0 && alpha < 1.0);
+        assert_eq!(cursor.next_deadline(), None);
+
+        assert_eq!(
+            cursor.update(start + Duration::from_secs(6)),
+            Some(CursorPresentation::Hidden)
+        );
+        assert_eq!(
+            cursor.motion(start + Duration::from_secs(7)),
+            Some(CursorPresentation::Visible)
+        );
+    }
+
+    #[test]
+    fn hide_while_typing() {
+        let start = Instant::now();
+        let mut cursor = CursorVisibility::new(HIDE_CONFIG, start);
+
+        assert_eq!(cursor.key_pressed(), Some(CursorPresentation::Hidden));
+        // Typing keeps the cursor hidden even before the idle fade would start.
+        assert_eq!(cursor.update(start + Duration::from_secs(1)), None);
+        assert_eq!(cursor.presentation(), CursorPresentation::Hidden);
+        assert_eq!(cursor.motion(start), Some(CursorPresentation::Visible));
+
+        let mut cursor = CursorVisibility::new(CursorHideConfig::default(), start);
+        assert_eq!(cursor.key_pressed(), None);
+        assert_eq!(cursor.update(start + Duration::from_secs(3600)), None);
+    }
+
+    #[test]
+    fn hidden_by_wm() {
+        let start = Instant::now();
+        let mut cursor = CursorVisibility::new(HIDE_CONFIG, start);
+
+        assert_eq!(cursor.set_hidden(true, start), Some(CursorPresentation::Hidden));
+        // Neither motion nor the idle fade shows the cursor.
+        assert_eq!(cursor.motion(start + Duration::from_secs(1)), None);
+        assert_eq!(cursor.update(start + Duration::from_secs(10)), None);
+        assert_eq!(cursor.presentation(), CursorPresentation::Hidden);
+
+        let shown = start + Duration::from_secs(20);
+        assert_eq!(cursor.set_hidden(false, shown), Some(CursorPresentation::Visible));
+        assert_eq!(cursor.next_deadline(), Some(shown + Duration::from_secs(5)));
+    }
+}
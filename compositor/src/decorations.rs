@@ -0,0 +1,705 @@
+//! Server side decorations
+//!
+//! The wm declares the decorations of a toplevel (the height of the title bar, the width of the border and how
+//! they are filled) and the display server draws them around the toplevel. The title bar is placed above the
+//! toplevel and the border surrounds both the title bar and the toplevel, so the geometry of the toplevel never
+//! includes the decorations.
+//!
+//! Pointer input over the decorations is sent to the wm instead of the client, which lets the wm implement
+//! moving the toplevel by the title bar and resizing by the border without the client being involved.
+//!
+//! Clients state whether they prefer to draw their own decorations with xdg-decoration, but the wm decides which
+//! mode is used. Toplevels draw their own decorations until the wm sets the decoration mode.
+//!
+//! The visible member of a toplevel group with a [`TabLayout`] has a tab bar in place of its title bar. Each tab is
+//! filled with the title bar of its member, so the wm draws the title of every member as usual and the display
+//! server arranges them into tabs.
+//!
+//! The decorations are drawn in a branch below the surface tree of the toplevel, inside the wm node drawing the
+//! toplevel, so the decorations move, stack and hide with the toplevel.
+
+use std::sync::{Arc, Mutex};
+
+use smithay::{
+    backend::input::ButtonState,
+    input::pointer::MotionEvent,
+    reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode as DecorationMode,
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER},
+    wayland::{compositor, shell::xdg::SurfaceCachedState},
+};
+use wayland_server::protocol::wl_surface::WlSurface;
+use wm_runtime::{
+    types::{self, ResizeEdge},
+    WmEvent,
+};
+
+use crate::{
+    group::TabLayout,
+    pointer::PointerEvent,
+    scene::{BranchIndex, NodeIndex, Shape, ShapeIndex},
+    shell::{Shell, Toplevel, ToplevelId},
+    wm, Aerugo,
+};
+
+/// The edge of the border.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// The part of the decorations at a point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecorationRegion {
+    TitleBar,
+    Border(Edge),
+
+    /// A tab of the tab bar, by the index of its member in the group.
+    Tab(usize),
+}
+
+/// How a part of the decorations is filled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecorationFill {
+    /// A color with premultiplied alpha.
+    Color([f32; 4]),
+
+    /// A texture stretched to fill the area.
+    Texture {
+        width: u32,
+        height: u32,
+        /// Premultiplied ARGB8888 pixels.
+        pixels: Arc<[u8]>,
+    },
+}
+
+impl DecorationFill {
+    fn shape(&self) -> Shape {
+        match self {
+            Self::Color(color) => Shape::Solid(*color),
+            Self::Texture { width, height, pixels } => Shape::Texture {
+                size: (*width as i32, *height as i32).into(),
+                pixels: pixels.to_vec(),
+            },
+        }
+    }
+}
+
+/// Server side decorations of a toplevel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerDecorations {
+    pub title_bar_height: i32,
+    pub border_width: i32,
+    pub title_bar: DecorationFill,
+    /// The color of the border with premultiplied alpha.
+    pub border: [f32; 4],
+}
+
+impl ServerDecorations {
+    /// The area covered by the toplevel and the decorations.
+    pub fn outer_geometry(&self, geometry: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+        let border = self.border_width;
+        Rectangle::from_loc_and_size(
+            (geometry.loc.x - border, geometry.loc.y - self.title_bar_height - border),
+            (
+                geometry.size.w + border * 2,
+                geometry.size.h + self.title_bar_height + border * 2,
+            ),
+        )
+    }
+
+    /// The area of the title bar.
+    pub fn title_bar(&self, geometry: Rectangle<i32, Logical>) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size(
+            (geometry.loc.x, geometry.loc.y - self.title_bar_height),
+            (geometry.size.w, self.title_bar_height),
+        )
+    }
+
+    /// The decorations with the title bar grown to fit the tab bar.
+    pub fn with_tab_bar(&self, tab_bar: &TabBar) -> Self {
+        Self {
+            title_bar_height: tab_bar.height(self.title_bar_height),
+            ..self.clone()
+        }
+    }
+
+    /// The part of the decorations at the point, or [`None`] if the point is outside the decorations.
+    pub fn region_at(&self, geometry: Rectangle<i32, Logical>, point: Point<f64, Logical>) -> Option<DecorationRegion> {
+        let outer = self.outer_geometry(geometry).to_f64();
+
+        if !outer.contains(point) || geometry.to_f64().contains(point) {
+            return None;
+        }
+
+        if self.title_bar(geometry).to_f64().contains(point) {
+            return Some(DecorationRegion::TitleBar);
+        }
+
+        let border = self.border_width as f64;
+        let top = point.y < outer.loc.y + border;
+        let bottom = point.y >= outer.loc.y + outer.size.h - border;
+        let left = point.x < outer.loc.x + border;
+        let right = point.x >= outer.loc.x + outer.size.w - border;
+
+        let edge = match (top, bottom, left, right) {
+            (true, _, true, _) => Edge::TopLeft,
+            (true, _, _, true) => Edge::TopRight,
+            (_, true, true, _) => Edge::BottomLeft,
+            (_, true, _, true) => Edge::BottomRight,
+            (true, ..) => Edge::Top,
+            (_, true, ..) => Edge::Bottom,
+            (_, _, true, _) => Edge::Left,
+            _ => Edge::Right,
+        };
+
+        Some(DecorationRegion::Border(edge))
+    }
+}
+
+/// The tabs drawn in place of the title bar of the visible member of a tabbed or stacked group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TabBar {
+    pub layout: TabLayout,
+    /// The number of members in the group.
+    pub tabs: usize,
+    /// The index of the visible member.
+    pub active: usize,
+}
+
+impl TabBar {
+    /// The height of the tab bar when a single title bar has the height.
+    pub fn height(&self, title_bar_height: i32) -> i32 {
+        match self.layout {
+            TabLayout::Tabbed => title_bar_height,
+            TabLayout::Stacked => title_bar_height.saturating_mul(self.tabs.max(1) as i32),
+        }
+    }
+
+    /// The area of a tab in the title bar of the decorations with the tab bar.
+    ///
+    /// Tabs share the title bar evenly, with the edges rounded so the tabs exactly cover the title bar.
+    pub fn tab(&self, title_bar: Rectangle<i32, Logical>, index: usize) -> Rectangle<i32, Logical> {
+        let tabs = self.tabs.max(1) as i64;
+        let edge = |length: i32, index: usize| (i64::from(length) * index as i64 / tabs) as i32;
+
+        match self.layout {
+            TabLayout::Tabbed => {
+                let (start, end) = (edge(title_bar.size.w, index), edge(title_bar.size.w, index + 1));
+                Rectangle::from_loc_and_size(
+                    (title_bar.loc.x + start, title_bar.loc.y),
+                    (end - start, title_bar.size.h),
+                )
+            }
+            TabLayout::Stacked => {
+                let (start, end) = (edge(title_bar.size.h, index), edge(title_bar.size.h, index + 1));
+                Rectangle::from_loc_and_size(
+                    (title_bar.loc.x, title_bar.loc.y + start),
+                    (title_bar.size.w, end - start),
+                )
+            }
+        }
+    }
+
+    /// The index of the tab at the point, or [`None`] if the point is outside the title bar.
+    pub fn tab_at(&self, title_bar: Rectangle<i32, Logical>, point: Point<f64, Logical>) -> Option<usize> {
+        (0..self.tabs).find(|&index| self.tab(title_bar, index).to_f64().contains(point))
+    }
+}
+
+/// The scene nodes drawing the decorations of a toplevel.
+#[derive(Debug)]
+pub struct DecorationNodes {
+    branch: BranchIndex,
+    border: ShapeIndex,
+
+    /// The title bar, or a title bar for each tab.
+    title_bars: Vec<ShapeIndex>,
+
+    /// The decorations and the fills of the title bars the nodes were created for.
+    decorations: ServerDecorations,
+    fills: Vec<DecorationFill>,
+}
+
+/// Decoration mode preferred by the client of a toplevel.
+#[derive(Debug, Default)]
+struct PreferredDecorationMode(Mutex<Option<DecorationMode>>);
+
+/// The decoration mode the client of the toplevel prefers, or [`None`] if it has no preference.
+pub fn preferred_decoration_mode(surface: &WlSurface) -> Option<DecorationMode> {
+    compositor::with_states(surface, |states| {
+        states
+            .data_map
+            .get::<PreferredDecorationMode>()
+            .and_then(|mode| *mode.0.lock().unwrap())
+    })
+}
+
+impl Aerugo {
+    /// The server side decorations drawn around the toplevel, including the tab bar of its group.
+    pub fn toplevel_decorations(&self, id: ToplevelId) -> Option<ServerDecorations> {
+        let decorations = self.server_decorations.get(&id)?;
+
+        match self.toplevel_groups.tab_bar(id) {
+            Some(tab_bar) => Some(decorations.with_tab_bar(&tab_bar)),
+            None => Some(decorations.clone()),
+        }
+    }
+
+    /// Set the server side decorations drawn around the toplevel, or [`None`] to draw no decorations.
+    pub fn set_server_decorations(&mut self, id: ToplevelId, decorations: Option<ServerDecorations>) {
+        let changed = match decorations {
+            Some(decorations) => self.server_decorations.insert(id, decorations.clone()) != Some(decorations),
+            None => self.server_decorations.remove(&id).is_some(),
+        };
+
+        if changed {
+            self.decorations_changed(id);
+        }
+    }
+
+    /// Draw the decorations of the toplevel and the members of its group again, since the decorations, the tab bar
+    /// or the geometry of the toplevel changed.
+    pub(crate) fn decorations_changed(&mut self, id: ToplevelId) {
+        let members = self
+            .toplevel_groups
+            .group_of(id)
+            .and_then(|group| self.toplevel_groups.get(group))
+            .map(|group| group.members().to_vec())
+            .unwrap_or_else(|| vec![id]);
+
+        for member in members {
+            self.update_decoration_nodes(member);
+        }
+
+        self.scene_changed();
+    }
+
+    /// Create, place or remove the scene nodes drawing the decorations of the toplevel.
+    ///
+    /// Hidden members of a group and toplevels which are not drawn by a wm node have no decorations.
+    fn update_decoration_nodes(&mut self, id: ToplevelId) {
+        let parent = self
+            .toplevel_node(id)
+            .and_then(|surface_tree| self.wm_toplevel_branch(id, surface_tree));
+
+        let (Some(decorations), Some(parent), Some(geometry), false) = (
+            self.toplevel_decorations(id),
+            parent,
+            self.window_geometry(id),
+            self.toplevel_groups.is_hidden(id),
+        ) else {
+            self.remove_decoration_nodes(id);
+            return;
+        };
+
+        // Each tab is filled with the title bar of its member.
+        let tab_bar = self.toplevel_groups.tab_bar(id);
+        let fills = match tab_bar {
+            Some(_) => self
+                .toplevel_groups
+                .group_of(id)
+                .and_then(|group| self.toplevel_groups.get(group))
+                .map(|group| group.members().to_vec())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|member| {
+                    let decorations = self.server_decorations.get(&member).unwrap_or(&decorations);
+                    decorations.title_bar.clone()
+                })
+                .collect(),
+            None => vec![decorations.title_bar.clone()],
+        };
+
+        let recreate = self
+            .decoration_nodes
+            .get(&id)
+            .map_or(true, |nodes| nodes.decorations != decorations || nodes.fills != fills);
+
+        if recreate {
+            self.remove_decoration_nodes(id);
+
+            let branch = self.scene.create_branch();
+            let border = self.scene.create_shape(
+                (0, 0).into(),
+                Shape::Border {
+                    width: decorations.border_width,
+                    color: decorations.border,
+                },
+            );
+            let title_bars = fills
+                .iter()
+                .map(|fill| self.scene.create_shape((0, 0).into(), fill.shape()))
+                .collect::<Vec<_>>();
+
+            for &shape in title_bars.iter().chain([&border]) {
+                let _ = self.scene.branch_add_child(branch, NodeIndex::Shape(shape));
+            }
+
+            self.decoration_nodes.insert(
+                id,
+                DecorationNodes {
+                    branch,
+                    border,
+                    title_bars,
+                    decorations: decorations.clone(),
+                    fills,
+                },
+            );
+        }
+
+        let Some(nodes) = self.decoration_nodes.get(&id) else {
+            return;
+        };
+
+        let scale = self.output.current_scale().integer_scale();
+        let outer = decorations.outer_geometry(geometry).to_physical(scale);
+        let title_bar = decorations.title_bar(geometry);
+
+        self.scene.set_node_offset(NodeIndex::Shape(nodes.border), outer.loc);
+        self.scene.set_shape_size(nodes.border, outer.size);
+
+        for (index, &shape) in nodes.title_bars.iter().enumerate() {
+            let area = match tab_bar {
+                Some(tab_bar) => tab_bar.tab(title_bar, index),
+                None => title_bar,
+            }
+            .to_physical(scale);
+
+            self.scene.set_node_offset(NodeIndex::Shape(shape), area.loc);
+            self.scene.set_shape_size(shape, area.size);
+        }
+
+        // The decorations are drawn below the toplevel and below the children the wm added to the toplevel node.
+        let branch = NodeIndex::Branch(nodes.branch);
+        if !self.scene.children(parent).any(|child| child == branch) {
+            if let Err(err) = self.scene.add_child(parent, branch) {
+                tracing::warn!(id, %err, "Failed to add decorations to the toplevel node");
+                return;
+            }
+
+            self.scene.lower_node_to_bottom(branch);
+        }
+    }
+
+    fn remove_decoration_nodes(&mut self, id: ToplevelId) {
+        let Some(nodes) = self.decoration_nodes.remove(&id) else {
+            return;
+        };
+
+        for shape in nodes.title_bars.into_iter().chain([nodes.border]) {
+            self.scene.destroy_shape(shape);
+        }
+
+        self.scene.destroy_branch(nodes.branch);
+    }
+
+    /// The window geometry of the toplevel relative to its surface, which excludes client side shadows.
+    fn window_geometry(&self, id: ToplevelId) -> Option<Rectangle<i32, Logical>> {
+        let surface = self.shell.get_state(id)?.wl_surface()?;
+        let index = self.scene.get_surface_tree_index(surface.clone())?;
+        let scale = self.output.current_scale().integer_scale();
+        let size = self.scene.surface_tree_geometry(index)?.size.to_logical(scale);
+
+        let geometry = compositor::with_states(&surface, |states| {
+            states.cached_state.current::<SurfaceCachedState>().geometry
+        });
+
+        Some(geometry.unwrap_or_else(|| Rectangle::from_loc_and_size((0, 0), size)))
+    }
+
+    /// The client of the toplevel stated which decoration mode it prefers, or [`None`] if it has no preference.
+    ///
+    /// The preference is only a hint, the mode is not changed until the wm sets it. The client is still sent a
+    /// configure with the current mode, since xdg-decoration requires a configure in response.
+    pub fn decoration_mode_requested(&mut self, surface: &WlSurface, mode: Option<DecorationMode>) {
+        compositor::with_states(surface, |states| {
+            states
+                .data_map
+                .insert_if_missing_threadsafe(PreferredDecorationMode::default);
+            *states
+                .data_map
+                .get::<PreferredDecorationMode>()
+                .unwrap()
+                .0
+                .lock()
+                .unwrap() = mode;
+        });
+
+        // The preference of a toplevel which is not mapped yet is sent when the toplevel is mapped, and the initial
+        // configure carries the current mode.
+        let Some(id) = Shell::get_toplevel_id(surface) else {
+            return;
+        };
+
+        tracing::debug!(id, ?mode, "Toplevel prefers decoration mode");
+
+        let serial = self
+            .shell
+            .get_state(id)
+            .and_then(Toplevel::xdg_toplevel)
+            .map(|toplevel| toplevel.send_configure());
+
+        if let Some(serial) = serial {
+            self.configure_sent(id, serial);
+        }
+
+        self.toplevel_updated(id);
+    }
+
+    /// Set whether the client or the display server decorates the toplevel, as decided by the wm.
+    ///
+    /// The mode is sent with the next configure of the toplevel. The client must draw its decorations as told,
+    /// whichever mode it prefers. The wm also sets the server side decorations which are drawn for server side
+    /// decorated toplevels.
+    pub fn set_decoration_mode(&mut self, id: ToplevelId, mode: DecorationMode) {
+        if let Some(toplevel) = self.shell.get_state(id) {
+            toplevel.set_decoration_mode(mode);
+        }
+    }
+
+    /// The part of the decorations of the toplevel at the point.
+    pub fn decoration_region_at(
+        &self,
+        id: ToplevelId,
+        geometry: Rectangle<i32, Logical>,
+        point: Point<f64, Logical>,
+    ) -> Option<DecorationRegion> {
+        let decorations = self.toplevel_decorations(id)?;
+
+        match decorations.region_at(geometry, point)? {
+            DecorationRegion::TitleBar => match self.toplevel_groups.tab_bar(id) {
+                Some(tab_bar) => tab_bar
+                    .tab_at(decorations.title_bar(geometry), point)
+                    .map(DecorationRegion::Tab),
+                None => Some(DecorationRegion::TitleBar),
+            },
+            region => Some(region),
+        }
+    }
+
+    /// The toplevel with decorations at the location in the global compositor space, with the area covered by the
+    /// toplevel and its decorations and the part of the decorations at the location.
+    ///
+    /// Decorations never cover the toplevel they surround, so decorations are only under the location if no
+    /// surface of another toplevel is.
+    fn decoration_under(
+        &self,
+        location: Point<f64, Logical>,
+    ) -> Option<(ToplevelId, Rectangle<i32, Logical>, DecorationRegion)> {
+        let surface = self
+            .surface_under(location)
+            .and_then(|(surface, _)| Shell::get_toplevel_id(&surface));
+
+        self.decoration_nodes
+            .keys()
+            .copied()
+            .filter(|&id| surface.map_or(true, |surface| surface == id))
+            .find_map(|id| {
+                let geometry = self.toplevel_geometry(id)?;
+                let region = self.decoration_region_at(id, geometry, location)?;
+                let outer = self.toplevel_decorations(id)?.outer_geometry(geometry);
+                Some((id, outer, region))
+            })
+    }
+
+    /// Send a pointer event over server side decorations to the wm instead of the client under the pointer.
+    ///
+    /// Returns [`true`] if the event was consumed. Pressing a tab shows the member of the tab before the wm is told.
+    pub(crate) fn decoration_pointer_input(&mut self, event: PointerEvent) -> bool {
+        if self.wm_crash.is_some() || self.is_session_locked() {
+            return false;
+        }
+
+        // A client holding a button keeps receiving motion while the pointer is over decorations.
+        let Some(pointer) = self.seat.get_pointer().filter(|pointer| !pointer.is_grabbed()) else {
+            return false;
+        };
+
+        let (time, button) = match event {
+            PointerEvent::Motion { time, .. } => (time, None),
+            PointerEvent::Button { time, button, state } => (time, Some((button, state))),
+            PointerEvent::Axis { .. } => return false,
+        };
+
+        let Some((id, outer, region)) = self.decoration_under(self.pointer_location) else {
+            return false;
+        };
+
+        let Some(toplevel) = self.wm.toplevel_id(id) else {
+            return false;
+        };
+
+        if let (Some((_, ButtonState::Pressed)), DecorationRegion::Tab(index)) = (button, region) {
+            self.show_tab(id, index);
+        }
+
+        let seat = self.wm.seat();
+        let position = wm::wm_point(self.pointer_location - outer.loc.to_f64());
+        let region = wm_decoration_region(region);
+
+        match button {
+            None => {
+                // The client the pointer was over is told the pointer left.
+                let location = self.pointer_location;
+                let serial = SERIAL_COUNTER.next_serial();
+                pointer.motion(self, None, &MotionEvent { location, serial, time });
+                pointer.frame(self);
+
+                self.send_wm(WmEvent::DecorationMotion {
+                    seat,
+                    toplevel,
+                    time,
+                    position,
+                    global: wm::wm_point(location),
+                    region,
+                });
+            }
+
+            Some((button, state)) => self.send_wm(WmEvent::DecorationButton {
+                seat,
+                toplevel,
+                time,
+                button,
+                state: match state {
+                    ButtonState::Pressed => types::ButtonState::Pressed,
+                    ButtonState::Released => types::ButtonState::Released,
+                },
+                position,
+                region,
+            }),
+        }
+
+        true
+    }
+
+    /// Show the member of the tab of the group of the toplevel.
+    fn show_tab(&mut self, id: ToplevelId, index: usize) {
+        let Some(group) = self.toplevel_groups.group_of(id) else {
+            return;
+        };
+
+        let member = self
+            .toplevel_groups
+            .get(group)
+            .and_then(|group| group.members().get(index).copied());
+
+        if let Some(member) = member {
+            if let Err(err) = self.set_visible_in_toplevel_group(group, member) {
+                tracing::warn!(id, member, %err, "Failed to show the member of the pressed tab");
+            }
+        }
+    }
+}
+
+fn wm_decoration_region(region: DecorationRegion) -> types::DecorationRegion {
+    match region {
+        DecorationRegion::TitleBar => types::DecorationRegion::TitleBar,
+        DecorationRegion::Tab(index) => types::DecorationRegion::Tab(index as u32),
+        DecorationRegion::Border(edge) => types::DecorationRegion::Border(match edge {
+            Edge::Top => ResizeEdge::Top,
+            Edge::Bottom => ResizeEdge::Bottom,
+            Edge::Left => ResizeEdge::Left,
+            Edge::Right => ResizeEdge::Right,
+            Edge::TopLeft => ResizeEdge::TopLeft,
+            Edge::TopRight => ResizeEdge::TopRight,
+            Edge::BottomLeft => ResizeEdge::BottomLeft,
+            Edge::BottomRight => ResizeEdge::BottomRight,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::Rectangle;
+
+    use crate::group::TabLayout;
+
+    use super::{DecorationFill, DecorationRegion, Edge, ServerDecorations, TabBar};
+
+    const DECORATIONS: ServerDecorations = ServerDecorations {
+        title_bar_height: 20,
+        border_width: 2,
+        title_bar: DecorationFill::Color([0.2, 0.2, 0.2, 1.0]),
+        border: [0.0, 0.0, 0.0, 1.0],
+    };
+
+    #[test]
+    fn geometry_excludes_decorations() {
+        let geometry = Rectangle::from_loc_and_size((100, 100), (200, 100));
+
+        assert_eq!(
+            DECORATIONS.outer_geometry(geometry),
+            Rectangle::from_loc_and_size((98, 78), (204, 124))
+        );
+        assert_eq!(
+            DECORATIONS.title_bar(geometry),
+            Rectangle::from_loc_and_size((100, 80), (200, 20))
+        );
+    }
+
+    #[test]
+    fn regions() {
+        let geometry = Rectangle::from_loc_and_size((100, 100), (200, 100));
+        let region = |x, y| DECORATIONS.region_at(geometry, (x, y).into());
+
+        assert_eq!(region(150., 90.), Some(DecorationRegion::TitleBar));
+        assert_eq!(region(150., 150.), None);
+        assert_eq!(region(50., 50.), None);
+        assert_eq!(region(150., 79.), Some(DecorationRegion::Border(Edge::Top)));
+        assert_eq!(region(99., 150.), Some(DecorationRegion::Border(Edge::Left)));
+        assert_eq!(region(301., 201.), Some(DecorationRegion::Border(Edge::BottomRight)));
+        assert_eq!(region(98., 78.), Some(DecorationRegion::Border(Edge::TopLeft)));
+    }
+
+    #[test]
+    fn tabs() {
+        let geometry = Rectangle::from_loc_and_size((100, 100), (200, 100));
+        let tabbed = TabBar {
+            layout: TabLayout::Tabbed,
+            tabs: 3,
+            active: 0,
+        };
+
+        let title_bar = DECORATIONS.with_tab_bar(&tabbed).title_bar(geometry);
+        assert_eq!(
+            tabbed.tab(title_bar, 0),
+            Rectangle::from_loc_and_size((100, 80), (66, 20))
+        );
+        assert_eq!(
+            tabbed.tab(title_bar, 1),
+            Rectangle::from_loc_and_size((166, 80), (67, 20))
+        );
+        assert_eq!(
+            tabbed.tab(title_bar, 2),
+            Rectangle::from_loc_and_size((233, 80), (67, 20))
+        );
+        assert_eq!(tabbed.tab_at(title_bar, (250., 90.).into()), Some(2));
+
+        // Stacked tabs each get a title bar, so the title bar grows with the number of members.
+        let stacked = TabBar {
+            layout: TabLayout::Stacked,
+            ..tabbed
+        };
+
+        let decorations = DECORATIONS.with_tab_bar(&stacked);
+        let title_bar = decorations.title_bar(geometry);
+        assert_eq!(title_bar, Rectangle::from_loc_and_size((100, 40), (200, 60)));
+        assert_eq!(
+            stacked.tab(title_bar, 1),
+            Rectangle::from_loc_and_size((100, 60), (200, 20))
+        );
+        assert_eq!(stacked.tab_at(title_bar, (150., 45.).into()), Some(0));
+        assert_eq!(stacked.tab_at(title_bar, (150., 100.).into()), None);
+        assert_eq!(
+            decorations.region_at(geometry, (150., 45.).into()),
+            Some(DecorationRegion::TitleBar)
+        );
+    }
+}
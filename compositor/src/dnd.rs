@@ -0,0 +1,144 @@
+//! Drag and drop
+//!
+//! The display server does not draw the drag icon. Instead the wm is told when a client starts dragging data
+//! (with a snapshot of the drag icon), as the pointer moves and when the data is dropped or the drag is cancelled.
+//! This allows the wm to place the icon in its own scene and to implement drop zones between tiled toplevels.
+//!
+//! Only one drag is in progress at a time since there is a single seat.
+
+use smithay::utils::{Logical, Point};
+use wayland_server::protocol::wl_surface::WlSurface;
+use wm_runtime::WmEvent;
+
+use crate::{shell::ToplevelId, wm, Aerugo};
+
+/// A drag started by a client.
+#[derive(Debug)]
+pub struct Drag {
+    /// The surface of the drag icon.
+    pub icon: Option<WlSurface>,
+    pub mime_types: Vec<String>,
+    /// The pid of the client which started the drag.
+    pub owner: Option<i32>,
+    pub position: Point<f64, Logical>,
+    /// The toplevel under the pointer which is offered the data.
+    pub focus: Option<ToplevelId>,
+}
+
+/// How a drag ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DragOutcome {
+    /// The data was dropped on the toplevel.
+    Dropped {
+        position: Point<f64, Logical>,
+        focus: ToplevelId,
+    },
+
+    /// The data was not dropped on a toplevel.
+    Cancelled,
+}
+
+impl Drag {
+    pub fn new(icon: Option<WlSurface>, mime_types: Vec<String>, owner: Option<i32>) -> Self {
+        Self {
+            icon,
+            mime_types,
+            owner,
+            position: Point::default(),
+            focus: None,
+        }
+    }
+
+    /// The outcome of releasing the drag at the current position.
+    pub fn outcome(&self) -> DragOutcome {
+        match self.focus {
+            Some(focus) => DragOutcome::Dropped {
+                position: self.position,
+                focus,
+            },
+            None => DragOutcome::Cancelled,
+        }
+    }
+}
+
+impl Aerugo {
+    /// A client started dragging data.
+    pub fn drag_started(&mut self, drag: Drag) {
+        tracing::debug!(mime_types = ?drag.mime_types, owner = drag.owner, "Drag started");
+        let icon = drag
+            .icon
+            .as_ref()
+            .and_then(|icon| self.snapshot_surface(icon))
+            .map(|thumbnail| self.give_snapshot(thumbnail));
+        self.send_wm(WmEvent::DragStarted {
+            icon,
+            mime_types: drag.mime_types.clone(),
+        });
+        self.drag = Some(drag);
+    }
+
+    /// The pointer moved while dragging.
+    pub fn drag_motion(&mut self, position: Point<f64, Logical>, focus: Option<ToplevelId>) {
+        let Some(drag) = self.drag.as_mut() else {
+            return;
+        };
+
+        drag.position = position;
+        drag.focus = focus;
+
+        let focus = focus.and_then(|focus| self.wm.toplevel_id(focus));
+        self.send_wm(WmEvent::DragMotion {
+            position: wm::wm_point(position),
+            focus,
+        });
+    }
+
+    /// The drag button was released.
+    pub fn drag_ended(&mut self) {
+        let Some(drag) = self.drag.take() else {
+            return;
+        };
+
+        let outcome = drag.outcome();
+        tracing::debug!(?outcome, "Drag ended");
+
+        let event = match outcome {
+            DragOutcome::Dropped { position, focus } => WmEvent::DragDropped {
+                position: wm::wm_point(position),
+                focus: self.wm.toplevel_id(focus),
+            },
+            DragOutcome::Cancelled => WmEvent::DragCancelled,
+        };
+        self.send_wm(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::toplevel;
+
+    use super::{Drag, DragOutcome};
+
+    #[test]
+    fn drop_on_focus() {
+        let mut drag = Drag::new(None, vec!["text/uri-list".into()], Some(42));
+        let focus = toplevel(3);
+
+        drag.position = (10., 20.).into();
+        drag.focus = Some(focus);
+
+        assert_eq!(
+            drag.outcome(),
+            DragOutcome::Dropped {
+                position: (10., 20.).into(),
+                focus,
+            }
+        );
+    }
+
+    #[test]
+    fn drop_without_focus_is_cancelled() {
+        let drag = Drag::new(None, Vec::new(), None);
+        assert_eq!(drag.outcome(), DragOutcome::Cancelled);
+    }
+}
@@ -0,0 +1,260 @@
+//! Emergency chords
+//!
+//! Key bindings are normally owned by the wm. A wm which hangs or keeps crashing would leave the user without any
+//! way to open a terminal or leave the session, so a few chords are matched by the display server before key
+//! events reach the wm runtime. These chords are always active, even while an exclusive grab is active. The only
+//! exception is the terminal while the session is locked, since the terminal would be shown over the lock screen.
+//!
+//! By default Ctrl+Alt+Shift+Return spawns a terminal, Ctrl+Alt+Shift+BackSpace reloads the wm and
+//! Ctrl+Alt+Shift+Escape exits the session. The chords and the terminal may be changed by the configuration.
+
+use std::{env, process::Command, thread};
+
+use bitflags::bitflags;
+use smithay::input::keyboard::{xkb, ModifiersState};
+
+use crate::Loop;
+
+/// The keysym of the return key.
+const KEY_RETURN: u32 = 0xff0d;
+/// The keysym of the backspace key.
+const KEY_BACKSPACE: u32 = 0xff08;
+/// The keysym of the escape key.
+const KEY_ESCAPE: u32 = 0xff1b;
+
+bitflags! {
+    /// The modifiers which must be held for a chord.
+    ///
+    /// Lock modifiers are ignored.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct ChordModifiers: u32 {
+        const CTRL = 0x01;
+        const ALT = 0x02;
+        const SHIFT = 0x04;
+        const LOGO = 0x08;
+    }
+}
+
+impl ChordModifiers {
+    pub fn from_state(modifiers: &ModifiersState) -> Self {
+        let mut chord = ChordModifiers::empty();
+        chord.set(ChordModifiers::CTRL, modifiers.ctrl);
+        chord.set(ChordModifiers::ALT, modifiers.alt);
+        chord.set(ChordModifiers::SHIFT, modifiers.shift);
+        chord.set(ChordModifiers::LOGO, modifiers.logo);
+        chord
+    }
+}
+
+/// What an emergency chord does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmergencyAction {
+    /// Spawn the configured terminal.
+    Terminal,
+
+    /// Load the wm module again, such as after the wm hung.
+    ReloadWm,
+
+    /// Exit the display server.
+    Exit,
+}
+
+/// A key and the modifiers which must be held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub modifiers: ChordModifiers,
+    pub sym: u32,
+}
+
+impl Chord {
+    const fn ctrl_alt_shift(sym: u32) -> Self {
+        Self {
+            modifiers: ChordModifiers::CTRL
+                .union(ChordModifiers::ALT)
+                .union(ChordModifiers::SHIFT),
+            sym,
+        }
+    }
+}
+
+/// An error from parsing a chord.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("unknown modifier {0:?}")]
+    Modifier(String),
+
+    #[error("unknown key {0:?}")]
+    Key(String),
+}
+
+/// Parse a chord from the configuration, such as `ctrl+alt+shift+Return`.
+///
+/// The key is the last part and is an xkb keysym name.
+pub fn parse_chord(chord: &str) -> Result<Chord, Error> {
+    let (modifiers, key) = chord.rsplit_once('+').unwrap_or(("", chord));
+    let sym = xkb::keysym_from_name(key, xkb::KEYSYM_NO_FLAGS);
+
+    if sym == xkb::keysyms::KEY_NoSymbol {
+        return Err(Error::Key(key.to_owned()));
+    }
+
+    let modifiers = modifiers.split('+').filter(|modifier| !modifier.is_empty()).try_fold(
+        ChordModifiers::empty(),
+        |modifiers, modifier| {
+            let modifier = match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => ChordModifiers::CTRL,
+                "alt" => ChordModifiers::ALT,
+                "shift" => ChordModifiers::SHIFT,
+                "logo" | "super" => ChordModifiers::LOGO,
+                _ => return Err(Error::Modifier(modifier.to_owned())),
+            };
+
+            Ok(modifiers | modifier)
+        },
+    )?;
+
+    Ok(Chord { modifiers, sym })
+}
+
+/// Configuration of the emergency chords.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmergencyConfig {
+    /// The terminal and its arguments.
+    pub terminal: Vec<String>,
+
+    pub chords: Vec<(Chord, EmergencyAction)>,
+}
+
+impl EmergencyConfig {
+    /// The action of the chord, if the key completes a chord.
+    pub fn action(&self, modifiers: ChordModifiers, sym: u32) -> Option<EmergencyAction> {
+        self.chords
+            .iter()
+            .find(|(chord, _)| chord.sym == sym && chord.modifiers == modifiers)
+            .map(|&(_, action)| action)
+    }
+}
+
+impl Default for EmergencyConfig {
+    /// The terminal is `$TERMINAL`, falling back to `xterm`.
+    fn default() -> Self {
+        let terminal = env::var("TERMINAL")
+            .ok()
+            .filter(|terminal| !terminal.is_empty())
+            .unwrap_or_else(|| "xterm".into());
+
+        Self {
+            terminal: vec![terminal],
+            chords: vec![
+                (Chord::ctrl_alt_shift(KEY_RETURN), EmergencyAction::Terminal),
+                (Chord::ctrl_alt_shift(KEY_BACKSPACE), EmergencyAction::ReloadWm),
+                (Chord::ctrl_alt_shift(KEY_ESCAPE), EmergencyAction::Exit),
+            ],
+        }
+    }
+}
+
+impl Loop {
+    /// Run the emergency action of a pressed key.
+    ///
+    /// Returns true if the key was an emergency chord, in which case the key must not be sent to the wm or a
+    /// client.
+    pub fn emergency_key(&mut self, modifiers: &ModifiersState, sym: u32) -> bool {
+        let Some(action) = self.emergency.action(ChordModifiers::from_state(modifiers), sym) else {
+            return false;
+        };
+
+        let locked = self.comp.is_session_locked();
+
+        if locked && action == EmergencyAction::Terminal {
+            return false;
+        }
+
+        tracing::info!(?action, "Emergency chord pressed");
+
+        match action {
+            EmergencyAction::Terminal => self.spawn_emergency_terminal(),
+
+            // The runtime restarts a hung wm on a new store, so this works even if the wm never returns.
+            EmergencyAction::ReloadWm => self.reload_wm(),
+
+            EmergencyAction::Exit => {
+                self.signal.stop();
+                self.signal.wakeup();
+            }
+        }
+
+        true
+    }
+
+    fn spawn_emergency_terminal(&mut self) {
+        let Some((program, args)) = self.emergency.terminal.split_first() else {
+            return;
+        };
+
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        self.environment.apply(&mut cmd);
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                tracing::error!(%err, program, "Failed to spawn emergency terminal");
+                return;
+            }
+        };
+
+        tracing::debug!(program, pid = child.id(), "Spawned emergency terminal");
+
+        // Wait for the terminal on another thread so the process is reaped when it exits.
+        let waited = thread::Builder::new().name("Emergency terminal".into()).spawn(move || {
+            let _ = child.wait();
+        });
+
+        if let Err(err) = waited {
+            tracing::warn!(%err, "Failed to wait for emergency terminal");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::input::keyboard::ModifiersState;
+
+    use super::{parse_chord, Chord, ChordModifiers, EmergencyAction, EmergencyConfig, Error, KEY_ESCAPE, KEY_RETURN};
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_chord("ctrl+alt+shift+Return"),
+            Ok(Chord::ctrl_alt_shift(KEY_RETURN))
+        );
+        assert_eq!(
+            parse_chord("Super+Escape"),
+            Ok(Chord {
+                modifiers: ChordModifiers::LOGO,
+                sym: KEY_ESCAPE,
+            })
+        );
+        assert_eq!(parse_chord("hyper+Return"), Err(Error::Modifier("hyper".into())));
+        assert_eq!(parse_chord("ctrl+NotAKey"), Err(Error::Key("NotAKey".into())));
+    }
+
+    #[test]
+    fn default_chords() {
+        let config = EmergencyConfig::default();
+        let held = ModifiersState {
+            ctrl: true,
+            alt: true,
+            shift: true,
+            caps_lock: true,
+            ..ModifiersState::default()
+        };
+
+        // Lock modifiers do not prevent the chord.
+        let modifiers = ChordModifiers::from_state(&held);
+        assert_eq!(config.action(modifiers, KEY_ESCAPE), Some(EmergencyAction::Exit));
+        assert_eq!(config.action(modifiers, 0x61), None);
+        assert_eq!(config.action(ChordModifiers::CTRL, KEY_RETURN), None);
+    }
+}
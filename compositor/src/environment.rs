@@ -0,0 +1,213 @@
+//! Environment of child processes
+//!
+//! Clients spawned by the display server (and by the wm) need to know how to connect to the display server, so
+//! `WAYLAND_DISPLAY` and, once XWayland is running, `DISPLAY` are exported to every child process. The
+//! configuration may set further variables for every client, such as the cursor theme or the hints telling Qt
+//! and GTK to prefer Wayland.
+//!
+//! Applications started by D-Bus activation or systemd are not children of the display server. If enabled, the
+//! environment is also exported using `dbus-update-activation-environment` so those applications can connect.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    process::Command,
+    thread,
+};
+
+/// Variables which make toolkits prefer Wayland, falling back to X11 if the toolkit does not support Wayland.
+pub const TOOLKIT_HINTS: &[(&str, &str)] = &[
+    ("QT_QPA_PLATFORM", "wayland;xcb"),
+    ("GDK_BACKEND", "wayland,x11"),
+    ("SDL_VIDEODRIVER", "wayland,x11"),
+    ("CLUTTER_BACKEND", "wayland"),
+    ("MOZ_ENABLE_WAYLAND", "1"),
+];
+
+/// The environment of child processes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Environment {
+    /// Variables set by the configuration.
+    vars: BTreeMap<String, OsString>,
+    wayland_display: Option<OsString>,
+    /// The value of `DISPLAY` while XWayland is running.
+    x_display: Option<OsString>,
+    update_activation_environment: bool,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a variable for every child process.
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<OsString>) -> &mut Self {
+        self.vars.insert(name.into(), value.into());
+        self
+    }
+
+    /// Remove a variable set with [`Environment::set`].
+    pub fn unset(&mut self, name: &str) -> &mut Self {
+        self.vars.remove(name);
+        self
+    }
+
+    /// Set the cursor theme and size used by clients.
+    pub fn set_cursor_theme(&mut self, theme: &str, size: u32) -> &mut Self {
+        self.set("XCURSOR_THEME", theme);
+        self.set("XCURSOR_SIZE", size.to_string())
+    }
+
+    /// Set the [`TOOLKIT_HINTS`].
+    ///
+    /// Hints already set by the configuration are not replaced.
+    pub fn set_toolkit_hints(&mut self) -> &mut Self {
+        for &(name, value) in TOOLKIT_HINTS {
+            self.vars.entry(name.into()).or_insert_with(|| value.into());
+        }
+
+        self
+    }
+
+    /// Export the environment using `dbus-update-activation-environment` whenever it changes.
+    pub fn set_update_activation_environment(&mut self, update: bool) -> &mut Self {
+        self.update_activation_environment = update;
+        self
+    }
+
+    /// Set the name of the Wayland socket clients connect to.
+    pub fn set_wayland_display(&mut self, socket: impl Into<OsString>) {
+        self.wayland_display = Some(socket.into());
+        self.update_activation_environment();
+    }
+
+    /// Set the X11 display number of XWayland, or [`None`] if XWayland is not running.
+    pub fn set_x_display(&mut self, display: Option<u32>) {
+        self.x_display = display.map(|display| format!(":{display}").into());
+        self.update_activation_environment();
+    }
+
+    /// Every variable exported to child processes.
+    ///
+    /// `WAYLAND_DISPLAY` and `DISPLAY` always reflect the display server and cannot be set by the configuration.
+    pub fn vars(&self) -> impl Iterator<Item = (&str, &OsStr)> {
+        let wayland_display = self
+            .wayland_display
+            .as_deref()
+            .map(|socket| ("WAYLAND_DISPLAY", socket));
+        let x_display = self.x_display.as_deref().map(|display| ("DISPLAY", display));
+
+        self.vars
+            .iter()
+            .filter(|(name, _)| !matches!(name.as_str(), "WAYLAND_DISPLAY" | "DISPLAY"))
+            .map(|(name, value)| (name.as_str(), value.as_os_str()))
+            .chain(wayland_display)
+            .chain(x_display)
+    }
+
+    /// Apply the environment to a command which spawns a client.
+    pub fn apply(&self, command: &mut Command) {
+        command.envs(self.vars());
+
+        // A client started without XWayland running must not connect to the X server the display server may be
+        // running inside of.
+        if self.x_display.is_none() {
+            command.env_remove("DISPLAY");
+        }
+    }
+
+    /// The `dbus-update-activation-environment` command exporting the environment.
+    pub fn activation_environment_command(&self) -> Command {
+        let mut command = Command::new("dbus-update-activation-environment");
+        command.arg("--systemd");
+
+        for (name, value) in self.vars() {
+            let mut arg = OsString::from(name);
+            arg.push("=");
+            arg.push(value);
+            command.arg(arg);
+        }
+
+        command
+    }
+
+    /// Export the environment using `dbus-update-activation-environment` if enabled.
+    pub fn update_activation_environment(&self) {
+        if !self.update_activation_environment {
+            return;
+        }
+
+        let mut command = self.activation_environment_command();
+
+        // Wait for the command on another thread so the event loop is not blocked.
+        let spawned = thread::Builder::new()
+            .name("Activation environment".into())
+            .spawn(move || match command.status() {
+                Ok(status) if status.success() => (),
+                Ok(status) => tracing::warn!(%status, "dbus-update-activation-environment failed"),
+                Err(err) => tracing::warn!(%err, "Failed to run dbus-update-activation-environment"),
+            });
+
+        if let Err(err) = spawned {
+            tracing::warn!(%err, "Failed to update the activation environment");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::Environment;
+
+    fn env() -> Environment {
+        let mut env = Environment::new();
+        env.set_cursor_theme("Adwaita", 24);
+        env.set("WAYLAND_DISPLAY", "wrong");
+        env.set_wayland_display("wayland-1");
+        env
+    }
+
+    #[test]
+    fn display_cannot_be_overridden() {
+        let env = env();
+        let vars = env.vars().collect::<Vec<_>>();
+
+        assert_eq!(
+            vars,
+            [
+                ("XCURSOR_SIZE", OsStr::new("24")),
+                ("XCURSOR_THEME", OsStr::new("Adwaita")),
+                ("WAYLAND_DISPLAY", OsStr::new("wayland-1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn toolkit_hints_keep_configured_values() {
+        let mut env = Environment::new();
+        env.set("QT_QPA_PLATFORM", "xcb");
+        env.set_toolkit_hints();
+
+        let vars = env.vars().collect::<Vec<_>>();
+        assert!(vars.contains(&("QT_QPA_PLATFORM", OsStr::new("xcb"))));
+        assert!(vars.contains(&("GDK_BACKEND", OsStr::new("wayland,x11"))));
+    }
+
+    #[test]
+    fn activation_environment_args() {
+        let env = env();
+        let command = env.activation_environment_command();
+
+        assert_eq!(command.get_program(), "dbus-update-activation-environment");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "--systemd",
+                "XCURSOR_SIZE=24",
+                "XCURSOR_THEME=Adwaita",
+                "WAYLAND_DISPLAY=wayland-1"
+            ]
+        );
+    }
+}
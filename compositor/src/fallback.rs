@@ -0,0 +1,196 @@
+//! Fallback layout
+//!
+//! If the wm crashes, toplevels must stay mapped and usable while the wm runtime restarts the wm, or until the user
+//! loads a working wm if the wm keeps crashing. Until then the display server arranges every toplevel in a grid on
+//! the output in place of the tree presented by the wm, and outlines the output to show that the wm crashed.
+
+use std::time::Instant;
+
+use smithay::utils::{Logical, Rectangle};
+
+use crate::{
+    scene::{NodeIndex, Shape, ShapeIndex},
+    touch::TouchPoints,
+    Aerugo,
+};
+
+/// The width of the outline around the output after the wm crashed.
+const OUTLINE: i32 = 4;
+
+const OUTLINE_COLOR: [f32; 4] = [0.8, 0.15, 0.15, 1.0];
+
+/// A crash of the wm, shown to the user by an outline around the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WmCrash {
+    /// The error the wm crashed with.
+    pub error: String,
+
+    /// When the wm crashed.
+    pub time: Instant,
+
+    /// The outline around the output.
+    pub outline: Option<ShapeIndex>,
+}
+
+/// Arrange toplevels in a grid filling the area.
+///
+/// The grid has as many columns as rows, or one more column. The last row is filled with fewer, wider cells if
+/// the toplevels do not fill the grid.
+pub fn fallback_layout(area: Rectangle<i32, Logical>, count: usize) -> Vec<Rectangle<i32, Logical>> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let columns = (count as f64).sqrt().ceil() as usize;
+    let rows = (count + columns - 1) / columns;
+    let mut cells = Vec::with_capacity(count);
+
+    for row in 0..rows {
+        let in_row = columns.min(count - row * columns);
+        let y = area.loc.y + split(area.size.h, rows, row);
+        let h = split(area.size.h, rows, row + 1) - split(area.size.h, rows, row);
+
+        for column in 0..in_row {
+            let x = area.loc.x + split(area.size.w, in_row, column);
+            let w = split(area.size.w, in_row, column + 1) - split(area.size.w, in_row, column);
+            cells.push(Rectangle::from_loc_and_size((x, y), (w, h)));
+        }
+    }
+
+    cells
+}
+
+/// The offset of the index-th of `parts` equal parts of the length, so the parts always add up to the length.
+fn split(length: i32, parts: usize, index: usize) -> i32 {
+    (length as i64 * index as i64 / parts as i64) as i32
+}
+
+impl Aerugo {
+    /// The wm crashed, so toplevels are arranged by the fallback layout until the wm is running again.
+    pub fn wm_crashed(&mut self, error: String) {
+        tracing::error!(%error, "The wm crashed, using the fallback layout");
+
+        self.wm_crash = Some(WmCrash {
+            error,
+            time: Instant::now(),
+            outline: None,
+        });
+        // The wm will not reply to the input it was offered.
+        self.release_held_input();
+        // A new wm does not know about the touch points of the crashed wm.
+        self.touch_points = TouchPoints::default();
+        // Nothing selects a toplevel in the switcher of the crashed wm.
+        self.hide_switcher();
+        self.apply_fallback_layout();
+    }
+
+    /// The wm was restarted or a new wm was loaded after a crash.
+    ///
+    /// The new wm is told about every toplevel and arranges them again.
+    pub fn wm_recovered(&mut self) {
+        let Some(crash) = self.wm_crash.take() else {
+            return;
+        };
+
+        if let Some(outline) = crash.outline {
+            self.scene.destroy_shape(outline);
+        }
+
+        self.scene_changed();
+    }
+
+    /// Arrange every toplevel in a grid on the output and draw them in place of the tree presented by the wm.
+    pub fn apply_fallback_layout(&mut self) {
+        let Some(mode) = self.output.current_mode() else {
+            return;
+        };
+
+        let scale = self.output.current_scale().integer_scale();
+        let size = mode.size.to_logical(scale);
+        let area = Rectangle::from_loc_and_size((0, 0), size);
+
+        let mut ids = self.shell.toplevels.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+
+        let now = Instant::now();
+        let branch = NodeIndex::Branch(self.layer_branches().wm());
+        self.present_wm_node(None);
+
+        for (&id, geometry) in ids.iter().zip(fallback_layout(area, ids.len())) {
+            if self
+                .shell
+                .get_state(id)
+                .is_some_and(|toplevel| toplevel.set_geometry(geometry))
+            {
+                self.configure_watchdog.sent(id, now);
+            }
+
+            let Some(node) = self.toplevel_node(id) else {
+                continue;
+            };
+
+            if let Err(err) = self.scene.add_child(branch, node) {
+                tracing::warn!(?id, %err, "Failed to draw a toplevel in the fallback layout");
+                continue;
+            }
+
+            self.scene.set_node_offset(node, geometry.loc.to_physical(scale));
+        }
+
+        if let Some(crash) = &mut self.wm_crash {
+            let outline = *crash.outline.get_or_insert_with(|| {
+                self.scene.create_shape(
+                    mode.size,
+                    Shape::Border {
+                        width: OUTLINE * scale,
+                        color: OUTLINE_COLOR,
+                    },
+                )
+            });
+
+            // The outline is drawn above the toplevels.
+            if let Err(err) = self.scene.add_child(branch, NodeIndex::Shape(outline)) {
+                tracing::warn!(%err, "Failed to outline the output after the wm crashed");
+            }
+        }
+
+        self.scene_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::Rectangle;
+
+    use super::fallback_layout;
+
+    #[test]
+    fn grid() {
+        let area = Rectangle::from_loc_and_size((0, 0), (1920, 1080));
+
+        assert!(fallback_layout(area, 0).is_empty());
+        assert_eq!(fallback_layout(area, 1), [area]);
+        assert_eq!(
+            fallback_layout(area, 3),
+            [
+                Rectangle::from_loc_and_size((0, 0), (960, 540)),
+                Rectangle::from_loc_and_size((960, 0), (960, 540)),
+                Rectangle::from_loc_and_size((0, 540), (1920, 540)),
+            ]
+        );
+    }
+
+    #[test]
+    fn cells_fill_area() {
+        let area = Rectangle::from_loc_and_size((100, 30), (1001, 701));
+
+        for count in 1..20 {
+            let cells = fallback_layout(area, count);
+            assert_eq!(cells.len(), count);
+
+            let covered = cells.iter().map(|cell| cell.size.w * cell.size.h).sum::<i32>();
+            assert_eq!(covered, area.size.w * area.size.h);
+            assert!(cells.iter().all(|cell| area.contains_rect(*cell)));
+        }
+    }
+}
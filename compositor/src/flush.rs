@@ -0,0 +1,152 @@
+//! Flushing events to clients
+//!
+//! Events sent to clients are queued and written to the client sockets when the display is flushed. Flushing at
+//! the end of every wakeup of the event loop keeps replies to client requests prompt, but a burst of events sent
+//! by the display server itself, such as enter and leave events for every surface during a workspace switch, may
+//! span many wakeups and cause a socket write per client for each of them.
+//!
+//! The display server opens a batch before such a burst. While a batch is open the display is not flushed until
+//! the next frame is drawn, so the burst is coalesced into one write per client alongside the frame callbacks.
+//! A batch never waits longer than [`MAX_BATCH_DELAY`], so events are still delivered while no frames are drawn.
+
+use std::time::{Duration, Instant};
+
+use crate::{shell::send_frames_surface_tree, Aerugo};
+
+/// The longest events are held back by a batch.
+pub const MAX_BATCH_DELAY: Duration = Duration::from_millis(8);
+
+/// Decides when the display is flushed.
+#[derive(Debug, Default)]
+pub struct FlushScheduler {
+    /// When the open batch must be flushed.
+    deadline: Option<Instant>,
+
+    /// Whether a frame was drawn since the batch was opened.
+    frame: bool,
+}
+
+impl FlushScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold events back until the next frame.
+    ///
+    /// Returns the deadline of the batch if a new batch was opened, so the caller can wake up the event loop to
+    /// flush it. Opening a batch while one is open does not extend the deadline.
+    pub fn begin_batch(&mut self, now: Instant) -> Option<Instant> {
+        if self.deadline.is_some() {
+            return None;
+        }
+
+        let deadline = now + MAX_BATCH_DELAY;
+        self.deadline = Some(deadline);
+        self.frame = false;
+        Some(deadline)
+    }
+
+    /// A frame was drawn, so the open batch may be flushed.
+    pub fn frame(&mut self) {
+        self.frame = true;
+    }
+
+    pub fn is_batching(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    /// Whether the display should be flushed at the end of this wakeup of the event loop.
+    pub fn should_flush(&self, now: Instant) -> bool {
+        match self.deadline {
+            Some(deadline) => self.frame || now >= deadline,
+            None => true,
+        }
+    }
+
+    /// The display was flushed, closing the open batch.
+    pub fn flushed(&mut self) {
+        self.deadline = None;
+        self.frame = false;
+    }
+}
+
+impl Aerugo {
+    /// Tell clients the frame was drawn so they may draw their next frame.
+    ///
+    /// Every callback is sent in one pass after the frame is drawn, so they are written with a single flush per
+    /// client. Hidden members of toplevel groups receive no callbacks until they are shown again, and while the
+    /// session is locked only the lock surface is drawn, so only the lock surface receives callbacks.
+    pub fn send_frame_callbacks(&mut self, time: u32) {
+        if let Some((surface, _)) = self.cursor_surface() {
+            send_frames_surface_tree(surface, time);
+        }
+
+        if let Some(session_lock) = &self.session_lock {
+            if let Some(surface) = session_lock.surface() {
+                send_frames_surface_tree(surface, time);
+            }
+
+            self.flush.frame();
+            return;
+        }
+
+        for (&id, toplevel) in &self.shell.toplevels {
+            if self.toplevel_groups.is_hidden(id) {
+                continue;
+            }
+
+            if let Some(surface) = toplevel.wl_surface() {
+                send_frames_surface_tree(&surface, time);
+            }
+        }
+
+        for surface in self.layer_shell.mapped() {
+            send_frames_surface_tree(surface, time);
+        }
+
+        self.flush.frame();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::{FlushScheduler, MAX_BATCH_DELAY};
+
+    #[test]
+    fn flush_every_wakeup() {
+        let scheduler = FlushScheduler::new();
+        assert!(!scheduler.is_batching());
+        assert!(scheduler.should_flush(Instant::now()));
+    }
+
+    #[test]
+    fn batch_until_frame() {
+        let start = Instant::now();
+        let mut scheduler = FlushScheduler::new();
+
+        assert_eq!(scheduler.begin_batch(start), Some(start + MAX_BATCH_DELAY));
+        assert_eq!(scheduler.begin_batch(start + MAX_BATCH_DELAY / 2), None);
+        assert!(!scheduler.should_flush(start));
+
+        scheduler.frame();
+        assert!(scheduler.should_flush(start));
+
+        scheduler.flushed();
+        assert!(!scheduler.is_batching());
+        assert!(scheduler.should_flush(start));
+    }
+
+    #[test]
+    fn batch_deadline() {
+        let start = Instant::now();
+        let mut scheduler = FlushScheduler::new();
+
+        // A frame drawn before the batch is opened does not flush it.
+        scheduler.frame();
+        scheduler.begin_batch(start);
+        assert!(!scheduler.should_flush(start + MAX_BATCH_DELAY / 2));
+        assert!(scheduler.should_flush(start + MAX_BATCH_DELAY));
+    }
+}
@@ -10,6 +10,9 @@ pub enum Error {
 
     #[error("failed to insert because the forest would become cyclic")]
     Cycle,
+
+    #[error("{0:?} does not have a parent")]
+    NoParent(Index),
 }
 
 #[derive(Debug)]
@@ -119,51 +122,71 @@ impl<T> Forest<T> {
         self.is_present(index)?;
 
         let node = self.get_mut(index).unwrap();
-        let parent = Node::parent(node);
-        node.parent.take();
-
-        let prev_sibling = Node::prev_sibling(node);
-        let next_sibling = Node::next_sibling(node);
-
-        match (prev_sibling, next_sibling) {
-            // If this node is the only child of it's parent we need to fully detach the parent.
-            (None, None) => {
-                if let Some(parent) = parent {
-                    let node = self.get_mut(parent).unwrap();
-                    node.first_last_child.take();
-                }
-            }
+        let parent = node.parent.take();
+        let prev_sibling = node.prev.take();
+        let next_sibling = node.next.take();
 
-            // This node is the first child of the parent
-            (None, Some(next)) => {
-                if let Some(parent) = parent {
-                    let node = self.get_mut(parent).unwrap();
-                    let last_child = Node::last_child(node).unwrap();
-                    node.first_last_child = Some((next, last_child))
-                }
-            }
+        // Relink the siblings around the node.
+        if let Some(prev) = prev_sibling {
+            self.get_mut(prev).unwrap().next = next_sibling;
+        }
 
-            // This node is the last child of the parent
-            (Some(prev), None) => {
-                if let Some(parent) = parent {
-                    let node = self.get_mut(parent).unwrap();
-                    let first_child = Node::first_child(node).unwrap();
-                    node.first_last_child = Some((first_child, prev))
-                }
-            }
+        if let Some(next) = next_sibling {
+            self.get_mut(next).unwrap().prev = prev_sibling;
+        }
 
-            (Some(prev), Some(next)) => {
-                // Detach the previous and next siblings, relinking those as needed.
-                if let Some(prev) = self.get_mut(prev) {
-                    prev.next = next_sibling;
-                }
+        if let Some(parent) = parent {
+            let node = self.get_mut(parent).unwrap();
 
-                if let Some(next) = self.get_mut(next) {
-                    next.prev = prev_sibling;
-                }
-            }
+            node.first_last_child = match (prev_sibling, next_sibling) {
+                // If this node is the only child of it's parent we need to fully detach the parent.
+                (None, None) => None,
+
+                // This node is the first child of the parent
+                (None, Some(next)) => Some((next, Node::last_child(node).unwrap())),
+
+                // This node is the last child of the parent
+                (Some(prev), None) => Some((Node::first_child(node).unwrap(), prev)),
+
+                // The first and last children of the parent are unchanged.
+                (Some(_), Some(_)) => node.first_last_child,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Places the node directly before the `sibling`, making the node a child of the sibling's parent.
+    ///
+    /// The node is detached from it's current parent and siblings first.
+    pub fn insert_before(&mut self, sibling: Index, index: Index) -> Result<(), Error> {
+        let parent = self.check_sibling(sibling, index)?;
+
+        if Node::prev_sibling(self.get(sibling).unwrap()) == Some(index) {
+            return Ok(());
+        }
+
+        self.detach(index)?;
+
+        let prev = self.get(sibling).unwrap().prev;
+        self.link(parent, prev, index, Some(sibling));
+        Ok(())
+    }
+
+    /// Places the node directly after the `sibling`, making the node a child of the sibling's parent.
+    ///
+    /// The node is detached from it's current parent and siblings first.
+    pub fn insert_after(&mut self, sibling: Index, index: Index) -> Result<(), Error> {
+        let parent = self.check_sibling(sibling, index)?;
+
+        if Node::next_sibling(self.get(sibling).unwrap()) == Some(index) {
+            return Ok(());
         }
 
+        self.detach(index)?;
+
+        let next = self.get(sibling).unwrap().next;
+        self.link(parent, Some(sibling), index, next);
         Ok(())
     }
 
@@ -218,8 +241,55 @@ impl<T> Forest<T> {
         }
     }
 
-    // TODO: Relation related methods
-    // - Raise/lower node as child
+    /// Returns whether `ancestor` is a parent of the node or a parent of one of the node's parents.
+    pub fn is_ancestor(&self, ancestor: Index, index: Index) -> bool {
+        let mut next = self.get(index).and_then(Node::parent);
+
+        while let Some(parent) = next {
+            if parent == ancestor {
+                return true;
+            }
+
+            next = self.get(parent).and_then(Node::parent);
+        }
+
+        false
+    }
+
+    /// Checks the node can become a sibling of `sibling`, returning the parent of the sibling.
+    fn check_sibling(&self, sibling: Index, index: Index) -> Result<Index, Error> {
+        self.is_present(sibling)?;
+        self.is_present(index)?;
+
+        if sibling == index || self.is_ancestor(index, sibling) {
+            return Err(Error::Cycle);
+        }
+
+        Node::parent(self.get(sibling).unwrap()).ok_or(Error::NoParent(sibling))
+    }
+
+    /// Links a detached node between `prev` and `next` as a child of the `parent`.
+    fn link(&mut self, parent: Index, prev: Option<Index>, index: Index, next: Option<Index>) {
+        let node = self.get_mut(index).unwrap();
+        node.parent = Some(parent);
+        node.prev = prev;
+        node.next = next;
+
+        if let Some(prev) = prev {
+            self.get_mut(prev).unwrap().next = Some(index);
+        }
+
+        if let Some(next) = next {
+            self.get_mut(next).unwrap().prev = Some(index);
+        }
+
+        let parent = self.get_mut(parent).unwrap();
+        let (first, last) = parent.first_last_child.unwrap_or((index, index));
+        parent.first_last_child = Some((
+            if prev.is_none() { index } else { first },
+            if next.is_none() { index } else { last },
+        ));
+    }
 
     fn is_present(&self, index: Index) -> Result<(), Error> {
         if !self.contains_index(index) {
@@ -583,4 +653,57 @@ mod tests {
         assert_eq!(children.next(), Some(c));
         assert_eq!(children.next(), None);
     }
+
+    #[test]
+    fn restack_siblings() {
+        let mut forest = Forest::new();
+        let a = forest.insert(0);
+        let b = forest.insert(1);
+        let c = forest.insert(2);
+        let d = forest.insert(3);
+
+        forest.add_child(a, b).unwrap();
+        forest.add_child(a, c).unwrap();
+        forest.add_child(a, d).unwrap();
+
+        // b c d -> c d b
+        forest.insert_after(d, b).unwrap();
+        assert_eq!(forest.children(a).collect::<Vec<_>>(), [c, d, b]);
+        assert_eq!(Node::last_child(forest.get(a).unwrap()), Some(b));
+        assert_eq!(Node::next_sibling(forest.get(b).unwrap()), None);
+
+        // c d b -> b c d
+        forest.insert_before(c, b).unwrap();
+        assert_eq!(forest.children(a).collect::<Vec<_>>(), [b, c, d]);
+        assert_eq!(Node::first_child(forest.get(a).unwrap()), Some(b));
+        assert_eq!(Node::prev_sibling(forest.get(b).unwrap()), None);
+
+        // b c d -> b d c
+        forest.insert_after(d, c).unwrap();
+        assert_eq!(forest.children(a).collect::<Vec<_>>(), [b, d, c]);
+        assert_eq!(forest.previous_siblings(c).unwrap().collect::<Vec<_>>(), [c, d, b]);
+
+        forest.detach(d).unwrap();
+        assert_eq!(forest.children(a).collect::<Vec<_>>(), [b, c]);
+        assert_eq!(Node::prev_sibling(forest.get(d).unwrap()), None);
+        assert_eq!(Node::next_sibling(forest.get(d).unwrap()), None);
+    }
+
+    #[test]
+    fn restack_errors() {
+        let mut forest = Forest::new();
+        let a = forest.insert(());
+        let b = forest.insert(());
+        let c = forest.insert(());
+
+        // a -> b -> c
+        forest.add_child(a, b).unwrap();
+        forest.add_child(b, c).unwrap();
+
+        assert!(forest.is_ancestor(a, c));
+        assert!(!forest.is_ancestor(c, a));
+        assert!(matches!(forest.insert_after(c, b), Err(Error::Cycle)));
+        assert!(matches!(forest.insert_before(b, b), Err(Error::Cycle)));
+        assert!(matches!(forest.insert_after(a, c), Err(Error::NoParent(_))));
+    }
 }
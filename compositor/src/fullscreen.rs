@@ -0,0 +1,211 @@
+//! Fullscreen on an output
+//!
+//! The wm may make a toplevel fullscreen on an output with a hint for the mode of the output, such as the
+//! resolution a game renders at. The output is switched to the mode with the size of the hint and the closest
+//! refresh rate, so the display scales the game instead of the compositor. The previous mode is restored once the
+//! toplevel leaves fullscreen or is destroyed.
+//!
+//! Before every frame, the display server checks whether the fullscreen toplevel is eligible for direct scanout: its
+//! buffer is a dmabuf which covers the whole output without subsurfaces, and neither the lock screen nor the window
+//! switcher is drawn over it. The wm is told when a toplevel becomes eligible or stops being eligible, so it can
+//! avoid drawing decorations or shadows over the toplevel which would force the display server to composite.
+//! Backends which drive the display directly may then place the buffer on the primary plane, while the nested X11
+//! backend always composites.
+
+use smithay::{
+    backend::renderer::utils::RendererSurfaceStateUserData,
+    output::{Mode, Output},
+    utils::Point,
+    wayland::{compositor, dmabuf::get_dmabuf},
+};
+use wayland_server::protocol::wl_buffer::WlBuffer;
+use wm_runtime::WmEvent;
+
+use crate::{shell::ToplevelId, Aerugo};
+
+/// The mode of the output closest to the hint.
+///
+/// Only modes with the size of the hint are considered. If several modes have the closest refresh rate, the higher
+/// refresh rate is preferred. Returns [`None`] if no mode has the size of the hint.
+pub fn matching_mode(modes: &[Mode], hint: Mode) -> Option<Mode> {
+    modes
+        .iter()
+        .filter(|mode| mode.size == hint.size)
+        .min_by_key(|mode| ((mode.refresh - hint.refresh).abs(), -mode.refresh))
+        .copied()
+}
+
+/// The mode an output had before a toplevel was made fullscreen on it.
+#[derive(Debug)]
+pub struct FullscreenOutput {
+    output: Output,
+    /// The mode to restore, or [`None`] if the mode was not changed.
+    previous_mode: Option<Mode>,
+    /// Whether the toplevel is eligible for direct scanout.
+    scanout: bool,
+}
+
+impl Aerugo {
+    /// Make the toplevel fullscreen on the output, switching the output to the mode closest to the hint.
+    ///
+    /// The fullscreen state itself is set by the configure of the toplevel.
+    pub fn fullscreen_on_output(&mut self, id: ToplevelId, output: &Output, hint: Option<Mode>) {
+        // Restore the mode of the output the toplevel was fullscreen on before.
+        self.leave_fullscreen_output(id);
+
+        let current = output.current_mode();
+        let mode = hint.and_then(|hint| matching_mode(&output.modes(), hint));
+        let mut previous_mode = None;
+
+        if let Some(mode) = mode.filter(|&mode| current != Some(mode)) {
+            if self.backend.set_output_mode(output, mode) {
+                output.change_current_state(Some(mode), None, None, None);
+                self.output_changed(output);
+                previous_mode = current;
+                tracing::info!(output = %output.name(), ?mode, "Output mode changed for fullscreen toplevel");
+            } else {
+                tracing::warn!(output = %output.name(), ?mode, "Failed to set mode for fullscreen toplevel");
+            }
+        }
+
+        self.fullscreen_outputs.insert(
+            id,
+            FullscreenOutput {
+                output: output.clone(),
+                previous_mode,
+                scanout: false,
+            },
+        );
+        self.scene_changed();
+    }
+
+    /// The toplevel left fullscreen or was destroyed.
+    ///
+    /// The mode the output had before the toplevel was made fullscreen is restored.
+    pub fn leave_fullscreen_output(&mut self, id: ToplevelId) {
+        let Some(fullscreen) = self.fullscreen_outputs.remove(&id) else {
+            return;
+        };
+
+        if fullscreen.scanout {
+            self.send_scanout_state(id, false);
+        }
+
+        let output = &fullscreen.output;
+
+        if let Some(mode) = fullscreen.previous_mode {
+            if self.backend.set_output_mode(output, mode) {
+                output.change_current_state(Some(mode), None, None, None);
+                self.output_changed(output);
+                tracing::info!(output = %output.name(), ?mode, "Output mode restored");
+            } else {
+                tracing::warn!(output = %output.name(), ?mode, "Failed to restore output mode");
+            }
+        }
+
+        self.scene_changed();
+    }
+
+    /// Check whether each fullscreen toplevel is eligible for direct scanout, and tell the wm if that changed.
+    pub(crate) fn update_scanout_eligibility(&mut self) {
+        let changed = self
+            .fullscreen_outputs
+            .iter()
+            .map(|(&id, fullscreen)| (id, fullscreen.scanout, self.is_scanout_eligible(id, &fullscreen.output)))
+            .filter(|&(_, previous, eligible)| previous != eligible)
+            .collect::<Vec<_>>();
+
+        for (id, _, eligible) in changed {
+            if let Some(fullscreen) = self.fullscreen_outputs.get_mut(&id) {
+                fullscreen.scanout = eligible;
+            }
+
+            tracing::debug!(id, eligible, "Toplevel scanout eligibility changed");
+            self.send_scanout_state(id, eligible);
+        }
+    }
+
+    /// Whether the buffer of the fullscreen toplevel could be scanned out on the output instead of compositing.
+    fn is_scanout_eligible(&self, id: ToplevelId, output: &Output) -> bool {
+        // The lock screen and the window switcher are drawn over every toplevel.
+        if self.is_session_locked() || self.switcher.is_some() {
+            return false;
+        }
+
+        let Some(surface) = self.shell.get_state(id).and_then(|toplevel| toplevel.wl_surface()) else {
+            return false;
+        };
+
+        // A subsurface would need a plane of its own.
+        if !compositor::get_children(&surface).is_empty() {
+            return false;
+        }
+
+        let geometry = self
+            .scene
+            .get_surface_tree_index(surface.clone())
+            .and_then(|index| self.scene.surface_tree_geometry(index));
+        let size = output
+            .current_mode()
+            .map(|mode| output.current_transform().transform_size(mode.size));
+
+        let covers_output = match (geometry, size) {
+            (Some(geometry), Some(size)) => geometry.loc == Point::from((0, 0)) && geometry.size == size,
+            _ => false,
+        };
+
+        if !covers_output {
+            return false;
+        }
+
+        compositor::with_states(&surface, |states| {
+            let data = states.data_map.get::<RendererSurfaceStateUserData>()?.borrow();
+            let buffer: &WlBuffer = data.buffer()?;
+            Some(get_dmabuf(buffer).is_ok())
+        })
+        .unwrap_or(false)
+    }
+
+    /// Tell the wm whether the toplevel is eligible for direct scanout, so it can avoid drawing over the toplevel.
+    fn send_scanout_state(&self, id: ToplevelId, scanout: bool) {
+        if let Some(toplevel) = self.wm.toplevel_id(id) {
+            self.send_wm(WmEvent::ScanoutStateChanged { toplevel, scanout });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matching_mode;
+    use crate::test_util::mode;
+
+    #[test]
+    fn closest_refresh() {
+        let modes = [
+            mode(2560, 1440, 165000),
+            mode(2560, 1440, 60000),
+            mode(1920, 1080, 144000),
+            mode(1920, 1080, 60000),
+        ];
+
+        assert_eq!(
+            matching_mode(&modes, mode(1920, 1080, 120000)),
+            Some(mode(1920, 1080, 144000))
+        );
+        assert_eq!(
+            matching_mode(&modes, mode(2560, 1440, 59940)),
+            Some(mode(2560, 1440, 60000))
+        );
+        assert_eq!(matching_mode(&modes, mode(1280, 720, 60000)), None);
+    }
+
+    #[test]
+    fn prefers_higher_refresh_on_tie() {
+        let modes = [mode(1920, 1080, 50000), mode(1920, 1080, 70000)];
+
+        assert_eq!(
+            matching_mode(&modes, mode(1920, 1080, 60000)),
+            Some(mode(1920, 1080, 70000))
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Touchpad gestures
+//!
+//! Swipe, pinch and hold gestures are offered to the wm first, so the wm can implement gestures such as swiping
+//! with three fingers to switch workspaces. The wm decides whether it consumes a gesture when the gesture begins,
+//! and gestures the wm does not consume are sent to the client under the pointer with pointer-gestures-v1.
+//!
+//! The updates of a gesture keep arriving while the wm decides, so they are held back until the wm replies and are
+//! then sent to the client or dropped together with the start of the gesture.
+
+use smithay::{
+    input::pointer::{
+        GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent, GesturePinchEndEvent,
+        GesturePinchUpdateEvent, GestureSwipeBeginEvent, GestureSwipeEndEvent, GestureSwipeUpdateEvent,
+    },
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+
+use wm_runtime::{types, WmEvent};
+
+use crate::{wm, Aerugo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureKind {
+    Swipe,
+    Pinch,
+    Hold,
+}
+
+/// An event of a touchpad gesture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GestureEvent {
+    Begin {
+        kind: GestureKind,
+        time: u32,
+        fingers: u32,
+    },
+
+    /// The fingers moved. Swipes have a scale of 1.0 and no rotation.
+    Update {
+        time: u32,
+        delta: Point<f64, Logical>,
+        scale: f64,
+        /// Clockwise rotation in degrees since the last update.
+        rotation: f64,
+    },
+
+    End {
+        time: u32,
+        cancelled: bool,
+    },
+}
+
+/// Where the events of the current gesture go.
+#[derive(Debug)]
+enum Route {
+    /// The wm has not decided yet, so the events are held back.
+    Pending {
+        serial: u32,
+        held: Vec<GestureEvent>,
+    },
+
+    Client,
+
+    /// The wm consumed the gesture.
+    Wm,
+}
+
+/// Decides which touchpad gesture events are sent to clients.
+#[derive(Debug, Default)]
+pub struct GestureRouter {
+    /// The gesture in progress.
+    active: Option<(GestureKind, Route)>,
+}
+
+impl GestureRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A gesture event from an input device.
+    ///
+    /// `serial` is the serial the start of a gesture is sent to the wm with. Returns the events to send to the
+    /// client right away.
+    pub fn input(&mut self, event: GestureEvent, serial: u32) -> Vec<(GestureKind, GestureEvent)> {
+        if let GestureEvent::Begin { kind, .. } = event {
+            let held = vec![event];
+            self.active = Some((kind, Route::Pending { serial, held }));
+            return Vec::new();
+        }
+
+        let Some((kind, route)) = &mut self.active else {
+            return Vec::new();
+        };
+
+        let kind = *kind;
+        let events = match route {
+            Route::Pending { held, .. } => {
+                held.push(event);
+                return Vec::new();
+            }
+            Route::Client => vec![(kind, event)],
+            Route::Wm => Vec::new(),
+        };
+
+        if matches!(event, GestureEvent::End { .. }) {
+            self.active = None;
+        }
+
+        events
+    }
+
+    /// The wm decided whether the gesture which began with the serial is sent to the client.
+    ///
+    /// Returns the events which were held back and are now sent to the client.
+    pub fn filter(&mut self, serial: u32, forward: bool) -> Vec<(GestureKind, GestureEvent)> {
+        let Some((kind, route)) = &mut self.active else {
+            return Vec::new();
+        };
+
+        let kind = *kind;
+        let held = match route {
+            Route::Pending { serial: pending, held } if *pending == serial => std::mem::take(held),
+            _ => return Vec::new(),
+        };
+
+        if held.iter().any(|event| matches!(event, GestureEvent::End { .. })) {
+            self.active = None;
+        } else {
+            *route = if forward { Route::Client } else { Route::Wm };
+        }
+
+        if !forward {
+            return Vec::new();
+        }
+
+        held.into_iter().map(|event| (kind, event)).collect()
+    }
+}
+
+impl Aerugo {
+    /// A touchpad gesture event from an input device.
+    pub fn gesture_input(&mut self, event: GestureEvent) {
+        let serial = u32::from(SERIAL_COUNTER.next_serial());
+        let events = self.gestures.input(event, serial);
+        self.send_gestures(events);
+
+        if !self.wm_filters_input() {
+            // Nothing decides whether the gesture is consumed, so it is sent to the client right away.
+            if matches!(event, GestureEvent::Begin { .. }) {
+                self.gesture_filter(serial, true);
+            }
+            return;
+        }
+
+        let seat = self.wm.seat();
+        self.send_wm(match event {
+            GestureEvent::Begin { kind, time, fingers } => WmEvent::GestureBegin {
+                seat,
+                serial,
+                time,
+                kind: match kind {
+                    GestureKind::Swipe => types::GestureKind::Swipe,
+                    GestureKind::Pinch => types::GestureKind::Pinch,
+                    GestureKind::Hold => types::GestureKind::Hold,
+                },
+                fingers,
+            },
+            GestureEvent::Update {
+                time,
+                delta,
+                scale,
+                rotation,
+            } => WmEvent::GestureUpdate {
+                seat,
+                time,
+                delta: wm::wm_point(delta),
+                scale,
+                rotation,
+            },
+            GestureEvent::End { time, cancelled } => WmEvent::GestureEnd { seat, time, cancelled },
+        });
+    }
+
+    /// The wm replied to the start of a gesture with a pointer filter.
+    pub(crate) fn gesture_filter(&mut self, serial: u32, forward: bool) {
+        let events = self.gestures.filter(serial, forward);
+        self.send_gestures(events);
+    }
+
+    /// Send gesture events to the client under the pointer.
+    fn send_gestures(&mut self, events: Vec<(GestureKind, GestureEvent)>) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return;
+        };
+
+        for (kind, event) in events {
+            match (kind, event) {
+                (GestureKind::Swipe, GestureEvent::Begin { time, fingers, .. }) => {
+                    let serial = SERIAL_COUNTER.next_serial();
+                    pointer.gesture_swipe_begin(self, &GestureSwipeBeginEvent { serial, time, fingers });
+                }
+                (GestureKind::Swipe, GestureEvent::Update { time, delta, .. }) => {
+                    pointer.gesture_swipe_update(self, &GestureSwipeUpdateEvent { time, delta });
+                }
+                (GestureKind::Swipe, GestureEvent::End { time, cancelled }) => {
+                    let serial = SERIAL_COUNTER.next_serial();
+                    pointer.gesture_swipe_end(
+                        self,
+                        &GestureSwipeEndEvent {
+                            serial,
+                            time,
+                            cancelled,
+                        },
+                    );
+                }
+                (GestureKind::Pinch, GestureEvent::Begin { time, fingers, .. }) => {
+                    let serial = SERIAL_COUNTER.next_serial();
+                    pointer.gesture_pinch_begin(self, &GesturePinchBeginEvent { serial, time, fingers });
+                }
+                (
+                    GestureKind::Pinch,
+                    GestureEvent::Update {
+                        time,
+                        delta,
+                        scale,
+                        rotation,
+                    },
+                ) => {
+                    let event = GesturePinchUpdateEvent {
+                        time,
+                        delta,
+                        scale,
+                        rotation,
+                    };
+                    pointer.gesture_pinch_update(self, &event);
+                }
+                (GestureKind::Pinch, GestureEvent::End { time, cancelled }) => {
+                    let serial = SERIAL_COUNTER.next_serial();
+                    pointer.gesture_pinch_end(
+                        self,
+                        &GesturePinchEndEvent {
+                            serial,
+                            time,
+                            cancelled,
+                        },
+                    );
+                }
+                (GestureKind::Hold, GestureEvent::Begin { time, fingers, .. }) => {
+                    let serial = SERIAL_COUNTER.next_serial();
+                    pointer.gesture_hold_begin(self, &GestureHoldBeginEvent { serial, time, fingers });
+                }
+                (GestureKind::Hold, GestureEvent::End { time, cancelled }) => {
+                    let serial = SERIAL_COUNTER.next_serial();
+                    pointer.gesture_hold_end(
+                        self,
+                        &GestureHoldEndEvent {
+                            serial,
+                            time,
+                            cancelled,
+                        },
+                    );
+                }
+                // Holds do not move.
+                (GestureKind::Hold, GestureEvent::Update { .. }) => {}
+            }
+        }
+
+        pointer.frame(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GestureEvent, GestureKind, GestureRouter};
+
+    const BEGIN: GestureEvent = GestureEvent::Begin {
+        kind: GestureKind::Swipe,
+        time: 0,
+        fingers: 3,
+    };
+
+    fn update(time: u32) -> GestureEvent {
+        GestureEvent::Update {
+            time,
+            delta: (10.0, 0.0).into(),
+            scale: 1.0,
+            rotation: 0.0,
+        }
+    }
+
+    const END: GestureEvent = GestureEvent::End {
+        time: 30,
+        cancelled: false,
+    };
+
+    #[test]
+    fn held_until_forwarded() {
+        let mut router = GestureRouter::new();
+
+        assert!(router.input(BEGIN, 1).is_empty());
+        assert!(router.input(update(10), 1).is_empty());
+
+        // A reply for another gesture does not release the events.
+        assert!(router.filter(2, true).is_empty());
+
+        let events = router.filter(1, true);
+        assert_eq!(events, [(GestureKind::Swipe, BEGIN), (GestureKind::Swipe, update(10))]);
+
+        assert_eq!(router.input(update(20), 1), [(GestureKind::Swipe, update(20))]);
+        assert_eq!(router.input(END, 1), [(GestureKind::Swipe, END)]);
+        assert!(router.input(update(40), 1).is_empty());
+    }
+
+    #[test]
+    fn consumed_by_wm() {
+        let mut router = GestureRouter::new();
+
+        router.input(BEGIN, 1);
+        router.input(update(10), 1);
+        assert!(router.filter(1, false).is_empty());
+        assert!(router.input(update(20), 1).is_empty());
+        assert!(router.input(END, 1).is_empty());
+
+        // The gesture ended before the wm replied.
+        router.input(BEGIN, 2);
+        router.input(END, 2);
+        assert_eq!(router.filter(2, true).len(), 2);
+        assert!(router.filter(2, true).is_empty());
+    }
+}
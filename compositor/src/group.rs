@@ -0,0 +1,480 @@
+//! Toplevel groups
+//!
+//! The wm groups toplevels to implement tabbed and stacked containers. Only one member of a group is visible at a
+//! time and the other members are hidden by the display server: they are not drawn and receive no frame callbacks,
+//! so clients in background tabs are throttled as if they were minimized.
+//!
+//! A toplevel is in at most one group. The first toplevel added to a group is visible, and when the visible member
+//! leaves the group the member after it becomes visible.
+//!
+//! A group with a [`TabLayout`] draws a tab bar for its members in place of the title bar of the visible member,
+//! see [`TabBar`](crate::decorations::TabBar).
+
+use rustc_hash::FxHashMap;
+use slotmap::{new_key_type, SlotMap};
+
+use crate::{
+    decorations::TabBar,
+    scene::{NodeIndex, RenderProperties},
+    shell::{send_frames_surface_tree, ToplevelId},
+    Aerugo,
+};
+
+new_key_type! {
+    pub struct ToplevelGroupId;
+}
+
+/// An error from managing toplevel groups.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0:?} does not exist")]
+    NotPresent(ToplevelGroupId),
+
+    #[error("toplevel {toplevel} is not in {group:?}")]
+    NotMember {
+        group: ToplevelGroupId,
+        toplevel: ToplevelId,
+    },
+}
+
+/// How the tabs of a group are arranged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TabLayout {
+    /// The tabs share a single title bar side by side.
+    Tabbed,
+
+    /// Each tab is a title bar, stacked above the visible member.
+    Stacked,
+}
+
+#[derive(Debug, Default)]
+pub struct ToplevelGroup {
+    members: Vec<ToplevelId>,
+    visible: Option<ToplevelId>,
+    tab_layout: Option<TabLayout>,
+}
+
+impl ToplevelGroup {
+    /// The members of the group, in the order they were added.
+    pub fn members(&self) -> &[ToplevelId] {
+        &self.members
+    }
+
+    pub fn visible(&self) -> Option<ToplevelId> {
+        self.visible
+    }
+
+    pub fn tab_layout(&self) -> Option<TabLayout> {
+        self.tab_layout
+    }
+}
+
+/// Toplevels which were shown or hidden by a change to the groups.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct VisibilityChanges {
+    pub shown: Vec<ToplevelId>,
+    pub hidden: Vec<ToplevelId>,
+}
+
+impl VisibilityChanges {
+    fn show(&mut self, toplevel: ToplevelId) {
+        match self.hidden.iter().position(|&hidden| hidden == toplevel) {
+            Some(index) => {
+                self.hidden.remove(index);
+            }
+            None => self.shown.push(toplevel),
+        }
+    }
+
+    fn hide(&mut self, toplevel: ToplevelId) {
+        match self.shown.iter().position(|&shown| shown == toplevel) {
+            Some(index) => {
+                self.shown.remove(index);
+            }
+            None => self.hidden.push(toplevel),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ToplevelGroups {
+    groups: SlotMap<ToplevelGroupId, ToplevelGroup>,
+    toplevels: FxHashMap<ToplevelId, ToplevelGroupId>,
+}
+
+impl ToplevelGroups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: ToplevelGroupId) -> Option<&ToplevelGroup> {
+        self.groups.get(id)
+    }
+
+    pub fn create(&mut self) -> ToplevelGroupId {
+        self.groups.insert(ToplevelGroup::default())
+    }
+
+    /// Destroy a group, showing the members which were hidden.
+    pub fn destroy(&mut self, id: ToplevelGroupId) -> Result<VisibilityChanges, Error> {
+        let group = self.groups.remove(id).ok_or(Error::NotPresent(id))?;
+        let mut changes = VisibilityChanges::default();
+
+        for toplevel in group.members {
+            self.toplevels.remove(&toplevel);
+
+            if group.visible != Some(toplevel) {
+                changes.show(toplevel);
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Add a toplevel to a group, removing it from its previous group.
+    ///
+    /// The toplevel is hidden unless the group has no visible member.
+    pub fn add(&mut self, id: ToplevelGroupId, toplevel: ToplevelId) -> Result<VisibilityChanges, Error> {
+        if !self.groups.contains_key(id) {
+            return Err(Error::NotPresent(id));
+        }
+
+        let mut changes = VisibilityChanges::default();
+
+        if self.group_of(toplevel) == Some(id) {
+            return Ok(changes);
+        }
+
+        self.remove_member(toplevel, &mut changes);
+
+        let group = &mut self.groups[id];
+        group.members.push(toplevel);
+        self.toplevels.insert(toplevel, id);
+
+        if group.visible.is_none() {
+            group.visible = Some(toplevel);
+        } else {
+            changes.hide(toplevel);
+        }
+
+        Ok(changes)
+    }
+
+    /// Remove a toplevel from a group, showing it if it was hidden.
+    pub fn remove(&mut self, id: ToplevelGroupId, toplevel: ToplevelId) -> Result<VisibilityChanges, Error> {
+        if !self.groups.contains_key(id) {
+            return Err(Error::NotPresent(id));
+        }
+
+        if self.group_of(toplevel) != Some(id) {
+            return Err(Error::NotMember { group: id, toplevel });
+        }
+
+        let mut changes = VisibilityChanges::default();
+        self.remove_member(toplevel, &mut changes);
+        Ok(changes)
+    }
+
+    /// Make the member the visible member of the group, hiding the previously visible member.
+    pub fn set_visible(&mut self, id: ToplevelGroupId, toplevel: ToplevelId) -> Result<VisibilityChanges, Error> {
+        let group = self.groups.get_mut(id).ok_or(Error::NotPresent(id))?;
+
+        if !group.members.contains(&toplevel) {
+            return Err(Error::NotMember { group: id, toplevel });
+        }
+
+        let mut changes = VisibilityChanges::default();
+
+        if let Some(previous) = group.visible.replace(toplevel) {
+            if previous != toplevel {
+                changes.hide(previous);
+                changes.show(toplevel);
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Draw a tab bar for the members of the group, or [`None`] to draw no tab bar.
+    pub fn set_tab_layout(&mut self, id: ToplevelGroupId, layout: Option<TabLayout>) -> Result<(), Error> {
+        let group = self.groups.get_mut(id).ok_or(Error::NotPresent(id))?;
+        group.tab_layout = layout;
+        Ok(())
+    }
+
+    /// The tab bar drawn above the toplevel, if it is the visible member of a group with a tab layout.
+    pub fn tab_bar(&self, toplevel: ToplevelId) -> Option<TabBar> {
+        let group = self.groups.get(self.group_of(toplevel)?)?;
+
+        if group.visible != Some(toplevel) {
+            return None;
+        }
+
+        Some(TabBar {
+            layout: group.tab_layout?,
+            tabs: group.members.len(),
+            active: group.members.iter().position(|&member| member == toplevel)?,
+        })
+    }
+
+    /// Remove a toplevel from its group, such as when the toplevel is closed.
+    pub fn remove_toplevel(&mut self, toplevel: ToplevelId) -> VisibilityChanges {
+        let mut changes = VisibilityChanges::default();
+        self.remove_member(toplevel, &mut changes);
+        changes
+    }
+
+    pub fn group_of(&self, toplevel: ToplevelId) -> Option<ToplevelGroupId> {
+        self.toplevels.get(&toplevel).copied()
+    }
+
+    /// Whether the toplevel is a hidden member of a group.
+    pub fn is_hidden(&self, toplevel: ToplevelId) -> bool {
+        self.group_of(toplevel)
+            .and_then(|id| self.groups.get(id))
+            .is_some_and(|group| group.visible != Some(toplevel))
+    }
+
+    fn remove_member(&mut self, toplevel: ToplevelId, changes: &mut VisibilityChanges) {
+        let Some(id) = self.toplevels.remove(&toplevel) else {
+            return;
+        };
+
+        let group = &mut self.groups[id];
+        let index = group.members.iter().position(|&member| member == toplevel).unwrap();
+        group.members.remove(index);
+
+        if group.visible != Some(toplevel) {
+            changes.show(toplevel);
+            return;
+        }
+
+        // The member after the removed member takes its place, or the last member if it was at the end.
+        group.visible = group.members.get(index).or(group.members.last()).copied();
+
+        if let Some(visible) = group.visible {
+            changes.show(visible);
+        }
+    }
+}
+
+impl Aerugo {
+    pub fn create_toplevel_group(&mut self) -> ToplevelGroupId {
+        self.toplevel_groups.create()
+    }
+
+    /// Destroy a toplevel group created by the wm, showing its hidden members.
+    pub fn destroy_toplevel_group(&mut self, id: ToplevelGroupId) -> Result<(), Error> {
+        let members = self.toplevel_groups.get(id).map(|group| group.members().to_vec());
+        let changes = self.toplevel_groups.destroy(id)?;
+        self.apply_visibility(changes);
+
+        // The members lose their tab bar.
+        for member in members.unwrap_or_default() {
+            self.decorations_changed(member);
+        }
+
+        Ok(())
+    }
+
+    pub fn add_to_toplevel_group(&mut self, id: ToplevelGroupId, toplevel: ToplevelId) -> Result<(), Error> {
+        let changes = self.toplevel_groups.add(id, toplevel)?;
+        self.apply_visibility(changes);
+        self.decorations_changed(toplevel);
+        Ok(())
+    }
+
+    pub fn remove_from_toplevel_group(&mut self, id: ToplevelGroupId, toplevel: ToplevelId) -> Result<(), Error> {
+        let changes = self.toplevel_groups.remove(id, toplevel)?;
+        self.apply_visibility(changes);
+        self.decorations_changed(toplevel);
+        self.group_decorations_changed(id);
+        Ok(())
+    }
+
+    /// Show a member of the group, hiding the member which was visible.
+    pub fn set_visible_in_toplevel_group(&mut self, id: ToplevelGroupId, toplevel: ToplevelId) -> Result<(), Error> {
+        let changes = self.toplevel_groups.set_visible(id, toplevel)?;
+        self.apply_visibility(changes);
+        Ok(())
+    }
+
+    /// Draw a tab bar for the members of the group, or [`None`] to draw no tab bar.
+    pub fn set_toplevel_group_tab_layout(
+        &mut self,
+        id: ToplevelGroupId,
+        layout: Option<TabLayout>,
+    ) -> Result<(), Error> {
+        self.toplevel_groups.set_tab_layout(id, layout)?;
+        self.group_decorations_changed(id);
+        Ok(())
+    }
+
+    /// A toplevel was closed, so another member of its group may become visible.
+    pub(crate) fn toplevel_group_member_closed(&mut self, toplevel: ToplevelId) {
+        let group = self.toplevel_groups.group_of(toplevel);
+        let changes = self.toplevel_groups.remove_toplevel(toplevel);
+        self.apply_visibility(changes);
+
+        if let Some(group) = group {
+            self.group_decorations_changed(group);
+        }
+    }
+
+    /// Draw the decorations of the members of the group again, since the tab bar of the group changed.
+    fn group_decorations_changed(&mut self, id: ToplevelGroupId) {
+        let member = self
+            .toplevel_groups
+            .get(id)
+            .and_then(|group| group.members().first().copied());
+
+        match member {
+            Some(member) => self.decorations_changed(member),
+            None => self.scene_changed(),
+        }
+    }
+
+    fn apply_visibility(&mut self, changes: VisibilityChanges) {
+        if changes.shown.is_empty() && changes.hidden.is_empty() {
+            return;
+        }
+
+        let visible = changes.shown.first().copied();
+
+        for toplevel in changes.hidden {
+            self.set_toplevel_hidden(toplevel, true);
+        }
+
+        for toplevel in changes.shown {
+            self.set_toplevel_hidden(toplevel, false);
+
+            // Frame callbacks were held back while the toplevel was hidden, so the client redraws once shown.
+            if let Some(surface) = self
+                .shell
+                .get_state(toplevel)
+                .and_then(|toplevel| toplevel.wl_surface())
+            {
+                send_frames_surface_tree(&surface, 0);
+            }
+        }
+
+        // The visible member draws the tab bar.
+        if let Some(toplevel) = visible {
+            self.decorations_changed(toplevel);
+        }
+
+        self.scene_changed();
+    }
+
+    fn set_toplevel_hidden(&mut self, id: ToplevelId, hidden: bool) {
+        let Some(NodeIndex::SurfaceTree(index)) = self.toplevel_node(id) else {
+            return;
+        };
+
+        if let Some(surface_tree) = self.scene.get_surface_tree(index) {
+            let render = RenderProperties {
+                hidden,
+                ..surface_tree.render_properties()
+            };
+            self.scene.set_render_properties(index, render);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{decorations::TabBar, test_util::toplevel};
+
+    use super::{Error, TabLayout, ToplevelGroups, VisibilityChanges};
+
+    fn changes(shown: &[u64], hidden: &[u64]) -> VisibilityChanges {
+        VisibilityChanges {
+            shown: shown.iter().copied().map(toplevel).collect(),
+            hidden: hidden.iter().copied().map(toplevel).collect(),
+        }
+    }
+
+    #[test]
+    fn one_visible_member() {
+        let mut groups = ToplevelGroups::new();
+        let group = groups.create();
+
+        assert_eq!(groups.add(group, toplevel(1)).unwrap(), changes(&[], &[]));
+        assert_eq!(groups.add(group, toplevel(2)).unwrap(), changes(&[], &[2]));
+        assert_eq!(groups.add(group, toplevel(3)).unwrap(), changes(&[], &[3]));
+        assert_eq!(groups.get(group).unwrap().visible(), Some(toplevel(1)));
+        assert!(groups.is_hidden(toplevel(2)));
+
+        assert_eq!(groups.set_visible(group, toplevel(3)).unwrap(), changes(&[3], &[1]));
+        assert_eq!(groups.set_visible(group, toplevel(3)).unwrap(), changes(&[], &[]));
+        assert!(!groups.is_hidden(toplevel(3)));
+        assert!(!groups.is_hidden(toplevel(4)));
+
+        assert!(matches!(
+            groups.set_visible(group, toplevel(4)),
+            Err(Error::NotMember { .. })
+        ));
+    }
+
+    #[test]
+    fn next_member_shown() {
+        let mut groups = ToplevelGroups::new();
+        let group = groups.create();
+
+        for id in 1..=3 {
+            groups.add(group, toplevel(id)).unwrap();
+        }
+
+        groups.set_visible(group, toplevel(2)).unwrap();
+        assert_eq!(groups.remove_toplevel(toplevel(2)), changes(&[3], &[]));
+        assert_eq!(groups.remove(group, toplevel(3)).unwrap(), changes(&[1], &[]));
+        assert_eq!(groups.remove_toplevel(toplevel(1)), changes(&[], &[]));
+        assert_eq!(groups.get(group).unwrap().visible(), None);
+
+        // A hidden member moved to another group is visible in the new group.
+        let other = groups.create();
+        groups.add(group, toplevel(4)).unwrap();
+        groups.add(group, toplevel(5)).unwrap();
+        assert_eq!(groups.add(other, toplevel(5)).unwrap(), changes(&[5], &[]));
+        assert_eq!(groups.get(group).unwrap().members(), [toplevel(4)]);
+    }
+
+    #[test]
+    fn destroy_shows_members() {
+        let mut groups = ToplevelGroups::new();
+        let group = groups.create();
+
+        for id in 1..=3 {
+            groups.add(group, toplevel(id)).unwrap();
+        }
+
+        assert_eq!(groups.destroy(group).unwrap(), changes(&[2, 3], &[]));
+        assert_eq!(groups.group_of(toplevel(1)), None);
+        assert!(!groups.is_hidden(toplevel(2)));
+        assert!(matches!(groups.destroy(group), Err(Error::NotPresent(_))));
+    }
+
+    #[test]
+    fn tab_bar_of_visible_member() {
+        let mut groups = ToplevelGroups::new();
+        let group = groups.create();
+
+        for id in 1..=3 {
+            groups.add(group, toplevel(id)).unwrap();
+        }
+
+        assert_eq!(groups.tab_bar(toplevel(1)), None);
+
+        groups.set_tab_layout(group, Some(TabLayout::Tabbed)).unwrap();
+        groups.set_visible(group, toplevel(2)).unwrap();
+        assert_eq!(groups.tab_bar(toplevel(1)), None);
+        assert_eq!(
+            groups.tab_bar(toplevel(2)),
+            Some(TabBar {
+                layout: TabLayout::Tabbed,
+                tabs: 3,
+                active: 1,
+            })
+        );
+    }
+}
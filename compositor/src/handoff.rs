@@ -0,0 +1,411 @@
+//! Restarting in place
+//!
+//! An upgraded display server is started by executing the new binary in place of the running process. The
+//! listening sockets and the DRM devices are inherited by the new process, and their file descriptors are passed
+//! in the [`HANDOFF_ENV`] environment variable.
+//!
+//! Since the listening sockets stay bound, clients which connect during the restart are accepted by the new
+//! process once it runs. The connections of existing clients do not survive, since their protocol state lives in
+//! the old process. Clients which re-establish their state on a new connection, such as Qt clients with
+//! `QT_WAYLAND_RECONNECT` set, reconnect to the same socket and survive the restart. The DRM devices of the backend
+//! stay open, so the session keeps DRM master and the outputs are not blanked.
+//!
+//! The name of a listening socket is reserved by a lock on `<socket>.lock`, which is passed on with the socket so
+//! no other display server can take the name while the new process starts.
+//!
+//! The variable holds entries separated by `;`. Each entry is `<kind>=<fd>:<value>`, such as
+//! `socket=5:wayland-1`, `lock=6:wayland-1` or `drm=7:/dev/dri/card0`.
+//!
+//! Taking over the file descriptors is opt-in: the binary reads the variable with [`Handoff::from_env`] and passes
+//! the [`Inherited`] file descriptors to [`Configuration::with_handoff`](crate::Configuration::with_handoff).
+//! Every file descriptor is checked to be open and of the kind the entry claims before it is owned, so a stale or
+//! forged variable cannot make the display server listen on or close an unrelated file.
+
+use std::{
+    env, io,
+    os::{
+        fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        unix::process::CommandExt,
+    },
+    path::PathBuf,
+    process::Command,
+};
+
+use rustix::{
+    fs::{fstat, FileType},
+    io::{fcntl_getfd, fcntl_setfd, FdFlags},
+    net::{
+        sockopt::{get_socket_acceptconn, get_socket_type},
+        SocketType,
+    },
+};
+
+/// The environment variable holding the file descriptors passed to the new process.
+pub const HANDOFF_ENV: &str = "AERUGO_HANDOFF";
+
+/// The file descriptors passed from the old process to the new process.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Handoff {
+    /// Listening sockets and the names clients connect with.
+    pub sockets: Vec<(RawFd, String)>,
+
+    /// The lock files of the listening sockets and the names of the sockets they reserve.
+    pub locks: Vec<(RawFd, String)>,
+
+    /// Open DRM devices and their paths.
+    pub drm: Vec<(RawFd, PathBuf)>,
+}
+
+/// An error from reading the handoff of the previous process.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("invalid entry {0:?}")]
+    Entry(String),
+
+    #[error("unknown kind of file descriptor {0:?}")]
+    Kind(String),
+
+    #[error("the value {0:?} contains a `;`")]
+    Separator(String),
+
+    #[error("file descriptor {0} is passed more than once")]
+    Duplicate(RawFd),
+
+    #[error("file descriptor {0} is not open")]
+    Closed(RawFd),
+
+    #[error("file descriptor {0} is not {1}")]
+    Mismatch(RawFd, &'static str),
+}
+
+/// The file descriptors taken over from the previous process.
+#[derive(Debug, Default)]
+pub struct Inherited {
+    /// The listening socket, the lock file reserving its name and the name clients connect with.
+    pub socket: Option<(OwnedFd, Option<OwnedFd>, String)>,
+}
+
+/// The kind of file a file descriptor of the handoff must be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Socket,
+    Lock,
+    Drm,
+}
+
+impl Kind {
+    fn description(self) -> &'static str {
+        match self {
+            Kind::Socket => "a listening stream socket",
+            Kind::Lock => "a regular file",
+            Kind::Drm => "a character device",
+        }
+    }
+}
+
+/// Check the file descriptor is open and of the kind.
+fn check_fd(fd: RawFd, kind: Kind) -> Result<(), Error> {
+    // SAFETY: The file descriptor is only used for the checks, which fail if it is not open.
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    fcntl_getfd(borrowed).map_err(|_| Error::Closed(fd))?;
+    let file_type = fstat(borrowed)
+        .map(|stat| FileType::from_raw_mode(stat.st_mode))
+        .map_err(|_| Error::Closed(fd))?;
+
+    let matches = match kind {
+        Kind::Socket => {
+            file_type == FileType::Socket
+                && get_socket_type(borrowed).is_ok_and(|ty| ty == SocketType::STREAM)
+                && get_socket_acceptconn(borrowed).unwrap_or(false)
+        }
+        Kind::Lock => file_type == FileType::RegularFile,
+        Kind::Drm => file_type == FileType::CharacterDevice,
+    };
+
+    match matches {
+        true => Ok(()),
+        false => Err(Error::Mismatch(fd, kind.description())),
+    }
+}
+
+impl Handoff {
+    pub fn parse(value: &str) -> Result<Self, Error> {
+        let mut handoff = Self::default();
+
+        for entry in value.split(';').filter(|entry| !entry.is_empty()) {
+            let (kind, rest) = entry.split_once('=').ok_or_else(|| Error::Entry(entry.into()))?;
+            let (fd, value) = rest.split_once(':').ok_or_else(|| Error::Entry(entry.into()))?;
+            let fd = fd
+                .parse::<RawFd>()
+                .ok()
+                .filter(|&fd| fd >= 0)
+                .ok_or_else(|| Error::Entry(entry.into()))?;
+
+            match kind {
+                "socket" => handoff.sockets.push((fd, value.into())),
+                "lock" => handoff.locks.push((fd, value.into())),
+                "drm" => handoff.drm.push((fd, value.into())),
+                _ => return Err(Error::Kind(kind.into())),
+            }
+        }
+
+        Ok(handoff)
+    }
+
+    /// Encode the handoff as the value of [`HANDOFF_ENV`].
+    pub fn encode(&self) -> Result<String, Error> {
+        let sockets = self.sockets.iter().map(|(fd, name)| ("socket", *fd, name.clone()));
+        let locks = self.locks.iter().map(|(fd, name)| ("lock", *fd, name.clone()));
+        let drm = self
+            .drm
+            .iter()
+            .map(|(fd, path)| ("drm", *fd, path.to_string_lossy().into_owned()));
+
+        let mut entries = Vec::new();
+
+        for (kind, fd, value) in sockets.chain(locks).chain(drm) {
+            if value.contains(';') {
+                return Err(Error::Separator(value));
+            }
+
+            entries.push(format!("{kind}={fd}:{value}"));
+        }
+
+        Ok(entries.join(";"))
+    }
+
+    /// Take the handoff of the previous process from the environment.
+    ///
+    /// The variable is removed, so child processes do not see it.
+    pub fn from_env() -> Option<Self> {
+        let value = env::var(HANDOFF_ENV).ok()?;
+        env::remove_var(HANDOFF_ENV);
+
+        match Self::parse(&value) {
+            Ok(handoff) => Some(handoff),
+            Err(err) => {
+                tracing::error!(%err, "Ignoring the invalid handoff of the previous process");
+                None
+            }
+        }
+    }
+
+    /// Check every file descriptor and take ownership of them.
+    ///
+    /// Nothing is owned if a file descriptor is passed twice, is not open or is not of the kind of its entry. Only
+    /// the first listening socket is used. The DRM devices are closed, since the X11 backend opens its own device.
+    ///
+    /// # Safety
+    ///
+    /// The file descriptors which are open must have been passed by the previous process and must not be owned by
+    /// anything else in this process.
+    pub unsafe fn adopt(self) -> Result<Inherited, Error> {
+        let entries = self
+            .sockets
+            .iter()
+            .map(|&(fd, _)| (fd, Kind::Socket))
+            .chain(self.locks.iter().map(|&(fd, _)| (fd, Kind::Lock)))
+            .chain(self.drm.iter().map(|&(fd, _)| (fd, Kind::Drm)))
+            .collect::<Vec<_>>();
+
+        for (index, &(fd, kind)) in entries.iter().enumerate() {
+            if entries[..index].iter().any(|&(other, _)| other == fd) {
+                return Err(Error::Duplicate(fd));
+            }
+
+            check_fd(fd, kind)?;
+        }
+
+        let mut locks = self
+            .locks
+            .into_iter()
+            .map(|(fd, name)| (adopt(fd), name))
+            .collect::<Vec<_>>();
+        let mut sockets = self.sockets.into_iter().map(|(fd, name)| {
+            let lock = locks
+                .iter()
+                .position(|(_, socket)| *socket == name)
+                .map(|index| locks.swap_remove(index).0);
+            (adopt(fd), lock, name)
+        });
+
+        let socket = sockets.next();
+
+        for (_, _, name) in sockets {
+            tracing::warn!(name, "Closing an inherited listening socket, since only one is used");
+        }
+
+        for (fd, path) in self.drm {
+            tracing::debug!(?path, "Closing an inherited DRM device");
+            drop(adopt(fd));
+        }
+
+        Ok(Inherited { socket })
+    }
+
+    /// Execute the binary in place of the running process, passing the file descriptors to it.
+    ///
+    /// The display server is started with the same arguments. Only returns if the binary could not be executed,
+    /// in which case the file descriptors are not inherited by child processes of the running process.
+    pub fn exec(&self, fds: &[BorrowedFd<'_>]) -> io::Error {
+        let value = match self.encode() {
+            Ok(value) => value,
+            Err(err) => return io::Error::new(io::ErrorKind::InvalidInput, err),
+        };
+
+        let executable = match env::current_exe() {
+            Ok(executable) => restart_executable(executable),
+            Err(err) => return err,
+        };
+
+        for fd in fds {
+            if let Err(err) = fcntl_setfd(fd, FdFlags::empty()) {
+                restore_cloexec(fds);
+                return err.into();
+            }
+        }
+
+        tracing::info!(?executable, "Restarting in place");
+        let err = Command::new(executable)
+            .args(env::args_os().skip(1))
+            .env(HANDOFF_ENV, value)
+            .exec();
+
+        restore_cloexec(fds);
+        err
+    }
+}
+
+/// Take ownership of a file descriptor inherited from the previous process.
+///
+/// The file descriptor is marked close on exec again, so child processes do not inherit it.
+///
+/// # Safety
+///
+/// The file descriptor must be open and must not be owned by anything else in this process.
+pub unsafe fn adopt(fd: RawFd) -> OwnedFd {
+    let fd = OwnedFd::from_raw_fd(fd);
+
+    if let Err(err) = fcntl_setfd(&fd, FdFlags::CLOEXEC) {
+        tracing::warn!(%err, fd = fd.as_raw_fd(), "Failed to mark inherited file descriptor close on exec");
+    }
+
+    fd
+}
+
+/// Mark the file descriptors close on exec again after a failed restart.
+fn restore_cloexec(fds: &[BorrowedFd<'_>]) {
+    for fd in fds {
+        if let Err(err) = fcntl_setfd(fd, FdFlags::CLOEXEC) {
+            tracing::warn!(%err, fd = fd.as_raw_fd(), "Failed to mark file descriptor close on exec");
+        }
+    }
+}
+
+/// The path of the binary to execute for a restart.
+///
+/// An upgrade replaces the binary while it is running, so the path of the running binary has ` (deleted)`
+/// appended. The new binary is at the original path.
+fn restart_executable(executable: PathBuf) -> PathBuf {
+    match executable.to_str().and_then(|path| path.strip_suffix(" (deleted)")) {
+        Some(path) => PathBuf::from(path),
+        None => executable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::File,
+        os::{fd::AsRawFd, unix::net::UnixListener},
+        path::PathBuf,
+        process,
+    };
+
+    use super::{check_fd, restart_executable, Error, Handoff, Kind};
+
+    #[test]
+    fn round_trip() {
+        let handoff = Handoff {
+            sockets: vec![(5, "wayland-1".into())],
+            locks: vec![(6, "wayland-1".into())],
+            drm: vec![(7, "/dev/dri/card0".into()), (8, "/dev/dri/card1".into())],
+        };
+
+        let value = handoff.encode().unwrap();
+        assert_eq!(
+            value,
+            "socket=5:wayland-1;lock=6:wayland-1;drm=7:/dev/dri/card0;drm=8:/dev/dri/card1"
+        );
+        assert_eq!(Handoff::parse(&value), Ok(handoff));
+        assert_eq!(Handoff::parse(""), Ok(Handoff::default()));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(Handoff::parse("socket=5"), Err(Error::Entry("socket=5".into())));
+        assert_eq!(
+            Handoff::parse("socket=-1:wayland-1"),
+            Err(Error::Entry("socket=-1:wayland-1".into()))
+        );
+        assert_eq!(Handoff::parse("tty=3:/dev/tty1"), Err(Error::Kind("tty".into())));
+
+        let handoff = Handoff {
+            sockets: vec![(5, "wayland;1".into())],
+            locks: Vec::new(),
+            drm: Vec::new(),
+        };
+        assert_eq!(handoff.encode(), Err(Error::Separator("wayland;1".into())));
+    }
+
+    #[test]
+    fn file_descriptors_are_checked() {
+        let path = env::temp_dir().join(format!("aerugo-handoff-test-{}", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let file = File::open(env::current_exe().unwrap()).unwrap();
+        let null = File::open("/dev/null").unwrap();
+
+        assert_eq!(check_fd(listener.as_raw_fd(), Kind::Socket), Ok(()));
+        assert_eq!(check_fd(file.as_raw_fd(), Kind::Lock), Ok(()));
+        assert_eq!(check_fd(null.as_raw_fd(), Kind::Drm), Ok(()));
+        assert_eq!(
+            check_fd(null.as_raw_fd(), Kind::Socket),
+            Err(Error::Mismatch(null.as_raw_fd(), "a listening stream socket"))
+        );
+        assert_eq!(
+            check_fd(listener.as_raw_fd(), Kind::Drm),
+            Err(Error::Mismatch(listener.as_raw_fd(), "a character device"))
+        );
+        assert_eq!(check_fd(1 << 20, Kind::Lock), Err(Error::Closed(1 << 20)));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn duplicates_are_rejected() {
+        let file = File::open(env::current_exe().unwrap()).unwrap();
+        let fd = file.as_raw_fd();
+        let handoff = Handoff {
+            sockets: Vec::new(),
+            locks: vec![(fd, "wayland-1".into()), (fd, "wayland-2".into())],
+            drm: Vec::new(),
+        };
+
+        // SAFETY: Nothing is adopted, since the file descriptor is passed twice.
+        assert_eq!(unsafe { handoff.adopt() }.unwrap_err(), Error::Duplicate(fd));
+    }
+
+    #[test]
+    fn replaced_executable() {
+        assert_eq!(
+            restart_executable("/usr/bin/aerugo (deleted)".into()),
+            PathBuf::from("/usr/bin/aerugo")
+        );
+        assert_eq!(
+            restart_executable("/usr/bin/aerugo".into()),
+            PathBuf::from("/usr/bin/aerugo")
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Idle management
+//!
+//! When there is no input for some time, outputs are dimmed and then powered off. Dimming is a smooth fade of
+//! the brightness over a configurable duration, after which the outputs are turned off using DPMS. Any input
+//! immediately aborts the fade and restores full brightness.
+//!
+//! ```text
+//! Active ---(dim timeout)---> Dimming ---(fade finished)---> Off
+//!   ^                            |                            |
+//!   \------------(input)---------/----------(input)-----------/
+//! ```
+//!
+//! The fade dims the outputs using the brightness of the color transform, on top of the brightness set by the wm.
+
+use std::time::{Duration, Instant};
+
+use calloop::timer::{TimeoutAction, Timer};
+
+use crate::{Aerugo, Loop};
+
+/// Configuration of the idle stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleConfig {
+    /// How long there must be no input before the outputs start to dim.
+    pub dim_timeout: Duration,
+
+    /// How long the fade to black takes before the outputs are powered off.
+    ///
+    /// If this is zero, the outputs are powered off immediately.
+    pub fade_duration: Duration,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            dim_timeout: Duration::from_secs(300),
+            fade_duration: Duration::from_secs(5),
+        }
+    }
+}
+
+/// The current idle stage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdleStage {
+    /// The user is active.
+    Active,
+
+    /// The outputs are fading to black.
+    Dimming {
+        /// When the fade started.
+        start: Instant,
+    },
+
+    /// The outputs are powered off.
+    Off,
+}
+
+/// A change the backend must apply to the outputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdleAction {
+    /// Set the brightness of all outputs.
+    ///
+    /// The value is between 0.0 (black) and 1.0 (full brightness). This may be applied using the backlight or
+    /// a gamma ramp.
+    SetBrightness(f32),
+
+    /// Power off all outputs.
+    PowerOff,
+
+    /// Power on all outputs and restore full brightness.
+    PowerOn,
+}
+
+#[derive(Debug)]
+pub struct IdleState {
+    config: IdleConfig,
+    stage: IdleStage,
+    last_input: Instant,
+}
+
+impl IdleState {
+    pub fn new(config: IdleConfig, now: Instant) -> Self {
+        Self {
+            config,
+            stage: IdleStage::Active,
+            last_input: now,
+        }
+    }
+
+    pub fn stage(&self) -> IdleStage {
+        self.stage
+    }
+
+    /// Notify the idle state that input has occurred.
+    ///
+    /// If the outputs were being dimmed or powered off, this returns the action to restore the outputs.
+    pub fn input(&mut self, now: Instant) -> Option<IdleAction> {
+        self.last_input = now;
+
+        match std::mem::replace(&mut self.stage, IdleStage::Active) {
+            IdleStage::Active => None,
+            IdleStage::Dimming { .. } => Some(IdleAction::SetBrightness(1.0)),
+            IdleStage::Off => Some(IdleAction::PowerOn),
+        }
+    }
+
+    /// Advance the idle stages to the specified time.
+    pub fn update(&mut self, now: Instant) -> Option<IdleAction> {
+        match self.stage {
+            IdleStage::Active => {
+                let dim_at = self.last_input + self.config.dim_timeout;
+
+                if now < dim_at {
+                    return None;
+                }
+
+                if self.config.fade_duration.is_zero() {
+                    self.stage = IdleStage::Off;
+                    return Some(IdleAction::PowerOff);
+                }
+
+                self.stage = IdleStage::Dimming { start: dim_at };
+                self.update(now)
+            }
+
+            IdleStage::Dimming { start } => {
+                let elapsed = now.saturating_duration_since(start);
+
+                if elapsed >= self.config.fade_duration {
+                    self.stage = IdleStage::Off;
+                    return Some(IdleAction::PowerOff);
+                }
+
+                Some(IdleAction::SetBrightness(fade_brightness(
+                    elapsed,
+                    self.config.fade_duration,
+                )))
+            }
+
+            IdleStage::Off => None,
+        }
+    }
+
+    /// The next time [`IdleState::update`] must be called.
+    ///
+    /// While dimming this returns [`None`] since the fade should be advanced every frame. Once the outputs are
+    /// powered off there is nothing left to wait for, so no timer is needed until the next input.
+    ///
+    /// The deadline is waited for with a one-shot timer, since the event loop has no timeout. Input does not move
+    /// the timer; if input occurred after the timer was set, the update does nothing and the timer is set again
+    /// for the new deadline. So the event loop wakes up at most once per dim timeout while the user is active.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.stage {
+            IdleStage::Active => Some(self.last_input + self.config.dim_timeout),
+            IdleStage::Dimming { .. } | IdleStage::Off => None,
+        }
+    }
+}
+
+impl Aerugo {
+    /// Advance the fade of the outputs, requesting another frame until the fade is finished.
+    pub fn update_idle_fade(&mut self, now: Instant) {
+        let Some(idle) = &mut self.idle else {
+            return;
+        };
+
+        if !matches!(idle.stage(), IdleStage::Dimming { .. }) {
+            return;
+        }
+
+        let action = idle.update(now);
+        let dimming = matches!(idle.stage(), IdleStage::Dimming { .. });
+
+        if let Some(action) = action {
+            self.apply_idle_action(action);
+        }
+
+        if dimming {
+            self.frame_requested = true;
+        }
+    }
+
+    fn apply_idle_action(&mut self, action: IdleAction) {
+        tracing::debug!(?action, "Idle stage changed");
+        let output = self.output.clone();
+
+        match action {
+            IdleAction::SetBrightness(brightness) => self.set_idle_brightness(brightness.into()),
+            IdleAction::PowerOff => self.set_output_dpms(&output, false),
+
+            IdleAction::PowerOn => {
+                self.set_idle_brightness(1.0);
+                self.set_output_dpms(&output, true);
+            }
+        }
+    }
+}
+
+impl Loop {
+    /// Notify the idle state that input has occurred, restoring the outputs if they were dimmed or powered off.
+    pub fn idle_input(&mut self) {
+        let Some(idle) = &mut self.comp.idle else {
+            return;
+        };
+
+        if let Some(action) = idle.input(Instant::now()) {
+            self.comp.apply_idle_action(action);
+            // The timer is only set while the user is active.
+            self.schedule_idle();
+        }
+    }
+
+    /// Wake up at the next idle deadline.
+    pub(crate) fn schedule_idle(&mut self) {
+        let Some(deadline) = self.comp.idle.as_ref().and_then(IdleState::next_deadline) else {
+            return;
+        };
+
+        self.r#loop
+            .insert_source(Timer::from_deadline(deadline), |_, _, state| {
+                if let Some(action) = state.comp.idle.as_mut().and_then(|idle| idle.update(Instant::now())) {
+                    state.comp.apply_idle_action(action);
+                }
+
+                state.schedule_idle();
+                TimeoutAction::Drop
+            })
+            .expect("Failed to insert timer");
+    }
+}
+
+/// The brightness after some time has elapsed in a fade.
+///
+/// The fade uses a smoothstep curve so the start and end of the fade are less abrupt.
+fn fade_brightness(elapsed: Duration, duration: Duration) -> f32 {
+    let t = (elapsed.as_secs_f32() / duration.as_secs_f32()).clamp(0.0, 1.0);
+    let eased = t * t * (3.0 - 2.0 * t);
+    1.0 - eased
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{IdleAction, IdleConfig, IdleStage, IdleState};
+
+    const CONFIG: IdleConfig = IdleConfig {
+        dim_timeout: Duration::from_secs(10),
+        fade_duration: Duration::from_secs(2),
+    };
+
+    #[test]
+    fn stays_active() {
+        let start = Instant::now();
+        let mut idle = IdleState::new(CONFIG, start);
+
+        assert_eq!(idle.update(start + Duration::from_secs(9)), None);
+        assert_eq!(idle.stage(), IdleStage::Active);
+        assert_eq!(idle.next_deadline(), Some(start + CONFIG.dim_timeout));
+    }
+
+    #[test]
+    fn fade_then_off() {
+        let start = Instant::now();
+        let mut idle = IdleState::new(CONFIG, start);
+
+        let Some(IdleAction::SetBrightness(brightness)) = idle.update(start + Duration::from_secs(11)) else {
+            panic!("expected the outputs to dim");
+        };
+        assert!(brightness > 0.0 && brightness < 1.0);
+        assert_eq!(idle.next_deadline(), None);
+
+        assert_eq!(idle.update(start + Duration::from_secs(12)), Some(IdleAction::PowerOff));
+        assert_eq!(idle.stage(), IdleStage::Off);
+        assert_eq!(idle.update(start + Duration::from_secs(13)), None);
+    }
+
+    #[test]
+    fn input_aborts_fade() {
+        let start = Instant::now();
+        let mut idle = IdleState::new(CONFIG, start);
+
+        assert!(idle.update(start + Duration::from_secs(11)).is_some());
+        assert_eq!(
+            idle.input(start + Duration::from_secs(11)),
+            Some(IdleAction::SetBrightness(1.0))
+        );
+        assert_eq!(idle.stage(), IdleStage::Active);
+    }
+
+    #[test]
+    fn input_powers_on() {
+        let start = Instant::now();
+        let mut idle = IdleState::new(
+            IdleConfig {
+                fade_duration: Duration::ZERO,
+                ..CONFIG
+            },
+            start,
+        );
+
+        assert_eq!(idle.update(start + Duration::from_secs(10)), Some(IdleAction::PowerOff));
+        assert_eq!(idle.input(start + Duration::from_secs(20)), Some(IdleAction::PowerOn));
+    }
+}
@@ -0,0 +1,909 @@
+//! Input routing
+//!
+//! # Session lock
+//!
+//! A screen locker using `ext-session-lock-v1` receives all input while the session is locked, and neither other
+//! clients nor the wm receive input. The session lock takes precedence over an exclusive grab, so a session can
+//! be locked while another client holds a grab.
+//!
+//! # Exclusive grabs
+//!
+//! Legacy screen lockers using `zwlr_input_inhibitor_v1` receive all input with an exclusive grab. While an
+//! exclusive grab is active, only the client which owns the grab may be given keyboard or pointer focus.
+//!
+//! # Keyboard focus
+//!
+//! The keyboard focus is decided by the wm. The compositor never moves the keyboard focus on its own (such as
+//! when a toplevel is mapped or clicked), which allows the wm to implement focus-follows-mouse or move the focus
+//! between tiles. The focused toplevel is given the activated state.
+//!
+//! # Filtering by the wm
+//!
+//! Keys and pointer events are offered to the wm before clients, so the wm may match bindings. The wm replies to
+//! each event with a filter deciding whether the event is sent to clients. Events are held back until the wm
+//! replies, so clients receive the events in the order they arrived. The wm is not asked while the session is
+//! locked, while a client holds an exclusive grab or while the fallback layout is used after a crash.
+//!
+//! A hung wm would hold input back forever, so the held input is sent to clients if the wm does not reply within
+//! [`INPUT_TIMEOUT`]. The bindings of the wm, including the bindings of its modes, are not matched in that case.
+//!
+//! # Pointer warps
+//!
+//! The wm may move the pointer, such as to center the pointer on a newly focused toplevel. A warp is handled like
+//! motion of the pointer to the new location, so the pointer focus and the wm are updated. Clients receiving
+//! relative pointer motion do not receive the warp, since the pointer did not move physically and a game would
+//! otherwise see the camera jump.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use calloop::timer::{TimeoutAction, Timer};
+use rustix::time::{clock_gettime, ClockId};
+use smithay::{
+    backend::input::{
+        self, AbsolutePositionEvent, Axis, Device, DeviceCapability, Event, GestureBeginEvent, GestureEndEvent,
+        GesturePinchUpdateEvent, GestureSwipeUpdateEvent, InputBackend, InputEvent, KeyboardKeyEvent, PointerAxisEvent,
+        PointerButtonEvent, PointerMotionEvent, SwitchState, SwitchToggleEvent, TouchEvent as _,
+    },
+    reexports::wayland_protocols::ext::session_lock::v1::server::ext_session_lock_v1::ExtSessionLockV1,
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+use wayland_server::{backend::ClientId, protocol::wl_surface::WlSurface, Resource};
+use wm_runtime::{types::SwitchKind, WmEvent};
+
+use crate::{
+    gesture::{GestureEvent, GestureKind},
+    pointer::PointerEvent,
+    shell::ToplevelId,
+    touch::TouchEvent,
+    Aerugo, Loop,
+};
+
+/// A switch of an input device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Switch {
+    /// The lid of a laptop, which is on while closed.
+    Lid,
+
+    /// The switch of a convertible laptop which is on while folded into a tablet.
+    TabletMode,
+}
+
+/// The protocol which created an exclusive grab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExclusiveGrabKind {
+    /// The grab was created by the legacy `zwlr_input_inhibitor_v1` protocol.
+    InputInhibitor,
+}
+
+/// An exclusive grab of all input by a single client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExclusiveGrab {
+    client: ClientId,
+    kind: ExclusiveGrabKind,
+}
+
+impl ExclusiveGrab {
+    pub fn new(client: ClientId, kind: ExclusiveGrabKind) -> Self {
+        Self { client, kind }
+    }
+
+    /// The client which owns the grab.
+    pub fn client(&self) -> &ClientId {
+        &self.client
+    }
+
+    pub fn kind(&self) -> ExclusiveGrabKind {
+        self.kind
+    }
+
+    /// Whether the grab is owned by the client and was created by the protocol, so the client may release it.
+    fn is_owned_by(&self, client: &ClientId, kind: ExclusiveGrabKind) -> bool {
+        &self.client == client && self.kind == kind
+    }
+}
+
+/// A session locked by a screen locker.
+#[derive(Debug)]
+pub struct SessionLock {
+    /// The lock of the screen locker, or [`None`] if the screen locker died without unlocking the session.
+    lock: Option<ExtSessionLockV1>,
+
+    /// The surface drawn instead of toplevels.
+    surface: Option<WlSurface>,
+
+    /// How far the screen locker is from being told the session is locked.
+    locked: Locked,
+}
+
+/// The screen locker is only told the session is locked once a frame without toplevels was presented, so it knows
+/// nothing private is visible anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Locked {
+    /// No frame was drawn since the session was locked.
+    Pending,
+
+    /// A frame without toplevels was drawn and is waiting to be presented.
+    Drawn,
+
+    /// A frame without toplevels was presented.
+    Presented,
+}
+
+impl SessionLock {
+    /// The client which locked the session, if the client is still connected.
+    pub fn client(&self) -> Option<ClientId> {
+        self.lock.as_ref()?.client().map(|client| client.id())
+    }
+
+    /// The surface drawn instead of toplevels.
+    pub fn surface(&self) -> Option<&WlSurface> {
+        self.surface.as_ref()
+    }
+}
+
+/// Input events held back until the wm decides whether each event is sent to clients.
+#[derive(Debug)]
+pub struct FilterQueue<T> {
+    /// The events in the order they arrived, with the serial the event was sent to the wm with and whether the
+    /// event is sent to clients once the wm replied.
+    events: VecDeque<(u32, T, Option<bool>)>,
+}
+
+impl<T> FilterQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// The serial of the oldest held event.
+    pub fn oldest(&self) -> Option<u32> {
+        self.events.front().map(|(serial, _, _)| *serial)
+    }
+
+    /// Hold the event back until the wm replies to the serial.
+    pub fn hold(&mut self, serial: u32, event: T) {
+        self.events.push_back((serial, event, None));
+    }
+
+    /// The wm decided whether the event with the serial is sent to clients.
+    ///
+    /// Returns the events which are released with whether each event is sent to clients. An event is only released
+    /// once the wm replied to every event before it.
+    pub fn filter(&mut self, serial: u32, forward: bool) -> Vec<(T, bool)> {
+        if let Some((_, _, decision)) = self.events.iter_mut().find(|(held, _, _)| *held == serial) {
+            *decision = Some(forward);
+        }
+
+        let mut released = Vec::new();
+
+        while let Some(&(_, _, Some(_))) = self.events.front() {
+            let (_, event, forward) = self.events.pop_front().unwrap();
+            released.push((event, forward.unwrap()));
+        }
+
+        released
+    }
+
+    /// Release every held event, such as after the wm crashed.
+    ///
+    /// Events the wm did not reply to are sent to clients.
+    pub fn release_all(&mut self) -> Vec<(T, bool)> {
+        self.events
+            .drain(..)
+            .map(|(_, event, forward)| (event, forward.unwrap_or(true)))
+            .collect()
+    }
+}
+
+impl<T> Default for FilterQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How long input is held back waiting for the wm before the input is sent to clients.
+pub const INPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The time of an input event which did not come from an input device, such as a pointer warp.
+///
+/// This is the monotonic clock in milliseconds, like the time of the events of libinput.
+pub fn time() -> u32 {
+    let now = clock_gettime(ClockId::Monotonic);
+    (now.tv_sec as u64 * 1000 + now.tv_nsec as u64 / 1_000_000) as u32
+}
+
+/// The time of an event of an input device which is sent to the wm or clients.
+fn event_time<B: InputBackend>(event: &InputEvent<B>) -> Option<u32> {
+    match event {
+        InputEvent::Keyboard { event } => Some(event.time_msec()),
+        InputEvent::PointerMotion { event } => Some(event.time_msec()),
+        InputEvent::PointerMotionAbsolute { event } => Some(event.time_msec()),
+        InputEvent::PointerButton { event } => Some(event.time_msec()),
+        InputEvent::PointerAxis { event } => Some(event.time_msec()),
+        InputEvent::TouchDown { event } => Some(event.time_msec()),
+        InputEvent::TouchMotion { event } => Some(event.time_msec()),
+        InputEvent::TouchUp { event } => Some(event.time_msec()),
+        _ => None,
+    }
+}
+
+/// The time since the time of an input event, or [`None`] if the time is in the future.
+fn latency(event: u32) -> Option<Duration> {
+    // The times wrap around after about 49 days.
+    let elapsed = time().wrapping_sub(event);
+    (elapsed <= i32::MAX as u32).then_some(Duration::from_millis(elapsed as u64))
+}
+
+/// Whether the client may receive input while the session lock and the exclusive grab are active.
+fn may_receive_input(session_lock: Option<&SessionLock>, grab: Option<&ExclusiveGrab>, client: &ClientId) -> bool {
+    if let Some(session_lock) = session_lock {
+        return session_lock.client().as_ref() == Some(client);
+    }
+
+    grab.map(|grab| &grab.client == client).unwrap_or(true)
+}
+
+/// Whether the client with keyboard focus keeps the focus when the grab starts.
+fn keeps_focus(grab: &ExclusiveGrab, focus: Option<&ClientId>) -> bool {
+    focus.map_or(true, |focus| focus == &grab.client)
+}
+
+impl Aerugo {
+    /// Lock the session with the lock of a screen locker.
+    ///
+    /// Returns [`false`] if another screen locker holds the lock. A screen locker may take over a session whose
+    /// screen locker died.
+    pub fn lock_session(&mut self, lock: ExtSessionLockV1) -> bool {
+        match &mut self.session_lock {
+            Some(session_lock) if session_lock.lock.is_some() => false,
+
+            Some(session_lock) => {
+                tracing::info!("Screen locker took over the locked session");
+
+                // Toplevels were hidden since the previous screen locker locked the session.
+                if session_lock.locked == Locked::Presented {
+                    lock.locked();
+                }
+
+                session_lock.lock = Some(lock);
+                true
+            }
+
+            None => {
+                // Toplevels must not receive input while the session is locked.
+                self.set_keyboard_focus(None);
+
+                self.session_lock = Some(SessionLock {
+                    lock: Some(lock),
+                    surface: None,
+                    locked: Locked::Pending,
+                });
+                self.session_lock_changed();
+                true
+            }
+        }
+    }
+
+    /// Whether the lock is the lock of the screen locker holding the session lock.
+    pub fn is_session_locker(&self, lock: &ExtSessionLockV1) -> bool {
+        self.session_lock
+            .as_ref()
+            .and_then(|session_lock| session_lock.lock.as_ref())
+            .is_some_and(|session_lock| session_lock == lock)
+    }
+
+    /// Unlock the session.
+    pub fn unlock_session(&mut self) {
+        if let Some(session_lock) = self.session_lock.take() {
+            if let Some(surface) = session_lock.surface {
+                self.scene.destroy_surface_tree(&surface);
+            }
+
+            self.session_lock_changed();
+        }
+    }
+
+    /// The lock of the screen locker was destroyed without unlocking the session, so the session stays locked.
+    pub fn session_locker_destroyed(&mut self) {
+        tracing::warn!("Screen locker died without unlocking the session");
+
+        if let Some(session_lock) = &mut self.session_lock {
+            session_lock.lock = None;
+        }
+
+        self.set_lock_surface(None);
+    }
+
+    /// Set the surface drawn instead of toplevels while the session is locked.
+    ///
+    /// The lock surface is given keyboard focus.
+    pub fn set_lock_surface(&mut self, surface: Option<WlSurface>) {
+        let Some(session_lock) = &mut self.session_lock else {
+            return;
+        };
+
+        if let Some(previous) = session_lock.surface.take() {
+            self.scene.destroy_surface_tree(&previous);
+        }
+
+        if let Some(surface) = &surface {
+            self.scene.create_surface_tree(surface.clone());
+        }
+
+        session_lock.surface = surface.clone();
+        self.set_text_input_focus(surface.as_ref());
+
+        if let Some(keyboard) = self.seat.get_keyboard() {
+            keyboard.set_focus(self, surface, SERIAL_COUNTER.next_serial());
+        }
+
+        self.scene_changed();
+    }
+
+    /// A frame was drawn, which hides every toplevel if the session is locked.
+    pub fn session_lock_frame_drawn(&mut self) {
+        if let Some(session_lock) = &mut self.session_lock {
+            if session_lock.locked == Locked::Pending {
+                session_lock.locked = Locked::Drawn;
+            }
+        }
+    }
+
+    /// The frame drawn last was presented, or the output is not visible at all.
+    ///
+    /// The screen locker is told the session is locked once a frame drawn after the session was locked is
+    /// presented.
+    pub fn session_lock_frame_presented(&mut self) {
+        let Some(session_lock) = &mut self.session_lock else {
+            return;
+        };
+
+        if session_lock.locked == Locked::Drawn {
+            session_lock.locked = Locked::Presented;
+
+            if let Some(lock) = &session_lock.lock {
+                lock.locked();
+            }
+        }
+    }
+
+    /// Whether a screen locker locked the session.
+    ///
+    /// Toplevels are not drawn while the session is locked.
+    pub fn is_session_locked(&self) -> bool {
+        self.session_lock.is_some()
+    }
+
+    /// The session was locked or unlocked.
+    fn session_lock_changed(&mut self) {
+        let locked = self.is_session_locked();
+        tracing::info!(locked, "Session lock changed");
+
+        self.send_wm(match locked {
+            true => WmEvent::SessionLocked,
+            false => WmEvent::SessionUnlocked,
+        });
+
+        if locked {
+            // The switcher shows thumbnails of toplevels.
+            self.hide_switcher();
+        } else {
+            // The wm was not told about modifiers changed while the session was locked.
+            self.send_key_modifiers();
+        }
+
+        // Toplevels are hidden or shown again.
+        self.scene_changed();
+    }
+
+    /// Set the exclusive grab.
+    ///
+    /// Returns [`false`] if another exclusive grab is already active. The grab is not replaced in that case.
+    pub fn set_exclusive_grab(&mut self, grab: ExclusiveGrab) -> bool {
+        if self.exclusive_grab.is_some() {
+            return false;
+        }
+
+        tracing::debug!(client = ?grab.client, kind = ?grab.kind, "Exclusive input grab started");
+
+        let focus = self
+            .seat
+            .get_keyboard()
+            .and_then(|keyboard| keyboard.current_focus())
+            .and_then(|surface| surface.client())
+            .map(|client| client.id());
+
+        let keep_focus = keeps_focus(&grab, focus.as_ref());
+        let owner = grab.client.clone();
+        self.exclusive_grab = Some(grab);
+
+        if !keep_focus {
+            // The keyboard focus moves to a toplevel of the owner of the grab, if the owner has one.
+            let mut owned = self
+                .shell
+                .toplevels
+                .iter()
+                .filter(|(_, toplevel)| {
+                    toplevel
+                        .wl_surface()
+                        .and_then(|surface| surface.client())
+                        .is_some_and(|client| client.id() == owner)
+                })
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            owned.sort_unstable();
+
+            self.set_keyboard_focus(owned.first().copied());
+        }
+
+        // Sends wl_pointer.leave to the surface under the pointer unless the surface belongs to the owner.
+        self.send_pointer_event(PointerEvent::Motion {
+            time: time(),
+            location: self.pointer_location,
+        });
+
+        true
+    }
+
+    /// Release the exclusive grab if the grab is owned by the client and was created by the same protocol.
+    pub fn release_exclusive_grab(&mut self, client: &ClientId, kind: ExclusiveGrabKind) {
+        if self
+            .exclusive_grab
+            .as_ref()
+            .is_some_and(|grab| grab.is_owned_by(client, kind))
+        {
+            tracing::debug!(?client, ?kind, "Exclusive input grab released");
+            self.exclusive_grab = None;
+            // The wm was not told about modifiers changed while the grab was active.
+            self.send_key_modifiers();
+        }
+    }
+
+    /// Whether the client may receive input events.
+    ///
+    /// While the session is locked, only the screen locker may receive input, even if another client holds an
+    /// exclusive grab. Otherwise if an exclusive grab is active, only the client which owns the grab may receive
+    /// input.
+    pub fn can_receive_input(&self, client: &ClientId) -> bool {
+        may_receive_input(self.session_lock.as_ref(), self.exclusive_grab.as_ref(), client)
+    }
+
+    /// Give the toplevel keyboard focus, or clear the keyboard focus.
+    ///
+    /// The previously focused toplevel is deactivated. Returns [`false`] if the toplevel does not exist, may not
+    /// receive input because of an exclusive grab or the session is locked, in which case the lock surface keeps
+    /// keyboard focus.
+    pub fn set_keyboard_focus(&mut self, focus: Option<ToplevelId>) -> bool {
+        if self.is_session_locked() {
+            return false;
+        }
+
+        let surface = match focus {
+            Some(id) => {
+                let Some(surface) = self.shell.get_state(id).and_then(|toplevel| toplevel.wl_surface()) else {
+                    return false;
+                };
+
+                if !surface
+                    .client()
+                    .is_some_and(|client| self.can_receive_input(&client.id()))
+                {
+                    return false;
+                }
+
+                Some(surface)
+            }
+
+            None => None,
+        };
+
+        if self.keyboard_focus != focus {
+            let now = Instant::now();
+
+            for (id, activated) in [(self.keyboard_focus, false), (focus, true)] {
+                let Some(id) = id else {
+                    continue;
+                };
+
+                if self
+                    .shell
+                    .get_state(id)
+                    .is_some_and(|toplevel| toplevel.set_activated(activated))
+                {
+                    self.configure_watchdog.sent(id, now);
+                }
+            }
+
+            self.keyboard_focus = focus;
+            // Streams of a client with several toplevels belong to the focused toplevel.
+            self.update_audio_streams();
+            self.update_zoom_focus();
+        }
+
+        // A layer surface with exclusive keyboard interactivity keeps keyboard focus until it is unmapped.
+        let surface = self.layer_shell.keyboard_focus().cloned().or(surface);
+        self.set_text_input_focus(surface.as_ref());
+
+        // Sends wl_keyboard.leave to the previous surface and wl_keyboard.enter to the new surface.
+        if let Some(keyboard) = self.seat.get_keyboard() {
+            keyboard.set_focus(self, surface, SERIAL_COUNTER.next_serial());
+        }
+
+        true
+    }
+
+    /// Move the pointer to the location in the global compositor space, such as when the wm warps the pointer.
+    ///
+    /// The location is clamped to the output.
+    pub fn warp_pointer(&mut self, location: Point<f64, Logical>) {
+        self.pointer_input(PointerEvent::Motion { time: time(), location });
+    }
+
+    /// A switch of an input device was toggled.
+    ///
+    /// libinput reports the state of the switches of a device when it is added, so the state may not have changed.
+    /// The wm is only told if the state changed. Returns [`true`] if the state changed.
+    pub fn switch_toggled(&mut self, switch: Switch, on: bool, time: u32) -> bool {
+        let (state, kind) = match switch {
+            Switch::Lid => (&mut self.lid_closed, SwitchKind::Lid),
+            Switch::TabletMode => (&mut self.tablet_mode, SwitchKind::TabletMode),
+        };
+
+        if *state == on {
+            return false;
+        }
+
+        *state = on;
+        self.send_wm(WmEvent::SwitchToggled {
+            seat: self.wm.seat(),
+            time,
+            kind,
+            on,
+        });
+        true
+    }
+
+    /// Whether the compositor and wm may intercept input, such as for key bindings.
+    ///
+    /// This is [`false`] while the session is locked or an exclusive grab is active.
+    pub fn can_intercept_input(&self) -> bool {
+        self.session_lock.is_none() && self.exclusive_grab.is_none()
+    }
+
+    /// Whether input is offered to the wm, which decides whether each event is sent to clients.
+    pub fn wm_filters_input(&self) -> bool {
+        self.wm.is_running() && self.wm_crash.is_none() && self.can_intercept_input()
+    }
+
+    /// Send the input which is waiting for the wm to clients, since the wm will not reply, such as after a crash.
+    pub fn release_held_input(&mut self) {
+        for (key, forward) in self.key_events.release_all() {
+            self.send_key(key, forward);
+        }
+
+        for (event, forward) in self.pointer_events.release_all() {
+            if forward {
+                self.send_pointer_event(event);
+            }
+        }
+    }
+}
+
+impl Loop {
+    /// Handle an event of an input device.
+    pub fn input_event<B: InputBackend>(&mut self, event: InputEvent<B>) {
+        if let Some(latency) = event_time(&event).and_then(latency) {
+            self.comp.input_dispatched(latency);
+        }
+
+        match event {
+            InputEvent::Keyboard { event } => self.keyboard_key(event.key_code(), event.state(), event.time_msec()),
+
+            InputEvent::PointerMotion { event } => {
+                self.idle_input();
+                let location = self.comp.pointer_location + event.delta();
+                self.comp.pointer_input(PointerEvent::Motion {
+                    time: event.time_msec(),
+                    location,
+                });
+            }
+
+            InputEvent::PointerMotionAbsolute { event } => {
+                self.idle_input();
+                let location = self.absolute_location(&event);
+                self.comp.pointer_input(PointerEvent::Motion {
+                    time: event.time_msec(),
+                    location,
+                });
+            }
+
+            InputEvent::PointerButton { event } => {
+                self.idle_input();
+                self.comp.pointer_input(PointerEvent::Button {
+                    time: event.time_msec(),
+                    button: event.button_code(),
+                    state: event.state(),
+                });
+            }
+
+            InputEvent::PointerAxis { event } => {
+                self.idle_input();
+                let amount = |axis| {
+                    event
+                        .amount(axis)
+                        .unwrap_or_else(|| event.amount_discrete(axis).unwrap_or(0.0) * 3.0)
+                };
+                self.comp.pointer_input(PointerEvent::Axis {
+                    time: event.time_msec(),
+                    source: event.source(),
+                    amount: (amount(Axis::Horizontal), amount(Axis::Vertical)),
+                    discrete: (
+                        event.amount_discrete(Axis::Horizontal),
+                        event.amount_discrete(Axis::Vertical),
+                    ),
+                });
+            }
+
+            InputEvent::SwitchToggle { event } => {
+                let switch = match event.switch() {
+                    Some(input::Switch::Lid) => Switch::Lid,
+                    Some(input::Switch::TabletMode) => Switch::TabletMode,
+                    None => return,
+                };
+
+                self.comp
+                    .switch_toggled(switch, event.state() == SwitchState::On, event.time_msec());
+            }
+
+            InputEvent::GestureSwipeBegin { event } => self.gesture_begin(GestureKind::Swipe, &event),
+            InputEvent::GesturePinchBegin { event } => self.gesture_begin(GestureKind::Pinch, &event),
+            InputEvent::GestureHoldBegin { event } => self.gesture_begin(GestureKind::Hold, &event),
+
+            InputEvent::GestureSwipeUpdate { event } => {
+                self.comp.gesture_input(GestureEvent::Update {
+                    time: event.time_msec(),
+                    delta: (event.delta_x(), event.delta_y()).into(),
+                    scale: 1.0,
+                    rotation: 0.0,
+                });
+            }
+
+            InputEvent::GesturePinchUpdate { event } => {
+                self.comp.gesture_input(GestureEvent::Update {
+                    time: event.time_msec(),
+                    delta: (event.delta_x(), event.delta_y()).into(),
+                    scale: event.scale(),
+                    rotation: event.rotation(),
+                });
+            }
+
+            InputEvent::GestureSwipeEnd { event } => self.gesture_end(&event),
+            InputEvent::GesturePinchEnd { event } => self.gesture_end(&event),
+            InputEvent::GestureHoldEnd { event } => self.gesture_end(&event),
+
+            InputEvent::TouchDown { event } => {
+                self.idle_input();
+                let location = self.absolute_location(&event);
+                self.comp.touch_input(TouchEvent::Down {
+                    time: event.time_msec(),
+                    id: i32::from(event.slot()),
+                    location,
+                });
+            }
+
+            InputEvent::TouchMotion { event } => {
+                let location = self.absolute_location(&event);
+                self.comp.touch_input(TouchEvent::Motion {
+                    time: event.time_msec(),
+                    id: i32::from(event.slot()),
+                    location,
+                });
+            }
+
+            InputEvent::TouchUp { event } => {
+                self.comp.touch_input(TouchEvent::Up {
+                    time: event.time_msec(),
+                    id: i32::from(event.slot()),
+                });
+            }
+
+            InputEvent::TouchCancel { .. } => self.comp.touch_input(TouchEvent::Cancel),
+
+            InputEvent::DeviceAdded { device } if device.has_capability(DeviceCapability::Touch) => {
+                self.comp.touch_device_added(device.id());
+            }
+
+            InputEvent::DeviceRemoved { device } if device.has_capability(DeviceCapability::Touch) => {
+                self.comp.touch_device_removed(&device.id());
+            }
+
+            _ => {}
+        }
+
+        self.watch_held_input();
+    }
+
+    /// The location in the global compositor space of an event of an absolute device.
+    ///
+    /// Absolute devices, such as touchscreens or the window of a nested display server, cover the part of the output
+    /// which is shown, so the location follows the zoom.
+    fn absolute_location<B: InputBackend>(&self, event: &impl AbsolutePositionEvent<B>) -> Point<f64, Logical> {
+        let viewport = self.comp.zoom.viewport();
+        self.comp.output.current_location().to_f64()
+            + viewport.loc
+            + Point::from((
+                event.x_transformed(1) * viewport.size.w,
+                event.y_transformed(1) * viewport.size.h,
+            ))
+    }
+
+    fn gesture_begin<B: InputBackend>(&mut self, kind: GestureKind, event: &impl GestureBeginEvent<B>) {
+        self.idle_input();
+        self.comp.gesture_input(GestureEvent::Begin {
+            kind,
+            time: event.time_msec(),
+            fingers: event.fingers(),
+        });
+    }
+
+    fn gesture_end<B: InputBackend>(&mut self, event: &impl GestureEndEvent<B>) {
+        self.comp.gesture_input(GestureEvent::End {
+            time: event.time_msec(),
+            cancelled: event.cancelled(),
+        });
+    }
+
+    /// Release the held input once [`INPUT_TIMEOUT`] elapsed if the wm did not reply to the oldest held event by
+    /// then.
+    fn watch_held_input(&mut self) {
+        if self.input_timeout.is_some() {
+            return;
+        }
+
+        let oldest = (self.comp.key_events.oldest(), self.comp.pointer_events.oldest());
+
+        if oldest == (None, None) {
+            return;
+        }
+
+        let token = self
+            .r#loop
+            .insert_source(Timer::from_duration(INPUT_TIMEOUT), move |_, _, state| {
+                state.input_timeout = None;
+
+                let held = (state.comp.key_events.oldest(), state.comp.pointer_events.oldest());
+
+                if (oldest.0.is_some() && held.0 == oldest.0) || (oldest.1.is_some() && held.1 == oldest.1) {
+                    tracing::warn!("The wm did not reply to input in time, sending the input to clients");
+                    state.comp.release_held_input();
+                }
+
+                // Input which arrived since the timer was set is watched again.
+                state.watch_held_input();
+                TimeoutAction::Drop
+            })
+            .expect("Failed to insert timer");
+
+        self.input_timeout = Some(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixStream, sync::Arc};
+
+    use wayland_server::{backend::ClientData, Display};
+
+    use super::*;
+
+    struct TestClient;
+
+    impl ClientData for TestClient {}
+
+    /// Connect two clients to a display which is never dispatched.
+    fn clients(display: &Display<()>) -> (ClientId, ClientId) {
+        let mut handle = display.handle();
+        let mut connect = || {
+            let (stream, _peer) = UnixStream::pair().unwrap();
+            handle.insert_client(stream, Arc::new(TestClient)).unwrap().id()
+        };
+
+        (connect(), connect())
+    }
+
+    #[test]
+    fn no_grab() {
+        let display = Display::<()>::new().unwrap();
+        let (client, _) = clients(&display);
+
+        assert!(may_receive_input(None, None, &client));
+    }
+
+    #[test]
+    fn grab_owner_receives_input() {
+        let display = Display::<()>::new().unwrap();
+        let (owner, other) = clients(&display);
+        let grab = ExclusiveGrab::new(owner.clone(), ExclusiveGrabKind::InputInhibitor);
+
+        assert!(may_receive_input(None, Some(&grab), &owner));
+        assert!(!may_receive_input(None, Some(&grab), &other));
+    }
+
+    #[test]
+    fn grab_moves_focus_from_other_client() {
+        let display = Display::<()>::new().unwrap();
+        let (owner, other) = clients(&display);
+        let grab = ExclusiveGrab::new(owner.clone(), ExclusiveGrabKind::InputInhibitor);
+
+        // Another client had keyboard focus when the grab started.
+        assert!(!keeps_focus(&grab, Some(&other)));
+        assert!(keeps_focus(&grab, Some(&owner)));
+        assert!(keeps_focus(&grab, None));
+    }
+
+    #[test]
+    fn only_owner_releases_grab() {
+        let display = Display::<()>::new().unwrap();
+        let (owner, other) = clients(&display);
+        let grab = ExclusiveGrab::new(owner.clone(), ExclusiveGrabKind::InputInhibitor);
+
+        assert!(grab.is_owned_by(&owner, ExclusiveGrabKind::InputInhibitor));
+        assert!(!grab.is_owned_by(&other, ExclusiveGrabKind::InputInhibitor));
+    }
+
+    #[test]
+    fn session_lock_takes_precedence_over_grab() {
+        let display = Display::<()>::new().unwrap();
+        let (owner, _) = clients(&display);
+        let grab = ExclusiveGrab::new(owner.clone(), ExclusiveGrabKind::InputInhibitor);
+        // The screen locker died, so no client may receive input until another screen locker takes over.
+        let session_lock = SessionLock {
+            lock: None,
+            surface: None,
+            locked: Locked::Pending,
+        };
+
+        assert!(!may_receive_input(Some(&session_lock), Some(&grab), &owner));
+        assert!(!may_receive_input(Some(&session_lock), None, &owner));
+    }
+
+    #[test]
+    fn filtered_input_is_released_in_order() {
+        let mut queue = FilterQueue::new();
+        queue.hold(1, 'a');
+        queue.hold(2, 'b');
+        queue.hold(3, 'c');
+
+        // The second event waits for the wm to reply to the first.
+        assert!(queue.filter(2, false).is_empty());
+        assert_eq!(queue.oldest(), Some(1));
+        assert_eq!(queue.filter(1, true), [('a', true), ('b', false)]);
+        assert_eq!(queue.oldest(), Some(3));
+
+        // A reply for an event which is not held is ignored.
+        assert!(queue.filter(7, true).is_empty());
+        assert!(!queue.is_empty());
+
+        assert_eq!(queue.filter(3, true), [('c', true)]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn held_input_is_released_after_crash() {
+        let mut queue = FilterQueue::new();
+        queue.hold(1, 'a');
+        queue.hold(2, 'b');
+        queue.filter(2, false);
+
+        assert_eq!(queue.release_all(), [('a', true), ('b', false)]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,334 @@
+//! Interactive move and resize
+//!
+//! Clients request an interactive move or resize when the user drags their client side decorations. The request is
+//! forwarded to the wm, which may start the move or resize, such as after checking the toplevel is not tiled. The wm
+//! may also start a move or resize itself, such as from a pointer binding.
+//!
+//! While a move or resize is active, pointer input is consumed by the display server. The display server computes
+//! the geometry of the toplevel from the motion of the pointer and tells the wm, which places and configures the
+//! toplevel, since the wm decides the geometry of every toplevel. The move or resize ends when every pointer button
+//! is released.
+
+use smithay::{
+    input::Seat,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
+    utils::{Logical, Point, Rectangle, Serial, Size},
+    wayland::{compositor, shell::xdg::SurfaceCachedState},
+};
+use wayland_server::protocol::{wl_seat::WlSeat, wl_surface::WlSurface};
+use wm_runtime::{
+    types::{Geometry, ResizeEdge},
+    ConfigureUpdate, ToplevelUpdate, WmEvent,
+};
+
+use crate::{
+    shell::{Shell, ToplevelId},
+    Aerugo,
+};
+
+/// An interactive move or resize of a toplevel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InteractiveGrab {
+    toplevel: ToplevelId,
+
+    /// The edge being dragged, or [`None`] for a move.
+    edge: Option<ResizeEdge>,
+
+    /// The location of the pointer when the grab started.
+    start: Point<f64, Logical>,
+
+    /// The geometry of the toplevel when the grab started.
+    geometry: Rectangle<i32, Logical>,
+
+    /// The geometry the wm was last told about.
+    current: Rectangle<i32, Logical>,
+}
+
+/// The edge of an xdg resize request as known to the wm.
+pub(crate) fn wm_resize_edge(edge: xdg_toplevel::ResizeEdge) -> Option<ResizeEdge> {
+    match edge {
+        xdg_toplevel::ResizeEdge::Top => Some(ResizeEdge::Top),
+        xdg_toplevel::ResizeEdge::Bottom => Some(ResizeEdge::Bottom),
+        xdg_toplevel::ResizeEdge::Left => Some(ResizeEdge::Left),
+        xdg_toplevel::ResizeEdge::Right => Some(ResizeEdge::Right),
+        xdg_toplevel::ResizeEdge::TopLeft => Some(ResizeEdge::TopLeft),
+        xdg_toplevel::ResizeEdge::TopRight => Some(ResizeEdge::TopRight),
+        xdg_toplevel::ResizeEdge::BottomLeft => Some(ResizeEdge::BottomLeft),
+        xdg_toplevel::ResizeEdge::BottomRight => Some(ResizeEdge::BottomRight),
+        _ => None,
+    }
+}
+
+/// The new length and the change of the start of an axis when its start or end edge is dragged by `delta`.
+///
+/// The length is clamped between `min` and `max`, where 0 means no limit. A dragged start edge keeps the end edge
+/// in place.
+fn resize_axis(length: i32, delta: i32, (start, end): (bool, bool), (min, max): (i32, i32)) -> (i32, i32) {
+    let resized = match (start, end) {
+        (true, false) => length - delta,
+        (false, true) => length + delta,
+        _ => return (length, 0),
+    };
+
+    let mut resized = resized.max(min.max(1));
+
+    if max > 0 {
+        resized = resized.min(max.max(min.max(1)));
+    }
+
+    let moved = if start { length - resized } else { 0 };
+    (resized, moved)
+}
+
+/// The geometry of a toplevel after the pointer moved by `delta` since the grab started.
+fn grab_geometry(
+    geometry: Rectangle<i32, Logical>,
+    edge: Option<ResizeEdge>,
+    delta: Point<i32, Logical>,
+    min_size: Size<i32, Logical>,
+    max_size: Size<i32, Logical>,
+) -> Rectangle<i32, Logical> {
+    let Some(edge) = edge else {
+        return Rectangle::from_loc_and_size(geometry.loc + delta, geometry.size);
+    };
+
+    let left = matches!(edge, ResizeEdge::Left | ResizeEdge::TopLeft | ResizeEdge::BottomLeft);
+    let right = matches!(edge, ResizeEdge::Right | ResizeEdge::TopRight | ResizeEdge::BottomRight);
+    let top = matches!(edge, ResizeEdge::Top | ResizeEdge::TopLeft | ResizeEdge::TopRight);
+    let bottom = matches!(
+        edge,
+        ResizeEdge::Bottom | ResizeEdge::BottomLeft | ResizeEdge::BottomRight
+    );
+
+    let (w, moved_x) = resize_axis(geometry.size.w, delta.x, (left, right), (min_size.w, max_size.w));
+    let (h, moved_y) = resize_axis(geometry.size.h, delta.y, (top, bottom), (min_size.h, max_size.h));
+
+    Rectangle::from_loc_and_size(geometry.loc + Point::from((moved_x, moved_y)), (w, h))
+}
+
+fn wm_geometry(geometry: Rectangle<i32, Logical>) -> Geometry {
+    Geometry {
+        x: geometry.loc.x,
+        y: geometry.loc.y,
+        width: geometry.size.w.max(0) as u32,
+        height: geometry.size.h.max(0) as u32,
+    }
+}
+
+impl Aerugo {
+    /// The client of a toplevel requested an interactive move, or a resize by the edge.
+    ///
+    /// The request is only forwarded to the wm if a button of the pointer of the seat is held since the button
+    /// press with the serial, so clients cannot start a move on their own.
+    pub(crate) fn interactive_grab_requested(
+        &mut self,
+        surface: &WlSurface,
+        seat: &WlSeat,
+        serial: Serial,
+        edge: Option<ResizeEdge>,
+    ) {
+        let Some(id) = Shell::get_toplevel_id(surface) else {
+            return;
+        };
+
+        if Seat::<Self>::from_resource(seat).as_ref() != Some(&self.seat)
+            || !self.seat.get_pointer().is_some_and(|pointer| pointer.has_grab(serial))
+        {
+            tracing::debug!(id, "Ignoring interactive move or resize without a held pointer button");
+            return;
+        }
+
+        let Some(toplevel) = self.wm.toplevel_id(id).filter(|_| self.wm_crash.is_none()) else {
+            return;
+        };
+
+        let update = match edge {
+            Some(edge) => ToplevelUpdate {
+                resize_edge: ConfigureUpdate::Update(Some(edge)),
+                ..ToplevelUpdate::default()
+            },
+            None => ToplevelUpdate {
+                request_move: true,
+                ..ToplevelUpdate::default()
+            },
+        };
+
+        self.send_wm(WmEvent::UpdateToplevel { toplevel, update });
+    }
+
+    /// The wm started an interactive move of the toplevel, or a resize by the edge.
+    ///
+    /// The grab is not started if no pointer button is held, since it would never end.
+    pub(crate) fn start_interactive_grab(&mut self, toplevel: ToplevelId, edge: Option<ResizeEdge>) {
+        if self.pointer_buttons.is_empty() || self.is_session_locked() {
+            tracing::debug!(
+                toplevel,
+                "Not starting interactive move or resize without a held pointer button"
+            );
+            return;
+        }
+
+        let Some(geometry) = self.toplevel_geometry(toplevel) else {
+            return;
+        };
+
+        self.interactive_grab = Some(InteractiveGrab {
+            toplevel,
+            edge,
+            start: self.pointer_location,
+            geometry,
+            current: geometry,
+        });
+    }
+
+    /// The pointer moved while an interactive move or resize is active, which moves or resizes the toplevel.
+    pub(crate) fn interactive_grab_motion(&mut self) {
+        let Some(grab) = self.interactive_grab else {
+            return;
+        };
+
+        let (Some(wm_id), Some(surface)) = (
+            self.wm.toplevel_id(grab.toplevel),
+            self.shell
+                .get_state(grab.toplevel)
+                .and_then(|toplevel| toplevel.wl_surface()),
+        ) else {
+            // The toplevel was closed during the grab.
+            self.interactive_grab = None;
+            return;
+        };
+
+        if self.is_session_locked() {
+            self.interactive_grab = None;
+            return;
+        }
+
+        let (min_size, max_size) = compositor::with_states(&surface, |states| {
+            let cached = states.cached_state.current::<SurfaceCachedState>();
+            (cached.min_size, cached.max_size)
+        });
+
+        let delta = (self.pointer_location - grab.start).to_i32_round();
+        let geometry = grab_geometry(grab.geometry, grab.edge, delta, min_size, max_size);
+
+        if geometry == grab.current {
+            return;
+        }
+
+        self.interactive_grab = Some(InteractiveGrab {
+            current: geometry,
+            ..grab
+        });
+        self.send_wm(WmEvent::UpdateToplevel {
+            toplevel: wm_id,
+            update: ToplevelUpdate {
+                geometry: ConfigureUpdate::Update(Some(wm_geometry(geometry))),
+                ..ToplevelUpdate::default()
+            },
+        });
+    }
+
+    /// The geometry of the toplevel in the global compositor space, as drawn in the scene.
+    pub(crate) fn toplevel_geometry(&self, id: ToplevelId) -> Option<Rectangle<i32, Logical>> {
+        let surface = self.shell.get_state(id)?.wl_surface()?;
+        let index = self.scene.get_surface_tree_index(surface.clone())?;
+        let scale = self.output.current_scale().integer_scale();
+        let drawn = self.scene.surface_tree_geometry(index)?.to_logical(scale);
+
+        // The window geometry excludes client side shadows.
+        let window_geometry = compositor::with_states(&surface, |states| {
+            states.cached_state.current::<SurfaceCachedState>().geometry
+        })
+        .unwrap_or(Rectangle::from_loc_and_size((0, 0), drawn.size));
+
+        Some(Rectangle::from_loc_and_size(
+            self.output.current_location() + drawn.loc + window_geometry.loc,
+            window_geometry.size,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::Rectangle;
+    use wm_runtime::types::ResizeEdge;
+
+    use super::grab_geometry;
+
+    #[test]
+    fn move_follows_pointer() {
+        let geometry = Rectangle::from_loc_and_size((100, 100), (640, 480));
+
+        assert_eq!(
+            grab_geometry(geometry, None, (30, -20).into(), (0, 0).into(), (0, 0).into()),
+            Rectangle::from_loc_and_size((130, 80), (640, 480))
+        );
+    }
+
+    #[test]
+    fn resize_keeps_opposite_edge() {
+        let geometry = Rectangle::from_loc_and_size((100, 100), (640, 480));
+
+        assert_eq!(
+            grab_geometry(
+                geometry,
+                Some(ResizeEdge::BottomRight),
+                (30, 20).into(),
+                (0, 0).into(),
+                (0, 0).into()
+            ),
+            Rectangle::from_loc_and_size((100, 100), (670, 500))
+        );
+
+        // Dragging the top left corner moves the toplevel so the bottom right corner stays.
+        assert_eq!(
+            grab_geometry(
+                geometry,
+                Some(ResizeEdge::TopLeft),
+                (40, -10).into(),
+                (0, 0).into(),
+                (0, 0).into()
+            ),
+            Rectangle::from_loc_and_size((140, 90), (600, 490))
+        );
+
+        // Only the dragged axis changes.
+        assert_eq!(
+            grab_geometry(
+                geometry,
+                Some(ResizeEdge::Left),
+                (10, 50).into(),
+                (0, 0).into(),
+                (0, 0).into()
+            ),
+            Rectangle::from_loc_and_size((110, 100), (630, 480))
+        );
+    }
+
+    #[test]
+    fn resize_respects_size_limits() {
+        let geometry = Rectangle::from_loc_and_size((100, 100), (640, 480));
+
+        assert_eq!(
+            grab_geometry(
+                geometry,
+                Some(ResizeEdge::TopLeft),
+                (600, 600).into(),
+                (200, 150).into(),
+                (0, 0).into()
+            ),
+            Rectangle::from_loc_and_size((540, 430), (200, 150))
+        );
+
+        assert_eq!(
+            grab_geometry(
+                geometry,
+                Some(ResizeEdge::Right),
+                (500, 0).into(),
+                (0, 0).into(),
+                (800, 600).into()
+            ),
+            Rectangle::from_loc_and_size((100, 100), (800, 480))
+        );
+    }
+}
@@ -0,0 +1,1026 @@
+//! IPC authentication
+//!
+//! Clients of the IPC socket (status bars, configuration tools and screen capture tools) are given a permission
+//! [`Tier`] when they connect. Each command requires a tier, so a status bar can subscribe to events without
+//! also being able to change the configuration or start capturing the screen.
+//!
+//! Only processes running as the same user as the display server may connect. A connection may present a token
+//! to be granted a higher tier. If no token is configured for the configuration tier, every connection from the
+//! same user may change the configuration. Privileged commands are only available if a privileged token is
+//! configured.
+//!
+//! # Wire format
+//!
+//! The socket is at the path in [`SOCKET_ENV`], which is exported to every child process. Commands and replies
+//! are lines of UTF-8 text, with arguments separated by spaces. The first line a client sends is `auth` or
+//! `auth <token>`, which is answered with the granted tier, or with an error after which the connection is closed.
+//! Every following line is a [`Command`] and answered with one line holding the [`Reply`] or an error. The
+//! `aerugo-msg` binary sends commands with a [`Client`]. With a configure token set, a connection without the token
+//! may only read:
+//!
+//! ```text
+//! > auth
+//! < ok read
+//! > wakeups
+//! < wakeups 1024
+//! > list-bindings
+//! < bindings 2
+//! < default logo+Return trigger:1 wm
+//! < * alt+Tab switcher switcher
+//! > restart
+//! < error the command requires the configure tier
+//! ```
+//!
+//! While a connection with the token may change the configuration:
+//!
+//! ```text
+//! > auth hunter2
+//! < ok configure
+//! > force-close 4
+//! < killed true
+//! > cursor-theme Adwaita 32
+//! < cursor-theme Adwaita 32
+//! > color-transform 4500 0.8 none
+//! < color-transform 4500 0.8 none
+//! > debug-report /tmp/report.tar
+//! < reported /tmp/report.tar
+//! ```
+//!
+//! # Events
+//!
+//! After `subscribe`, every [`Event`] is sent to the connection as a line starting with `event`, so a status bar
+//! can follow the active workspace and binding mode without polling:
+//!
+//! ```text
+//! > subscribe
+//! < subscribed
+//! < event workspace web
+//! < event mode resize
+//! ```
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    os::{
+        fd::AsFd,
+        unix::net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction};
+use smithay::input::keyboard::xkb;
+use wm_runtime::{
+    types::{KeyModifiers, PointerTrigger, ScrollDirection},
+    Action, BindingInfo, BindingOwner, Trigger, WmEvent,
+};
+
+use crate::{backend::color::ColorTransform, cursor, protocol_log::Filter, shell::ToplevelId, Aerugo, Loop};
+
+/// The environment variable holding the path of the IPC socket.
+pub const SOCKET_ENV: &str = "AERUGO_SOCKET";
+
+/// The longest line a client may send, so a client cannot make the display server buffer without bound.
+const MAX_LINE: usize = 4096;
+
+/// The permissions of an IPC connection.
+///
+/// Each tier includes the permissions of the lower tiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Tier {
+    /// Query state and subscribe to events, such as workspace and binding mode changes.
+    Read,
+
+    /// Change the configuration, such as output modes and key bindings.
+    Configure,
+
+    /// Control privileged features, such as screen capture.
+    Privileged,
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Read => "read",
+            Self::Configure => "configure",
+            Self::Privileged => "privileged",
+        })
+    }
+}
+
+impl Tier {
+    /// Whether a connection with this tier may use a command requiring the `required` tier.
+    pub fn allows(self, required: Tier) -> bool {
+        self >= required
+    }
+}
+
+/// A command sent over the IPC socket.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// List the toplevels which are not responding.
+    ListUnresponsive,
+
+    /// Disconnect the client of a toplevel which is not responding, or ask the toplevel to close if it is
+    /// responding.
+    ForceClose(ToplevelId),
+
+    /// Exit so another instance of the display server started with `--replace` can take over the session.
+    ///
+    /// The reply is sent before the display server exits. The new instance waits for the connection to be closed.
+    Replace,
+
+    /// Execute the display server binary in place of the running process, such as after an upgrade.
+    ///
+    /// The reply is sent before the display server restarts. See [`crate::handoff`] for which clients survive.
+    Restart,
+
+    /// Log the protocol messages matching the filter and stream them over the connection until it is closed.
+    ///
+    /// Messages may contain what the user types, so streaming them is privileged. Only one filter is active, so a
+    /// later command changes the filter of every connection streaming the log.
+    LogProtocol(Filter),
+
+    /// Query how many times the event loop has woken up, such as to check nothing wakes the display server while
+    /// it should be idle.
+    Wakeups,
+
+    /// Write a [`DebugReport`](crate::report::DebugReport) of the display server to the absolute path, such as to
+    /// attach it to an issue.
+    ///
+    /// The path is the rest of the line, so it may contain spaces.
+    DebugReport(PathBuf),
+
+    /// List every key and pointer binding of the wm with its owner, such as to check whether a key is taken.
+    ///
+    /// Each binding is a line following the reply, formatted by [`binding_line`].
+    ListBindings,
+
+    /// Send every following [`Event`] over the connection until it is closed.
+    Subscribe,
+
+    /// Change the cursor theme and the cursor size in logical pixels.
+    ///
+    /// The theme is also exported to clients spawned afterwards, so cursors drawn by clients match.
+    CursorTheme(String, u32),
+
+    /// Change the color temperature in kelvin, the brightness between 0.0 and 1.0 and the color filter of the
+    /// output, such as from a night light daemon.
+    ColorTransform(ColorTransform),
+}
+
+impl Command {
+    /// Parse a line sent by a client.
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let mut args = line.split_whitespace();
+        let name = args.next().unwrap_or_default();
+        let command = match name {
+            "list-unresponsive" => Self::ListUnresponsive,
+            "force-close" => {
+                let id = args.next().and_then(|id| id.parse().ok());
+                Self::ForceClose(id.ok_or_else(|| Error::Invalid(line.into()))?)
+            }
+            "replace" => Self::Replace,
+            "restart" => Self::Restart,
+            "log-protocol" => {
+                let filter = Filter::parse(&mut args).ok_or_else(|| Error::Invalid(line.into()))?;
+                return Ok(Self::LogProtocol(filter));
+            }
+            "wakeups" => Self::Wakeups,
+            "debug-report" => {
+                let path = line.trim_start()[name.len()..].trim();
+
+                if !Path::new(path).is_absolute() {
+                    return Err(Error::Invalid(line.into()));
+                }
+
+                return Ok(Self::DebugReport(path.into()));
+            }
+            "list-bindings" => Self::ListBindings,
+            "subscribe" => Self::Subscribe,
+            "cursor-theme" => {
+                let theme = args.next().ok_or_else(|| Error::Invalid(line.into()))?;
+                let size = args
+                    .next()
+                    .and_then(|size| size.parse().ok())
+                    .and_then(|size| cursor::check_size(size).ok());
+                Self::CursorTheme(theme.into(), size.ok_or_else(|| Error::Invalid(line.into()))?)
+            }
+            "color-transform" => {
+                let transform = match (args.next(), args.next(), args.next()) {
+                    (Some(temperature), Some(brightness), Some(filter)) => {
+                        ColorTransform::parse(temperature, brightness, filter)
+                    }
+                    _ => None,
+                };
+                Self::ColorTransform(transform.ok_or_else(|| Error::Invalid(line.into()))?)
+            }
+            _ => return Err(Error::Unknown(name.into())),
+        };
+
+        match args.next() {
+            Some(_) => Err(Error::Invalid(line.into())),
+            None => Ok(command),
+        }
+    }
+
+    /// The tier required to use the command.
+    pub fn tier(&self) -> Tier {
+        match self {
+            Self::ListUnresponsive | Self::Wakeups | Self::ListBindings | Self::Subscribe => Tier::Read,
+            Self::ForceClose(_)
+            | Self::Replace
+            | Self::Restart
+            | Self::DebugReport(_)
+            | Self::CursorTheme(..)
+            | Self::ColorTransform(_) => Tier::Configure,
+            Self::LogProtocol(_) => Tier::Privileged,
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ListUnresponsive => f.write_str("list-unresponsive"),
+            Self::ForceClose(id) => write!(f, "force-close {id}"),
+            Self::Replace => f.write_str("replace"),
+            Self::Restart => f.write_str("restart"),
+            Self::LogProtocol(filter) => write!(f, "log-protocol {filter}"),
+            Self::Wakeups => f.write_str("wakeups"),
+            Self::DebugReport(path) => write!(f, "debug-report {}", path.display()),
+            Self::ListBindings => f.write_str("list-bindings"),
+            Self::Subscribe => f.write_str("subscribe"),
+            Self::CursorTheme(theme, size) => write!(f, "cursor-theme {theme} {size}"),
+            Self::ColorTransform(transform) => write!(f, "color-transform {transform}"),
+        }
+    }
+}
+
+/// The reply to a [`Command`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Toplevels(Vec<ToplevelId>),
+
+    /// Whether the client was disconnected.
+    Killed(bool),
+
+    /// The display server is exiting to be replaced.
+    Replacing,
+
+    /// The display server is restarting in place.
+    Restarting,
+
+    /// The protocol log is enabled, and every following line is a logged message.
+    Logging,
+
+    /// The number of times the event loop has woken up.
+    Wakeups(u64),
+
+    /// The debug report was written to the path.
+    Reported(PathBuf),
+
+    /// The bindings were requested from the wm, and are sent to the connection once the wm replies.
+    ListingBindings,
+
+    /// Every binding, each formatted by [`binding_line`].
+    Bindings(Vec<String>),
+
+    /// Every following line is an event.
+    Subscribed,
+
+    /// The cursor theme and size to change to, which is applied by the connection since the environment of child
+    /// processes is owned by the event loop.
+    CursorTheme(String, u32),
+
+    /// The color transform of the output changed.
+    ColorTransform(ColorTransform),
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Toplevels(ids) => {
+                f.write_str("toplevels")?;
+                ids.iter().try_for_each(|id| write!(f, " {id}"))
+            }
+            Self::Killed(killed) => write!(f, "killed {killed}"),
+            Self::Replacing => f.write_str("replacing"),
+            Self::Restarting => f.write_str("restarting"),
+            Self::Logging => f.write_str("logging"),
+            Self::Wakeups(count) => write!(f, "wakeups {count}"),
+            Self::Reported(path) => write!(f, "reported {}", path.display()),
+            Self::ListingBindings => f.write_str("listing-bindings"),
+            Self::Bindings(lines) => {
+                write!(f, "bindings {}", lines.len())?;
+                lines.iter().try_for_each(|line| write!(f, "\n{line}"))
+            }
+            Self::Subscribed => f.write_str("subscribed"),
+            Self::CursorTheme(theme, size) => write!(f, "cursor-theme {theme} {size}"),
+            Self::ColorTransform(transform) => write!(f, "color-transform {transform}"),
+        }
+    }
+}
+
+/// A change sent to the connections which subscribed to events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The workspace with the name was activated.
+    Workspace(String),
+
+    /// The binding mode with the name became the active mode.
+    Mode(String),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Workspace(name) => write!(f, "event workspace {name}"),
+            Self::Mode(name) => write!(f, "event mode {name}"),
+        }
+    }
+}
+
+/// An error from authenticating an IPC connection or running a command.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("the credentials of the connection could not be read")]
+    NoCredentials,
+
+    #[error("uid {0} does not match the uid of the display server")]
+    Uid(u32),
+
+    #[error("the token is not valid")]
+    InvalidToken,
+
+    #[error("the command requires the {0} tier")]
+    Denied(Tier),
+
+    #[error("unknown command {0:?}")]
+    Unknown(String),
+
+    #[error("invalid arguments in {0:?}")]
+    Invalid(String),
+
+    #[error("the debug report could not be written: {0}")]
+    Report(String),
+}
+
+/// The tokens granting higher tiers, from the `[ipc]` section of the configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpcTokens {
+    /// The token granting [`Tier::Configure`].
+    pub configure: Option<String>,
+
+    /// The token granting [`Tier::Privileged`].
+    pub privileged: Option<String>,
+}
+
+/// Authentication settings of the IPC socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpcAuth {
+    uid: u32,
+    configure_token: Option<String>,
+    privileged_token: Option<String>,
+}
+
+impl IpcAuth {
+    /// Only allow connections from the user.
+    pub fn new(uid: u32) -> Self {
+        Self {
+            uid,
+            configure_token: None,
+            privileged_token: None,
+        }
+    }
+
+    /// Only allow connections from the user running the display server.
+    pub fn current_user() -> Self {
+        Self::new(rustix::process::getuid().as_raw())
+    }
+
+    /// Require a token to change the configuration.
+    ///
+    /// Connections without a token may only use [`Tier::Read`] commands. The privileged token is also accepted.
+    pub fn with_configure_token(mut self, token: String) -> Self {
+        self.configure_token = Some(token);
+        self
+    }
+
+    /// Set the token required to use privileged commands.
+    ///
+    /// If no token is set, privileged commands are not available over IPC.
+    pub fn with_privileged_token(mut self, token: String) -> Self {
+        self.privileged_token = Some(token);
+        self
+    }
+
+    /// Require the tokens which are set.
+    pub fn with_tokens(mut self, tokens: IpcTokens) -> Self {
+        if let Some(token) = tokens.configure {
+            self = self.with_configure_token(token);
+        }
+
+        if let Some(token) = tokens.privileged {
+            self = self.with_privileged_token(token);
+        }
+
+        self
+    }
+
+    /// Authenticate a connection to the IPC socket.
+    ///
+    /// The token is sent by the client after connecting.
+    pub fn authenticate(&self, socket: impl AsFd, token: Option<&str>) -> Result<Tier, Error> {
+        let credentials = rustix::net::sockopt::get_socket_peercred(socket).map_err(|_| Error::NoCredentials)?;
+        self.authenticate_uid(credentials.uid.as_raw(), token)
+    }
+
+    fn authenticate_uid(&self, uid: u32, token: Option<&str>) -> Result<Tier, Error> {
+        if uid != self.uid {
+            return Err(Error::Uid(uid));
+        }
+
+        let matches = |expected: &Option<String>, token: &str| {
+            expected
+                .as_deref()
+                .is_some_and(|expected| constant_time_eq(expected.as_bytes(), token.as_bytes()))
+        };
+
+        match token {
+            Some(token) if matches(&self.privileged_token, token) => Ok(Tier::Privileged),
+            Some(token) if matches(&self.configure_token, token) => Ok(Tier::Configure),
+            Some(_) => Err(Error::InvalidToken),
+            None if self.configure_token.is_some() => Ok(Tier::Read),
+            None => Ok(Tier::Configure),
+        }
+    }
+}
+
+/// Compare tokens without returning early, so the time taken does not reveal how much of the token matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+impl Aerugo {
+    /// Run a command from an IPC connection with the tier.
+    pub fn ipc_command(&mut self, tier: Tier, command: Command) -> Result<Reply, Error> {
+        if !tier.allows(command.tier()) {
+            return Err(Error::Denied(command.tier()));
+        }
+
+        Ok(match command {
+            Command::ListUnresponsive => {
+                let mut ids = self.configure_watchdog.unresponsive().copied().collect::<Vec<_>>();
+                ids.sort_unstable();
+                Reply::Toplevels(ids)
+            }
+            Command::ForceClose(id) => Reply::Killed(self.force_close(id)),
+            Command::Replace => {
+                tracing::info!("Exiting to be replaced by another instance");
+                self.shutdown_requested = true;
+                Reply::Replacing
+            }
+            Command::Restart => {
+                self.restart_requested = true;
+                Reply::Restarting
+            }
+            Command::LogProtocol(filter) => {
+                tracing::info!(%filter, "Protocol log enabled");
+                self.protocol_log.enable(filter);
+                Reply::Logging
+            }
+            Command::Wakeups => Reply::Wakeups(self.wakeups.load(Ordering::Relaxed)),
+            Command::DebugReport(path) => {
+                File::create(&path)
+                    .and_then(|file| self.debug_report().write_tar(BufWriter::new(file)))
+                    .map_err(|err| Error::Report(err.to_string()))?;
+
+                tracing::info!(?path, "Debug report written");
+                Reply::Reported(path)
+            }
+            // Without a wm there is nothing to reply, so there are no bindings.
+            Command::ListBindings if !self.wm.is_running() => Reply::Bindings(Vec::new()),
+            Command::ListBindings => {
+                self.send_wm(WmEvent::ListBindings);
+                Reply::ListingBindings
+            }
+            Command::Subscribe => Reply::Subscribed,
+            Command::CursorTheme(theme, size) => Reply::CursorTheme(theme, size),
+            Command::ColorTransform(transform) => {
+                self.set_color_transform(transform);
+                Reply::ColorTransform(transform)
+            }
+        })
+    }
+
+    /// Send the event to the IPC connections which subscribed to events.
+    pub(crate) fn ipc_event(&mut self, event: Event) {
+        self.ipc_events.push(event);
+    }
+}
+
+/// Format a binding as a line of [`Reply::Bindings`].
+///
+/// The line holds the mode of the binding (`*` if it is matched in every mode), the trigger, the action and the
+/// owner, separated by spaces: `resize ctrl+Escape exit-mode wm`.
+pub fn binding_line(binding: &BindingInfo) -> String {
+    let mode = binding.mode.as_deref().unwrap_or("*");
+
+    let (modifiers, trigger) = match &binding.trigger {
+        Trigger::Key(combo) => (combo.modifiers, xkb::keysym_get_name(combo.sym)),
+        Trigger::Pointer(combo) => {
+            let trigger = match combo.trigger {
+                PointerTrigger::Button(button) => format!("button:{button}"),
+                PointerTrigger::Scroll(direction) => format!(
+                    "scroll:{}",
+                    match direction {
+                        ScrollDirection::Up => "up",
+                        ScrollDirection::Down => "down",
+                        ScrollDirection::Left => "left",
+                        ScrollDirection::Right => "right",
+                    }
+                ),
+            };
+
+            (combo.modifiers, trigger)
+        }
+    };
+
+    // Lock modifiers are ignored when matching bindings.
+    let modifiers = [
+        (KeyModifiers::CTRL, "ctrl"),
+        (KeyModifiers::ALT, "alt"),
+        (KeyModifiers::SHIFT, "shift"),
+        (KeyModifiers::LOGO, "logo"),
+    ]
+    .into_iter()
+    .filter(|(modifier, _)| modifiers.contains(*modifier))
+    .map(|(_, name)| format!("{name}+"))
+    .collect::<String>();
+
+    let action = match &binding.action {
+        Some(Action::EnterMode(mode)) => format!("mode:{mode}"),
+        Some(Action::ExitMode) => "exit-mode".into(),
+        Some(Action::Trigger(id)) => format!("trigger:{id}"),
+        None => "switcher".into(),
+    };
+
+    let owner = match binding.owner {
+        BindingOwner::Wm => "wm",
+        BindingOwner::Switcher => "switcher",
+    };
+
+    format!("{mode} {modifiers}{trigger} {action} {owner}")
+}
+
+/// The path of the IPC socket of the display server listening on the Wayland socket.
+pub fn socket_path(wayland_socket: &str) -> Option<PathBuf> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")?;
+    Some(Path::new(&runtime_dir).join(format!("aerugo.{wayland_socket}.sock")))
+}
+
+/// The path of the IPC socket of the display server the current process runs in, from [`SOCKET_ENV`] or else from
+/// `WAYLAND_DISPLAY`.
+pub fn running_socket_path() -> Option<PathBuf> {
+    std::env::var_os(SOCKET_ENV)
+        .map(PathBuf::from)
+        .or_else(|| socket_path(&std::env::var("WAYLAND_DISPLAY").ok()?))
+}
+
+/// A connection to the IPC socket of a running display server.
+#[derive(Debug)]
+pub struct Client {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl Client {
+    /// Connect to the socket at the path, presenting the token to be granted a higher tier.
+    pub fn connect(path: &Path, token: Option<&str>) -> io::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        let mut client = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+        };
+
+        let auth = match token {
+            Some(token) => client.request_line(&format!("auth {token}"))?,
+            None => client.request_line("auth")?,
+        };
+
+        if !auth.starts_with("ok ") {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, auth));
+        }
+
+        Ok(client)
+    }
+
+    /// Send the command and read the first line of the reply.
+    ///
+    /// An error reply is returned as an error.
+    pub fn request(&mut self, command: &Command) -> io::Result<String> {
+        let reply = self.request_line(&command.to_string())?;
+
+        match reply.strip_prefix("error ") {
+            Some(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
+            None => Ok(reply),
+        }
+    }
+
+    /// Read the next line, such as a line following a reply or an event, or [`None`] once the connection is closed.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+
+        match self.reader.read_line(&mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line.trim_end().to_owned())),
+        }
+    }
+
+    fn request_line(&mut self, line: &str) -> io::Result<String> {
+        writeln!(&self.stream, "{line}")?;
+        self.read_line()?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "the display server closed the connection"))
+    }
+}
+
+/// Ask the display server listening on the IPC socket to exit so another instance can take over the session.
+///
+/// Returns once the display server has exited, which closes the connection.
+pub fn replace(path: &Path) -> io::Result<()> {
+    let mut client = Client::connect(path, None)?;
+
+    let reply = client.request(&Command::Replace)?;
+    if reply != Reply::Replacing.to_string() {
+        return Err(io::Error::new(io::ErrorKind::Other, reply));
+    }
+
+    while client.read_line()?.is_some() {}
+    Ok(())
+}
+
+/// The IPC socket, which is removed when the display server exits.
+#[derive(Debug)]
+struct IpcSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl AsFd for IpcSocket {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+}
+
+impl Drop for IpcSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Listen for IPC connections on the socket at the path.
+///
+/// The socket is named after the Wayland socket, whose lock is held, so a socket at the path was left behind by a
+/// display server which did not exit cleanly or which restarted in place, and is replaced.
+pub(crate) fn register_socket(r#loop: &LoopHandle<'static, Loop>, path: PathBuf, auth: IpcAuth) -> io::Result<()> {
+    match std::fs::remove_file(&path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => (),
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    listener.set_nonblocking(true)?;
+    tracing::info!(?path, "Listening for IPC connections");
+
+    r#loop
+        .insert_source(
+            Generic::new(IpcSocket { listener, path }, Interest::READ, Mode::Level),
+            move |_, socket, state| {
+                loop {
+                    let stream = match socket.as_ref().listener.accept() {
+                        Ok((stream, _)) => stream,
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(err) => return Err(err),
+                    };
+
+                    if let Err(err) = register_connection(&state.r#loop, stream, auth.clone()) {
+                        tracing::warn!(%err, "Failed to accept IPC connection");
+                    }
+                }
+
+                Ok(PostAction::Continue)
+            },
+        )
+        .map_err(|err| err.error)?;
+
+    Ok(())
+}
+
+/// An IPC connection.
+#[derive(Debug)]
+struct Connection {
+    auth: IpcAuth,
+    /// The tier of the connection, or [`None`] until the client authenticated.
+    tier: Option<Tier>,
+    /// Bytes received after the last complete line.
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    /// Handle a line from the client.
+    ///
+    /// Returns the reply, or [`None`] if the reply is sent later, and whether the connection is closed after
+    /// sending the reply.
+    fn handle(&mut self, state: &mut Loop, stream: &UnixStream, line: &str) -> (Option<String>, bool) {
+        let Some(tier) = self.tier else {
+            let token = match line.split_once(' ') {
+                Some(("auth", token)) => Some(token),
+                _ if line == "auth" => None,
+                _ => return (Some(format!("error expected auth, got {line:?}")), true),
+            };
+
+            return match self.auth.authenticate(stream, token) {
+                Ok(tier) => {
+                    self.tier = Some(tier);
+                    (Some(format!("ok {tier}")), false)
+                }
+                Err(err) => (Some(format!("error {err}")), true),
+            };
+        };
+
+        let reply = match Command::parse(line).and_then(|command| state.comp.ipc_command(tier, command)) {
+            Ok(Reply::Logging) => match stream.try_clone() {
+                Ok(stream) => {
+                    state.protocol_log_streams.push(stream);
+                    Reply::Logging.to_string()
+                }
+                Err(err) => format!("error {err}"),
+            },
+            Ok(Reply::Subscribed) => match stream.try_clone() {
+                Ok(stream) => {
+                    state.event_streams.push(stream);
+                    Reply::Subscribed.to_string()
+                }
+                Err(err) => format!("error {err}"),
+            },
+            Ok(Reply::ListingBindings) => match stream.try_clone() {
+                Ok(stream) => {
+                    state.binding_list_streams.push(stream);
+                    return (None, false);
+                }
+                Err(err) => format!("error {err}"),
+            },
+            Ok(Reply::CursorTheme(theme, size)) => {
+                state.set_cursor_theme(&theme, size);
+                Reply::CursorTheme(theme, size).to_string()
+            }
+            Ok(reply) => reply.to_string(),
+            Err(err) => format!("error {err}"),
+        };
+
+        (Some(reply), false)
+    }
+}
+
+fn register_connection(r#loop: &LoopHandle<'static, Loop>, stream: UnixStream, auth: IpcAuth) -> io::Result<()> {
+    // Replies are short, so a client which does not read them is disconnected instead of blocking the event loop.
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+
+    let mut connection = Connection {
+        auth,
+        tier: None,
+        buffer: Vec::new(),
+    };
+
+    r#loop
+        .insert_source(
+            Generic::new(stream, Interest::READ, Mode::Level),
+            move |_, stream, state| {
+                let mut stream = stream.as_ref();
+                let mut buffer = [0; 1024];
+
+                let read = match stream.read(&mut buffer) {
+                    Ok(0) => return Ok(PostAction::Remove),
+                    Ok(read) => read,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => return Ok(PostAction::Continue),
+                    Err(err) => return Err(err),
+                };
+                connection.buffer.extend_from_slice(&buffer[..read]);
+
+                while let Some(end) = connection.buffer.iter().position(|&b| b == b'\n') {
+                    let line = connection.buffer.drain(..=end).collect::<Vec<_>>();
+                    let line = String::from_utf8_lossy(&line[..end]);
+                    let (reply, close) = connection.handle(state, stream, line.trim());
+
+                    if let Some(reply) = reply {
+                        writeln!(stream, "{reply}")?;
+                    }
+
+                    if close {
+                        return Ok(PostAction::Remove);
+                    }
+                }
+
+                if connection.buffer.len() > MAX_LINE {
+                    let _ = writeln!(stream, "error the line is too long");
+                    return Ok(PostAction::Remove);
+                }
+
+                Ok(PostAction::Continue)
+            },
+        )
+        .map_err(|err| err.error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use wm_runtime::{
+        types::{KeyModifiers, PointerCombo, PointerTrigger, ScrollDirection},
+        Action, BindingInfo, BindingOwner, KeyCombo, Trigger,
+    };
+
+    use super::{binding_line, Command, Error, Event, Filter, IpcAuth, Reply, Tier};
+    use crate::{
+        backend::color::{ColorFilter, ColorTransform},
+        test_util::toplevel,
+    };
+
+    #[test]
+    fn tiers_are_ordered() {
+        assert!(Tier::Privileged.allows(Tier::Configure));
+        assert!(Tier::Configure.allows(Tier::Read));
+        assert!(!Tier::Read.allows(Tier::Configure));
+        assert!(!Tier::Configure.allows(Tier::Privileged));
+    }
+
+    #[test]
+    fn other_users_rejected() {
+        let auth = IpcAuth::new(1000).with_privileged_token("secret".into());
+
+        assert_eq!(auth.authenticate_uid(1001, None), Err(Error::Uid(1001)));
+        assert_eq!(auth.authenticate_uid(0, Some("secret")), Err(Error::Uid(0)));
+    }
+
+    #[test]
+    fn tokens() {
+        let open = IpcAuth::new(1000).with_privileged_token("capture".into());
+        assert_eq!(open.authenticate_uid(1000, None), Ok(Tier::Configure));
+        assert_eq!(open.authenticate_uid(1000, Some("capture")), Ok(Tier::Privileged));
+        assert_eq!(open.authenticate_uid(1000, Some("captur")), Err(Error::InvalidToken));
+
+        let locked = IpcAuth::new(1000).with_configure_token("config".into());
+        assert_eq!(locked.authenticate_uid(1000, None), Ok(Tier::Read));
+        assert_eq!(locked.authenticate_uid(1000, Some("config")), Ok(Tier::Configure));
+        // Without a privileged token, privileged commands are never available.
+        assert_eq!(locked.authenticate_uid(1000, Some("")), Err(Error::InvalidToken));
+    }
+
+    #[test]
+    fn command_tiers() {
+        let force_close = Command::ForceClose(toplevel(1));
+
+        assert!(Tier::Read.allows(Command::ListUnresponsive.tier()));
+        assert!(Tier::Read.allows(Command::Wakeups.tier()));
+        assert!(!Tier::Read.allows(force_close.tier()));
+        assert!(Tier::Configure.allows(force_close.tier()));
+        assert!(!Tier::Read.allows(Command::Replace.tier()));
+        assert!(!Tier::Read.allows(Command::Restart.tier()));
+        assert!(Tier::Read.allows(Command::Subscribe.tier()));
+        assert!(!Tier::Read.allows(Command::ColorTransform(ColorTransform::IDENTITY).tier()));
+        assert_eq!(
+            Error::Denied(Command::Restart.tier()).to_string(),
+            "the command requires the configure tier"
+        );
+
+        // Logged messages may contain what the user types, so only the privileged token may stream them.
+        let log = Command::LogProtocol(Filter::parse("*".split(' ')).unwrap());
+        assert!(!Tier::Configure.allows(log.tier()));
+        assert!(Tier::Privileged.allows(log.tier()));
+
+        let auth = IpcAuth::new(1000).with_privileged_token("capture".into());
+        let tier = auth.authenticate_uid(1000, Some("capture")).unwrap();
+        assert!(tier.allows(log.tier()));
+    }
+
+    #[test]
+    fn parse_commands() {
+        let id = toplevel(4);
+
+        for command in [
+            Command::ListUnresponsive,
+            Command::ForceClose(id),
+            Command::Replace,
+            Command::Restart,
+            Command::LogProtocol(Filter::parse("exe=foot xdg_*".split(' ')).unwrap()),
+            Command::Wakeups,
+            Command::DebugReport("/tmp/aerugo report.tar".into()),
+            Command::ListBindings,
+            Command::Subscribe,
+            Command::CursorTheme("Adwaita".into(), 32),
+            Command::ColorTransform(ColorTransform {
+                temperature: 4500,
+                brightness: 0.8,
+                filter: ColorFilter::Grayscale,
+            }),
+        ] {
+            assert_eq!(Command::parse(&command.to_string()), Ok(command));
+        }
+
+        assert_eq!(Command::parse("force-close  4 "), Ok(Command::ForceClose(id)));
+        assert_eq!(
+            Command::parse("force-close 0"),
+            Err(Error::Invalid("force-close 0".into()))
+        );
+        assert_eq!(Command::parse("restart now"), Err(Error::Invalid("restart now".into())));
+        assert_eq!(Command::parse("exit"), Err(Error::Unknown("exit".into())));
+        assert_eq!(
+            Command::parse("cursor-theme Adwaita 0"),
+            Err(Error::Invalid("cursor-theme Adwaita 0".into()))
+        );
+        assert_eq!(
+            Command::parse("color-transform 6500 1 none"),
+            Ok(Command::ColorTransform(ColorTransform::IDENTITY))
+        );
+        assert_eq!(
+            Command::parse("color-transform 500 1 none"),
+            Err(Error::Invalid("color-transform 500 1 none".into()))
+        );
+        assert_eq!(
+            Command::parse("color-transform 4500 1.5 none"),
+            Err(Error::Invalid("color-transform 4500 1.5 none".into()))
+        );
+        assert_eq!(
+            Command::parse("color-transform 4500 1 sepia"),
+            Err(Error::Invalid("color-transform 4500 1 sepia".into()))
+        );
+        assert_eq!(
+            Command::parse("debug-report report.tar"),
+            Err(Error::Invalid("debug-report report.tar".into()))
+        );
+        assert_eq!(
+            Command::parse("log-protocol"),
+            Err(Error::Invalid("log-protocol".into()))
+        );
+    }
+
+    #[test]
+    fn format_replies() {
+        let ids = [1, 3].map(toplevel).to_vec();
+
+        assert_eq!(Reply::Toplevels(ids).to_string(), "toplevels 1 3");
+        assert_eq!(Reply::Toplevels(Vec::new()).to_string(), "toplevels");
+        assert_eq!(Reply::Killed(false).to_string(), "killed false");
+        assert_eq!(Reply::Wakeups(12).to_string(), "wakeups 12");
+        assert_eq!(Event::Workspace("web".into()).to_string(), "event workspace web");
+        assert_eq!(Event::Mode("resize".into()).to_string(), "event mode resize");
+        assert_eq!(
+            Reply::Bindings(vec!["* alt+Tab switcher switcher".into()]).to_string(),
+            "bindings 1\n* alt+Tab switcher switcher"
+        );
+    }
+
+    #[test]
+    fn format_bindings() {
+        let key = BindingInfo {
+            mode: Some("resize".into()),
+            trigger: Trigger::Key(KeyCombo {
+                modifiers: KeyModifiers::CTRL | KeyModifiers::NUM_LOCK,
+                sym: 0xff1b,
+            }),
+            action: Some(Action::ExitMode),
+            owner: BindingOwner::Wm,
+        };
+        let pointer = BindingInfo {
+            mode: Some("default".into()),
+            trigger: Trigger::Pointer(PointerCombo {
+                modifiers: KeyModifiers::LOGO,
+                trigger: PointerTrigger::Scroll(ScrollDirection::Up),
+            }),
+            action: Some(Action::Trigger(3)),
+            owner: BindingOwner::Wm,
+        };
+        let switcher = BindingInfo {
+            mode: None,
+            trigger: Trigger::Key(KeyCombo {
+                modifiers: KeyModifiers::ALT,
+                sym: 0xff09,
+            }),
+            action: None,
+            owner: BindingOwner::Switcher,
+        };
+
+        assert_eq!(binding_line(&key), "resize ctrl+Escape exit-mode wm");
+        assert_eq!(binding_line(&pointer), "default logo+scroll:up trigger:3 wm");
+        assert_eq!(binding_line(&switcher), "* alt+Tab switcher switcher");
+    }
+}
@@ -0,0 +1,505 @@
+//! Keyboard lock state, LEDs and layouts
+//!
+//! The configuration may enable numlock and capslock when the display server starts. The locks belong to the
+//! seat rather than a keyboard, so the LEDs of every keyboard attached to the seat show the same lock state.
+//!
+//! The kernel does not remember the LEDs for the display server, so the LEDs are set again when a keyboard is
+//! plugged in and when the session is resumed after a VT switch.
+//!
+//! The wm may change the keymap and the active layout, such as to implement per window layouts. The keymap is
+//! sent to clients by the keyboard of the seat.
+//!
+//! Keys are offered to the wm before clients, so the wm may match its bindings. The keyboard of the seat only
+//! processes a key once the wm decided whether the key is sent to clients, so the keysym and modifiers the wm and
+//! the emergency chords are matched against come from a second xkb state which is updated as soon as a key arrives.
+
+use std::fmt;
+
+use bitflags::bitflags;
+use smithay::{
+    backend::input::KeyState,
+    input::keyboard::{xkb, FilterResult, Layout, ModifiersState, XkbConfig},
+    utils::SERIAL_COUNTER,
+};
+use wm_runtime::{
+    types::{self, KeyStatus},
+    WmEvent,
+};
+
+use crate::{
+    emergency::{ChordModifiers, EmergencyAction},
+    Aerugo, Loop,
+};
+
+/// The evdev code of the capslock key.
+const KEY_CAPSLOCK: u32 = 58;
+/// The evdev code of the numlock key.
+const KEY_NUMLOCK: u32 = 69;
+
+/// How keys repeat while they are held, which clients are told with the keymap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRepeat {
+    /// Repeats per second, or 0 to disable repeat.
+    pub rate: i32,
+
+    /// How long a key is held in milliseconds before it repeats.
+    pub delay: i32,
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        Self { rate: 25, delay: 200 }
+    }
+}
+
+/// The lock state of a seat when the display server starts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockConfig {
+    pub num_lock: bool,
+    pub caps_lock: bool,
+}
+
+impl LockConfig {
+    /// The keys which must be pressed to reach the lock state from a keyboard with no locks.
+    fn keys(&self) -> Vec<u32> {
+        [(self.num_lock, KEY_NUMLOCK), (self.caps_lock, KEY_CAPSLOCK)]
+            .into_iter()
+            .filter_map(|(locked, key)| locked.then_some(key))
+            .collect()
+    }
+}
+
+bitflags! {
+    /// The LEDs of a keyboard.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct Leds: u32 {
+        const NUM_LOCK = 0x01;
+        const CAPS_LOCK = 0x02;
+    }
+}
+
+impl Leds {
+    /// The LEDs which show the lock state of the modifiers.
+    pub fn from_modifiers(modifiers: &ModifiersState) -> Self {
+        let mut leds = Leds::empty();
+        leds.set(Leds::NUM_LOCK, modifiers.num_lock);
+        leds.set(Leds::CAPS_LOCK, modifiers.caps_lock);
+        leds
+    }
+}
+
+/// Compile the keymap from the rules, or [`None`] if the keymap cannot be compiled.
+fn compile_keymap(config: &XkbConfig<'_>) -> Option<xkb::Keymap> {
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    xkb::Keymap::new_from_names(
+        &context,
+        config.rules,
+        config.model,
+        config.layout,
+        config.variant,
+        config.options.clone(),
+        xkb::KEYMAP_COMPILE_NO_FLAGS,
+    )
+}
+
+/// The names of the layouts of the keymap compiled from the rules, or [`None`] if the keymap cannot be compiled.
+fn layout_names(config: &XkbConfig<'_>) -> Option<Vec<String>> {
+    let keymap = compile_keymap(config)?;
+
+    Some(
+        (0..keymap.num_layouts())
+            .map(|layout| keymap.layout_get_name(layout).to_owned())
+            .collect(),
+    )
+}
+
+/// A key waiting for the wm to decide whether it is sent to clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeldKey {
+    /// The evdev code of the key.
+    pub key: u32,
+    pub state: KeyState,
+    pub time: u32,
+}
+
+/// What changed when a key arrived.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyUpdate {
+    /// The keysym of the key with the modifiers applied.
+    pub sym: u32,
+
+    /// The text typed by a pressed key.
+    pub text: Option<String>,
+
+    pub modifiers_changed: bool,
+    pub layout_changed: bool,
+}
+
+/// The xkb state of the seat, updated as soon as a key arrives.
+pub struct KeyboardState {
+    state: xkb::State,
+}
+
+impl fmt::Debug for KeyboardState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyboardState").finish_non_exhaustive()
+    }
+}
+
+impl KeyboardState {
+    /// Create the state of the keymap compiled from the rules, or [`None`] if the keymap cannot be compiled.
+    pub fn new(config: &XkbConfig<'_>) -> Option<Self> {
+        let keymap = compile_keymap(config)?;
+        Some(Self {
+            state: xkb::State::new(&keymap),
+        })
+    }
+
+    /// Update the state with a key.
+    pub fn key(&mut self, key: u32, state: KeyState) -> KeyUpdate {
+        // xkb keycodes are offset from evdev keycodes by 8.
+        let keycode = key + 8;
+        let direction = match state {
+            KeyState::Pressed => xkb::KeyDirection::Down,
+            KeyState::Released => xkb::KeyDirection::Up,
+        };
+
+        let changed = self.state.update_key(keycode, direction);
+        let text = self.state.key_get_utf8(keycode);
+
+        KeyUpdate {
+            sym: self.state.key_get_one_sym(keycode),
+            text: Some(text).filter(|text| state == KeyState::Pressed && !text.is_empty()),
+            modifiers_changed: changed & xkb::STATE_MODS_EFFECTIVE != 0,
+            layout_changed: changed & xkb::STATE_LAYOUT_EFFECTIVE != 0,
+        }
+    }
+
+    /// The modifiers which are held or locked.
+    pub fn modifiers(&self) -> ModifiersState {
+        let active = |name| self.state.mod_name_is_active(name, xkb::STATE_MODS_EFFECTIVE);
+
+        ModifiersState {
+            ctrl: active(xkb::MOD_NAME_CTRL),
+            alt: active(xkb::MOD_NAME_ALT),
+            shift: active(xkb::MOD_NAME_SHIFT),
+            caps_lock: active(xkb::MOD_NAME_CAPS),
+            logo: active(xkb::MOD_NAME_LOGO),
+            num_lock: active(xkb::MOD_NAME_NUM),
+            ..ModifiersState::default()
+        }
+    }
+
+    /// The index of the active layout.
+    pub fn layout(&self) -> u32 {
+        self.state.serialize_layout(xkb::STATE_LAYOUT_EFFECTIVE)
+    }
+
+    /// Make the layout active, keeping the modifiers.
+    pub fn set_layout(&mut self, index: u32) {
+        let depressed = self.state.serialize_mods(xkb::STATE_MODS_DEPRESSED);
+        let latched = self.state.serialize_mods(xkb::STATE_MODS_LATCHED);
+        let locked = self.state.serialize_mods(xkb::STATE_MODS_LOCKED);
+        self.state.update_mask(depressed, latched, locked, 0, 0, index);
+    }
+}
+
+/// The modifiers as known to the wm.
+pub fn wm_modifiers(modifiers: &ModifiersState) -> types::KeyModifiers {
+    let mut wm_modifiers = types::KeyModifiers::empty();
+
+    for (active, modifier) in [
+        (modifiers.ctrl, types::KeyModifiers::CTRL),
+        (modifiers.alt, types::KeyModifiers::ALT),
+        (modifiers.shift, types::KeyModifiers::SHIFT),
+        (modifiers.caps_lock, types::KeyModifiers::CAPS_LOCK),
+        (modifiers.logo, types::KeyModifiers::LOGO),
+        (modifiers.num_lock, types::KeyModifiers::NUM_LOCK),
+    ] {
+        if active {
+            wm_modifiers |= modifier;
+        }
+    }
+
+    wm_modifiers
+}
+
+impl Aerugo {
+    /// Set the keymap of the seat's keyboard, which is sent to clients.
+    ///
+    /// Returns the names of the layouts of the keymap, or [`None`] if the keymap cannot be compiled. The first
+    /// layout of the keymap becomes active.
+    pub fn set_keymap(&mut self, config: XkbConfig<'_>) -> Option<Vec<String>> {
+        let keyboard = self.seat.get_keyboard()?;
+        let layouts = layout_names(&config)?;
+        let state = KeyboardState::new(&config)?;
+
+        if let Err(err) = keyboard.set_xkb_config(self, config) {
+            tracing::warn!(?err, "Failed to set the keymap");
+            return None;
+        }
+
+        self.keyboard_state = Some(state);
+        self.keyboard_layout = 0;
+        self.send_wm(WmEvent::KeymapChanged {
+            layouts: layouts.clone(),
+        });
+        self.send_wm(WmEvent::LayoutChanged {
+            seat: self.wm.seat(),
+            index: 0,
+        });
+
+        Some(layouts)
+    }
+
+    /// Set the active layout of the seat's keyboard.
+    ///
+    /// Returns [`false`] if the keymap has no layout with the index.
+    pub fn set_keyboard_layout(&mut self, index: u32) -> bool {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return false;
+        };
+
+        let set = keyboard.with_xkb_state(self, |mut context| {
+            if index >= context.keymap().num_layouts() {
+                return false;
+            }
+
+            context.set_layout(Layout(index));
+            true
+        });
+
+        if set {
+            if let Some(state) = &mut self.keyboard_state {
+                state.set_layout(index);
+            }
+
+            self.keyboard_layout = index;
+            self.send_wm(WmEvent::LayoutChanged {
+                seat: self.wm.seat(),
+                index,
+            });
+        }
+
+        set
+    }
+
+    /// Lock the modifiers of the seat as set by the configuration.
+    ///
+    /// The lock keys are pressed on the seat's keyboard without being sent to clients, so the xkb state matches
+    /// the configuration.
+    pub fn apply_lock_config(&mut self, config: LockConfig) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return;
+        };
+
+        for key in config.keys() {
+            for state in [KeyState::Pressed, KeyState::Released] {
+                if let Some(keyboard_state) = &mut self.keyboard_state {
+                    keyboard_state.key(key, state);
+                }
+
+                keyboard.input::<(), _>(self, key, state, SERIAL_COUNTER.next_serial(), 0, |_, _, _| {
+                    FilterResult::Intercept(())
+                });
+            }
+        }
+
+        self.update_keyboard_leds();
+    }
+
+    /// Update the LEDs of every keyboard if the lock state changed.
+    pub fn update_keyboard_leds(&mut self) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return;
+        };
+
+        let leds = Leds::from_modifiers(&keyboard.modifier_state());
+
+        if leds != self.keyboard_leds {
+            self.keyboard_leds = leds;
+            self.backend.set_keyboard_leds(leds);
+        }
+    }
+
+    /// Set the LEDs of every keyboard again, such as after a keyboard was plugged in or the session was resumed.
+    pub fn restore_keyboard_leds(&mut self) {
+        self.backend.set_keyboard_leds(self.keyboard_leds);
+    }
+
+    /// Tell the wm the modifiers of the seat, such as when the wm may receive input again after the session was
+    /// unlocked.
+    pub fn send_key_modifiers(&self) {
+        if let Some(state) = &self.keyboard_state {
+            self.send_wm(WmEvent::KeyModifiers {
+                seat: self.wm.seat(),
+                modifiers: wm_modifiers(&state.modifiers()),
+            });
+        }
+    }
+
+    /// The wm decided whether the key with the serial is sent to the client with keyboard focus.
+    pub fn key_filter(&mut self, serial: u32, forward: bool) {
+        for (key, forward) in self.key_events.filter(serial, forward) {
+            self.send_key(key, forward);
+        }
+    }
+
+    /// Process a key with the keyboard of the seat, which sends the key to the client with keyboard focus unless the
+    /// key is dropped.
+    ///
+    /// Dropped keys are processed as well, so the xkb state of the keyboard matches the keys which are held.
+    pub(crate) fn send_key(&mut self, key: HeldKey, forward: bool) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return;
+        };
+
+        // Only the client allowed to receive input while a grab or the session lock is active receives keys.
+        let allowed = keyboard
+            .current_focus()
+            .and_then(|surface| surface.client())
+            .map_or(true, |client| self.can_receive_input(&client.id()));
+
+        keyboard.input::<(), _>(
+            self,
+            key.key,
+            key.state,
+            SERIAL_COUNTER.next_serial(),
+            key.time,
+            |_, _, _| match forward && allowed {
+                true => FilterResult::Forward,
+                false => FilterResult::Intercept(()),
+            },
+        );
+
+        self.update_keyboard_leds();
+    }
+}
+
+impl Loop {
+    /// Handle a key of the seat's keyboard.
+    ///
+    /// Emergency chords and the bindings of the configuration are handled right away, even if the wm hung. Other
+    /// keys are offered to the wm, which decides whether the key is sent to the client with keyboard focus.
+    pub fn keyboard_key(&mut self, key: u32, state: KeyState, time: u32) {
+        self.idle_input();
+
+        if state == KeyState::Pressed {
+            self.comp.cursor_key_pressed();
+        }
+
+        let Some(keyboard_state) = &mut self.comp.keyboard_state else {
+            return;
+        };
+
+        let update = keyboard_state.key(key, state);
+        let modifiers = keyboard_state.modifiers();
+        let layout = keyboard_state.layout();
+
+        let serial = u32::from(SERIAL_COUNTER.next_serial());
+        self.comp.key_events.hold(serial, HeldKey { key, state, time });
+
+        let chord = match self
+            .emergency
+            .action(ChordModifiers::from_state(&modifiers), update.sym)
+        {
+            // The screen locker may use the chord of the emergency terminal.
+            Some(EmergencyAction::Terminal) if self.comp.is_session_locked() => None,
+            chord => chord,
+        };
+
+        let bound = match chord {
+            Some(_) => None,
+            None => self.spawn_binding(&modifiers, update.sym),
+        };
+
+        if chord.is_some() {
+            // The press of the chord is not sent, so neither is the release.
+            self.comp.key_filter(serial, false);
+
+            if state == KeyState::Pressed {
+                self.emergency_key(&modifiers, update.sym);
+            }
+        } else if let Some(command) = bound {
+            self.comp.key_filter(serial, false);
+
+            if state == KeyState::Pressed {
+                self.spawn_bound_command(&command);
+            }
+        } else if !self.comp.wm_filters_input() {
+            // Nothing decides whether the key is consumed, so it is sent to the client right away.
+            self.comp.key_filter(serial, true);
+        } else {
+            self.comp.send_wm(WmEvent::Key {
+                seat: self.comp.wm.seat(),
+                serial,
+                time,
+                sym: update.sym,
+                compose: update.text,
+                status: match state {
+                    KeyState::Pressed => KeyStatus::Press,
+                    KeyState::Released => KeyStatus::Release,
+                },
+            });
+        }
+
+        if update.modifiers_changed && self.comp.wm_filters_input() {
+            self.comp.send_key_modifiers();
+        }
+
+        if update.layout_changed && layout != self.comp.keyboard_layout {
+            self.comp.keyboard_layout = layout;
+            self.comp.send_wm(WmEvent::LayoutChanged {
+                seat: self.comp.wm.seat(),
+                index: layout,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::input::keyboard::ModifiersState;
+
+    use wm_runtime::types::KeyModifiers;
+
+    use super::{wm_modifiers, Leds, LockConfig, KEY_CAPSLOCK, KEY_NUMLOCK};
+
+    #[test]
+    fn lock_keys() {
+        assert!(LockConfig::default().keys().is_empty());
+
+        let config = LockConfig {
+            num_lock: true,
+            caps_lock: true,
+        };
+        assert_eq!(config.keys(), [KEY_NUMLOCK, KEY_CAPSLOCK]);
+    }
+
+    #[test]
+    fn modifiers_of_the_wm() {
+        let modifiers = ModifiersState {
+            ctrl: true,
+            logo: true,
+            caps_lock: true,
+            ..ModifiersState::default()
+        };
+
+        assert_eq!(
+            wm_modifiers(&modifiers),
+            KeyModifiers::CTRL | KeyModifiers::LOGO | KeyModifiers::CAPS_LOCK
+        );
+        assert_eq!(wm_modifiers(&ModifiersState::default()), KeyModifiers::empty());
+    }
+
+    #[test]
+    fn leds_from_modifiers() {
+        let modifiers = ModifiersState {
+            num_lock: true,
+            ..ModifiersState::default()
+        };
+
+        assert_eq!(Leds::from_modifiers(&modifiers), Leds::NUM_LOCK);
+        assert_eq!(Leds::from_modifiers(&ModifiersState::default()), Leds::empty());
+    }
+}
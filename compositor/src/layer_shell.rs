@@ -0,0 +1,721 @@
+//! Layer surfaces
+//!
+//! Layer surfaces are used by desktop components such as bars, launchers and wallpapers. The wm decides the size
+//! of each layer surface with a configure, and the display server places the layer surface on the output using its
+//! anchors and margins. While no wm can answer, layer surfaces are configured with the size they request, stretched
+//! between the anchored edges.
+//!
+//! Each layer is a branch below the root of the output, and the tree presented by the wm is placed between the
+//! bottom and top layers. Overlays of the display server are drawn above every layer:
+//!
+//! ```text
+//! output
+//! └── root
+//!     ├── background
+//!     ├── bottom
+//!     ├── wm
+//!     ├── top
+//!     ├── overlay
+//!     └── server
+//! ```
+//!
+//! Layer surfaces which reserve an exclusive zone are placed first, in the order they were created, and the
+//! remaining layer surfaces are placed in the area left by the exclusive zones.
+//!
+//! A layer surface in the top or overlay layer which requests exclusive keyboard interactivity takes keyboard
+//! focus from toplevels while it is mapped.
+
+use smithay::{
+    backend::renderer::utils::with_renderer_surface_state,
+    utils::{Logical, Rectangle, Serial, Size},
+    wayland::{
+        compositor,
+        shell::wlr_layer::{
+            Anchor, ExclusiveZone, KeyboardInteractivity, Layer, LayerSurface, LayerSurfaceCachedState, Margins,
+        },
+    },
+};
+use wayland_server::{protocol::wl_surface::WlSurface, Resource};
+use wm_runtime::{types, Id, IdType, WmEvent};
+
+use crate::{
+    scene::{BranchIndex, NodeIndex},
+    Aerugo,
+};
+
+/// A layer surface of a client.
+#[derive(Debug)]
+struct LayerEntry {
+    surface: LayerSurface,
+
+    namespace: String,
+
+    /// The id the wm knows the layer surface by, or [`None`] if the wm was not told about the layer surface.
+    wm_id: Option<Id>,
+
+    /// Whether the client made the initial commit, after which the layer surface may be configured.
+    committed: bool,
+
+    /// Whether the layer surface has a buffer.
+    mapped: bool,
+
+    /// The layer the surface tree of the layer surface is in.
+    layer: Layer,
+
+    /// The state the wm was last told about.
+    state: Option<LayerSurfaceCachedState>,
+
+    /// The configures sent by the wm which were not acked yet, with the serial the wm allocated for each.
+    configures: Vec<(Serial, u32)>,
+}
+
+/// The branches of the output holding each layer.
+#[derive(Debug, Clone, Copy)]
+pub struct LayerBranches {
+    background: BranchIndex,
+    bottom: BranchIndex,
+    wm: BranchIndex,
+    placeholders: BranchIndex,
+    top: BranchIndex,
+    overlay: BranchIndex,
+    server: BranchIndex,
+}
+
+impl LayerBranches {
+    fn layer(&self, layer: Layer) -> BranchIndex {
+        match layer {
+            Layer::Background => self.background,
+            Layer::Bottom => self.bottom,
+            Layer::Top => self.top,
+            Layer::Overlay => self.overlay,
+        }
+    }
+
+    /// The branch the tree presented by the wm is placed in.
+    pub fn wm(&self) -> BranchIndex {
+        self.wm
+    }
+
+    /// The branch above the tree presented by the wm the placeholders of toplevels are drawn in.
+    pub fn placeholders(&self) -> BranchIndex {
+        self.placeholders
+    }
+
+    /// The branch above every layer the display server draws its own overlays in, such as the window switcher.
+    pub fn server(&self) -> BranchIndex {
+        self.server
+    }
+}
+
+/// The layer surfaces of clients.
+#[derive(Debug, Default)]
+pub struct LayerShell {
+    /// The layer surfaces in the order they were created.
+    surfaces: Vec<LayerEntry>,
+
+    /// The branches of the output, created when the output first presents something.
+    branches: Option<LayerBranches>,
+
+    /// The layer surface holding keyboard focus with exclusive keyboard interactivity.
+    keyboard_focus: Option<WlSurface>,
+}
+
+impl LayerShell {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get_mut(&mut self, surface: &WlSurface) -> Option<&mut LayerEntry> {
+        self.surfaces
+            .iter_mut()
+            .find(|entry| entry.surface.wl_surface() == surface)
+    }
+
+    /// The layer surface with the id the wm knows.
+    fn wm_layer_surface(&self, id: Id) -> Option<&LayerEntry> {
+        self.surfaces.iter().find(|entry| entry.wm_id == Some(id))
+    }
+
+    /// The layer surfaces which have a buffer.
+    pub fn mapped(&self) -> impl Iterator<Item = &WlSurface> {
+        self.surfaces
+            .iter()
+            .filter(|entry| entry.mapped)
+            .map(|entry| entry.surface.wl_surface())
+    }
+
+    /// The layer surface which takes keyboard focus from toplevels.
+    pub fn keyboard_focus(&self) -> Option<&WlSurface> {
+        self.keyboard_focus.as_ref()
+    }
+}
+
+fn cached_state(surface: &WlSurface) -> LayerSurfaceCachedState {
+    compositor::with_states(surface, |states| {
+        *states.cached_state.current::<LayerSurfaceCachedState>()
+    })
+}
+
+/// The state of the layer surface as known to the wm.
+fn wm_layer_state(state: &LayerSurfaceCachedState) -> types::LayerSurfaceState {
+    let mut anchor = types::Anchor::empty();
+
+    for (edge, wm_edge) in [
+        (Anchor::TOP, types::Anchor::TOP),
+        (Anchor::BOTTOM, types::Anchor::BOTTOM),
+        (Anchor::LEFT, types::Anchor::LEFT),
+        (Anchor::RIGHT, types::Anchor::RIGHT),
+    ] {
+        if state.anchor.contains(edge) {
+            anchor |= wm_edge;
+        }
+    }
+
+    types::LayerSurfaceState {
+        layer: match state.layer {
+            Layer::Background => types::Layer::Background,
+            Layer::Bottom => types::Layer::Bottom,
+            Layer::Top => types::Layer::Top,
+            Layer::Overlay => types::Layer::Overlay,
+        },
+        anchor,
+        exclusive_zone: match state.exclusive_zone {
+            ExclusiveZone::Exclusive(size) => i32::try_from(size).unwrap_or(i32::MAX),
+            ExclusiveZone::Neutral => 0,
+            ExclusiveZone::DontCare => -1,
+        },
+        margin: types::Margins {
+            top: state.margin.top,
+            bottom: state.margin.bottom,
+            left: state.margin.left,
+            right: state.margin.right,
+        },
+        size: types::Size {
+            width: state.size.w.max(0) as u32,
+            height: state.size.h.max(0) as u32,
+        },
+        keyboard_interactivity: match state.keyboard_interactivity {
+            KeyboardInteractivity::None => types::KeyboardInteractivity::None,
+            KeyboardInteractivity::Exclusive => types::KeyboardInteractivity::Exclusive,
+            KeyboardInteractivity::OnDemand => types::KeyboardInteractivity::OnDemand,
+        },
+    }
+}
+
+/// An edge of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+/// The edge the exclusive zone of a layer surface is reserved along.
+///
+/// An exclusive zone only applies if the layer surface is anchored to a single edge, or to an edge and both
+/// perpendicular edges.
+fn exclusive_edge(anchor: Anchor) -> Option<Edge> {
+    let horizontal = Anchor::LEFT | Anchor::RIGHT;
+    let vertical = Anchor::TOP | Anchor::BOTTOM;
+
+    // Being anchored to both perpendicular edges is the same as being anchored to neither.
+    let spans_horizontal = !anchor.intersects(horizontal) || anchor.contains(horizontal);
+    let spans_vertical = !anchor.intersects(vertical) || anchor.contains(vertical);
+
+    if anchor.contains(Anchor::TOP) && !anchor.contains(Anchor::BOTTOM) && spans_horizontal {
+        Some(Edge::Top)
+    } else if anchor.contains(Anchor::BOTTOM) && !anchor.contains(Anchor::TOP) && spans_horizontal {
+        Some(Edge::Bottom)
+    } else if anchor.contains(Anchor::LEFT) && !anchor.contains(Anchor::RIGHT) && spans_vertical {
+        Some(Edge::Left)
+    } else if anchor.contains(Anchor::RIGHT) && !anchor.contains(Anchor::LEFT) && spans_vertical {
+        Some(Edge::Right)
+    } else {
+        None
+    }
+}
+
+/// Remove the exclusive zone of a layer surface from the area.
+///
+/// The zone is clamped so the area never becomes smaller than nothing.
+fn reserve(area: &mut Rectangle<i32, Logical>, anchor: Anchor, zone: u32, margin: &Margins) {
+    let Some(edge) = exclusive_edge(anchor) else {
+        return;
+    };
+
+    let zone = i32::try_from(zone).unwrap_or(i32::MAX);
+
+    match edge {
+        Edge::Top => {
+            let reserved = zone.saturating_add(margin.top).clamp(0, area.size.h.max(0));
+            area.loc.y += reserved;
+            area.size.h -= reserved;
+        }
+        Edge::Bottom => {
+            let reserved = zone.saturating_add(margin.bottom).clamp(0, area.size.h.max(0));
+            area.size.h -= reserved;
+        }
+        Edge::Left => {
+            let reserved = zone.saturating_add(margin.left).clamp(0, area.size.w.max(0));
+            area.loc.x += reserved;
+            area.size.w -= reserved;
+        }
+        Edge::Right => {
+            let reserved = zone.saturating_add(margin.right).clamp(0, area.size.w.max(0));
+            area.size.w -= reserved;
+        }
+    }
+}
+
+/// The location and size of a layer surface along one axis of the area.
+///
+/// A size of 0 is stretched between the edges if the layer surface is anchored to both edges. A layer surface
+/// anchored to neither or both edges is centered.
+fn place_axis(
+    (area_loc, area_size): (i32, i32),
+    size: i32,
+    (margin_start, margin_end): (i32, i32),
+    anchored: (bool, bool),
+) -> (i32, i32) {
+    let size = match anchored {
+        (true, true) if size == 0 => (area_size - margin_start - margin_end).max(0),
+        _ => size,
+    };
+
+    let loc = match anchored {
+        (true, true) => area_loc + margin_start + (area_size - margin_start - margin_end - size) / 2,
+        (true, false) => area_loc + margin_start,
+        (false, true) => area_loc + area_size - margin_end - size,
+        (false, false) => area_loc + (area_size - size) / 2,
+    };
+
+    (loc, size)
+}
+
+/// The geometry of a layer surface of the size placed in the area.
+fn layer_geometry(
+    area: Rectangle<i32, Logical>,
+    size: Size<i32, Logical>,
+    anchor: Anchor,
+    margin: &Margins,
+) -> Rectangle<i32, Logical> {
+    let (x, w) = place_axis(
+        (area.loc.x, area.size.w),
+        size.w,
+        (margin.left, margin.right),
+        (anchor.contains(Anchor::LEFT), anchor.contains(Anchor::RIGHT)),
+    );
+    let (y, h) = place_axis(
+        (area.loc.y, area.size.h),
+        size.h,
+        (margin.top, margin.bottom),
+        (anchor.contains(Anchor::TOP), anchor.contains(Anchor::BOTTOM)),
+    );
+
+    Rectangle::from_loc_and_size((x, y), (w, h))
+}
+
+impl Aerugo {
+    /// The branches of the output holding each layer, which are created and presented on the output the first time
+    /// they are needed.
+    pub(crate) fn layer_branches(&mut self) -> LayerBranches {
+        if let Some(branches) = self.layer_shell.branches {
+            return branches;
+        }
+
+        let root = self.scene.create_branch();
+        let [background, bottom, wm, placeholders, top, overlay, server] = [(); 7].map(|_| {
+            let branch = self.scene.create_branch();
+            self.scene
+                .add_child(NodeIndex::Branch(root), NodeIndex::Branch(branch))
+                .expect("A new branch has no parent");
+            branch
+        });
+
+        let branches = LayerBranches {
+            background,
+            bottom,
+            wm,
+            placeholders,
+            top,
+            overlay,
+            server,
+        };
+
+        let output = self.output.clone();
+        self.scene.set_output_node(&output, NodeIndex::Branch(root));
+        self.layer_shell.branches = Some(branches);
+        branches
+    }
+
+    /// A client created a layer surface.
+    pub(crate) fn layer_surface_created(&mut self, surface: LayerSurface, layer: Layer, namespace: String) {
+        let tree = self.scene.create_surface_tree(surface.wl_surface().clone());
+        let branch = self.layer_branches().layer(layer);
+
+        if let Err(err) = self
+            .scene
+            .add_child(NodeIndex::Branch(branch), NodeIndex::SurfaceTree(tree))
+        {
+            tracing::warn!(%err, "Failed to add layer surface to its layer");
+        }
+
+        self.layer_shell.surfaces.push(LayerEntry {
+            surface,
+            namespace,
+            wm_id: None,
+            committed: false,
+            mapped: false,
+            layer,
+            state: None,
+            configures: Vec::new(),
+        });
+    }
+
+    /// Tell the wm about a layer surface, or configure the layer surface with the size it requests if no wm can
+    /// answer.
+    fn announce_layer_surface(&mut self, index: usize) {
+        let Some(entry) = self.layer_shell.surfaces.get(index) else {
+            return;
+        };
+
+        let state = cached_state(entry.surface.wl_surface());
+
+        if !self.wm.is_running() || self.wm_crash.is_some() {
+            let surface = entry.surface.clone();
+            let size = self.layer_fallback_size(&state);
+            self.configure_layer_surface(&surface, size, None);
+            return;
+        }
+
+        let id = self.wm.alloc_id(IdType::LayerSurface);
+        let namespace = entry.namespace.clone();
+
+        let entry = &mut self.layer_shell.surfaces[index];
+        entry.wm_id = Some(id);
+        entry.state = Some(state);
+        entry.configures.clear();
+
+        self.send_wm(WmEvent::NewLayerSurface {
+            layer_surface: id,
+            // There is a single output, so it is also the output the client asked for.
+            output: Some(self.wm.output()),
+            namespace,
+            state: wm_layer_state(&state),
+        });
+    }
+
+    /// Tell a newly started wm about every layer surface which made the initial commit.
+    pub(crate) fn announce_layer_surfaces(&mut self) {
+        for index in 0..self.layer_shell.surfaces.len() {
+            if self.layer_shell.surfaces[index].committed {
+                self.announce_layer_surface(index);
+            }
+        }
+    }
+
+    /// The layer surface was committed.
+    pub(crate) fn layer_surface_committed(&mut self, surface: &WlSurface) {
+        let Some(index) = self
+            .layer_shell
+            .surfaces
+            .iter()
+            .position(|entry| entry.surface.wl_surface() == surface)
+        else {
+            return;
+        };
+
+        let has_buffer = with_renderer_surface_state(surface, |state| state.buffer().is_some());
+        let state = cached_state(surface);
+        let entry = &mut self.layer_shell.surfaces[index];
+
+        if !entry.committed {
+            // The initial commit, which is answered with a configure.
+            entry.committed = true;
+            self.announce_layer_surface(index);
+        } else if entry.mapped && !has_buffer {
+            // The layer surface was unmapped and must make an initial commit again before it is configured.
+            entry.committed = false;
+            entry.mapped = false;
+            entry.state = None;
+
+            if let Some(id) = entry.wm_id.take() {
+                self.send_wm(WmEvent::ClosedLayerSurface(id));
+            }
+        } else {
+            entry.mapped = has_buffer;
+
+            if let Some(id) = entry.wm_id.filter(|_| entry.state != Some(state)) {
+                entry.state = Some(state);
+                self.send_wm(WmEvent::UpdateLayerSurface {
+                    layer_surface: id,
+                    state: wm_layer_state(&state),
+                });
+            }
+        }
+
+        let entry = &mut self.layer_shell.surfaces[index];
+
+        if entry.layer != state.layer {
+            entry.layer = state.layer;
+            let branch = self.layer_branches().layer(state.layer);
+
+            if let Some(tree) = self.scene.get_surface_tree_index(surface.clone()) {
+                if let Err(err) = self
+                    .scene
+                    .add_child(NodeIndex::Branch(branch), NodeIndex::SurfaceTree(tree))
+                {
+                    tracing::warn!(%err, "Failed to move layer surface to its layer");
+                }
+            }
+        }
+
+        self.arrange_layers();
+        self.update_layer_focus();
+    }
+
+    /// The layer surface acked a configure.
+    pub(crate) fn layer_surface_acked(&mut self, surface: &WlSurface, serial: Serial) {
+        let Some(entry) = self.layer_shell.get_mut(surface) else {
+            return;
+        };
+
+        let Some(position) = entry.configures.iter().position(|&(sent, _)| sent == serial) else {
+            return;
+        };
+
+        // Acking a configure also acks every configure sent before it.
+        let (_, wm_serial) = entry.configures.drain(..=position).last().unwrap();
+
+        if let Some(id) = entry.wm_id {
+            self.send_wm(WmEvent::AckLayerSurface {
+                layer_surface: id,
+                serial: wm_serial,
+            });
+        }
+    }
+
+    /// The layer surface was destroyed.
+    pub(crate) fn layer_surface_destroyed(&mut self, surface: &WlSurface) {
+        let Some(index) = self
+            .layer_shell
+            .surfaces
+            .iter()
+            .position(|entry| entry.surface.wl_surface() == surface)
+        else {
+            return;
+        };
+
+        let entry = self.layer_shell.surfaces.remove(index);
+        self.scene.destroy_surface_tree(surface);
+        self.arrange_layers();
+        self.update_layer_focus();
+
+        if let Some(id) = entry.wm_id {
+            self.send_wm(WmEvent::ClosedLayerSurface(id));
+        }
+    }
+
+    /// The wm configured the layer surface with the size.
+    pub(crate) fn wm_configure_layer_surface(&mut self, id: Id, size: types::Size, serial: u32) {
+        let Some(entry) = self.layer_shell.wm_layer_surface(id) else {
+            return;
+        };
+
+        let surface = entry.surface.clone();
+        let size = Size::from((size.width as i32, size.height as i32));
+        self.configure_layer_surface(&surface, size, Some(serial));
+    }
+
+    /// The wm closed the layer surface.
+    pub(crate) fn wm_close_layer_surface(&mut self, id: Id) {
+        if let Some(entry) = self.layer_shell.wm_layer_surface(id) {
+            entry.surface.send_close();
+        }
+    }
+
+    /// Configure the layer surface with the size, remembering the serial of the wm for the ack.
+    fn configure_layer_surface(&mut self, surface: &LayerSurface, size: Size<i32, Logical>, wm_serial: Option<u32>) {
+        let Some(entry) = self.layer_shell.get_mut(surface.wl_surface()) else {
+            return;
+        };
+
+        surface.with_pending_state(|state| state.size = Some(size));
+        let serial = surface.send_configure();
+
+        if let Some(wm_serial) = wm_serial {
+            entry.configures.push((serial, wm_serial));
+        }
+    }
+
+    /// Place every mapped layer surface on the output.
+    pub(crate) fn arrange_layers(&mut self) {
+        let Some(mode) = self.output.current_mode() else {
+            return;
+        };
+
+        let scale = self.output.current_scale().integer_scale();
+        let output = Rectangle::from_loc_and_size((0, 0), mode.size.to_logical(scale));
+        let mut usable = output;
+        let mut placed = Vec::new();
+
+        // Layer surfaces reserving an exclusive zone are placed first, so the others avoid the zones.
+        for exclusive in [true, false] {
+            for entry in self.layer_shell.surfaces.iter().filter(|entry| entry.mapped) {
+                let surface = entry.surface.wl_surface();
+                let state = cached_state(surface);
+
+                if matches!(state.exclusive_zone, ExclusiveZone::Exclusive(zone) if zone > 0) != exclusive {
+                    continue;
+                }
+
+                let Some(size) = with_renderer_surface_state(surface, |state| state.surface_size()) else {
+                    continue;
+                };
+
+                let area = match state.exclusive_zone {
+                    ExclusiveZone::DontCare => output,
+                    _ => usable,
+                };
+
+                let geometry = layer_geometry(area, size, state.anchor, &state.margin);
+
+                if let ExclusiveZone::Exclusive(zone) = state.exclusive_zone {
+                    reserve(&mut usable, state.anchor, zone, &state.margin);
+                }
+
+                placed.push((surface.clone(), geometry));
+            }
+        }
+
+        for (surface, geometry) in placed {
+            if let Some(tree) = self.scene.get_surface_tree_index(surface) {
+                self.scene
+                    .set_node_offset(NodeIndex::SurfaceTree(tree), geometry.loc.to_physical(scale));
+            }
+        }
+
+        self.scene_changed();
+    }
+
+    /// Give keyboard focus to the topmost layer surface requesting exclusive keyboard interactivity, or give
+    /// keyboard focus back to the focused toplevel.
+    fn update_layer_focus(&mut self) {
+        let focus = [Layer::Overlay, Layer::Top].into_iter().find_map(|layer| {
+            self.layer_shell
+                .surfaces
+                .iter()
+                .rev()
+                .filter(|entry| entry.mapped && entry.layer == layer)
+                .map(|entry| entry.surface.wl_surface())
+                .find(|surface| {
+                    cached_state(surface).keyboard_interactivity == KeyboardInteractivity::Exclusive
+                        && surface
+                            .client()
+                            .is_some_and(|client| self.can_receive_input(&client.id()))
+                })
+                .cloned()
+        });
+
+        if self.layer_shell.keyboard_focus != focus {
+            self.layer_shell.keyboard_focus = focus;
+            self.set_keyboard_focus(self.keyboard_focus);
+        }
+    }
+
+    /// The size a layer surface is configured with when no wm can answer.
+    fn layer_fallback_size(&self, state: &LayerSurfaceCachedState) -> Size<i32, Logical> {
+        let Some(mode) = self.output.current_mode() else {
+            return state.size;
+        };
+
+        let scale = self.output.current_scale().integer_scale();
+        let output = Rectangle::from_loc_and_size((0, 0), mode.size.to_logical(scale));
+        layer_geometry(output, state.size, state.anchor, &state.margin).size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::{
+        utils::Rectangle,
+        wayland::shell::wlr_layer::{Anchor, ExclusiveZone, LayerSurfaceCachedState, Margins},
+    };
+    use wm_runtime::types;
+
+    use super::{layer_geometry, reserve, wm_layer_state};
+
+    #[test]
+    fn placement() {
+        let output = Rectangle::from_loc_and_size((0, 0), (1920, 1080));
+        let margin = Margins {
+            top: 4,
+            bottom: 0,
+            left: 8,
+            right: 8,
+        };
+
+        // A bar stretched along the top edge.
+        assert_eq!(
+            layer_geometry(
+                output,
+                (0, 30).into(),
+                Anchor::TOP | Anchor::LEFT | Anchor::RIGHT,
+                &margin
+            ),
+            Rectangle::from_loc_and_size((8, 4), (1904, 30))
+        );
+
+        // A notification in the bottom right corner.
+        assert_eq!(
+            layer_geometry(output, (300, 100).into(), Anchor::BOTTOM | Anchor::RIGHT, &margin),
+            Rectangle::from_loc_and_size((1612, 980), (300, 100))
+        );
+
+        // A launcher centered on the output.
+        assert_eq!(
+            layer_geometry(output, (600, 400).into(), Anchor::empty(), &Margins::default()),
+            Rectangle::from_loc_and_size((660, 340), (600, 400))
+        );
+    }
+
+    #[test]
+    fn exclusive_zones() {
+        let mut area = Rectangle::from_loc_and_size((0, 0), (1920, 1080));
+
+        reserve(
+            &mut area,
+            Anchor::TOP | Anchor::LEFT | Anchor::RIGHT,
+            30,
+            &Margins::default(),
+        );
+        assert_eq!(area, Rectangle::from_loc_and_size((0, 30), (1920, 1050)));
+
+        // Anchored to opposite edges, so no zone is reserved.
+        reserve(&mut area, Anchor::LEFT | Anchor::RIGHT, 100, &Margins::default());
+        assert_eq!(area, Rectangle::from_loc_and_size((0, 30), (1920, 1050)));
+
+        let margin = Margins {
+            left: 8,
+            ..Margins::default()
+        };
+        reserve(&mut area, Anchor::LEFT | Anchor::TOP | Anchor::BOTTOM, 64, &margin);
+        assert_eq!(area, Rectangle::from_loc_and_size((72, 30), (1848, 1050)));
+    }
+
+    #[test]
+    fn state_of_the_wm() {
+        let state = LayerSurfaceCachedState {
+            anchor: Anchor::BOTTOM | Anchor::LEFT,
+            exclusive_zone: ExclusiveZone::DontCare,
+            size: (200, 0).into(),
+            ..LayerSurfaceCachedState::default()
+        };
+        let state = wm_layer_state(&state);
+
+        assert_eq!(state.anchor, types::Anchor::BOTTOM | types::Anchor::LEFT);
+        assert_eq!(state.exclusive_zone, -1);
+        assert_eq!((state.size.width, state.size.height), (200, 0));
+    }
+}
@@ -1,31 +1,150 @@
+//! The Aerugo display server
+//!
+//! This crate is the core of the display server: the scene, the Wayland protocols, the backends and renderers,
+//! and the host of the wasm wm. The `aerugo` binary is a thin frontend which parses the command line and the
+//! configuration file and starts the display server using this crate. A custom shell or kiosk may embed the display
+//! server the same way instead of forking the whole repository.
+//!
+//! # Embedding
+//!
+//! A display server is described by a [`Configuration`], created with the function which creates the [`Backend`]
+//! once the event loop exists. The display server runs on its own thread, started by
+//! [`Configuration::create_server`]. The returned [`AerugoExecutor`] is used to stop the display server, to hand it
+//! the connections of clients the embedder spawned itself, or to wait for it to exit.
+//!
+//! ```no_run
+//! use aerugo_comp::{backend, rules::Rules, Configuration};
+//!
+//! let executor = Configuration::new(backend::default_backend)
+//!     .with_rules(Rules::default())
+//!     .create_server()
+//!     .expect("Failed to create server");
+//!
+//! executor.join().expect("The display server panicked");
+//! ```
+//!
+//! # Stability
+//!
+//! The [`Configuration`] builder and the [`AerugoExecutor`] are the embedding API. New settings are added as
+//! `with_*` methods with defaults, so existing embedders keep compiling. The modules behind [`Aerugo`] and
+//! [`Loop`] are public so the backends and the wm runtime can be driven by the embedder, but may change between
+//! releases.
+
 use std::{
+    env,
     error::Error,
-    io,
-    os::fd::OwnedFd,
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    mem,
+    os::{
+        fd::{AsFd, AsRawFd, OwnedFd},
+        unix::{
+            net::{UnixListener, UnixStream},
+            process::ExitStatusExt,
+        },
+    },
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
     sync::{
+        atomic::{AtomicU64, Ordering},
         mpsc::{self, SendError},
         Arc,
     },
     thread::{self, JoinHandle, Thread},
+    time::{Duration, Instant},
 };
 
-use calloop::{channel::SyncSender, generic::Generic, EventLoop, Interest, LoopHandle, LoopSignal, Mode, PostAction};
+use calloop::{
+    channel::SyncSender,
+    generic::Generic,
+    timer::{TimeoutAction, Timer},
+    EventLoop, Interest, LoopHandle, LoopSignal, Mode, PostAction, RegistrationToken,
+};
 
 use backend::Backend;
-use smithay::wayland::{compositor::CompositorClientState, socket::ListeningSocketSource};
+use rustc_hash::FxHashMap;
+use smithay::wayland::compositor::CompositorClientState;
 use wayland_server::{Display, DisplayHandle};
+use wm_runtime::{types::ProcessStatus, WmEvent};
 
+pub mod activation;
+pub mod alpha;
+pub mod audio;
 pub mod backend;
+pub mod bindings;
+pub mod config;
+pub mod cursor;
+pub mod decorations;
+pub mod dnd;
+pub mod emergency;
+pub mod environment;
+pub mod fallback;
+pub mod flush;
 pub mod forest;
+pub mod fullscreen;
+pub mod gesture;
+pub mod group;
+pub mod handoff;
+pub mod idle;
+mod input;
+pub mod interactive;
+pub mod ipc;
+pub mod keyboard;
+pub mod layer_shell;
+pub mod metrics;
+pub mod output_config;
+pub mod ping;
+pub mod placeholder;
+pub mod pointer;
+pub mod policy;
+pub mod popup;
+pub mod protocol_log;
+pub mod report;
+pub mod rounded;
+pub mod rules;
 mod scene;
+pub mod selection;
 mod shell;
 mod state;
+pub mod switcher;
+#[cfg(test)]
+mod test_util;
+pub mod thumbnail;
+pub mod touch;
 mod transaction;
+pub mod vrr;
 mod wayland;
+mod wm;
+pub mod wm_scene;
+pub mod wm_store;
+pub mod workspace;
+pub mod zoom;
 
-pub use state::Aerugo;
+pub use input::{ExclusiveGrab, ExclusiveGrabKind, SessionLock, Switch};
+pub use shell::ToplevelId;
+pub use state::{Aerugo, PrivilegedGlobals};
 
-use crate::state::{ClientData, PrivilegedGlobals};
+use crate::{
+    audio::AudioMonitor,
+    backend::modifiers::ModifierPolicy,
+    bindings::SpawnBindings,
+    config::OutputSettings,
+    cursor::CursorHideConfig,
+    emergency::EmergencyConfig,
+    environment::Environment,
+    handoff::{Handoff, Inherited},
+    idle::{IdleConfig, IdleState},
+    ipc::IpcAuth,
+    keyboard::{KeyRepeat, LockConfig},
+    metrics::{self, Metrics, MetricsConfig},
+    placeholder::PlaceholderConfig,
+    policy::{ClientInfo, GlobalPolicy},
+    report::RecentLogs,
+    rules::Rules,
+    state::ClientData,
+    wm_store::WmStore,
+};
 
 type BackendConstructor = Box<
     dyn FnOnce(LoopHandle<'static, Loop>, DisplayHandle) -> Result<Box<dyn Backend>, Box<dyn Error>> + Send + 'static,
@@ -34,6 +153,24 @@ type BackendConstructor = Box<
 /// Configuration used to create a server instance.
 pub struct Configuration {
     backend_constructor: BackendConstructor,
+    global_policy: GlobalPolicy,
+    environment: Environment,
+    rules: Rules,
+    cursor_theme: Option<(String, u32)>,
+    cursor_hiding: CursorHideConfig,
+    locks: LockConfig,
+    key_repeat: KeyRepeat,
+    placeholders: PlaceholderConfig,
+    emergency: EmergencyConfig,
+    bindings: SpawnBindings,
+    metrics: MetricsConfig,
+    idle: Option<IdleConfig>,
+    modifier_policy: Option<ModifierPolicy>,
+    recent_logs: RecentLogs,
+    ipc_auth: IpcAuth,
+    outputs: Vec<OutputSettings>,
+    wm: Option<PathBuf>,
+    inherited: Inherited,
 }
 
 impl Configuration {
@@ -45,9 +182,142 @@ impl Configuration {
     {
         Self {
             backend_constructor: Box::new(b),
+            global_policy: GlobalPolicy::default(),
+            environment: Environment::default(),
+            rules: Rules::default(),
+            cursor_theme: None,
+            cursor_hiding: CursorHideConfig::default(),
+            locks: LockConfig::default(),
+            key_repeat: KeyRepeat::default(),
+            placeholders: PlaceholderConfig::default(),
+            emergency: EmergencyConfig::default(),
+            bindings: SpawnBindings::default(),
+            metrics: MetricsConfig::default(),
+            idle: None,
+            modifier_policy: None,
+            recent_logs: RecentLogs::new(),
+            ipc_auth: IpcAuth::current_user(),
+            outputs: Vec::new(),
+            wm: None,
+            inherited: Inherited::default(),
         }
     }
 
+    /// Set the policy deciding which privileged globals are visible to each client.
+    pub fn with_global_policy(mut self, policy: GlobalPolicy) -> Self {
+        self.global_policy = policy;
+        self
+    }
+
+    /// Set the environment of child processes.
+    pub fn with_environment(mut self, environment: Environment) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    /// Set the window rules.
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Set the cursor theme and the size of the cursor in logical pixels.
+    pub fn with_cursor_theme(mut self, theme: impl Into<String>, size: u32) -> Self {
+        self.cursor_theme = Some((theme.into(), size));
+        self
+    }
+
+    /// Set when the cursor is hidden while typing or faded out after the pointer has not moved.
+    pub fn with_cursor_hiding(mut self, config: CursorHideConfig) -> Self {
+        self.cursor_hiding = config;
+        self
+    }
+
+    /// Set whether numlock and capslock are enabled when the display server starts.
+    pub fn with_initial_locks(mut self, locks: LockConfig) -> Self {
+        self.locks = locks;
+        self
+    }
+
+    /// Set how held keys repeat.
+    pub fn with_key_repeat(mut self, repeat: KeyRepeat) -> Self {
+        self.key_repeat = repeat;
+        self
+    }
+
+    /// Set whether placeholders are drawn for toplevels which are slow to commit their first buffer.
+    pub fn with_placeholders(mut self, config: PlaceholderConfig) -> Self {
+        self.placeholders = config;
+        self
+    }
+
+    /// Set the emergency chords and the terminal they spawn.
+    pub fn with_emergency_chords(mut self, config: EmergencyConfig) -> Self {
+        self.emergency = config;
+        self
+    }
+
+    /// Set the chords which spawn a command.
+    pub fn with_spawn_bindings(mut self, bindings: SpawnBindings) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    /// Serve metrics for monitoring on an endpoint.
+    pub fn with_metrics(mut self, config: MetricsConfig) -> Self {
+        self.metrics = config;
+        self
+    }
+
+    /// Dim and then power off the outputs when there is no input.
+    ///
+    /// This is disabled by default.
+    pub fn with_idle(mut self, config: IdleConfig) -> Self {
+        self.idle = Some(config);
+        self
+    }
+
+    /// Decide which modifiers are offered when buffers are allocated, instead of the default policy.
+    pub fn with_modifier_policy(mut self, policy: ModifierPolicy) -> Self {
+        self.modifier_policy = Some(policy);
+        self
+    }
+
+    /// Add the recent lines of the log to debug reports.
+    ///
+    /// The logger must write to a clone of the logs.
+    pub fn with_recent_logs(mut self, logs: RecentLogs) -> Self {
+        self.recent_logs = logs;
+        self
+    }
+
+    /// Listen on the socket inherited from the previous process after a restart in place, instead of binding a
+    /// new socket.
+    pub fn with_handoff(mut self, inherited: Inherited) -> Self {
+        self.inherited = inherited;
+        self
+    }
+
+    /// Set who may connect to the IPC socket and which tokens grant higher tiers.
+    ///
+    /// By default only the user running the display server may connect, with the configure tier.
+    pub fn with_ipc_auth(mut self, auth: IpcAuth) -> Self {
+        self.ipc_auth = auth;
+        self
+    }
+
+    /// Set the settings of outputs, which are applied to the output with the same name once it is connected.
+    pub fn with_outputs(mut self, outputs: Vec<OutputSettings>) -> Self {
+        self.outputs = outputs;
+        self
+    }
+
+    /// Start the wm component at the path once the display server is running.
+    pub fn with_wm(mut self, path: PathBuf) -> Self {
+        self.wm = Some(path);
+        self
+    }
+
     // TODO: Socket creation here
 
     /// Creates a server using the configuration.
@@ -60,6 +330,8 @@ impl Configuration {
         // In calloop EventLoop is !Send and !Sync, so we need to send the loop signal from the event loop
         // thread to the caller. We do this with a rendezvous channel (which is why the bound is 0).
         let (send, recv) = mpsc::sync_channel(0);
+        let wakeups = Arc::new(AtomicU64::new(0));
+        let loop_wakeups = wakeups.clone();
 
         let thread = thread::Builder::new().name("Aerugo event loop".into()).spawn(move || {
             // TODO: Proper typedef
@@ -69,23 +341,30 @@ impl Configuration {
             let (send_server, recv_server) = calloop::channel::sync_channel::<ExecutorMessage>(5);
             send.send((signal, send_server)).expect("Executor thread died");
 
-            let mut aerugo = Loop::new(&r#loop, self.backend_constructor).expect("TODO: Error type");
+            let mut aerugo = Loop::new(&r#loop, self).expect("TODO: Error type");
+            aerugo.comp.wakeups = loop_wakeups;
 
             {
                 let r#loop = r#loop.handle();
                 r#loop
-                    .insert_source(recv_server, |msg, _, _state| {
-                        if let calloop::channel::Event::Msg(_msg) = msg {
-                            todo!("Handle executor messages")
+                    .insert_source(recv_server, |msg, _, state| match msg {
+                        calloop::channel::Event::Msg(ExecutorMessage::CreateClient(fd)) => {
+                            state.insert_client(UnixStream::from(fd));
                         }
+                        calloop::channel::Event::Closed => (),
                     })
                     .unwrap();
             }
 
             r#loop
                 .run(None, &mut aerugo, |state| {
+                    // The event loop has no timeout, so every wakeup was caused by an event source.
+                    state.comp.wakeups.fetch_add(1, Ordering::Relaxed);
                     // Flush any pending messages to ensure clients can respond to server events.
                     state.flush_display();
+                    state.stream_protocol_log();
+                    state.stream_ipc_events();
+                    state.schedule_deadline();
                     // Check the backend has met any internal shutdown conditions.
                     state.check_shutdown();
                 })
@@ -104,6 +383,7 @@ impl Configuration {
             thread,
             signal,
             channel,
+            wakeups,
         })
     }
 }
@@ -115,6 +395,7 @@ pub struct AerugoExecutor {
     thread: JoinHandle<()>,
     signal: LoopSignal,
     channel: SyncSender<ExecutorMessage>,
+    wakeups: Arc<AtomicU64>,
 }
 
 impl AerugoExecutor {
@@ -134,6 +415,14 @@ impl AerugoExecutor {
             })
     }
 
+    /// The number of times the event loop has woken up.
+    ///
+    /// When no clients are committing, no animations are running and there is no input, this value should not
+    /// increase. This is intended for debugging idle power usage.
+    pub fn wakeup_count(&self) -> u64 {
+        self.wakeups.load(Ordering::Relaxed)
+    }
+
     /// Stops the server event loop.
     pub fn stop(&self) {
         // Stopping the server is twofold, first we send the event loop to stop and then immediately wake the
@@ -158,10 +447,61 @@ pub struct Loop {
     signal: LoopSignal,
     comp: Aerugo,
     display: DisplayHandle,
+    /// Timers set by the wm.
+    wm_timers: FxHashMap<u32, RegistrationToken>,
+    /// Sends the exit status of processes spawned by the wm from the threads waiting for the processes.
+    wm_process_exits: calloop::channel::Sender<(u32, io::Result<ExitStatus>)>,
+    environment: Environment,
+    /// Values the wm persists across restarts.
+    wm_store: WmStore,
+    /// The timer which writes the wm store to disk, if a change is waiting to be written.
+    wm_store_save: Option<RegistrationToken>,
+    /// The timer which releases held input if the wm does not reply in time.
+    input_timeout: Option<RegistrationToken>,
+    /// The timer which draws a frame at the next deadline of the display server, and the deadline.
+    deadline: Option<(Instant, RegistrationToken)>,
+    /// Decides which privileged globals each new client can see.
+    global_policy: GlobalPolicy,
+    /// Chords handled by the display server even if the wm is hung.
+    emergency: EmergencyConfig,
+    /// Chords from the configuration which spawn a command.
+    bindings: SpawnBindings,
+    /// A duplicate of the listening socket, passed to the new process when restarting in place.
+    listening_socket: OwnedFd,
+    /// A duplicate of the lock file reserving the name of the listening socket, passed on with the socket.
+    socket_lock: OwnedFd,
+    socket_name: OsString,
+    /// IPC connections streaming the protocol log.
+    protocol_log_streams: Vec<UnixStream>,
+    /// IPC connections waiting for the wm to list its bindings.
+    binding_list_streams: Vec<UnixStream>,
+    /// IPC connections which subscribed to events.
+    event_streams: Vec<UnixStream>,
 }
 
 impl Loop {
-    pub fn new(r#loop: &EventLoop<'static, Self>, backend: BackendConstructor) -> Result<Self, ()> {
+    pub fn new(r#loop: &EventLoop<'static, Self>, config: Configuration) -> Result<Self, ()> {
+        let Configuration {
+            backend_constructor: backend,
+            global_policy,
+            mut environment,
+            rules,
+            cursor_theme,
+            cursor_hiding,
+            locks,
+            key_repeat,
+            placeholders,
+            emergency,
+            bindings,
+            metrics,
+            idle,
+            modifier_policy,
+            recent_logs,
+            ipc_auth,
+            outputs,
+            wm,
+            inherited,
+        } = config;
         let display = Display::new().expect("Failed to initialize Wayland display");
         let signal = r#loop.get_signal();
         let r#loop = r#loop.handle();
@@ -174,31 +514,394 @@ impl Loop {
         let display = display_handle;
 
         // Register the listening socket so clients can connect
-        register_listening_socket(&r#loop);
+        let (listening_socket, socket_lock, socket_name) = register_listening_socket(&r#loop, inherited.socket);
+        environment.set_wayland_display(socket_name.clone());
+
+        match ipc::socket_path(&socket_name.to_string_lossy()) {
+            Some(path) => match ipc::register_socket(&r#loop, path.clone(), ipc_auth) {
+                Ok(()) => {
+                    environment.set(ipc::SOCKET_ENV, path);
+                }
+                Err(err) => tracing::error!(%err, ?path, "Failed to bind the IPC socket"),
+            },
+            None => tracing::error!("XDG_RUNTIME_DIR is not set, so the IPC socket is not available"),
+        }
 
         let backend = backend(r#loop.clone(), display.clone()).expect("TODO: Error type");
-        let comp = Aerugo::new(&r#loop, display.clone(), backend);
+        let mut comp = Aerugo::new(&r#loop, display.clone(), backend, &outputs, key_repeat);
+        comp.rules = rules;
+        comp.cursor_visibility.set_config(cursor_hiding);
+        comp.apply_lock_config(locks);
+        comp.placeholders.set_config(placeholders);
+        comp.idle = idle.map(|config| IdleState::new(config, Instant::now()));
+        comp.recent_logs = recent_logs;
+
+        if let Some(policy) = modifier_policy {
+            comp.set_modifier_policy(policy);
+        }
+
+        let output = comp.output.clone();
+        if let Some(settings) = outputs.iter().find(|settings| settings.name == output.name()) {
+            if let Err(err) = comp.configure_output(&output, settings.config) {
+                tracing::error!(%err, output = %output.name(), "Failed to apply the output settings");
+            }
+        }
+
+        if let Some(endpoint) = &metrics.endpoint {
+            match metrics::register_metrics_endpoint(&r#loop, endpoint) {
+                Ok(()) => comp.metrics = Some(Metrics::default()),
+                Err(err) => tracing::error!(%err, %endpoint, "Failed to serve metrics"),
+            }
+        }
+
+        // The processes spawned by the wm share one channel, which is never removed.
+        let (wm_process_exits, channel) = calloop::channel::channel();
+        r#loop
+            .insert_source(channel, |event, _, state: &mut Loop| {
+                if let calloop::channel::Event::Msg((process, status)) = event {
+                    tracing::debug!(process, ?status, "Process spawned by the wm exited");
+                    state.comp.send_wm(WmEvent::ProcessExited {
+                        process,
+                        status: process_status(status),
+                    });
+                }
+            })
+            .expect("Failed to insert channel");
+
+        let (audio_events, channel) = calloop::channel::channel();
+        match AudioMonitor::spawn(audio_events) {
+            Ok(monitor) => {
+                r#loop
+                    .insert_source(channel, |event, _, state: &mut Loop| {
+                        if let calloop::channel::Event::Msg(event) = event {
+                            state.comp.audio_event(event);
+                        }
+                    })
+                    .expect("Failed to insert channel");
+                comp.audio_monitor = Some(monitor);
+            }
+            Err(err) => tracing::warn!(%err, "Failed to start the PipeWire monitor"),
+        }
 
-        Ok(Self {
+        let mut state = Self {
             r#loop,
             signal,
             comp,
             display,
-        })
+            wm_timers: FxHashMap::default(),
+            wm_process_exits,
+            environment,
+            wm_store: WmStore::default_path().map(WmStore::load).unwrap_or_default(),
+            wm_store_save: None,
+            input_timeout: None,
+            deadline: None,
+            global_policy,
+            emergency,
+            bindings,
+            listening_socket,
+            socket_lock,
+            socket_name,
+            protocol_log_streams: Vec::new(),
+            binding_list_streams: Vec::new(),
+            event_streams: Vec::new(),
+        };
+
+        state.schedule_idle();
+
+        match cursor_theme {
+            Some((theme, size)) => state.set_cursor_theme(&theme, size),
+            None => state.comp.update_cursor_image(),
+        }
+
+        if let Some(path) = wm {
+            state.start_wm(path);
+        }
+
+        Ok(state)
     }
 
+    /// The environment of child processes.
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    /// Change the cursor theme and size.
+    ///
+    /// The theme is also exported to clients spawned afterwards, so client-drawn cursors match.
+    pub fn set_cursor_theme(&mut self, theme: &str, size: u32) {
+        self.comp.cursor_theme.set_theme(theme, size);
+        self.environment.set_cursor_theme(theme, size);
+        self.environment.update_activation_environment();
+        self.comp.update_cursor_image();
+    }
+
+    /// Flush events to clients, unless they are held back by a batch.
     pub fn flush_display(&mut self) {
+        if !self.comp.flush.should_flush(Instant::now()) {
+            return;
+        }
+
         self.display.flush_clients().expect("TODO: Error?");
+        self.comp.flush.flushed();
+    }
+
+    /// Write the logged protocol messages to the IPC connections streaming the protocol log.
+    ///
+    /// The log is disabled once every connection streaming it was closed.
+    pub fn stream_protocol_log(&mut self) {
+        if self.protocol_log_streams.is_empty() {
+            return;
+        }
+
+        let lines = self.comp.protocol_log.drain().collect::<Vec<_>>();
+
+        self.protocol_log_streams
+            .retain(|mut stream| lines.iter().try_for_each(|line| writeln!(stream, "{line}")).is_ok());
+
+        if self.protocol_log_streams.is_empty() {
+            tracing::info!("Protocol log disabled");
+            self.comp.protocol_log.disable();
+        }
+    }
+
+    /// Write the events to the IPC connections which subscribed to events.
+    ///
+    /// A connection which cannot be written to, such as because the client closed it, is unsubscribed.
+    pub fn stream_ipc_events(&mut self) {
+        let events = mem::take(&mut self.comp.ipc_events);
+
+        self.event_streams
+            .retain(|mut stream| events.iter().try_for_each(|event| writeln!(stream, "{event}")).is_ok());
+    }
+
+    /// Hold events sent to clients back until the next frame is drawn.
+    ///
+    /// This should be called before the display server sends a burst of events, such as when switching
+    /// workspaces, so the events are written to each client at once.
+    pub fn batch_events(&mut self) {
+        let Some(deadline) = self.comp.flush.begin_batch(Instant::now()) else {
+            return;
+        };
+
+        // Wake up at the deadline so the batch is flushed even if no frame is drawn.
+        self.r#loop
+            .insert_source(Timer::from_deadline(deadline), |_, _, _| TimeoutAction::Drop)
+            .expect("Failed to insert timer");
+    }
+
+    /// Draw a frame at the next deadline of the display server, such as when the cursor starts to fade, even if
+    /// nothing else changes until then.
+    fn schedule_deadline(&mut self) {
+        let deadline = self.comp.next_deadline(Instant::now());
+
+        if self.deadline.map(|(deadline, _)| deadline) == deadline {
+            return;
+        }
+
+        if let Some((_, token)) = self.deadline.take() {
+            self.r#loop.remove(token);
+        }
+
+        let Some(deadline) = deadline else {
+            return;
+        };
+
+        let token = self
+            .r#loop
+            .insert_source(Timer::from_deadline(deadline), |_, _, state| {
+                state.deadline = None;
+                let output = state.comp.output.clone();
+                state.comp.request_frame(&output);
+                TimeoutAction::Drop
+            })
+            .expect("Failed to insert timer");
+
+        self.deadline = Some((deadline, token));
+    }
+
+    /// Set a timer requested by the wm.
+    ///
+    /// A timer with the same id is replaced.
+    pub fn set_wm_timer(&mut self, timer: u32, duration: Duration) {
+        self.cancel_wm_timer(timer);
+
+        let token = self
+            .r#loop
+            .insert_source(Timer::from_duration(duration), move |_, _, state| {
+                state.wm_timers.remove(&timer);
+                state.comp.send_wm(WmEvent::TimerExpired(timer));
+                TimeoutAction::Drop
+            })
+            .expect("Failed to insert timer");
+
+        self.wm_timers.insert(timer, token);
+    }
+
+    /// Cancel a timer set by the wm.
+    pub fn cancel_wm_timer(&mut self, timer: u32) {
+        if let Some(token) = self.wm_timers.remove(&timer) {
+            self.r#loop.remove(token);
+        }
+    }
+
+    /// The values the wm stored, used to start the wm.
+    pub fn wm_store(&self) -> &WmStore {
+        &self.wm_store
+    }
+
+    /// Set a value in the persistent store of the wm.
+    ///
+    /// The store is written to disk after [`wm_store::SAVE_DELAY`], so several changes are written at once.
+    pub fn set_wm_store(&mut self, key: String, value: Option<String>) {
+        if let Err(err) = self.wm_store.set(key, value) {
+            tracing::warn!(%err, "Rejected value in the wm store");
+            return;
+        }
+
+        if self.wm_store_save.is_some() {
+            return;
+        }
+
+        let token = self
+            .r#loop
+            .insert_source(Timer::from_duration(wm_store::SAVE_DELAY), |_, _, state| {
+                state.wm_store_save = None;
+                state.wm_store.save();
+                TimeoutAction::Drop
+            })
+            .expect("Failed to insert timer");
+
+        self.wm_store_save = Some(token);
+    }
+
+    /// Start a process requested by the wm.
+    ///
+    /// The process inherits the environment of child processes plus the variables set by the wm. The process is
+    /// waited for on another thread so the event loop is not blocked.
+    pub fn spawn_wm_process(&mut self, process: u32, command: &str, args: &[String], env: &[(String, String)]) {
+        let mut cmd = Command::new(command);
+        cmd.args(args).stdin(Stdio::null());
+        self.environment.apply(&mut cmd);
+        cmd.envs(env.iter().map(|(name, value)| (name, value)));
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                tracing::warn!(%err, command, "Failed to spawn process for the wm");
+                self.comp.send_wm(WmEvent::ProcessExited {
+                    process,
+                    status: ProcessStatus::Failed(err.to_string()),
+                });
+                return;
+            }
+        };
+
+        tracing::debug!(command, pid = child.id(), "Spawned process for the wm");
+
+        let sender = self.wm_process_exits.clone();
+        let waited = thread::Builder::new().name("Wm process".into()).spawn(move || {
+            let _ = sender.send((process, child.wait()));
+        });
+
+        if let Err(err) = waited {
+            // The process is not waited for, so the wm is never told when the process exits.
+            tracing::warn!(%err, "Failed to wait for process spawned by the wm");
+            self.comp.send_wm(WmEvent::ProcessExited {
+                process,
+                status: ProcessStatus::Failed(err.to_string()),
+            });
+        }
+    }
+
+    /// Execute the display server binary in place of the running process, passing the listening socket and the
+    /// DRM devices to the new process.
+    ///
+    /// Only returns if the restart failed, in which case the display server keeps running.
+    pub fn restart(&mut self) {
+        self.comp.restart_requested = false;
+
+        // The new process loads the wm store when it starts.
+        if let Some(token) = self.wm_store_save.take() {
+            self.r#loop.remove(token);
+            self.wm_store.save();
+        }
+
+        self.wm_store.wait();
+
+        // Send the events which are waiting, such as the reply to the IPC command which requested the restart.
+        if let Err(err) = self.display.flush_clients() {
+            tracing::warn!(%err, "Failed to flush clients before restarting");
+        }
+
+        let drm = self.comp.backend.drm_devices();
+
+        let handoff = Handoff {
+            sockets: vec![(
+                self.listening_socket.as_raw_fd(),
+                self.socket_name.to_string_lossy().into_owned(),
+            )],
+            locks: vec![(
+                self.socket_lock.as_raw_fd(),
+                self.socket_name.to_string_lossy().into_owned(),
+            )],
+            drm: drm.iter().map(|(path, fd)| (fd.as_raw_fd(), path.clone())).collect(),
+        };
+
+        let mut fds = vec![self.listening_socket.as_fd(), self.socket_lock.as_fd()];
+        fds.extend(drm.iter().map(|&(_, fd)| fd));
+
+        let err = handoff.exec(&fds);
+        tracing::error!(%err, "Failed to restart in place");
+    }
+
+    /// Add a client connected on the socket, with the privileged globals the global policy allows it to see.
+    ///
+    /// This is used for clients accepted on the listening socket and clients handed over by
+    /// [`AerugoExecutor::create_client`].
+    pub fn insert_client(&mut self, client: UnixStream) {
+        let client_info = ClientInfo::from_socket(&client);
+        self.insert_client_with_info(client, client_info, false);
+    }
+
+    /// Add a client with the information the global policy is applied to.
+    pub(crate) fn insert_client_with_info(&mut self, client: UnixStream, client_info: ClientInfo, sandboxed: bool) {
+        let info = format!("{client:?}");
+        let globals = self.global_policy.globals_for(&client_info);
+        tracing::debug!(?client_info, ?globals, "New client");
+
+        if let Err(err) = self.display.insert_client(
+            client,
+            Arc::new(ClientData {
+                globals,
+                compositor: CompositorClientState::default(),
+                info: client_info,
+                sandboxed,
+                _counted: self.comp.clients.connected(),
+            }),
+        ) {
+            // TODO: Provide info about the socket (name)
+            tracing::error!(%err, "Failed to register client with fd: {info}");
+        }
     }
 
     pub fn check_shutdown(&mut self) {
+        if self.comp.restart_requested {
+            self.restart();
+        }
+
         let shutdown =
             // Check if the backend has requested a shutdown
-            self.comp.backend.should_shutdown();
+            self.comp.backend.should_shutdown()
+            // Or another instance is replacing the display server.
+            || self.comp.shutdown_requested;
 
         // TODO: Other shutdown check mechanisms, such as a logout.
 
         if shutdown {
+            // The seat is gone with the display server, so the wm may forget the state it keeps for the seat, such
+            // as the focus, before it is stopped.
+            self.comp.send_wm(WmEvent::RemovedSeat(self.comp.wm.seat()));
+
             // Signal the event loop to stop
             self.signal.stop();
             // In order to terminate the event loop quickly after stopping it, we need to wake the event loop.
@@ -207,6 +910,18 @@ impl Loop {
     }
 }
 
+/// The status the wm is told about a process it spawned once the process exited.
+fn process_status(status: io::Result<ExitStatus>) -> ProcessStatus {
+    match status {
+        Ok(status) => match (status.code(), status.signal()) {
+            (Some(code), _) => ProcessStatus::Exited(code),
+            (None, Some(signal)) => ProcessStatus::Signaled(signal),
+            (None, None) => ProcessStatus::Failed(format!("The process exited with an unknown status: {status}")),
+        },
+        Err(err) => ProcessStatus::Failed(format!("Failed to wait for the process: {err}")),
+    }
+}
+
 fn register_display_source(display: Display<Aerugo>, r#loop: &LoopHandle<'static, Loop>) {
     r#loop
         .insert_source(
@@ -223,28 +938,143 @@ fn register_display_source(display: Display<Aerugo>, r#loop: &LoopHandle<'static
         .unwrap();
 }
 
-fn register_listening_socket(r#loop: &LoopHandle<'static, Loop>) {
-    let listening_socket = ListeningSocketSource::new_auto().expect("Failed to bind a socket");
+/// The socket clients connect to.
+///
+/// The name of the socket is reserved by a lock on `<socket>.lock` in `XDG_RUNTIME_DIR`, like libwayland does. The
+/// socket and the lock file are removed once the listener is dropped when the display server exits. Restarting in
+/// place executes the new process without dropping the listener, so the new process inherits both.
+#[derive(Debug)]
+struct Listener {
+    listener: UnixListener,
+    lock: OwnedFd,
+    path: PathBuf,
+}
+
+impl Listener {
+    /// Bind a new socket with the name.
+    fn bind(name: &str) -> io::Result<Self> {
+        let path = socket_path(name)?;
+        let lock = lock_socket(&path)?;
+
+        // A socket left behind by a display server which did not exit cleanly is unused, since the lock is held.
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, lock, path })
+    }
+
+    /// Listen on the socket inherited from the previous process when restarting in place.
+    ///
+    /// The lock is taken again if the previous process did not pass the lock on.
+    fn inherited(name: &str, listener: OwnedFd, lock: Option<OwnedFd>) -> io::Result<Self> {
+        let path = socket_path(name)?;
+        let lock = match lock {
+            Some(lock) => lock,
+            None => lock_socket(&path)?,
+        };
+
+        let listener = UnixListener::from(listener);
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, lock, path })
+    }
 
-    let socket = listening_socket.socket_name().to_owned();
-    tracing::info!("Bound Wayland socket: {:?}", socket);
+    fn accept(&self) -> io::Result<Option<UnixStream>> {
+        match self.listener.accept() {
+            Ok((stream, _)) => Ok(Some(stream)),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl AsFd for Listener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        let _ = fs::remove_file(lock_path(&self.path));
+    }
+}
+
+/// The path of the socket with the name in `XDG_RUNTIME_DIR`.
+fn socket_path(name: &str) -> io::Result<PathBuf> {
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set"))?;
+    Ok(PathBuf::from(runtime_dir).join(name))
+}
+
+fn lock_path(socket: &Path) -> PathBuf {
+    let mut path = socket.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+/// Lock the lock file of the socket, failing if another display server holds the lock.
+fn lock_socket(socket: &Path) -> io::Result<OwnedFd> {
+    let lock = rustix::fs::open(
+        lock_path(socket),
+        rustix::fs::OFlags::CREATE | rustix::fs::OFlags::RDWR | rustix::fs::OFlags::CLOEXEC,
+        rustix::fs::Mode::from_raw_mode(0o660),
+    )?;
+    rustix::fs::flock(&lock, rustix::fs::FlockOperation::NonBlockingLockExclusive)?;
+    Ok(lock)
+}
+
+/// Register the listening socket, binding a new socket unless one was inherited from the previous process.
+///
+/// Returns duplicates of the listening socket and its lock file, and the name of the socket.
+fn register_listening_socket(
+    r#loop: &LoopHandle<'static, Loop>,
+    inherited: Option<(OwnedFd, Option<OwnedFd>, String)>,
+) -> (OwnedFd, OwnedFd, OsString) {
+    let (listener, socket) = match inherited {
+        Some((fd, lock, socket)) => {
+            tracing::info!("Inherited Wayland socket: {:?}", socket);
+            let listener = Listener::inherited(&socket, fd, lock).expect("Failed to listen on the inherited socket");
+            (listener, socket)
+        }
+
+        None => {
+            let (listener, socket) = (1..33)
+                .find_map(|display| {
+                    let socket = format!("wayland-{display}");
+                    Listener::bind(&socket).ok().map(|listener| (listener, socket))
+                })
+                .expect("Failed to bind a socket");
+            tracing::info!("Bound Wayland socket: {:?}", socket);
+            (listener, socket)
+        }
+    };
+
+    let duplicate = listener
+        .as_fd()
+        .try_clone_to_owned()
+        .expect("Failed to duplicate the listening socket");
+    let lock = listener
+        .lock
+        .try_clone()
+        .expect("Failed to duplicate the lock of the listening socket");
 
     r#loop
-        .insert_source(listening_socket, |client, _, state| {
-            let info = format!("{client:?}");
-
-            // TODO: Graceful error handling
-            if let Err(err) = state.display.insert_client(
-                client,
-                Arc::new(ClientData {
-                    // TODO: Limit the available globals
-                    globals: PrivilegedGlobals::all(),
-                    compositor: CompositorClientState::default(),
-                }),
-            ) {
-                // TODO: Provide info about the socket (name)
-                tracing::error!(%err, "Failed to register client with fd: {info}");
-            }
-        })
+        .insert_source(
+            Generic::new(listener, Interest::READ, Mode::Level),
+            move |_, listener, state| {
+                while let Some(client) = listener.as_ref().accept()? {
+                    state.insert_client(client);
+                }
+
+                Ok(PostAction::Continue)
+            },
+        )
         .unwrap();
+
+    (duplicate, lock, OsString::from(socket))
 }
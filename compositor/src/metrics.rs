@@ -0,0 +1,521 @@
+//! Metrics
+//!
+//! Kiosks and signage fleets are monitored like any other service. If an endpoint is configured, the display
+//! server collects frame times, missed frames, the number of connected clients, memory usage and input latency,
+//! and serves them in the OpenMetrics text format on a local TCP address or Unix socket. Nothing is collected
+//! unless an endpoint is configured.
+//!
+//! Every connection to the endpoint is answered with the current metrics as an HTTP response, which is what
+//! Prometheus expects from a scrape target. Connections are answered on another thread so a slow scraper never
+//! stalls the event loop. Only a few scrapes are answered at once, further connections are closed immediately.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    fs,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::{
+            fs::FileTypeExt,
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction};
+use smithay::output::Output;
+
+use crate::{Aerugo, Loop};
+
+/// How many recent samples quantiles are computed from.
+pub const WINDOW: usize = 1024;
+
+/// The quantiles reported for frame times and input latency.
+pub(crate) const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+/// How long a scraper may take to send its request.
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many scrapes are answered at once.
+const MAX_SCRAPES: usize = 4;
+
+/// Where metrics are served.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetricsEndpoint {
+    /// A TCP address, such as `127.0.0.1:9100`.
+    Tcp(SocketAddr),
+
+    /// A Unix socket, written as `unix:/run/user/1000/aerugo-metrics`.
+    Unix(PathBuf),
+}
+
+impl FromStr for MetricsEndpoint {
+    type Err = String;
+
+    fn from_str(endpoint: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = endpoint.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("missing socket path".into());
+            }
+
+            return Ok(Self::Unix(path.into()));
+        }
+
+        endpoint
+            .parse()
+            .map(Self::Tcp)
+            .map_err(|err| format!("invalid address {endpoint:?}: {err}"))
+    }
+}
+
+impl fmt::Display for MetricsEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Configuration of the metrics endpoint.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MetricsConfig {
+    /// Where metrics are served, or [`None`] to disable metrics.
+    pub endpoint: Option<MetricsEndpoint>,
+}
+
+/// Recent durations and the totals of every duration.
+#[derive(Debug, Default)]
+pub struct Samples {
+    recent: VecDeque<Duration>,
+    count: u64,
+    sum: Duration,
+}
+
+impl Samples {
+    pub fn push(&mut self, sample: Duration) {
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+
+        self.recent.push_back(sample);
+        self.count += 1;
+        self.sum += sample;
+    }
+
+    /// The number of every sample.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The mean of every sample, or [`None`] if there are no samples.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count).ok().filter(|&count| count > 0)?;
+        Some(self.sum / count)
+    }
+
+    /// The quantile of the recent samples using the nearest rank, or [`None`] if there are no samples.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let mut sorted = self.recent.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        let rank = (quantile * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.saturating_sub(1)).copied()
+    }
+
+    /// Write the samples as an OpenMetrics summary.
+    fn encode(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# TYPE {name} summary");
+        let _ = writeln!(out, "# UNIT {name} seconds");
+        let _ = writeln!(out, "# HELP {name} {help}");
+
+        for quantile in QUANTILES {
+            if let Some(value) = self.quantile(quantile) {
+                let _ = writeln!(out, "{name}{{quantile=\"{quantile}\"}} {}", value.as_secs_f64());
+            }
+        }
+
+        let _ = writeln!(out, "{name}_count {}", self.count);
+        let _ = writeln!(out, "{name}_sum {}", self.sum.as_secs_f64());
+    }
+}
+
+/// Counts the connected clients.
+///
+/// Each client holds a [`ClientGuard`] in its client data, which is dropped when the client is destroyed.
+#[derive(Debug, Clone, Default)]
+pub struct ClientCounter(Arc<AtomicUsize>);
+
+impl ClientCounter {
+    pub fn connected(&self) -> ClientGuard {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        ClientGuard(self.0.clone())
+    }
+
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub struct ClientGuard(Arc<AtomicUsize>);
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Metrics collected while an endpoint is configured.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    frame_times: Samples,
+    missed_frames: u64,
+    input_latency: Samples,
+}
+
+impl Metrics {
+    /// A frame took the duration to draw. The frame is missed if drawing took longer than the refresh interval.
+    pub fn frame_drawn(&mut self, duration: Duration, refresh_interval: Option<Duration>) {
+        self.frame_times.push(duration);
+
+        if refresh_interval.is_some_and(|interval| duration > interval) {
+            self.missed_frames += 1;
+        }
+    }
+
+    /// The time between the kernel receiving an input event and the event being dispatched.
+    pub fn input_dispatched(&mut self, latency: Duration) {
+        self.input_latency.push(latency);
+    }
+
+    /// Encode the metrics in the OpenMetrics text format.
+    pub fn encode(&self, clients: usize, resident_memory: Option<u64>) -> String {
+        let mut out = String::new();
+
+        self.frame_times
+            .encode(&mut out, "aerugo_frame_time_seconds", "Time taken to draw a frame.");
+
+        let _ = writeln!(out, "# TYPE aerugo_missed_frames counter");
+        let _ = writeln!(
+            out,
+            "# HELP aerugo_missed_frames Frames which took longer than the refresh interval."
+        );
+        let _ = writeln!(out, "aerugo_missed_frames_total {}", self.missed_frames);
+
+        let _ = writeln!(out, "# TYPE aerugo_clients gauge");
+        let _ = writeln!(out, "# HELP aerugo_clients Connected Wayland clients.");
+        let _ = writeln!(out, "aerugo_clients {clients}");
+
+        if let Some(bytes) = resident_memory {
+            let _ = writeln!(out, "# TYPE aerugo_resident_memory_bytes gauge");
+            let _ = writeln!(out, "# UNIT aerugo_resident_memory_bytes bytes");
+            let _ = writeln!(
+                out,
+                "# HELP aerugo_resident_memory_bytes Resident memory of the display server."
+            );
+            let _ = writeln!(out, "aerugo_resident_memory_bytes {bytes}");
+        }
+
+        self.input_latency.encode(
+            &mut out,
+            "aerugo_input_latency_seconds",
+            "Time between the kernel receiving an input event and the event being dispatched.",
+        );
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// The resident memory of the display server in bytes.
+fn resident_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find_map(|line| line.strip_prefix("VmRSS:"))?;
+    let kib = line.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kib * 1024)
+}
+
+impl Aerugo {
+    /// Called by the backend after a frame was submitted to the output.
+    pub fn frame_drawn(&mut self, output: &Output, duration: Duration) {
+        // Debug reports include frame statistics even if metrics are not collected.
+        self.frame_stats.push(duration);
+
+        let Some(metrics) = &mut self.metrics else {
+            return;
+        };
+
+        let refresh_interval = output
+            .current_mode()
+            .filter(|mode| mode.refresh > 0)
+            .map(|mode| Duration::from_secs_f64(1000.0 / mode.refresh as f64));
+
+        metrics.frame_drawn(duration, refresh_interval);
+    }
+
+    /// Record the time between an input event and when it was dispatched to the wm or clients.
+    pub fn input_dispatched(&mut self, latency: Duration) {
+        if let Some(metrics) = &mut self.metrics {
+            metrics.input_dispatched(latency);
+        }
+    }
+
+    /// The current metrics in the OpenMetrics text format, or [`None`] if metrics are disabled.
+    pub fn encode_metrics(&self) -> Option<String> {
+        let metrics = self.metrics.as_ref()?;
+        Some(metrics.encode(self.clients.get(), resident_memory()))
+    }
+}
+
+#[derive(Debug)]
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    fn bind(endpoint: &MetricsEndpoint) -> io::Result<Self> {
+        let listener = match endpoint {
+            MetricsEndpoint::Tcp(addr) => Self::Tcp(TcpListener::bind(addr)?),
+
+            MetricsEndpoint::Unix(path) => {
+                // Remove the socket left behind by a previous instance, but never anything else at the path.
+                match fs::symlink_metadata(path) {
+                    Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+                    Ok(_) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{} exists and is not a socket", path.display()),
+                        ))
+                    }
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    Err(_) => {}
+                }
+
+                Self::Unix(UnixListener::bind(path)?)
+            }
+        };
+
+        match &listener {
+            Self::Tcp(listener) => listener.set_nonblocking(true)?,
+            Self::Unix(listener) => listener.set_nonblocking(true)?,
+        }
+
+        Ok(listener)
+    }
+
+    /// Accept a pending connection and answer with the metrics on another thread.
+    ///
+    /// The connection is closed without an answer if [`MAX_SCRAPES`] scrapes are already being answered.
+    fn answer(&self, scrapes: &ScrapeCounter, metrics: impl FnOnce() -> String) -> io::Result<()> {
+        let result = match self {
+            Self::Tcp(listener) => listener.accept().map(|(stream, _)| Stream::Tcp(stream)),
+            Self::Unix(listener) => listener.accept().map(|(stream, _)| Stream::Unix(stream)),
+        };
+        let mut stream = result?;
+
+        let Some(guard) = scrapes.start() else {
+            tracing::debug!("Too many metrics scrapes, closing connection");
+            return Ok(());
+        };
+
+        let metrics = metrics();
+        thread::Builder::new().name("Metrics scrape".into()).spawn(move || {
+            if let Err(err) = stream.respond(&metrics) {
+                tracing::debug!(%err, "Failed to answer metrics scrape");
+            }
+
+            drop(guard);
+        })?;
+
+        Ok(())
+    }
+}
+
+impl AsFd for Listener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Self::Tcp(listener) => listener.as_fd(),
+            Self::Unix(listener) => listener.as_fd(),
+        }
+    }
+}
+
+/// Counts the scrapes which are being answered.
+#[derive(Debug, Default)]
+struct ScrapeCounter(Arc<AtomicUsize>);
+
+impl ScrapeCounter {
+    /// Start answering a scrape, or [`None`] if too many scrapes are being answered.
+    fn start(&self) -> Option<ClientGuard> {
+        self.0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |scrapes| {
+                (scrapes < MAX_SCRAPES).then_some(scrapes + 1)
+            })
+            .ok()?;
+
+        Some(ClientGuard(self.0.clone()))
+    }
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn respond(&mut self, metrics: &str) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => {
+                stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+                stream.set_write_timeout(Some(SCRAPE_TIMEOUT))?;
+                respond(stream, metrics)
+            }
+
+            Self::Unix(stream) => {
+                stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+                stream.set_write_timeout(Some(SCRAPE_TIMEOUT))?;
+                respond(stream, metrics)
+            }
+        }
+    }
+}
+
+/// Read the request head and write the metrics as the response.
+///
+/// Every request is answered with the metrics, so the request is not parsed.
+fn respond(stream: &mut (impl Read + Write), metrics: &str) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+
+    while !request.windows(4).any(|end| end == b"\r\n\r\n") && request.len() < 8192 {
+        let read = stream.read(&mut buf)?;
+
+        if read == 0 {
+            break;
+        }
+
+        request.extend_from_slice(&buf[..read]);
+    }
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/openmetrics-text; version=1.0.0; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{metrics}",
+        metrics.len()
+    )?;
+    stream.flush()
+}
+
+/// Serve metrics on the endpoint.
+pub(crate) fn register_metrics_endpoint(
+    r#loop: &LoopHandle<'static, Loop>,
+    endpoint: &MetricsEndpoint,
+) -> io::Result<()> {
+    let listener = Listener::bind(endpoint)?;
+    let scrapes = ScrapeCounter::default();
+    tracing::info!(%endpoint, "Serving metrics");
+
+    r#loop
+        .insert_source(
+            Generic::new(listener, Interest::READ, Mode::Level),
+            move |_, listener, state| {
+                if state.comp.metrics.is_none() {
+                    return Ok(PostAction::Remove);
+                }
+
+                let metrics = || state.comp.encode_metrics().unwrap_or_default();
+
+                if let Err(err) = listener.as_ref().answer(&scrapes, metrics) {
+                    if err.kind() != io::ErrorKind::WouldBlock {
+                        tracing::warn!(%err, "Failed to accept metrics scrape");
+                    }
+                }
+
+                Ok(PostAction::Continue)
+            },
+        )
+        .map_err(|err| err.error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Metrics, MetricsEndpoint, Samples, ScrapeCounter, MAX_SCRAPES, WINDOW};
+
+    #[test]
+    fn quantiles() {
+        let mut samples = Samples::default();
+        assert_eq!(samples.quantile(0.5), None);
+
+        for ms in 1..=100 {
+            samples.push(Duration::from_millis(ms));
+        }
+
+        assert_eq!(samples.quantile(0.5), Some(Duration::from_millis(50)));
+        assert_eq!(samples.quantile(0.99), Some(Duration::from_millis(99)));
+        assert_eq!(samples.quantile(1.0), Some(Duration::from_millis(100)));
+
+        // Old samples leave the window, but still count towards the totals.
+        for _ in 0..WINDOW {
+            samples.push(Duration::from_millis(1));
+        }
+
+        assert_eq!(samples.quantile(0.99), Some(Duration::from_millis(1)));
+        assert_eq!(samples.count, 100 + WINDOW as u64);
+    }
+
+    #[test]
+    fn scrape_limit() {
+        let scrapes = ScrapeCounter::default();
+        let guards = (0..MAX_SCRAPES).map(|_| scrapes.start()).collect::<Option<Vec<_>>>();
+        assert!(guards.is_some());
+        assert!(scrapes.start().is_none());
+
+        drop(guards);
+        assert!(scrapes.start().is_some());
+    }
+
+    #[test]
+    fn encode() {
+        let mut metrics = Metrics::default();
+        let interval = Some(Duration::from_micros(16_667));
+        metrics.frame_drawn(Duration::from_millis(4), interval);
+        metrics.frame_drawn(Duration::from_millis(20), interval);
+
+        let encoded = metrics.encode(3, Some(4096));
+        let lines = encoded.lines().collect::<Vec<_>>();
+
+        assert!(lines.contains(&"aerugo_frame_time_seconds{quantile=\"0.5\"} 0.004"));
+        assert!(lines.contains(&"aerugo_frame_time_seconds_count 2"));
+        assert!(lines.contains(&"aerugo_missed_frames_total 1"));
+        assert!(lines.contains(&"aerugo_clients 3"));
+        assert!(lines.contains(&"aerugo_resident_memory_bytes 4096"));
+        assert!(lines.contains(&"aerugo_input_latency_seconds_count 0"));
+        assert_eq!(lines.last(), Some(&"# EOF"));
+
+        assert_eq!(
+            "unix:/tmp/metrics".parse(),
+            Ok(MetricsEndpoint::Unix("/tmp/metrics".into()))
+        );
+        assert_eq!(
+            "127.0.0.1:9100".parse(),
+            Ok(MetricsEndpoint::Tcp(([127, 0, 0, 1], 9100).into()))
+        );
+        assert!("localhost".parse::<MetricsEndpoint>().is_err());
+    }
+}
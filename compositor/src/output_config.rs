@@ -0,0 +1,183 @@
+//! Output configuration
+//!
+//! The wm (or its configuration file) arranges outputs by changing their mode, scale, transform and position.
+//! Every change requested for an output is applied at once: if any part of the configuration is invalid or the
+//! backend cannot set the mode, nothing is changed. This avoids an output briefly showing a half applied
+//! configuration, such as a new mode with the scale intended for the old mode.
+//!
+//! # Subpixel layout
+//!
+//! The subpixel layout of an output is advertised through `wl_output` so clients can use subpixel antialiasing
+//! for text. The layout is read from the connector (which the kernel parses from the EDID), but panels often
+//! report an unknown layout, so the layout may be overridden by the configuration. The layout cannot change
+//! while the output exists, so the override must be known when the output is created.
+
+use smithay::{
+    output::{Mode, Output, Scale, Subpixel},
+    utils::{Logical, Point, Transform},
+};
+
+/// The largest scale an output may be configured with.
+pub const MAX_SCALE: f64 = 10.0;
+
+/// An error from configuring an output.
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    #[error("{0:?} is not a mode of the output")]
+    UnsupportedMode(Mode),
+
+    #[error("scale {0} must be greater than 0 and at most {MAX_SCALE}")]
+    InvalidScale(f64),
+
+    #[error("the backend failed to set {0:?}")]
+    ModesetFailed(Mode),
+}
+
+/// Changes to apply to an output.
+///
+/// Properties which are [`None`] are not changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OutputConfig {
+    pub mode: Option<Mode>,
+    pub scale: Option<f64>,
+    pub transform: Option<Transform>,
+    /// The position of the output in the global compositor space.
+    pub position: Option<Point<i32, Logical>>,
+}
+
+impl OutputConfig {
+    /// Check the configuration can be applied to the output.
+    pub fn validate(&self, output: &Output) -> Result<(), Error> {
+        self.validate_modes(&output.modes())
+    }
+
+    /// Apply the configuration to the output.
+    ///
+    /// The mode must already have been set by the backend.
+    pub fn apply(&self, output: &Output) {
+        output.change_current_state(
+            self.mode,
+            self.transform,
+            self.scale.map(Scale::Fractional),
+            self.position,
+        );
+    }
+
+    fn validate_modes(&self, modes: &[Mode]) -> Result<(), Error> {
+        if let Some(mode) = self.mode {
+            if !modes.contains(&mode) {
+                return Err(Error::UnsupportedMode(mode));
+            }
+        }
+
+        match self.scale {
+            Some(scale) if !(scale > 0.0 && scale <= MAX_SCALE) => Err(Error::InvalidScale(scale)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The subpixel layout of a connector, from the `subpixel` field of `drmModeConnector`.
+pub fn subpixel_from_drm(subpixel: u32) -> Subpixel {
+    match subpixel {
+        2 => Subpixel::HorizontalRgb,
+        3 => Subpixel::HorizontalBgr,
+        4 => Subpixel::VerticalRgb,
+        5 => Subpixel::VerticalBgr,
+        6 => Subpixel::None,
+        _ => Subpixel::Unknown,
+    }
+}
+
+/// Parse a subpixel layout from the configuration.
+///
+/// The names match those used by sway and wlr-randr: `rgb`, `bgr`, `vrgb`, `vbgr` and `none`.
+pub fn parse_subpixel(name: &str) -> Option<Subpixel> {
+    Some(match name {
+        "rgb" => Subpixel::HorizontalRgb,
+        "bgr" => Subpixel::HorizontalBgr,
+        "vrgb" => Subpixel::VerticalRgb,
+        "vbgr" => Subpixel::VerticalBgr,
+        "none" => Subpixel::None,
+        "unknown" => Subpixel::Unknown,
+        _ => return None,
+    })
+}
+
+/// The subpixel layout to advertise for an output, preferring the layout set in the configuration.
+pub fn output_subpixel(detected: Subpixel, configured: Option<Subpixel>) -> Subpixel {
+    configured.unwrap_or(detected)
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::{
+        output::{Mode, Subpixel},
+        utils::Transform,
+    };
+
+    use super::{output_subpixel, parse_subpixel, subpixel_from_drm, Error, OutputConfig};
+    use crate::test_util::mode;
+
+    fn modes() -> [Mode; 2] {
+        [mode(3840, 2160, 60_000), mode(1920, 1080, 144_000)]
+    }
+
+    #[test]
+    fn empty_config_is_valid() {
+        assert_eq!(OutputConfig::default().validate_modes(&[]), Ok(()));
+    }
+
+    #[test]
+    fn mode_must_be_advertised() {
+        let config = OutputConfig {
+            mode: Some(modes()[1]),
+            transform: Some(Transform::_90),
+            ..OutputConfig::default()
+        };
+        assert_eq!(config.validate_modes(&modes()), Ok(()));
+
+        let unsupported = mode(1920, 1080, 60_000);
+        let config = OutputConfig {
+            mode: Some(unsupported),
+            ..OutputConfig::default()
+        };
+        assert_eq!(
+            config.validate_modes(&modes()),
+            Err(Error::UnsupportedMode(unsupported))
+        );
+    }
+
+    #[test]
+    fn scale_range() {
+        for scale in [1.0, 1.25, 2.0] {
+            let config = OutputConfig {
+                scale: Some(scale),
+                ..OutputConfig::default()
+            };
+            assert_eq!(config.validate_modes(&modes()), Ok(()));
+        }
+
+        for scale in [0.0, -1.0, 11.0, f64::NAN] {
+            let config = OutputConfig {
+                scale: Some(scale),
+                ..OutputConfig::default()
+            };
+            assert!(matches!(config.validate_modes(&modes()), Err(Error::InvalidScale(_))));
+        }
+    }
+
+    #[test]
+    fn subpixel_override() {
+        assert_eq!(subpixel_from_drm(2), Subpixel::HorizontalRgb);
+        assert_eq!(subpixel_from_drm(1), Subpixel::Unknown);
+        assert_eq!(parse_subpixel("vbgr"), Some(Subpixel::VerticalBgr));
+        assert_eq!(parse_subpixel("RGB"), None);
+
+        assert_eq!(
+            output_subpixel(Subpixel::Unknown, parse_subpixel("bgr")),
+            Subpixel::HorizontalBgr
+        );
+        assert_eq!(output_subpixel(Subpixel::HorizontalRgb, None), Subpixel::HorizontalRgb);
+    }
+}
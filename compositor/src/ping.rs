@@ -0,0 +1,446 @@
+//! Client ping watchdog
+//!
+//! Every xdg shell client is periodically sent an `xdg_wm_base.ping`. A client which does not answer with a pong
+//! before the timeout is considered unresponsive and the wm is told, so it may grey out the toplevels of the client
+//! or offer to force quit it. Once the client answers again, the wm is told the client is responsive.
+//!
+//! A client may still answer pings from another thread while the thread drawing a toplevel is stuck, so a toplevel
+//! which does not ack a configure before the same timeout is also unresponsive. The display server dims the
+//! toplevels of unresponsive clients and unresponsive toplevels until they respond again.
+//!
+//! ```text
+//! Waiting ---(interval)---> Pinged ---(timeout)---> Unresponsive
+//!    ^                        |                          |
+//!    \---------(pong)---------/----------(pong)----------/
+//! ```
+
+use std::time::{Duration, Instant};
+
+use smithay::{
+    utils::{Serial, SERIAL_COUNTER},
+    wayland::shell::xdg::ShellClient,
+};
+use wm_runtime::WmEvent;
+
+use crate::{shell::ToplevelId, Aerugo};
+
+/// How much unresponsive toplevels are darkened.
+pub const UNRESPONSIVE_DIM: f32 = 0.5;
+
+/// Configuration of the ping watchdog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingConfig {
+    /// How long to wait after a pong before pinging the client again.
+    pub interval: Duration,
+
+    /// How long the client has to answer a ping or ack a configure before it is unresponsive.
+    pub timeout: Duration,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// What must be done for a client after the watchdog is updated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PingAction<C> {
+    /// Send a ping to the client.
+    Ping(C),
+
+    /// The client did not answer the ping in time.
+    Unresponsive(C),
+}
+
+#[derive(Debug)]
+struct ClientPing<C> {
+    client: C,
+
+    /// When the unanswered ping was sent.
+    sent: Option<Instant>,
+
+    /// When the next ping is sent if no ping is outstanding.
+    next_ping: Instant,
+
+    unresponsive: bool,
+}
+
+/// Tracks the outstanding pings of every client.
+///
+/// The watchdog does not send pings itself so it can be tested without clients.
+#[derive(Debug)]
+pub struct PingWatchdog<C> {
+    config: PingConfig,
+    clients: Vec<ClientPing<C>>,
+}
+
+impl<C: Clone + PartialEq> PingWatchdog<C> {
+    pub fn new(config: PingConfig) -> Self {
+        Self {
+            config,
+            clients: Vec::new(),
+        }
+    }
+
+    /// Start watching a client.
+    ///
+    /// The first ping is sent after the interval, giving the client time to start up.
+    pub fn add(&mut self, client: C, now: Instant) {
+        self.clients.push(ClientPing {
+            client,
+            sent: None,
+            next_ping: now + self.config.interval,
+            unresponsive: false,
+        });
+    }
+
+    /// Stop watching every client the function returns false for, such as disconnected clients.
+    pub fn retain(&mut self, mut f: impl FnMut(&C) -> bool) {
+        self.clients.retain(|ping| f(&ping.client));
+    }
+
+    /// The client answered the outstanding ping.
+    ///
+    /// Returns true if the client was unresponsive.
+    pub fn pong(&mut self, client: &C, now: Instant) -> bool {
+        let Some(ping) = self.clients.iter_mut().find(|ping| ping.client == *client) else {
+            return false;
+        };
+
+        ping.sent = None;
+        ping.next_ping = now + self.config.interval;
+        std::mem::take(&mut ping.unresponsive)
+    }
+
+    pub fn is_unresponsive(&self, client: &C) -> bool {
+        self.clients
+            .iter()
+            .any(|ping| ping.client == *client && ping.unresponsive)
+    }
+
+    /// Advance the watchdog to the specified time.
+    pub fn update(&mut self, now: Instant) -> Vec<PingAction<C>> {
+        let mut actions = Vec::new();
+
+        for ping in &mut self.clients {
+            match ping.sent {
+                Some(sent) if !ping.unresponsive && now >= sent + self.config.timeout => {
+                    ping.unresponsive = true;
+                    actions.push(PingAction::Unresponsive(ping.client.clone()));
+                }
+
+                Some(_) => (),
+
+                None if now >= ping.next_ping => {
+                    ping.sent = Some(now);
+                    actions.push(PingAction::Ping(ping.client.clone()));
+                }
+
+                None => (),
+            }
+        }
+
+        actions
+    }
+
+    /// The next time [`PingWatchdog::update`] must be called.
+    ///
+    /// Unresponsive clients are not pinged again until they answer the outstanding ping, so they need no timer.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.clients
+            .iter()
+            .filter_map(|ping| match ping.sent {
+                Some(_) if ping.unresponsive => None,
+                Some(sent) => Some(sent + self.config.timeout),
+                None => Some(ping.next_ping),
+            })
+            .min()
+    }
+}
+
+#[derive(Debug)]
+struct PendingConfigure<T> {
+    toplevel: T,
+
+    /// When the oldest configure which was not acked was sent.
+    sent: Instant,
+
+    unresponsive: bool,
+}
+
+/// Tracks configures which the toplevels have not acked.
+#[derive(Debug)]
+pub struct ConfigureWatchdog<T> {
+    timeout: Duration,
+    pending: Vec<PendingConfigure<T>>,
+}
+
+impl<T: Clone + PartialEq> ConfigureWatchdog<T> {
+    pub fn new(config: PingConfig) -> Self {
+        Self {
+            timeout: config.timeout,
+            pending: Vec::new(),
+        }
+    }
+
+    /// A configure was sent to the toplevel.
+    ///
+    /// The timeout starts at the oldest configure which was not acked.
+    pub fn sent(&mut self, toplevel: T, now: Instant) {
+        if !self.pending.iter().any(|pending| pending.toplevel == toplevel) {
+            self.pending.push(PendingConfigure {
+                toplevel,
+                sent: now,
+                unresponsive: false,
+            });
+        }
+    }
+
+    /// The toplevel acked a configure.
+    ///
+    /// Acking the latest configure implicitly acks the previous configures. Returns true if the toplevel was
+    /// unresponsive.
+    pub fn acked(&mut self, toplevel: &T) -> bool {
+        let Some(index) = self.pending.iter().position(|pending| pending.toplevel == *toplevel) else {
+            return false;
+        };
+
+        self.pending.swap_remove(index).unresponsive
+    }
+
+    /// Stop waiting for a destroyed toplevel.
+    pub fn remove(&mut self, toplevel: &T) {
+        self.pending.retain(|pending| pending.toplevel != *toplevel);
+    }
+
+    pub fn is_unresponsive(&self, toplevel: &T) -> bool {
+        self.pending
+            .iter()
+            .any(|pending| pending.toplevel == *toplevel && pending.unresponsive)
+    }
+
+    /// The toplevels which did not ack a configure in time.
+    pub fn unresponsive(&self) -> impl Iterator<Item = &T> {
+        self.pending
+            .iter()
+            .filter(|pending| pending.unresponsive)
+            .map(|pending| &pending.toplevel)
+    }
+
+    /// Advance the watchdog to the specified time.
+    ///
+    /// Returns the toplevels which became unresponsive.
+    pub fn update(&mut self, now: Instant) -> Vec<T> {
+        self.pending
+            .iter_mut()
+            .filter(|pending| !pending.unresponsive && now >= pending.sent + self.timeout)
+            .map(|pending| {
+                pending.unresponsive = true;
+                pending.toplevel.clone()
+            })
+            .collect()
+    }
+
+    /// The next time [`ConfigureWatchdog::update`] must be called.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .iter()
+            .filter(|pending| !pending.unresponsive)
+            .map(|pending| pending.sent + self.timeout)
+            .min()
+    }
+}
+
+impl Aerugo {
+    /// Send pings which are due and notify the wm about clients which did not answer in time.
+    pub fn update_ping_watchdog(&mut self, now: Instant) {
+        self.ping_watchdog.retain(ShellClient::alive);
+
+        for action in self.ping_watchdog.update(now) {
+            match action {
+                PingAction::Ping(client) => {
+                    // Sending fails if the client disconnected or another ping is still pending.
+                    if let Err(err) = client.send_ping(SERIAL_COUNTER.next_serial()) {
+                        tracing::debug!(?err, "Failed to ping client");
+                    }
+                }
+
+                PingAction::Unresponsive(client) => {
+                    tracing::info!(?client, "Client is not responding");
+
+                    for id in self.client_toplevels(&client) {
+                        self.set_toplevel_unresponsive(id, true);
+                    }
+                }
+            }
+        }
+
+        for id in self.configure_watchdog.update(now) {
+            tracing::info!(id, "Toplevel did not ack a configure");
+            self.set_toplevel_unresponsive(id, true);
+        }
+    }
+
+    /// A configure which the client must ack was sent to the toplevel.
+    pub fn configure_sent(&mut self, id: ToplevelId, serial: Serial) {
+        self.configure_watchdog.sent(id, Instant::now());
+        self.transactions.configure_sent(id, serial);
+    }
+
+    /// The toplevel acked the configure with the serial.
+    pub fn configure_acked(&mut self, id: ToplevelId, serial: Serial) {
+        self.transactions.acked(id, serial);
+        self.wm_configure_acked(id, serial);
+
+        if self.configure_watchdog.acked(&id) && !self.is_toplevel_unresponsive(id) {
+            tracing::info!(id, "Toplevel is responding again");
+            self.set_toplevel_unresponsive(id, false);
+        }
+    }
+
+    /// Dim or undim an unresponsive toplevel and tell the wm, which may offer to force close the toplevel.
+    fn set_toplevel_unresponsive(&mut self, id: ToplevelId, unresponsive: bool) {
+        let dim = if unresponsive { UNRESPONSIVE_DIM } else { 0.0 };
+        self.set_toplevel_dim(id, dim);
+
+        if let Some(toplevel) = self.wm.toplevel_id(id) {
+            self.send_wm(match unresponsive {
+                true => WmEvent::ToplevelUnresponsive(toplevel),
+                false => WmEvent::ToplevelResponsive(toplevel),
+            });
+        }
+    }
+
+    /// Whether the toplevel did not ack a configure in time, or its client did not answer a ping in time.
+    pub fn is_toplevel_unresponsive(&self, id: ToplevelId) -> bool {
+        self.configure_watchdog.is_unresponsive(&id)
+            || self
+                .shell
+                .get_state(id)
+                .and_then(|toplevel| toplevel.xdg_toplevel()?.client())
+                .is_some_and(|client| self.ping_watchdog.is_unresponsive(&client))
+    }
+
+    /// The toplevels of the client.
+    fn client_toplevels(&self, client: &ShellClient) -> Vec<ToplevelId> {
+        self.shell
+            .toplevels
+            .iter()
+            .filter(|(_, toplevel)| {
+                toplevel.xdg_toplevel().and_then(|toplevel| toplevel.client()).as_ref() == Some(client)
+            })
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    /// Disconnect the client of a toplevel which is not responding.
+    ///
+    /// Returns false if the toplevel is responding, in which case the client is asked to close the toplevel
+    /// instead.
+    pub fn force_close(&mut self, id: ToplevelId) -> bool {
+        if !self.is_toplevel_unresponsive(id) {
+            self.close_toplevel(id);
+            return false;
+        }
+
+        self.kill_client(id);
+        true
+    }
+
+    /// The client answered a ping.
+    pub fn ping_answered(&mut self, client: &ShellClient) {
+        if self.ping_watchdog.pong(client, Instant::now()) {
+            tracing::info!(?client, "Client is responding again");
+
+            for id in self.client_toplevels(client) {
+                if !self.is_toplevel_unresponsive(id) {
+                    self.set_toplevel_unresponsive(id, false);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{ConfigureWatchdog, PingAction, PingConfig, PingWatchdog};
+
+    const CONFIG: PingConfig = PingConfig {
+        interval: Duration::from_secs(5),
+        timeout: Duration::from_secs(2),
+    };
+
+    #[test]
+    fn responsive_client() {
+        let start = Instant::now();
+        let mut watchdog = PingWatchdog::new(CONFIG);
+        watchdog.add(1, start);
+
+        assert!(watchdog.update(start + Duration::from_secs(4)).is_empty());
+        assert_eq!(watchdog.next_deadline(), Some(start + CONFIG.interval));
+
+        let pinged = start + CONFIG.interval;
+        assert_eq!(watchdog.update(pinged), [PingAction::Ping(1)]);
+        // The ping is not sent again while it is outstanding.
+        assert!(watchdog.update(pinged + Duration::from_secs(1)).is_empty());
+        assert_eq!(watchdog.next_deadline(), Some(pinged + CONFIG.timeout));
+
+        assert!(!watchdog.pong(&1, pinged + Duration::from_secs(1)));
+        assert!(!watchdog.is_unresponsive(&1));
+    }
+
+    #[test]
+    fn unresponsive_client() {
+        let start = Instant::now();
+        let mut watchdog = PingWatchdog::new(CONFIG);
+        watchdog.add(1, start);
+        watchdog.add(2, start);
+
+        let pinged = start + CONFIG.interval;
+        assert_eq!(watchdog.update(pinged), [PingAction::Ping(1), PingAction::Ping(2)]);
+        watchdog.pong(&2, pinged);
+
+        let timeout = pinged + CONFIG.timeout;
+        assert_eq!(watchdog.update(timeout), [PingAction::Unresponsive(1)]);
+        assert!(watchdog.is_unresponsive(&1));
+        // The wm is only told once.
+        assert!(watchdog.update(timeout + Duration::from_secs(1)).is_empty());
+
+        assert!(watchdog.pong(&1, timeout + Duration::from_secs(10)));
+        assert!(!watchdog.is_unresponsive(&1));
+
+        watchdog.retain(|&client| client != 1);
+        assert!(!watchdog.pong(&1, timeout + Duration::from_secs(11)));
+    }
+
+    #[test]
+    fn unacked_configure() {
+        let start = Instant::now();
+        let mut watchdog = ConfigureWatchdog::new(CONFIG);
+
+        watchdog.sent(1, start);
+        // The timeout starts at the oldest configure.
+        watchdog.sent(1, start + Duration::from_secs(1));
+        watchdog.sent(2, start + Duration::from_secs(1));
+        assert_eq!(watchdog.next_deadline(), Some(start + CONFIG.timeout));
+
+        assert!(!watchdog.acked(&2));
+        assert_eq!(watchdog.update(start + CONFIG.timeout), [1]);
+        assert!(watchdog.is_unresponsive(&1));
+        assert!(watchdog.update(start + CONFIG.timeout * 2).is_empty());
+        assert_eq!(watchdog.next_deadline(), None);
+
+        assert!(watchdog.acked(&1));
+        assert!(!watchdog.is_unresponsive(&1));
+
+        watchdog.sent(3, start);
+        watchdog.remove(&3);
+        assert!(watchdog.update(start + CONFIG.timeout).is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+//! Placeholder frames
+//!
+//! A slow client may take a while after the wm gives its toplevel a geometry before it commits its first buffer.
+//! A tiled layout would show a hole in the meantime, so the display server may draw a placeholder with a spinner in
+//! the geometry of the toplevel. Placeholders are drawn above the tree presented by the wm, and a placeholder is
+//! replaced by the toplevel as soon as the client commits its first buffer.
+//!
+//! Placeholders only appear after a timeout, so clients which start quickly never flash a placeholder.
+
+use std::{
+    f64::consts::TAU,
+    time::{Duration, Instant},
+};
+
+use rustc_hash::FxHashMap;
+use smithay::utils::{Logical, Rectangle, Size};
+
+use crate::{
+    scene::{NodeIndex, Shape, ShapeIndex},
+    shell::ToplevelId,
+    Aerugo,
+};
+
+/// How long one turn of the spinner takes.
+pub const SPINNER_PERIOD: Duration = Duration::from_secs(1);
+
+/// The radius of the circle the dot of the spinner moves along.
+const SPINNER_RADIUS: f64 = 16.0;
+
+/// The size of the dot of the spinner.
+const SPINNER_DOT: i32 = 8;
+
+const BACKGROUND: [f32; 4] = [0.12, 0.12, 0.12, 1.0];
+const SPINNER: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+/// Configuration of placeholder frames.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlaceholderConfig {
+    /// How long to wait for the first buffer before a placeholder is drawn, or [`None`] to never draw
+    /// placeholders.
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    geometry: Rectangle<i32, Logical>,
+
+    /// When the wm first gave the toplevel a geometry.
+    allotted: Instant,
+}
+
+/// Tracks toplevels which are waiting for their first buffer.
+#[derive(Debug)]
+pub struct Placeholders {
+    config: PlaceholderConfig,
+    pending: FxHashMap<ToplevelId, Pending>,
+
+    /// The shapes drawing the visible placeholders.
+    shapes: Vec<ShapeIndex>,
+}
+
+impl Placeholders {
+    pub fn new(config: PlaceholderConfig) -> Self {
+        Self {
+            config,
+            pending: FxHashMap::default(),
+            shapes: Vec::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: PlaceholderConfig) {
+        self.config = config;
+    }
+
+    /// The wm gave a toplevel which has no buffer yet a geometry.
+    ///
+    /// The timeout starts when the toplevel is first given a geometry. Later calls only move the placeholder.
+    pub fn allot(&mut self, id: ToplevelId, geometry: Rectangle<i32, Logical>, now: Instant) {
+        self.pending
+            .entry(id)
+            .and_modify(|pending| pending.geometry = geometry)
+            .or_insert(Pending {
+                geometry,
+                allotted: now,
+            });
+    }
+
+    /// The client committed the first buffer of the toplevel.
+    ///
+    /// Returns the geometry of the placeholder if a placeholder was visible, so the area can be damaged.
+    pub fn mapped(&mut self, id: ToplevelId, now: Instant) -> Option<Rectangle<i32, Logical>> {
+        let pending = self.pending.remove(&id)?;
+        let timeout = self.config.timeout?;
+        (now >= pending.allotted + timeout).then_some(pending.geometry)
+    }
+
+    /// Stop waiting for a destroyed toplevel.
+    pub fn remove(&mut self, id: ToplevelId) {
+        self.pending.remove(&id);
+    }
+
+    /// The placeholders to draw and the rotation of their spinners in turns, from 0 to 1.
+    pub fn visible(&self, now: Instant) -> Vec<(ToplevelId, Rectangle<i32, Logical>, f64)> {
+        let Some(timeout) = self.config.timeout else {
+            return Vec::new();
+        };
+
+        let mut visible = self
+            .pending
+            .iter()
+            .filter_map(|(&id, pending)| {
+                let elapsed = now.checked_duration_since(pending.allotted + timeout)?;
+                let spinner = elapsed.as_secs_f64() / SPINNER_PERIOD.as_secs_f64();
+                Some((id, pending.geometry, spinner.fract()))
+            })
+            .collect::<Vec<_>>();
+
+        visible.sort_by_key(|&(id, _, _)| id);
+        visible
+    }
+
+    /// The next time a placeholder appears.
+    pub fn next_deadline(&self, now: Instant) -> Option<Instant> {
+        let timeout = self.config.timeout?;
+
+        self.pending
+            .values()
+            .map(|pending| pending.allotted + timeout)
+            .filter(|&shown| shown > now)
+            .min()
+    }
+}
+
+impl Aerugo {
+    /// The wm configured a toplevel with a size.
+    ///
+    /// If the client has not committed a buffer yet, the placeholder is drawn where the wm placed the toplevel.
+    pub fn allot_placeholder(&mut self, id: ToplevelId, size: Size<i32, Logical>) {
+        if self.shell.get_state(id).map_or(true, |toplevel| toplevel.is_mapped()) {
+            return;
+        }
+
+        let Some(NodeIndex::SurfaceTree(index)) = self.toplevel_node(id) else {
+            return;
+        };
+
+        let scale = self.output.current_scale().fractional_scale();
+        let location = self
+            .scene
+            .surface_tree_location(index)
+            .to_f64()
+            .to_logical(scale)
+            .to_i32_round();
+        self.placeholders
+            .allot(id, Rectangle::from_loc_and_size(location, size), Instant::now());
+    }
+
+    /// Draw the visible placeholders, and keep drawing frames while a spinner is visible.
+    pub fn update_placeholders(&mut self, now: Instant) {
+        let visible = self.placeholders.visible(now);
+
+        if visible.is_empty() && self.placeholders.shapes.is_empty() {
+            return;
+        }
+
+        for shape in self.placeholders.shapes.drain(..) {
+            self.scene.destroy_shape(shape);
+        }
+
+        let branch = self.layer_branches().placeholders();
+        let scale = self.output.current_scale().integer_scale();
+
+        for (_, geometry, spinner) in visible {
+            let shapes = [
+                (geometry, Shape::Solid(BACKGROUND)),
+                (spinner_geometry(geometry, spinner), Shape::Solid(SPINNER)),
+            ];
+
+            for (geometry, shape) in shapes {
+                let geometry = geometry.to_physical(scale);
+                let index = self.scene.create_shape(geometry.size, shape);
+                self.scene.set_node_offset(NodeIndex::Shape(index), geometry.loc);
+                let _ = self.scene.branch_add_child(branch, NodeIndex::Shape(index));
+                self.placeholders.shapes.push(index);
+            }
+        }
+
+        // The spinner turns every frame.
+        if !self.placeholders.shapes.is_empty() {
+            self.frame_requested = true;
+        }
+
+        self.scene_changed();
+    }
+}
+
+/// The dot of the spinner of the placeholder with the geometry, after the turns.
+///
+/// The dot circles the center of the placeholder clockwise, starting at the top.
+fn spinner_geometry(geometry: Rectangle<i32, Logical>, turns: f64) -> Rectangle<i32, Logical> {
+    let angle = turns * TAU;
+    let center_x = geometry.loc.x as f64 + geometry.size.w as f64 / 2.0 + SPINNER_RADIUS * angle.sin();
+    let center_y = geometry.loc.y as f64 + geometry.size.h as f64 / 2.0 - SPINNER_RADIUS * angle.cos();
+    let half = SPINNER_DOT as f64 / 2.0;
+
+    Rectangle::from_loc_and_size(
+        ((center_x - half).round() as i32, (center_y - half).round() as i32),
+        (SPINNER_DOT, SPINNER_DOT),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use smithay::utils::Rectangle;
+
+    use crate::test_util::toplevel;
+
+    use super::{spinner_geometry, PlaceholderConfig, Placeholders, SPINNER_PERIOD};
+
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    #[test]
+    fn shown_after_timeout() {
+        let mut placeholders = Placeholders::new(PlaceholderConfig { timeout: Some(TIMEOUT) });
+        let start = Instant::now();
+        let id = toplevel(1);
+        let geometry = Rectangle::from_loc_and_size((0, 30), (960, 1050));
+
+        placeholders.allot(id, geometry, start);
+        assert!(placeholders.visible(start).is_empty());
+        assert_eq!(placeholders.next_deadline(start), Some(start + TIMEOUT));
+
+        // Moving the placeholder does not restart the timeout.
+        let moved = Rectangle::from_loc_and_size((960, 30), (960, 1050));
+        placeholders.allot(id, moved, start + Duration::from_millis(200));
+
+        let shown = start + TIMEOUT + SPINNER_PERIOD / 4;
+        assert_eq!(placeholders.visible(shown), [(id, moved, 0.25)]);
+        assert_eq!(placeholders.next_deadline(shown), None);
+
+        // The first buffer replaces the placeholder.
+        assert_eq!(placeholders.mapped(id, shown), Some(moved));
+        assert!(placeholders.visible(shown).is_empty());
+    }
+
+    #[test]
+    fn fast_clients_and_disabled() {
+        let start = Instant::now();
+        let id = toplevel(1);
+        let geometry = Rectangle::from_loc_and_size((0, 0), (800, 600));
+
+        // A client which commits before the timeout never shows a placeholder.
+        let mut placeholders = Placeholders::new(PlaceholderConfig { timeout: Some(TIMEOUT) });
+        placeholders.allot(id, geometry, start);
+        assert_eq!(placeholders.mapped(id, start + TIMEOUT / 2), None);
+
+        let mut placeholders = Placeholders::new(PlaceholderConfig::default());
+        placeholders.allot(id, geometry, start);
+        assert!(placeholders.visible(start + Duration::from_secs(10)).is_empty());
+        assert_eq!(placeholders.next_deadline(start), None);
+    }
+
+    #[test]
+    fn spinner_circles_center() {
+        let geometry = Rectangle::from_loc_and_size((100, 100), (200, 100));
+
+        assert_eq!(
+            spinner_geometry(geometry, 0.0),
+            Rectangle::from_loc_and_size((196, 130), (8, 8))
+        );
+        assert_eq!(
+            spinner_geometry(geometry, 0.25),
+            Rectangle::from_loc_and_size((212, 146), (8, 8))
+        );
+        assert_eq!(
+            spinner_geometry(geometry, 0.5),
+            Rectangle::from_loc_and_size((196, 162), (8, 8))
+        );
+    }
+}
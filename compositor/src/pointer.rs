@@ -0,0 +1,271 @@
+//! Pointer input
+//!
+//! Pointer events are offered to the wm before clients, so the wm may match pointer bindings and detect clicks. The
+//! wm replies to each event with a pointer filter deciding whether the event is sent to the client under the
+//! pointer.
+//!
+//! The pointer itself moves right away, so the cursor follows the pointer while the wm decides. The client under the
+//! pointer is looked up when the event is sent to clients, since the wm may have moved toplevels in the meantime.
+//!
+//! Events over the window switcher are handled by the display server and events over server side decorations are sent
+//! to the wm as decoration events, so neither reach clients.
+
+use std::time::Instant;
+
+use smithay::{
+    backend::input::{Axis, AxisSource, ButtonState},
+    input::pointer::{AxisFrame, ButtonEvent, MotionEvent},
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+use wayland_server::{protocol::wl_surface::WlSurface, Resource};
+use wm_runtime::{types, Id, WmEvent};
+
+use crate::{shell::Shell, wm, Aerugo};
+
+/// An event of the pointer of the seat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointerEvent {
+    /// The pointer moved to the location in the global compositor space.
+    Motion { time: u32, location: Point<f64, Logical> },
+
+    /// A button was pressed or released. The button is a linux evdev button code, such as `BTN_LEFT`.
+    Button { time: u32, button: u32, state: ButtonState },
+
+    /// The pointer was scrolled.
+    Axis {
+        time: u32,
+        source: AxisSource,
+        /// The horizontal and vertical scroll distance.
+        amount: (f64, f64),
+        /// The horizontal and vertical steps of a mouse wheel.
+        discrete: (Option<f64>, Option<f64>),
+    },
+}
+
+impl Aerugo {
+    /// An event of the pointer from an input device.
+    pub fn pointer_input(&mut self, event: PointerEvent) {
+        // The pointer stays on the output, so the location of motion is clamped.
+        let event = match event {
+            PointerEvent::Motion { time, location } => {
+                self.move_pointer(location);
+                PointerEvent::Motion {
+                    time,
+                    location: self.pointer_location,
+                }
+            }
+            event => event,
+        };
+
+        if let PointerEvent::Button { button, state, .. } = event {
+            match state {
+                ButtonState::Pressed if !self.pointer_buttons.contains(&button) => self.pointer_buttons.push(button),
+                ButtonState::Pressed => (),
+                ButtonState::Released => self.pointer_buttons.retain(|&pressed| pressed != button),
+            }
+        }
+
+        // An interactive move or resize consumes the motion of the pointer until every button is released. Buttons
+        // are still sent, so the client sees the release of the button it requested the move with.
+        if self.interactive_grab.is_some() {
+            if self.pointer_buttons.is_empty() {
+                self.interactive_grab = None;
+            } else if matches!(event, PointerEvent::Motion { .. }) {
+                self.interactive_grab_motion();
+                return;
+            }
+        }
+
+        if self.switcher_pointer_input(event) || self.decoration_pointer_input(event) {
+            return;
+        }
+
+        let serial = u32::from(SERIAL_COUNTER.next_serial());
+        self.pointer_events.hold(serial, event);
+
+        if !self.wm_filters_input() {
+            // Nothing decides whether the event is consumed, so it is sent to the client right away.
+            self.pointer_filter(serial, true);
+            return;
+        }
+
+        let seat = self.wm.seat();
+        let (focus, position) = self.wm_focus_under(self.pointer_location);
+        let context = match focus {
+            Some(_) => types::PointerContext::SURFACE,
+            None => types::PointerContext::ROOT,
+        };
+
+        self.send_wm(match event {
+            PointerEvent::Motion { time, location } => WmEvent::PointerMotion {
+                seat,
+                serial,
+                time,
+                focus,
+                position: wm::wm_point(position),
+                global: wm::wm_point(location),
+            },
+
+            PointerEvent::Button { time, button, state } => WmEvent::PointerButton {
+                seat,
+                serial,
+                time,
+                button,
+                state: match state {
+                    ButtonState::Pressed => types::ButtonState::Pressed,
+                    ButtonState::Released => types::ButtonState::Released,
+                },
+                context,
+            },
+
+            PointerEvent::Axis {
+                time, source, amount, ..
+            } => WmEvent::PointerAxis {
+                seat,
+                serial,
+                time,
+                horizontal: amount.0,
+                vertical: amount.1,
+                source: match source {
+                    AxisSource::Wheel => types::AxisSource::Wheel,
+                    AxisSource::Finger => types::AxisSource::Finger,
+                    AxisSource::Continuous => types::AxisSource::Continuous,
+                    AxisSource::WheelTilt => types::AxisSource::WheelTilt,
+                },
+                context,
+            },
+        });
+    }
+
+    /// The wm replied to a pointer event or the start of a gesture with a pointer filter.
+    pub fn pointer_filter(&mut self, serial: u32, forward: bool) {
+        for (event, forward) in self.pointer_events.filter(serial, forward) {
+            if forward {
+                self.send_pointer_event(event);
+            }
+        }
+
+        self.gesture_filter(serial, forward);
+    }
+
+    /// The surface under the location in the global compositor space which may receive input, with the location of
+    /// the surface.
+    ///
+    /// While the session is locked, only the lock surface is under the pointer.
+    pub fn surface_under(&self, location: Point<f64, Logical>) -> Option<(WlSurface, Point<i32, Logical>)> {
+        let origin = self.output.current_location().to_f64();
+        let scale = self.output.current_scale().fractional_scale();
+
+        let graph = match &self.session_lock {
+            Some(session_lock) => self.scene.get_surface_graph(session_lock.surface()?)?,
+            None => self.scene.get_graph(&self.output)?,
+        };
+
+        let (surface, surface_location) = graph.surface_under((location - origin).to_physical(scale))?;
+
+        if !surface
+            .client()
+            .is_some_and(|client| self.can_receive_input(&client.id()))
+        {
+            return None;
+        }
+
+        let surface_location = surface_location.to_f64().to_logical(scale) + origin;
+        Some((surface, surface_location.to_i32_round()))
+    }
+
+    /// The toplevel under the location as known to the wm, with the location relative to the surface of the
+    /// toplevel, or the location itself if no toplevel is under the location.
+    pub(crate) fn wm_focus_under(&self, location: Point<f64, Logical>) -> (Option<Id>, Point<f64, Logical>) {
+        self.surface_under(location)
+            .and_then(|(surface, surface_location)| {
+                let toplevel = self.wm.toplevel_id(Shell::get_toplevel_id(&surface)?)?;
+                Some((Some(toplevel), location - surface_location.to_f64()))
+            })
+            .unwrap_or((None, location))
+    }
+
+    /// Move the pointer to the location in the global compositor space, clamped to the output.
+    fn move_pointer(&mut self, location: Point<f64, Logical>) {
+        let Some(mode) = self.output.current_mode() else {
+            return;
+        };
+
+        // There is only one output, so the pointer never moves to another output.
+        let origin = self.output.current_location().to_f64();
+        let size = mode.size.to_logical(self.output.current_scale().integer_scale());
+        let max_x = f64::from((size.w - 1).max(0));
+        let max_y = f64::from((size.h - 1).max(0));
+        let local = location - origin;
+
+        self.pointer_location = origin + Point::from((local.x.clamp(0.0, max_x), local.y.clamp(0.0, max_y)));
+        self.zoom.pointer_moved(self.pointer_location - origin);
+        self.cursor_motion(Instant::now());
+
+        if self.drag.is_some() {
+            let focus = self
+                .surface_under(self.pointer_location)
+                .and_then(|(surface, _)| Shell::get_toplevel_id(&surface));
+            self.drag_motion(self.pointer_location, focus);
+        }
+
+        self.scene_changed();
+    }
+
+    /// Send a pointer event to the client under the pointer.
+    pub(crate) fn send_pointer_event(&mut self, event: PointerEvent) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return;
+        };
+
+        let serial = SERIAL_COUNTER.next_serial();
+
+        match event {
+            PointerEvent::Motion { time, location } => {
+                let focus = self.surface_under(location);
+                pointer.motion(self, focus, &MotionEvent { location, serial, time });
+            }
+
+            PointerEvent::Button { time, button, state } => {
+                pointer.button(
+                    self,
+                    &ButtonEvent {
+                        serial,
+                        time,
+                        button,
+                        state,
+                    },
+                );
+            }
+
+            PointerEvent::Axis {
+                time,
+                source,
+                amount,
+                discrete,
+            } => {
+                let mut frame = AxisFrame::new(time).source(source);
+
+                for (axis, amount, discrete) in [
+                    (Axis::Horizontal, amount.0, discrete.0),
+                    (Axis::Vertical, amount.1, discrete.1),
+                ] {
+                    if amount != 0.0 {
+                        frame = frame.value(axis, amount);
+                    } else if source == AxisSource::Finger {
+                        // Lifting the fingers from a touchpad stops kinetic scrolling.
+                        frame = frame.stop(axis);
+                    }
+
+                    if let Some(discrete) = discrete {
+                        frame = frame.discrete(axis, discrete as i32);
+                    }
+                }
+
+                pointer.axis(self, frame);
+            }
+        }
+
+        pointer.frame(self);
+    }
+}
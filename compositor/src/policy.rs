@@ -0,0 +1,319 @@
+//! Global filtering policy
+//!
+//! Privileged globals (such as layer-shell or session-lock) should only be visible to the clients which need
+//! them. When a client connects, information about the client is collected and matched against the rules of
+//! the [`GlobalPolicy`] to decide which [`PrivilegedGlobals`] the client can see. The policy is read from the
+//! `[policy]` section of the configuration, and without one no client can see any privileged global.
+//!
+//! Information is collected from the client's socket credentials and from `/proc`:
+//!
+//! - The uid and pid of the client.
+//! - The path of the client's executable.
+//! - The cgroup of the client, which identifies the systemd unit or flatpak instance.
+//! - A token provided in the client's environment using [`TOKEN_ENV`]. This allows a launcher to grant a
+//!   specific process privileges without knowing its path in advance.
+//!
+//! Since the executable and environment of a process can be changed after the client connects, these are only
+//! as trustworthy as the processes running as the same user.
+
+use std::{
+    fs,
+    os::fd::AsFd,
+    path::{Path, PathBuf},
+};
+
+use crate::state::PrivilegedGlobals;
+
+/// The environment variable used to provide a token to the display server.
+pub const TOKEN_ENV: &str = "AERUGO_CLIENT_TOKEN";
+
+/// The names of the privileged globals in the configuration.
+const GLOBAL_NAMES: &[(&str, PrivilegedGlobals)] = &[
+    ("foreign-toplevel-list", PrivilegedGlobals::FOREIGN_TOPLEVEL_LIST),
+    ("foreign-toplevel-state", PrivilegedGlobals::FOREIGN_TOPLEVEL_STATE),
+    (
+        "foreign-toplevel-management",
+        PrivilegedGlobals::FOREIGN_TOPLEVEL_MANAGEMENT,
+    ),
+    ("session-lock", PrivilegedGlobals::SESSION_LOCK),
+    ("layer-shell", PrivilegedGlobals::LAYER_SHELL),
+    ("aerugo-shell", PrivilegedGlobals::AERUGO_SHELL),
+];
+
+/// Parse the names of privileged globals, such as `layer-shell`.
+pub fn parse_globals<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<PrivilegedGlobals, String> {
+    names.into_iter().try_fold(PrivilegedGlobals::empty(), |globals, name| {
+        let (_, global) = GLOBAL_NAMES.iter().find(|&&(known, _)| known == name).ok_or_else(|| {
+            let known = GLOBAL_NAMES.iter().map(|(known, _)| *known).collect::<Vec<_>>();
+            format!("unknown global {name:?}, expected one of {}", known.join(", "))
+        })?;
+
+        Ok(globals | *global)
+    })
+}
+
+/// Information about a client used to apply the global policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub uid: Option<u32>,
+    pub pid: Option<i32>,
+    pub executable: Option<PathBuf>,
+    pub cgroup: Option<String>,
+
+    /// The sandbox engine of the security context the client connected through, such as `org.flatpak`.
+    pub sandbox_engine: Option<String>,
+
+    pub token: Option<String>,
+}
+
+impl ClientInfo {
+    /// Collect information about the client connected to the socket.
+    pub fn from_socket(socket: impl AsFd) -> Self {
+        let Ok(credentials) = rustix::net::sockopt::get_socket_peercred(socket) else {
+            return Self::default();
+        };
+
+        let uid = credentials.uid.as_raw();
+        let pid = credentials.pid.as_raw_nonzero().get();
+        let proc = PathBuf::from(format!("/proc/{pid}"));
+
+        Self {
+            uid: Some(uid),
+            pid: Some(pid),
+            executable: fs::read_link(proc.join("exe")).ok(),
+            cgroup: process_cgroup(pid),
+            sandbox_engine: None,
+            token: fs::read(proc.join("environ")).ok().as_deref().and_then(parse_token),
+        }
+    }
+}
+
+/// Get the cgroup of the process with the pid.
+pub(crate) fn process_cgroup(pid: i32) -> Option<String> {
+    fs::read_to_string(format!("/proc/{pid}/cgroup"))
+        .ok()
+        .as_deref()
+        .and_then(parse_cgroup)
+}
+
+/// Get the path of the cgroup v2 hierarchy from the contents of `/proc/<pid>/cgroup`.
+fn parse_cgroup(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(ToOwned::to_owned)
+}
+
+/// Get the token from the contents of `/proc/<pid>/environ`.
+fn parse_token(environ: &[u8]) -> Option<String> {
+    environ
+        .split(|&b| b == 0)
+        .filter_map(|var| std::str::from_utf8(var).ok())
+        .find_map(|var| var.strip_prefix(TOKEN_ENV)?.strip_prefix('='))
+        .map(ToOwned::to_owned)
+}
+
+/// A condition a client must meet for a rule to apply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Match {
+    /// The client is running as the user.
+    Uid(u32),
+
+    /// The client's executable is at the path.
+    Executable(PathBuf),
+
+    /// The client's cgroup starts with the prefix, such as `/user.slice/user-1000.slice/app.slice/waybar`.
+    Cgroup(String),
+
+    /// The client connected through a security context with the sandbox engine.
+    SandboxEngine(String),
+
+    /// The client provided the token in its environment.
+    Token(String),
+}
+
+impl Match {
+    fn matches(&self, info: &ClientInfo) -> bool {
+        match self {
+            Match::Uid(uid) => info.uid == Some(*uid),
+            Match::Executable(path) => info.executable.as_deref() == Some(path.as_path()),
+            Match::Cgroup(prefix) => info
+                .cgroup
+                .as_deref()
+                .is_some_and(|cgroup| cgroup_has_prefix(cgroup, prefix)),
+            Match::SandboxEngine(engine) => info.sandbox_engine.as_ref() == Some(engine),
+            Match::Token(token) => info.token.as_ref() == Some(token),
+        }
+    }
+}
+
+/// Check the cgroup is the prefix or a child of the prefix.
+fn cgroup_has_prefix(cgroup: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    cgroup == prefix || Path::new(cgroup).starts_with(prefix)
+}
+
+/// A rule granting globals to matching clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    /// Every condition must match for the rule to apply.
+    pub conditions: Vec<Match>,
+
+    pub globals: PrivilegedGlobals,
+}
+
+/// Policy deciding which privileged globals are visible to a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalPolicy {
+    default: PrivilegedGlobals,
+    rules: Vec<Rule>,
+    denied: Vec<Rule>,
+}
+
+impl GlobalPolicy {
+    /// Create a policy where every client can see the default globals.
+    pub fn new(default: PrivilegedGlobals) -> Self {
+        Self {
+            default,
+            rules: Vec::new(),
+            denied: Vec::new(),
+        }
+    }
+
+    /// Grant the globals to clients matching every condition.
+    pub fn allow(mut self, conditions: impl IntoIterator<Item = Match>, globals: PrivilegedGlobals) -> Self {
+        self.rules.push(Rule {
+            conditions: conditions.into_iter().collect(),
+            globals,
+        });
+        self
+    }
+
+    /// Hide the globals from clients matching every condition.
+    ///
+    /// Denied globals take precedence over granted globals. This is useful to remove the default globals from
+    /// sandboxed clients.
+    pub fn deny(mut self, conditions: impl IntoIterator<Item = Match>, globals: PrivilegedGlobals) -> Self {
+        self.denied.push(Rule {
+            conditions: conditions.into_iter().collect(),
+            globals,
+        });
+        self
+    }
+
+    /// The globals visible to the client.
+    pub fn globals_for(&self, info: &ClientInfo) -> PrivilegedGlobals {
+        let applies = |rule: &&Rule| rule.conditions.iter().all(|condition| condition.matches(info));
+
+        let granted = self
+            .rules
+            .iter()
+            .filter(applies)
+            .fold(self.default, |globals, rule| globals | rule.globals);
+
+        self.denied
+            .iter()
+            .filter(applies)
+            .fold(granted, |globals, rule| globals - rule.globals)
+    }
+}
+
+impl Default for GlobalPolicy {
+    /// No client can see any privileged global.
+    fn default() -> Self {
+        Self::new(PrivilegedGlobals::empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::state::PrivilegedGlobals;
+
+    use super::{parse_cgroup, parse_globals, parse_token, ClientInfo, GlobalPolicy, Match};
+
+    fn bar() -> ClientInfo {
+        ClientInfo {
+            uid: Some(1000),
+            pid: Some(42),
+            executable: Some(PathBuf::from("/usr/bin/waybar")),
+            cgroup: Some("/user.slice/user-1000.slice/app.slice/waybar.service".into()),
+            sandbox_engine: None,
+            token: None,
+        }
+    }
+
+    #[test]
+    fn only_bar_sees_layer_shell() {
+        let policy = GlobalPolicy::new(PrivilegedGlobals::empty())
+            .allow(
+                [Match::Executable("/usr/bin/waybar".into())],
+                PrivilegedGlobals::LAYER_SHELL,
+            )
+            .allow([Match::Token("locker".into())], PrivilegedGlobals::SESSION_LOCK);
+
+        assert_eq!(policy.globals_for(&bar()), PrivilegedGlobals::LAYER_SHELL);
+        assert_eq!(policy.globals_for(&ClientInfo::default()), PrivilegedGlobals::empty());
+
+        let locker = ClientInfo {
+            token: Some("locker".into()),
+            ..ClientInfo::default()
+        };
+        assert_eq!(policy.globals_for(&locker), PrivilegedGlobals::SESSION_LOCK);
+    }
+
+    #[test]
+    fn default_denies_privileged_globals() {
+        assert_eq!(GlobalPolicy::default().globals_for(&bar()), PrivilegedGlobals::empty());
+    }
+
+    #[test]
+    fn conditions_and_deny() {
+        let policy = GlobalPolicy::new(PrivilegedGlobals::all())
+            .deny(
+                [Match::SandboxEngine("org.flatpak".into())],
+                PrivilegedGlobals::SESSION_LOCK | PrivilegedGlobals::LAYER_SHELL,
+            )
+            .deny(
+                [
+                    Match::Uid(1000),
+                    Match::Cgroup("/user.slice/user-1000.slice/app.slice/".into()),
+                ],
+                PrivilegedGlobals::SESSION_LOCK,
+            );
+
+        let sandboxed = ClientInfo {
+            sandbox_engine: Some("org.flatpak".into()),
+            ..ClientInfo::default()
+        };
+        assert!(!policy.globals_for(&sandboxed).contains(PrivilegedGlobals::LAYER_SHELL));
+
+        let globals = policy.globals_for(&bar());
+        assert!(globals.contains(PrivilegedGlobals::LAYER_SHELL));
+        assert!(!globals.contains(PrivilegedGlobals::SESSION_LOCK));
+    }
+
+    #[test]
+    fn global_names() {
+        assert_eq!(
+            parse_globals(["layer-shell", "session-lock"]),
+            Ok(PrivilegedGlobals::LAYER_SHELL | PrivilegedGlobals::SESSION_LOCK)
+        );
+        assert_eq!(parse_globals([]), Ok(PrivilegedGlobals::empty()));
+        assert!(parse_globals(["xwayland"]).is_err());
+    }
+
+    #[test]
+    fn parse_proc() {
+        assert_eq!(
+            parse_cgroup("0::/user.slice/user-1000.slice/session-2.scope\n").as_deref(),
+            Some("/user.slice/user-1000.slice/session-2.scope")
+        );
+        assert_eq!(
+            parse_token(b"HOME=/home/user\0AERUGO_CLIENT_TOKEN=abc\0AERUGO_CLIENT_TOKEN_OTHER=def\0").as_deref(),
+            Some("abc")
+        );
+        assert_eq!(parse_token(b"AERUGO_CLIENT_TOKEN_OTHER=def\0"), None);
+    }
+}
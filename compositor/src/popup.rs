@@ -0,0 +1,443 @@
+//! xdg popups
+//!
+//! Popups are placed by the wm. The display server tells the wm about each popup with its positioner, and the wm
+//! configures the popup with its geometry relative to the parent, applying the constraint adjustments it sees fit.
+//! While no wm can answer, popups are placed where the positioner asks without any adjustment.
+//!
+//! A popup is drawn above its parent as a child of the surface tree of the parent, so the popup follows its parent
+//! when the wm moves the parent.
+
+use smithay::{
+    reexports::wayland_protocols::xdg::shell::server::xdg_positioner,
+    utils::{Logical, Point, Rectangle},
+    wayland::{
+        compositor,
+        shell::xdg::{PopupSurface, PositionerState, SurfaceCachedState},
+    },
+};
+use wayland_server::protocol::wl_surface::WlSurface;
+use wm_runtime::{
+    types::{self, Geometry},
+    Id, IdType, WmEvent,
+};
+
+use crate::{scene::NodeIndex, shell::Shell, Aerugo};
+
+/// A popup of a client.
+#[derive(Debug)]
+struct Popup {
+    surface: PopupSurface,
+
+    /// The id the wm knows the popup by, or [`None`] if the wm was not told about the popup.
+    wm_id: Option<Id>,
+
+    /// Whether the client made the initial commit, after which the popup may be configured.
+    committed: bool,
+
+    /// Whether the geometry of the popup was decided, by the wm or the positioner.
+    placed: bool,
+}
+
+/// The popups of clients.
+#[derive(Debug, Default)]
+pub struct Popups {
+    /// The popups in the order they were created, so a parent comes before its child popups.
+    popups: Vec<Popup>,
+}
+
+impl Popups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, surface: &WlSurface) -> Option<&Popup> {
+        self.popups.iter().find(|popup| popup.surface.wl_surface() == surface)
+    }
+
+    fn get_mut(&mut self, surface: &WlSurface) -> Option<&mut Popup> {
+        self.popups
+            .iter_mut()
+            .find(|popup| popup.surface.wl_surface() == surface)
+    }
+
+    /// The popup with the id the wm knows.
+    fn wm_popup(&self, id: Id) -> Option<&Popup> {
+        self.popups.iter().find(|popup| popup.wm_id == Some(id))
+    }
+
+    /// The id the wm knows the popup by.
+    pub fn wm_id(&self, surface: &WlSurface) -> Option<Id> {
+        self.get(surface)?.wm_id
+    }
+}
+
+/// The positioner as known to the wm.
+fn wm_positioner(positioner: &PositionerState) -> types::Positioner {
+    types::Positioner {
+        size: types::Size {
+            width: positioner.rect_size.w.max(0) as u32,
+            height: positioner.rect_size.h.max(0) as u32,
+        },
+        anchor_rect: Geometry {
+            x: positioner.anchor_rect.loc.x,
+            y: positioner.anchor_rect.loc.y,
+            width: positioner.anchor_rect.size.w.max(0) as u32,
+            height: positioner.anchor_rect.size.h.max(0) as u32,
+        },
+        anchor: match positioner.anchor_edges {
+            xdg_positioner::Anchor::Top => types::PositionerEdge::Top,
+            xdg_positioner::Anchor::Bottom => types::PositionerEdge::Bottom,
+            xdg_positioner::Anchor::Left => types::PositionerEdge::Left,
+            xdg_positioner::Anchor::Right => types::PositionerEdge::Right,
+            xdg_positioner::Anchor::TopLeft => types::PositionerEdge::TopLeft,
+            xdg_positioner::Anchor::BottomLeft => types::PositionerEdge::BottomLeft,
+            xdg_positioner::Anchor::TopRight => types::PositionerEdge::TopRight,
+            xdg_positioner::Anchor::BottomRight => types::PositionerEdge::BottomRight,
+            _ => types::PositionerEdge::None,
+        },
+        gravity: match positioner.gravity {
+            xdg_positioner::Gravity::Top => types::PositionerEdge::Top,
+            xdg_positioner::Gravity::Bottom => types::PositionerEdge::Bottom,
+            xdg_positioner::Gravity::Left => types::PositionerEdge::Left,
+            xdg_positioner::Gravity::Right => types::PositionerEdge::Right,
+            xdg_positioner::Gravity::TopLeft => types::PositionerEdge::TopLeft,
+            xdg_positioner::Gravity::BottomLeft => types::PositionerEdge::BottomLeft,
+            xdg_positioner::Gravity::TopRight => types::PositionerEdge::TopRight,
+            xdg_positioner::Gravity::BottomRight => types::PositionerEdge::BottomRight,
+            _ => types::PositionerEdge::None,
+        },
+        constraint_adjustment: wm_constraint_adjustment(positioner.constraint_adjustment),
+        offset_x: positioner.offset.x,
+        offset_y: positioner.offset.y,
+        reactive: positioner.reactive,
+    }
+}
+
+fn wm_constraint_adjustment(adjustment: xdg_positioner::ConstraintAdjustment) -> types::ConstraintAdjustment {
+    let mut wm_adjustment = types::ConstraintAdjustment::empty();
+
+    for (xdg, wm) in [
+        (
+            xdg_positioner::ConstraintAdjustment::SlideX,
+            types::ConstraintAdjustment::SLIDE_X,
+        ),
+        (
+            xdg_positioner::ConstraintAdjustment::SlideY,
+            types::ConstraintAdjustment::SLIDE_Y,
+        ),
+        (
+            xdg_positioner::ConstraintAdjustment::FlipX,
+            types::ConstraintAdjustment::FLIP_X,
+        ),
+        (
+            xdg_positioner::ConstraintAdjustment::FlipY,
+            types::ConstraintAdjustment::FLIP_Y,
+        ),
+        (
+            xdg_positioner::ConstraintAdjustment::ResizeX,
+            types::ConstraintAdjustment::RESIZE_X,
+        ),
+        (
+            xdg_positioner::ConstraintAdjustment::ResizeY,
+            types::ConstraintAdjustment::RESIZE_Y,
+        ),
+    ] {
+        if adjustment.contains(xdg) {
+            wm_adjustment |= wm;
+        }
+    }
+
+    wm_adjustment
+}
+
+/// The location of the window geometry of the surface relative to the surface.
+fn window_geometry_location(surface: &WlSurface) -> Point<i32, Logical> {
+    compositor::with_states(surface, |states| {
+        states.cached_state.current::<SurfaceCachedState>().geometry
+    })
+    .map(|geometry| geometry.loc)
+    .unwrap_or_default()
+}
+
+impl Aerugo {
+    /// A client created a popup.
+    pub(crate) fn popup_created(&mut self, surface: PopupSurface) {
+        self.scene.create_surface_tree(surface.wl_surface().clone());
+        self.attach_popup(&surface);
+
+        self.popups.popups.push(Popup {
+            surface,
+            wm_id: None,
+            committed: false,
+            placed: false,
+        });
+
+        let index = self.popups.popups.len() - 1;
+        self.announce_popup(index);
+    }
+
+    /// A layer surface became the parent of a popup created without a parent.
+    ///
+    /// The wm does not know layer surfaces as parents of popups, so the popup is placed where the positioner asks.
+    pub(crate) fn popup_parent_set(&mut self, surface: &PopupSurface) {
+        self.attach_popup(surface);
+
+        if let Some(index) = self
+            .popups
+            .popups
+            .iter()
+            .position(|popup| popup.surface.wl_surface() == surface.wl_surface())
+        {
+            self.announce_popup(index);
+        }
+    }
+
+    /// Add the surface tree of the popup to the surface tree of its parent.
+    fn attach_popup(&mut self, surface: &PopupSurface) {
+        let (Some(tree), Some(parent)) = (
+            self.scene.get_surface_tree_index(surface.wl_surface().clone()),
+            surface
+                .get_parent_surface()
+                .and_then(|parent| self.scene.get_surface_tree_index(parent)),
+        ) else {
+            return;
+        };
+
+        if let Err(err) = self
+            .scene
+            .add_child(NodeIndex::SurfaceTree(parent), NodeIndex::SurfaceTree(tree))
+        {
+            tracing::warn!(%err, "Failed to add popup to the surface tree of its parent");
+        }
+    }
+
+    /// Tell the wm about a popup, or place the popup where the positioner asks if no wm can answer.
+    pub(crate) fn announce_popup(&mut self, index: usize) {
+        let Some(popup) = self.popups.popups.get(index) else {
+            return;
+        };
+
+        let parent = popup
+            .surface
+            .get_parent_surface()
+            .and_then(|parent| match Shell::get_toplevel_id(&parent) {
+                Some(toplevel) => self.wm.toplevel_id(toplevel),
+                None => self.popups.wm_id(&parent),
+            })
+            .filter(|_| self.wm.is_running() && self.wm_crash.is_none());
+
+        let Some(parent) = parent else {
+            let surface = popup.surface.clone();
+            let geometry = surface.with_pending_state(|state| state.positioner.get_geometry());
+            self.configure_popup(&surface, geometry, None);
+            return;
+        };
+
+        let id = self.wm.alloc_id(IdType::Popup);
+        let positioner = popup
+            .surface
+            .with_pending_state(|state| wm_positioner(&state.positioner));
+        self.popups.popups[index].wm_id = Some(id);
+
+        self.send_wm(WmEvent::NewPopup {
+            popup: id,
+            parent,
+            positioner,
+        });
+    }
+
+    /// Tell a newly started wm about every popup.
+    pub(crate) fn announce_popups(&mut self) {
+        for index in 0..self.popups.popups.len() {
+            self.announce_popup(index);
+        }
+    }
+
+    /// The popup was committed.
+    pub(crate) fn popup_committed(&mut self, surface: &WlSurface) {
+        let Some(popup) = self.popups.get_mut(surface) else {
+            return;
+        };
+
+        popup.committed = true;
+        let surface = popup.surface.clone();
+
+        // The initial configure waits for the geometry to be decided.
+        if popup.placed && !surface.is_initial_configure_sent() {
+            if let Err(err) = surface.send_configure() {
+                tracing::warn!(?err, "Failed to configure popup");
+            }
+        }
+
+        // The window geometry of the popup may have changed.
+        self.place_popup(&surface);
+    }
+
+    /// The client requested the popup be placed using a new positioner.
+    pub(crate) fn popup_reposition_requested(
+        &mut self,
+        surface: PopupSurface,
+        positioner: PositionerState,
+        token: u32,
+    ) {
+        surface.with_pending_state(|state| state.positioner = positioner);
+
+        match self.popups.wm_id(surface.wl_surface()) {
+            Some(popup) if self.wm_crash.is_none() => {
+                self.send_wm(WmEvent::RepositionPopup {
+                    popup,
+                    positioner: wm_positioner(&positioner),
+                    token,
+                });
+            }
+
+            _ => self.configure_popup(&surface, positioner.get_geometry(), Some(token)),
+        }
+    }
+
+    /// The popup was destroyed.
+    pub(crate) fn popup_destroyed(&mut self, surface: &WlSurface) {
+        let Some(index) = self
+            .popups
+            .popups
+            .iter()
+            .position(|popup| popup.surface.wl_surface() == surface)
+        else {
+            return;
+        };
+
+        let popup = self.popups.popups.remove(index);
+        self.scene.destroy_surface_tree(surface);
+        self.scene_changed();
+
+        if let Some(id) = popup.wm_id {
+            self.send_wm(WmEvent::ClosedPopup(id));
+        }
+    }
+
+    /// The wm configured the popup with the geometry relative to the parent.
+    pub(crate) fn wm_configure_popup(&mut self, id: Id, geometry: Geometry, reposition_token: Option<u32>) {
+        let Some(popup) = self.popups.wm_popup(id) else {
+            return;
+        };
+
+        let surface = popup.surface.clone();
+        let geometry = Rectangle::from_loc_and_size(
+            (geometry.x, geometry.y),
+            (geometry.width as i32, geometry.height as i32),
+        );
+        self.configure_popup(&surface, geometry, reposition_token);
+    }
+
+    /// The wm dismissed the popup, which also dismisses the child popups of the popup.
+    pub(crate) fn wm_dismiss_popup(&mut self, id: Id) {
+        let Some(popup) = self.popups.wm_popup(id) else {
+            return;
+        };
+
+        // Parents come before their children, so every descendant is found in one pass.
+        let mut dismissed = vec![popup.surface.wl_surface().clone()];
+
+        for popup in &self.popups.popups {
+            if popup
+                .surface
+                .get_parent_surface()
+                .is_some_and(|parent| dismissed.contains(&parent))
+            {
+                dismissed.push(popup.surface.wl_surface().clone());
+            }
+        }
+
+        // The topmost child popup is dismissed first.
+        for surface in dismissed.iter().rev() {
+            if let Some(popup) = self.popups.get(surface) {
+                popup.surface.send_popup_done();
+            }
+        }
+    }
+
+    /// Configure the popup with the geometry relative to the parent.
+    fn configure_popup(&mut self, surface: &PopupSurface, geometry: Rectangle<i32, Logical>, token: Option<u32>) {
+        let Some(popup) = self.popups.get_mut(surface.wl_surface()) else {
+            return;
+        };
+
+        popup.placed = true;
+        surface.with_pending_state(|state| state.geometry = geometry);
+
+        // The initial configure is sent after the initial commit.
+        if popup.committed {
+            if let Some(token) = token {
+                surface.send_repositioned(token);
+            }
+
+            if let Err(err) = surface.send_configure() {
+                tracing::warn!(?err, "Failed to configure popup");
+            }
+        }
+
+        self.place_popup(surface);
+    }
+
+    /// Move the surface tree of the popup to the geometry of the popup relative to the window geometry of the
+    /// parent.
+    fn place_popup(&mut self, surface: &PopupSurface) {
+        let Some(parent) = surface.get_parent_surface() else {
+            return;
+        };
+
+        let Some(tree) = self.scene.get_surface_tree_index(surface.wl_surface().clone()) else {
+            return;
+        };
+
+        let geometry = surface.with_pending_state(|state| state.geometry);
+        let offset = window_geometry_location(&parent) + geometry.loc - window_geometry_location(surface.wl_surface());
+        let scale = self.output.current_scale().integer_scale();
+
+        self.scene
+            .set_node_offset(NodeIndex::SurfaceTree(tree), offset.to_physical(scale));
+        self.scene_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::{
+        reexports::wayland_protocols::xdg::shell::server::xdg_positioner, wayland::shell::xdg::PositionerState,
+    };
+    use wm_runtime::types;
+
+    use super::{wm_constraint_adjustment, wm_positioner};
+
+    #[test]
+    fn constraint_adjustment() {
+        let adjustment = xdg_positioner::ConstraintAdjustment::FlipY | xdg_positioner::ConstraintAdjustment::SlideX;
+
+        assert_eq!(
+            wm_constraint_adjustment(adjustment),
+            types::ConstraintAdjustment::FLIP_Y | types::ConstraintAdjustment::SLIDE_X
+        );
+        assert_eq!(
+            wm_constraint_adjustment(xdg_positioner::ConstraintAdjustment::None),
+            types::ConstraintAdjustment::empty()
+        );
+    }
+
+    #[test]
+    fn positioner() {
+        let positioner = PositionerState {
+            rect_size: (200, 100).into(),
+            anchor_edges: xdg_positioner::Anchor::BottomLeft,
+            gravity: xdg_positioner::Gravity::BottomRight,
+            offset: (4, -2).into(),
+            ..PositionerState::default()
+        };
+        let positioner = wm_positioner(&positioner);
+
+        assert_eq!(positioner.size.width, 200);
+        assert_eq!(positioner.size.height, 100);
+        assert_eq!(positioner.anchor, types::PositionerEdge::BottomLeft);
+        assert_eq!(positioner.gravity, types::PositionerEdge::BottomRight);
+        assert_eq!((positioner.offset_x, positioner.offset_y), (4, -2));
+    }
+}
@@ -0,0 +1,393 @@
+//! Protocol message logging
+//!
+//! `WAYLAND_DEBUG` logs every message of every client and must be set before the display server starts. The
+//! protocol log can instead be enabled while the display server is running and only logs the messages of the
+//! clients and interfaces matching a [`Filter`], so a trace can be captured for a single misbehaving client.
+//!
+//! Messages are formatted like `WAYLAND_DEBUG`, prefixed with the pid of the client. Logged messages are kept in
+//! a bounded buffer until they are drained and streamed to the IPC connections which enabled the log.
+//!
+//! Messages may contain what the user types, so the `log-protocol` IPC command requires the privileged tier. It is
+//! only available once `privileged-token` is set in the `[ipc]` section of the configuration, and the connection
+//! authenticates with that token:
+//!
+//! ```text
+//! > auth correct horse battery staple
+//! < ok privileged
+//! > log-protocol exe=foot xdg_*
+//! < logging
+//! ```
+//!
+//! wayland-server does not expose a hook for every message, so only the requests to the protocols the display
+//! server dispatches itself are logged, as each `Dispatch::request` of the display server logs the request first.
+//! Protocols dispatched by smithay, such as `wl_surface` and `xdg_toplevel`, are not logged.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write as _},
+    time::{Duration, Instant},
+};
+
+use crate::policy::ClientInfo;
+
+/// The default number of messages kept before the oldest messages are dropped.
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// The direction of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// A request sent by the client.
+    Request,
+
+    /// An event sent to the client.
+    Event,
+}
+
+/// Which clients are logged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientMatch {
+    Any,
+
+    /// The client with the pid.
+    Pid(i32),
+
+    /// Clients whose executable has the file name, such as `firefox`.
+    Executable(String),
+}
+
+impl ClientMatch {
+    fn matches(&self, client: &ClientInfo) -> bool {
+        match self {
+            ClientMatch::Any => true,
+            ClientMatch::Pid(pid) => client.pid == Some(*pid),
+            ClientMatch::Executable(name) => client
+                .executable
+                .as_ref()
+                .and_then(|executable| executable.file_name())
+                .is_some_and(|file_name| file_name == name.as_str()),
+        }
+    }
+}
+
+/// The messages which are logged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub client: ClientMatch,
+
+    /// The interfaces to log, or every interface if empty.
+    ///
+    /// An interface ending with `*` matches every interface with the prefix, such as `xdg_*`.
+    pub interfaces: Vec<String>,
+}
+
+impl Filter {
+    /// Parse a filter from the arguments of the IPC command, such as `exe=foot wl_surface xdg_*`.
+    ///
+    /// The first argument selects the clients and is `*`, `pid=<pid>` or `exe=<file name>`. The remaining arguments
+    /// are the interfaces.
+    pub fn parse<'a>(mut args: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let client = match args.next()? {
+            "*" => ClientMatch::Any,
+            client => match client.split_once('=')? {
+                ("pid", pid) => ClientMatch::Pid(pid.parse().ok()?),
+                ("exe", name) => ClientMatch::Executable(name.into()),
+                _ => return None,
+            },
+        };
+
+        Some(Self {
+            client,
+            interfaces: args.map(ToOwned::to_owned).collect(),
+        })
+    }
+
+    pub fn matches(&self, client: &ClientInfo, interface: &str) -> bool {
+        let interface_matches = self.interfaces.is_empty()
+            || self.interfaces.iter().any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => interface.starts_with(prefix),
+                None => interface == pattern,
+            });
+
+        interface_matches && self.client.matches(client)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.client {
+            ClientMatch::Any => f.write_str("*")?,
+            ClientMatch::Pid(pid) => write!(f, "pid={pid}")?,
+            ClientMatch::Executable(name) => write!(f, "exe={name}")?,
+        }
+
+        self.interfaces
+            .iter()
+            .try_for_each(|interface| write!(f, " {interface}"))
+    }
+}
+
+/// A protocol message.
+#[derive(Debug, Clone, Copy)]
+pub struct Message<'a> {
+    /// The time since the display server started.
+    pub time: Duration,
+    pub direction: Direction,
+    pub interface: &'a str,
+    pub object: u32,
+    pub name: &'a str,
+    /// The arguments, formatted like `WAYLAND_DEBUG`.
+    pub args: &'a str,
+}
+
+impl<'a> Message<'a> {
+    /// Split the debug formatting of a request or event, such as `SetAppId { app_id: "foot" }`, into the name and
+    /// the arguments.
+    pub fn split_debug(debug: &'a str) -> (&'a str, &'a str) {
+        match debug.split_once([' ', '(']) {
+            Some((name, args)) => (
+                name,
+                args.trim()
+                    .trim_start_matches(['{', '('])
+                    .trim_end_matches(['}', ')'])
+                    .trim(),
+            ),
+            None => (debug, ""),
+        }
+    }
+}
+
+/// Convert the name of a request variant, such as `SetAppId`, to the name in the protocol, `set_app_id`.
+pub fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+
+    snake
+}
+
+/// The protocol log.
+#[derive(Debug)]
+pub struct ProtocolLog {
+    /// When the display server started, which message times are relative to.
+    started: Instant,
+    filter: Option<Filter>,
+    capacity: usize,
+    lines: VecDeque<String>,
+    /// The number of messages dropped because the buffer was full.
+    dropped: usize,
+}
+
+impl ProtocolLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            filter: None,
+            capacity,
+            lines: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Start logging messages matching the filter, replacing the previous filter.
+    pub fn enable(&mut self, filter: Filter) {
+        self.filter = Some(filter);
+    }
+
+    /// Stop logging messages.
+    ///
+    /// Messages which were already logged may still be drained.
+    pub fn disable(&mut self) {
+        self.filter = None;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.filter.is_some()
+    }
+
+    /// The time since the display server started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Log a message if it matches the filter.
+    ///
+    /// Returns [`true`] if the message was logged.
+    pub fn log(&mut self, client: &ClientInfo, message: &Message<'_>) -> bool {
+        if !self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| filter.matches(client, message.interface))
+        {
+            return false;
+        }
+
+        if self.lines.len() >= self.capacity {
+            self.lines.pop_front();
+            self.dropped += 1;
+        }
+
+        self.lines.push_back(format_message(client, message));
+        true
+    }
+
+    /// Take the logged messages.
+    ///
+    /// If messages were dropped since the last drain, a line noting how many were dropped is returned first.
+    pub fn drain(&mut self) -> impl Iterator<Item = String> + '_ {
+        let dropped = std::mem::take(&mut self.dropped);
+        let note = (dropped > 0).then(|| format!("{dropped} messages dropped"));
+
+        note.into_iter().chain(self.lines.drain(..))
+    }
+}
+
+impl Default for ProtocolLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+fn format_message(client: &ClientInfo, message: &Message<'_>) -> String {
+    let mut line = String::new();
+    let time = message.time.as_secs_f64() * 1000.0;
+    let _ = write!(line, "[{time:10.3}] ");
+
+    match client.pid {
+        Some(pid) => {
+            let _ = write!(line, "{{{pid}}} ");
+        }
+        None => line.push_str("{?} "),
+    }
+
+    if message.direction == Direction::Event {
+        line.push_str("-> ");
+    }
+
+    let _ = write!(
+        line,
+        "{}@{}.{}({})",
+        message.interface, message.object, message.name, message.args
+    );
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::policy::ClientInfo;
+
+    use super::{snake_case, ClientMatch, Direction, Filter, Message, ProtocolLog};
+
+    fn client(pid: i32, executable: &str) -> ClientInfo {
+        ClientInfo {
+            pid: Some(pid),
+            executable: Some(executable.into()),
+            ..ClientInfo::default()
+        }
+    }
+
+    fn message(direction: Direction, interface: &str) -> Message<'_> {
+        Message {
+            time: Duration::from_millis(1500),
+            direction,
+            interface,
+            object: 3,
+            name: "commit",
+            args: "",
+        }
+    }
+
+    #[test]
+    fn filter_by_client_and_interface() {
+        let filter = Filter {
+            client: ClientMatch::Executable("foot".into()),
+            interfaces: vec!["wl_surface".into(), "xdg_*".into()],
+        };
+
+        assert!(filter.matches(&client(10, "/usr/bin/foot"), "wl_surface"));
+        assert!(filter.matches(&client(10, "/usr/bin/foot"), "xdg_toplevel"));
+        assert!(!filter.matches(&client(10, "/usr/bin/foot"), "wl_pointer"));
+        assert!(!filter.matches(&client(10, "/usr/bin/footclient"), "wl_surface"));
+
+        let filter = Filter {
+            client: ClientMatch::Pid(11),
+            interfaces: Vec::new(),
+        };
+        assert!(filter.matches(&client(11, "/usr/bin/foot"), "wl_pointer"));
+        assert!(!filter.matches(&client(10, "/usr/bin/foot"), "wl_pointer"));
+    }
+
+    #[test]
+    fn parse_filter() {
+        let filter = Filter::parse("exe=foot wl_surface xdg_*".split(' ')).unwrap();
+        assert_eq!(filter.client, ClientMatch::Executable("foot".into()));
+        assert_eq!(filter.interfaces, ["wl_surface", "xdg_*"]);
+        assert_eq!(filter.to_string(), "exe=foot wl_surface xdg_*");
+
+        assert_eq!(Filter::parse("pid=11".split(' ')).unwrap().client, ClientMatch::Pid(11));
+        assert_eq!(Filter::parse("*".split(' ')).unwrap().client, ClientMatch::Any);
+        assert_eq!(Filter::parse("uid=0".split(' ')), None);
+        assert_eq!(Filter::parse(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn log_only_while_enabled() {
+        let mut log = ProtocolLog::default();
+        let client = client(10, "/usr/bin/foot");
+
+        assert!(!log.log(&client, &message(Direction::Request, "wl_surface")));
+
+        log.enable(Filter {
+            client: ClientMatch::Any,
+            interfaces: Vec::new(),
+        });
+        assert!(log.log(&client, &message(Direction::Request, "wl_surface")));
+        assert!(log.log(&client, &message(Direction::Event, "wl_callback")));
+
+        let lines = log.drain().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "[  1500.000] {10} wl_surface@3.commit()",
+                "[  1500.000] {10} -> wl_callback@3.commit()",
+            ]
+        );
+        assert_eq!(log.drain().count(), 0);
+    }
+
+    #[test]
+    fn request_names_and_args() {
+        assert_eq!(
+            Message::split_debug(r#"SetAppId { app_id: "foot" }"#),
+            ("SetAppId", r#"app_id: "foot""#)
+        );
+        assert_eq!(Message::split_debug("Destroy"), ("Destroy", ""));
+        assert_eq!(snake_case("GetActivationToken"), "get_activation_token");
+        assert_eq!(snake_case("Destroy"), "destroy");
+    }
+
+    #[test]
+    fn full_buffer_drops_oldest() {
+        let mut log = ProtocolLog::new(2);
+        let client = client(10, "/usr/bin/foot");
+        log.enable(Filter {
+            client: ClientMatch::Any,
+            interfaces: Vec::new(),
+        });
+
+        for interface in ["a", "b", "c"] {
+            log.log(&client, &message(Direction::Request, interface));
+        }
+
+        let lines = log.drain().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "1 messages dropped");
+        assert!(lines[1].contains("b@3"));
+    }
+}
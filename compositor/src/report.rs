@@ -0,0 +1,383 @@
+//! Debug reports
+//!
+//! A debug report bundles information which is useful when reporting a bug: the version of the display server,
+//! the output topology, the protocol globals, the GPU and driver, frame statistics, the recent lines of the log and
+//! the state of every toplevel. The report is written as a tarball so it can be attached to an issue.
+//!
+//! Reports are sanitized before they are written. The home directory and user name are replaced and tokens
+//! provided to clients through [`TOKEN_ENV`] are removed, so the report can be shared publicly.
+//!
+//! The report is written by the `debug-report` IPC command, see [`crate::ipc::Command::DebugReport`], which
+//! `aerugo-msg debug report` sends. The log only reaches the report if the logger also writes to
+//! [`RecentLogs`].
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use crate::{metrics::QUANTILES, policy::TOKEN_ENV, wayland::versions, Aerugo};
+
+/// The directory in the tarball containing the files of the report.
+const REPORT_DIR: &str = "aerugo-report";
+
+/// How many lines of the log are kept for the report.
+pub const LOG_LINES: usize = 500;
+
+/// The size of a tar block.
+const BLOCK: usize = 512;
+
+/// A sanitized debug report.
+#[derive(Debug)]
+pub struct DebugReport {
+    home: Option<String>,
+    user: Option<String>,
+    files: Vec<(String, String)>,
+}
+
+impl DebugReport {
+    /// Create a report sanitizing the home directory and name of the current user.
+    pub fn new() -> Self {
+        Self::with_identity(std::env::var("HOME").ok(), std::env::var("USER").ok())
+    }
+
+    fn with_identity(home: Option<String>, user: Option<String>) -> Self {
+        let mut report = Self {
+            home: home.filter(|home| !home.is_empty() && home != "/"),
+            user: user.filter(|user| !user.is_empty()),
+            files: Vec::new(),
+        };
+
+        report.add("version.txt", format!("aerugo {}\n", env!("CARGO_PKG_VERSION")));
+        report
+    }
+
+    /// Add a file to the report.
+    ///
+    /// The contents are sanitized. A file with the same name is replaced.
+    pub fn add(&mut self, name: &str, contents: impl AsRef<str>) {
+        let contents = self.sanitize(contents.as_ref());
+        self.files.retain(|(file, _)| file != name);
+        self.files.push((name.into(), contents));
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.files
+            .iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_str()))
+    }
+
+    /// Write the report as a tarball.
+    pub fn write_tar(&self, mut writer: impl Write) -> io::Result<()> {
+        for (name, contents) in &self.files {
+            let path = format!("{REPORT_DIR}/{name}");
+            writer.write_all(&tar_header(&path, contents.len())?)?;
+            writer.write_all(contents.as_bytes())?;
+
+            let padding = (BLOCK - contents.len() % BLOCK) % BLOCK;
+            writer.write_all(&[0; BLOCK][..padding])?;
+        }
+
+        // The end of the archive is marked by two empty blocks.
+        writer.write_all(&[0; BLOCK * 2])?;
+        writer.flush()
+    }
+
+    fn sanitize(&self, contents: &str) -> String {
+        let mut sanitized = String::with_capacity(contents.len());
+
+        for line in contents.split_inclusive('\n') {
+            match line.find(TOKEN_ENV) {
+                Some(index) => {
+                    sanitized.push_str(&line[..index]);
+                    sanitized.push_str(TOKEN_ENV);
+                    sanitized.push_str("=<redacted>");
+
+                    if line.ends_with('\n') {
+                        sanitized.push('\n');
+                    }
+                }
+                None => sanitized.push_str(line),
+            }
+        }
+
+        if let Some(home) = &self.home {
+            sanitized = sanitized.replace(home.as_str(), "~");
+        }
+
+        if let Some(user) = &self.user {
+            sanitized = sanitized.replace(user.as_str(), "<user>");
+        }
+
+        sanitized
+    }
+}
+
+impl Default for DebugReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The most recent lines of the log.
+///
+/// The logger writes every line to a clone, and the display server adds the lines to debug reports. Terminal
+/// escape sequences are removed from the lines.
+#[derive(Debug, Clone, Default)]
+pub struct RecentLogs(Arc<Mutex<LogLines>>);
+
+#[derive(Debug, Default)]
+struct LogLines {
+    lines: VecDeque<String>,
+    /// The start of a line which was not written completely yet.
+    partial: String,
+}
+
+impl RecentLogs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The kept lines, from oldest to newest.
+    pub fn lines(&self) -> Vec<String> {
+        self.0.lock().unwrap().lines.iter().cloned().collect()
+    }
+}
+
+impl Write for RecentLogs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut logs = self.0.lock().unwrap();
+        logs.partial.push_str(&String::from_utf8_lossy(buf));
+
+        while let Some(end) = logs.partial.find('\n') {
+            let line = strip_escapes(&logs.partial[..end]);
+            logs.partial.drain(..=end);
+
+            if logs.lines.len() == LOG_LINES {
+                logs.lines.pop_front();
+            }
+
+            logs.lines.push_back(line);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Remove the terminal escape sequences which color the log.
+fn strip_escapes(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+
+        // A control sequence ends with a byte between `@` and `~`.
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+
+    stripped
+}
+
+/// Create a ustar header for a regular file.
+fn tar_header(path: &str, size: usize) -> io::Result<[u8; BLOCK]> {
+    if path.len() >= 100 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is too long"));
+    }
+
+    let mut header = [0; BLOCK];
+    let mut field = |offset: usize, value: &[u8]| header[offset..offset + value.len()].copy_from_slice(value);
+
+    field(0, path.as_bytes());
+    field(100, b"0000644\0");
+    // uid and gid
+    field(108, b"0000000\0");
+    field(116, b"0000000\0");
+    field(124, format!("{size:011o}\0").as_bytes());
+    // mtime
+    field(136, b"00000000000\0");
+    field(156, b"0");
+    field(257, b"ustar\0");
+    field(263, b"00");
+
+    // The checksum is calculated with the checksum field filled with spaces.
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|&b| b as u32).sum::<u32>();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+    Ok(header)
+}
+
+impl Aerugo {
+    /// Collect a debug report of the display server's current state.
+    pub fn debug_report(&self) -> DebugReport {
+        let mut report = DebugReport::new();
+
+        let output = &self.output;
+        let properties = output.physical_properties();
+        let mut outputs = String::new();
+        let _ = writeln!(outputs, "{}", output.name());
+        let _ = writeln!(outputs, "  make: {}, model: {}", properties.make, properties.model);
+        let _ = writeln!(outputs, "  subpixel: {:?}", properties.subpixel);
+        let _ = writeln!(outputs, "  current mode: {:?}", output.current_mode());
+        let _ = writeln!(outputs, "  preferred mode: {:?}", output.preferred_mode());
+        let _ = writeln!(outputs, "  scale: {:?}", output.current_scale());
+        let _ = writeln!(outputs, "  transform: {:?}", output.current_transform());
+        let _ = writeln!(outputs, "  position: {:?}", output.current_location());
+        report.add("outputs.txt", outputs);
+
+        let globals = [
+            ("wl_compositor", 5),
+            ("xdg_wm_base", 5),
+            ("wl_seat", 8),
+            ("wl_output", 4),
+            ("wl_shm", 1),
+            ("wp_viewporter", 1),
+            ("ext_foreign_toplevel_list_v1", versions::EXT_FOREIGN_TOPLEVEL_LIST_V1),
+            ("xdg_activation_v1", versions::XDG_ACTIVATION_V1),
+            ("zwlr_input_inhibit_manager_v1", versions::ZWLR_INPUT_INHIBIT_MANAGER_V1),
+        ];
+        report.add(
+            "globals.txt",
+            globals
+                .iter()
+                .map(|(name, version)| format!("{name} v{version}\n"))
+                .collect::<String>(),
+        );
+
+        report.add("gpu.txt", self.backend.debug_info());
+
+        let mut frames = String::new();
+        let _ = writeln!(frames, "frames: {}", self.frame_stats.count());
+        let _ = writeln!(frames, "mean: {:?}", self.frame_stats.mean());
+        for quantile in QUANTILES {
+            let _ = writeln!(
+                frames,
+                "p{}: {:?}",
+                quantile * 100.0,
+                self.frame_stats.quantile(quantile)
+            );
+        }
+        report.add("frames.txt", frames);
+
+        let mut log = self.recent_logs.lines().join("\n");
+        log.push('\n');
+        report.add("log.txt", log);
+
+        let mut ids = self.shell.toplevels.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+
+        let mut toplevels = String::new();
+        let _ = writeln!(toplevels, "{} toplevels", ids.len());
+        let _ = writeln!(toplevels, "keyboard focus: {:?}", self.keyboard_focus);
+
+        for id in ids {
+            let Some(toplevel) = self.shell.get_state(id) else {
+                continue;
+            };
+
+            let _ = writeln!(toplevels, "{id}");
+            let _ = writeln!(toplevels, "  app id: {:?}", toplevel.app_id());
+            let _ = writeln!(toplevels, "  title: {:?}", toplevel.title());
+            let _ = writeln!(toplevels, "  geometry: {:?}", self.toplevel_geometry(id));
+            let _ = writeln!(
+                toplevels,
+                "  unresponsive: {}",
+                self.configure_watchdog.is_unresponsive(&id)
+            );
+        }
+
+        report.add("toplevels.txt", toplevels);
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::{tar_header, DebugReport, RecentLogs, BLOCK, LOG_LINES};
+
+    fn report() -> DebugReport {
+        DebugReport::with_identity(Some("/home/alice".into()), Some("alice".into()))
+    }
+
+    #[test]
+    fn sanitized() {
+        let mut report = report();
+        report.add(
+            "env.txt",
+            "config: /home/alice/.config/aerugo\nAERUGO_CLIENT_TOKEN=secret\nuser alice\n",
+        );
+
+        let (_, contents) = report.files().find(|(name, _)| *name == "env.txt").unwrap();
+        assert_eq!(
+            contents,
+            "config: ~/.config/aerugo\nAERUGO_CLIENT_TOKEN=<redacted>\nuser <user>\n"
+        );
+    }
+
+    #[test]
+    fn recent_logs() {
+        let mut logs = RecentLogs::new();
+        write!(logs, "\x1b[2m2023-10-01\x1b[0m \x1b[32m INFO\x1b[0m started\npartial").unwrap();
+        assert_eq!(logs.lines(), ["2023-10-01  INFO started"]);
+
+        writeln!(logs, " line").unwrap();
+        assert_eq!(logs.lines()[1], "partial line");
+
+        for line in 0..LOG_LINES {
+            writeln!(logs, "{line}").unwrap();
+        }
+
+        // Only the newest lines are kept.
+        assert_eq!(logs.lines().len(), LOG_LINES);
+        assert_eq!(logs.lines()[0], "0");
+    }
+
+    #[test]
+    fn tar_layout() {
+        let mut report = report();
+        report.add("a.txt", "hello");
+
+        let mut tar = Vec::new();
+        report.write_tar(&mut tar).unwrap();
+
+        // Each file is a header and one block of contents, followed by two empty blocks.
+        assert_eq!(tar.len(), BLOCK * 2 * report.files().count() + BLOCK * 2);
+        assert!(tar.ends_with(&[0; BLOCK * 2]));
+        assert_eq!(&tar[..22], b"aerugo-report/version.");
+    }
+
+    #[test]
+    fn tar_header_checksum() {
+        let header = tar_header("aerugo-report/a.txt", 5).unwrap();
+
+        let expected = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u32 } else { b as u32 })
+            .sum::<u32>();
+        let checksum = std::str::from_utf8(&header[148..154]).unwrap();
+        assert_eq!(u32::from_str_radix(checksum, 8).unwrap(), expected);
+        assert_eq!(&header[124..136], b"00000000005\0");
+
+        assert!(tar_header(&"a".repeat(100), 0).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+//! Rounded corners
+//!
+//! The wm may round the corners of a toplevel, or of the clip of a toplevel. The corners are masked in the shader
+//! which draws each surface of the toplevel, so subsurfaces reaching into a corner are cut the same way as the
+//! toplevel surface. The mask is described in texture coordinates, which the texture coordinates of the fragment
+//! are compared against, so the mask follows the buffer transform and viewport of the surface.
+//!
+//! Rounded surfaces are never scanned out, since the display controller cannot mask the corners.
+
+use smithay::{
+    backend::renderer::{
+        element::{Element, Id, RenderElement, UnderlyingStorage},
+        gles::{GlesError, GlesFrame, GlesRenderer, GlesTexProgram, Uniform, UniformName, UniformType},
+        utils::CommitCounter,
+    },
+    utils::{Buffer, Physical, Rectangle, Scale, Size},
+};
+
+/// Fragment shader for GLES which masks the corners of a rounded rectangle.
+///
+/// The shader also premultiplies straight alpha textures and darkens dimmed surfaces, since a dimmed surface cannot
+/// be covered by a solid rectangle without filling the masked corners.
+pub const ROUNDED_CORNERS_SHADER: &str = r#"
+#version 100
+//_DEFINES_
+
+#if defined(EXTERNAL)
+#extension GL_OES_EGL_image_external : require
+#endif
+
+precision mediump float;
+#if defined(EXTERNAL)
+uniform samplerExternalOES tex;
+#else
+uniform sampler2D tex;
+#endif
+
+uniform float alpha;
+varying vec2 v_coords;
+
+// The rounded rectangle in texture coordinates, as the top left and bottom right corners.
+uniform vec4 corner_rect;
+// The radius of the corners in texture coordinates.
+uniform vec2 corner_radius;
+// The radius of the corners in output pixels, which decides how wide the antialiased edge is.
+uniform float corner_pixels;
+uniform float premultiply;
+uniform float dim;
+
+void main() {
+    vec4 color = texture2D(tex, v_coords);
+#if defined(NO_ALPHA)
+    color.a = 1.0;
+#else
+    color.rgb *= mix(1.0, color.a, premultiply);
+#endif
+    color.rgb *= 1.0 - dim;
+
+    vec2 inner = clamp(v_coords, corner_rect.xy + corner_radius, corner_rect.zw - corner_radius);
+    float distance = length((v_coords - inner) / corner_radius);
+    float edge = 0.5 / max(corner_pixels, 1.0);
+    float coverage = 1.0 - smoothstep(1.0 - edge, 1.0 + edge, distance);
+
+    gl_FragColor = color * alpha * coverage;
+}
+"#;
+
+/// Compile [`ROUNDED_CORNERS_SHADER`] for the renderer.
+pub fn compile_rounded_corners_shader(renderer: &mut GlesRenderer) -> Result<GlesTexProgram, GlesError> {
+    renderer.compile_custom_texture_shader(
+        ROUNDED_CORNERS_SHADER,
+        &[
+            UniformName::new("corner_rect", UniformType::_4f),
+            UniformName::new("corner_radius", UniformType::_2f),
+            UniformName::new("corner_pixels", UniformType::_1f),
+            UniformName::new("premultiply", UniformType::_1f),
+            UniformName::new("dim", UniformType::_1f),
+        ],
+    )
+}
+
+/// The rounded rectangle a surface is masked with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CornerMask {
+    /// The rounded rectangle in normalized texture coordinates.
+    pub rect: Rectangle<f64, Buffer>,
+
+    /// The radius of the corners in normalized texture coordinates.
+    pub radius: Size<f64, Buffer>,
+
+    /// The radius of the corners in output pixels.
+    pub pixels: f64,
+
+    /// Whether the texture has straight alpha.
+    pub premultiply: bool,
+
+    /// How much the surface is darkened.
+    pub dim: f32,
+}
+
+impl CornerMask {
+    /// Normalize a rounded rectangle and radius in buffer coordinates by the size of the buffer.
+    pub fn new(
+        rect: Rectangle<f64, Buffer>,
+        radius: Size<f64, Buffer>,
+        buffer_size: Size<f64, Buffer>,
+        pixels: f64,
+    ) -> Option<Self> {
+        if buffer_size.w <= 0.0 || buffer_size.h <= 0.0 || radius.w <= 0.0 || radius.h <= 0.0 {
+            return None;
+        }
+
+        // The radius cannot be larger than half of the rectangle.
+        let radius = Size::from((radius.w.min(rect.size.w / 2.0), radius.h.min(rect.size.h / 2.0)));
+
+        Some(Self {
+            rect: Rectangle::from_loc_and_size(
+                (rect.loc.x / buffer_size.w, rect.loc.y / buffer_size.h),
+                (rect.size.w / buffer_size.w, rect.size.h / buffer_size.h),
+            ),
+            radius: (radius.w / buffer_size.w, radius.h / buffer_size.h).into(),
+            pixels,
+            premultiply: false,
+            dim: 0.0,
+        })
+    }
+
+    /// The uniforms of [`ROUNDED_CORNERS_SHADER`] which apply the mask.
+    pub fn uniforms(&self) -> Vec<Uniform<'static>> {
+        let rect = self.rect;
+
+        vec![
+            Uniform::new(
+                "corner_rect",
+                (
+                    rect.loc.x as f32,
+                    rect.loc.y as f32,
+                    (rect.loc.x + rect.size.w) as f32,
+                    (rect.loc.y + rect.size.h) as f32,
+                ),
+            ),
+            Uniform::new("corner_radius", (self.radius.w as f32, self.radius.h as f32)),
+            Uniform::new("corner_pixels", self.pixels as f32),
+            Uniform::new("premultiply", if self.premultiply { 1.0 } else { 0.0 }),
+            Uniform::new("dim", self.dim.clamp(0.0, 1.0)),
+        ]
+    }
+}
+
+/// An element whose corners are masked, drawn with [`ROUNDED_CORNERS_SHADER`].
+#[derive(Debug)]
+pub struct RoundedElement<E> {
+    inner: E,
+    program: GlesTexProgram,
+    mask: CornerMask,
+}
+
+impl<E> RoundedElement<E> {
+    /// Draw the element with the program compiled by [`compile_rounded_corners_shader`].
+    pub fn new(inner: E, program: GlesTexProgram, mask: CornerMask) -> Self {
+        Self { inner, program, mask }
+    }
+}
+
+impl<E: Element> Element for RoundedElement<E> {
+    fn id(&self) -> &Id {
+        self.inner.id()
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.inner.current_commit()
+    }
+
+    fn src(&self) -> Rectangle<f64, Buffer> {
+        self.inner.src()
+    }
+
+    fn geometry(&self, scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.inner.geometry(scale)
+    }
+
+    fn damage_since(&self, scale: Scale<f64>, commit: Option<CommitCounter>) -> Vec<Rectangle<i32, Physical>> {
+        self.inner.damage_since(scale, commit)
+    }
+
+    fn opaque_regions(&self, scale: Scale<f64>) -> Vec<Rectangle<i32, Physical>> {
+        self.inner.opaque_regions(scale)
+    }
+}
+
+impl<E: RenderElement<GlesRenderer>> RenderElement<GlesRenderer> for RoundedElement<E> {
+    fn draw<'a>(
+        &self,
+        frame: &mut GlesFrame<'a>,
+        src: Rectangle<f64, Buffer>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<(), GlesError> {
+        frame.override_default_tex_program(self.program.clone(), self.mask.uniforms());
+        let result = self.inner.draw(frame, src, dst, damage);
+        frame.clear_tex_program_override();
+        result
+    }
+
+    fn underlying_storage(&self, _renderer: &mut GlesRenderer) -> Option<UnderlyingStorage> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::Rectangle;
+
+    use super::CornerMask;
+
+    #[test]
+    fn normalized_by_buffer() {
+        let mask = CornerMask::new(
+            Rectangle::from_loc_and_size((0.0, 0.0), (200.0, 100.0)),
+            (10.0, 10.0).into(),
+            (400.0, 100.0).into(),
+            10.0,
+        )
+        .unwrap();
+
+        assert_eq!(mask.rect, Rectangle::from_loc_and_size((0.0, 0.0), (0.5, 1.0)));
+        assert_eq!(mask.radius, (0.025, 0.1).into());
+    }
+
+    #[test]
+    fn radius_fits_rectangle() {
+        let mask = CornerMask::new(
+            Rectangle::from_loc_and_size((0.0, 0.0), (20.0, 20.0)),
+            (50.0, 50.0).into(),
+            (20.0, 20.0).into(),
+            50.0,
+        )
+        .unwrap();
+
+        assert_eq!(mask.radius, (0.5, 0.5).into());
+    }
+}
@@ -0,0 +1,195 @@
+//! Window rules
+//!
+//! Window rules match toplevels by app id and title and decide how the toplevel is initially placed: which
+//! workspace it opens on, whether it floats, its size and its opacity. Rules are loaded from the configuration
+//! and kept by the display server, so the built-in policy and the wm share one set of rules. The wm queries the
+//! rules which apply to a toplevel and decides whether to honor them.
+//!
+//! Every rule which matches a toplevel is applied in order, so a later rule overrides the actions of an earlier
+//! rule. Patterns are regular expressions and match anywhere in the app id or title unless anchored with `^` and
+//! `$`.
+
+use regex::Regex;
+use smithay::utils::{Logical, Size};
+
+/// An error from creating a window rule.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid pattern: {0}")]
+    InvalidPattern(#[from] regex::Error),
+
+    #[error("opacity {0} must be between 0 and 1")]
+    InvalidOpacity(f32),
+}
+
+/// Actions applied to a toplevel matching a rule.
+///
+/// Actions which are [`None`] are not changed by the rule.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleActions {
+    /// The name of the workspace the toplevel opens on.
+    pub workspace: Option<String>,
+    pub floating: Option<bool>,
+    /// The initial size of the toplevel.
+    pub size: Option<Size<i32, Logical>>,
+    pub opacity: Option<f32>,
+}
+
+impl RuleActions {
+    /// Apply the actions of a later rule on top of these actions.
+    fn merge(&mut self, other: &RuleActions) {
+        if let Some(workspace) = &other.workspace {
+            self.workspace = Some(workspace.clone());
+        }
+
+        self.floating = other.floating.or(self.floating);
+        self.size = other.size.or(self.size);
+        self.opacity = other.opacity.or(self.opacity);
+    }
+}
+
+/// A window rule.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    app_id: Option<Regex>,
+    title: Option<Regex>,
+    actions: RuleActions,
+}
+
+impl Rule {
+    /// Create a rule matching the app id and title patterns.
+    ///
+    /// A rule without patterns matches every toplevel.
+    pub fn new(app_id: Option<&str>, title: Option<&str>, actions: RuleActions) -> Result<Self, Error> {
+        if let Some(opacity) = actions.opacity.filter(|opacity| !(0.0..=1.0).contains(opacity)) {
+            return Err(Error::InvalidOpacity(opacity));
+        }
+
+        Ok(Self {
+            app_id: app_id.map(Regex::new).transpose()?,
+            title: title.map(Regex::new).transpose()?,
+            actions,
+        })
+    }
+
+    /// Whether the rule applies to a toplevel.
+    ///
+    /// A pattern never matches a toplevel which has not set the property.
+    pub fn matches(&self, app_id: Option<&str>, title: Option<&str>) -> bool {
+        let matches = |pattern: &Option<Regex>, value: Option<&str>| match pattern {
+            Some(pattern) => value.is_some_and(|value| pattern.is_match(value)),
+            None => true,
+        };
+
+        matches(&self.app_id, app_id) && matches(&self.title, title)
+    }
+}
+
+/// The window rules from the configuration.
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rule after every other rule.
+    pub fn push(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    /// Replace the rules, such as when the configuration is reloaded.
+    pub fn replace(&mut self, rules: Vec<Rule>) {
+        self.rules = rules;
+    }
+
+    /// The actions of every rule which applies to a toplevel.
+    pub fn query(&self, app_id: Option<&str>, title: Option<&str>) -> RuleActions {
+        self.rules.iter().filter(|rule| rule.matches(app_id, title)).fold(
+            RuleActions::default(),
+            |mut actions, rule| {
+                actions.merge(&rule.actions);
+                actions
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Rule, RuleActions, Rules};
+
+    fn rules() -> Rules {
+        let mut rules = Rules::new();
+        rules.push(
+            Rule::new(
+                Some("^firefox$"),
+                None,
+                RuleActions {
+                    workspace: Some("web".into()),
+                    opacity: Some(0.9),
+                    ..RuleActions::default()
+                },
+            )
+            .unwrap(),
+        );
+        rules.push(
+            Rule::new(
+                None,
+                Some("Picture-in-Picture"),
+                RuleActions {
+                    floating: Some(true),
+                    opacity: Some(1.0),
+                    ..RuleActions::default()
+                },
+            )
+            .unwrap(),
+        );
+        rules
+    }
+
+    #[test]
+    fn later_rules_override() {
+        let rules = rules();
+
+        let actions = rules.query(Some("firefox"), Some("Picture-in-Picture"));
+        assert_eq!(
+            actions,
+            RuleActions {
+                workspace: Some("web".into()),
+                floating: Some(true),
+                size: None,
+                opacity: Some(1.0),
+            }
+        );
+
+        let actions = rules.query(Some("firefox"), Some("Mozilla Firefox"));
+        assert_eq!(actions.floating, None);
+        assert_eq!(actions.opacity, Some(0.9));
+    }
+
+    #[test]
+    fn unset_properties_do_not_match() {
+        let rules = rules();
+
+        assert_eq!(rules.query(None, None), RuleActions::default());
+        assert_eq!(rules.query(Some("firefox-esr"), None), RuleActions::default());
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(matches!(
+            Rule::new(Some("("), None, RuleActions::default()),
+            Err(Error::InvalidPattern(_))
+        ));
+
+        let actions = RuleActions {
+            opacity: Some(1.5),
+            ..RuleActions::default()
+        };
+        assert!(matches!(Rule::new(None, None, actions), Err(Error::InvalidOpacity(_))));
+    }
+}
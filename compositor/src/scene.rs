@@ -2,22 +2,39 @@
 //!
 //! TODO: Documentation
 
-use std::ops::{Deref, DerefMut};
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::hash_map::Entry,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
 
 use rustc_hash::FxHashMap;
 use smithay::{
-    backend::renderer::{
-        element::{AsRenderElements, Element, Id, RenderElement, UnderlyingStorage},
-        utils::{CommitCounter, RendererSurfaceStateUserData},
-        Frame, ImportAll, Renderer,
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            element::{AsRenderElements, Element, Id, RenderElement, UnderlyingStorage},
+            utils::{CommitCounter, RendererSurfaceStateUserData, SurfaceView},
+            Frame, ImportAll, ImportMem, Renderer,
+        },
     },
     output::Output,
-    utils::{Buffer, Physical, Point, Rectangle, Scale, Transform},
-    wayland::compositor,
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Scale, Size, Transform},
+    wayland::compositor::{self, SurfaceAttributes, SurfaceData},
+};
+use wayland_server::{
+    backend::ObjectId,
+    protocol::{wl_buffer::WlBuffer, wl_surface},
+    Resource,
 };
-use wayland_server::{backend::ObjectId, protocol::wl_surface, Resource};
 
-use crate::forest::{Error, Forest, Index};
+use crate::{
+    alpha::{self, AlphaMode},
+    forest::{Error, Forest, Index, Node},
+    rounded::CornerMask,
+};
 
 /// A stable index to reference an [`OutputNode`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -35,10 +52,15 @@ pub struct SurfaceIndex(Index);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BranchIndex(Index);
 
+/// A stable index to reference a [`ShapeNode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ShapeIndex(Index);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NodeIndex {
     SurfaceTree(SurfaceTreeIndex),
     Branch(BranchIndex),
+    Shape(ShapeIndex),
 }
 
 impl PartialEq<SurfaceTreeIndex> for NodeIndex {
@@ -53,6 +75,12 @@ impl PartialEq<BranchIndex> for NodeIndex {
     }
 }
 
+impl PartialEq<ShapeIndex> for NodeIndex {
+    fn eq(&self, other: &ShapeIndex) -> bool {
+        Self::Shape(*other) == *self
+    }
+}
+
 #[derive(Debug)]
 pub struct OutputNode {
     index: OutputIndex,
@@ -70,6 +98,138 @@ impl OutputNode {
     }
 }
 
+/// Properties applied when compositing a surface tree.
+///
+/// The wm uses these for effects such as fading in a toplevel or scaling toplevels down for an overview. The
+/// properties are not visible to clients.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderProperties {
+    /// Opacity between 0.0 (transparent) and 1.0 (opaque).
+    pub opacity: f32,
+
+    /// Scale around the center of the root surface of the surface tree.
+    pub scale: f64,
+
+    /// Clockwise rotation in degrees around the center of the root surface of the surface tree.
+    ///
+    /// The rotation is rounded to a multiple of 90 degrees, which is drawn by transforming the textures like an
+    /// output transform.
+    pub rotation: f64,
+
+    /// Offset from the position of the surface tree.
+    pub translation: Point<i32, Physical>,
+
+    /// The part of the surface tree which is drawn, relative to the surface tree, or [`None`] to draw all of it.
+    ///
+    /// The clip is applied before the scale.
+    pub clip: Option<Rectangle<i32, Physical>>,
+
+    /// The radius of the rounded corners of the root surface of the surface tree, or of the clip if one is set.
+    ///
+    /// The corners are masked by a [`RoundedElement`](crate::rounded::RoundedElement).
+    pub corner_radius: i32,
+
+    /// How much the surface tree is darkened, from 0.0 (unchanged) to 1.0 (black).
+    ///
+    /// This is set by the display server rather than the wm, such as to dim unresponsive toplevels.
+    pub dim: f32,
+
+    /// Whether the surface tree is not drawn, such as a toplevel in a background tab.
+    ///
+    /// This is set by the display server rather than the wm.
+    pub hidden: bool,
+}
+
+impl Default for RenderProperties {
+    fn default() -> Self {
+        Self {
+            opacity: 1.0,
+            scale: 1.0,
+            rotation: 0.0,
+            translation: (0, 0).into(),
+            clip: None,
+            corner_radius: 0,
+            dim: 0.0,
+            hidden: false,
+        }
+    }
+}
+
+impl RenderProperties {
+    /// The number of clockwise quarter turns the surface tree is rotated by.
+    pub fn quarter_turns(&self) -> u8 {
+        // A NaN rotation is not rotated.
+        (self.rotation / 90.0).round().rem_euclid(4.0) as u8
+    }
+
+    /// Apply the scale, rotation and translation to the geometry of a surface in the surface tree.
+    ///
+    /// `center` is the center of the root surface the scale and rotation are applied around.
+    pub fn transform_geometry(
+        &self,
+        geometry: Rectangle<i32, Physical>,
+        center: Point<f64, Physical>,
+    ) -> Rectangle<i32, Physical> {
+        let transform = |x: i32, y: i32| {
+            let x = (x as f64 - center.x) * self.scale;
+            let y = (y as f64 - center.y) * self.scale;
+
+            // Clockwise, since the y axis points down.
+            let (x, y) = match self.quarter_turns() {
+                0 => (x, y),
+                1 => (-y, x),
+                2 => (-x, -y),
+                _ => (y, -x),
+            };
+
+            ((center.x + x).round() as i32, (center.y + y).round() as i32)
+        };
+
+        let (x1, y1) = transform(geometry.loc.x, geometry.loc.y);
+        let (x2, y2) = transform(geometry.loc.x + geometry.size.w, geometry.loc.y + geometry.size.h);
+
+        let mut geometry = Rectangle::from_extemities((x1.min(x2), y1.min(y2)), (x1.max(x2), y1.max(y2)));
+        geometry.loc += self.translation;
+        geometry
+    }
+
+    /// Rotate a rectangle inside of an area of the given size by the rotation, such as the damage of a surface.
+    fn rotate_in(&self, rect: Rectangle<i32, Physical>, size: Size<i32, Physical>) -> Rectangle<i32, Physical> {
+        let Rectangle { loc, size: rect } = rect;
+
+        match self.quarter_turns() {
+            0 => Rectangle::from_loc_and_size(loc, rect),
+            1 => Rectangle::from_loc_and_size((size.h - loc.y - rect.h, loc.x), (rect.h, rect.w)),
+            2 => Rectangle::from_loc_and_size((size.w - loc.x - rect.w, size.h - loc.y - rect.h), rect),
+            _ => Rectangle::from_loc_and_size((loc.y, size.w - loc.x - rect.w), (rect.h, rect.w)),
+        }
+    }
+
+    /// The transform a texture of the surface tree is drawn with, given the transform of the buffer.
+    ///
+    /// A buffer transform is undone when drawing, so a clockwise rotation is drawn with the buffer transform
+    /// rotated counter-clockwise.
+    fn texture_transform(&self, transform: Transform) -> Transform {
+        const ROTATIONS: [Transform; 4] = [Transform::Normal, Transform::_90, Transform::_180, Transform::_270];
+        const FLIPPED: [Transform; 4] = [
+            Transform::Flipped,
+            Transform::Flipped90,
+            Transform::Flipped180,
+            Transform::Flipped270,
+        ];
+
+        let turns = usize::from(self.quarter_turns());
+
+        if let Some(index) = ROTATIONS.iter().position(|&rotation| rotation == transform) {
+            ROTATIONS[(index + turns) % 4]
+        } else {
+            // Rotating after the flip rotates the other way, since the flip is applied first.
+            let index = FLIPPED.iter().position(|&flipped| flipped == transform).unwrap_or(0);
+            FLIPPED[(index + 4 - turns) % 4]
+        }
+    }
+}
+
 /// A node for a surface and it's subsurface tree.
 #[derive(Debug)]
 pub struct SurfaceTreeNode {
@@ -79,6 +239,7 @@ pub struct SurfaceTreeNode {
     top: SurfaceIndex,
     /// The offset of the root surface from the parent.
     offset: Point<i32, Physical>,
+    render: RenderProperties,
 }
 
 impl SurfaceTreeNode {
@@ -107,6 +268,10 @@ impl SurfaceTreeNode {
     pub fn top(&self) -> SurfaceIndex {
         self.top
     }
+
+    pub fn render_properties(&self) -> RenderProperties {
+        self.render
+    }
 }
 
 #[derive(Debug)]
@@ -122,6 +287,133 @@ pub struct BranchNode {
     offset: Point<i32, Physical>,
 }
 
+/// What a [`ShapeNode`] draws.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    /// A color with premultiplied alpha filling the node.
+    Solid([f32; 4]),
+
+    /// A border of the width along the inside of the node.
+    Border { width: i32, color: [f32; 4] },
+
+    /// An image stretched to fill the node.
+    Texture {
+        size: Size<i32, Buffer>,
+
+        /// Premultiplied ARGB8888 pixels, row by row.
+        pixels: Vec<u8>,
+    },
+}
+
+/// A node drawn by the display server on behalf of the wm, such as the background of a workspace.
+#[derive(Debug)]
+pub struct ShapeNode {
+    index: ShapeIndex,
+    offset: Point<i32, Physical>,
+    size: Size<i32, Physical>,
+    shape: Shape,
+    /// The ids of the elements of the shape, so damage is tracked across frames. A border is drawn as four
+    /// elements.
+    ids: [Id; 4],
+    commit: CommitCounter,
+    /// The texture of a texture shape imported into each renderer, by the id of the renderer.
+    textures: ImportedTextures,
+}
+
+type ImportedTextures = Rc<RefCell<FxHashMap<usize, Box<dyn Any>>>>;
+
+impl ShapeNode {
+    pub fn index(&self) -> ShapeIndex {
+        self.index
+    }
+
+    pub fn size(&self) -> Size<i32, Physical> {
+        self.size
+    }
+
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    /// The elements drawing the shape at the location.
+    ///
+    /// Textures are drawn by [`ShapeNode::texture_element`] instead, since they must be imported first.
+    fn elements(&self, location: Point<i32, Physical>) -> Vec<ShapeElement> {
+        let (w, h) = (self.size.w, self.size.h);
+
+        let (color, rects) = match self.shape {
+            Shape::Solid(color) => (color, vec![Rectangle::from_loc_and_size((0, 0), (w, h))]),
+
+            Shape::Border { width, color } => {
+                let width = width.clamp(0, w.min(h) / 2);
+
+                let rects = vec![
+                    Rectangle::from_loc_and_size((0, 0), (w, width)),
+                    Rectangle::from_loc_and_size((0, h - width), (w, width)),
+                    Rectangle::from_loc_and_size((0, width), (width, h - 2 * width)),
+                    Rectangle::from_loc_and_size((w - width, width), (width, h - 2 * width)),
+                ];
+                (color, rects)
+            }
+
+            Shape::Texture { .. } => return Vec::new(),
+        };
+
+        rects
+            .into_iter()
+            .zip(&self.ids)
+            .filter(|(rect, _)| !rect.is_empty())
+            .map(|(mut geometry, id)| {
+                geometry.loc += location;
+
+                ShapeElement {
+                    id: id.clone(),
+                    commit: self.commit,
+                    geometry,
+                    color,
+                }
+            })
+            .collect()
+    }
+
+    /// The element drawing a texture shape at the location, importing the texture into the renderer the first
+    /// time it is drawn by the renderer.
+    fn texture_element<R>(&self, renderer: &mut R, location: Point<i32, Physical>) -> Option<TextureElement>
+    where
+        R: Renderer + ImportMem,
+        R::TextureId: 'static,
+    {
+        let Shape::Texture { size, pixels } = &self.shape else {
+            return None;
+        };
+
+        if pixels.len() != size.w.max(0) as usize * size.h.max(0) as usize * 4 {
+            return None;
+        }
+
+        if let Entry::Vacant(entry) = self.textures.borrow_mut().entry(renderer.id()) {
+            match renderer.import_memory(pixels, Fourcc::Argb8888, *size, false) {
+                Ok(texture) => {
+                    entry.insert(Box::new(texture));
+                }
+
+                Err(err) => {
+                    tracing::warn!(?err, "Failed to import the texture of a shape");
+                    return None;
+                }
+            }
+        }
+
+        Some(TextureElement {
+            id: self.ids[0].clone(),
+            commit: self.commit,
+            geometry: Rectangle::from_loc_and_size(location, self.size),
+            buffer_size: *size,
+            textures: self.textures.clone(),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct Scene {
     outputs: FxHashMap<Output, OutputIndex>,
@@ -191,6 +483,15 @@ impl Scene {
         // TODO: Send enter and exit events
     }
 
+    /// Present nothing on the output.
+    pub fn clear_output_node(&mut self, output: &Output) {
+        self.unset_output_root(output);
+
+        if let Some(index) = self.get_output_index(output) {
+            self.get_output_mut(index).unwrap().present = None;
+        }
+    }
+
     pub fn get_surface_tree_index(&self, surface: wl_surface::WlSurface) -> Option<SurfaceTreeIndex> {
         self.surface_trees.get(&surface.id()).cloned()
     }
@@ -219,16 +520,40 @@ impl Scene {
                 base: root,
                 top: root,
                 offset: Default::default(),
+                render: Default::default(),
             })
         }));
 
         self.forest.add_child(index.0, root.0).unwrap();
+        self.surface_trees.insert(surface.id(), index);
+        self.surfaces.insert(surface.id(), root);
 
         // Initialize the surface tree
         self.apply_surface_commit(&surface);
         index
     }
 
+    /// Destroy the surface tree of the surface, such as when the toplevel is unmapped.
+    pub fn destroy_surface_tree(&mut self, surface: &wl_surface::WlSurface) {
+        // Surface trees placed on the surface tree, such as popups, are detached so they do not refer to a removed
+        // parent.
+        if let Some(&index) = self.surface_trees.get(&surface.id()) {
+            let children = self.children(NodeIndex::SurfaceTree(index)).collect::<Vec<_>>();
+
+            for child in children {
+                self.detach_node(child);
+            }
+        }
+
+        if let Some(root) = self.surfaces.remove(&surface.id()) {
+            let _ = self.forest.remove(root.0);
+        }
+
+        if let Some(index) = self.surface_trees.remove(&surface.id()) {
+            let _ = self.forest.remove(index.0);
+        }
+    }
+
     pub fn get_surface_index(&self, surface: wl_surface::WlSurface) -> Option<SurfaceIndex> {
         self.surfaces.get(&surface.id()).cloned()
     }
@@ -247,8 +572,6 @@ impl Scene {
         // TODO: Do we need a commit state to apply since we are transaction based?
     }
 
-    // TODO: Surface destroyed (for both tree and surface)
-
     pub fn create_branch(&mut self) -> BranchIndex {
         BranchIndex(self.forest.insert_with(|index| {
             SceneNode::Branch(BranchNode {
@@ -273,6 +596,69 @@ impl Scene {
         let _ = self.forest.remove(index.into());
     }
 
+    pub fn create_shape(&mut self, size: Size<i32, Physical>, shape: Shape) -> ShapeIndex {
+        ShapeIndex(self.forest.insert_with(|index| {
+            SceneNode::Shape(ShapeNode {
+                index: ShapeIndex(index),
+                offset: (0, 0).into(),
+                size,
+                shape,
+                ids: [(); 4].map(|_| Id::new()),
+                commit: CommitCounter::default(),
+                textures: ImportedTextures::default(),
+            })
+        }))
+    }
+
+    pub fn get_shape(&mut self, index: ShapeIndex) -> Option<&mut ShapeNode> {
+        self.forest.get_mut(index.0).map(|node| match node.deref_mut() {
+            SceneNode::Shape(node) => node,
+            _ => unreachable!(),
+        })
+    }
+
+    /// Resize a solid color, border or texture.
+    pub fn set_shape_size(&mut self, index: ShapeIndex, size: Size<i32, Physical>) {
+        if let Some(shape) = self.get_shape(index).filter(|shape| shape.size != size) {
+            shape.size = size;
+            shape.commit.increment();
+        }
+    }
+
+    /// Change the color of a solid color or border.
+    pub fn set_shape_color(&mut self, index: ShapeIndex, color: [f32; 4]) {
+        let Some(node) = self.get_shape(index) else {
+            return;
+        };
+
+        match &mut node.shape {
+            Shape::Solid(current) | Shape::Border { color: current, .. } => *current = color,
+            Shape::Texture { .. } => return,
+        }
+
+        node.commit.increment();
+    }
+
+    pub fn destroy_shape(&mut self, index: ShapeIndex) {
+        let _ = self.forest.remove(index.into());
+    }
+
+    /// Add the node above the other children of the parent, removing the node from its previous parent.
+    pub fn add_child(&mut self, parent: NodeIndex, index: NodeIndex) -> Result<(), Error> {
+        // Check for cycles before the node loses its previous parent.
+        if parent == index || self.forest.is_ancestor(index.into(), parent.into()) {
+            return Err(Error::Cycle);
+        }
+
+        self.forest.detach(index.into())?;
+        self.forest.add_child(parent.into(), index.into())
+    }
+
+    /// Remove the node from its parent, so the node and its children are no longer drawn.
+    pub fn detach_node(&mut self, index: NodeIndex) {
+        let _ = self.forest.detach(index.into());
+    }
+
     /// Sets the offset of the node relative to it's parent.
     pub fn set_node_offset(&mut self, index: NodeIndex, offset: Point<i32, Physical>) {
         match index {
@@ -287,31 +673,206 @@ impl Scene {
                     branch.offset = offset;
                 }
             }
+
+            NodeIndex::Shape(index) => {
+                if let Some(shape) = self.get_shape(index) {
+                    shape.offset = offset;
+                }
+            }
+        }
+    }
+
+    /// Set the properties used when compositing the surface tree.
+    pub fn set_render_properties(&mut self, index: SurfaceTreeIndex, render: RenderProperties) {
+        if let Some(surface_tree) = self.get_surface_tree(index) {
+            surface_tree.render = render;
+        }
+    }
+
+    /// The render properties of the surface tree containing the surface.
+    fn surface_render_properties(&self, index: Index) -> RenderProperties {
+        let mut next = self.forest.get(index).and_then(Node::parent);
+
+        while let Some(parent) = next {
+            let node = self.forest.get(parent).unwrap();
+
+            if let SceneNode::SurfaceTree(surface_tree) = node.deref() {
+                return surface_tree.render;
+            }
+
+            next = Node::parent(node);
         }
+
+        RenderProperties::default()
+    }
+
+    /// The center of the root surface of the surface tree containing the surface, relative to the surface.
+    ///
+    /// The render properties of the surface tree are applied around this point, so the subsurfaces of a toplevel
+    /// are scaled and rotated with the toplevel.
+    fn surface_center(&self, index: Index) -> Point<f64, Physical> {
+        self.root_surface_geometry(index)
+            .map(|geometry| {
+                let geometry = geometry.to_f64();
+                geometry.loc + Point::from((geometry.size.w / 2.0, geometry.size.h / 2.0))
+            })
+            .unwrap_or_default()
+    }
+
+    /// The geometry of the root surface of the surface tree containing the surface, relative to the surface.
+    fn root_surface_geometry(&self, index: Index) -> Option<Rectangle<i32, Physical>> {
+        let mut next = Some(index);
+
+        while let Some(node) = next.and_then(|index| self.forest.get(index)) {
+            if let SceneNode::SurfaceTree(surface_tree) = node.deref() {
+                let root = surface_tree.root;
+                let SceneNode::Surface(surface) = self.forest.get(root.into())?.deref() else {
+                    return None;
+                };
+
+                let view = compositor::with_states(&surface.surface, |states| {
+                    let data = states.data_map.get::<RendererSurfaceStateUserData>();
+                    data.and_then(|d| d.borrow().view())
+                })?;
+
+                let offset = self.location(root.into()) - self.location(index);
+                return Some(Rectangle::from_loc_and_size(
+                    view.offset.to_physical(1) + offset,
+                    view.dst.to_physical(1),
+                ));
+            }
+
+            next = Node::parent(node);
+        }
+
+        None
+    }
+
+    /// The geometry of the root surface of the surface tree, relative to the root of the scene.
+    pub fn surface_tree_geometry(&self, index: SurfaceTreeIndex) -> Option<Rectangle<i32, Physical>> {
+        let SceneNode::SurfaceTree(surface_tree) = self.forest.get(index.into())?.deref() else {
+            return None;
+        };
+
+        let root = surface_tree.root;
+        let SceneNode::Surface(surface) = self.forest.get(root.into())?.deref() else {
+            return None;
+        };
+
+        let view = compositor::with_states(&surface.surface, |states| {
+            let data = states.data_map.get::<RendererSurfaceStateUserData>();
+            data.and_then(|d| d.borrow().view())
+        })?;
+
+        let location = self.location(root.into()) + view.offset.to_physical(1);
+        Some(Rectangle::from_loc_and_size(location, view.dst.to_physical(1)))
+    }
+
+    /// The position of the surface tree relative to the root of the scene.
+    pub fn surface_tree_location(&self, index: SurfaceTreeIndex) -> Point<i32, Physical> {
+        self.location(index.into())
+    }
+
+    /// The clip of the surface tree containing the surface, relative to the surface.
+    fn surface_clip(&self, index: Index, clip: Rectangle<i32, Physical>) -> Rectangle<i32, Physical> {
+        let mut clip = clip;
+        let mut next = Some(index);
+
+        while let Some(node) = next.and_then(|index| self.forest.get(index)) {
+            match node.deref() {
+                SceneNode::Surface(node) => clip.loc -= node.offset,
+                _ => break,
+            }
+
+            next = Node::parent(node);
+        }
+
+        clip
+    }
+
+    /// The position of the node relative to the root of its tree.
+    fn location(&self, index: Index) -> Point<i32, Physical> {
+        let mut location = Point::default();
+        let mut next = Some(index);
+
+        while let Some(node) = next.and_then(|index| self.forest.get(index)) {
+            location += match node.deref() {
+                SceneNode::Output(_) => Point::default(),
+                SceneNode::SurfaceTree(node) => node.offset,
+                SceneNode::Surface(node) => node.offset,
+                SceneNode::Branch(node) => node.offset,
+                SceneNode::Shape(node) => node.offset,
+            };
+
+            next = Node::parent(node);
+        }
+
+        location
     }
 
     /// Raise the node one node higher relative to the parent.
     ///
     /// This will cause the node to farther above the parent.
     pub fn raise_node(&mut self, index: NodeIndex) {
-        todo!()
+        if let Some(next) = self.forest.get(index.into()).and_then(Node::next_sibling) {
+            let _ = self.forest.insert_after(next, index.into());
+        }
     }
 
     /// Raise the node to become child node placed highest above the parent.
     pub fn raise_node_to_top(&mut self, index: NodeIndex) {
-        todo!()
+        let last = self
+            .forest
+            .get(index.into())
+            .and_then(Node::parent)
+            .and_then(|parent| self.forest.get(parent))
+            .and_then(Node::last_child);
+
+        if let Some(last) = last.filter(|&last| last != index.into()) {
+            let _ = self.forest.insert_after(last, index.into());
+        }
     }
 
     /// Lower the node one node relative to other children of it's parent.
     ///
     /// This will cause the node to be closer but still above the parent node.
     pub fn lower_node(&mut self, index: NodeIndex) {
-        todo!()
+        if let Some(prev) = self.forest.get(index.into()).and_then(Node::prev_sibling) {
+            let _ = self.forest.insert_before(prev, index.into());
+        }
     }
 
     /// Lower the node to be the lowest node above it's parent.
     pub fn lower_node_to_bottom(&mut self, index: NodeIndex) {
-        todo!()
+        let first = self
+            .forest
+            .get(index.into())
+            .and_then(Node::parent)
+            .and_then(|parent| self.forest.get(parent))
+            .and_then(Node::first_child);
+
+        if let Some(first) = first.filter(|&first| first != index.into()) {
+            let _ = self.forest.insert_before(first, index.into());
+        }
+    }
+
+    /// Place the node directly above the sibling.
+    ///
+    /// If the node has a different parent than the sibling, the node becomes a child of the sibling's parent.
+    pub fn restack_above(&mut self, index: NodeIndex, sibling: NodeIndex) -> Result<(), Error> {
+        self.forest.insert_after(sibling.into(), index.into())
+    }
+
+    /// The children of the node, ordered from the lowest to the highest node.
+    pub fn children(&self, index: NodeIndex) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.forest
+            .children(index.into())
+            .filter_map(|child| match self.forest.get(child)?.deref() {
+                SceneNode::SurfaceTree(node) => Some(NodeIndex::SurfaceTree(node.index)),
+                SceneNode::Branch(node) => Some(NodeIndex::Branch(node.index)),
+                SceneNode::Shape(node) => Some(NodeIndex::Shape(node.index)),
+                SceneNode::Output(_) | SceneNode::Surface(_) => None,
+            })
     }
 
     pub fn get_graph(&self, output: &Output) -> Option<Hierarchy<'_>> {
@@ -323,6 +884,15 @@ impl Scene {
         })
     }
 
+    /// The graph of the surface tree of the surface, such as the lock surface drawn instead of the output root.
+    pub fn get_surface_graph(&self, surface: &wl_surface::WlSurface) -> Option<Hierarchy<'_>> {
+        let index = self.surface_trees.get(&surface.id())?;
+        Some(Hierarchy {
+            scene: self,
+            root: NodeIndex::SurfaceTree(*index),
+        })
+    }
+
     /// Unsets the node which is the output root and sends leave events.
     fn unset_output_root(&mut self, output: &Output) {
         if let Some(index) = self.get_output_index(output) {
@@ -338,15 +908,217 @@ impl Scene {
 pub struct SceneGraphElement {
     id: Id,
     surface: wl_surface::WlSurface,
+    render: RenderProperties,
+    /// The center of the root surface of the surface tree relative to the surface.
+    center: Point<f64, Physical>,
+    /// The clip of the surface tree relative to the surface.
+    clip: Option<Rectangle<i32, Logical>>,
+    /// The rectangle whose corners are rounded relative to the surface, the clip or the root surface.
+    rounded: Option<Rectangle<i32, Logical>>,
+    alpha: AlphaMode,
 }
 
-impl SceneGraphElement {}
+impl SceneGraphElement {
+    /// The scale and transform of the buffer attached to the surface.
+    fn buffer_transform(states: &SurfaceData) -> (i32, Transform) {
+        let attributes = states.cached_state.current::<SurfaceAttributes>();
+        (attributes.buffer_scale, attributes.buffer_transform.into())
+    }
+
+    /// The geometry of the surface before the render properties are applied.
+    fn untransformed_geometry(&self) -> Rectangle<i32, Physical> {
+        compositor::with_states(&self.surface, |states| {
+            let data = states.data_map.get::<RendererSurfaceStateUserData>();
+            data.and_then(|d| d.borrow().view())
+                .and_then(|surface_view| self.clipped_view(surface_view))
+                .map(|(_, dst)| {
+                    let size = (dst.size.to_f64().to_physical(1.0).to_point()).to_i32_round().to_size();
+                    // The offset accumulated from wl_surface.offset and the offset of wl_surface.attach.
+                    let loc = dst.loc.to_f64().to_physical(1.0).to_i32_round();
+                    Rectangle::from_loc_and_size(loc, size)
+                })
+        })
+        .unwrap_or_default()
+    }
+
+    /// The mask of the rounded corners of the surface tree, or [`None`] if the corners are not rounded.
+    pub fn corner_mask(&self) -> Option<CornerMask> {
+        let rounded = self.rounded?.to_f64();
+        let radius = f64::from(self.render.corner_radius);
+
+        let mut mask = compositor::with_states(&self.surface, |states| {
+            let data = states.data_map.get::<RendererSurfaceStateUserData>()?.borrow();
+            let (buffer_scale, transform) = Self::buffer_transform(states);
+            let (view, buffer_size) = (data.view()?, data.buffer_size()?);
+
+            if view.dst.w <= 0 || view.dst.h <= 0 {
+                return None;
+            }
+
+            // The viewport scales the source to the destination, so the rectangle is moved into the source.
+            let scale_x = view.src.size.w / f64::from(view.dst.w);
+            let scale_y = view.src.size.h / f64::from(view.dst.h);
+            let src = Rectangle::from_loc_and_size(
+                (
+                    view.src.loc.x + (rounded.loc.x - f64::from(view.offset.x)) * scale_x,
+                    view.src.loc.y + (rounded.loc.y - f64::from(view.offset.y)) * scale_y,
+                ),
+                (rounded.size.w * scale_x, rounded.size.h * scale_y),
+            );
+            let radius = Size::<f64, Logical>::from((radius * scale_x, radius * scale_y));
+
+            CornerMask::new(
+                buffer_src(src, buffer_scale, transform, buffer_size),
+                radius.to_buffer(f64::from(buffer_scale), transform),
+                buffer_size.to_buffer(buffer_scale, transform).to_f64(),
+                radius * self.render.scale,
+            )
+        })?;
+
+        mask.premultiply = self.alpha.needs_premultiply();
+        mask.dim = self.render.dim;
+        Some(mask)
+    }
+
+    /// The source and destination of the surface after clipping, or [`None`] if the whole surface is clipped.
+    fn clipped_view(&self, view: SurfaceView) -> Option<(Rectangle<f64, Logical>, Rectangle<i32, Logical>)> {
+        let dst = Rectangle::from_loc_and_size(view.offset, view.dst);
+
+        match self.clip {
+            Some(clip) => clip_view(view.src, dst, clip),
+            None => Some((view.src, dst)),
+        }
+    }
+}
+
+/// The part of a surface view inside the clip.
+///
+/// The source is cropped by the same fraction as the destination, so the part of the surface which is drawn is not
+/// stretched.
+fn clip_view(
+    src: Rectangle<f64, Logical>,
+    dst: Rectangle<i32, Logical>,
+    clip: Rectangle<i32, Logical>,
+) -> Option<(Rectangle<f64, Logical>, Rectangle<i32, Logical>)> {
+    if dst.size.w <= 0 || dst.size.h <= 0 {
+        return None;
+    }
+
+    let clipped = dst
+        .intersection(clip)
+        .filter(|clipped| clipped.size.w > 0 && clipped.size.h > 0)?;
+    let scale_x = src.size.w / f64::from(dst.size.w);
+    let scale_y = src.size.h / f64::from(dst.size.h);
+
+    let src = Rectangle::from_loc_and_size(
+        (
+            src.loc.x + f64::from(clipped.loc.x - dst.loc.x) * scale_x,
+            src.loc.y + f64::from(clipped.loc.y - dst.loc.y) * scale_y,
+        ),
+        (f64::from(clipped.size.w) * scale_x, f64::from(clipped.size.h) * scale_y),
+    );
+
+    Some((src, clipped))
+}
+
+/// The area of the buffer sampled for a surface, in buffer coordinates.
+///
+/// The source rectangle of a viewport is in surface coordinates and may be fractional. It is converted without
+/// rounding, so a client scrolling by a fraction of a pixel or cropping a video samples exactly the texels it
+/// requested.
+fn buffer_src(
+    src: Rectangle<f64, Logical>,
+    buffer_scale: i32,
+    transform: Transform,
+    buffer_size: Size<i32, Logical>,
+) -> Rectangle<f64, Buffer> {
+    src.to_buffer(f64::from(buffer_scale), transform, &buffer_size.to_f64())
+}
+
+/// The damage of a buffer in the coordinates of the surface.
+///
+/// The damage is transformed like the buffer is when drawn, so a client which rotates or flips its buffers, such
+/// as a video player or a phone in portrait, is redrawn where its contents changed. Damage outside of the viewport
+/// source is not visible and is dropped.
+fn surface_damage(
+    damage: Rectangle<i32, Buffer>,
+    buffer_scale: i32,
+    transform: Transform,
+    buffer_size: Size<i32, Logical>,
+    src: Rectangle<f64, Logical>,
+    dst: Size<i32, Logical>,
+) -> Option<Rectangle<f64, Logical>> {
+    if src.size.w <= 0.0 || src.size.h <= 0.0 {
+        return None;
+    }
+
+    let buffer_area = buffer_size.to_buffer(buffer_scale, transform).to_f64();
+    let damage = damage
+        .to_f64()
+        .to_logical(f64::from(buffer_scale), transform, &buffer_area)
+        .intersection(src)?;
+
+    // The viewport scales the source to the destination size.
+    let scale_x = f64::from(dst.w) / src.size.w;
+    let scale_y = f64::from(dst.h) / src.size.h;
+
+    Some(Rectangle::from_loc_and_size(
+        (
+            (damage.loc.x - src.loc.x) * scale_x,
+            (damage.loc.y - src.loc.y) * scale_y,
+        ),
+        (damage.size.w * scale_x, damage.size.h * scale_y),
+    ))
+}
 
 impl Element for SceneGraphElement {
     fn id(&self) -> &Id {
         &self.id
     }
 
+    fn damage_since(&self, _scale: Scale<f64>, commit: Option<CommitCounter>) -> Vec<Rectangle<i32, Physical>> {
+        // The damage is scaled before it is rotated, so the geometry is not rotated here.
+        let render = RenderProperties {
+            rotation: 0.0,
+            ..self.render
+        };
+        let geometry = render.transform_geometry(self.untransformed_geometry(), self.center);
+
+        compositor::with_states(&self.surface, |states| {
+            let Some(data) = states.data_map.get::<RendererSurfaceStateUserData>() else {
+                return Vec::new();
+            };
+
+            let data = data.borrow();
+            let (buffer_scale, transform) = Self::buffer_transform(states);
+
+            let (Some(view), Some(buffer_size)) = (data.view(), data.buffer_size()) else {
+                return Vec::new();
+            };
+
+            let Some((src, dst)) = self.clipped_view(view) else {
+                return Vec::new();
+            };
+
+            if dst.size.w <= 0 || dst.size.h <= 0 {
+                return Vec::new();
+            }
+
+            // The geometry is scaled by the render properties of the surface tree.
+            let to_geometry = Scale::from((
+                f64::from(geometry.size.w) / f64::from(dst.size.w),
+                f64::from(geometry.size.h) / f64::from(dst.size.h),
+            ));
+
+            data.damage_since(commit)
+                .into_iter()
+                .filter_map(|damage| surface_damage(damage, buffer_scale, transform, buffer_size, src, dst.size))
+                .map(|damage| damage.to_physical(to_geometry).to_i32_up())
+                .map(|damage| self.render.rotate_in(damage, geometry.size))
+                .collect()
+        })
+    }
+
     fn current_commit(&self) -> CommitCounter {
         compositor::with_states(&self.surface, |states| {
             let data = states.data_map.get::<RendererSurfaceStateUserData>();
@@ -360,17 +1132,11 @@ impl Element for SceneGraphElement {
             let data = states.data_map.get::<RendererSurfaceStateUserData>();
             if let Some(data) = data {
                 let data = data.borrow();
+                let (buffer_scale, transform) = Self::buffer_transform(states);
 
-                if let Some(view) = data.view() {
-                    Some(view.src.to_buffer(
-                        // TODO: Do not hardcode these
-                        1.0,
-                        Transform::Normal,
-                        &data.buffer_size().unwrap().to_f64(),
-                    ))
-                } else {
-                    None
-                }
+                let (src, _) = self.clipped_view(data.view()?)?;
+                data.buffer_size()
+                    .map(|buffer_size| buffer_src(src, buffer_scale, transform, buffer_size))
             } else {
                 None
             }
@@ -379,17 +1145,19 @@ impl Element for SceneGraphElement {
     }
 
     fn geometry(&self, _scale: Scale<f64>) -> Rectangle<i32, Physical> {
-        let size = compositor::with_states(&self.surface, |states| {
-            let data = states.data_map.get::<RendererSurfaceStateUserData>();
-            data.and_then(|d| d.borrow().view()).map(|surface_view| {
-                (surface_view.dst.to_f64().to_physical(1.0).to_point())
-                    .to_i32_round()
-                    .to_size()
-            })
-        })
-        .unwrap_or_default();
+        self.render
+            .transform_geometry(self.untransformed_geometry(), self.center)
+    }
+
+    fn opaque_regions(&self, scale: Scale<f64>) -> Vec<Rectangle<i32, Physical>> {
+        // Surfaces below an opaque surface do not need to be drawn. Opaque regions are relative to the geometry.
+        // Rounded corners are transparent, so the surface is not reported as opaque.
+        if self.alpha == AlphaMode::Opaque && self.render.opacity >= 1.0 && self.render.corner_radius <= 0 {
+            let size = self.geometry(scale).size;
+            return vec![Rectangle::from_loc_and_size((0, 0), size)];
+        }
 
-        Rectangle::from_loc_and_size((0, 0), size)
+        Vec::new()
     }
 }
 
@@ -410,8 +1178,19 @@ where
                 let data = data.borrow();
 
                 if let Some(texture) = data.texture::<R>(frame.id()) {
-                    // TODO: data.buffer_transform is private
-                    frame.render_texture_from_to(texture, src, dst, damage, Transform::Normal, 1.0f32)?;
+                    let (_, transform) = Self::buffer_transform(states);
+                    let transform = self.render.texture_transform(transform);
+
+                    // Straight alpha textures are premultiplied by the shader of the alpha::StraightAlphaElement
+                    // wrapping this element.
+                    frame.render_texture_from_to(texture, src, dst, damage, transform, self.render.opacity)?;
+
+                    // Rounded surfaces are darkened by the shader of the rounded::RoundedElement wrapping this
+                    // element, so the masked corners are not filled.
+                    if self.render.dim > 0.0 && self.rounded.is_none() {
+                        let alpha = self.render.dim.min(1.0) * self.render.opacity;
+                        frame.draw_solid(dst, damage, [0.0, 0.0, 0.0, alpha])?;
+                    }
                 } else {
                     dbg!("Not available");
                     // warn!("trying to render texture from different renderer");
@@ -431,16 +1210,243 @@ where
     }
 }
 
+/// A solid color drawn for a shape node.
+pub struct ShapeElement {
+    id: Id,
+    commit: CommitCounter,
+    geometry: Rectangle<i32, Physical>,
+    color: [f32; 4],
+}
+
+impl Element for ShapeElement {
+    fn id(&self) -> &Id {
+        &self.id
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.commit
+    }
+
+    fn src(&self) -> Rectangle<f64, Buffer> {
+        Rectangle::from_loc_and_size((0.0, 0.0), (self.geometry.size.w as f64, self.geometry.size.h as f64))
+    }
+
+    fn geometry(&self, _scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.geometry
+    }
+
+    fn opaque_regions(&self, _scale: Scale<f64>) -> Vec<Rectangle<i32, Physical>> {
+        if self.color[3] >= 1.0 {
+            return vec![Rectangle::from_loc_and_size((0, 0), self.geometry.size)];
+        }
+
+        Vec::new()
+    }
+}
+
+impl<R: Renderer> RenderElement<R> for ShapeElement {
+    fn draw<'a>(
+        &self,
+        frame: &mut R::Frame<'a>,
+        _src: Rectangle<f64, Buffer>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<(), R::Error> {
+        frame.draw_solid(dst, damage, self.color)
+    }
+}
+
+/// A texture drawn for a shape node, stretched to fill the node.
+pub struct TextureElement {
+    id: Id,
+    commit: CommitCounter,
+    geometry: Rectangle<i32, Physical>,
+    buffer_size: Size<i32, Buffer>,
+    textures: ImportedTextures,
+}
+
+impl Element for TextureElement {
+    fn id(&self) -> &Id {
+        &self.id
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        self.commit
+    }
+
+    fn src(&self) -> Rectangle<f64, Buffer> {
+        Rectangle::from_loc_and_size((0.0, 0.0), self.buffer_size.to_f64())
+    }
+
+    fn geometry(&self, _scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        self.geometry
+    }
+}
+
+impl<R: Renderer> RenderElement<R> for TextureElement
+where
+    R::TextureId: 'static,
+{
+    fn draw<'a>(
+        &self,
+        frame: &mut R::Frame<'a>,
+        src: Rectangle<f64, Buffer>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<(), R::Error> {
+        let textures = self.textures.borrow();
+        let Some(texture) = textures
+            .get(&frame.id())
+            .and_then(|texture| texture.downcast_ref::<R::TextureId>())
+        else {
+            return Ok(());
+        };
+
+        frame.render_texture_from_to(texture, src, dst, damage, Transform::Normal, 1.0)
+    }
+}
+
+/// An element of the scene graph.
+pub enum SceneElement {
+    Surface(SceneGraphElement),
+    Shape(ShapeElement),
+    Texture(TextureElement),
+}
+
+impl SceneElement {
+    /// Whether the texture has straight alpha, so the element must be drawn by an [`alpha::StraightAlphaElement`].
+    pub fn needs_premultiply(&self) -> bool {
+        match self {
+            Self::Surface(element) => element.alpha.needs_premultiply(),
+            Self::Shape(_) | Self::Texture(_) => false,
+        }
+    }
+
+    /// The mask of the rounded corners, so the element must be drawn by a [`crate::rounded::RoundedElement`].
+    pub fn corner_mask(&self) -> Option<CornerMask> {
+        match self {
+            Self::Surface(element) => element.corner_mask(),
+            Self::Shape(_) | Self::Texture(_) => None,
+        }
+    }
+}
+
+impl Element for SceneElement {
+    fn id(&self) -> &Id {
+        match self {
+            Self::Surface(element) => element.id(),
+            Self::Shape(element) => element.id(),
+            Self::Texture(element) => element.id(),
+        }
+    }
+
+    fn current_commit(&self) -> CommitCounter {
+        match self {
+            Self::Surface(element) => element.current_commit(),
+            Self::Shape(element) => element.current_commit(),
+            Self::Texture(element) => element.current_commit(),
+        }
+    }
+
+    fn src(&self) -> Rectangle<f64, Buffer> {
+        match self {
+            Self::Surface(element) => element.src(),
+            Self::Shape(element) => element.src(),
+            Self::Texture(element) => element.src(),
+        }
+    }
+
+    fn geometry(&self, scale: Scale<f64>) -> Rectangle<i32, Physical> {
+        match self {
+            Self::Surface(element) => element.geometry(scale),
+            Self::Shape(element) => element.geometry(scale),
+            Self::Texture(element) => element.geometry(scale),
+        }
+    }
+
+    fn opaque_regions(&self, scale: Scale<f64>) -> Vec<Rectangle<i32, Physical>> {
+        match self {
+            Self::Surface(element) => element.opaque_regions(scale),
+            Self::Shape(element) => element.opaque_regions(scale),
+            Self::Texture(element) => element.opaque_regions(scale),
+        }
+    }
+}
+
+impl<R: Renderer + ImportAll + ImportMem> RenderElement<R> for SceneElement
+where
+    R::TextureId: 'static,
+{
+    fn draw<'a>(
+        &self,
+        frame: &mut R::Frame<'a>,
+        src: Rectangle<f64, Buffer>,
+        dst: Rectangle<i32, Physical>,
+        damage: &[Rectangle<i32, Physical>],
+    ) -> Result<(), R::Error> {
+        match self {
+            Self::Surface(element) => RenderElement::<R>::draw(element, frame, src, dst, damage),
+            Self::Shape(element) => RenderElement::<R>::draw(element, frame, src, dst, damage),
+            Self::Texture(element) => RenderElement::<R>::draw(element, frame, src, dst, damage),
+        }
+    }
+
+    fn underlying_storage(&self, renderer: &mut R) -> Option<UnderlyingStorage> {
+        match self {
+            Self::Surface(element) => element.underlying_storage(renderer),
+            Self::Shape(element) => element.underlying_storage(renderer),
+            Self::Texture(element) => element.underlying_storage(renderer),
+        }
+    }
+}
+
 pub struct Hierarchy<'scene> {
     scene: &'scene Scene,
     root: NodeIndex,
 }
 
-impl<R: Renderer + ImportAll> AsRenderElements<R> for Hierarchy<'_>
+impl Hierarchy<'_> {
+    /// The highest surface under the point, with the location of the surface relative to the root of the scene.
+    ///
+    /// Hidden surface trees are skipped, since they are not drawn.
+    pub fn surface_under(&self, point: Point<f64, Physical>) -> Option<(wl_surface::WlSurface, Point<i32, Physical>)> {
+        let indices = self.scene.forest.dfs_descend(self.root.into())?.collect::<Vec<_>>();
+
+        // Later nodes are drawn above earlier nodes.
+        indices.into_iter().rev().find_map(|index| {
+            let SceneNode::Surface(node) = self.scene.forest.get(index)?.deref() else {
+                return None;
+            };
+
+            let render = self.scene.surface_render_properties(index);
+
+            if render.hidden {
+                return None;
+            }
+
+            let view = compositor::with_states(&node.surface, |states| {
+                let data = states.data_map.get::<RendererSurfaceStateUserData>();
+                data.and_then(|d| d.borrow().view())
+            })?;
+
+            let location = self.scene.location(index);
+            let geometry = Rectangle::from_loc_and_size(location + view.offset.to_physical(1), view.dst.to_physical(1));
+            let center = location.to_f64() + self.scene.surface_center(index);
+            let geometry = render.transform_geometry(geometry, center);
+
+            geometry
+                .to_f64()
+                .contains(point)
+                .then(|| (node.surface.clone(), geometry.loc))
+        })
+    }
+}
+
+impl<R: Renderer + ImportAll + ImportMem> AsRenderElements<R> for Hierarchy<'_>
 where
     R::TextureId: 'static,
 {
-    type RenderElement = SceneGraphElement;
+    type RenderElement = SceneElement;
 
     fn render_elements<C: From<Self::RenderElement>>(
         &self,
@@ -460,6 +1466,7 @@ where
                 SceneNode::SurfaceTree(node) => offset += node.offset,
                 SceneNode::Surface(node) => offset += node.offset,
                 SceneNode::Branch(node) => offset += node.offset,
+                SceneNode::Shape(node) => offset += node.offset,
             }
 
             offset
@@ -472,32 +1479,82 @@ where
         indices
             .iter()
             .rev()
-            .filter_map(|&index| {
-                let node = self.scene.forest.get(index)?;
+            .flat_map(|&index| {
+                let Some(node) = self.scene.forest.get(index) else {
+                    return Vec::new();
+                };
 
                 match node.deref() {
                     SceneNode::Output(_) => unreachable!(),
                     SceneNode::SurfaceTree(node) => {
                         offset -= node.offset;
-                        None
+                        Vec::new()
                     }
 
                     SceneNode::Surface(node) => {
+                        let render = self.scene.surface_render_properties(index);
+
+                        if render.hidden {
+                            offset -= node.offset;
+                            return Vec::new();
+                        }
+
                         smithay::backend::renderer::utils::import_surface_tree(renderer, &node.surface)
                             .expect("Failed to import");
 
+                        let alpha = compositor::with_states(&node.surface, |states| {
+                            let data = states.data_map.get::<RendererSurfaceStateUserData>()?.borrow();
+                            let buffer: &WlBuffer = data.buffer()?;
+                            Some(alpha::surface_alpha_mode(states, Some(buffer)))
+                        })
+                        .unwrap_or_default();
+
+                        let clip = render.clip.map(|clip| {
+                            let clip = self.scene.surface_clip(index, clip);
+                            Rectangle::from_loc_and_size((clip.loc.x, clip.loc.y), (clip.size.w, clip.size.h))
+                        });
+
+                        let rounded = match clip {
+                            _ if render.corner_radius <= 0 => None,
+                            Some(clip) => Some(clip),
+                            None => self
+                                .scene
+                                .root_surface_geometry(index)
+                                .map(|geometry| geometry.to_f64().to_logical(1.0).to_i32_round()),
+                        };
+
                         let elem = SceneGraphElement {
                             id: Id::from_wayland_resource(&node.surface),
                             surface: node.surface.clone(),
+                            render,
+                            center: self.scene.surface_center(index),
+                            clip,
+                            rounded,
+                            alpha,
                         };
 
                         offset -= node.offset;
-                        Some(elem)
+                        vec![SceneElement::Surface(elem)]
                     }
 
                     SceneNode::Branch(node) => {
                         offset -= node.offset;
-                        None
+                        Vec::new()
+                    }
+
+                    SceneNode::Shape(node) => {
+                        offset -= node.offset;
+                        let location = self.scene.location(index);
+
+                        match node.shape {
+                            Shape::Texture { .. } => node
+                                .texture_element(renderer, location)
+                                .map(SceneElement::Texture)
+                                .into_iter()
+                                .collect(),
+
+                            _ => node.elements(location).into_iter().map(SceneElement::Shape).collect(),
+                        }
                     }
                 }
             })
@@ -512,6 +1569,7 @@ enum SceneNode {
     SurfaceTree(SurfaceTreeNode),
     Surface(SurfaceNode),
     Branch(BranchNode),
+    Shape(ShapeNode),
 }
 
 impl From<BranchIndex> for Index {
@@ -520,6 +1578,12 @@ impl From<BranchIndex> for Index {
     }
 }
 
+impl From<ShapeIndex> for Index {
+    fn from(value: ShapeIndex) -> Self {
+        value.0
+    }
+}
+
 impl From<SurfaceTreeIndex> for Index {
     fn from(value: SurfaceTreeIndex) -> Self {
         value.0
@@ -537,6 +1601,312 @@ impl From<NodeIndex> for Index {
         match value {
             NodeIndex::SurfaceTree(index) => index.into(),
             NodeIndex::Branch(index) => index.into(),
+            NodeIndex::Shape(index) => index.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::{Buffer, Logical, Physical, Rectangle, Size, Transform};
+
+    use super::{buffer_src, clip_view, surface_damage, NodeIndex, RenderProperties, Scene, Shape};
+
+    #[test]
+    fn restack_branches() {
+        let mut scene = Scene::new();
+        let root = scene.create_branch();
+        let [a, b, c] = [(); 3].map(|_| NodeIndex::Branch(scene.create_branch()));
+
+        for node in [a, b, c] {
+            scene.branch_add_child(root, node).unwrap();
+        }
+
+        let root = NodeIndex::Branch(root);
+        let order = |scene: &Scene| scene.children(root).collect::<Vec<_>>();
+
+        scene.raise_node(a);
+        assert_eq!(order(&scene), [b, a, c]);
+
+        scene.raise_node_to_top(b);
+        assert_eq!(order(&scene), [a, c, b]);
+
+        scene.lower_node(b);
+        assert_eq!(order(&scene), [a, b, c]);
+
+        scene.lower_node_to_bottom(c);
+        assert_eq!(order(&scene), [c, a, b]);
+
+        scene.restack_above(c, a).unwrap();
+        assert_eq!(order(&scene), [a, c, b]);
+
+        // Raising the highest node and lowering the lowest node do nothing.
+        scene.raise_node(b);
+        scene.lower_node_to_bottom(a);
+        assert_eq!(order(&scene), [a, c, b]);
+    }
+
+    #[test]
+    fn wm_tree() {
+        let mut scene = Scene::new();
+        let [workspace, toplevel] = [(); 2].map(|_| NodeIndex::Branch(scene.create_branch()));
+        let background = NodeIndex::Shape(scene.create_shape((1920, 1080).into(), Shape::Solid([0.0, 0.0, 0.0, 1.0])));
+        let border = NodeIndex::Shape(scene.create_shape(
+            (800, 600).into(),
+            Shape::Border {
+                width: 2,
+                color: [1.0, 1.0, 1.0, 1.0],
+            },
+        ));
+
+        scene.add_child(workspace, background).unwrap();
+        scene.add_child(workspace, toplevel).unwrap();
+        scene.add_child(toplevel, border).unwrap();
+        assert_eq!(scene.children(workspace).collect::<Vec<_>>(), [background, toplevel]);
+
+        // A node cannot become a child of its own tree, and the failed attempt keeps the previous parent.
+        assert!(scene.add_child(border, workspace).is_err());
+        assert!(scene.add_child(toplevel, toplevel).is_err());
+
+        // Adding a child again moves the child to the new parent.
+        scene.add_child(workspace, border).unwrap();
+        assert_eq!(
+            scene.children(workspace).collect::<Vec<_>>(),
+            [background, toplevel, border]
+        );
+        assert_eq!(scene.children(toplevel).count(), 0);
+
+        scene.detach_node(background);
+        assert_eq!(scene.children(workspace).collect::<Vec<_>>(), [toplevel, border]);
+    }
+
+    #[test]
+    fn border_elements() {
+        let mut scene = Scene::new();
+        let root = scene.create_branch();
+        let color = [1.0, 0.0, 0.0, 1.0];
+        let border = scene.create_shape((100, 50).into(), Shape::Border { width: 2, color });
+
+        scene.branch_add_child(root, NodeIndex::Shape(border)).unwrap();
+        scene.set_node_offset(NodeIndex::Branch(root), (10, 20).into());
+        scene.set_node_offset(NodeIndex::Shape(border), (5, 5).into());
+
+        let location = scene.location(border.into());
+        assert_eq!(location, (15, 25).into());
+
+        let geometry = |scene: &mut Scene| {
+            let node = scene.get_shape(border).unwrap();
+            node.elements(location)
+                .into_iter()
+                .map(|element| element.geometry)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            geometry(&mut scene),
+            [
+                Rectangle::from_loc_and_size((15, 25), (100, 2)),
+                Rectangle::from_loc_and_size((15, 73), (100, 2)),
+                Rectangle::from_loc_and_size((15, 27), (2, 46)),
+                Rectangle::from_loc_and_size((113, 27), (2, 46)),
+            ]
+        );
+
+        // A border wider than the shape fills the shape without overlapping.
+        scene.set_shape_size(border, (4, 4).into());
+        assert_eq!(
+            geometry(&mut scene),
+            [
+                Rectangle::from_loc_and_size((15, 25), (4, 2)),
+                Rectangle::from_loc_and_size((15, 27), (4, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_properties_scale_around_center() {
+        let geometry = Rectangle::<i32, Physical>::from_loc_and_size((0, 0), (100, 50));
+        let center = (50.0, 25.0).into();
+
+        assert_eq!(
+            RenderProperties::default().transform_geometry(geometry, center),
+            geometry
+        );
+
+        let overview = RenderProperties {
+            scale: 0.5,
+            translation: (10, -10).into(),
+            ..RenderProperties::default()
+        };
+        assert_eq!(
+            overview.transform_geometry(geometry, center),
+            Rectangle::from_loc_and_size((35, 3), (50, 25))
+        );
+    }
+
+    #[test]
+    fn render_properties_rotate_around_center() {
+        // A subsurface on the right of a toplevel, which is rotated around the center of the toplevel.
+        let toplevel = Rectangle::<i32, Physical>::from_loc_and_size((0, 0), (100, 50));
+        let subsurface = Rectangle::<i32, Physical>::from_loc_and_size((100, 0), (20, 50));
+        let center = (50.0, 25.0).into();
+
+        let rotated = |rotation| RenderProperties {
+            rotation,
+            ..RenderProperties::default()
+        };
+
+        assert_eq!(
+            rotated(90.0).transform_geometry(toplevel, center),
+            Rectangle::from_loc_and_size((25, -25), (50, 100))
+        );
+        assert_eq!(
+            rotated(90.0).transform_geometry(subsurface, center),
+            Rectangle::from_loc_and_size((25, 75), (50, 20))
+        );
+        assert_eq!(
+            rotated(180.0).transform_geometry(subsurface, center),
+            Rectangle::from_loc_and_size((-20, 0), (20, 50))
+        );
+
+        // The rotation is rounded to quarter turns, and a full turn is not rotated.
+        assert_eq!(rotated(80.0).quarter_turns(), 1);
+        assert_eq!(rotated(-90.0).quarter_turns(), 3);
+        assert_eq!(rotated(360.0).quarter_turns(), 0);
+        assert_eq!(rotated(f64::NAN).quarter_turns(), 0);
+
+        // Damage along the top of the surface is along the right side once rotated clockwise.
+        let damage = Rectangle::from_loc_and_size((0, 0), (100, 10));
+        assert_eq!(
+            rotated(90.0).rotate_in(damage, (100, 50).into()),
+            Rectangle::from_loc_and_size((40, 0), (10, 100))
+        );
+    }
+
+    #[test]
+    fn rotation_texture_transform() {
+        let rotated = |rotation| RenderProperties {
+            rotation,
+            ..RenderProperties::default()
+        };
+
+        assert_eq!(rotated(0.0).texture_transform(Transform::_90), Transform::_90);
+        assert_eq!(rotated(90.0).texture_transform(Transform::Normal), Transform::_90);
+        assert_eq!(rotated(270.0).texture_transform(Transform::_180), Transform::_90);
+        assert_eq!(
+            rotated(90.0).texture_transform(Transform::Flipped),
+            Transform::Flipped270
+        );
+    }
+
+    #[test]
+    fn clip_crops_source() {
+        // A scale 2 viewport, where each surface pixel samples two source pixels.
+        let src = Rectangle::<f64, Logical>::from_loc_and_size((0.0, 0.0), (200.0, 100.0));
+        let dst = Rectangle::<i32, Logical>::from_loc_and_size((10, 10), (100, 50));
+
+        // Revealing the left half of the surface samples the left half of the source.
+        let clip = Rectangle::from_loc_and_size((0, 0), (60, 100));
+        assert_eq!(
+            clip_view(src, dst, clip),
+            Some((
+                Rectangle::from_loc_and_size((0.0, 0.0), (100.0, 100.0)),
+                Rectangle::from_loc_and_size((10, 10), (50, 50)),
+            ))
+        );
+
+        let clip = Rectangle::from_loc_and_size((30, 20), (20, 10));
+        assert_eq!(
+            clip_view(src, dst, clip),
+            Some((
+                Rectangle::from_loc_and_size((40.0, 20.0), (40.0, 20.0)),
+                Rectangle::from_loc_and_size((30, 20), (20, 10)),
+            ))
+        );
+
+        // A clip outside of the surface draws nothing.
+        assert_eq!(
+            clip_view(src, dst, Rectangle::from_loc_and_size((200, 0), (10, 10))),
+            None
+        );
+    }
+
+    #[test]
+    fn fractional_viewport_source() {
+        let src = Rectangle::<f64, Logical>::from_loc_and_size((10.25, 20.5), (100.5, 50.75));
+        let buffer_size = (400, 300).into();
+
+        assert_eq!(
+            buffer_src(src, 1, Transform::Normal, buffer_size),
+            Rectangle::from_loc_and_size((10.25, 20.5), (100.5, 50.75))
+        );
+
+        // A scale 2 buffer has two texels per surface pixel.
+        assert_eq!(
+            buffer_src(src, 2, Transform::Normal, buffer_size),
+            Rectangle::from_loc_and_size((20.5, 41.0), (201.0, 101.5))
+        );
+
+        // An upside down buffer samples from the opposite corner.
+        assert_eq!(
+            buffer_src(src, 2, Transform::_180, buffer_size),
+            Rectangle::from_loc_and_size((578.5, 457.5), (201.0, 101.5))
+        );
+    }
+
+    #[test]
+    fn transformed_damage() {
+        let buffer_size: Size<i32, Logical> = (400, 300).into();
+        let full = Rectangle::<f64, Logical>::from_loc_and_size((0.0, 0.0), (400.0, 300.0));
+        let damage = Rectangle::<f64, Logical>::from_loc_and_size((10.0, 20.0), (30.0, 40.0));
+
+        // Damage is transformed back to where the buffer is drawn, whichever way the client rotated the buffer.
+        for transform in [
+            Transform::Normal,
+            Transform::_90,
+            Transform::_180,
+            Transform::_270,
+            Transform::Flipped,
+            Transform::Flipped90,
+            Transform::Flipped180,
+            Transform::Flipped270,
+        ] {
+            for scale in [1, 2] {
+                let buffer_damage = buffer_src(damage, scale, transform, buffer_size).to_i32_round();
+                assert_eq!(
+                    surface_damage(buffer_damage, scale, transform, buffer_size, full, buffer_size),
+                    Some(damage),
+                    "{transform:?} at scale {scale}"
+                );
+            }
         }
+
+        // Transposing a buffer swaps the axes of the damage.
+        let transposed = Rectangle::<i32, Buffer>::from_loc_and_size((20, 10), (40, 30));
+        assert_eq!(
+            surface_damage(transposed, 1, Transform::Flipped90, buffer_size, full, buffer_size),
+            Some(damage)
+        );
+    }
+
+    #[test]
+    fn viewport_damage() {
+        let buffer_size = (400, 300).into();
+        let src = Rectangle::<f64, Logical>::from_loc_and_size((100.0, 50.0), (100.0, 50.0));
+        let dst = (200, 100).into();
+
+        // The source is scaled up to twice its size.
+        let damage = Rectangle::<i32, Buffer>::from_loc_and_size((100, 50), (10, 10));
+        assert_eq!(
+            surface_damage(damage, 1, Transform::Normal, buffer_size, src, dst),
+            Some(Rectangle::from_loc_and_size((0.0, 0.0), (20.0, 20.0)))
+        );
+
+        // Damage outside of the source is not visible.
+        let outside = Rectangle::<i32, Buffer>::from_loc_and_size((0, 0), (10, 10));
+        assert_eq!(
+            surface_damage(outside, 1, Transform::Normal, buffer_size, src, dst),
+            None
+        );
     }
 }
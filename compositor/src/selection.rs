@@ -0,0 +1,129 @@
+//! Clipboard and primary selection
+//!
+//! The wm is told whenever the clipboard or primary selection changes, including the mime types offered and the
+//! client which owns the selection. The wm may also set the selection itself, in which case the display server
+//! owns the selection and sends the contents provided by the wm to clients which paste. This allows a wm (or a
+//! plugin) to implement a clipboard manager which keeps the clipboard after the owning client exits, or to clear
+//! the selections when the session is locked.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::fd::OwnedFd,
+    sync::Arc,
+    thread,
+};
+
+/// Which selection is changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SelectionKind {
+    /// The clipboard, set by copying.
+    Clipboard,
+
+    /// The primary selection, set by selecting text and pasted with the middle button.
+    Primary,
+}
+
+/// The selection changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectionChange {
+    pub kind: SelectionKind,
+
+    /// The mime types offered, or empty if the selection was cleared.
+    pub mime_types: Vec<String>,
+
+    /// The pid of the client which owns the selection, or [`None`] if the display server owns the selection.
+    pub owner: Option<i32>,
+}
+
+/// The contents of a selection owned by the display server.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelectionContents(Arc<[(String, Vec<u8>)]>);
+
+impl SelectionContents {
+    /// Create the contents from the data of each mime type.
+    ///
+    /// If a mime type is given more than once, the first data is used.
+    pub fn new(data: Vec<(String, Vec<u8>)>) -> Self {
+        let mut contents: Vec<(String, Vec<u8>)> = Vec::with_capacity(data.len());
+
+        for (mime_type, data) in data {
+            if !contents.iter().any(|(existing, _)| *existing == mime_type) {
+                contents.push((mime_type, data));
+            }
+        }
+
+        Self(contents.into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn mime_types(&self) -> Vec<String> {
+        self.0.iter().map(|(mime_type, _)| mime_type.clone()).collect()
+    }
+
+    pub fn get(&self, mime_type: &str) -> Option<&[u8]> {
+        self.0
+            .iter()
+            .find(|(offered, _)| offered == mime_type)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// Send the data of the mime type to a client which is pasting.
+    ///
+    /// The data is written on another thread, since the client may be slow to read a large selection.
+    pub fn send(&self, mime_type: &str, fd: OwnedFd) {
+        if self.get(mime_type).is_none() {
+            tracing::debug!(mime_type, "Requested mime type is not offered by the selection");
+            return;
+        }
+
+        let contents = self.clone();
+        let mime_type = mime_type.to_owned();
+
+        let spawned = thread::Builder::new().name("Selection".into()).spawn(move || {
+            let data = contents.get(&mime_type).unwrap_or_default();
+
+            if let Err(err) = write_all(File::from(fd), data) {
+                tracing::debug!(%err, mime_type, "Failed to send selection");
+            }
+        });
+
+        if let Err(err) = spawned {
+            tracing::warn!(%err, "Failed to send selection");
+        }
+    }
+}
+
+fn write_all(mut file: File, data: &[u8]) -> io::Result<()> {
+    file.write_all(data)?;
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SelectionContents;
+
+    #[test]
+    fn first_data_of_mime_type_is_used() {
+        let contents = SelectionContents::new(vec![
+            ("text/plain".into(), b"first".to_vec()),
+            ("text/html".into(), b"<b>first</b>".to_vec()),
+            ("text/plain".into(), b"second".to_vec()),
+        ]);
+
+        assert_eq!(contents.mime_types(), ["text/plain", "text/html"]);
+        assert_eq!(contents.get("text/plain"), Some(b"first".as_slice()));
+        assert_eq!(contents.get("image/png"), None);
+    }
+
+    #[test]
+    fn empty_contents() {
+        let contents = SelectionContents::new(Vec::new());
+
+        assert!(contents.is_empty());
+        assert!(contents.mime_types().is_empty());
+    }
+}
@@ -66,14 +66,17 @@ If the clients fail to commit the previous transaction states, should the WM's n
 client state, and cancel the previous transaction?
 */
 
-use std::{fmt, num::NonZeroU64, sync::Arc};
+use std::{fmt, num::NonZeroU64, sync::Arc, time::Instant};
 
 use rustc_hash::FxHashMap;
 use smithay::{
     backend::renderer::utils::with_renderer_surface_state,
-    utils::{Logical, Serial, Size},
+    reexports::wayland_protocols::xdg::{
+        decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode as DecorationMode, shell::server::xdg_toplevel,
+    },
+    utils::{Logical, Point, Rectangle, Serial, Size},
     wayland::{
-        compositor::{self, SurfaceAttributes, TraversalAction},
+        compositor::{self, SubsurfaceCachedState, SurfaceAttributes, TraversalAction},
         shell::{
             wlr_layer,
             xdg::{ToplevelSurface, XdgToplevelSurfaceData},
@@ -270,6 +273,126 @@ impl Toplevel {
         }
     }
 
+    /// The xdg toplevel, or [`None`] for xwayland surfaces.
+    /// Whether the client committed a buffer for the toplevel.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.current, State::Mapped(_))
+    }
+
+    pub fn xdg_toplevel(&self) -> Option<&ToplevelSurface> {
+        match &self.surface {
+            Surface::Toplevel(toplevel) => Some(toplevel),
+            Surface::XWayland(_) => None,
+        }
+    }
+
+    /// The toplevel the toplevel is a child of, such as the main window of a dialog.
+    pub fn parent(&self) -> Option<ToplevelId> {
+        match &self.surface {
+            Surface::Toplevel(toplevel) => toplevel.parent().as_ref().and_then(Shell::get_toplevel_id),
+            Surface::XWayland(_) => None,
+        }
+    }
+
+    /// Set whether the toplevel is activated, sending a configure if the state changed.
+    ///
+    /// Returns whether a configure which the client must ack was sent.
+    pub fn set_activated(&self, activated: bool) -> bool {
+        match &self.surface {
+            Surface::Toplevel(toplevel) => {
+                let changed = toplevel.with_pending_state(|state| match activated {
+                    true => state.states.set(xdg_toplevel::State::Activated),
+                    false => state.states.unset(xdg_toplevel::State::Activated),
+                });
+
+                if changed {
+                    toplevel.send_configure();
+                }
+
+                changed
+            }
+
+            Surface::XWayland(surface) => {
+                if let Err(err) = surface.set_activated(activated) {
+                    tracing::warn!(id = self.id, %err, "Failed to activate xwayland surface");
+                }
+
+                false
+            }
+        }
+    }
+
+    /// Set the size and position of the toplevel, such as for the fallback layout.
+    ///
+    /// The position of xdg toplevels is only known to the scene. Returns whether a configure which the client must
+    /// ack was sent.
+    pub fn set_geometry(&self, geometry: Rectangle<i32, Logical>) -> bool {
+        match &self.surface {
+            Surface::Toplevel(toplevel) => {
+                toplevel.with_pending_state(|state| state.size = Some(geometry.size));
+                toplevel.send_configure();
+                true
+            }
+
+            Surface::XWayland(surface) => {
+                if let Err(err) = surface.configure(geometry) {
+                    tracing::warn!(id = self.id, %err, "Failed to configure xwayland surface");
+                }
+
+                false
+            }
+        }
+    }
+
+    /// Set the size of the toplevel, keeping the position of Xwayland surfaces.
+    ///
+    /// Returns whether a configure which the client must ack was sent.
+    pub fn resize(&self, size: Size<i32, Logical>) -> bool {
+        match &self.surface {
+            Surface::Toplevel(_) => self.set_geometry(Rectangle::from_loc_and_size((0, 0), size)),
+            Surface::XWayland(surface) => {
+                let position = surface.geometry().loc;
+                self.set_geometry(Rectangle::from_loc_and_size(position, size))
+            }
+        }
+    }
+
+    /// Set whether the client or the display server decorates the toplevel.
+    ///
+    /// Clients which did not create a decoration object always decorate their toplevels, so the mode is only sent
+    /// to clients which support xdg-decoration. The mode is sent with the next configure.
+    pub fn set_decoration_mode(&self, mode: DecorationMode) {
+        match &self.surface {
+            Surface::Toplevel(toplevel) => toplevel.with_pending_state(|state| state.decoration_mode = Some(mode)),
+
+            // X11 windows draw their own decorations.
+            Surface::XWayland(_) => (),
+        }
+    }
+
+    /// Ask the client to close the toplevel.
+    pub fn send_close(&self) {
+        match &self.surface {
+            Surface::Toplevel(toplevel) => toplevel.send_close(),
+
+            Surface::XWayland(surface) => {
+                if let Err(err) = surface.close() {
+                    tracing::warn!(id = self.id, %err, "Failed to close xwayland surface");
+                }
+            }
+        }
+    }
+
+    /// The client which owns the toplevel.
+    ///
+    /// Xwayland surfaces are owned by Xwayland rather than the X11 client, so [`None`] is returned.
+    pub fn client(&self) -> Option<Client> {
+        match &self.surface {
+            Surface::Toplevel(toplevel) => toplevel.wl_surface().client(),
+            Surface::XWayland(_) => None,
+        }
+    }
+
     pub fn update_state(&mut self) {
         todo!()
     }
@@ -329,20 +452,24 @@ impl Shell {
     pub fn commit(comp: &mut Aerugo, surface: &WlSurface) {
         // Handle commit for each type of role.
         Shell::toplevel_commit(comp, surface);
+        comp.popup_committed(surface);
+        comp.layer_surface_committed(surface);
     }
 
     pub fn toplevel_commit(comp: &mut Aerugo, surface: &WlSurface) {
-        let Some(id) = Shell::get_toplevel_id(surface) else {
-            // If the surface is pending, then an initial commit has happened.
-            if let Some(toplevel_index) = comp
-                .shell
-                .pending_toplevels
-                .iter()
-                .position(|toplevel| toplevel.wl_surface() == surface)
-            {
-                let toplevel = comp.shell.pending_toplevels.remove(toplevel_index);
-            }
+        // If the surface is pending, then an initial commit has happened.
+        if let Some(toplevel_index) = comp
+            .shell
+            .pending_toplevels
+            .iter()
+            .position(|toplevel| toplevel.wl_surface() == surface)
+        {
+            let toplevel = comp.shell.pending_toplevels.remove(toplevel_index);
+            Shell::initial_commit(comp, toplevel);
+            return;
+        }
 
+        let Some(id) = Shell::get_toplevel_id(surface) else {
             return;
         };
 
@@ -373,16 +500,83 @@ impl Shell {
                     Surface::XWayland(_) => todo!("How to handle xwayland?"),
                 }
 
+                comp.scene.destroy_surface_tree(surface);
+                comp.toplevel_closed(id);
                 return;
             }
         }
 
+        // The first buffer replaces the placeholder.
+        if has_buffer
+            && matches!(toplevel.current, State::NotYetMapped)
+            && comp.placeholders.mapped(id, Instant::now()).is_some()
+        {
+            comp.damaged = true;
+        }
+
         // Make sure initial configure was acked.
         if has_buffer && !toplevel.surface.ensure_configured() {
             let id = toplevel.id;
             let app_id = toplevel.app_id().unwrap_or_default();
             tracing::warn!(%id, %app_id, "Killing client: toplevel not configured");
         }
+
+        // Present the transaction the toplevel was waited for.
+        if has_buffer && comp.transactions.committed(id) {
+            comp.scene_changed();
+        }
+
+        comp.toplevel_committed(id);
+    }
+
+    /// Map the toplevel into the shell after the initial commit, so the toplevel can be configured.
+    fn initial_commit(comp: &mut Aerugo, toplevel: ToplevelSurface) {
+        let surface = toplevel.wl_surface().clone();
+
+        // A toplevel which is mapped again after being unmapped keeps its id.
+        let id = Shell::get_toplevel_id(&surface).unwrap_or_else(|| {
+            let id = comp.shell.next_toplevel_id;
+            comp.shell.next_toplevel_id = id.checked_add(1).expect("u64 overflow (unlikely)");
+
+            compositor::with_states(&surface, |states| {
+                states
+                    .data_map
+                    .insert_if_missing(|| AerugoToplevelData { toplevel_id: id })
+            });
+
+            id
+        });
+
+        let toplevel = comp.shell.toplevels.entry(id).or_insert(Toplevel {
+            id,
+            surface: Surface::Toplevel(toplevel),
+            current: State::default(),
+            pending: None,
+            handles: FxHashMap::default(),
+        });
+
+        tracing::debug!(id, app_id = toplevel.app_id(), "Initial commit of toplevel");
+
+        // Create all toplevel handle instances before describing the toplevel, so extension protocols do not refer
+        // to handles that were not yet created.
+        let mut new_instances = Vec::with_capacity(comp.shell.foreign_toplevel_instances.len());
+
+        for instance in comp.shell.foreign_toplevel_instances.values() {
+            if instance.stopped {
+                continue;
+            }
+
+            if let Some(client) = instance.instance.client() {
+                new_instances.push(toplevel.create_handle(comp.generation, &instance.instance, &comp.display, &client));
+            }
+        }
+
+        for new in new_instances {
+            toplevel.initialize_handle(&new);
+        }
+
+        comp.scene.create_surface_tree(surface);
+        comp.toplevel_created(id);
     }
 
     // pub fn commit(comp: &mut Aerugo, surface: &WlSurface) {
@@ -579,6 +773,25 @@ impl Shell {
             let toplevel = comp.shell.toplevels.remove(&id).unwrap();
             let app_id = toplevel.app_id();
             tracing::debug!(id, app_id, "Removed toplevel");
+
+            comp.scene.destroy_surface_tree(surface);
+            comp.toplevel_closed(id);
+
+            // The keyboard leaves the surface when it is destroyed.
+            if comp.keyboard_focus == Some(id) {
+                comp.keyboard_focus = None;
+            }
+
+            comp.set_server_decorations(id, None);
+            comp.leave_fullscreen_output(id);
+            comp.thumbnails.remove(id);
+            comp.placeholders.remove(id);
+            comp.configure_watchdog.remove(&id);
+            comp.toplevel_group_member_closed(id);
+
+            if comp.transactions.remove(id) {
+                comp.scene_changed();
+            }
         }
     }
 
@@ -591,6 +804,73 @@ impl Shell {
     }
 }
 
+/// A subsurface of a toplevel, sent to the wm with the contents of the toplevel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurfaceNode {
+    pub surface: WlSurface,
+
+    /// The position of the subsurface relative to the toplevel surface.
+    pub offset: Point<i32, Logical>,
+
+    /// The index of the parent subsurface, or [`None`] if the parent is the toplevel surface.
+    pub parent: Option<usize>,
+
+    /// Whether the subsurface is drawn below the toplevel surface.
+    pub below: bool,
+}
+
+/// Flatten the subsurfaces of a surface in the order they are drawn, from bottom to top.
+///
+/// Wms which place toplevels in their own scene would otherwise only see the toplevel surface and lose content
+/// such as video players and GL widgets drawn into subsurfaces.
+pub fn surface_nodes(surface: &WlSurface) -> Vec<SurfaceNode> {
+    let mut nodes = Vec::new();
+    let mut below = true;
+
+    compositor::with_surface_tree_upward(
+        surface,
+        Point::from((0, 0)),
+        |_, states, &offset| {
+            let mut offset = offset;
+
+            if states.role == Some("subsurface") {
+                offset += states.cached_state.current::<SubsurfaceCachedState>().location;
+            }
+
+            TraversalAction::DoChildren(offset)
+        },
+        |child, _, &offset| {
+            if child == surface {
+                below = false;
+                return;
+            }
+
+            nodes.push(SurfaceNode {
+                surface: child.clone(),
+                offset,
+                parent: None,
+                below,
+            });
+        },
+        |_, _, _| true,
+    );
+
+    // A subsurface below its parent is drawn before the parent, so parents are found once every node is known.
+    let parents = nodes
+        .iter()
+        .map(|node| {
+            let parent = compositor::get_parent(&node.surface)?;
+            nodes.iter().position(|node| node.surface == parent)
+        })
+        .collect::<Vec<_>>();
+
+    for (node, parent) in nodes.iter_mut().zip(parents) {
+        node.parent = parent;
+    }
+
+    nodes
+}
+
 pub fn send_frames_surface_tree(surface: &WlSurface, time: u32) {
     compositor::with_surface_tree_downward(
         surface,
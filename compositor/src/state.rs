@@ -1,34 +1,102 @@
 use std::{
     fmt,
-    time::{Duration, SystemTime},
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant, SystemTime},
 };
 
 use bitflags::bitflags;
 use calloop::LoopHandle;
+use rustc_hash::FxHashMap;
 use smithay::{
-    input::SeatState,
-    output::{Output, PhysicalProperties},
+    input::{keyboard::XkbConfig, pointer::CursorImageStatus, Seat, SeatState},
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::wayland_protocols::ext::session_lock::v1::server::ext_session_lock_manager_v1::ExtSessionLockManagerV1,
+    reexports::wayland_protocols::wp::text_input::zv3::server::zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+    reexports::wayland_protocols::xdg::activation::v1::server::xdg_activation_v1::XdgActivationV1,
+    reexports::wayland_protocols_wlr::input_inhibitor::v1::server::zwlr_input_inhibit_manager_v1::ZwlrInputInhibitManagerV1,
+    utils::{Logical, Point, Size},
     wayland::{
         compositor::{CompositorClientState, CompositorState},
-        shell::xdg::XdgShellState,
+        content_type::ContentTypeState,
+        data_device::{self, DataDeviceState},
+        pointer_gestures::PointerGesturesState,
+        primary_selection::{self, PrimarySelectionState},
+        security_context::SecurityContextState,
+        shell::{
+            wlr_layer::WlrLayerShellState,
+            xdg::{decoration::XdgDecorationState, ShellClient, XdgShellState},
+        },
+        viewporter::ViewporterState,
     },
 };
 use wayland_server::{
     backend::{ClientId, DisconnectReason},
-    Client, DisplayHandle,
+    Client, DisplayHandle, Resource,
 };
+use wm_runtime::WmEvent;
 
 use crate::{
-    backend::Backend,
-    scene::Scene,
-    shell::Shell,
-    wayland::{ext::foreign_toplevel::ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, versions},
+    activation::ActivationTokens,
+    audio::{AudioMonitor, AudioStreams},
+    backend::{
+        color::{self, ColorTransform, KmsColor},
+        modeset::{self, Fallback, ModesetOutcome},
+        modifiers::ModifierPolicy,
+        render_scale::{self, RenderScale},
+        Backend,
+    },
+    config::OutputSettings,
+    cursor::{CursorHideConfig, CursorOverride, CursorTheme, CursorVisibility},
+    decorations::{DecorationNodes, ServerDecorations},
+    dnd::Drag,
+    fallback::WmCrash,
+    flush::FlushScheduler,
+    fullscreen::FullscreenOutput,
+    gesture::GestureRouter,
+    group::ToplevelGroups,
+    idle::IdleState,
+    input::{ExclusiveGrab, FilterQueue, SessionLock},
+    interactive::InteractiveGrab,
+    ipc,
+    keyboard::{HeldKey, KeyRepeat, KeyboardState, Leds},
+    layer_shell::LayerShell,
+    metrics::{ClientCounter, ClientGuard, Metrics, Samples},
+    output_config::{self, OutputConfig},
+    ping::{ConfigureWatchdog, PingConfig, PingWatchdog},
+    placeholder::{PlaceholderConfig, Placeholders},
+    pointer::PointerEvent,
+    policy::ClientInfo,
+    popup::Popups,
+    protocol_log::{self, Direction, Message, ProtocolLog},
+    report::RecentLogs,
+    rules::{RuleActions, Rules},
+    scene::{NodeIndex, RenderProperties, Scene},
+    selection::{SelectionChange, SelectionContents, SelectionKind},
+    shell::{Shell, ToplevelId},
+    switcher::SwitcherOverlay,
+    thumbnail::{ThumbnailCache, ThumbnailConfig},
+    touch::TouchPoints,
+    transaction::Transactions,
+    wayland::{
+        ext::foreign_toplevel::ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        versions,
+        wp::{
+            color_representation::wp_color_representation_manager_v1::WpColorRepresentationManagerV1,
+            text_input::TextInputs,
+        },
+    },
+    wm::{self, Wm},
+    wm_scene::WmNodes,
+    workspace::Workspaces,
+    zoom::Zoom,
     Loop,
 };
 
 #[derive(Debug)]
 pub struct Aerugo {
     pub display: DisplayHandle,
+    /// Inserts event sources which are created by protocol requests, such as the sockets of security contexts.
+    pub loop_handle: LoopHandle<'static, Loop>,
     pub shell: Shell,
     pub scene: Scene,
     // This is not what I want in the future, but is for testing.
@@ -36,23 +104,197 @@ pub struct Aerugo {
     pub backend: Box<dyn Backend>,
     pub wl_compositor: CompositorState,
     pub xdg_shell: XdgShellState,
+    pub xdg_decoration: XdgDecorationState,
+    pub wlr_layer_shell: WlrLayerShellState,
     pub seat_state: SeatState<Self>,
+    pub seat: Seat<Self>,
+    pub data_device_state: DataDeviceState,
+    pub primary_selection_state: PrimarySelectionState,
     pub generation: u64,
+    /// The number of times the event loop has woken up, shared with [`crate::AerugoExecutor::wakeup_count`].
+    pub wakeups: Arc<AtomicU64>,
+    /// Whether the scene has been damaged since the last frame was drawn.
+    ///
+    /// If the scene is not damaged, backends should not draw a new frame so the compositor can go idle.
+    pub damaged: bool,
+    /// The client which is currently grabbing all input, such as a legacy screen locker.
+    pub exclusive_grab: Option<ExclusiveGrab>,
+    /// The session lock of a screen locker, which takes precedence over the exclusive grab.
+    pub session_lock: Option<SessionLock>,
+    pub workspaces: Workspaces,
+    /// Toplevels grouped by the wm, such as into tabs, of which only one member is visible.
+    pub toplevel_groups: ToplevelGroups,
+    /// The toplevel with keyboard focus.
+    ///
+    /// Only the wm changes the keyboard focus.
+    pub keyboard_focus: Option<ToplevelId>,
+    /// The location of the pointer in the global compositor space.
+    pub pointer_location: Point<f64, Logical>,
+    /// Pointer events waiting for the wm to decide whether they are sent to clients.
+    pub pointer_events: FilterQueue<PointerEvent>,
+    /// The pointer buttons which are held.
+    pub pointer_buttons: Vec<u32>,
+    /// The interactive move or resize of a toplevel using the pointer.
+    pub interactive_grab: Option<InteractiveGrab>,
+    pub touch_points: TouchPoints,
+    pub popups: Popups,
+    pub layer_shell: LayerShell,
+    /// Whether the wm requested a frame to be drawn, such as to advance an animation.
+    pub frame_requested: bool,
+    pub protocol_log: ProtocolLog,
+    /// Events for the IPC connections which subscribed to events, sent once the event loop is idle.
+    pub ipc_events: Vec<ipc::Event>,
+    /// Window rules from the configuration.
+    pub rules: Rules,
+    pub cursor_theme: CursorTheme,
+    pub cursor_visibility: CursorVisibility,
+    /// The cursor set by the wm instead of the cursor set by clients.
+    pub cursor_override: Option<CursorOverride>,
+    /// The cursor set by the client with pointer focus, or [`None`] if no client set a cursor.
+    pub cursor_status: Option<CursorImageStatus>,
+    /// The LEDs shown on every keyboard.
+    pub keyboard_leds: Leds,
+    /// The index of the active layout of the keymap.
+    pub keyboard_layout: u32,
+    /// The xkb state of the seat's keyboard, updated before the wm decides whether a key is sent to clients.
+    pub keyboard_state: Option<KeyboardState>,
+    /// Keys waiting for the wm to decide whether they are sent to clients.
+    pub key_events: FilterQueue<HeldKey>,
+    /// Whether the lid is closed.
+    pub lid_closed: bool,
+    /// Whether a convertible laptop is folded into a tablet.
+    pub tablet_mode: bool,
+    /// Which modifiers are offered when buffers are allocated.
+    pub modifier_policy: ModifierPolicy,
+    /// Server side decorations set by the wm.
+    pub server_decorations: FxHashMap<ToplevelId, ServerDecorations>,
+    /// The scene nodes drawing the server side decorations of each toplevel.
+    pub decoration_nodes: FxHashMap<ToplevelId, DecorationNodes>,
+    /// The drag in progress.
+    pub drag: Option<Drag>,
+    /// The night light, brightness and color filter of the output set by the wm.
+    pub color_transform: ColorTransform,
+    /// The color transform applied to the output, which is dimmed by the idle fade.
+    pub applied_color_transform: ColorTransform,
+    /// Whether the color transform is applied by the display hardware. Otherwise the backend applies it with
+    /// [`COLOR_TRANSFORM_SHADER`](crate::backend::color::COLOR_TRANSFORM_SHADER).
+    pub color_offloaded: bool,
+    /// The idle stages, if the outputs are dimmed and powered off when there is no input.
+    pub idle: Option<IdleState>,
+    /// The brightness of the idle fade, between 0.0 (black) and 1.0.
+    pub idle_brightness: f64,
+    /// The magnified part of the output.
+    pub zoom: Zoom,
+    /// The text inputs of clients.
+    pub text_inputs: TextInputs,
+    /// The outputs toplevels are fullscreen on with the mode to restore.
+    pub fullscreen_outputs: FxHashMap<ToplevelId, FullscreenOutput>,
+    /// Detects clients which stopped answering pings.
+    pub ping_watchdog: PingWatchdog<ShellClient>,
+    /// Detects toplevels which stopped acking configures.
+    pub configure_watchdog: ConfigureWatchdog<ToplevelId>,
+    /// Transactions of configures which are presented together.
+    pub transactions: Transactions,
+    /// Thumbnails of toplevels shared by the wm, foreign toplevel clients and the window switcher.
+    pub thumbnails: ThumbnailCache,
+    /// The window switcher drawn while it is open.
+    pub switcher: Option<SwitcherOverlay>,
+    /// Toplevels waiting for their first buffer, which may be drawn as placeholders.
+    pub placeholders: Placeholders,
+    /// The crash of the wm while the fallback layout is used.
+    pub wm_crash: Option<WmCrash>,
+    /// The scene nodes created by the wm.
+    pub wm_nodes: WmNodes,
+    /// The number of connected clients.
+    pub clients: ClientCounter,
+    /// Metrics for monitoring, if a metrics endpoint is configured.
+    pub metrics: Option<Metrics>,
+    /// The time taken to draw recent frames, for debug reports.
+    pub frame_stats: Samples,
+    /// The recent lines of the log, for debug reports.
+    pub recent_logs: RecentLogs,
+    /// Tokens clients and the wm may activate toplevels with.
+    pub activation_tokens: ActivationTokens,
+    /// Whether the display server should exit, such as to be replaced by another instance.
+    pub shutdown_requested: bool,
+    /// Whether the display server should restart in place, such as after an upgrade.
+    pub restart_requested: bool,
+    /// Decides when events are flushed to clients.
+    pub flush: FlushScheduler,
+    /// Routes touchpad gestures to the wm or clients.
+    pub gestures: GestureRouter,
+    /// Associates audio streams with toplevels.
+    pub audio_streams: AudioStreams,
+    /// Watches the audio streams of PipeWire, or [`None`] if the monitor could not be started.
+    pub audio_monitor: Option<AudioMonitor>,
+    /// The wm runtime and the ids of the objects the wm knows.
+    pub wm: Wm,
 }
 
 impl Aerugo {
-    pub fn new(_loop: &LoopHandle<'static, Loop>, display: DisplayHandle, backend: Box<dyn Backend>) -> Self {
+    pub fn new(
+        r#loop: &LoopHandle<'static, Loop>,
+        display: DisplayHandle,
+        backend: Box<dyn Backend>,
+        outputs: &[OutputSettings],
+        repeat: KeyRepeat,
+    ) -> Self {
         // Initialize common globals
-        let seat_state = SeatState::new();
+        let mut seat_state = SeatState::new();
+        // The display server drives one seat, which the wm is told about when the wm is started.
+        let mut seat = seat_state.new_wl_seat(&display, "seat0");
+        // The keymap is set by the wm.
+        seat.add_keyboard(Default::default(), repeat.delay, repeat.rate)
+            .expect("Failed to create keyboard");
+        seat.add_pointer();
         let wl_compositor = CompositorState::new::<Self>(&display);
         let xdg_shell = XdgShellState::new::<Self>(&display);
+        // The wm decides whether toplevels are decorated by the client or the display server.
+        let xdg_decoration = XdgDecorationState::new::<Self>(&display);
+        let wlr_layer_shell = WlrLayerShellState::new_with_filter::<Self, _>(&display, |client| {
+            ClientData::get_data(client)
+                .map(|data| data.is_visible(PrivilegedGlobals::LAYER_SHELL))
+                .unwrap_or(false)
+        });
+        let data_device_state = DataDeviceState::new::<Self>(&display);
+        let primary_selection_state = PrimarySelectionState::new::<Self>(&display);
+        // Surfaces are cropped and scaled with the viewport when the surface is drawn.
+        let _viewporter = ViewporterState::new::<Self>(&display);
+        // Gestures the wm does not consume are sent to clients.
+        let _pointer_gestures = PointerGesturesState::new::<Self>(&display);
+        // The content type of toplevels decides whether the toplevel prefers variable refresh.
+        let _content_type = ContentTypeState::new::<Self>(&display);
+        // Sandbox engines create a socket for sandboxed clients, which cannot create security contexts themselves.
+        let _security_context = SecurityContextState::new::<Self, _>(&display, |client| {
+            ClientData::get_data(client)
+                .map(|data| !data.sandboxed)
+                .unwrap_or(false)
+        });
         let _foreign_toplevel_list =
             display.create_global::<Self, ExtForeignToplevelListV1, _>(versions::EXT_FOREIGN_TOPLEVEL_LIST_V1, ());
+        let _activation = display.create_global::<Self, XdgActivationV1, _>(versions::XDG_ACTIVATION_V1, ());
+        let _input_inhibit_manager =
+            display.create_global::<Self, ZwlrInputInhibitManagerV1, _>(versions::ZWLR_INPUT_INHIBIT_MANAGER_V1, ());
+        let _session_lock_manager =
+            display.create_global::<Self, ExtSessionLockManagerV1, _>(versions::EXT_SESSION_LOCK_MANAGER_V1, ());
+        // Clients declare straight alpha buffers, which are premultiplied when drawn.
+        let _color_representation_manager = display
+            .create_global::<Self, WpColorRepresentationManagerV1, _>(versions::WP_COLOR_REPRESENTATION_MANAGER_V1, ());
+        // The cursor rectangle of text inputs is the caret zoom follows.
+        let _text_input_manager =
+            display.create_global::<Self, ZwpTextInputManagerV3, _>(versions::ZWP_TEXT_INPUT_MANAGER_V3, ());
+        // The window of the X11 backend is the only output. The output is named like the output of the X11 backend
+        // of other compositors, so the configuration may refer to it.
+        let name = "X11-1";
+        let subpixel = outputs
+            .iter()
+            .find(|settings| settings.name == name)
+            .and_then(|settings| settings.subpixel);
         let output = Output::new(
-            "Test output".into(),
+            name.into(),
             PhysicalProperties {
                 size: (0, 0).into(),
-                subpixel: smithay::output::Subpixel::Unknown,
+                subpixel: output_config::output_subpixel(Subpixel::Unknown, subpixel),
                 make: String::new(),
                 model: String::new(),
             },
@@ -64,6 +306,10 @@ impl Aerugo {
 
         let shell = Shell::new();
 
+        // Every output starts with a workspace until the wm creates its own workspaces.
+        let mut workspaces = Workspaces::new();
+        workspaces.create("1".into(), Some(output.clone()));
+
         let generation = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .as_ref()
@@ -73,15 +319,445 @@ impl Aerugo {
 
         Self {
             display,
+            loop_handle: r#loop.clone(),
             wl_compositor,
             xdg_shell,
+            xdg_decoration,
+            wlr_layer_shell,
             seat_state,
+            seat,
+            data_device_state,
+            primary_selection_state,
             shell,
             scene,
             output,
             backend,
             generation,
+            damaged: true,
+            exclusive_grab: None,
+            session_lock: None,
+            workspaces,
+            toplevel_groups: ToplevelGroups::new(),
+            keyboard_focus: None,
+            pointer_location: (0.0, 0.0).into(),
+            pointer_events: FilterQueue::new(),
+            pointer_buttons: Vec::new(),
+            interactive_grab: None,
+            touch_points: TouchPoints::default(),
+            popups: Popups::new(),
+            layer_shell: LayerShell::new(),
+            frame_requested: false,
+            protocol_log: ProtocolLog::default(),
+            ipc_events: Vec::new(),
+            rules: Rules::default(),
+            cursor_theme: CursorTheme::default(),
+            cursor_visibility: CursorVisibility::new(CursorHideConfig::default(), Instant::now()),
+            cursor_override: None,
+            cursor_status: None,
+            drag: None,
+            wakeups: Arc::default(),
+            color_transform: ColorTransform::IDENTITY,
+            applied_color_transform: ColorTransform::IDENTITY,
+            color_offloaded: false,
+            idle: None,
+            idle_brightness: 1.0,
+            zoom: Zoom::new(Size::default()),
+            text_inputs: TextInputs::default(),
+            fullscreen_outputs: FxHashMap::default(),
+            ping_watchdog: PingWatchdog::new(PingConfig::default()),
+            configure_watchdog: ConfigureWatchdog::new(PingConfig::default()),
+            transactions: Transactions::new(),
+            thumbnails: ThumbnailCache::new(ThumbnailConfig::default()),
+            switcher: None,
+            placeholders: Placeholders::new(PlaceholderConfig::default()),
+            wm_crash: None,
+            wm_nodes: WmNodes::new(),
+            clients: ClientCounter::default(),
+            metrics: None,
+            frame_stats: Samples::default(),
+            recent_logs: RecentLogs::new(),
+            activation_tokens: ActivationTokens::new(),
+            shutdown_requested: false,
+            restart_requested: false,
+            flush: FlushScheduler::new(),
+            gestures: GestureRouter::new(),
+            audio_streams: AudioStreams::new(),
+            audio_monitor: None,
+            wm: Wm::new(),
+            keyboard_leds: Leds::empty(),
+            keyboard_layout: 0,
+            keyboard_state: KeyboardState::new(&XkbConfig::default()),
+            key_events: FilterQueue::new(),
+            lid_closed: false,
+            tablet_mode: false,
+            modifier_policy: ModifierPolicy::default(),
+            server_decorations: FxHashMap::default(),
+            decoration_nodes: FxHashMap::default(),
+        }
+    }
+}
+
+impl Aerugo {
+    /// Set the scale the scene is composited at before it is scaled to the output.
+    ///
+    /// This takes effect on the next frame.
+    pub fn set_render_scale(&mut self, output: &Output, scale: RenderScale) {
+        if !render_scale::set_render_scale(output, scale) {
+            return;
+        }
+
+        tracing::info!(output = %output.name(), scale = scale.get(), "Render scale changed");
+        self.damaged = true;
+        self.backend.schedule_render();
+    }
+
+    /// Apply a configuration to the output.
+    ///
+    /// Nothing is changed if the configuration is invalid. If the mode could not be set, the modes of the fallback
+    /// cascade are tried instead and the output is disabled if none of them could be set either.
+    pub fn configure_output(&mut self, output: &Output, mut config: OutputConfig) -> Result<(), output_config::Error> {
+        config.validate(output)?;
+
+        if let Some(mode) = config.mode.filter(|&mode| output.current_mode() != Some(mode)) {
+            match self.set_output_mode(output, mode) {
+                ModesetOutcome::Requested(_) => (),
+
+                ModesetOutcome::Fallback { requested, chosen } => {
+                    tracing::warn!(output = %output.name(), ?requested, ?chosen, "Modeset failed, using a fallback mode");
+                    config.mode = Some(chosen);
+                }
+
+                ModesetOutcome::Disabled { requested } => {
+                    tracing::error!(output = %output.name(), ?requested, "Modeset failed, disabled the output");
+                    return Err(output_config::Error::ModesetFailed(requested));
+                }
+            }
+        }
+
+        config.apply(output);
+        tracing::info!(output = %output.name(), ?config, "Output configured");
+        self.output_changed(output);
+        self.scene_changed();
+        Ok(())
+    }
+
+    /// Set the mode of the output, trying the fallback cascade if the mode could not be set.
+    fn set_output_mode(&mut self, output: &Output, mode: Mode) -> ModesetOutcome {
+        if self.backend.set_output_mode(output, mode) {
+            return ModesetOutcome::Requested(mode);
+        }
+
+        for fallback in modeset::fallback_cascade(mode, &output.modes()) {
+            match fallback {
+                Fallback::Mode(chosen) => {
+                    if self.backend.set_output_mode(output, chosen) {
+                        return ModesetOutcome::Fallback {
+                            requested: mode,
+                            chosen,
+                        };
+                    }
+                }
+
+                Fallback::Disable => {
+                    self.set_output_enabled(output, false);
+                }
+            }
+        }
+
+        ModesetOutcome::Disabled { requested: mode }
+    }
+
+    /// Enable or disable the output.
+    pub fn set_output_enabled(&mut self, output: &Output, enabled: bool) {
+        if !self.backend.set_output_enabled(output, enabled) {
+            tracing::warn!(output = %output.name(), enabled, "Failed to enable or disable output");
+            return;
+        }
+
+        tracing::info!(output = %output.name(), enabled, "Output enabled changed");
+
+        if enabled {
+            self.scene_changed();
+        }
+    }
+
+    /// Change which modifiers are offered when buffers are allocated.
+    pub fn set_modifier_policy(&mut self, policy: ModifierPolicy) {
+        self.backend.set_modifier_policy(&policy);
+        self.modifier_policy = policy;
+    }
+
+    /// Turn the display of the output on or off.
+    pub fn set_output_dpms(&mut self, output: &Output, on: bool) {
+        if !self.backend.set_output_dpms(output, on) {
+            tracing::warn!(output = %output.name(), on, "Failed to set output power state");
+            return;
+        }
+
+        tracing::info!(output = %output.name(), on, "Output power state changed");
+
+        // Redraw once the display is on again, since the last frame may be stale.
+        if on {
+            self.scene_changed();
+        }
+    }
+
+    /// Set the night light, brightness and color filter of the output.
+    ///
+    /// The transform is offloaded to the CTM and gamma LUTs of the CRTC where possible to save the GPU a shader
+    /// pass. Otherwise the renderer applies the transform.
+    pub fn set_color_transform(&mut self, transform: ColorTransform) {
+        self.color_transform = transform;
+        self.update_color_transform();
+    }
+
+    /// Dim the output while it fades out before being powered off.
+    pub(crate) fn set_idle_brightness(&mut self, brightness: f64) {
+        self.idle_brightness = brightness;
+        self.update_color_transform();
+    }
+
+    /// Apply the color transform, dimmed by the idle fade.
+    fn update_color_transform(&mut self) {
+        let transform = ColorTransform {
+            brightness: self.color_transform.brightness * self.idle_brightness,
+            ..self.color_transform
+        };
+
+        if self.applied_color_transform == transform {
+            return;
+        }
+
+        let output = self.output.clone();
+        let caps = self.backend.crtc_color_caps(&output);
+        let offloaded = match color::offload(&transform, &caps) {
+            Some(color) => self.backend.set_output_color(&output, &color),
+            None => false,
+        };
+
+        // Clear the properties set for the previous transform, so the transform is not applied twice.
+        if self.color_offloaded && !offloaded {
+            self.backend.set_output_color(&output, &KmsColor::default());
+        }
+
+        tracing::debug!(output = %output.name(), ?transform, offloaded, "Color transform changed");
+        self.applied_color_transform = transform;
+        self.color_offloaded = offloaded;
+        self.scene_changed();
+    }
+
+    /// The actions of the window rules which apply to the toplevel.
+    pub fn toplevel_rules(&self, id: ToplevelId) -> Option<RuleActions> {
+        let toplevel = self.shell.get_state(id)?;
+        Some(
+            self.rules
+                .query(toplevel.app_id().as_deref(), toplevel.title().as_deref()),
+        )
+    }
+
+    /// The clipboard or primary selection changed.
+    pub fn selection_changed(&mut self, kind: SelectionKind, mime_types: Vec<String>, owner: Option<i32>) {
+        let change = SelectionChange {
+            kind,
+            mime_types,
+            owner,
+        };
+        tracing::debug!(?change, "Selection changed");
+        self.send_wm(WmEvent::SelectionChanged {
+            kind: wm::wm_selection_kind(change.kind),
+            mime_types: change.mime_types,
+            owner: change.owner,
+        });
+    }
+
+    /// Set the selection to contents owned by the display server, or clear the selection if the contents are
+    /// empty.
+    pub fn set_selection(&mut self, kind: SelectionKind, contents: SelectionContents) {
+        let mime_types = contents.mime_types();
+
+        match (kind, contents.is_empty()) {
+            (SelectionKind::Clipboard, true) => data_device::clear_data_device_selection(&self.display, &self.seat),
+            (SelectionKind::Clipboard, false) => {
+                data_device::set_data_device_selection(&self.display, &self.seat, mime_types.clone(), contents)
+            }
+            (SelectionKind::Primary, true) => primary_selection::clear_primary_selection(&self.display, &self.seat),
+            (SelectionKind::Primary, false) => {
+                primary_selection::set_primary_selection(&self.display, &self.seat, mime_types.clone(), contents)
+            }
+        }
+
+        self.selection_changed(kind, mime_types, None);
+    }
+
+    /// Log a request of the client if the protocol log is enabled.
+    ///
+    /// Every `Dispatch::request` of the display server calls this before handling the request.
+    pub fn log_request<I: Resource>(&mut self, client: &Client, resource: &I, request: &impl fmt::Debug) {
+        if !self.protocol_log.is_enabled() {
+            return;
+        }
+
+        let Some(data) = ClientData::get_data(client) else {
+            return;
+        };
+
+        let debug = format!("{request:?}");
+        let (name, args) = Message::split_debug(&debug);
+        let message = Message {
+            time: self.protocol_log.elapsed(),
+            direction: Direction::Request,
+            interface: I::interface().name,
+            object: resource.id().protocol_id(),
+            name: &protocol_log::snake_case(name),
+            args,
+        };
+
+        self.protocol_log.log(&data.info, &message);
+    }
+
+    /// Draw a frame on the output even if nothing changed.
+    ///
+    /// This is used by the wm to drive animations.
+    pub fn request_frame(&mut self, output: &Output) {
+        if output != &self.output {
+            return;
         }
+
+        self.frame_requested = true;
+        self.backend.schedule_render();
+    }
+
+    /// Called by the backend before a frame is drawn on the output.
+    ///
+    /// The time is the presentation time of the previous frame in milliseconds.
+    pub fn frame_started(&mut self, output: &Output, time: u32) {
+        self.frame_requested = false;
+        self.update_cursor_visibility(Instant::now());
+        self.update_ping_watchdog(Instant::now());
+        self.update_thumbnails(Instant::now());
+        self.update_switcher();
+        self.update_placeholders(Instant::now());
+        self.update_idle_fade(Instant::now());
+        self.update_zoom_focus();
+        self.update_scanout_eligibility();
+        // The wm advances its animations before the scene is drawn.
+        self.wm_frame(output, time);
+    }
+
+    /// The next time a frame must be drawn even if nothing changed, so the state advanced in
+    /// [`Aerugo::frame_started`] changes on time, such as the cursor starting to fade or a client being pinged.
+    pub(crate) fn next_deadline(&self, now: Instant) -> Option<Instant> {
+        [
+            self.cursor_visibility.next_deadline(),
+            self.ping_watchdog.next_deadline(),
+            self.configure_watchdog.next_deadline(),
+            self.thumbnails.next_deadline(),
+            self.placeholders.next_deadline(now),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|&deadline| deadline > now)
+        .min()
+    }
+
+    /// Ask the client to close the toplevel.
+    pub fn close_toplevel(&mut self, id: ToplevelId) {
+        if let Some(toplevel) = self.shell.get_state(id) {
+            toplevel.send_close();
+        }
+    }
+
+    /// Disconnect the client which owns the toplevel, such as to force quit a client which is not responding.
+    ///
+    /// Xwayland toplevels are not killed, since disconnecting Xwayland would close every X11 client.
+    pub fn kill_client(&mut self, id: ToplevelId) {
+        let Some(toplevel) = self.shell.get_state(id) else {
+            return;
+        };
+
+        let Some(client) = toplevel.client() else {
+            tracing::warn!(id, "Cannot kill the client of an xwayland toplevel");
+            return;
+        };
+
+        tracing::info!(id, app_id = toplevel.app_id(), "Killing client");
+        self.display
+            .backend_handle()
+            .kill_client(client.id(), DisconnectReason::ConnectionClosed);
+    }
+
+    /// Place the toplevel above its siblings in the scene.
+    pub fn raise_toplevel(&mut self, id: ToplevelId) {
+        if let Some(node) = self.toplevel_node(id) {
+            self.scene.raise_node_to_top(node);
+            self.scene_changed();
+        }
+    }
+
+    /// Place the toplevel below its siblings in the scene.
+    pub fn lower_toplevel(&mut self, id: ToplevelId) {
+        if let Some(node) = self.toplevel_node(id) {
+            self.scene.lower_node_to_bottom(node);
+            self.scene_changed();
+        }
+    }
+
+    /// Place the toplevel directly above the sibling in the scene.
+    pub fn restack_toplevel_above(&mut self, id: ToplevelId, sibling: ToplevelId) {
+        let (Some(node), Some(sibling_node)) = (self.toplevel_node(id), self.toplevel_node(sibling)) else {
+            return;
+        };
+
+        match self.scene.restack_above(node, sibling_node) {
+            Ok(()) => self.scene_changed(),
+            Err(err) => tracing::warn!(id, sibling, %err, "Failed to restack toplevel"),
+        }
+    }
+
+    /// Set the properties used when compositing the toplevel, such as the opacity.
+    ///
+    /// The dimming and hiding set by the display server are kept.
+    pub fn set_toplevel_render(&mut self, id: ToplevelId, mut render: RenderProperties) {
+        if let Some(NodeIndex::SurfaceTree(index)) = self.toplevel_node(id) {
+            if let Some(surface_tree) = self.scene.get_surface_tree(index) {
+                let current = surface_tree.render_properties();
+                render.dim = current.dim;
+                render.hidden = current.hidden;
+            }
+
+            self.scene.set_render_properties(index, render);
+            self.scene_changed();
+        }
+    }
+
+    /// Set how much the toplevel is darkened, keeping the properties set by the wm.
+    pub fn set_toplevel_dim(&mut self, id: ToplevelId, dim: f32) {
+        let Some(NodeIndex::SurfaceTree(index)) = self.toplevel_node(id) else {
+            return;
+        };
+
+        let Some(surface_tree) = self.scene.get_surface_tree(index) else {
+            return;
+        };
+
+        let render = RenderProperties {
+            dim,
+            ..surface_tree.render_properties()
+        };
+        self.scene.set_render_properties(index, render);
+        self.scene_changed();
+    }
+
+    /// The scene node of a mapped toplevel.
+    pub(crate) fn toplevel_node(&self, id: ToplevelId) -> Option<NodeIndex> {
+        let surface = self.shell.get_state(id)?.wl_surface()?;
+        self.scene.get_surface_tree_index(surface).map(NodeIndex::SurfaceTree)
+    }
+
+    /// Schedule a frame after the scene changed.
+    pub(crate) fn scene_changed(&mut self) {
+        self.damaged = true;
+        self.backend.schedule_render();
     }
 }
 
@@ -127,6 +803,11 @@ pub struct ClientData {
     // TODO: Make private
     pub(super) globals: PrivilegedGlobals,
     pub(super) compositor: CompositorClientState,
+    pub(super) info: ClientInfo,
+    /// Whether the client connected through the socket of a security context.
+    pub(super) sandboxed: bool,
+    /// Counts the client as connected until the client is destroyed.
+    pub(super) _counted: ClientGuard,
 }
 
 impl ClientData {
@@ -0,0 +1,635 @@
+//! Window switcher
+//!
+//! The wm runtime implements the built-in window switcher and the display server draws it while it is open: a panel
+//! in the middle of the output with a thumbnail of each toplevel, from most to least recently focused, and the
+//! selected toplevel outlined. The panel is drawn above every layer surface, but never while the session is locked.
+//!
+//! Below the toplevels the panel shows a cell for each workspace of the output. A toplevel may be dragged onto
+//! another workspace with the left button, while a ghost of its thumbnail follows the pointer. The display server
+//! does not move the toplevel itself: dropping the toplevel asks the wm to move it, and the wm decides whether to do
+//! so. The drag only starts once the pointer moved [`DRAG_THRESHOLD`] from where the toplevel was pressed, so
+//! clicking a toplevel does not start a drag.
+//!
+//! Thumbnails come from the [`ThumbnailCache`](crate::thumbnail::ThumbnailCache), which renders them before the next
+//! frame, so a toplevel is drawn as a placeholder until its thumbnail is ready.
+
+use smithay::{
+    backend::input::ButtonState,
+    input::pointer::MotionEvent,
+    utils::{Logical, Point, Rectangle, Size, SERIAL_COUNTER},
+};
+use wm_runtime::WmEvent;
+
+use crate::{
+    pointer::PointerEvent,
+    scene::{BranchIndex, NodeIndex, Shape, ShapeIndex},
+    shell::ToplevelId,
+    thumbnail::{thumbnail_size, ThumbnailConsumers},
+    workspace::WorkspaceId,
+    Aerugo,
+};
+
+/// The size of the cell of each toplevel.
+const CELL_WIDTH: i32 = 240;
+const CELL_HEIGHT: i32 = 160;
+
+/// The height of the cell of each workspace.
+const WORKSPACE_HEIGHT: i32 = 48;
+
+/// The space around the cells and between them.
+const PADDING: i32 = 16;
+
+/// The width of the outline of the selected toplevel.
+const OUTLINE: i32 = 4;
+
+/// The distance in logical pixels the pointer must move before a pressed toplevel is dragged.
+pub const DRAG_THRESHOLD: f64 = 8.0;
+
+/// The ghost of a dragged toplevel fits in a cell shrunk by this factor.
+const GHOST_SHRINK: i32 = 2;
+
+/// The linux evdev code of the left button.
+const BTN_LEFT: u32 = 0x110;
+
+const BACKGROUND: [f32; 4] = [0.08, 0.08, 0.08, 0.9];
+const PLACEHOLDER: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+const SELECTED: [f32; 4] = [0.35, 0.55, 0.9, 1.0];
+const WORKSPACE: [f32; 4] = [0.15, 0.15, 0.15, 1.0];
+const ACTIVE_WORKSPACE: [f32; 4] = [0.3, 0.3, 0.3, 1.0];
+
+/// The window switcher while it is open.
+#[derive(Debug)]
+pub struct SwitcherOverlay {
+    /// The toplevels from most to least recently focused.
+    toplevels: Vec<ToplevelId>,
+    selected: usize,
+    branch: BranchIndex,
+    shapes: Vec<ShapeIndex>,
+
+    /// The workspaces of the output when the switcher was drawn.
+    workspaces: Vec<WorkspaceId>,
+
+    /// The left button pressed on the panel.
+    press: Option<Press>,
+
+    /// The ghost of the dragged toplevel and its size.
+    ghost: Option<(ShapeIndex, Size<i32, Logical>)>,
+
+    /// Whether every toplevel was drawn with its thumbnail.
+    complete: bool,
+}
+
+/// The left button was pressed on the panel and is still held.
+#[derive(Debug)]
+struct Press {
+    /// The toplevel which was pressed, if the button was not pressed between the cells.
+    toplevel: Option<ToplevelId>,
+
+    /// Where the button was pressed in output-local coordinates.
+    start: Point<f64, Logical>,
+
+    /// Whether the pointer moved past the drag threshold.
+    dragging: bool,
+
+    /// The workspace the dragged toplevel would be moved to.
+    target: Option<WorkspaceId>,
+}
+
+/// What is under the pointer on the panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hit {
+    /// The cell of the toplevel at the index.
+    Toplevel(usize),
+
+    /// The cell of the workspace at the index.
+    Workspace(usize),
+
+    /// The panel between the cells.
+    Panel,
+}
+
+/// The number of columns and rows of cells on an output with the size.
+fn grid(output: Size<i32, Logical>, count: usize) -> (usize, usize) {
+    let columns = ((output.w - PADDING) / (CELL_WIDTH + PADDING)).max(1) as usize;
+    let columns = columns.min(count.max(1));
+    (columns, (count.max(1) + columns - 1) / columns)
+}
+
+/// The panel behind the cells, centered on an output with the size.
+fn panel_geometry(output: Size<i32, Logical>, count: usize, workspaces: usize) -> Rectangle<i32, Logical> {
+    let (columns, rows) = grid(output, count);
+    let strip = if workspaces == 0 { 0 } else { WORKSPACE_HEIGHT + PADDING };
+    let size = Size::from((
+        columns as i32 * (CELL_WIDTH + PADDING) + PADDING,
+        rows as i32 * (CELL_HEIGHT + PADDING) + PADDING + strip,
+    ));
+
+    Rectangle::from_loc_and_size(((output.w - size.w) / 2, (output.h - size.h) / 2), size)
+}
+
+/// The cell of the toplevel at the index, filled row by row.
+fn cell_geometry(output: Size<i32, Logical>, count: usize, workspaces: usize, index: usize) -> Rectangle<i32, Logical> {
+    let (columns, _) = grid(output, count);
+    let panel = panel_geometry(output, count, workspaces);
+    let (column, row) = ((index % columns) as i32, (index / columns) as i32);
+
+    Rectangle::from_loc_and_size(
+        (
+            panel.loc.x + PADDING + column * (CELL_WIDTH + PADDING),
+            panel.loc.y + PADDING + row * (CELL_HEIGHT + PADDING),
+        ),
+        (CELL_WIDTH, CELL_HEIGHT),
+    )
+}
+
+/// The cell of the workspace at the index, in a row below the toplevels which spans the panel.
+fn workspace_geometry(
+    output: Size<i32, Logical>,
+    count: usize,
+    workspaces: usize,
+    index: usize,
+) -> Rectangle<i32, Logical> {
+    let panel = panel_geometry(output, count, workspaces);
+    let width = panel.size.w - PADDING;
+    let columns = workspaces.max(1) as i32;
+    let (start, end) = (width * index as i32 / columns, width * (index as i32 + 1) / columns);
+
+    Rectangle::from_loc_and_size(
+        (
+            panel.loc.x + PADDING + start,
+            panel.loc.y + panel.size.h - PADDING - WORKSPACE_HEIGHT,
+        ),
+        (end - start - PADDING, WORKSPACE_HEIGHT),
+    )
+}
+
+/// What is under the point in output-local coordinates.
+fn hit(output: Size<i32, Logical>, count: usize, workspaces: usize, point: Point<i32, Logical>) -> Option<Hit> {
+    if !panel_geometry(output, count, workspaces).contains(point) {
+        return None;
+    }
+
+    let toplevel = (0..count).find(|&index| cell_geometry(output, count, workspaces, index).contains(point));
+    let workspace = (0..workspaces).find(|&index| workspace_geometry(output, count, workspaces, index).contains(point));
+
+    Some(match (toplevel, workspace) {
+        (Some(index), _) => Hit::Toplevel(index),
+        (None, Some(index)) => Hit::Workspace(index),
+        (None, None) => Hit::Panel,
+    })
+}
+
+impl Aerugo {
+    /// Show the window switcher with the toplevels from most to least recently focused, or change its selection.
+    pub fn show_switcher(&mut self, toplevels: Vec<ToplevelId>, selected: usize) {
+        if self.is_session_locked() {
+            return;
+        }
+
+        let previous = self.switcher.as_ref().map(|switcher| switcher.toplevels.clone());
+
+        for &id in previous.iter().flatten().filter(|id| !toplevels.contains(id)) {
+            self.thumbnails.release(id, ThumbnailConsumers::SWITCHER);
+        }
+
+        for &id in &toplevels {
+            self.thumbnails.acquire(id, ThumbnailConsumers::SWITCHER);
+        }
+
+        let branch = match &self.switcher {
+            Some(switcher) => switcher.branch,
+            None => {
+                let branch = self.scene.create_branch();
+                let server = NodeIndex::Branch(self.layer_branches().server());
+
+                if let Err(err) = self.scene.add_child(server, NodeIndex::Branch(branch)) {
+                    tracing::warn!(%err, "Failed to show the window switcher");
+                }
+
+                branch
+            }
+        };
+
+        let (shapes, workspaces, mut press, mut ghost) = match self.switcher.take() {
+            Some(switcher) => (switcher.shapes, switcher.workspaces, switcher.press, switcher.ghost),
+            None => Default::default(),
+        };
+
+        // The dragged toplevel is gone, but the button stays pressed on the panel.
+        if let Some(press) = press
+            .as_mut()
+            .filter(|press| press.toplevel.is_some_and(|toplevel| !toplevels.contains(&toplevel)))
+        {
+            press.toplevel = None;
+            press.dragging = false;
+            press.target = None;
+
+            if let Some((ghost, _)) = ghost.take() {
+                self.scene.destroy_shape(ghost);
+            }
+        }
+
+        self.switcher = Some(SwitcherOverlay {
+            selected: selected.min(toplevels.len().saturating_sub(1)),
+            toplevels,
+            branch,
+            shapes,
+            workspaces,
+            press,
+            ghost,
+            complete: false,
+        });
+
+        self.draw_switcher();
+    }
+
+    /// Hide the window switcher.
+    pub fn hide_switcher(&mut self) {
+        let Some(switcher) = self.switcher.take() else {
+            return;
+        };
+
+        for id in switcher.toplevels {
+            self.thumbnails.release(id, ThumbnailConsumers::SWITCHER);
+        }
+
+        for shape in switcher
+            .shapes
+            .into_iter()
+            .chain(switcher.ghost.map(|(ghost, _)| ghost))
+        {
+            self.scene.destroy_shape(shape);
+        }
+
+        self.scene.destroy_branch(switcher.branch);
+        self.scene_changed();
+    }
+
+    /// Draw the thumbnails rendered since the switcher was drawn.
+    pub(crate) fn update_switcher(&mut self) {
+        let Some(switcher) = &self.switcher else {
+            return;
+        };
+
+        let ready = switcher.toplevels.iter().all(|&id| self.thumbnails.get(id).is_some());
+
+        if !switcher.complete && ready {
+            self.draw_switcher();
+        }
+    }
+
+    /// Drag toplevels onto workspaces while the switcher is open.
+    ///
+    /// Returns [`true`] if the event was consumed. Events over the panel are consumed, as is every event from when the
+    /// left button is pressed on the panel until it is released.
+    pub(crate) fn switcher_pointer_input(&mut self, event: PointerEvent) -> bool {
+        let Some(switcher) = &self.switcher else {
+            return false;
+        };
+
+        let pressed = switcher.press.is_some();
+
+        // A client holding a button keeps receiving events while the pointer is over the panel.
+        let Some(pointer) = self
+            .seat
+            .get_pointer()
+            .filter(|pointer| pressed || !pointer.is_grabbed())
+        else {
+            return false;
+        };
+
+        let location = self.pointer_location - self.output.current_location().to_f64();
+        let hit = hit(
+            self.switcher_output_size(),
+            switcher.toplevels.len(),
+            switcher.workspaces.len(),
+            location.to_i32_floor(),
+        );
+
+        if hit.is_none() && !pressed {
+            return false;
+        }
+
+        let (toplevel, workspace) = match hit {
+            Some(Hit::Toplevel(index)) => (switcher.toplevels.get(index).copied(), None),
+            Some(Hit::Workspace(index)) => (None, switcher.workspaces.get(index).copied()),
+            _ => (None, None),
+        };
+
+        match event {
+            PointerEvent::Motion { time, .. } => {
+                // The client the pointer was over is told the pointer left.
+                let serial = SERIAL_COUNTER.next_serial();
+                pointer.motion(
+                    self,
+                    None,
+                    &MotionEvent {
+                        location: self.pointer_location,
+                        serial,
+                        time,
+                    },
+                );
+                pointer.frame(self);
+
+                self.switcher_drag(location, workspace);
+            }
+
+            PointerEvent::Button {
+                button: BTN_LEFT,
+                state: ButtonState::Pressed,
+                ..
+            } if !pressed => {
+                if let Some(switcher) = &mut self.switcher {
+                    switcher.press = Some(Press {
+                        toplevel,
+                        start: location,
+                        dragging: false,
+                        target: None,
+                    });
+                }
+            }
+
+            PointerEvent::Button {
+                button: BTN_LEFT,
+                state: ButtonState::Released,
+                ..
+            } => self.switcher_drop(),
+
+            _ => (),
+        }
+
+        true
+    }
+
+    /// The pointer moved to the location in output-local coordinates, over the workspace, while the left button is
+    /// held.
+    fn switcher_drag(&mut self, location: Point<f64, Logical>, workspace: Option<WorkspaceId>) {
+        let Some(press) = self.switcher.as_mut().and_then(|switcher| switcher.press.as_mut()) else {
+            return;
+        };
+
+        let Some(toplevel) = press.toplevel else {
+            return;
+        };
+
+        let moved = location - press.start;
+
+        if !press.dragging && moved.x.hypot(moved.y) < DRAG_THRESHOLD {
+            return;
+        }
+
+        press.dragging = true;
+
+        // A toplevel cannot be dropped onto the workspace it is in.
+        let target = workspace.filter(|&workspace| self.workspaces.workspace_of(toplevel) != Some(workspace));
+        let retarget = press.target != target;
+        press.target = target;
+
+        if retarget {
+            self.draw_switcher();
+        }
+
+        self.move_ghost(toplevel, location);
+    }
+
+    /// The left button was released, asking the wm to move the dragged toplevel to the workspace under the pointer.
+    fn switcher_drop(&mut self) {
+        let Some(switcher) = &mut self.switcher else {
+            return;
+        };
+
+        let Some(press) = switcher.press.take() else {
+            return;
+        };
+
+        if let Some((ghost, _)) = switcher.ghost.take() {
+            self.scene.destroy_shape(ghost);
+            self.scene_changed();
+        }
+
+        let (Some(toplevel), Some(workspace)) = (press.toplevel, press.target) else {
+            return;
+        };
+
+        // The wm decides whether the toplevel is moved.
+        if let (Some(toplevel), Some(workspace)) = (self.wm.toplevel_id(toplevel), self.wm.workspace_id(workspace)) {
+            self.send_wm(WmEvent::RequestMoveToWorkspace { toplevel, workspace });
+        }
+
+        // Remove the outline of the workspace the toplevel was dropped onto.
+        self.draw_switcher();
+    }
+
+    /// Center the ghost of the dragged toplevel on the location in output-local coordinates, creating the ghost
+    /// when the drag starts.
+    fn move_ghost(&mut self, toplevel: ToplevelId, location: Point<f64, Logical>) {
+        let Some(switcher) = &mut self.switcher else {
+            return;
+        };
+
+        let scale = self.output.current_scale().integer_scale();
+        let branch = switcher.branch;
+
+        let (ghost, size) = *switcher.ghost.get_or_insert_with(|| {
+            let bounds = Size::from((CELL_WIDTH / GHOST_SHRINK, CELL_HEIGHT / GHOST_SHRINK));
+
+            let (size, shape) = match self.thumbnails.get(toplevel) {
+                Some(thumbnail) => (
+                    thumbnail_size(thumbnail.size, bounds.to_physical(1)).to_logical(1),
+                    Shape::Texture {
+                        size: (thumbnail.size.w, thumbnail.size.h).into(),
+                        pixels: thumbnail.pixels.to_vec(),
+                    },
+                ),
+                None => (bounds, Shape::Solid(PLACEHOLDER)),
+            };
+
+            let ghost = self.scene.create_shape(size.to_physical(scale), shape);
+            let _ = self.scene.branch_add_child(branch, NodeIndex::Shape(ghost));
+            (ghost, size)
+        });
+
+        let offset = location.to_i32_round() - Point::from((size.w / 2, size.h / 2));
+        self.scene
+            .set_node_offset(NodeIndex::Shape(ghost), offset.to_physical(scale));
+        self.scene_changed();
+    }
+
+    /// The size of the output the switcher is drawn on.
+    fn switcher_output_size(&self) -> Size<i32, Logical> {
+        let scale = self.output.current_scale().integer_scale();
+
+        self.output
+            .current_mode()
+            .map(|mode| mode.size.to_logical(scale))
+            .unwrap_or_default()
+    }
+
+    /// Draw the panel, the cell of each toplevel and workspace, and the outlines of the selected toplevel and of the
+    /// workspace a toplevel is dragged onto.
+    fn draw_switcher(&mut self) {
+        let Some(mut switcher) = self.switcher.take() else {
+            return;
+        };
+
+        for shape in switcher.shapes.drain(..) {
+            self.scene.destroy_shape(shape);
+        }
+
+        let scale = self.output.current_scale().integer_scale();
+        let output = self.switcher_output_size();
+        let count = switcher.toplevels.len();
+
+        switcher.workspaces = self
+            .workspaces
+            .iter()
+            .filter(|(_, workspace)| workspace.output() == Some(&self.output))
+            .map(|(id, _)| id)
+            .collect();
+        let workspaces = switcher.workspaces.len();
+
+        let target = switcher.press.as_ref().and_then(|press| press.target);
+        let outline = |cell: Rectangle<i32, Logical>| {
+            let outline = Rectangle::from_loc_and_size(
+                (cell.loc.x - OUTLINE, cell.loc.y - OUTLINE),
+                (cell.size.w + OUTLINE * 2, cell.size.h + OUTLINE * 2),
+            );
+
+            (
+                outline,
+                Shape::Border {
+                    width: OUTLINE,
+                    color: SELECTED,
+                },
+            )
+        };
+
+        let mut shapes = vec![(panel_geometry(output, count, workspaces), Shape::Solid(BACKGROUND))];
+        switcher.complete = true;
+
+        for (index, &id) in switcher.workspaces.iter().enumerate() {
+            let cell = workspace_geometry(output, count, workspaces, index);
+            let active = self.workspaces.get(id).is_some_and(|workspace| workspace.is_active());
+
+            if target == Some(id) {
+                shapes.push(outline(cell));
+            }
+
+            shapes.push((cell, Shape::Solid(if active { ACTIVE_WORKSPACE } else { WORKSPACE })));
+        }
+
+        for (index, &id) in switcher.toplevels.iter().enumerate() {
+            let cell = cell_geometry(output, count, workspaces, index);
+
+            if index == switcher.selected {
+                shapes.push(outline(cell));
+            }
+
+            let Some(thumbnail) = self.thumbnails.get(id) else {
+                switcher.complete = false;
+                shapes.push((cell, Shape::Solid(PLACEHOLDER)));
+                continue;
+            };
+
+            // The thumbnail keeps its aspect ratio and is centered in the cell.
+            let size = thumbnail_size(thumbnail.size, cell.size.to_physical(1)).to_logical(1);
+            let area = Rectangle::from_loc_and_size(
+                (
+                    cell.loc.x + (cell.size.w - size.w) / 2,
+                    cell.loc.y + (cell.size.h - size.h) / 2,
+                ),
+                size,
+            );
+
+            shapes.push((
+                area,
+                Shape::Texture {
+                    size: (thumbnail.size.w, thumbnail.size.h).into(),
+                    pixels: thumbnail.pixels.to_vec(),
+                },
+            ));
+        }
+
+        for (geometry, shape) in shapes {
+            let geometry = geometry.to_physical(scale);
+            let index = self.scene.create_shape(geometry.size, shape);
+            self.scene.set_node_offset(NodeIndex::Shape(index), geometry.loc);
+            let _ = self.scene.branch_add_child(switcher.branch, NodeIndex::Shape(index));
+            switcher.shapes.push(index);
+        }
+
+        // The ghost stays above the cells.
+        if let Some((ghost, _)) = switcher.ghost {
+            let _ = self
+                .scene
+                .add_child(NodeIndex::Branch(switcher.branch), NodeIndex::Shape(ghost));
+        }
+
+        self.switcher = Some(switcher);
+        self.scene_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::Rectangle;
+
+    use super::{cell_geometry, hit, panel_geometry, workspace_geometry, Hit};
+
+    #[test]
+    fn centered_on_output() {
+        let output = (1920, 1080).into();
+
+        assert_eq!(
+            panel_geometry(output, 2, 0),
+            Rectangle::from_loc_and_size((696, 444), (528, 192))
+        );
+        assert_eq!(
+            cell_geometry(output, 2, 0, 1),
+            Rectangle::from_loc_and_size((968, 460), (240, 160))
+        );
+    }
+
+    #[test]
+    fn wraps_into_rows() {
+        // Only two cells fit side by side.
+        let output = (600, 800).into();
+
+        assert_eq!(
+            panel_geometry(output, 3, 0),
+            Rectangle::from_loc_and_size((36, 216), (528, 368))
+        );
+        assert_eq!(
+            cell_geometry(output, 3, 0, 2),
+            Rectangle::from_loc_and_size((52, 408), (240, 160))
+        );
+    }
+
+    #[test]
+    fn workspaces_below_toplevels() {
+        let output = (1920, 1080).into();
+
+        assert_eq!(
+            panel_geometry(output, 2, 2),
+            Rectangle::from_loc_and_size((696, 412), (528, 256))
+        );
+        assert_eq!(
+            workspace_geometry(output, 2, 2, 0),
+            Rectangle::from_loc_and_size((712, 604), (240, 48))
+        );
+        assert_eq!(
+            workspace_geometry(output, 2, 2, 1),
+            Rectangle::from_loc_and_size((968, 604), (240, 48))
+        );
+    }
+
+    #[test]
+    fn hit_cells() {
+        let output = (1920, 1080).into();
+
+        assert_eq!(hit(output, 2, 2, (0, 0).into()), None);
+        assert_eq!(hit(output, 2, 2, (700, 420).into()), Some(Hit::Panel));
+        assert_eq!(hit(output, 2, 2, (1000, 500).into()), Some(Hit::Toplevel(1)));
+        assert_eq!(hit(output, 2, 2, (720, 620).into()), Some(Hit::Workspace(0)));
+    }
+}
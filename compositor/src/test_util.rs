@@ -0,0 +1,20 @@
+//! Fixtures shared by the tests of several modules
+
+use std::num::NonZeroU64;
+
+use smithay::output::Mode;
+
+use crate::shell::ToplevelId;
+
+/// A mode with the size and the refresh rate in mHz.
+pub fn mode(w: i32, h: i32, refresh: i32) -> Mode {
+    Mode {
+        size: (w, h).into(),
+        refresh,
+    }
+}
+
+/// The id of a toplevel.
+pub fn toplevel(id: u64) -> ToplevelId {
+    NonZeroU64::new(id).unwrap()
+}
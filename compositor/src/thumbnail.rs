@@ -0,0 +1,338 @@
+//! Toplevel thumbnails
+//!
+//! The wm, foreign toplevel consumers and the window switcher all show small copies of toplevels. Rather than each
+//! consumer copying the toplevel itself, a single thumbnail is cached for each toplevel and shared by every
+//! consumer. Thumbnails are only kept while at least one consumer wants them.
+//!
+//! A thumbnail is not rendered again on every commit. Damage is accumulated until enough of the toplevel has
+//! changed, or until the refresh interval passes if only a little changed, so a blinking cursor in a terminal does
+//! not cause a copy of the terminal every frame.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bitflags::bitflags;
+use rustc_hash::FxHashMap;
+use smithay::utils::{Logical, Physical, Rectangle, Size};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use crate::{shell::ToplevelId, Aerugo};
+
+bitflags! {
+    /// The consumers which want the thumbnail of a toplevel.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct ThumbnailConsumers: u32 {
+        /// The wm requested an image of the toplevel.
+        const WM = 0x01;
+
+        /// A foreign toplevel client requested a preview of the toplevel.
+        const FOREIGN_TOPLEVEL = 0x02;
+
+        /// The window switcher is showing the toplevel.
+        const SWITCHER = 0x04;
+    }
+}
+
+/// Configuration of when thumbnails are rendered again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThumbnailConfig {
+    /// The largest size of a thumbnail. Toplevels are scaled down to fit, keeping the aspect ratio.
+    pub max_size: Size<i32, Physical>,
+
+    /// The fraction of the toplevel which must be damaged before the thumbnail is rendered immediately.
+    pub damage_threshold: f64,
+
+    /// How often a thumbnail with less damage than the threshold is rendered.
+    pub refresh_interval: Duration,
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        Self {
+            max_size: (512, 512).into(),
+            damage_threshold: 0.25,
+            refresh_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A rendered thumbnail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub size: Size<i32, Physical>,
+
+    /// Premultiplied ARGB8888 pixels.
+    pub pixels: Arc<[u8]>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    consumers: ThumbnailConsumers,
+    thumbnail: Option<Thumbnail>,
+
+    /// The area damaged since the thumbnail was rendered, as a fraction of the toplevel.
+    damage: f64,
+
+    /// When the thumbnail was last rendered.
+    rendered: Option<Instant>,
+}
+
+/// The cached thumbnails of every toplevel.
+#[derive(Debug)]
+pub struct ThumbnailCache {
+    config: ThumbnailConfig,
+    entries: FxHashMap<ToplevelId, Entry>,
+}
+
+impl ThumbnailCache {
+    pub fn new(config: ThumbnailConfig) -> Self {
+        Self {
+            config,
+            entries: FxHashMap::default(),
+        }
+    }
+
+    /// The consumer wants the thumbnail of the toplevel.
+    ///
+    /// A toplevel without a thumbnail is rendered on the next call to [`ThumbnailCache::due`].
+    pub fn acquire(&mut self, id: ToplevelId, consumer: ThumbnailConsumers) {
+        self.entries
+            .entry(id)
+            .or_insert(Entry {
+                consumers: ThumbnailConsumers::empty(),
+                thumbnail: None,
+                damage: 0.0,
+                rendered: None,
+            })
+            .consumers |= consumer;
+    }
+
+    /// The consumer no longer needs the thumbnail of the toplevel.
+    ///
+    /// The thumbnail is dropped once no consumer wants it.
+    pub fn release(&mut self, id: ToplevelId, consumer: ThumbnailConsumers) {
+        let Some(entry) = self.entries.get_mut(&id) else {
+            return;
+        };
+
+        entry.consumers.remove(consumer);
+
+        if entry.consumers.is_empty() {
+            self.entries.remove(&id);
+        }
+    }
+
+    /// Drop the thumbnail of a destroyed toplevel.
+    pub fn remove(&mut self, id: ToplevelId) {
+        self.entries.remove(&id);
+    }
+
+    /// The cached thumbnail of the toplevel, which may be slightly out of date.
+    pub fn get(&self, id: ToplevelId) -> Option<&Thumbnail> {
+        self.entries.get(&id)?.thumbnail.as_ref()
+    }
+
+    /// Accumulate the damage of a commit of the toplevel.
+    ///
+    /// Overlapping damage is counted more than once, which at worst renders the thumbnail early.
+    pub fn damage(
+        &mut self,
+        id: ToplevelId,
+        size: Size<i32, Logical>,
+        damage: impl IntoIterator<Item = Rectangle<i32, Logical>>,
+    ) {
+        let Some(entry) = self.entries.get_mut(&id) else {
+            return;
+        };
+
+        let bounds = Rectangle::from_loc_and_size((0, 0), size);
+        let total = (size.w.max(1) as f64) * (size.h.max(1) as f64);
+        let damaged = damage
+            .into_iter()
+            .filter_map(|rect| rect.intersection(bounds))
+            .map(|rect| rect.size.w as f64 * rect.size.h as f64)
+            .sum::<f64>();
+
+        entry.damage = (entry.damage + damaged / total).min(1.0);
+    }
+
+    /// The toplevels whose thumbnails must be rendered.
+    pub fn due(&self, now: Instant) -> Vec<ToplevelId> {
+        self.entries
+            .iter()
+            .filter(|(_, entry)| match entry.rendered {
+                None => true,
+                Some(_) if entry.damage >= self.config.damage_threshold => true,
+                Some(rendered) => entry.damage > 0.0 && now >= rendered + self.config.refresh_interval,
+            })
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    /// The next time [`ThumbnailCache::due`] returns a toplevel because of the refresh interval.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.entries
+            .values()
+            .filter(|entry| entry.damage > 0.0)
+            .filter_map(|entry| entry.rendered)
+            .map(|rendered| rendered + self.config.refresh_interval)
+            .min()
+    }
+
+    /// Store a newly rendered thumbnail of the toplevel.
+    pub fn update(&mut self, id: ToplevelId, thumbnail: Thumbnail, now: Instant) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.thumbnail = Some(thumbnail);
+            entry.damage = 0.0;
+            entry.rendered = Some(now);
+        }
+    }
+}
+
+/// The size of the thumbnail of a toplevel with the size.
+///
+/// The toplevel is scaled down to fit the maximum size while keeping the aspect ratio and is never scaled up.
+pub fn thumbnail_size(size: Size<i32, Physical>, max: Size<i32, Physical>) -> Size<i32, Physical> {
+    let scale = (max.w as f64 / size.w.max(1) as f64)
+        .min(max.h as f64 / size.h.max(1) as f64)
+        .min(1.0);
+
+    (
+        ((size.w as f64 * scale).round() as i32).max(1),
+        ((size.h as f64 * scale).round() as i32).max(1),
+    )
+        .into()
+}
+
+impl Aerugo {
+    /// Render the thumbnails which are due.
+    pub fn update_thumbnails(&mut self, now: Instant) {
+        for id in self.thumbnails.due(now) {
+            let Some(surface) = self.shell.get_state(id).and_then(|toplevel| toplevel.wl_surface()) else {
+                continue;
+            };
+
+            let max_size = self.thumbnails.config.max_size;
+            if let Some(thumbnail) = self.backend.render_thumbnail(&self.scene, &surface, max_size) {
+                self.thumbnails.update(id, thumbnail, now);
+            }
+        }
+    }
+
+    /// Capture the last buffer committed by the toplevel for the wm, scaled down to fit the maximum size.
+    ///
+    /// Unlike a thumbnail the snapshot is rendered right away and never refreshed, so the wm decides how often a
+    /// preview is updated. Without a maximum size the snapshot is the size of the toplevel.
+    pub fn snapshot_toplevel(&mut self, id: ToplevelId, max_size: Option<Size<i32, Physical>>) -> Option<Thumbnail> {
+        let surface = self.shell.get_state(id)?.wl_surface()?;
+        let max_size = max_size.unwrap_or_else(|| (i32::MAX, i32::MAX).into());
+        self.backend.render_thumbnail(&self.scene, &surface, max_size)
+    }
+
+    /// Capture the last buffer committed to a surface which is not drawn in the scene, such as a drag icon.
+    pub fn snapshot_surface(&mut self, surface: &WlSurface) -> Option<Thumbnail> {
+        let drawn = self.scene.get_surface_tree_index(surface.clone()).is_some();
+
+        if !drawn {
+            self.scene.create_surface_tree(surface.clone());
+        }
+
+        let thumbnail = self
+            .backend
+            .render_thumbnail(&self.scene, surface, (i32::MAX, i32::MAX).into());
+
+        if !drawn {
+            self.scene.destroy_surface_tree(surface);
+        }
+
+        thumbnail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use smithay::utils::Rectangle;
+
+    use crate::test_util::toplevel;
+
+    use super::{thumbnail_size, Thumbnail, ThumbnailCache, ThumbnailConfig, ThumbnailConsumers};
+
+    const REFRESH: Duration = Duration::from_secs(1);
+
+    fn config() -> ThumbnailConfig {
+        ThumbnailConfig {
+            max_size: (256, 256).into(),
+            damage_threshold: 0.25,
+            refresh_interval: REFRESH,
+        }
+    }
+
+    fn thumbnail() -> Thumbnail {
+        Thumbnail {
+            size: (256, 144).into(),
+            pixels: vec![0; 256 * 144 * 4].into(),
+        }
+    }
+
+    #[test]
+    fn shared_between_consumers() {
+        let mut cache = ThumbnailCache::new(config());
+        let start = Instant::now();
+        let id = toplevel(1);
+
+        cache.acquire(id, ThumbnailConsumers::WM);
+        cache.acquire(id, ThumbnailConsumers::SWITCHER);
+        assert_eq!(cache.due(start), [id]);
+
+        cache.update(id, thumbnail(), start);
+        assert!(cache.due(start).is_empty());
+        assert!(cache.get(id).is_some());
+
+        cache.release(id, ThumbnailConsumers::WM);
+        assert!(cache.get(id).is_some());
+        cache.release(id, ThumbnailConsumers::SWITCHER);
+        assert!(cache.get(id).is_none());
+    }
+
+    #[test]
+    fn damage_is_accumulated() {
+        let mut cache = ThumbnailCache::new(config());
+        let start = Instant::now();
+        let id = toplevel(1);
+        cache.acquire(id, ThumbnailConsumers::FOREIGN_TOPLEVEL);
+        cache.update(id, thumbnail(), start);
+
+        // A blinking cursor only damages a small area.
+        let cursor = Rectangle::from_loc_and_size((10, 10), (10, 20));
+        cache.damage(id, (1000, 1000).into(), [cursor]);
+        assert!(cache.due(start).is_empty());
+        assert_eq!(cache.next_deadline(), Some(start + REFRESH));
+        assert_eq!(cache.due(start + REFRESH), [id]);
+
+        cache.update(id, thumbnail(), start);
+        cache.damage(
+            id,
+            (1000, 1000).into(),
+            [Rectangle::from_loc_and_size((0, 0), (1000, 200))],
+        );
+        assert!(cache.due(start).is_empty());
+        cache.damage(
+            id,
+            (1000, 1000).into(),
+            [Rectangle::from_loc_and_size((0, 900), (1000, 500))],
+        );
+        assert_eq!(cache.due(start), [id]);
+    }
+
+    #[test]
+    fn scaled_to_fit() {
+        let max = (256, 256).into();
+
+        assert_eq!(thumbnail_size((1920, 1080).into(), max), (256, 144).into());
+        assert_eq!(thumbnail_size((100, 50).into(), max), (100, 50).into());
+    }
+}
@@ -0,0 +1,133 @@
+//! Touch input
+//!
+//! Touch points are sent to the wm, so the wm may implement touch gestures and detect taps. Clients do not receive
+//! touch input, since the seat has no touch handle yet.
+//!
+//! The seat known to the wm has the touch capability while a touchscreen is connected.
+//!
+//! The position of a touch point is relative to the toplevel under the point when the point appeared, like the
+//! position of the pointer is relative to the toplevel under the pointer.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+use smithay::utils::{Logical, Point};
+use wm_runtime::WmEvent;
+
+use crate::{wm, Aerugo};
+
+/// An event of a touchscreen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TouchEvent {
+    /// A touch point appeared at the location in the global compositor space.
+    Down {
+        time: u32,
+        id: i32,
+        location: Point<f64, Logical>,
+    },
+
+    /// A touch point moved to the location in the global compositor space.
+    Motion {
+        time: u32,
+        id: i32,
+        location: Point<f64, Logical>,
+    },
+
+    Up {
+        time: u32,
+        id: i32,
+    },
+
+    /// Every touch point is gone.
+    Cancel,
+}
+
+/// The touch points known to the wm.
+#[derive(Debug, Default)]
+pub struct TouchPoints {
+    /// The origin the position of each touch point is relative to.
+    points: FxHashMap<i32, Point<f64, Logical>>,
+
+    /// The ids of the connected touchscreens.
+    devices: FxHashSet<String>,
+}
+
+impl TouchPoints {
+    /// Whether a touchscreen is connected.
+    pub fn has_devices(&self) -> bool {
+        !self.devices.is_empty()
+    }
+}
+
+impl Aerugo {
+    /// A touchscreen was connected.
+    pub fn touch_device_added(&mut self, device: String) {
+        let first = !self.touch_points.has_devices();
+        self.touch_points.devices.insert(device);
+
+        if first {
+            self.send_seat_capabilities();
+        }
+    }
+
+    /// A touchscreen was disconnected, which cancels the touch points once no touchscreen is left.
+    pub fn touch_device_removed(&mut self, device: &str) {
+        if !self.touch_points.devices.remove(device) || self.touch_points.has_devices() {
+            return;
+        }
+
+        self.touch_input(TouchEvent::Cancel);
+        self.send_seat_capabilities();
+    }
+
+    /// An event of a touchscreen.
+    ///
+    /// The wm is not told about touch points which appear while the session is locked or an exclusive grab is
+    /// active, but is told when a touch point it knows about disappears.
+    pub fn touch_input(&mut self, event: TouchEvent) {
+        let seat = self.wm.seat();
+
+        match event {
+            TouchEvent::Down { time, id, location } => {
+                if !self.wm_filters_input() {
+                    return;
+                }
+
+                let (focus, position) = self.wm_focus_under(location);
+                self.touch_points.points.insert(id, location - position);
+                self.send_wm(WmEvent::TouchDown {
+                    seat,
+                    time,
+                    id,
+                    focus,
+                    position: wm::wm_point(position),
+                });
+            }
+
+            TouchEvent::Motion { time, id, location } => {
+                let Some(origin) = self.touch_points.points.get(&id) else {
+                    return;
+                };
+
+                let position = location - *origin;
+                self.send_wm(WmEvent::TouchMotion {
+                    seat,
+                    time,
+                    id,
+                    position: wm::wm_point(position),
+                });
+            }
+
+            TouchEvent::Up { time, id } => {
+                if self.touch_points.points.remove(&id).is_some() {
+                    self.send_wm(WmEvent::TouchUp { seat, time, id });
+                }
+            }
+
+            TouchEvent::Cancel => {
+                if !self.touch_points.points.is_empty() {
+                    self.touch_points.points.clear();
+                    self.send_wm(WmEvent::TouchCancel(seat));
+                }
+            }
+        }
+    }
+}
@@ -1,10 +1,29 @@
 //! Dependency tracking
 //!
 //! This module provides the [`DependencyTracker`] type to help manage transaction dependencies.
+//!
+//! # Transactions
+//!
+//! When the wm retiles, every toplevel is configured with a new size. The toplevels ack and commit their new
+//! buffers at different times, so presenting each buffer as soon as it is committed shows a torn layout for a few
+//! frames, with some toplevels at their new size and others overlapping or leaving gaps. The wm may begin a
+//! transaction before configuring the toplevels and commit it afterwards. [`Transactions`] then holds back
+//! presentation until every toplevel configured in the transaction acked its configure and committed a buffer, so
+//! the new layout is presented at once.
+//!
+//! A transaction depends on one node of the [`DependencyTracker`] per configure. A toplevel which does not commit
+//! in time must not freeze the display, so a committed transaction holds back presentation for at most
+//! [`TRANSACTION_TIMEOUT`].
 
-use std::mem;
+use std::{
+    fmt, mem,
+    time::{Duration, Instant},
+};
 
 use slotmap::SlotMap;
+use smithay::utils::Serial;
+
+use crate::{shell::ToplevelId, Aerugo};
 
 slotmap::new_key_type! {
     pub struct Id;
@@ -199,6 +218,28 @@ impl DependencyTracker {
     pub fn drain_finished(&mut self) -> Vec<Id> {
         mem::take(&mut self.finished)
     }
+
+    /// Remove the node.
+    ///
+    /// The node is removed from the dependencies of its dependents and the dependents of its dependencies without
+    /// finishing or failing any node.
+    pub fn remove(&mut self, id: Id) {
+        let Some(node) = self.nodes.remove(id) else {
+            return;
+        };
+
+        for dependent in node.dependents {
+            if let Some(node) = self.nodes.get_mut(dependent) {
+                node.dependencies.retain(|&dependency| dependency != id);
+            }
+        }
+
+        for dependency in node.dependencies {
+            if let Some(node) = self.nodes.get_mut(dependency) {
+                node.dependents.retain(|&dependent| dependent != id);
+            }
+        }
+    }
 }
 
 #[derive(Default)]
@@ -208,13 +249,197 @@ struct Node {
     status: Status,
 }
 
+/// The longest a committed transaction holds back presentation.
+pub const TRANSACTION_TIMEOUT: Duration = Duration::from_millis(150);
+
+/// A configure submitted in a transaction.
+#[derive(Debug)]
+struct PendingConfigure {
+    toplevel: ToplevelId,
+    serial: Serial,
+    node: Id,
+    acked: bool,
+}
+
+/// Tracks the configures of transactions which are presented together.
+#[derive(Default)]
+pub struct Transactions {
+    tracker: DependencyTracker,
+
+    /// The open transaction and the node which keeps it from finishing until it is committed.
+    open: Option<(Id, Id)>,
+
+    /// Configures which the toplevels have not acked and committed.
+    configures: Vec<PendingConfigure>,
+
+    /// Committed transactions and when they stop holding back presentation.
+    committed: Vec<(Id, Instant)>,
+}
+
+impl fmt::Debug for Transactions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transactions")
+            .field("open", &self.open.is_some())
+            .field("configures", &self.configures)
+            .field("committed", &self.committed.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Transactions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begin a transaction.
+    ///
+    /// Beginning a transaction while one is open does nothing.
+    pub fn begin(&mut self) {
+        if self.open.is_some() {
+            return;
+        }
+
+        let transaction = self.tracker.create_id();
+        let guard = self.tracker.create_id();
+        let _ = self.tracker.add_dependency(transaction, guard);
+        self.open = Some((transaction, guard));
+    }
+
+    /// Commit the open transaction.
+    ///
+    /// Presentation is held back until every configure of the transaction was acked and committed, or until the
+    /// timeout passed.
+    pub fn commit(&mut self, now: Instant) {
+        let Some((transaction, guard)) = self.open.take() else {
+            return;
+        };
+
+        self.tracker.finish(guard);
+        self.tracker.remove(guard);
+        self.committed.push((transaction, now + TRANSACTION_TIMEOUT));
+        self.collect();
+    }
+
+    /// A configure which the client must ack was sent to the toplevel.
+    pub fn configure_sent(&mut self, toplevel: ToplevelId, serial: Serial) {
+        let Some((transaction, _)) = self.open else {
+            return;
+        };
+
+        let node = self.tracker.create_id();
+        let _ = self.tracker.add_dependency(transaction, node);
+        self.configures.push(PendingConfigure {
+            toplevel,
+            serial,
+            node,
+            acked: false,
+        });
+    }
+
+    /// The toplevel acked a configure.
+    ///
+    /// Acking a configure implicitly acks the configures sent before it, but not the configures sent after it.
+    pub fn acked(&mut self, toplevel: ToplevelId, serial: Serial) {
+        for configure in &mut self.configures {
+            if configure.toplevel == toplevel && serial.is_no_older_than(&configure.serial) {
+                configure.acked = true;
+            }
+        }
+    }
+
+    /// The toplevel committed a buffer.
+    ///
+    /// Returns true if a committed transaction finished, so the held back frame should be presented.
+    pub fn committed(&mut self, toplevel: ToplevelId) -> bool {
+        self.finish_configures(|configure| configure.toplevel == toplevel && configure.acked)
+    }
+
+    /// Stop waiting for a destroyed toplevel.
+    ///
+    /// Returns true if a committed transaction finished.
+    pub fn remove(&mut self, toplevel: ToplevelId) -> bool {
+        self.finish_configures(|configure| configure.toplevel == toplevel)
+    }
+
+    /// Whether presentation is held back by a committed transaction.
+    pub fn is_blocking(&mut self, now: Instant) -> bool {
+        self.collect();
+
+        for &(transaction, _) in self.committed.iter().filter(|(_, deadline)| now >= *deadline) {
+            tracing::debug!(?transaction, "Transaction timed out");
+            self.tracker.remove(transaction);
+        }
+
+        self.committed.retain(|(_, deadline)| now < *deadline);
+        !self.committed.is_empty()
+    }
+
+    /// When the oldest committed transaction times out.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.committed.iter().map(|(_, deadline)| *deadline).min()
+    }
+
+    fn finish_configures(&mut self, mut f: impl FnMut(&PendingConfigure) -> bool) -> bool {
+        let mut finished = false;
+
+        self.configures.retain(|configure| {
+            if !f(configure) {
+                return true;
+            }
+
+            self.tracker.finish(configure.node);
+            self.tracker.remove(configure.node);
+            finished = true;
+            false
+        });
+
+        finished && self.collect()
+    }
+
+    /// Remove the finished transactions. Returns true if a committed transaction finished.
+    fn collect(&mut self) -> bool {
+        let finished = self.tracker.drain_finished();
+        let len = self.committed.len();
+
+        self.committed
+            .retain(|&(transaction, _)| !finished.contains(&transaction));
+
+        for transaction in finished {
+            self.tracker.remove(transaction);
+        }
+
+        self.committed.len() != len
+    }
+}
+
+impl Aerugo {
+    /// Begin a transaction for the configures the wm submits.
+    pub fn begin_transaction(&mut self) {
+        self.transactions.begin();
+    }
+
+    /// Commit the open transaction, holding back presentation until every configure of the transaction was acked
+    /// and committed.
+    pub fn commit_transaction(&mut self) {
+        self.transactions.commit(Instant::now());
+        // Draw again once the transaction times out, even if none of its toplevels commit.
+        self.backend.schedule_render();
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::time::Instant;
+
     use slotmap::KeyData;
+    use smithay::utils::Serial;
 
-    use crate::transaction::{Error, Status};
+    use crate::{
+        test_util::toplevel,
+        transaction::{Error, Status},
+    };
 
-    use super::{DependencyTracker, Id};
+    use super::{DependencyTracker, Id, Transactions, TRANSACTION_TIMEOUT};
 
     #[test]
     fn add_missing() {
@@ -586,4 +811,92 @@ mod tests {
         assert!(finished.contains(&c));
         assert_eq!(finished.len(), 3);
     }
+
+    #[test]
+    fn transaction_waits_for_every_toplevel() {
+        let mut transactions = Transactions::new();
+        let now = Instant::now();
+
+        transactions.begin();
+        transactions.configure_sent(toplevel(1), Serial::from(1));
+        transactions.configure_sent(toplevel(2), Serial::from(2));
+        transactions.commit(now);
+        assert!(transactions.is_blocking(now));
+
+        // A commit before the configure is acked does not apply the configure.
+        assert!(!transactions.committed(toplevel(1)));
+        transactions.acked(toplevel(1), Serial::from(1));
+        assert!(!transactions.committed(toplevel(1)));
+        assert!(transactions.is_blocking(now));
+
+        transactions.acked(toplevel(2), Serial::from(2));
+        assert!(transactions.committed(toplevel(2)));
+        assert!(!transactions.is_blocking(now));
+
+        // Configures outside of a transaction are not held back.
+        transactions.configure_sent(toplevel(1), Serial::from(3));
+        assert!(!transactions.is_blocking(now));
+    }
+
+    #[test]
+    fn transaction_timeout() {
+        let mut transactions = Transactions::new();
+        let now = Instant::now();
+
+        transactions.begin();
+        transactions.configure_sent(toplevel(1), Serial::from(4));
+        transactions.commit(now);
+
+        assert_eq!(transactions.next_deadline(), Some(now + TRANSACTION_TIMEOUT));
+        assert!(transactions.is_blocking(now + TRANSACTION_TIMEOUT / 2));
+        assert!(!transactions.is_blocking(now + TRANSACTION_TIMEOUT));
+        assert_eq!(transactions.next_deadline(), None);
+
+        // The late commit of the toplevel does not finish another transaction.
+        transactions.acked(toplevel(1), Serial::from(4));
+        assert!(!transactions.committed(toplevel(1)));
+    }
+
+    #[test]
+    fn transaction_waits_for_ack_of_its_configure() {
+        let mut transactions = Transactions::new();
+        let now = Instant::now();
+
+        transactions.begin();
+        transactions.configure_sent(toplevel(1), Serial::from(6));
+        transactions.configure_sent(toplevel(1), Serial::from(7));
+        transactions.commit(now);
+
+        // Acking an older configure does not ack the configure of the transaction.
+        transactions.acked(toplevel(1), Serial::from(5));
+        assert!(!transactions.committed(toplevel(1)));
+        assert!(transactions.is_blocking(now));
+
+        // Acking the first configure of the transaction does not ack the second.
+        transactions.acked(toplevel(1), Serial::from(6));
+        assert!(!transactions.committed(toplevel(1)));
+        assert!(transactions.is_blocking(now));
+
+        transactions.acked(toplevel(1), Serial::from(7));
+        assert!(transactions.committed(toplevel(1)));
+        assert!(!transactions.is_blocking(now));
+    }
+
+    #[test]
+    fn transaction_destroyed_toplevel() {
+        let mut transactions = Transactions::new();
+        let now = Instant::now();
+
+        // An empty transaction does not hold back presentation.
+        transactions.begin();
+        transactions.commit(now);
+        assert!(!transactions.is_blocking(now));
+
+        transactions.begin();
+        transactions.configure_sent(toplevel(1), Serial::from(5));
+        transactions.commit(now);
+
+        assert!(transactions.remove(toplevel(1)));
+        assert!(!transactions.is_blocking(now));
+    }
 }
@@ -0,0 +1,67 @@
+//! Variable refresh rate
+//!
+//! Displays with variable refresh (adaptive sync) wait for the next frame instead of refreshing at a fixed rate.
+//! This avoids stutter when a game renders slower than the refresh rate, but some displays flicker when the frame
+//! rate changes a lot, such as on an idle desktop. Variable refresh is therefore never enabled by the display server
+//! itself. The wm enables it on an output, usually while a fullscreen toplevel which prefers variable refresh is
+//! focused.
+//!
+//! Clients state what a surface shows using `wp-content-type-v1`. A toplevel prefers variable refresh if it shows
+//! a game, or a video which may then be shown at its own frame rate.
+
+use smithay::{
+    output::Output,
+    reexports::wayland_protocols::wp::content_type::v1::server::wp_content_type_v1::Type as ContentType,
+    wayland::{compositor, content_type::ContentTypeSurfaceCachedState},
+};
+use wayland_server::protocol::wl_surface::WlSurface;
+
+use crate::Aerugo;
+
+/// Whether a surface showing the content type prefers variable refresh.
+pub fn prefers_variable_refresh(content_type: ContentType) -> bool {
+    matches!(content_type, ContentType::Game | ContentType::Video)
+}
+
+/// Whether the surface prefers variable refresh, from its committed content type.
+pub fn surface_prefers_variable_refresh(surface: &WlSurface) -> bool {
+    compositor::with_states(surface, |states| {
+        let content_type = *states
+            .cached_state
+            .current::<ContentTypeSurfaceCachedState>()
+            .content_type();
+        prefers_variable_refresh(content_type)
+    })
+}
+
+impl Aerugo {
+    /// Enable or disable variable refresh on the output.
+    pub fn set_output_vrr(&mut self, output: &Output, enabled: bool) {
+        if !self.backend.output_supports_vrr(output) {
+            tracing::debug!(output = %output.name(), "Output does not support variable refresh");
+            return;
+        }
+
+        if !self.backend.set_output_vrr(output, enabled) {
+            tracing::warn!(output = %output.name(), enabled, "Failed to set variable refresh");
+            return;
+        }
+
+        tracing::info!(output = %output.name(), enabled, "Variable refresh changed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::reexports::wayland_protocols::wp::content_type::v1::server::wp_content_type_v1::Type as ContentType;
+
+    use super::prefers_variable_refresh;
+
+    #[test]
+    fn content_types() {
+        assert!(prefers_variable_refresh(ContentType::Game));
+        assert!(prefers_variable_refresh(ContentType::Video));
+        assert!(!prefers_variable_refresh(ContentType::Photo));
+        assert!(!prefers_variable_refresh(ContentType::None));
+    }
+}
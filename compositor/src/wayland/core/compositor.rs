@@ -1,12 +1,16 @@
 use std::borrow::Cow;
 
 use smithay::{
-    backend::renderer::utils::on_commit_buffer_handler,
-    wayland::compositor::{self, CompositorClientState, CompositorHandler, CompositorState},
+    backend::renderer::utils::{on_commit_buffer_handler, with_renderer_surface_state},
+    utils::{Logical, Point, Rectangle},
+    wayland::compositor::{
+        self, CompositorClientState, CompositorHandler, CompositorState, Damage, SubsurfaceCachedState,
+        SurfaceAttributes, TraversalAction,
+    },
 };
 use wayland_server::{protocol::wl_surface::WlSurface, Client};
 
-use crate::{shell::Shell, state::ClientData, Aerugo};
+use crate::{alpha, shell::Shell, state::ClientData, Aerugo};
 
 impl CompositorHandler for Aerugo {
     fn compositor_state(&mut self) -> &mut CompositorState {
@@ -14,10 +18,17 @@ impl CompositorHandler for Aerugo {
     }
 
     fn commit(&mut self, surface: &WlSurface) {
+        // The damage is taken from the surface state by the buffer handler, so it is read first.
+        let damage = surface_tree_damage(surface);
+
         // Let Smithay perform buffer management for us.
         //
         // on_commit_buffer_handler will manage the buffer, damage and opaque regions.
         on_commit_buffer_handler::<Self>(surface);
+        alpha::commit_alpha_mode(surface);
+
+        self.damaged = true;
+        self.backend.schedule_render();
 
         // If the surface is sync the parent needs to be committed to apply the pending state.
         //
@@ -28,14 +39,27 @@ impl CompositorHandler for Aerugo {
 
         // Select the root surface if a desync subsurface was committed.
         let mut surface = Cow::Borrowed(surface);
+        let mut offset = Point::<i32, Logical>::default();
 
         while let Some(parent) = compositor::get_parent(&surface) {
+            offset += compositor::with_states(&surface, |states| {
+                states.cached_state.current::<SubsurfaceCachedState>().location
+            });
             surface = Cow::Owned(parent);
         }
 
         // Commit the root surface state in the shell. This will complete any transactions that are in flight
         // and are waiting for the acked state to be applied.
         Shell::commit(self, &surface);
+
+        if let Some(id) = Shell::get_toplevel_id(&surface) {
+            let size = with_renderer_surface_state(&surface, |state| state.surface_size()).unwrap_or_default();
+            let damage = damage.into_iter().map(|mut rect| {
+                rect.loc += offset;
+                rect
+            });
+            self.thumbnails.damage(id, size, damage);
+        }
     }
 
     fn client_compositor_state<'a>(&self, client: &'a Client) -> &'a CompositorClientState {
@@ -43,8 +67,50 @@ impl CompositorHandler for Aerugo {
     }
 
     fn destroyed(&mut self, surface: &WlSurface) {
+        self.cursor_surface_destroyed(surface);
         Shell::remove_toplevel(self, surface)
     }
 }
 
+/// The damage of a committed surface and the subsurfaces committed with it, relative to the surface.
+fn surface_tree_damage(surface: &WlSurface) -> Vec<Rectangle<i32, Logical>> {
+    let mut damage = Vec::new();
+
+    compositor::with_surface_tree_upward(
+        surface,
+        Point::<i32, Logical>::default(),
+        |child, states, &offset| {
+            let mut offset = offset;
+
+            if child != surface {
+                offset += states.cached_state.current::<SubsurfaceCachedState>().location;
+            }
+
+            TraversalAction::DoChildren(offset)
+        },
+        |_, states, &offset| {
+            let attributes = states.cached_state.current::<SurfaceAttributes>();
+            let scale = attributes.buffer_scale.max(1);
+
+            damage.extend(attributes.damage.iter().map(|damage| {
+                let mut rect = match *damage {
+                    Damage::Surface(rect) => rect,
+                    // Thumbnails only need the damaged area, so the buffer transform is ignored.
+                    Damage::Buffer(rect) => Rectangle::from_loc_and_size(
+                        (rect.loc.x / scale, rect.loc.y / scale),
+                        ((rect.size.w + scale - 1) / scale, (rect.size.h + scale - 1) / scale),
+                    ),
+                };
+                rect.loc += offset;
+                rect
+            }));
+        },
+        |_, _, _| true,
+    );
+
+    damage
+}
+
 smithay::delegate_compositor!(Aerugo);
+smithay::delegate_viewporter!(Aerugo);
+smithay::delegate_content_type!(Aerugo);
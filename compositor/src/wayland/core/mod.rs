@@ -4,3 +4,4 @@ mod buffer;
 mod compositor;
 mod output;
 mod seat;
+mod selection;
@@ -13,5 +13,9 @@ impl SeatHandler for Aerugo {
 
     fn focus_changed(&mut self, _seat: &Seat<Self>, _focused: Option<&Self::KeyboardFocus>) {}
 
-    fn cursor_image(&mut self, _seat: &Seat<Self>, _image: CursorImageStatus) {}
+    fn cursor_image(&mut self, _seat: &Seat<Self>, image: CursorImageStatus) {
+        self.client_cursor_changed(image);
+    }
 }
+
+smithay::delegate_pointer_gestures!(Aerugo);
@@ -0,0 +1,101 @@
+//! Clipboard (`wl_data_device`) and primary selection (`zwp_primary_selection_device_v1`) handling.
+
+use std::os::fd::OwnedFd;
+
+use smithay::{
+    input::Seat,
+    reexports::wayland_protocols::wp::primary_selection::zv1::server::zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+    wayland::{
+        data_device::{self, ClientDndGrabHandler, DataDeviceHandler, DataDeviceState, ServerDndGrabHandler},
+        primary_selection::{self, PrimarySelectionHandler, PrimarySelectionState},
+    },
+};
+use wayland_server::{
+    protocol::{wl_data_source::WlDataSource, wl_surface::WlSurface},
+    Resource,
+};
+
+use crate::{
+    dnd::Drag,
+    selection::{SelectionContents, SelectionKind},
+    Aerugo, ClientData,
+};
+
+impl DataDeviceHandler for Aerugo {
+    type SelectionUserData = SelectionContents;
+
+    fn data_device_state(&self) -> &DataDeviceState {
+        &self.data_device_state
+    }
+
+    fn new_selection(&mut self, source: Option<WlDataSource>, _seat: Seat<Self>) {
+        let (mime_types, owner) = source
+            .map(|source| {
+                let mime_types = data_device::with_source_metadata(&source, |metadata| metadata.mime_types.clone())
+                    .unwrap_or_default();
+                (mime_types, source_owner(&source))
+            })
+            .unwrap_or_default();
+
+        self.selection_changed(SelectionKind::Clipboard, mime_types, owner);
+    }
+
+    fn send_selection(&mut self, mime_type: String, fd: OwnedFd, _seat: Seat<Self>, contents: &SelectionContents) {
+        contents.send(&mime_type, fd);
+    }
+}
+
+impl ClientDndGrabHandler for Aerugo {
+    fn started(&mut self, source: Option<WlDataSource>, icon: Option<WlSurface>, _seat: Seat<Self>) {
+        let (mime_types, owner) = source
+            .map(|source| {
+                let mime_types = data_device::with_source_metadata(&source, |metadata| metadata.mime_types.clone())
+                    .unwrap_or_default();
+                (mime_types, source_owner(&source))
+            })
+            .unwrap_or_default();
+
+        self.drag_started(Drag::new(icon, mime_types, owner));
+    }
+
+    fn dropped(&mut self, _seat: Seat<Self>) {
+        self.drag_ended();
+    }
+}
+
+impl ServerDndGrabHandler for Aerugo {}
+
+smithay::delegate_data_device!(Aerugo);
+
+impl PrimarySelectionHandler for Aerugo {
+    type SelectionUserData = SelectionContents;
+
+    fn primary_selection_state(&self) -> &PrimarySelectionState {
+        &self.primary_selection_state
+    }
+
+    fn new_selection(&mut self, source: Option<ZwpPrimarySelectionSourceV1>, _seat: Seat<Self>) {
+        let (mime_types, owner) = source
+            .map(|source| {
+                let mime_types =
+                    primary_selection::with_source_metadata(&source, |metadata| metadata.mime_types.clone())
+                        .unwrap_or_default();
+                (mime_types, source_owner(&source))
+            })
+            .unwrap_or_default();
+
+        self.selection_changed(SelectionKind::Primary, mime_types, owner);
+    }
+
+    fn send_selection(&mut self, mime_type: String, fd: OwnedFd, _seat: Seat<Self>, contents: &SelectionContents) {
+        contents.send(&mime_type, fd);
+    }
+}
+
+smithay::delegate_primary_selection!(Aerugo);
+
+/// The pid of the client which created the source.
+fn source_owner(source: &impl Resource) -> Option<i32> {
+    let client = source.client()?;
+    ClientData::get_data(&client)?.info.pid
+}
@@ -78,13 +78,15 @@ impl GlobalDispatch<ExtForeignToplevelListV1, ()> for Aerugo {
 impl Dispatch<ExtForeignToplevelListV1, ()> for Aerugo {
     fn request(
         state: &mut Self,
-        _client: &Client,
+        client: &Client,
         resource: &ExtForeignToplevelListV1,
         request: ext_foreign_toplevel_list_v1::Request,
         _: &(),
         _display: &DisplayHandle,
         _init: &mut DataInit<'_, Self>,
     ) {
+        state.log_request(client, resource, &request);
+
         // in tree generated protocol
         #[allow(unreachable_patterns)]
         match request {
@@ -112,13 +114,15 @@ impl Dispatch<ExtForeignToplevelListV1, ()> for Aerugo {
 impl Dispatch<ExtForeignToplevelHandleV1, ToplevelId> for Aerugo {
     fn request(
         state: &mut Self,
-        _client: &Client,
+        client: &Client,
         resource: &ExtForeignToplevelHandleV1,
         request: ext_foreign_toplevel_handle_v1::Request,
         id: &ToplevelId,
         _display: &DisplayHandle,
         _init: &mut DataInit<'_, Self>,
     ) {
+        state.log_request(client, resource, &request);
+
         // in tree generated protocol
         #[allow(unreachable_patterns)]
         match request {
@@ -1,3 +1,4 @@
 //! `ext` vendored wayland protocol implementations
 
 pub mod foreign_toplevel;
+pub mod session_lock;
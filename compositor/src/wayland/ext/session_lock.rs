@@ -0,0 +1,193 @@
+//! Implementation of the `ext-session-lock-v1` protocol.
+//!
+//! While the session is locked only the lock surface is drawn and only the screen locker receives input. The screen
+//! locker is told the session is locked once a frame without toplevels was presented. If the screen locker dies
+//! without unlocking the session, the session stays locked until another screen locker unlocks it.
+
+use smithay::{
+    reexports::wayland_protocols::ext::session_lock::v1::server::{
+        ext_session_lock_manager_v1::{self, ExtSessionLockManagerV1},
+        ext_session_lock_surface_v1::{self, ExtSessionLockSurfaceV1},
+        ext_session_lock_v1::{self, ExtSessionLockV1},
+    },
+    utils::SERIAL_COUNTER,
+    wayland::compositor,
+};
+use wayland_server::{
+    backend::ClientId, protocol::wl_surface::WlSurface, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
+    Resource,
+};
+
+use crate::{Aerugo, ClientData, PrivilegedGlobals};
+
+/// The role of a lock surface.
+const LOCK_SURFACE_ROLE: &str = "ext_session_lock_surface_v1";
+
+impl GlobalDispatch<ExtSessionLockManagerV1, ()> for Aerugo {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtSessionLockManagerV1>,
+        _global_data: &(),
+        init: &mut DataInit<'_, Self>,
+    ) {
+        init.init(resource, ());
+    }
+
+    fn can_view(client: Client, _global_data: &()) -> bool {
+        ClientData::get_data(&client)
+            .map(|data| data.is_visible(PrivilegedGlobals::SESSION_LOCK))
+            .unwrap_or(false)
+    }
+}
+
+impl Dispatch<ExtSessionLockManagerV1, ()> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &ExtSessionLockManagerV1,
+        request: ext_session_lock_manager_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        match request {
+            ext_session_lock_manager_v1::Request::Lock { id } => {
+                let lock = init.init(id, ());
+
+                // The screen locker is told the session is locked once a frame without toplevels was presented.
+                if !state.lock_session(lock.clone()) {
+                    lock.finished();
+                }
+            }
+
+            ext_session_lock_manager_v1::Request::Destroy => {
+                // Dispatch::destroyed handles cleanup
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ExtSessionLockV1, ()> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &ExtSessionLockV1,
+        request: ext_session_lock_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        let active = state.is_session_locker(resource);
+
+        match request {
+            ext_session_lock_v1::Request::GetLockSurface { id, surface, output: _ } => {
+                let lock_surface = init.init(id, surface.clone());
+
+                if !active {
+                    // The lock was finished, so the surface is never configured.
+                    return;
+                }
+
+                if state.session_lock.as_ref().is_some_and(|lock| lock.surface().is_some()) {
+                    // There is only one output, so a second lock surface is always for the same output.
+                    resource.post_error(
+                        ext_session_lock_v1::Error::DuplicateOutput,
+                        "the output already has a lock surface",
+                    );
+                    return;
+                }
+
+                if compositor::give_role(&surface, LOCK_SURFACE_ROLE).is_err() {
+                    resource.post_error(ext_session_lock_v1::Error::Role, "the surface already has a role");
+                    return;
+                }
+
+                let size = state
+                    .output
+                    .current_mode()
+                    .map(|mode| mode.size.to_logical(state.output.current_scale().integer_scale()))
+                    .unwrap_or_default();
+
+                lock_surface.configure(
+                    SERIAL_COUNTER.next_serial().into(),
+                    size.w.max(0) as u32,
+                    size.h.max(0) as u32,
+                );
+                state.set_lock_surface(Some(surface));
+            }
+
+            ext_session_lock_v1::Request::UnlockAndDestroy => {
+                if !active {
+                    resource.post_error(
+                        ext_session_lock_v1::Error::InvalidUnlock,
+                        "the session was not locked by this lock",
+                    );
+                    return;
+                }
+
+                state.unlock_session();
+            }
+
+            ext_session_lock_v1::Request::Destroy => {
+                if active {
+                    resource.post_error(
+                        ext_session_lock_v1::Error::InvalidDestroy,
+                        "the lock was destroyed without unlocking the session",
+                    );
+                }
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: &ExtSessionLockV1, _data: &()) {
+        // This is also called if the screen locker dies, in which case the session stays locked.
+        if state.is_session_locker(resource) {
+            state.session_locker_destroyed();
+        }
+    }
+}
+
+impl Dispatch<ExtSessionLockSurfaceV1, WlSurface> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &ExtSessionLockSurfaceV1,
+        request: ext_session_lock_surface_v1::Request,
+        _data: &WlSurface,
+        _display: &DisplayHandle,
+        _init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        match request {
+            // The lock surface always fills the output, so there is no state to apply once the configure is acked.
+            ext_session_lock_surface_v1::Request::AckConfigure { .. } => (),
+
+            ext_session_lock_surface_v1::Request::Destroy => {
+                // Dispatch::destroyed handles cleanup
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, _resource: &ExtSessionLockSurfaceV1, surface: &WlSurface) {
+        if state
+            .session_lock
+            .as_ref()
+            .is_some_and(|lock| lock.surface() == Some(surface))
+        {
+            state.set_lock_surface(None);
+        }
+    }
+}
@@ -7,9 +7,18 @@
 
 pub mod core;
 pub mod ext;
+pub mod wlr;
+pub mod wp;
 
+pub mod xdg_activation;
+pub mod xdg_decoration;
 pub mod xdg_shell;
 
 pub mod versions {
     pub const EXT_FOREIGN_TOPLEVEL_LIST_V1: u32 = 1;
+    pub const EXT_SESSION_LOCK_MANAGER_V1: u32 = 1;
+    pub const XDG_ACTIVATION_V1: u32 = 1;
+    pub const WP_COLOR_REPRESENTATION_MANAGER_V1: u32 = 1;
+    pub const ZWLR_INPUT_INHIBIT_MANAGER_V1: u32 = 1;
+    pub const ZWP_TEXT_INPUT_MANAGER_V3: u32 = 1;
 }
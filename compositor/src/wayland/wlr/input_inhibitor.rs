@@ -0,0 +1,94 @@
+//! Implementation of the legacy `zwlr_input_inhibitor_v1` protocol.
+//!
+//! Screen lockers should use `ext-session-lock-v1`, but some lockers have not migrated yet. The input inhibitor
+//! is implemented with an exclusive grab, which a session lock takes precedence over.
+
+use smithay::reexports::wayland_protocols_wlr::input_inhibitor::v1::server::{
+    zwlr_input_inhibit_manager_v1::{self, ZwlrInputInhibitManagerV1},
+    zwlr_input_inhibitor_v1::{self, ZwlrInputInhibitorV1},
+};
+use wayland_server::{backend::ClientId, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource};
+
+use crate::{
+    input::{ExclusiveGrab, ExclusiveGrabKind},
+    Aerugo, ClientData, PrivilegedGlobals,
+};
+
+impl GlobalDispatch<ZwlrInputInhibitManagerV1, ()> for Aerugo {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwlrInputInhibitManagerV1>,
+        _global_data: &(),
+        init: &mut DataInit<'_, Self>,
+    ) {
+        init.init(resource, ());
+    }
+
+    fn can_view(client: Client, _global_data: &()) -> bool {
+        // The input inhibitor is only used by screen lockers, so treat it the same as the session lock.
+        ClientData::get_data(&client)
+            .map(|data| data.is_visible(PrivilegedGlobals::SESSION_LOCK))
+            .unwrap_or(false)
+    }
+}
+
+impl Dispatch<ZwlrInputInhibitManagerV1, ()> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &ZwlrInputInhibitManagerV1,
+        request: zwlr_input_inhibit_manager_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        match request {
+            zwlr_input_inhibit_manager_v1::Request::GetInhibitor { id } => {
+                // The inhibitor must be initialized even if the grab fails.
+                init.init(id, ());
+
+                let grab = ExclusiveGrab::new(client.id(), ExclusiveGrabKind::InputInhibitor);
+
+                if !state.set_exclusive_grab(grab) {
+                    resource.post_error(
+                        zwlr_input_inhibit_manager_v1::Error::AlreadyInhibited,
+                        "an input inhibitor is already active",
+                    );
+                }
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ZwlrInputInhibitorV1, ()> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &ZwlrInputInhibitorV1,
+        request: zwlr_input_inhibitor_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        _init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        match request {
+            zwlr_input_inhibitor_v1::Request::Destroy => {
+                // Dispatch::destroyed handles cleanup
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, client: ClientId, _resource: &ZwlrInputInhibitorV1, _data: &()) {
+        // This is also called if the client disconnects, so the grab cannot outlive the client.
+        state.release_exclusive_grab(&client, ExclusiveGrabKind::InputInhibitor);
+    }
+}
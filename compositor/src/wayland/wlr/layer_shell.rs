@@ -0,0 +1,38 @@
+//! Implementation of the `zwlr_layer_shell_v1` protocol.
+//!
+//! Layer surfaces are configured by the wm, see [`crate::layer_shell`].
+
+use smithay::{
+    delegate_layer_shell,
+    wayland::shell::{
+        wlr_layer::{Layer, LayerSurface, LayerSurfaceConfigure, WlrLayerShellHandler, WlrLayerShellState},
+        xdg::PopupSurface,
+    },
+};
+use wayland_server::protocol::{wl_output::WlOutput, wl_surface::WlSurface};
+
+use crate::Aerugo;
+
+impl WlrLayerShellHandler for Aerugo {
+    fn shell_state(&mut self) -> &mut WlrLayerShellState {
+        &mut self.wlr_layer_shell
+    }
+
+    fn new_layer_surface(&mut self, surface: LayerSurface, _output: Option<WlOutput>, layer: Layer, namespace: String) {
+        self.layer_surface_created(surface, layer, namespace);
+    }
+
+    fn new_popup(&mut self, _parent: LayerSurface, popup: PopupSurface) {
+        self.popup_parent_set(&popup);
+    }
+
+    fn ack_configure(&mut self, surface: WlSurface, configure: LayerSurfaceConfigure) {
+        self.layer_surface_acked(&surface, configure.serial);
+    }
+
+    fn layer_destroyed(&mut self, surface: LayerSurface) {
+        self.layer_surface_destroyed(surface.wl_surface());
+    }
+}
+
+delegate_layer_shell!(Aerugo);
@@ -0,0 +1,4 @@
+//! `wlr` protocol implementations
+
+pub mod input_inhibitor;
+pub mod layer_shell;
@@ -0,0 +1,171 @@
+//! Implementation of the `color-representation-v1` protocol.
+//!
+//! Only the alpha mode is supported, so clients may declare that their buffers have straight alpha. Buffers are
+//! drawn as RGB, so the only supported coefficients are identity with full range. Optical and electrical
+//! premultiplied alpha are both blended as electrical premultiplied alpha, since the display server blends without
+//! linearizing.
+
+// in tree generated protocol
+#![allow(non_upper_case_globals, non_camel_case_types)]
+
+use smithay::reexports::wayland_server;
+use wayland_server::{
+    backend::ClientId, protocol::wl_surface::WlSurface, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
+    Resource, WEnum,
+};
+
+use crate::{
+    alpha::{self, AlphaMode},
+    Aerugo,
+};
+
+use self::{
+    wp_color_representation_manager_v1::WpColorRepresentationManagerV1,
+    wp_color_representation_surface_v1::{
+        AlphaMode as WpAlphaMode, Coefficients, Range, WpColorRepresentationSurfaceV1,
+    },
+};
+
+#[allow(non_upper_case_globals)]
+pub mod __interfaces {
+    use smithay::reexports::wayland_server::backend as wayland_backend;
+    wayland_scanner::generate_interfaces!("../protocols/color-representation-v1.xml");
+}
+use self::__interfaces::*;
+
+wayland_scanner::generate_server_code!("../protocols/color-representation-v1.xml");
+
+impl GlobalDispatch<WpColorRepresentationManagerV1, ()> for Aerugo {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<WpColorRepresentationManagerV1>,
+        _global_data: &(),
+        init: &mut DataInit<'_, Self>,
+    ) {
+        let manager = init.init(resource, ());
+
+        for mode in [
+            WpAlphaMode::PremultipliedElectrical,
+            WpAlphaMode::PremultipliedOptical,
+            WpAlphaMode::Straight,
+        ] {
+            manager.supported_alpha_mode(mode);
+        }
+
+        manager.supported_coefficients_and_ranges(Coefficients::Identity, Range::Full);
+        manager.done();
+    }
+}
+
+impl Dispatch<WpColorRepresentationManagerV1, ()> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &WpColorRepresentationManagerV1,
+        request: wp_color_representation_manager_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        match request {
+            wp_color_representation_manager_v1::Request::GetSurface { id, surface } => {
+                if !alpha::claim_alpha_mode(&surface) {
+                    resource.post_error(
+                        wp_color_representation_manager_v1::Error::SurfaceExists,
+                        "the surface already has a color representation object",
+                    );
+                    return;
+                }
+
+                init.init(id, surface);
+            }
+
+            wp_color_representation_manager_v1::Request::Destroy => {
+                // Dispatch::destroyed handles cleanup
+            }
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<WpColorRepresentationSurfaceV1, WlSurface> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &WpColorRepresentationSurfaceV1,
+        request: wp_color_representation_surface_v1::Request,
+        surface: &WlSurface,
+        _display: &DisplayHandle,
+        _init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        if !surface.is_alive() && !matches!(request, wp_color_representation_surface_v1::Request::Destroy) {
+            resource.post_error(
+                wp_color_representation_surface_v1::Error::Inert,
+                "the surface was destroyed",
+            );
+            return;
+        }
+
+        match request {
+            wp_color_representation_surface_v1::Request::SetAlphaMode { alpha_mode } => {
+                let mode = match alpha_mode {
+                    WEnum::Value(WpAlphaMode::PremultipliedElectrical | WpAlphaMode::PremultipliedOptical) => {
+                        AlphaMode::Premultiplied
+                    }
+                    WEnum::Value(WpAlphaMode::Straight) => AlphaMode::Straight,
+                    _ => {
+                        resource.post_error(
+                            wp_color_representation_surface_v1::Error::AlphaMode,
+                            "unsupported alpha mode",
+                        );
+                        return;
+                    }
+                };
+
+                alpha::set_pending_alpha_mode(surface, Some(mode));
+            }
+
+            wp_color_representation_surface_v1::Request::SetCoefficientsAndRange { coefficients, range } => {
+                if !matches!(
+                    (coefficients, range),
+                    (WEnum::Value(Coefficients::Identity), WEnum::Value(Range::Full))
+                ) {
+                    resource.post_error(
+                        wp_color_representation_surface_v1::Error::Coefficients,
+                        "only identity coefficients with full range are supported",
+                    );
+                }
+            }
+
+            // Buffers with subsampled chroma are not supported, so the chroma location has no effect.
+            wp_color_representation_surface_v1::Request::SetChromaLocation { .. } => (),
+
+            wp_color_representation_surface_v1::Request::Destroy => {
+                // The alpha mode is unset on the next commit.
+                if surface.is_alive() {
+                    alpha::set_pending_alpha_mode(surface, None);
+                }
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(
+        _state: &mut Self,
+        _client: ClientId,
+        _resource: &WpColorRepresentationSurfaceV1,
+        surface: &WlSurface,
+    ) {
+        if surface.is_alive() {
+            alpha::release_alpha_mode(surface);
+        }
+    }
+}
@@ -0,0 +1,5 @@
+//! `wp` protocol implementations
+
+pub mod color_representation;
+pub mod security_context;
+pub mod text_input;
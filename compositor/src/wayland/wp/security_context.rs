@@ -0,0 +1,39 @@
+//! Implementation of the `wp_security_context_v1` protocol.
+//!
+//! Sandbox engines such as Flatpak create a listening socket for each sandboxed app. Clients connecting through the
+//! socket are tagged with the sandbox engine, which the global policy may match, and cannot see the protocol to
+//! create security contexts of their own.
+
+use std::os::unix::net::UnixStream;
+
+use smithay::wayland::security_context::{SecurityContext, SecurityContextHandler, SecurityContextListenerSource};
+
+use crate::{policy::ClientInfo, Aerugo, Loop};
+
+impl SecurityContextHandler for Aerugo {
+    fn context_created(&mut self, source: SecurityContextListenerSource, context: SecurityContext) {
+        tracing::debug!(sandbox_engine = ?context.sandbox_engine, app_id = ?context.app_id, "Security context created");
+
+        let inserted = self.loop_handle.insert_source(source, move |client, _, state| {
+            state.insert_sandboxed_client(client, &context);
+        });
+
+        if let Err(err) = inserted {
+            tracing::warn!(err = %err.error, "Failed to listen on the socket of a security context");
+        }
+    }
+}
+
+smithay::delegate_security_context!(Aerugo);
+
+impl Loop {
+    /// Add a client connected through the socket of a security context.
+    fn insert_sandboxed_client(&mut self, client: UnixStream, context: &SecurityContext) {
+        let client_info = ClientInfo {
+            sandbox_engine: context.sandbox_engine.clone(),
+            ..ClientInfo::from_socket(&client)
+        };
+
+        self.insert_client_with_info(client, client_info, true);
+    }
+}
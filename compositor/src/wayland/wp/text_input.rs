@@ -0,0 +1,214 @@
+//! Implementation of the `zwp_text_input_v3` protocol.
+//!
+//! There is no input method yet, so text inputs never receive text. The cursor rectangle of the text input of the
+//! surface with keyboard focus is the caret zoom follows.
+
+use std::sync::Mutex;
+
+use smithay::{
+    reexports::wayland_protocols::wp::text_input::zv3::server::{
+        zwp_text_input_manager_v3::{self, ZwpTextInputManagerV3},
+        zwp_text_input_v3::{self, ZwpTextInputV3},
+    },
+    utils::{Logical, Rectangle},
+};
+use wayland_server::{
+    backend::ClientId, protocol::wl_surface::WlSurface, Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New,
+    Resource,
+};
+
+use crate::Aerugo;
+
+/// The text inputs of clients and the surface they are entered on.
+#[derive(Debug, Default)]
+pub struct TextInputs {
+    instances: Vec<ZwpTextInputV3>,
+    focus: Option<WlSurface>,
+}
+
+/// The double-buffered state of a text input.
+#[derive(Debug, Default)]
+pub struct TextInputData {
+    pending: Mutex<TextInputState>,
+    current: Mutex<TextInputState>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct TextInputState {
+    enabled: bool,
+    /// The cursor rectangle relative to the focused surface.
+    cursor: Option<Rectangle<i32, Logical>>,
+}
+
+impl TextInputs {
+    /// The text inputs of the client owning the surface.
+    fn of_surface<'a>(&'a self, surface: &'a WlSurface) -> impl Iterator<Item = &'a ZwpTextInputV3> {
+        self.instances
+            .iter()
+            .filter(move |instance| instance.id().same_client_as(&surface.id()))
+    }
+}
+
+impl Aerugo {
+    /// Move the text inputs to the surface with keyboard focus.
+    pub(crate) fn set_text_input_focus(&mut self, surface: Option<&WlSurface>) {
+        if self.text_inputs.focus.as_ref() == surface {
+            return;
+        }
+
+        if let Some(previous) = self.text_inputs.focus.take() {
+            for instance in self.text_inputs.of_surface(&previous) {
+                instance.leave(&previous);
+            }
+        }
+
+        if let Some(surface) = surface {
+            for instance in self.text_inputs.of_surface(surface) {
+                instance.enter(surface);
+            }
+        }
+
+        self.text_inputs.focus = surface.cloned();
+        self.zoom.caret_moved(None);
+    }
+
+    /// The client committed the state of a text input.
+    fn text_input_committed(&mut self, instance: &ZwpTextInputV3, state: TextInputState) {
+        let Some(focus) = &self.text_inputs.focus else {
+            return;
+        };
+
+        if !instance.id().same_client_as(&focus.id()) {
+            return;
+        }
+
+        // The scene is scaled to the output when it is drawn, like the focus zoom follows.
+        let caret = state
+            .cursor
+            .filter(|_| state.enabled)
+            .zip(self.scene.get_surface_tree_index(focus.clone()))
+            .and_then(|(cursor, index)| {
+                let geometry = self.scene.surface_tree_geometry(index)?;
+                let location = geometry.loc.to_f64().to_logical(1.0);
+                Some(Rectangle::from_loc_and_size(
+                    location + cursor.loc.to_f64(),
+                    cursor.size.to_f64(),
+                ))
+            });
+
+        let viewport = self.zoom.viewport();
+        self.zoom.caret_moved(caret);
+
+        if self.zoom.viewport() != viewport {
+            self.scene_changed();
+        }
+    }
+}
+
+impl GlobalDispatch<ZwpTextInputManagerV3, ()> for Aerugo {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<ZwpTextInputManagerV3>,
+        _global_data: &(),
+        init: &mut DataInit<'_, Self>,
+    ) {
+        init.init(resource, ());
+    }
+}
+
+impl Dispatch<ZwpTextInputManagerV3, ()> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &ZwpTextInputManagerV3,
+        request: zwp_text_input_manager_v3::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        match request {
+            zwp_text_input_manager_v3::Request::GetTextInput { id, seat: _ } => {
+                // There is a single seat, so every text input belongs to it.
+                let instance = init.init(id, TextInputData::default());
+
+                if let Some(focus) = &state.text_inputs.focus {
+                    if instance.id().same_client_as(&focus.id()) {
+                        instance.enter(focus);
+                    }
+                }
+
+                state.text_inputs.instances.push(instance);
+            }
+
+            zwp_text_input_manager_v3::Request::Destroy => (),
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<ZwpTextInputV3, TextInputData> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &ZwpTextInputV3,
+        request: zwp_text_input_v3::Request,
+        data: &TextInputData,
+        _display: &DisplayHandle,
+        _init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        match request {
+            zwp_text_input_v3::Request::Enable => {
+                // Enabling resets the state of the text input.
+                *data.pending.lock().unwrap() = TextInputState {
+                    enabled: true,
+                    cursor: None,
+                };
+            }
+
+            zwp_text_input_v3::Request::Disable => data.pending.lock().unwrap().enabled = false,
+
+            zwp_text_input_v3::Request::SetCursorRectangle { x, y, width, height } => {
+                data.pending.lock().unwrap().cursor = Some(Rectangle::from_loc_and_size((x, y), (width, height)));
+            }
+
+            zwp_text_input_v3::Request::Commit => {
+                let pending = *data.pending.lock().unwrap();
+                *data.current.lock().unwrap() = pending;
+                state.text_input_committed(resource, pending);
+            }
+
+            // The surrounding text and content type are only used by input methods.
+            zwp_text_input_v3::Request::SetSurroundingText { .. }
+            | zwp_text_input_v3::Request::SetTextChangeCause { .. }
+            | zwp_text_input_v3::Request::SetContentType { .. } => (),
+
+            zwp_text_input_v3::Request::Destroy => {
+                // Dispatch::destroyed handles cleanup
+            }
+
+            _ => unreachable!(),
+        }
+    }
+
+    fn destroyed(state: &mut Self, _client: ClientId, resource: &ZwpTextInputV3, data: &TextInputData) {
+        state.text_inputs.instances.retain(|instance| instance != resource);
+
+        let was_enabled = data.current.lock().unwrap().enabled;
+        let focused = state
+            .text_inputs
+            .focus
+            .as_ref()
+            .is_some_and(|focus| resource.id().same_client_as(&focus.id()));
+
+        if was_enabled && focused {
+            state.zoom.caret_moved(None);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Implementation of the `xdg_activation_v1` protocol.
+//!
+//! Tokens are kept in [`ActivationTokens`], which also holds the tokens created by the wm, so an app spawned by the
+//! wm may activate its toplevel with the token passed in its environment.
+
+use std::{sync::Mutex, time::Instant};
+
+use smithay::{
+    input::Seat,
+    reexports::wayland_protocols::xdg::activation::v1::server::{
+        xdg_activation_token_v1::{self, XdgActivationTokenV1},
+        xdg_activation_v1::{self, XdgActivationV1},
+    },
+    utils::Serial,
+};
+use wayland_server::{
+    protocol::{wl_seat::WlSeat, wl_surface::WlSurface},
+    Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::{
+    activation::{TokenData, TokenOrigin},
+    shell::Shell,
+    Aerugo,
+};
+
+/// The state of a token before it is committed.
+#[derive(Debug, Default)]
+pub struct PendingToken {
+    /// The serial of the input event the token was created for, and the seat of the event.
+    serial: Option<(Serial, WlSeat)>,
+    app_id: Option<String>,
+    surface: Option<WlSurface>,
+    committed: bool,
+}
+
+impl GlobalDispatch<XdgActivationV1, ()> for Aerugo {
+    fn bind(
+        _state: &mut Self,
+        _display: &DisplayHandle,
+        _client: &Client,
+        resource: New<XdgActivationV1>,
+        _global_data: &(),
+        init: &mut DataInit<'_, Self>,
+    ) {
+        init.init(resource, ());
+    }
+}
+
+impl Dispatch<XdgActivationV1, ()> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &XdgActivationV1,
+        request: xdg_activation_v1::Request,
+        _data: &(),
+        _display: &DisplayHandle,
+        init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        match request {
+            xdg_activation_v1::Request::GetActivationToken { id } => {
+                init.init(id, Mutex::new(PendingToken::default()));
+            }
+
+            xdg_activation_v1::Request::Activate { token, surface } => {
+                // Only toplevels may be activated.
+                let Some(toplevel) = Shell::get_toplevel_id(&surface) else {
+                    return;
+                };
+
+                match state.activation_tokens.take(&token, Instant::now()) {
+                    Some(data) => state.activation_requested(toplevel, data),
+                    None => tracing::debug!(toplevel, "Activation requested with an invalid or expired token"),
+                }
+            }
+
+            xdg_activation_v1::Request::Destroy => {}
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Dispatch<XdgActivationTokenV1, Mutex<PendingToken>> for Aerugo {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &XdgActivationTokenV1,
+        request: xdg_activation_token_v1::Request,
+        data: &Mutex<PendingToken>,
+        _display: &DisplayHandle,
+        _init: &mut DataInit<'_, Self>,
+    ) {
+        state.log_request(client, resource, &request);
+
+        let mut pending = data.lock().unwrap();
+
+        if pending.committed && !matches!(request, xdg_activation_token_v1::Request::Destroy) {
+            resource.post_error(
+                xdg_activation_token_v1::Error::AlreadyUsed,
+                "the activation token was already committed",
+            );
+            return;
+        }
+
+        match request {
+            xdg_activation_token_v1::Request::SetSerial { serial, seat } => {
+                pending.serial = Some((Serial::from(serial), seat));
+            }
+
+            xdg_activation_token_v1::Request::SetAppId { app_id } => pending.app_id = Some(app_id),
+
+            xdg_activation_token_v1::Request::SetSurface { surface } => pending.surface = Some(surface),
+
+            xdg_activation_token_v1::Request::Commit => {
+                pending.committed = true;
+
+                let focused = pending
+                    .surface
+                    .as_ref()
+                    .and_then(Shell::get_toplevel_id)
+                    .is_some_and(|toplevel| state.keyboard_focus == Some(toplevel));
+
+                let from_input = pending
+                    .serial
+                    .take()
+                    .is_some_and(|(serial, seat)| state.is_recent_input(serial, &seat));
+
+                let data = TokenData {
+                    app_id: pending.app_id.take(),
+                    origin: TokenOrigin::Client { from_input, focused },
+                    created: Instant::now(),
+                };
+
+                match state.activation_tokens.create(data) {
+                    Ok(token) => resource.done(token),
+                    Err(err) => tracing::error!(%err, "Failed to create activation token"),
+                }
+            }
+
+            xdg_activation_token_v1::Request::Destroy => {}
+
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Aerugo {
+    /// Whether the serial belongs to an input event sent since the keyboard last entered a surface.
+    ///
+    /// Input sent before the keyboard focus last changed, possibly to another client, does not count.
+    fn is_recent_input(&self, serial: Serial, seat: &WlSeat) -> bool {
+        if Seat::<Self>::from_resource(seat).as_ref() != Some(&self.seat) {
+            return false;
+        }
+
+        self.seat
+            .get_keyboard()
+            .and_then(|keyboard| keyboard.last_enter())
+            .is_some_and(|enter| serial.is_no_older_than(&enter))
+    }
+}
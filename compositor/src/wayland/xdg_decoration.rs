@@ -0,0 +1,28 @@
+//! Implementation of the `zxdg_decoration_manager_v1` protocol.
+//!
+//! The preference of the client is forwarded to the wm, which decides the decoration mode with a configure.
+
+use smithay::{
+    reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode,
+    wayland::shell::xdg::{decoration::XdgDecorationHandler, ToplevelSurface},
+};
+
+use crate::Aerugo;
+
+impl XdgDecorationHandler for Aerugo {
+    fn new_decoration(&mut self, toplevel: ToplevelSurface) {
+        // The client draws its own decorations until the wm decides otherwise. The mode is sent with the initial
+        // configure.
+        toplevel.with_pending_state(|state| state.decoration_mode = Some(Mode::ClientSide));
+    }
+
+    fn request_mode(&mut self, toplevel: ToplevelSurface, mode: Mode) {
+        self.decoration_mode_requested(toplevel.wl_surface(), Some(mode));
+    }
+
+    fn unset_mode(&mut self, toplevel: ToplevelSurface) {
+        self.decoration_mode_requested(toplevel.wl_surface(), None);
+    }
+}
+
+smithay::delegate_xdg_decoration!(Aerugo);
@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use smithay::{
     reexports::wayland_protocols::xdg::shell::server::xdg_toplevel,
     utils::{Logical, Point, Serial},
@@ -6,62 +8,70 @@ use smithay::{
     },
 };
 use wayland_server::protocol::{wl_output, wl_seat, wl_surface};
+use wm_runtime::types::StateRequest;
 
-use crate::{shell::Shell, Aerugo};
+use crate::{interactive, shell::Shell, Aerugo};
 
 impl XdgShellHandler for Aerugo {
     fn xdg_shell_state(&mut self) -> &mut XdgShellState {
         &mut self.xdg_shell
     }
 
-    fn new_client(&mut self, _client: ShellClient) {}
+    fn new_client(&mut self, client: ShellClient) {
+        self.ping_watchdog.add(client, Instant::now());
+    }
 
-    fn client_pong(&mut self, _client: ShellClient) {}
+    fn client_pong(&mut self, client: ShellClient) {
+        self.ping_answered(&client);
+    }
 
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
         self.shell.pending_toplevels.push(surface);
     }
 
-    fn new_popup(&mut self, _surface: PopupSurface, _positioner: PositionerState) {
-        // TODO: track popups
+    fn new_popup(&mut self, surface: PopupSurface, _positioner: PositionerState) {
+        self.popup_created(surface);
     }
 
-    fn move_request(&mut self, _surface: ToplevelSurface, _seat: wl_seat::WlSeat, _serial: Serial) {
-        // TODO: Forward to wm
+    fn move_request(&mut self, surface: ToplevelSurface, seat: wl_seat::WlSeat, serial: Serial) {
+        self.interactive_grab_requested(surface.wl_surface(), &seat, serial, None);
     }
 
     fn resize_request(
         &mut self,
-        _surface: ToplevelSurface,
-        _seat: wl_seat::WlSeat,
-        _serial: Serial,
-        _edges: xdg_toplevel::ResizeEdge,
+        surface: ToplevelSurface,
+        seat: wl_seat::WlSeat,
+        serial: Serial,
+        edges: xdg_toplevel::ResizeEdge,
     ) {
-        // TODO: forward to wm
+        // A resize by no edge is not a resize.
+        if let Some(edge) = interactive::wm_resize_edge(edges) {
+            self.interactive_grab_requested(surface.wl_surface(), &seat, serial, Some(edge));
+        }
     }
 
     fn grab(&mut self, _surface: PopupSurface, _seat: wl_seat::WlSeat, _serial: Serial) {
         // TODO
     }
 
-    fn maximize_request(&mut self, _surface: ToplevelSurface) {
-        // TODO: forward to wm
+    fn maximize_request(&mut self, surface: ToplevelSurface) {
+        self.toplevel_state_requested(surface.wl_surface(), StateRequest::Maximize, None);
     }
 
-    fn unmaximize_request(&mut self, _surface: ToplevelSurface) {
-        // TODO: forward to wm
+    fn unmaximize_request(&mut self, surface: ToplevelSurface) {
+        self.toplevel_state_requested(surface.wl_surface(), StateRequest::Unmaximize, None);
     }
 
-    fn fullscreen_request(&mut self, _surface: ToplevelSurface, _output: Option<wl_output::WlOutput>) {
-        // TODO: forward to wm
+    fn fullscreen_request(&mut self, surface: ToplevelSurface, output: Option<wl_output::WlOutput>) {
+        self.toplevel_state_requested(surface.wl_surface(), StateRequest::Fullscreen, output.as_ref());
     }
 
-    fn unfullscreen_request(&mut self, _surface: ToplevelSurface) {
-        // TODO: forward to wm
+    fn unfullscreen_request(&mut self, surface: ToplevelSurface) {
+        self.toplevel_state_requested(surface.wl_surface(), StateRequest::Unfullscreen, None);
     }
 
-    fn minimize_request(&mut self, _surface: ToplevelSurface) {
-        // TODO: forward to wm
+    fn minimize_request(&mut self, surface: ToplevelSurface) {
+        self.toplevel_state_requested(surface.wl_surface(), StateRequest::Minimize, None);
     }
 
     fn show_window_menu(
@@ -74,20 +84,24 @@ impl XdgShellHandler for Aerugo {
         // TODO: Forward to wm
     }
 
-    fn ack_configure(&mut self, _surface: wl_surface::WlSurface, _configure: Configure) {
+    fn ack_configure(&mut self, surface: wl_surface::WlSurface, configure: Configure) {
+        if let (Some(id), Configure::Toplevel(configure)) = (Shell::get_toplevel_id(&surface), configure) {
+            self.configure_acked(id, configure.serial);
+        }
+
         // TODO: Notify wm about current window state
     }
 
-    fn reposition_request(&mut self, _surface: PopupSurface, _positioner: PositionerState, _token: u32) {
-        // TODO: forward to wm
+    fn reposition_request(&mut self, surface: PopupSurface, positioner: PositionerState, token: u32) {
+        self.popup_reposition_requested(surface, positioner, token);
     }
 
     fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
         Shell::remove_toplevel(self, surface.wl_surface());
     }
 
-    fn popup_destroyed(&mut self, _surface: PopupSurface) {
-        // TODO: Handle popup death
+    fn popup_destroyed(&mut self, surface: PopupSurface) {
+        Aerugo::popup_destroyed(self, surface.wl_surface());
     }
 }
 
@@ -0,0 +1,1545 @@
+//! The wm runtime in the display server
+//!
+//! The wm runs on the thread of the wm runtime. The display server tells the wm about the seat, outputs and
+//! toplevels with [`WmEvent`]s and applies the [`WmRequest`]s of the wm to the shell and the scene.
+//!
+//! Both sides refer to objects by [`Id`]. The display server allocates the ids of the objects it tells the wm about,
+//! while the wm runtime allocates the ids of the objects the wm creates, such as workspaces and scene nodes.
+
+use std::{error::Error, fs, io::Write, num::NonZeroU32, path::PathBuf, time::Instant};
+
+use calloop::channel::Sender;
+use rustc_hash::FxHashMap;
+use smithay::{
+    input::keyboard::XkbConfig,
+    output::{self, Mode, Output},
+    reexports::wayland_protocols::xdg::{
+        decoration::zv1::server::zxdg_toplevel_decoration_v1, shell::server::xdg_toplevel,
+    },
+    utils::{Logical, Physical, Point, Rectangle, Serial, Size, Transform},
+    wayland::{
+        compositor,
+        shell::xdg::{SurfaceCachedState, ToplevelStateSet},
+    },
+};
+use wayland_server::{
+    protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+    Resource,
+};
+use wm_runtime::{
+    types::{self, ActivationTokenInfo, Features, Geometry, SeatCapabilities, Subpixel},
+    ConfigureUpdate, Id, IdType, NodeKind, OutputConfiguration, OutputInfo, RuntimeMessage, SnapshotInfo,
+    SurfaceNodeInfo, ToplevelConfiguration, ToplevelUpdate, WmCursor, WmEvent, WmRequest, WmRuntime,
+};
+
+use crate::{
+    activation::{TokenData, TokenOrigin},
+    cursor::{CursorImage, CursorOverride, CursorShape},
+    decorations::{self, DecorationFill, ServerDecorations},
+    group::{TabLayout, ToplevelGroupId},
+    ipc,
+    output_config::OutputConfig,
+    scene::{NodeIndex, RenderProperties, Shape},
+    selection::{SelectionContents, SelectionKind},
+    shell::{self, Shell, Toplevel, ToplevelId},
+    thumbnail::Thumbnail,
+    vrr,
+    wm_scene::WmNodeKind,
+    workspace::WorkspaceId,
+    zoom::FollowMode,
+    Aerugo, Loop,
+};
+
+/// The wm as seen by the display server.
+#[derive(Debug)]
+pub struct Wm {
+    /// The wm module, loaded again when the wm is reloaded.
+    path: Option<PathBuf>,
+
+    /// Sends events to the wm runtime, or [`None`] if no wm is running.
+    sender: Option<Sender<WmEvent>>,
+
+    next_id: NonZeroU32,
+    seat: Id,
+    output: Id,
+
+    /// What the wm was told about each toplevel.
+    toplevels: FxHashMap<ToplevelId, WmToplevel>,
+
+    /// The toplevel of each id the wm knows.
+    toplevel_ids: FxHashMap<Id, ToplevelId>,
+
+    /// The configures of the wm which were sent to each toplevel but not acked yet, as the serial of the configure
+    /// and the serial of the wm.
+    configures: FxHashMap<ToplevelId, Vec<(Serial, u32)>>,
+
+    /// The workspaces created by the wm.
+    workspaces: FxHashMap<Id, WorkspaceId>,
+
+    /// The toplevel groups created by the wm.
+    groups: FxHashMap<Id, ToplevelGroupId>,
+
+    /// The snapshots owned by the wm, until the wm drops them.
+    snapshots: FxHashMap<Id, Thumbnail>,
+
+    /// The size of each toplevel when its last commit was sent with a snapshot.
+    committed_sizes: FxHashMap<ToplevelId, Size<i32, Physical>>,
+}
+
+impl Wm {
+    pub fn new() -> Self {
+        let mut next_id = NonZeroU32::MIN;
+        let seat = alloc_id(&mut next_id, IdType::Seat);
+        let output = alloc_id(&mut next_id, IdType::Output);
+
+        Self {
+            path: None,
+            sender: None,
+            next_id,
+            seat,
+            output,
+            toplevels: FxHashMap::default(),
+            toplevel_ids: FxHashMap::default(),
+            configures: FxHashMap::default(),
+            workspaces: FxHashMap::default(),
+            groups: FxHashMap::default(),
+            snapshots: FxHashMap::default(),
+            committed_sizes: FxHashMap::default(),
+        }
+    }
+
+    /// Whether a wm runtime is running, even if the wm itself crashed.
+    pub fn is_running(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// The id of the seat known to the wm.
+    pub fn seat(&self) -> Id {
+        self.seat
+    }
+
+    /// The id of the output known to the wm.
+    pub fn output(&self) -> Id {
+        self.output
+    }
+
+    /// The id of the toplevel known to the wm.
+    pub fn toplevel_id(&self, toplevel: ToplevelId) -> Option<Id> {
+        self.toplevels.get(&toplevel).map(|toplevel| toplevel.id)
+    }
+
+    /// The toplevel with the id the wm knows.
+    pub fn toplevel(&self, id: Id) -> Option<ToplevelId> {
+        self.toplevel_ids.get(&id).copied()
+    }
+
+    /// The id of the workspace known to the wm.
+    pub fn workspace_id(&self, workspace: WorkspaceId) -> Option<Id> {
+        self.workspaces
+            .iter()
+            .find(|(_, &id)| id == workspace)
+            .map(|(&wm_id, _)| wm_id)
+    }
+
+    /// Allocate the id of an object the wm is told about.
+    pub(crate) fn alloc_id(&mut self, ty: IdType) -> Id {
+        alloc_id(&mut self.next_id, ty)
+    }
+
+    /// Forget the objects the previous wm runtime was told about, since a new wm runtime is told about every object
+    /// again.
+    fn reset(&mut self, sender: Sender<WmEvent>) {
+        self.sender = Some(sender);
+        self.toplevels.clear();
+        self.toplevel_ids.clear();
+        self.configures.clear();
+        self.workspaces.clear();
+        self.groups.clear();
+        self.snapshots.clear();
+        self.committed_sizes.clear();
+    }
+}
+
+impl Default for Wm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Allocate an id of an object the display server tells the wm about.
+///
+/// Ids are never reused, so a request of the wm about a closed toplevel never applies to another toplevel.
+fn alloc_id(next_id: &mut NonZeroU32, ty: IdType) -> Id {
+    let id = Id::server(*next_id, ty).expect("the display server ran out of wm ids");
+    *next_id = next_id.checked_add(1).expect("u32 overflow (unlikely)");
+    id
+}
+
+/// What the wm was told about a toplevel, so only changes are sent after a commit.
+#[derive(Debug, Clone, PartialEq)]
+struct WmToplevel {
+    id: Id,
+    app_id: Option<String>,
+    title: Option<String>,
+    min_size: Size<i32, Logical>,
+    max_size: Size<i32, Logical>,
+    parent: Option<ToplevelId>,
+    /// The states the client acked.
+    state: types::ToplevelState,
+    preferred_decorations: Option<zxdg_toplevel_decoration_v1::Mode>,
+    prefers_variable_refresh: bool,
+}
+
+impl WmToplevel {
+    fn new(id: Id, toplevel: &Toplevel) -> Self {
+        let surface = toplevel.wl_surface();
+        let (min_size, max_size) = surface.as_ref().map_or_else(Default::default, |surface| {
+            compositor::with_states(surface, |states| {
+                let cached = states.cached_state.current::<SurfaceCachedState>();
+                (cached.min_size, cached.max_size)
+            })
+        });
+
+        Self {
+            id,
+            app_id: toplevel.app_id(),
+            title: toplevel.title(),
+            min_size,
+            max_size,
+            parent: toplevel.parent(),
+            state: toplevel
+                .xdg_toplevel()
+                .map_or(types::ToplevelState::empty(), |toplevel| {
+                    wm_toplevel_state(&toplevel.current_state().states)
+                }),
+            preferred_decorations: surface.as_ref().and_then(decorations::preferred_decoration_mode),
+            prefers_variable_refresh: surface.as_ref().is_some_and(vrr::surface_prefers_variable_refresh),
+        }
+    }
+
+    /// The update telling the wm what changed since the previous state, or every property if the wm was not told
+    /// about the toplevel yet.
+    fn update(&self, previous: Option<&Self>, parent: Option<Id>) -> ToplevelUpdate {
+        let mut update = ToplevelUpdate::default();
+
+        if previous.map(|previous| &previous.app_id) != Some(&self.app_id) {
+            update.app_id = self.app_id.clone();
+        }
+
+        if previous.map(|previous| &previous.title) != Some(&self.title) {
+            update.title = self.title.clone();
+        }
+
+        if previous.map(|previous| previous.min_size) != Some(self.min_size) {
+            update.min_size = ConfigureUpdate::Update(wm_size(self.min_size));
+        }
+
+        if previous.map(|previous| previous.max_size) != Some(self.max_size) {
+            update.max_size = ConfigureUpdate::Update(wm_size(self.max_size));
+        }
+
+        if previous.map(|previous| previous.parent) != Some(self.parent) {
+            update.parent = ConfigureUpdate::Update(parent);
+        }
+
+        if previous.map(|previous| previous.state) != Some(self.state) {
+            update.state = Some(self.state);
+        }
+
+        if previous.map(|previous| previous.preferred_decorations) != Some(self.preferred_decorations) {
+            update.decorations = ConfigureUpdate::Update(self.preferred_decorations.map(wm_decoration_mode));
+        }
+
+        if previous.map(|previous| previous.prefers_variable_refresh) != Some(self.prefers_variable_refresh) {
+            update.prefers_variable_refresh = Some(self.prefers_variable_refresh);
+        }
+
+        update
+    }
+}
+
+/// The xdg toplevel states which correspond to the toplevel states of the wm.
+const TOPLEVEL_STATES: [(types::ToplevelState, xdg_toplevel::State); 7] = [
+    (types::ToplevelState::MAXIMIZED, xdg_toplevel::State::Maximized),
+    (types::ToplevelState::FULLSCREEN, xdg_toplevel::State::Fullscreen),
+    (types::ToplevelState::RESIZING, xdg_toplevel::State::Resizing),
+    (types::ToplevelState::TILED_LEFT, xdg_toplevel::State::TiledLeft),
+    (types::ToplevelState::TILED_RIGHT, xdg_toplevel::State::TiledRight),
+    (types::ToplevelState::TILED_TOP, xdg_toplevel::State::TiledTop),
+    (types::ToplevelState::TILED_BOTTOM, xdg_toplevel::State::TiledBottom),
+];
+
+fn wm_toplevel_state(states: &ToplevelStateSet) -> types::ToplevelState {
+    let mut state = types::ToplevelState::empty();
+
+    // Activation follows the keyboard focus, so the state is only told to the wm.
+    if states.contains(xdg_toplevel::State::Activated) {
+        state |= types::ToplevelState::ACTIVATED;
+    }
+
+    for (wm_state, xdg_state) in TOPLEVEL_STATES {
+        if states.contains(xdg_state) {
+            state |= wm_state;
+        }
+    }
+
+    state
+}
+
+/// A size limit of a toplevel, or [`None`] if the client set no limit.
+fn wm_size(size: Size<i32, Logical>) -> Option<types::Size> {
+    (size.w > 0 || size.h > 0).then(|| types::Size {
+        width: size.w.max(0) as u32,
+        height: size.h.max(0) as u32,
+    })
+}
+
+/// What the client of the toplevel supports.
+fn toplevel_features(toplevel: &Toplevel) -> Features {
+    let mut features = Features::empty();
+
+    let Some(xdg) = toplevel.xdg_toplevel() else {
+        return features;
+    };
+
+    // Clients which support server side decorations create a decoration object before the initial commit.
+    if xdg.with_pending_state(|state| state.decoration_mode.is_some()) {
+        features |= Features::SERVER_SIDE_DECORATIONS;
+    }
+
+    let version = xdg.xdg_toplevel().version();
+
+    if version >= 2 {
+        features |= Features::TILED_STATES;
+    }
+
+    if version >= 6 {
+        features |= Features::SUSPENDED;
+    }
+
+    features
+}
+
+/// The geometry of the output in the global compositor space.
+fn output_geometry(output: &Output) -> Geometry {
+    let location = output.current_location();
+    let size = output
+        .current_mode()
+        .map(|mode| mode.size.to_logical(output.current_scale().integer_scale()))
+        .unwrap_or_default();
+
+    Geometry {
+        x: location.x,
+        y: location.y,
+        width: size.w.max(0) as u32,
+        height: size.h.max(0) as u32,
+    }
+}
+
+fn wm_subpixel(subpixel: output::Subpixel) -> Subpixel {
+    match subpixel {
+        output::Subpixel::Unknown => Subpixel::Unknown,
+        output::Subpixel::None => Subpixel::None,
+        output::Subpixel::HorizontalRgb => Subpixel::HorizontalRgb,
+        output::Subpixel::HorizontalBgr => Subpixel::HorizontalBgr,
+        output::Subpixel::VerticalRgb => Subpixel::VerticalRgb,
+        output::Subpixel::VerticalBgr => Subpixel::VerticalBgr,
+    }
+}
+
+pub(crate) fn wm_selection_kind(kind: SelectionKind) -> types::SelectionKind {
+    match kind {
+        SelectionKind::Clipboard => types::SelectionKind::Clipboard,
+        SelectionKind::Primary => types::SelectionKind::Primary,
+    }
+}
+
+pub(crate) fn wm_point(point: Point<f64, Logical>) -> types::Point {
+    types::Point { x: point.x, y: point.y }
+}
+
+impl Aerugo {
+    /// Send an event to the wm, if a wm is running.
+    pub(crate) fn send_wm(&self, event: WmEvent) {
+        if let Some(sender) = &self.wm.sender {
+            // The wm runtime closes its channel if its thread exits, which is handled when the channel is dispatched.
+            let _ = sender.send(event);
+        }
+    }
+
+    /// The capabilities of the seat as known to the wm.
+    fn seat_capabilities(&self) -> SeatCapabilities {
+        let mut capabilities = SeatCapabilities::empty();
+
+        if self.seat.get_keyboard().is_some() {
+            capabilities |= SeatCapabilities::KEYBOARD;
+        }
+
+        if self.seat.get_pointer().is_some() {
+            capabilities |= SeatCapabilities::POINTER;
+        }
+
+        if self.touch_points.has_devices() {
+            capabilities |= SeatCapabilities::TOUCH;
+        }
+
+        capabilities
+    }
+
+    /// Tell the wm the capabilities of the seat changed, such as after a touchscreen was connected.
+    pub(crate) fn send_seat_capabilities(&self) {
+        self.send_wm(WmEvent::SeatCapabilities {
+            seat: self.wm.seat,
+            capabilities: self.seat_capabilities(),
+        });
+    }
+
+    /// Tell a newly started wm about the seat, the output and every toplevel, popup and layer surface.
+    fn announce_to_wm(&mut self) {
+        self.send_wm(WmEvent::NewSeat {
+            seat: self.wm.seat,
+            name: self.seat.name().to_owned(),
+            capabilities: self.seat_capabilities(),
+        });
+        self.send_key_modifiers();
+
+        let output = self.output.clone();
+        self.send_wm(WmEvent::NewOutput {
+            output: self.wm.output,
+            geometry: output_geometry(&output),
+            info: self.output_info(&output),
+        });
+
+        let mut ids = self.shell.toplevels.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+
+        for id in ids {
+            self.announce_toplevel(id);
+        }
+
+        self.announce_popups();
+        self.announce_layer_surfaces();
+    }
+
+    fn output_info(&self, output: &Output) -> OutputInfo {
+        let properties = output.physical_properties();
+
+        OutputInfo {
+            name: Some(output.name()),
+            make: Some(properties.make).filter(|make| !make.is_empty()),
+            model: Some(properties.model).filter(|model| !model.is_empty()),
+            serial: None,
+            physical_size: (properties.size.w > 0 && properties.size.h > 0).then(|| types::Size {
+                width: properties.size.w as u32,
+                height: properties.size.h as u32,
+            }),
+            refresh_rate: output.current_mode().map_or(0, |mode| mode.refresh.max(0) as u32),
+            scale: output.current_scale().fractional_scale(),
+            subpixel: wm_subpixel(properties.subpixel),
+            vrr_capable: self.backend.output_supports_vrr(output),
+        }
+    }
+
+    /// Tell the wm about a toplevel with every property of the toplevel.
+    fn announce_toplevel(&mut self, id: ToplevelId) {
+        if !self.wm.is_running() {
+            return;
+        }
+
+        let Some(toplevel) = self.shell.get_state(id) else {
+            return;
+        };
+
+        let wm_id = alloc_id(&mut self.wm.next_id, IdType::Toplevel);
+        let features = toplevel_features(toplevel);
+        let state = WmToplevel::new(wm_id, toplevel);
+        let parent = state.parent.and_then(|parent| self.wm.toplevel_id(parent));
+        let update = state.update(None, parent);
+
+        self.send_wm(WmEvent::NewToplevel {
+            toplevel: wm_id,
+            features,
+        });
+        self.send_toplevel_rules(id, wm_id);
+        // The wm is told about the toplevel once the runtime has the initial state.
+        self.send_wm(WmEvent::UpdateToplevel {
+            toplevel: wm_id,
+            update,
+        });
+
+        self.wm.toplevels.insert(id, state);
+        self.wm.toplevel_ids.insert(wm_id, id);
+    }
+
+    /// A toplevel was mapped into the shell after the initial commit.
+    pub(crate) fn toplevel_created(&mut self, id: ToplevelId) {
+        self.announce_toplevel(id);
+
+        // Toplevels are arranged by the display server while no wm is running. Otherwise the wm sends the initial
+        // configure.
+        if !self.wm.is_running() || self.wm_crash.is_some() {
+            self.apply_fallback_layout();
+        }
+
+        self.update_audio_streams();
+    }
+
+    /// Tell the wm what changed about a mapped toplevel.
+    pub(crate) fn toplevel_updated(&mut self, id: ToplevelId) {
+        let (Some(toplevel), Some(previous)) = (self.shell.get_state(id), self.wm.toplevels.get(&id)) else {
+            return;
+        };
+
+        let state = WmToplevel::new(previous.id, toplevel);
+
+        if state != *previous {
+            let rules_changed = state.app_id != previous.app_id || state.title != previous.title;
+            let parent = state.parent.and_then(|parent| self.wm.toplevel_id(parent));
+            let update = state.update(Some(previous), parent);
+            self.send_wm(WmEvent::UpdateToplevel {
+                toplevel: state.id,
+                update,
+            });
+
+            if rules_changed {
+                self.send_toplevel_rules(id, state.id);
+            }
+
+            self.wm.toplevels.insert(id, state);
+        }
+    }
+
+    /// Tell the wm which window rules apply to a toplevel, which depends on its app id and title.
+    fn send_toplevel_rules(&mut self, id: ToplevelId, wm_id: Id) {
+        let Some(rules) = self.toplevel_rules(id) else {
+            return;
+        };
+
+        self.send_wm(WmEvent::ToplevelRules {
+            toplevel: wm_id,
+            rules: types::WindowRules {
+                workspace: rules.workspace,
+                floating: rules.floating,
+                size: rules.size.map(|size| types::Size {
+                    width: size.w.max(0) as u32,
+                    height: size.h.max(0) as u32,
+                }),
+                opacity: rules.opacity,
+            },
+        });
+    }
+
+    /// A mapped toplevel was committed.
+    pub(crate) fn toplevel_committed(&mut self, id: ToplevelId) {
+        self.toplevel_updated(id);
+
+        // The decorations follow the size of the toplevel.
+        if self.server_decorations.contains_key(&id) {
+            self.decorations_changed(id);
+        }
+
+        let (Some(toplevel), Some(wm_id)) = (self.shell.get_state(id), self.wm.toplevel_id(id)) else {
+            return;
+        };
+
+        let surface = toplevel.wl_surface();
+        let size = surface
+            .as_ref()
+            .and_then(|surface| self.scene.get_surface_tree_index(surface.clone()))
+            .and_then(|index| self.scene.surface_tree_geometry(index))
+            .map(|geometry| geometry.size);
+
+        // The wm gets a new snapshot when the size of the toplevel changed.
+        let snapshot = match size {
+            Some(size) if self.wm.committed_sizes.get(&id) != Some(&size) => {
+                self.wm.committed_sizes.insert(id, size);
+                self.wm_snapshot(id)
+            }
+            _ => None,
+        };
+
+        // The snapshot of the toplevel includes its subsurfaces, so the subsurfaces have no snapshots of their own.
+        let subsurfaces = surface
+            .map(|surface| shell::surface_nodes(&surface))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|node| SurfaceNodeInfo {
+                snapshot: None,
+                offset: types::Point {
+                    x: f64::from(node.offset.x),
+                    y: f64::from(node.offset.y),
+                },
+                parent: node.parent.map(|parent| parent as u32),
+                below: node.below,
+            })
+            .collect();
+
+        self.send_wm(WmEvent::CommittedToplevel {
+            toplevel: wm_id,
+            snapshot,
+            subsurfaces,
+        });
+    }
+
+    /// Take a snapshot of the toplevel which is owned by the wm until the wm drops it.
+    fn wm_snapshot(&mut self, id: ToplevelId) -> Option<SnapshotInfo> {
+        let thumbnail = self.snapshot_toplevel(id, None)?;
+        Some(self.give_snapshot(thumbnail))
+    }
+
+    /// Give a captured image to the wm, which owns it until the wm drops it.
+    pub(crate) fn give_snapshot(&mut self, thumbnail: Thumbnail) -> SnapshotInfo {
+        let snapshot = self.wm.alloc_id(IdType::Snapshot);
+        let info = SnapshotInfo {
+            id: snapshot,
+            size: types::Size {
+                width: thumbnail.size.w as u32,
+                height: thumbnail.size.h as u32,
+            },
+            scale: 1.0,
+        };
+
+        self.wm.snapshots.insert(snapshot, thumbnail);
+        info
+    }
+
+    /// A toplevel was unmapped or destroyed.
+    pub(crate) fn toplevel_closed(&mut self, id: ToplevelId) {
+        self.wm.configures.remove(&id);
+        self.wm.committed_sizes.remove(&id);
+
+        if let Some(state) = self.wm.toplevels.remove(&id) {
+            self.wm.toplevel_ids.remove(&state.id);
+            self.send_wm(WmEvent::ClosedToplevel(state.id));
+        }
+
+        self.update_audio_streams();
+    }
+
+    /// The client of a toplevel requested a change to its state, which the wm answers with a configure.
+    ///
+    /// While no wm can answer, the current state is sent again, since the client expects a configure in response.
+    /// Requests of toplevels which are not mapped yet are answered by the initial configure.
+    pub(crate) fn toplevel_state_requested(
+        &mut self,
+        surface: &WlSurface,
+        request: types::StateRequest,
+        output: Option<&WlOutput>,
+    ) {
+        let Some(id) = Shell::get_toplevel_id(surface) else {
+            return;
+        };
+
+        match self.wm.toplevel_id(id) {
+            Some(toplevel) if self.wm_crash.is_none() => {
+                let output = output
+                    .and_then(Output::from_resource)
+                    .filter(|output| output == &self.output)
+                    .map(|_| self.wm.output);
+
+                self.send_wm(WmEvent::RequestToplevelState {
+                    toplevel,
+                    request,
+                    output,
+                });
+            }
+
+            // There is no state to change for minimize requests.
+            _ if matches!(request, types::StateRequest::Minimize) => (),
+
+            _ => {
+                let serial = self
+                    .shell
+                    .get_state(id)
+                    .and_then(Toplevel::xdg_toplevel)
+                    .map(|toplevel| toplevel.send_configure());
+
+                if let Some(serial) = serial {
+                    self.configure_sent(id, serial);
+                }
+            }
+        }
+    }
+
+    /// Apply a configure the wm submitted for the toplevel.
+    ///
+    /// The wm is told once the client acked the configure. Xwayland surfaces do not ack configures, so their
+    /// configures are acked right away.
+    fn configure_toplevel(&mut self, id: ToplevelId, wm_serial: u32, configuration: ToplevelConfiguration) {
+        if let Some(decorations) = configuration.decorations {
+            self.set_decoration_mode(id, decoration_mode(decorations));
+        }
+
+        let fullscreen = configuration
+            .fullscreen
+            .and_then(|(output, hint)| Some((self.wm_output(output)?, hint)));
+
+        let Some(toplevel) = self.shell.get_state(id) else {
+            return;
+        };
+
+        let serial = match toplevel.xdg_toplevel() {
+            Some(xdg_toplevel) => {
+                let fullscreen_output = fullscreen.as_ref().and_then(|(output, _)| {
+                    let client = toplevel.client()?;
+                    output.client_outputs(&client).into_iter().next()
+                });
+
+                xdg_toplevel.with_pending_state(|state| {
+                    if let ConfigureUpdate::Update(size) = configuration.size {
+                        state.size = size.map(logical_size);
+                    }
+
+                    if let ConfigureUpdate::Update(bounds) = configuration.bounds {
+                        state.bounds = bounds.map(logical_size);
+                    }
+
+                    if let Some(wm_state) = configuration.state {
+                        for (wm_state_flag, xdg_state) in TOPLEVEL_STATES {
+                            if wm_state.contains(wm_state_flag) {
+                                state.states.set(xdg_state);
+                            } else {
+                                state.states.unset(xdg_state);
+                            }
+                        }
+
+                        if !wm_state.contains(types::ToplevelState::FULLSCREEN) {
+                            state.fullscreen_output = None;
+                        }
+                    }
+
+                    if fullscreen_output.is_some() {
+                        state.fullscreen_output = fullscreen_output;
+                    }
+                });
+
+                Some(xdg_toplevel.send_configure())
+            }
+
+            // Xwayland surfaces only take the size of the configure.
+            None => {
+                if let ConfigureUpdate::Update(Some(size)) = configuration.size {
+                    toplevel.resize(logical_size(size));
+                }
+
+                None
+            }
+        };
+
+        let unfullscreen = configuration
+            .state
+            .is_some_and(|state| !state.contains(types::ToplevelState::FULLSCREEN));
+
+        if let Some((output, hint)) = fullscreen {
+            self.fullscreen_on_output(id, &output, hint.map(output_mode));
+        } else if unfullscreen {
+            self.leave_fullscreen_output(id);
+        }
+
+        if configuration.clip.is_update() || configuration.corner_radius.is_some() {
+            self.set_toplevel_clip(id, configuration.clip, configuration.corner_radius);
+        }
+
+        if let ConfigureUpdate::Update(Some(size)) = configuration.size {
+            self.allot_placeholder(id, logical_size(size));
+        }
+
+        match serial {
+            Some(serial) => {
+                self.configure_sent(id, serial);
+                self.wm.configures.entry(id).or_default().push((serial, wm_serial));
+            }
+
+            None => {
+                if let Some(toplevel) = self.wm.toplevel_id(id) {
+                    self.send_wm(WmEvent::AckToplevel {
+                        toplevel,
+                        serial: wm_serial,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Set the part of the toplevel which is drawn and the radius of its corners.
+    fn set_toplevel_clip(&mut self, id: ToplevelId, clip: ConfigureUpdate<Geometry>, corner_radius: Option<u32>) {
+        let Some(NodeIndex::SurfaceTree(index)) = self.toplevel_node(id) else {
+            return;
+        };
+
+        let scale = self.output.current_scale().fractional_scale();
+        let Some(surface_tree) = self.scene.get_surface_tree(index) else {
+            return;
+        };
+
+        let mut render = surface_tree.render_properties();
+
+        if let ConfigureUpdate::Update(clip) = clip {
+            render.clip = clip.map(|clip| {
+                Rectangle::<i32, Logical>::from_loc_and_size((clip.x, clip.y), (clip.width as i32, clip.height as i32))
+                    .to_f64()
+                    .to_physical(scale)
+                    .to_i32_round()
+            });
+        }
+
+        if let Some(radius) = corner_radius {
+            render.corner_radius = (f64::from(radius) * scale).round() as i32;
+        }
+
+        self.scene.set_render_properties(index, render);
+        self.scene_changed();
+    }
+
+    /// The toplevel acked a configure, which also acks every configure of the wm sent before it.
+    pub(crate) fn wm_configure_acked(&mut self, id: ToplevelId, serial: Serial) {
+        let Some(configures) = self.wm.configures.get_mut(&id) else {
+            return;
+        };
+
+        let acked = configures.iter().take_while(|(sent, _)| *sent <= serial).count();
+        let Some(&(_, wm_serial)) = acked.checked_sub(1).and_then(|last| configures.get(last)) else {
+            return;
+        };
+        configures.drain(..acked);
+
+        if let Some(toplevel) = self.wm.toplevel_id(id) {
+            self.send_wm(WmEvent::AckToplevel {
+                toplevel,
+                serial: wm_serial,
+            });
+        }
+    }
+
+    /// Tell the wm the geometry or properties of the output changed, such as after a mode set.
+    pub(crate) fn output_changed(&mut self, output: &Output) {
+        if output != &self.output {
+            return;
+        }
+
+        let size = output
+            .current_mode()
+            .map(|mode| mode.size.to_logical(output.current_scale().integer_scale()))
+            .unwrap_or_default();
+        self.zoom.set_output_size(size.to_f64());
+        // Layer surfaces are anchored to the edges of the output.
+        self.arrange_layers();
+        // Cursor images are loaded for the scale of the output.
+        self.update_cursor_image();
+
+        self.send_wm(WmEvent::UpdateOutput {
+            output: self.wm.output,
+            geometry: output_geometry(output),
+            info: self.output_info(output),
+        });
+    }
+
+    /// Tell the wm the output is about to draw a frame.
+    pub(crate) fn wm_frame(&self, output: &Output, time: u32) {
+        if output == &self.output {
+            self.send_wm(WmEvent::Frame {
+                output: self.wm.output,
+                time,
+            });
+        }
+    }
+
+    /// The output with the id the wm knows.
+    fn wm_output(&self, id: Id) -> Option<Output> {
+        (id == self.wm.output).then(|| self.output.clone())
+    }
+}
+
+impl Loop {
+    /// Start the wm module at the path.
+    ///
+    /// If the wm cannot be started, toplevels are arranged by the fallback layout until a wm is loaded.
+    pub fn start_wm(&mut self, path: PathBuf) {
+        self.comp.wm.path = Some(path.clone());
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                self.comp
+                    .wm_crashed(format!("Failed to read the wm {}: {err}", path.display()));
+                return;
+            }
+        };
+
+        let runtime = match WmRuntime::new(&bytes, self.wm_store.entries()) {
+            Ok(runtime) => runtime,
+            Err(err) => {
+                self.comp
+                    .wm_crashed(format!("Failed to start the wm {}: {err:#}", path.display()));
+                return;
+            }
+        };
+
+        tracing::info!(?path, "Started the wm");
+
+        // Timers of a previous wm runtime must not expire in the new wm.
+        for (_, token) in self.wm_timers.drain() {
+            self.r#loop.remove(token);
+        }
+
+        self.comp.wm.reset(runtime.sender());
+        self.r#loop
+            .insert_source(runtime, |message, _, state| state.wm_message(message))
+            .expect("Failed to insert the wm runtime");
+
+        self.comp.announce_to_wm();
+        self.comp.wm_recovered();
+    }
+
+    /// Load the wm module again, such as after the wm was rebuilt.
+    ///
+    /// The running wm is replaced without losing the session. If the wm runtime is not running, the wm is started
+    /// again.
+    pub fn reload_wm(&mut self) {
+        let Some(path) = self.comp.wm.path.clone() else {
+            tracing::warn!("No wm was loaded, so there is no wm to reload");
+            return;
+        };
+
+        if !self.comp.wm.is_running() {
+            self.start_wm(path);
+            return;
+        }
+
+        match fs::read(&path) {
+            Ok(bytes) => self.comp.send_wm(WmEvent::Reload(bytes)),
+            Err(err) => tracing::error!(%err, ?path, "Failed to read the wm"),
+        }
+    }
+
+    fn wm_message(&mut self, message: RuntimeMessage) {
+        let request = match message {
+            RuntimeMessage::Request(request) => request,
+
+            RuntimeMessage::Closed => {
+                self.comp.wm.sender = None;
+                self.comp.wm_crashed("The wm runtime stopped".into());
+                self.reply_bindings(Vec::new());
+                return;
+            }
+        };
+
+        if let Err(err) = self.wm_request(request) {
+            tracing::warn!(%err, "Failed to apply a request of the wm");
+        }
+    }
+
+    /// Send the bindings listed by the wm to the IPC connections waiting for them.
+    fn reply_bindings(&mut self, lines: Vec<String>) {
+        let reply = ipc::Reply::Bindings(lines);
+
+        for mut stream in self.binding_list_streams.drain(..) {
+            // The connection is removed once the client closes it.
+            let _ = writeln!(stream, "{reply}");
+        }
+    }
+
+    fn wm_request(&mut self, request: WmRequest) -> Result<(), Box<dyn Error>> {
+        let comp = &mut self.comp;
+
+        match request {
+            WmRequest::TerminateWm => tracing::info!("The wm runtime is terminating"),
+
+            WmRequest::Reloaded => {
+                tracing::info!("Reloaded the wm");
+                comp.wm_recovered();
+            }
+
+            WmRequest::ReloadFailed(error) => tracing::error!(%error, "Failed to reload the wm"),
+            WmRequest::Crashed(error) => comp.wm_crashed(error),
+
+            WmRequest::Restarted => {
+                tracing::info!("Restarted the wm after a crash");
+                comp.wm_recovered();
+            }
+
+            // The toplevel stays until the client destroys it.
+            WmRequest::ToplevelDrop(_) => (),
+
+            WmRequest::ToplevelRequestClose(toplevel) => {
+                if let Some(id) = comp.wm.toplevel(toplevel) {
+                    comp.close_toplevel(id);
+                }
+            }
+
+            WmRequest::ConfigureToplevel {
+                toplevel,
+                serial,
+                configuration,
+            } => {
+                if let Some(id) = comp.wm.toplevel(toplevel) {
+                    comp.configure_toplevel(id, serial, configuration);
+                }
+            }
+
+            WmRequest::ConfigureOutput {
+                output,
+                serial,
+                configuration,
+            } => {
+                let Some(wm_output) = comp.wm_output(output) else {
+                    return Ok(());
+                };
+
+                let applied = match comp.configure_output(&wm_output, output_config(configuration)) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        tracing::warn!(output = %wm_output.name(), %err, "Output configuration of the wm was rejected");
+                        false
+                    }
+                };
+
+                comp.send_wm(WmEvent::OutputConfigured {
+                    output,
+                    serial,
+                    applied,
+                });
+            }
+
+            WmRequest::RequestFrame(output) => {
+                if let Some(output) = comp.wm_output(output) {
+                    comp.request_frame(&output);
+                }
+            }
+
+            WmRequest::SetOutputEnabled { output, enabled } => {
+                if let Some(output) = comp.wm_output(output) {
+                    comp.set_output_enabled(&output, enabled);
+                }
+            }
+
+            WmRequest::SetOutputDpms { output, on } => {
+                if let Some(output) = comp.wm_output(output) {
+                    comp.set_output_dpms(&output, on);
+                }
+            }
+
+            WmRequest::KeyFilter { serial, filter } => {
+                comp.key_filter(serial, matches!(filter, types::KeyFilter::Forward));
+            }
+
+            WmRequest::PopupConfigure {
+                popup,
+                geometry,
+                reposition_token,
+            } => comp.wm_configure_popup(popup, geometry, reposition_token),
+
+            WmRequest::PopupDismiss(popup) => comp.wm_dismiss_popup(popup),
+
+            // The popup stays until the client destroys it.
+            WmRequest::PopupDrop(_) => (),
+
+            WmRequest::LayerSurfaceConfigure {
+                layer_surface,
+                size,
+                serial,
+            } => comp.wm_configure_layer_surface(layer_surface, size, serial),
+
+            WmRequest::LayerSurfaceClose(layer_surface) => comp.wm_close_layer_surface(layer_surface),
+
+            // The layer surface stays until the client destroys it.
+            WmRequest::LayerSurfaceDrop(_) => (),
+
+            WmRequest::PointerFilter { serial, filter } => {
+                comp.pointer_filter(serial, matches!(filter, types::PointerFilter::Forward));
+            }
+
+            WmRequest::SetOutputVrr { output, enabled } => {
+                if let Some(output) = comp.wm_output(output) {
+                    comp.set_output_vrr(&output, enabled);
+                }
+            }
+
+            WmRequest::SetOutputZoom { output, level, follow } => {
+                if let Some(output) = comp.wm_output(output) {
+                    let mode = match follow {
+                        types::ZoomFollow::Pointer => FollowMode::Pointer,
+                        types::ZoomFollow::Focus => FollowMode::Focus,
+                        types::ZoomFollow::Caret => FollowMode::Caret,
+                    };
+                    comp.set_output_zoom(&output, level, mode);
+                }
+            }
+
+            WmRequest::KillClient(toplevel) => {
+                if let Some(id) = comp.wm.toplevel(toplevel) {
+                    comp.kill_client(id);
+                }
+            }
+
+            WmRequest::KeyboardFocus(focus) => {
+                let focus = match focus {
+                    Some(toplevel) => match comp.wm.toplevel(toplevel) {
+                        Some(id) => Some(id),
+                        // The toplevel was closed before the request arrived.
+                        None => return Ok(()),
+                    },
+                    None => None,
+                };
+
+                if !comp.set_keyboard_focus(focus) {
+                    tracing::debug!(?focus, "Keyboard focus set by the wm was refused");
+                }
+            }
+
+            WmRequest::Raise(toplevel) => {
+                if let Some(id) = comp.wm.toplevel(toplevel) {
+                    comp.raise_toplevel(id);
+                }
+            }
+
+            WmRequest::Lower(toplevel) => {
+                if let Some(id) = comp.wm.toplevel(toplevel) {
+                    comp.lower_toplevel(id);
+                }
+            }
+
+            WmRequest::RestackAbove { toplevel, sibling } => {
+                if let (Some(id), Some(sibling)) = (comp.wm.toplevel(toplevel), comp.wm.toplevel(sibling)) {
+                    comp.restack_toplevel_above(id, sibling);
+                }
+            }
+
+            WmRequest::StartMove(toplevel) => {
+                if let Some(id) = comp.wm.toplevel(toplevel) {
+                    comp.start_interactive_grab(id, None);
+                }
+            }
+
+            WmRequest::StartResize { toplevel, edge } => {
+                if let Some(id) = comp.wm.toplevel(toplevel) {
+                    comp.start_interactive_grab(id, Some(edge));
+                }
+            }
+
+            WmRequest::CreateWorkspace {
+                workspace,
+                name,
+                output,
+            } => {
+                let output = output.and_then(|output| comp.wm_output(output));
+                let id = comp.workspaces.create(name, output);
+                comp.wm.workspaces.insert(workspace, id);
+            }
+
+            WmRequest::RenameWorkspace { workspace, name } => {
+                if let Some(&id) = comp.wm.workspaces.get(&workspace) {
+                    comp.workspaces.rename(id, name)?;
+                }
+            }
+
+            WmRequest::AssignWorkspace { workspace, output } => {
+                if let (Some(&id), Some(output)) = (comp.wm.workspaces.get(&workspace), comp.wm_output(output)) {
+                    comp.workspaces.assign(id, output)?;
+                }
+            }
+
+            WmRequest::ActivateWorkspace(workspace) => {
+                if let Some(&id) = comp.wm.workspaces.get(&workspace) {
+                    comp.workspaces.activate(id)?;
+                    let name = comp.workspaces.get(id).map(|workspace| workspace.name().to_owned());
+                    comp.ipc_event(ipc::Event::Workspace(name.unwrap_or_default()));
+                }
+            }
+
+            WmRequest::DestroyWorkspace(workspace) => {
+                if let Some(id) = comp.wm.workspaces.remove(&workspace) {
+                    comp.workspaces.destroy(id)?;
+                }
+            }
+
+            WmRequest::MoveToWorkspace { toplevel, workspace } => {
+                if let (Some(toplevel), Some(&id)) = (comp.wm.toplevel(toplevel), comp.wm.workspaces.get(&workspace)) {
+                    comp.workspaces.move_toplevel(toplevel, id)?;
+                }
+            }
+
+            WmRequest::CreateToplevelGroup(group) => {
+                let id = comp.create_toplevel_group();
+                comp.wm.groups.insert(group, id);
+            }
+
+            WmRequest::AddToToplevelGroup { group, toplevel } => {
+                if let (Some(&id), Some(toplevel)) = (comp.wm.groups.get(&group), comp.wm.toplevel(toplevel)) {
+                    comp.add_to_toplevel_group(id, toplevel)?;
+                }
+            }
+
+            WmRequest::RemoveFromToplevelGroup { group, toplevel } => {
+                if let (Some(&id), Some(toplevel)) = (comp.wm.groups.get(&group), comp.wm.toplevel(toplevel)) {
+                    comp.remove_from_toplevel_group(id, toplevel)?;
+                }
+            }
+
+            WmRequest::SetVisibleInToplevelGroup { group, toplevel } => {
+                if let (Some(&id), Some(toplevel)) = (comp.wm.groups.get(&group), comp.wm.toplevel(toplevel)) {
+                    comp.set_visible_in_toplevel_group(id, toplevel)?;
+                }
+            }
+
+            WmRequest::SetToplevelGroupTabLayout { group, layout } => {
+                if let Some(&id) = comp.wm.groups.get(&group) {
+                    let layout = layout.map(|layout| match layout {
+                        types::TabLayout::Tabbed => TabLayout::Tabbed,
+                        types::TabLayout::Stacked => TabLayout::Stacked,
+                    });
+                    comp.set_toplevel_group_tab_layout(id, layout)?;
+                }
+            }
+
+            WmRequest::DestroyToplevelGroup(group) => {
+                if let Some(id) = comp.wm.groups.remove(&group) {
+                    comp.destroy_toplevel_group(id)?;
+                }
+            }
+
+            WmRequest::CreateNode { node, kind } => {
+                let kind = match kind {
+                    NodeKind::Group => WmNodeKind::Group,
+
+                    NodeKind::Toplevel { toplevel, .. } => match comp.wm.toplevel(toplevel) {
+                        Some(id) => WmNodeKind::Toplevel(id),
+                        None => WmNodeKind::Group,
+                    },
+
+                    NodeKind::Solid { size, color } => WmNodeKind::Shape {
+                        size: logical_size(size),
+                        shape: Shape::Solid(rgba(color)),
+                    },
+
+                    NodeKind::Border { size, width, color } => WmNodeKind::Shape {
+                        size: logical_size(size),
+                        shape: Shape::Border {
+                            width: width as i32,
+                            color: rgba(color),
+                        },
+                    },
+
+                    NodeKind::Texture { size, texture } => WmNodeKind::Shape {
+                        size: logical_size(size),
+                        shape: Shape::Texture {
+                            size: (texture.width as i32, texture.height as i32).into(),
+                            pixels: texture.pixels,
+                        },
+                    },
+
+                    // A snapshot which could not be rendered draws nothing, so the children of the node are still
+                    // drawn.
+                    NodeKind::Snapshot { size, snapshot } => match comp.wm.snapshots.get(&snapshot) {
+                        Some(thumbnail) => WmNodeKind::Shape {
+                            size: logical_size(size),
+                            shape: Shape::Texture {
+                                size: (thumbnail.size.w, thumbnail.size.h).into(),
+                                pixels: thumbnail.pixels.to_vec(),
+                            },
+                        },
+                        None => WmNodeKind::Group,
+                    },
+                };
+
+                comp.create_wm_node(node.rep().get(), kind);
+            }
+
+            WmRequest::AddNodeChild { parent, child } => comp.add_wm_node_child(parent.rep().get(), child.rep().get()),
+            WmRequest::DetachNode(node) => comp.detach_wm_node(node.rep().get()),
+
+            WmRequest::PlaceNodeAbove { node, sibling } => {
+                comp.place_wm_node_above(node.rep().get(), sibling.rep().get());
+            }
+
+            WmRequest::SetNodeOffset { node, offset } => {
+                let offset = Point::from((offset.x.round() as i32, offset.y.round() as i32));
+                comp.set_wm_node_offset(node.rep().get(), offset);
+            }
+
+            WmRequest::SetNodeSize { node, size } => comp.set_wm_node_size(node.rep().get(), logical_size(size)),
+            WmRequest::SetNodeColor { node, color } => comp.set_wm_node_color(node.rep().get(), rgba(color)),
+
+            WmRequest::NodeRender { node, properties } => {
+                let scale = comp.output.current_scale().fractional_scale();
+                comp.set_wm_node_render(node.rep().get(), render_properties(properties, scale));
+            }
+
+            WmRequest::ToplevelRender { toplevel, properties } => {
+                if let Some(id) = comp.wm.toplevel(toplevel) {
+                    let scale = comp.output.current_scale().fractional_scale();
+                    comp.set_toplevel_render(id, render_properties(properties, scale));
+                }
+            }
+
+            WmRequest::ShowSwitcher { toplevels, selected } => {
+                let selected = toplevels.get(selected).and_then(|&id| comp.wm.toplevel(id));
+                let toplevels = toplevels
+                    .into_iter()
+                    .filter_map(|id| comp.wm.toplevel(id))
+                    .collect::<Vec<_>>();
+                let selected = toplevels
+                    .iter()
+                    .position(|&id| Some(id) == selected)
+                    .unwrap_or_default();
+
+                comp.show_switcher(toplevels, selected);
+            }
+
+            WmRequest::HideSwitcher => comp.hide_switcher(),
+
+            WmRequest::ToplevelDecorations { toplevel, decorations } => {
+                if let Some(id) = comp.wm.toplevel(toplevel) {
+                    comp.set_server_decorations(id, decorations.map(server_decorations));
+                }
+            }
+
+            WmRequest::DestroyNode(node) => comp.destroy_wm_node(node.rep().get()),
+
+            WmRequest::SnapshotToplevel {
+                toplevel,
+                snapshot,
+                size,
+            } => {
+                let size = (size.width as i32, size.height as i32).into();
+                let thumbnail = comp
+                    .wm
+                    .toplevel(toplevel)
+                    .and_then(|id| comp.snapshot_toplevel(id, Some(size)));
+
+                match thumbnail {
+                    Some(thumbnail) => {
+                        comp.wm.snapshots.insert(snapshot, thumbnail);
+                    }
+                    None => tracing::debug!(?toplevel, "Failed to snapshot the toplevel for the wm"),
+                }
+            }
+
+            WmRequest::DestroySnapshot(snapshot) => {
+                comp.wm.snapshots.remove(&snapshot);
+            }
+
+            WmRequest::Present { output, root } => {
+                if comp.wm_output(output).is_some() {
+                    comp.present_wm_node(root.map(|root| root.rep().get()));
+                }
+            }
+
+            WmRequest::ActivationToken { token, app_id } => comp.add_wm_activation_token(token, app_id),
+            WmRequest::SetCursor(cursor) => comp.set_cursor_override(cursor.map(cursor_override)),
+            WmRequest::SetCursorVisible(visible) => comp.set_cursor_visible(visible),
+            WmRequest::WarpPointer { position, .. } => comp.warp_pointer((position.x, position.y).into()),
+
+            WmRequest::SetSelection { kind, data } => {
+                let kind = match kind {
+                    types::SelectionKind::Clipboard => SelectionKind::Clipboard,
+                    types::SelectionKind::Primary => SelectionKind::Primary,
+                };
+                let data = data.into_iter().map(|data| (data.mime_type, data.data)).collect();
+                comp.set_selection(kind, SelectionContents::new(data));
+            }
+
+            WmRequest::SetKeymap(rules) => {
+                let config = XkbConfig {
+                    rules: &rules.rules,
+                    model: &rules.model,
+                    layout: &rules.layout,
+                    variant: &rules.variant,
+                    options: rules.options,
+                };
+
+                if comp.set_keymap(config).is_none() {
+                    tracing::warn!(layout = rules.layout, "The keymap set by the wm cannot be compiled");
+                }
+            }
+
+            WmRequest::SetLayoutIndex(index) => {
+                if !comp.set_keyboard_layout(index) {
+                    tracing::warn!(index, "The keymap has no layout with the index set by the wm");
+                }
+            }
+
+            WmRequest::SetTimer { timer, duration } => self.set_wm_timer(timer, duration),
+            WmRequest::CancelTimer(timer) => self.cancel_wm_timer(timer),
+            WmRequest::Spawn {
+                process,
+                command,
+                args,
+                env,
+            } => self.spawn_wm_process(process, &command, &args, &env),
+
+            WmRequest::BeginTransaction => comp.begin_transaction(),
+            WmRequest::CommitTransaction => comp.commit_transaction(),
+            WmRequest::StoreSet { key, value } => self.set_wm_store(key, value),
+            WmRequest::BindingModeChanged(mode) => comp.ipc_event(ipc::Event::Mode(mode)),
+            WmRequest::Bindings(bindings) => self.reply_bindings(bindings.iter().map(ipc::binding_line).collect()),
+        }
+
+        Ok(())
+    }
+}
+
+fn logical_size(size: types::Size) -> Size<i32, Logical> {
+    Size::from((size.width as i32, size.height as i32))
+}
+
+/// The render properties of the wm in the physical coordinates of an output with the scale.
+fn render_properties(properties: types::RenderProperties, scale: f64) -> RenderProperties {
+    let clip = properties.clip.map(|clip| {
+        Rectangle::<i32, Logical>::from_loc_and_size((clip.x, clip.y), (clip.width as i32, clip.height as i32))
+            .to_f64()
+            .to_physical(scale)
+            .to_i32_round()
+    });
+
+    RenderProperties {
+        opacity: properties.opacity,
+        scale: f64::from(properties.scale),
+        rotation: f64::from(properties.rotation),
+        translation: Point::<i32, Logical>::from((properties.translate_x, properties.translate_y))
+            .to_f64()
+            .to_physical(scale)
+            .to_i32_round(),
+        clip,
+        corner_radius: (f64::from(properties.corner_radius) * scale).round() as i32,
+        ..RenderProperties::default()
+    }
+}
+
+fn output_config(configuration: OutputConfiguration) -> OutputConfig {
+    OutputConfig {
+        mode: configuration.mode.map(output_mode),
+        scale: configuration.scale,
+        transform: configuration.transform.map(output_transform),
+        position: configuration.position.map(Point::from),
+    }
+}
+
+fn output_mode(mode: types::OutputMode) -> Mode {
+    Mode {
+        size: (mode.size.width as i32, mode.size.height as i32).into(),
+        refresh: mode.refresh as i32,
+    }
+}
+
+fn output_transform(transform: types::OutputTransform) -> Transform {
+    match transform {
+        types::OutputTransform::Normal => Transform::Normal,
+        types::OutputTransform::Rotate90 => Transform::_90,
+        types::OutputTransform::Rotate180 => Transform::_180,
+        types::OutputTransform::Rotate270 => Transform::_270,
+        types::OutputTransform::Flipped => Transform::Flipped,
+        types::OutputTransform::Flipped90 => Transform::Flipped90,
+        types::OutputTransform::Flipped180 => Transform::Flipped180,
+        types::OutputTransform::Flipped270 => Transform::Flipped270,
+    }
+}
+
+fn wm_decoration_mode(mode: zxdg_toplevel_decoration_v1::Mode) -> types::DecorationMode {
+    match mode {
+        zxdg_toplevel_decoration_v1::Mode::ServerSide => types::DecorationMode::ServerSide,
+        _ => types::DecorationMode::ClientSide,
+    }
+}
+
+fn decoration_mode(mode: types::DecorationMode) -> zxdg_toplevel_decoration_v1::Mode {
+    match mode {
+        types::DecorationMode::ClientSide => zxdg_toplevel_decoration_v1::Mode::ClientSide,
+        types::DecorationMode::ServerSide => zxdg_toplevel_decoration_v1::Mode::ServerSide,
+    }
+}
+
+fn rgba(color: types::Color) -> [f32; 4] {
+    [color.r, color.g, color.b, color.a]
+}
+
+fn server_decorations(decorations: types::ServerDecorations) -> ServerDecorations {
+    ServerDecorations {
+        title_bar_height: decorations.title_bar_height.min(i32::MAX as u32) as i32,
+        border_width: decorations.border_width.min(i32::MAX as u32) as i32,
+        title_bar: match decorations.title_bar {
+            types::DecorationFill::Color(color) => DecorationFill::Color(rgba(color)),
+            types::DecorationFill::Texture(texture) => DecorationFill::Texture {
+                width: texture.width,
+                height: texture.height,
+                pixels: texture.pixels.into(),
+            },
+        },
+        border: rgba(decorations.border),
+    }
+}
+
+fn cursor_override(cursor: WmCursor) -> CursorOverride {
+    match cursor {
+        WmCursor::Shape(shape) => CursorOverride::Shape(cursor_shape(shape)),
+
+        WmCursor::Image { image, hotspot } => CursorOverride::Image(CursorImage {
+            nominal_size: image.width.max(image.height),
+            width: image.width,
+            height: image.height,
+            xhot: hotspot.x.max(0.0) as u32,
+            yhot: hotspot.y.max(0.0) as u32,
+            delay: 0,
+            pixels: image.pixels,
+        }),
+
+        WmCursor::Hidden => CursorOverride::Hidden,
+    }
+}
+
+fn cursor_shape(shape: types::CursorShape) -> CursorShape {
+    use types::CursorShape as Wm;
+
+    match shape {
+        Wm::Default => CursorShape::Default,
+        Wm::ContextMenu => CursorShape::ContextMenu,
+        Wm::Help => CursorShape::Help,
+        Wm::Pointer => CursorShape::Pointer,
+        Wm::Progress => CursorShape::Progress,
+        Wm::Wait => CursorShape::Wait,
+        Wm::Cell => CursorShape::Cell,
+        Wm::Crosshair => CursorShape::Crosshair,
+        Wm::Text => CursorShape::Text,
+        Wm::VerticalText => CursorShape::VerticalText,
+        Wm::Alias => CursorShape::Alias,
+        Wm::Copy => CursorShape::Copy,
+        Wm::Move => CursorShape::Move,
+        Wm::NoDrop => CursorShape::NoDrop,
+        Wm::NotAllowed => CursorShape::NotAllowed,
+        Wm::Grab => CursorShape::Grab,
+        Wm::Grabbing => CursorShape::Grabbing,
+        Wm::EResize => CursorShape::EResize,
+        Wm::NResize => CursorShape::NResize,
+        Wm::NeResize => CursorShape::NeResize,
+        Wm::NwResize => CursorShape::NwResize,
+        Wm::SResize => CursorShape::SResize,
+        Wm::SeResize => CursorShape::SeResize,
+        Wm::SwResize => CursorShape::SwResize,
+        Wm::WResize => CursorShape::WResize,
+        Wm::EwResize => CursorShape::EwResize,
+        Wm::NsResize => CursorShape::NsResize,
+        Wm::NeswResize => CursorShape::NeswResize,
+        Wm::NwseResize => CursorShape::NwseResize,
+        Wm::ColResize => CursorShape::ColResize,
+        Wm::RowResize => CursorShape::RowResize,
+        Wm::AllScroll => CursorShape::AllScroll,
+        Wm::ZoomIn => CursorShape::ZoomIn,
+        Wm::ZoomOut => CursorShape::ZoomOut,
+    }
+}
+
+/// What the wm is told about an activation token when the token is used.
+pub(crate) fn activation_token_info(token: &TokenData, now: Instant) -> ActivationTokenInfo {
+    let (from_wm, from_input, focused) = match token.origin {
+        TokenOrigin::Wm => (true, false, false),
+        TokenOrigin::Client { from_input, focused } => (false, from_input, focused),
+    };
+
+    ActivationTokenInfo {
+        app_id: token.app_id.clone(),
+        from_wm,
+        from_input,
+        focused,
+        age: u32::try_from(now.saturating_duration_since(token.created).as_millis()).unwrap_or(u32::MAX),
+    }
+}
@@ -0,0 +1,216 @@
+//! Scene nodes of the wm
+//!
+//! The wm describes what is drawn on an output as a tree of nodes: groups, toplevels, solid colors, borders and
+//! textures uploaded by the wm. Each node of the wm is backed by a node in the scene graph, so the wm can move,
+//! restack and fade parts of the scene without describing the whole tree again every frame.
+//!
+//! A toplevel node is a branch holding the surface tree of the toplevel. Children the wm adds to a toplevel node
+//! are drawn above the toplevel, and dropping the node leaves the surface tree of the toplevel intact.
+
+use rustc_hash::FxHashMap;
+use smithay::utils::{Logical, Point, Size};
+
+use crate::{
+    scene::{NodeIndex, RenderProperties, Shape},
+    shell::ToplevelId,
+    Aerugo,
+};
+
+/// The id the wm runtime gave a node.
+pub type WmNodeId = u32;
+
+/// What a node of the wm draws.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WmNodeKind {
+    /// Nothing, the node only groups its children.
+    Group,
+
+    /// The toplevel and its subsurfaces.
+    Toplevel(ToplevelId),
+
+    /// A shape drawn by the display server.
+    Shape { size: Size<i32, Logical>, shape: Shape },
+}
+
+#[derive(Debug)]
+struct WmNode {
+    index: NodeIndex,
+
+    /// The toplevel drawn by the node.
+    toplevel: Option<ToplevelId>,
+}
+
+/// The scene nodes created by the wm.
+#[derive(Debug, Default)]
+pub struct WmNodes {
+    nodes: FxHashMap<WmNodeId, WmNode>,
+}
+
+impl WmNodes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The scene node backing the node of the wm.
+    pub fn get(&self, id: WmNodeId) -> Option<NodeIndex> {
+        self.nodes.get(&id).map(|node| node.index)
+    }
+}
+
+impl Aerugo {
+    /// The wm created a scene node.
+    ///
+    /// A surface tree has one parent, so a new node of a toplevel takes the toplevel from the previous node.
+    pub fn create_wm_node(&mut self, id: WmNodeId, kind: WmNodeKind) {
+        let scale = self.output.current_scale().integer_scale();
+
+        let (index, toplevel) = match kind {
+            WmNodeKind::Group => (NodeIndex::Branch(self.scene.create_branch()), None),
+
+            WmNodeKind::Toplevel(toplevel) => {
+                let branch = self.scene.create_branch();
+
+                if let Some(surface_tree) = self.toplevel_node(toplevel) {
+                    if let Err(err) = self.scene.add_child(NodeIndex::Branch(branch), surface_tree) {
+                        tracing::warn!(id, toplevel, %err, "Failed to add toplevel to wm node");
+                    }
+                }
+
+                (NodeIndex::Branch(branch), Some(toplevel))
+            }
+
+            WmNodeKind::Shape { size, shape } => {
+                let index = self.scene.create_shape(size.to_physical(scale), shape);
+                (NodeIndex::Shape(index), None)
+            }
+        };
+
+        self.wm_nodes.nodes.insert(id, WmNode { index, toplevel });
+
+        if let Some(toplevel) = toplevel {
+            self.decorations_changed(toplevel);
+        }
+    }
+
+    /// The node of the wm drawing the toplevel, which holds the surface tree of the toplevel.
+    pub(crate) fn wm_toplevel_branch(&self, toplevel: ToplevelId, surface_tree: NodeIndex) -> Option<NodeIndex> {
+        self.wm_nodes
+            .nodes
+            .values()
+            .filter(|node| node.toplevel == Some(toplevel))
+            .map(|node| node.index)
+            .find(|&index| self.scene.children(index).any(|child| child == surface_tree))
+    }
+
+    /// The wm added a child above the other children of the node.
+    pub fn add_wm_node_child(&mut self, parent: WmNodeId, child: WmNodeId) {
+        let (Some(parent_index), Some(child_index)) = (self.wm_nodes.get(parent), self.wm_nodes.get(child)) else {
+            return;
+        };
+
+        match self.scene.add_child(parent_index, child_index) {
+            Ok(()) => self.scene_changed(),
+            Err(err) => tracing::warn!(parent, child, %err, "Failed to add wm node child"),
+        }
+    }
+
+    /// The wm removed the node from its parent.
+    pub fn detach_wm_node(&mut self, id: WmNodeId) {
+        if let Some(index) = self.wm_nodes.get(id) {
+            self.scene.detach_node(index);
+            self.scene_changed();
+        }
+    }
+
+    /// The wm placed the node directly above a sibling.
+    pub fn place_wm_node_above(&mut self, id: WmNodeId, sibling: WmNodeId) {
+        let (Some(index), Some(sibling_index)) = (self.wm_nodes.get(id), self.wm_nodes.get(sibling)) else {
+            return;
+        };
+
+        match self.scene.restack_above(index, sibling_index) {
+            Ok(()) => self.scene_changed(),
+            Err(err) => tracing::warn!(id, sibling, %err, "Failed to restack wm node"),
+        }
+    }
+
+    /// The wm moved the node relative to its parent.
+    pub fn set_wm_node_offset(&mut self, id: WmNodeId, offset: Point<i32, Logical>) {
+        let scale = self.output.current_scale().integer_scale();
+
+        if let Some(index) = self.wm_nodes.get(id) {
+            self.scene.set_node_offset(index, offset.to_physical(scale));
+            self.scene_changed();
+        }
+    }
+
+    /// The wm resized a shape node.
+    pub fn set_wm_node_size(&mut self, id: WmNodeId, size: Size<i32, Logical>) {
+        let scale = self.output.current_scale().integer_scale();
+
+        if let Some(NodeIndex::Shape(index)) = self.wm_nodes.get(id) {
+            self.scene.set_shape_size(index, size.to_physical(scale));
+            self.scene_changed();
+        }
+    }
+
+    /// The wm changed the color of a solid color or border node.
+    pub fn set_wm_node_color(&mut self, id: WmNodeId, color: [f32; 4]) {
+        if let Some(NodeIndex::Shape(index)) = self.wm_nodes.get(id) {
+            self.scene.set_shape_color(index, color);
+            self.scene_changed();
+        }
+    }
+
+    /// The wm set the properties used when compositing the node.
+    ///
+    /// Only surface trees have render properties, so the properties of groups and shapes are ignored.
+    pub fn set_wm_node_render(&mut self, id: WmNodeId, render: RenderProperties) {
+        if let Some(toplevel) = self.wm_nodes.nodes.get(&id).and_then(|node| node.toplevel) {
+            self.set_toplevel_render(toplevel, render);
+        }
+    }
+
+    /// The wm presented the tree of the node on the output, or nothing.
+    ///
+    /// The tree is placed between the bottom and top layers of layer surfaces.
+    pub fn present_wm_node(&mut self, root: Option<WmNodeId>) {
+        let branch = NodeIndex::Branch(self.layer_branches().wm());
+        let presented = self.scene.children(branch).collect::<Vec<_>>();
+
+        for child in presented {
+            self.scene.detach_node(child);
+        }
+
+        if let Some(index) = root.and_then(|root| self.wm_nodes.get(root)) {
+            if let Err(err) = self.scene.add_child(branch, index) {
+                tracing::warn!(?root, %err, "Failed to present wm node");
+            }
+        }
+
+        self.scene_changed();
+    }
+
+    /// The wm dropped the node.
+    ///
+    /// The children of the node are detached rather than destroyed, since the wm may still hold them.
+    pub fn destroy_wm_node(&mut self, id: WmNodeId) {
+        let Some(node) = self.wm_nodes.nodes.remove(&id) else {
+            return;
+        };
+
+        let children = self.scene.children(node.index).collect::<Vec<_>>();
+
+        for child in children {
+            self.scene.detach_node(child);
+        }
+
+        match node.index {
+            NodeIndex::Branch(index) => self.scene.destroy_branch(index),
+            NodeIndex::Shape(index) => self.scene.destroy_shape(index),
+            NodeIndex::SurfaceTree(_) => unreachable!("wm nodes are never surface trees"),
+        }
+
+        self.scene_changed();
+    }
+}
@@ -0,0 +1,325 @@
+//! Persistent store of the wm
+//!
+//! Wms run sandboxed without access to the filesystem, so the display server keeps a small key-value store for the
+//! wm in a state file. This lets a wm restore workspace layouts and window positions after a restart.
+//!
+//! The file is `$XDG_STATE_HOME/aerugo/wm-store`, falling back to `~/.local/state/aerugo/wm-store`. Each line
+//! contains a key and a value separated by an `=`, where `\`, `=`, newlines and carriage returns are escaped with a
+//! backslash. A wm often sets several values at once, so the file is written [`SAVE_DELAY`] after the first change
+//! rather than once for every change. The file is written on another thread so a slow disk never delays a frame.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs, io,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use wm_runtime::{MAX_STORE_ENTRIES, MAX_STORE_KEY, MAX_STORE_SIZE, MAX_STORE_VALUE};
+
+/// How long after a value changes the store is written to disk.
+pub const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// A value which the store does not accept.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum StoreError {
+    #[error("key must be between 1 and {MAX_STORE_KEY} bytes")]
+    InvalidKey,
+
+    #[error("value must be at most {MAX_STORE_VALUE} bytes")]
+    ValueTooLong,
+
+    #[error("store may contain at most {MAX_STORE_ENTRIES} values")]
+    TooManyEntries,
+
+    #[error("store would be larger than {MAX_STORE_SIZE} bytes")]
+    TooLarge,
+}
+
+#[derive(Debug, Default)]
+pub struct WmStore {
+    /// The state file, or [`None`] if the store is only kept in memory.
+    path: Option<PathBuf>,
+    entries: BTreeMap<String, String>,
+
+    /// The total size of the keys and values.
+    size: usize,
+
+    /// The thread writing the state file.
+    writer: Option<JoinHandle<()>>,
+}
+
+impl WmStore {
+    /// The default location of the state file.
+    pub fn default_path() -> Option<PathBuf> {
+        let state_home = env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))?;
+
+        Some(state_home.join("aerugo").join("wm-store"))
+    }
+
+    /// Load the store from the state file.
+    ///
+    /// A missing file is an empty store. Lines which cannot be parsed and values exceeding the limits are skipped.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => parse(&contents),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => {
+                tracing::warn!(%err, ?path, "Failed to read the wm store");
+                BTreeMap::new()
+            }
+        };
+
+        let mut store = Self {
+            path: Some(path),
+            ..Self::default()
+        };
+
+        for (key, value) in entries {
+            if let Err(err) = store.set(key.clone(), Some(value)) {
+                tracing::warn!(%err, key, "Skipping value in the wm store");
+            }
+        }
+
+        store
+    }
+
+    /// Every stored value, used to start the wm.
+    pub fn entries(&self) -> HashMap<String, String> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Set a value, or remove the value if the value is [`None`].
+    ///
+    /// The store is left unchanged if the value exceeds the limits of the store.
+    pub fn set(&mut self, key: String, value: Option<String>) -> Result<(), StoreError> {
+        if key.is_empty() || key.len() > MAX_STORE_KEY {
+            return Err(StoreError::InvalidKey);
+        }
+
+        let previous = self.entries.get(&key).map_or(0, |value| key.len() + value.len());
+
+        let Some(value) = value else {
+            self.entries.remove(&key);
+            self.size -= previous;
+            return Ok(());
+        };
+
+        if value.len() > MAX_STORE_VALUE {
+            return Err(StoreError::ValueTooLong);
+        }
+
+        if previous == 0 && self.entries.len() >= MAX_STORE_ENTRIES {
+            return Err(StoreError::TooManyEntries);
+        }
+
+        let size = self.size - previous + key.len() + value.len();
+
+        if size > MAX_STORE_SIZE {
+            return Err(StoreError::TooLarge);
+        }
+
+        self.size = size;
+        self.entries.insert(key, value);
+        Ok(())
+    }
+
+    /// Write the store to the state file on another thread.
+    ///
+    /// A previous write is waited for first, so an older store never replaces a newer one.
+    pub fn save(&mut self) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+
+        let contents = serialize(&self.entries);
+        self.wait();
+
+        let writer = thread::Builder::new().name("Wm store".into()).spawn(move || {
+            if let Err(err) = write(&path, &contents) {
+                tracing::warn!(%err, ?path, "Failed to write the wm store");
+            }
+        });
+
+        match writer {
+            Ok(writer) => self.writer = Some(writer),
+            Err(err) => tracing::warn!(%err, "Failed to write the wm store"),
+        }
+    }
+
+    /// Wait until the state file is written.
+    pub fn wait(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Write the contents to a temporary file which replaces the state file, so a crash while writing never leaves a
+/// truncated store behind.
+fn write(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)
+}
+
+fn escape(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '=' => out.push_str("\\="),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn serialize(entries: &BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+
+    for (key, value) in entries {
+        escape(key, &mut out);
+        out.push('=');
+        escape(value, &mut out);
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Parse a line into the key and value.
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut current = &mut key;
+    let mut in_value = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                'n' => current.push('\n'),
+                'r' => current.push('\r'),
+                c @ ('\\' | '=') => current.push(c),
+                _ => return None,
+            },
+            '=' if !in_value => {
+                in_value = true;
+                current = &mut value;
+            }
+            c => current.push(c),
+        }
+    }
+
+    (in_value && !key.is_empty()).then_some((key, value))
+}
+
+fn parse(contents: &str) -> BTreeMap<String, String> {
+    contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            let entry = parse_line(line);
+
+            if entry.is_none() {
+                tracing::warn!(line, "Skipping invalid line in the wm store");
+            }
+
+            entry
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use wm_runtime::{MAX_STORE_ENTRIES, MAX_STORE_KEY, MAX_STORE_VALUE};
+
+    use super::{parse, serialize, StoreError, WmStore};
+
+    #[test]
+    fn round_trip() {
+        let entries = BTreeMap::from([
+            ("layout".to_string(), "{\"workspaces\": 4}".to_string()),
+            ("a=b\\c".to_string(), "line 1\nline 2=x".to_string()),
+            ("crlf\r\n".to_string(), "line 1\r\nline 2\r".to_string()),
+            ("empty".to_string(), String::new()),
+        ]);
+
+        let contents = serialize(&entries);
+        assert!(!contents.contains('\r'));
+        assert_eq!(contents.lines().count(), 4);
+        assert_eq!(parse(&contents), entries);
+    }
+
+    #[test]
+    fn invalid_lines_are_skipped() {
+        let entries = parse("valid=1\nno separator\n=no key\nbad\\escape=2\n\nlast=a=b\n");
+
+        assert_eq!(
+            entries,
+            BTreeMap::from([
+                ("valid".to_string(), "1".to_string()),
+                ("last".to_string(), "a=b".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let mut store = WmStore::default();
+
+        assert_eq!(store.set(String::new(), Some("1".into())), Err(StoreError::InvalidKey));
+        assert_eq!(
+            store.set("k".repeat(MAX_STORE_KEY + 1), Some("1".into())),
+            Err(StoreError::InvalidKey)
+        );
+        assert_eq!(
+            store.set("key".into(), Some("v".repeat(MAX_STORE_VALUE + 1))),
+            Err(StoreError::ValueTooLong)
+        );
+
+        for i in 0..MAX_STORE_ENTRIES {
+            store.set(i.to_string(), Some(String::new())).unwrap();
+        }
+
+        assert_eq!(
+            store.set("key".into(), Some("1".into())),
+            Err(StoreError::TooManyEntries)
+        );
+
+        // Replacing and removing values is allowed with a full store.
+        store.set("0".into(), Some("1".into())).unwrap();
+        store.set("1".into(), None).unwrap();
+        store.set("key".into(), Some("1".into())).unwrap();
+        assert_eq!(store.entries().len(), MAX_STORE_ENTRIES);
+    }
+
+    #[test]
+    fn total_size() {
+        let mut store = WmStore::default();
+        let value = "v".repeat(MAX_STORE_VALUE);
+
+        for i in 0..15 {
+            store.set(i.to_string(), Some(value.clone())).unwrap();
+        }
+
+        assert_eq!(store.set("15".into(), Some(value.clone())), Err(StoreError::TooLarge));
+
+        store.set("0".into(), None).unwrap();
+        store.set("15".into(), Some(value)).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+//! Workspaces
+//!
+//! Workspaces (also known as virtual desktops) group toplevels. The wm creates and activates workspaces, and the
+//! display server tracks them so that they can be advertised to clients through ext-workspace and so
+//! foreign-toplevel clients know which workspace a toplevel is in.
+//!
+//! A workspace is assigned to at most one output and each output has at most one active workspace. A toplevel
+//! is in at most one workspace.
+
+use rustc_hash::FxHashMap;
+use slotmap::{new_key_type, SlotMap};
+use smithay::output::Output;
+
+use crate::shell::ToplevelId;
+
+new_key_type! {
+    pub struct WorkspaceId;
+}
+
+/// An error from managing workspaces.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0:?} does not exist")]
+    NotPresent(WorkspaceId),
+
+    #[error("{0:?} is not assigned to an output")]
+    NoOutput(WorkspaceId),
+}
+
+#[derive(Debug)]
+pub struct Workspace {
+    name: String,
+    output: Option<Output>,
+    active: bool,
+}
+
+impl Workspace {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Workspaces {
+    workspaces: SlotMap<WorkspaceId, Workspace>,
+    toplevels: FxHashMap<ToplevelId, WorkspaceId>,
+}
+
+impl Workspaces {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: WorkspaceId) -> Option<&Workspace> {
+        self.workspaces.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (WorkspaceId, &Workspace)> {
+        self.workspaces.iter()
+    }
+
+    /// Create a workspace.
+    ///
+    /// If the workspace is assigned to an output without an active workspace, the new workspace is activated.
+    pub fn create(&mut self, name: String, output: Option<Output>) -> WorkspaceId {
+        let active = output.as_ref().is_some_and(|output| self.active(output).is_none());
+
+        self.workspaces.insert(Workspace { name, output, active })
+    }
+
+    /// Destroy a workspace.
+    ///
+    /// Returns the toplevels which were in the workspace.
+    pub fn destroy(&mut self, id: WorkspaceId) -> Result<Vec<ToplevelId>, Error> {
+        self.workspaces.remove(id).ok_or(Error::NotPresent(id))?;
+
+        let toplevels = self
+            .toplevels
+            .iter()
+            .filter(|(_, &workspace)| workspace == id)
+            .map(|(&toplevel, _)| toplevel)
+            .collect::<Vec<_>>();
+
+        for toplevel in &toplevels {
+            self.toplevels.remove(toplevel);
+        }
+
+        Ok(toplevels)
+    }
+
+    pub fn rename(&mut self, id: WorkspaceId, name: String) -> Result<(), Error> {
+        self.workspaces.get_mut(id).ok_or(Error::NotPresent(id))?.name = name;
+        Ok(())
+    }
+
+    /// Assign a workspace to an output.
+    ///
+    /// The workspace is deactivated if it moves to another output.
+    pub fn assign(&mut self, id: WorkspaceId, output: Output) -> Result<(), Error> {
+        let workspace = self.workspaces.get_mut(id).ok_or(Error::NotPresent(id))?;
+
+        if workspace.output.as_ref() != Some(&output) {
+            workspace.output = Some(output);
+            workspace.active = false;
+        }
+
+        Ok(())
+    }
+
+    /// Make the workspace the active workspace of its output.
+    pub fn activate(&mut self, id: WorkspaceId) -> Result<(), Error> {
+        let output = self
+            .workspaces
+            .get(id)
+            .ok_or(Error::NotPresent(id))?
+            .output
+            .clone()
+            .ok_or(Error::NoOutput(id))?;
+
+        for (workspace_id, workspace) in self.workspaces.iter_mut() {
+            if workspace.output.as_ref() == Some(&output) {
+                workspace.active = workspace_id == id;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The active workspace of the output.
+    pub fn active(&self, output: &Output) -> Option<WorkspaceId> {
+        self.workspaces
+            .iter()
+            .find(|(_, workspace)| workspace.active && workspace.output.as_ref() == Some(output))
+            .map(|(id, _)| id)
+    }
+
+    /// Move a toplevel to a workspace, removing it from the previous workspace.
+    pub fn move_toplevel(&mut self, toplevel: ToplevelId, id: WorkspaceId) -> Result<(), Error> {
+        if !self.workspaces.contains_key(id) {
+            return Err(Error::NotPresent(id));
+        }
+
+        self.toplevels.insert(toplevel, id);
+        Ok(())
+    }
+
+    /// Remove a toplevel from its workspace, such as when the toplevel is closed.
+    pub fn remove_toplevel(&mut self, toplevel: ToplevelId) {
+        self.toplevels.remove(&toplevel);
+    }
+
+    pub fn workspace_of(&self, toplevel: ToplevelId) -> Option<WorkspaceId> {
+        self.toplevels.get(&toplevel).copied()
+    }
+
+    /// Unassign the workspaces of an output which was disconnected.
+    ///
+    /// The workspaces are kept so that they can be assigned to another output.
+    pub fn output_removed(&mut self, output: &Output) {
+        for workspace in self.workspaces.values_mut() {
+            if workspace.output.as_ref() == Some(output) {
+                workspace.output = None;
+                workspace.active = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::output::{Output, PhysicalProperties, Subpixel};
+
+    use crate::test_util::toplevel;
+
+    use super::{Error, Workspaces};
+
+    fn output(name: &str) -> Output {
+        Output::new(
+            name.into(),
+            PhysicalProperties {
+                size: (0, 0).into(),
+                subpixel: Subpixel::Unknown,
+                make: String::new(),
+                model: String::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn first_workspace_is_active() {
+        let output = output("test");
+        let mut workspaces = Workspaces::new();
+
+        let first = workspaces.create("1".into(), Some(output.clone()));
+        let second = workspaces.create("2".into(), Some(output.clone()));
+        let unassigned = workspaces.create("3".into(), None);
+
+        assert_eq!(workspaces.active(&output), Some(first));
+        assert!(!workspaces.get(second).unwrap().is_active());
+        assert!(!workspaces.get(unassigned).unwrap().is_active());
+    }
+
+    #[test]
+    fn activate_per_output() {
+        let a = output("a");
+        let b = output("b");
+        let mut workspaces = Workspaces::new();
+
+        let a1 = workspaces.create("1".into(), Some(a.clone()));
+        let a2 = workspaces.create("2".into(), Some(a.clone()));
+        let b1 = workspaces.create("3".into(), Some(b.clone()));
+
+        workspaces.activate(a2).unwrap();
+        assert_eq!(workspaces.active(&a), Some(a2));
+        assert!(!workspaces.get(a1).unwrap().is_active());
+        assert_eq!(workspaces.active(&b), Some(b1));
+
+        let unassigned = workspaces.create("4".into(), None);
+        assert!(matches!(workspaces.activate(unassigned), Err(Error::NoOutput(_))));
+    }
+
+    #[test]
+    fn destroy_returns_toplevels() {
+        let mut workspaces = Workspaces::new();
+        let first = workspaces.create("1".into(), None);
+        let second = workspaces.create("2".into(), None);
+        let toplevel = toplevel(1);
+
+        workspaces.move_toplevel(toplevel, first).unwrap();
+        workspaces.move_toplevel(toplevel, second).unwrap();
+        assert_eq!(workspaces.workspace_of(toplevel), Some(second));
+
+        assert!(workspaces.destroy(first).unwrap().is_empty());
+        assert_eq!(workspaces.destroy(second).unwrap(), vec![toplevel]);
+        assert_eq!(workspaces.workspace_of(toplevel), None);
+        assert!(matches!(workspaces.destroy(second), Err(Error::NotPresent(_))));
+    }
+}
@@ -0,0 +1,321 @@
+//! Output zoom
+//!
+//! Zoom magnifies part of an output. The zoomed viewport follows one of several targets:
+//!
+//! - The pointer, which is the default.
+//! - The keyboard focus, so the focused toplevel stays visible when switching windows.
+//! - The text caret, driven by the cursor rectangle of the text-input protocol, so magnifier users can type
+//!   without manually panning.
+//!
+//! When following the focus or caret, the viewport only pans once the target leaves the viewport (minus a
+//! margin). This avoids moving the view on every keystroke. If the caret follow mode is active but no caret
+//! rectangle is known (the focused client does not use text-input), the keyboard focus is followed instead.
+//!
+//! The wm sets the magnification level and the target. The backend draws the output into an offscreen buffer and
+//! scales the part of the buffer inside the viewport to fill the output.
+
+use smithay::{
+    output::Output,
+    utils::{Buffer, Logical, Point, Rectangle, Size},
+};
+
+use crate::Aerugo;
+
+/// The target the zoomed viewport follows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FollowMode {
+    /// Keep the pointer in the center of the viewport.
+    #[default]
+    Pointer,
+
+    /// Keep the keyboard focus inside the viewport.
+    Focus,
+
+    /// Keep the text caret inside the viewport.
+    Caret,
+}
+
+/// Fraction of the viewport on each side which the focus or caret should not enter before the viewport pans.
+const EDGE_MARGIN: f64 = 0.1;
+
+/// The highest magnification level.
+const MAX_LEVEL: f64 = 32.0;
+
+#[derive(Debug)]
+pub struct Zoom {
+    /// The magnification level. A level of 1.0 disables zoom.
+    level: f64,
+    mode: FollowMode,
+    /// The center of the viewport in output-local coordinates.
+    center: Point<f64, Logical>,
+    /// The geometry of the keyboard focus in output-local coordinates.
+    focus: Option<Rectangle<f64, Logical>>,
+    /// The text caret in output-local coordinates.
+    caret: Option<Rectangle<f64, Logical>>,
+    output_size: Size<f64, Logical>,
+}
+
+impl Zoom {
+    pub fn new(output_size: Size<f64, Logical>) -> Self {
+        Self {
+            level: 1.0,
+            mode: FollowMode::default(),
+            center: (output_size.w / 2.0, output_size.h / 2.0).into(),
+            focus: None,
+            caret: None,
+            output_size,
+        }
+    }
+
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    /// Set the magnification level.
+    ///
+    /// Levels are clamped between 1.0 and [`MAX_LEVEL`], and a level which is not a number disables zoom.
+    pub fn set_level(&mut self, level: f64) {
+        self.level = level.max(1.0).min(MAX_LEVEL);
+        self.follow();
+    }
+
+    pub fn mode(&self) -> FollowMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: FollowMode) {
+        self.mode = mode;
+        self.follow();
+    }
+
+    pub fn set_output_size(&mut self, size: Size<f64, Logical>) {
+        self.output_size = size;
+        self.follow();
+    }
+
+    /// Notify the zoom that the pointer has moved.
+    pub fn pointer_moved(&mut self, position: Point<f64, Logical>) {
+        if self.mode == FollowMode::Pointer {
+            self.center = position;
+            self.clamp_center();
+        }
+    }
+
+    /// Notify the zoom that the keyboard focus has changed or the focused toplevel moved.
+    ///
+    /// A new focus does not have a caret until the client sends one, so the caret is cleared.
+    pub fn focus_changed(&mut self, focus: Option<Rectangle<f64, Logical>>) {
+        self.focus = focus;
+        self.caret = None;
+        self.follow();
+    }
+
+    /// The geometry of the keyboard focus in output-local coordinates.
+    pub fn focus(&self) -> Option<Rectangle<f64, Logical>> {
+        self.focus
+    }
+
+    /// Notify the zoom that the text caret of the focused client has moved.
+    pub fn caret_moved(&mut self, caret: Option<Rectangle<f64, Logical>>) {
+        self.caret = caret;
+        self.follow();
+    }
+
+    /// The region of the output which is magnified to fill the output.
+    pub fn viewport(&self) -> Rectangle<f64, Logical> {
+        let size = self.viewport_size();
+        Rectangle::from_loc_and_size((self.center.x - size.w / 2.0, self.center.y - size.h / 2.0), size)
+    }
+
+    /// The part of a buffer with the size, holding the whole output, which is magnified to fill the output.
+    ///
+    /// Returns [`None`] if the output is not zoomed.
+    pub fn source(&self, size: Size<f64, Buffer>) -> Option<Rectangle<f64, Buffer>> {
+        if self.level == 1.0 || self.output_size.w <= 0.0 || self.output_size.h <= 0.0 {
+            return None;
+        }
+
+        let viewport = self.viewport();
+        let scale_x = size.w / self.output_size.w;
+        let scale_y = size.h / self.output_size.h;
+
+        Some(Rectangle::from_loc_and_size(
+            (viewport.loc.x * scale_x, viewport.loc.y * scale_y),
+            (viewport.size.w * scale_x, viewport.size.h * scale_y),
+        ))
+    }
+
+    fn viewport_size(&self) -> Size<f64, Logical> {
+        (self.output_size.w / self.level, self.output_size.h / self.level).into()
+    }
+
+    fn follow(&mut self) {
+        let target = match self.mode {
+            FollowMode::Pointer => None,
+            FollowMode::Focus => self.focus,
+            FollowMode::Caret => self.caret.or(self.focus),
+        };
+
+        if let Some(target) = target {
+            self.pan_to(target);
+        }
+
+        self.clamp_center();
+    }
+
+    /// Pan the viewport the least amount needed so the target is inside the viewport.
+    fn pan_to(&mut self, target: Rectangle<f64, Logical>) {
+        let viewport = self.viewport();
+        let margin_x = viewport.size.w * EDGE_MARGIN;
+        let margin_y = viewport.size.h * EDGE_MARGIN;
+
+        self.center.x += pan_axis(
+            viewport.loc.x + margin_x,
+            viewport.loc.x + viewport.size.w - margin_x,
+            target.loc.x,
+            target.loc.x + target.size.w,
+        );
+        self.center.y += pan_axis(
+            viewport.loc.y + margin_y,
+            viewport.loc.y + viewport.size.h - margin_y,
+            target.loc.y,
+            target.loc.y + target.size.h,
+        );
+    }
+
+    /// Keep the viewport inside the output.
+    fn clamp_center(&mut self) {
+        let size = self.viewport_size();
+        self.center.x = self
+            .center
+            .x
+            .clamp(size.w / 2.0, (self.output_size.w - size.w / 2.0).max(size.w / 2.0));
+        self.center.y = self
+            .center
+            .y
+            .clamp(size.h / 2.0, (self.output_size.h - size.h / 2.0).max(size.h / 2.0));
+    }
+}
+
+impl Aerugo {
+    /// Magnify the output by the level, following the target.
+    pub fn set_output_zoom(&mut self, output: &Output, level: f64, mode: FollowMode) {
+        if output != &self.output {
+            return;
+        }
+
+        tracing::debug!(output = %output.name(), level, ?mode, "Output zoom changed");
+        self.zoom.set_mode(mode);
+        self.zoom.set_level(level);
+        self.update_zoom_focus();
+        self.scene_changed();
+    }
+
+    /// Pan the zoomed viewport to the keyboard focus if the focused toplevel changed or moved.
+    pub fn update_zoom_focus(&mut self) {
+        let focus = self
+            .keyboard_focus
+            .and_then(|id| self.shell.get_state(id)?.wl_surface())
+            .and_then(|surface| self.scene.get_surface_tree_index(surface))
+            .and_then(|index| self.scene.surface_tree_geometry(index))
+            // The scene is scaled to the output when it is drawn.
+            .map(|geometry| geometry.to_f64().to_logical(1.0));
+
+        if focus == self.zoom.focus() {
+            return;
+        }
+
+        let viewport = self.zoom.viewport();
+        self.zoom.focus_changed(focus);
+
+        if self.zoom.viewport() != viewport {
+            self.scene_changed();
+        }
+    }
+}
+
+/// The distance to pan along an axis so `start..end` is inside `min..max`.
+///
+/// If the target is larger than the range, the start of the target is preferred so the beginning of a line of
+/// text stays visible.
+fn pan_axis(min: f64, max: f64, start: f64, end: f64) -> f64 {
+    if start < min || end - start > max - min {
+        start - min
+    } else if end > max {
+        end - max
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::utils::Rectangle;
+
+    use super::{FollowMode, Zoom};
+
+    fn zoom(mode: FollowMode) -> Zoom {
+        let mut zoom = Zoom::new((1000.0, 1000.0).into());
+        zoom.set_level(2.0);
+        zoom.set_mode(mode);
+        zoom
+    }
+
+    #[test]
+    fn pointer_centered_and_clamped() {
+        let mut zoom = zoom(FollowMode::Pointer);
+
+        zoom.pointer_moved((400.0, 600.0).into());
+        assert_eq!(
+            zoom.viewport(),
+            Rectangle::from_loc_and_size((150.0, 350.0), (500.0, 500.0))
+        );
+
+        zoom.pointer_moved((0.0, 1000.0).into());
+        assert_eq!(
+            zoom.viewport(),
+            Rectangle::from_loc_and_size((0.0, 500.0), (500.0, 500.0))
+        );
+    }
+
+    #[test]
+    fn caret_pans_only_when_leaving_viewport() {
+        let mut zoom = zoom(FollowMode::Caret);
+        let initial = zoom.viewport();
+
+        // The caret is inside the viewport, so the view does not move.
+        zoom.caret_moved(Some(Rectangle::from_loc_and_size((500.0, 500.0), (2.0, 20.0))));
+        assert_eq!(zoom.viewport(), initial);
+
+        // Typing past the right edge pans just enough to keep the caret inside the margin.
+        zoom.caret_moved(Some(Rectangle::from_loc_and_size((720.0, 500.0), (2.0, 20.0))));
+        let viewport = zoom.viewport();
+        assert_eq!(viewport.loc.y, initial.loc.y);
+        assert_eq!(viewport.loc.x + viewport.size.w * 0.9, 722.0);
+    }
+
+    #[test]
+    fn caret_falls_back_to_focus() {
+        let mut zoom = zoom(FollowMode::Caret);
+
+        zoom.focus_changed(Some(Rectangle::from_loc_and_size((0.0, 0.0), (100.0, 100.0))));
+        assert_eq!(zoom.viewport().loc, (0.0, 0.0).into());
+
+        // The pointer is ignored when following the caret.
+        zoom.pointer_moved((1000.0, 1000.0).into());
+        assert_eq!(zoom.viewport().loc, (0.0, 0.0).into());
+    }
+
+    #[test]
+    fn source_in_buffer_coordinates() {
+        let mut zoom = Zoom::new((1000.0, 1000.0).into());
+        assert_eq!(zoom.source((2000.0, 2000.0).into()), None);
+
+        zoom.set_level(2.0);
+        zoom.pointer_moved((400.0, 600.0).into());
+        assert_eq!(
+            zoom.source((2000.0, 2000.0).into()),
+            Some(Rectangle::from_loc_and_size((300.0, 700.0), (1000.0, 1000.0)))
+        );
+    }
+}
@@ -0,0 +1,44 @@
+//! Activation tokens created by the wm
+//!
+//! The wm passes a token to a process it spawns, so the app may activate its toplevel once it starts. The runtime
+//! creates the token itself so the wm receives the token right away, and tells the display server about the token
+//! with [`WmRequest::ActivationToken`](crate::WmRequest::ActivationToken).
+
+use std::{fmt::Write as _, io};
+
+use rustix::rand::{getrandom, GetRandomFlags};
+
+/// Create a token which cannot be guessed by clients.
+pub fn new_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    let mut filled = 0;
+
+    while filled < bytes.len() {
+        match getrandom(&mut bytes[filled..], GetRandomFlags::empty()) {
+            Ok(read) => filled += read,
+            Err(rustix::io::Errno::INTR) => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let mut token = String::with_capacity(bytes.len() * 2);
+
+    for byte in bytes {
+        let _ = write!(token, "{byte:02x}");
+    }
+
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::new_token;
+
+    #[test]
+    fn tokens() {
+        let token = new_token().unwrap();
+        assert_eq!(token.len(), 32);
+        assert!(token.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_ne!(token, new_token().unwrap());
+    }
+}
@@ -0,0 +1,737 @@
+//! Key and pointer bindings
+//!
+//! Bindings are matched by the runtime before key events are sent to the wm. Bindings are grouped into named
+//! modes (like sway modes) and only the bindings of the active mode are matched. This allows a wm to implement
+//! workflows such as a resize mode without tracking the mode in the wm.
+//!
+//! The [`DEFAULT_MODE`] is active when the runtime starts. Modes do not need to be created, a mode exists once a
+//! binding is added to it. A mode without bindings may still be active, in which case every key is sent to the
+//! wm.
+//!
+//! When a bound key is pressed, the press and the matching release are not sent to the wm or the client.
+//!
+//! # Key bindings
+//!
+//! Besides changing the mode, a key binding may notify the wm with the id of the binding when it is pressed.
+//! This lets the wm register its shortcuts instead of matching every key event itself.
+//!
+//! # Pointer bindings
+//!
+//! Pointer bindings match a button or scroll direction while modifiers are held. A pointer binding may be limited
+//! to some parts of the scene using a [`PointerContext`], such as the title bar of a toplevel or the background.
+//! The display server decides the context of each pointer event. When a pointer binding is triggered, the wm is
+//! notified with the id of the binding.
+//!
+//! # Conflicts
+//!
+//! Adding a binding reports conflicts to the wm, such as replacing an existing binding or shadowing a shortcut
+//! which applications commonly use. The list of application shortcuts is set by the configuration. Every
+//! binding may also be listed with its owner, so IPC clients can show which keys are taken.
+
+use std::collections::HashMap;
+
+use crate::host::aerugo::wm::types::{
+    BindingConflicts, ButtonState, KeyModifiers, KeyStatus, PointerCombo, PointerContext, PointerTrigger,
+    ScrollDirection,
+};
+
+/// The name of the mode which is active when the runtime starts.
+pub const DEFAULT_MODE: &str = "default";
+
+/// Shortcuts of common application actions: copy, paste, cut, undo, select all, save, find, close, new tab and
+/// quit.
+pub const DEFAULT_APP_SHORTCUTS: &[KeyCombo] = &[
+    KeyCombo::ctrl(0x63),
+    KeyCombo::ctrl(0x76),
+    KeyCombo::ctrl(0x78),
+    KeyCombo::ctrl(0x7a),
+    KeyCombo::ctrl(0x61),
+    KeyCombo::ctrl(0x73),
+    KeyCombo::ctrl(0x66),
+    KeyCombo::ctrl(0x77),
+    KeyCombo::ctrl(0x74),
+    KeyCombo::ctrl(0x71),
+];
+
+/// A key and the modifiers which must be held.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyCombo {
+    pub modifiers: KeyModifiers,
+    pub sym: u32,
+}
+
+impl KeyCombo {
+    const fn ctrl(sym: u32) -> Self {
+        Self {
+            modifiers: KeyModifiers::CTRL,
+            sym,
+        }
+    }
+
+    /// Whether the combo matches the key. Lock modifiers are ignored.
+    pub fn matches(&self, modifiers: KeyModifiers, sym: u32) -> bool {
+        self.sym == sym && modifiers_match(self.modifiers, modifiers)
+    }
+}
+
+/// Whether the modifiers are equal, ignoring lock modifiers.
+pub fn modifiers_match(a: KeyModifiers, b: KeyModifiers) -> bool {
+    let locks = KeyModifiers::CAPS_LOCK | KeyModifiers::NUM_LOCK;
+    (a & !locks) == (b & !locks)
+}
+
+fn pointer_combo_eq(a: &PointerCombo, b: &PointerCombo) -> bool {
+    let trigger = match (&a.trigger, &b.trigger) {
+        (PointerTrigger::Button(a), PointerTrigger::Button(b)) => a == b,
+        (PointerTrigger::Scroll(a), PointerTrigger::Scroll(b)) => a == b,
+        _ => false,
+    };
+
+    trigger && modifiers_match(a.modifiers, b.modifiers)
+}
+
+/// The direction of a scroll event.
+///
+/// The axis with the larger distance is used. Returns [`None`] if the pointer was not scrolled.
+pub fn scroll_direction(horizontal: f64, vertical: f64) -> Option<ScrollDirection> {
+    if horizontal == 0.0 && vertical == 0.0 {
+        return None;
+    }
+
+    Some(if vertical.abs() >= horizontal.abs() {
+        match vertical < 0.0 {
+            true => ScrollDirection::Up,
+            false => ScrollDirection::Down,
+        }
+    } else {
+        match horizontal < 0.0 {
+            true => ScrollDirection::Left,
+            false => ScrollDirection::Right,
+        }
+    })
+}
+
+#[derive(Debug, Clone)]
+struct PointerBinding {
+    id: u32,
+    combo: PointerCombo,
+    contexts: PointerContext,
+}
+
+/// What happens when a binding is pressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Make the mode the active mode.
+    EnterMode(String),
+
+    /// Return to the default mode.
+    ExitMode,
+
+    /// Notify the wm that the binding with the id was triggered.
+    Trigger(u32),
+}
+
+/// The result of matching a key against the bindings of the active mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyMatch {
+    /// The key is not bound and should be sent to the wm.
+    Unbound,
+
+    /// The key is bound and should not be sent to the wm or the client.
+    Bound,
+
+    /// The key is bound and changed the active mode.
+    ModeChanged(String),
+
+    /// The key binding with the id was triggered. The key should not be sent to the client.
+    Triggered(u32),
+}
+
+/// The result of matching a pointer event against the bindings of the active mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PointerMatch {
+    /// The event is not bound and should be sent to the wm.
+    Unbound,
+
+    /// The event is the release of a bound button and should not be sent to the wm or the client.
+    Bound,
+
+    /// The binding with the id was triggered. The event should not be sent to the client.
+    Triggered(u32),
+}
+
+/// What triggers a binding.
+#[derive(Debug, Clone)]
+pub enum Trigger {
+    Key(KeyCombo),
+    Pointer(PointerCombo),
+}
+
+/// Who added a binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingOwner {
+    /// The binding was added by the wm.
+    Wm,
+
+    /// The binding opens the built-in window switcher.
+    Switcher,
+}
+
+/// A binding listed for IPC clients.
+#[derive(Debug, Clone)]
+pub struct BindingInfo {
+    /// The mode of the binding, or [`None`] if the binding is matched in every mode.
+    pub mode: Option<String>,
+    pub trigger: Trigger,
+    /// What happens when the binding is triggered, or [`None`] if the binding is owned by the switcher.
+    pub action: Option<Action>,
+    pub owner: BindingOwner,
+}
+
+/// The bindings of a mode.
+#[derive(Debug, Default)]
+struct Mode {
+    keys: Vec<(KeyCombo, Action)>,
+    pointer: Vec<PointerBinding>,
+}
+
+#[derive(Debug)]
+pub struct Bindings {
+    modes: HashMap<String, Mode>,
+    active: String,
+    /// Keys which were pressed while bound, so the release is also consumed.
+    pressed: Vec<u32>,
+    /// Buttons which were pressed while bound, so the release is also consumed.
+    pressed_buttons: Vec<u32>,
+    /// The id of the next key or pointer binding.
+    next_id: u32,
+    /// Shortcuts used by applications which bindings should not shadow.
+    app_shortcuts: Vec<KeyCombo>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        Self {
+            modes: HashMap::new(),
+            active: DEFAULT_MODE.into(),
+            pressed: Vec::new(),
+            pressed_buttons: Vec::new(),
+            next_id: 1,
+            app_shortcuts: DEFAULT_APP_SHORTCUTS.to_vec(),
+        }
+    }
+
+    /// Set the shortcuts used by applications, which are reported as conflicts when bound.
+    pub fn set_app_shortcuts(&mut self, shortcuts: Vec<KeyCombo>) {
+        self.app_shortcuts = shortcuts;
+    }
+
+    /// The conflicts of binding the key combo in the mode.
+    ///
+    /// This must be called before the binding is added.
+    pub fn key_conflicts(&self, mode: &str, combo: KeyCombo) -> BindingConflicts {
+        let mut conflicts = BindingConflicts::empty();
+
+        if self.modes.get(mode).is_some_and(|mode| {
+            mode.keys
+                .iter()
+                .any(|(bound, _)| bound.matches(combo.modifiers, combo.sym))
+        }) {
+            conflicts |= BindingConflicts::REPLACED;
+        }
+
+        if self
+            .app_shortcuts
+            .iter()
+            .any(|shortcut| shortcut.matches(combo.modifiers, combo.sym))
+        {
+            conflicts |= BindingConflicts::SHADOWS_APP_SHORTCUT;
+        }
+
+        conflicts
+    }
+
+    /// The conflicts of binding the pointer combo in the mode.
+    ///
+    /// This must be called before the binding is added.
+    pub fn pointer_conflicts(&self, mode: &str, combo: &PointerCombo) -> BindingConflicts {
+        match self.modes.get(mode).is_some_and(|mode| {
+            mode.pointer
+                .iter()
+                .any(|binding| pointer_combo_eq(&binding.combo, combo))
+        }) {
+            true => BindingConflicts::REPLACED,
+            false => BindingConflicts::empty(),
+        }
+    }
+
+    /// Every binding, ordered by mode.
+    pub fn list(&self) -> Vec<BindingInfo> {
+        let mut modes = self.modes.iter().collect::<Vec<_>>();
+        modes.sort_by_key(|(name, _)| name.as_str());
+
+        modes
+            .into_iter()
+            .flat_map(|(name, mode)| {
+                let keys = mode.keys.iter().map(|(combo, action)| BindingInfo {
+                    mode: Some(name.clone()),
+                    trigger: Trigger::Key(*combo),
+                    action: Some(action.clone()),
+                    owner: BindingOwner::Wm,
+                });
+                let pointer = mode.pointer.iter().map(|binding| BindingInfo {
+                    mode: Some(name.clone()),
+                    trigger: Trigger::Pointer(binding.combo.clone()),
+                    action: Some(Action::Trigger(binding.id)),
+                    owner: BindingOwner::Wm,
+                });
+
+                keys.chain(pointer).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    pub fn active_mode(&self) -> &str {
+        &self.active
+    }
+
+    /// Make the mode the active mode.
+    ///
+    /// Returns [`true`] if the active mode changed.
+    pub fn set_mode(&mut self, mode: &str) -> bool {
+        if self.active == mode {
+            return false;
+        }
+
+        self.active = mode.into();
+        true
+    }
+
+    /// Add a binding to the mode, replacing the binding with the same combo.
+    pub fn bind(&mut self, mode: &str, combo: KeyCombo, action: Action) {
+        let bindings = &mut self.modes.entry(mode.into()).or_default().keys;
+        bindings.retain(|(bound, _)| !bound.matches(combo.modifiers, combo.sym));
+        bindings.push((combo, action));
+    }
+
+    /// Add a key binding to the mode which notifies the wm when triggered.
+    ///
+    /// A binding with the same combo in the mode is replaced. Returns the id of the binding.
+    pub fn bind_key(&mut self, mode: &str, combo: KeyCombo) -> u32 {
+        let id = self.alloc_id();
+        self.bind(mode, combo, Action::Trigger(id));
+        id
+    }
+
+    /// Remove the key binding with the id.
+    ///
+    /// Returns [`true`] if a binding was removed.
+    pub fn unbind_key(&mut self, id: u32) -> bool {
+        self.modes.values_mut().any(|mode| {
+            let len = mode.keys.len();
+            mode.keys.retain(|(_, action)| *action != Action::Trigger(id));
+            len != mode.keys.len()
+        })
+    }
+
+    /// Remove the binding with the combo from the mode.
+    ///
+    /// Returns [`true`] if a binding was removed.
+    pub fn unbind(&mut self, mode: &str, combo: KeyCombo) -> bool {
+        let Some(bindings) = self.modes.get_mut(mode).map(|mode| &mut mode.keys) else {
+            return false;
+        };
+
+        let len = bindings.len();
+        bindings.retain(|(bound, _)| !bound.matches(combo.modifiers, combo.sym));
+        len != bindings.len()
+    }
+
+    /// Match a key event against the bindings of the active mode.
+    pub fn key(&mut self, modifiers: KeyModifiers, sym: u32, status: KeyStatus) -> KeyMatch {
+        if let KeyStatus::Release = status {
+            return match self.pressed.iter().position(|&pressed| pressed == sym) {
+                Some(index) => {
+                    self.pressed.swap_remove(index);
+                    KeyMatch::Bound
+                }
+                None => KeyMatch::Unbound,
+            };
+        }
+
+        let action = self
+            .modes
+            .get(&self.active)
+            .and_then(|mode| mode.keys.iter().find(|(combo, _)| combo.matches(modifiers, sym)))
+            .map(|(_, action)| action.clone());
+
+        let Some(action) = action else {
+            return KeyMatch::Unbound;
+        };
+
+        self.pressed.push(sym);
+
+        let mode = match action {
+            Action::EnterMode(mode) => mode,
+            Action::ExitMode => DEFAULT_MODE.into(),
+            Action::Trigger(id) => return KeyMatch::Triggered(id),
+        };
+
+        match self.set_mode(&mode) {
+            true => KeyMatch::ModeChanged(mode),
+            false => KeyMatch::Bound,
+        }
+    }
+
+    /// Add a pointer binding to the mode which is only triggered in the contexts.
+    ///
+    /// A binding with the same combo in the mode is replaced. Returns the id of the binding.
+    pub fn bind_pointer(&mut self, mode: &str, combo: PointerCombo, contexts: PointerContext) -> u32 {
+        let id = self.alloc_id();
+
+        let bindings = &mut self.modes.entry(mode.into()).or_default().pointer;
+        bindings.retain(|binding| !pointer_combo_eq(&binding.combo, &combo));
+        bindings.push(PointerBinding { id, combo, contexts });
+        id
+    }
+
+    /// Remove the pointer binding with the id.
+    ///
+    /// Returns [`true`] if a binding was removed.
+    pub fn unbind_pointer(&mut self, id: u32) -> bool {
+        self.modes.values_mut().any(|mode| {
+            let len = mode.pointer.len();
+            mode.pointer.retain(|binding| binding.id != id);
+            len != mode.pointer.len()
+        })
+    }
+
+    /// Match a button event against the pointer bindings of the active mode.
+    pub fn pointer_button(
+        &mut self,
+        modifiers: KeyModifiers,
+        button: u32,
+        state: ButtonState,
+        context: PointerContext,
+    ) -> PointerMatch {
+        if let ButtonState::Released = state {
+            return match self.pressed_buttons.iter().position(|&pressed| pressed == button) {
+                Some(index) => {
+                    self.pressed_buttons.swap_remove(index);
+                    PointerMatch::Bound
+                }
+                None => PointerMatch::Unbound,
+            };
+        }
+
+        let combo = PointerCombo {
+            modifiers,
+            trigger: PointerTrigger::Button(button),
+        };
+
+        match self.find_pointer(&combo, context) {
+            Some(id) => {
+                self.pressed_buttons.push(button);
+                PointerMatch::Triggered(id)
+            }
+            None => PointerMatch::Unbound,
+        }
+    }
+
+    /// Match a scroll event against the pointer bindings of the active mode.
+    pub fn pointer_axis(
+        &mut self,
+        modifiers: KeyModifiers,
+        horizontal: f64,
+        vertical: f64,
+        context: PointerContext,
+    ) -> PointerMatch {
+        let Some(direction) = scroll_direction(horizontal, vertical) else {
+            return PointerMatch::Unbound;
+        };
+
+        let combo = PointerCombo {
+            modifiers,
+            trigger: PointerTrigger::Scroll(direction),
+        };
+
+        match self.find_pointer(&combo, context) {
+            Some(id) => PointerMatch::Triggered(id),
+            None => PointerMatch::Unbound,
+        }
+    }
+
+    fn alloc_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        id
+    }
+
+    fn find_pointer(&self, combo: &PointerCombo, context: PointerContext) -> Option<u32> {
+        self.modes
+            .get(&self.active)?
+            .pointer
+            .iter()
+            .find(|binding| binding.contexts.intersects(context) && pointer_combo_eq(&binding.combo, combo))
+            .map(|binding| binding.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::host::aerugo::wm::types::{
+        ButtonState, KeyModifiers, KeyStatus, PointerCombo, PointerContext, PointerTrigger, ScrollDirection,
+    };
+
+    use crate::host::aerugo::wm::types::BindingConflicts;
+
+    use super::{Action, BindingOwner, Bindings, KeyCombo, KeyMatch, PointerMatch, Trigger, DEFAULT_MODE};
+
+    const KEY_R: u32 = 0x72;
+    const KEY_ESCAPE: u32 = 0xff1b;
+
+    fn resize_mode() -> Bindings {
+        let mut bindings = Bindings::new();
+        let enter = KeyCombo {
+            modifiers: KeyModifiers::LOGO,
+            sym: KEY_R,
+        };
+        let exit = KeyCombo {
+            modifiers: KeyModifiers::empty(),
+            sym: KEY_ESCAPE,
+        };
+
+        bindings.bind(DEFAULT_MODE, enter, Action::EnterMode("resize".into()));
+        bindings.bind("resize", exit, Action::ExitMode);
+        bindings
+    }
+
+    #[test]
+    fn enter_and_exit_mode() {
+        let mut bindings = resize_mode();
+
+        // Escape is only bound in the resize mode.
+        assert_eq!(
+            bindings.key(KeyModifiers::empty(), KEY_ESCAPE, KeyStatus::Press),
+            KeyMatch::Unbound
+        );
+
+        assert_eq!(
+            bindings.key(KeyModifiers::LOGO, KEY_R, KeyStatus::Press),
+            KeyMatch::ModeChanged("resize".into())
+        );
+        assert_eq!(bindings.active_mode(), "resize");
+
+        // The binding to enter the mode is not bound in the resize mode.
+        assert_eq!(
+            bindings.key(KeyModifiers::LOGO, KEY_R, KeyStatus::Press),
+            KeyMatch::Unbound
+        );
+
+        assert_eq!(
+            bindings.key(KeyModifiers::empty(), KEY_ESCAPE, KeyStatus::Press),
+            KeyMatch::ModeChanged(DEFAULT_MODE.into())
+        );
+        assert_eq!(bindings.active_mode(), DEFAULT_MODE);
+    }
+
+    #[test]
+    fn release_of_bound_key_consumed() {
+        let mut bindings = resize_mode();
+
+        bindings.key(KeyModifiers::LOGO, KEY_R, KeyStatus::Press);
+        // The modifier may be released before the key.
+        assert_eq!(
+            bindings.key(KeyModifiers::empty(), KEY_R, KeyStatus::Release),
+            KeyMatch::Bound
+        );
+        assert_eq!(
+            bindings.key(KeyModifiers::empty(), KEY_R, KeyStatus::Release),
+            KeyMatch::Unbound
+        );
+    }
+
+    #[test]
+    fn lock_modifiers_ignored() {
+        let mut bindings = resize_mode();
+        let combo = KeyCombo {
+            modifiers: KeyModifiers::LOGO,
+            sym: KEY_R,
+        };
+
+        assert_eq!(
+            bindings.key(KeyModifiers::LOGO | KeyModifiers::NUM_LOCK, KEY_R, KeyStatus::Press),
+            KeyMatch::ModeChanged("resize".into())
+        );
+
+        bindings.set_mode(DEFAULT_MODE);
+        assert!(bindings.unbind(DEFAULT_MODE, combo));
+        assert!(!bindings.unbind(DEFAULT_MODE, combo));
+        assert_eq!(
+            bindings.key(KeyModifiers::LOGO, KEY_R, KeyStatus::Press),
+            KeyMatch::Unbound
+        );
+    }
+
+    #[test]
+    fn key_binding_ids() {
+        let mut bindings = resize_mode();
+        let combo = KeyCombo {
+            modifiers: KeyModifiers::LOGO,
+            sym: KEY_ESCAPE,
+        };
+
+        let id = bindings.bind_key(DEFAULT_MODE, combo);
+        assert_eq!(
+            bindings.key(KeyModifiers::LOGO, KEY_ESCAPE, KeyStatus::Press),
+            KeyMatch::Triggered(id)
+        );
+        assert_eq!(
+            bindings.key(KeyModifiers::LOGO, KEY_ESCAPE, KeyStatus::Release),
+            KeyMatch::Bound
+        );
+
+        // Rebinding the combo replaces the binding.
+        let replaced = bindings.bind_key(DEFAULT_MODE, combo);
+        assert_ne!(id, replaced);
+        assert!(!bindings.unbind_key(id));
+        assert!(bindings.unbind_key(replaced));
+        assert_eq!(
+            bindings.key(KeyModifiers::LOGO, KEY_ESCAPE, KeyStatus::Press),
+            KeyMatch::Unbound
+        );
+    }
+
+    #[test]
+    fn key_binding_in_mode() {
+        let mut bindings = resize_mode();
+        let combo = KeyCombo {
+            modifiers: KeyModifiers::empty(),
+            sym: KEY_R,
+        };
+
+        let id = bindings.bind_key("resize", combo);
+        assert_eq!(
+            bindings.key(KeyModifiers::empty(), KEY_R, KeyStatus::Press),
+            KeyMatch::Unbound
+        );
+
+        bindings.set_mode("resize");
+        assert_eq!(
+            bindings.key(KeyModifiers::empty(), KEY_R, KeyStatus::Press),
+            KeyMatch::Triggered(id)
+        );
+    }
+
+    #[test]
+    fn pointer_contexts() {
+        const BTN_LEFT: u32 = 0x110;
+        let mut bindings = Bindings::new();
+
+        let cycle = bindings.bind_pointer(
+            DEFAULT_MODE,
+            PointerCombo {
+                modifiers: KeyModifiers::empty(),
+                trigger: PointerTrigger::Scroll(ScrollDirection::Down),
+            },
+            PointerContext::ROOT,
+        );
+        let drag = bindings.bind_pointer(
+            DEFAULT_MODE,
+            PointerCombo {
+                modifiers: KeyModifiers::LOGO,
+                trigger: PointerTrigger::Button(BTN_LEFT),
+            },
+            PointerContext::SURFACE | PointerContext::TITLEBAR | PointerContext::BORDER,
+        );
+
+        // Scrolling on the background cycles workspaces, scrolling over a toplevel goes to the client.
+        assert_eq!(
+            bindings.pointer_axis(KeyModifiers::empty(), 0.0, 10.0, PointerContext::ROOT),
+            PointerMatch::Triggered(cycle)
+        );
+        assert_eq!(
+            bindings.pointer_axis(KeyModifiers::empty(), 0.0, 10.0, PointerContext::SURFACE),
+            PointerMatch::Unbound
+        );
+        assert_eq!(
+            bindings.pointer_axis(KeyModifiers::empty(), 0.0, -10.0, PointerContext::ROOT),
+            PointerMatch::Unbound
+        );
+
+        assert_eq!(
+            bindings.pointer_button(
+                KeyModifiers::LOGO,
+                BTN_LEFT,
+                ButtonState::Pressed,
+                PointerContext::TITLEBAR
+            ),
+            PointerMatch::Triggered(drag)
+        );
+        assert_eq!(
+            bindings.pointer_button(
+                KeyModifiers::empty(),
+                BTN_LEFT,
+                ButtonState::Released,
+                PointerContext::SURFACE
+            ),
+            PointerMatch::Bound
+        );
+
+        assert!(bindings.unbind_pointer(drag));
+        assert_eq!(
+            bindings.pointer_button(
+                KeyModifiers::LOGO,
+                BTN_LEFT,
+                ButtonState::Pressed,
+                PointerContext::TITLEBAR
+            ),
+            PointerMatch::Unbound
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        const KEY_C: u32 = 0x63;
+        let mut bindings = resize_mode();
+        let copy = KeyCombo {
+            modifiers: KeyModifiers::CTRL,
+            sym: KEY_C,
+        };
+        let enter = KeyCombo {
+            modifiers: KeyModifiers::LOGO,
+            sym: KEY_R,
+        };
+
+        assert_eq!(
+            bindings.key_conflicts(DEFAULT_MODE, copy),
+            BindingConflicts::SHADOWS_APP_SHORTCUT
+        );
+        assert_eq!(bindings.key_conflicts(DEFAULT_MODE, enter), BindingConflicts::REPLACED);
+        // The combo is only bound in the default mode.
+        assert_eq!(bindings.key_conflicts("resize", enter), BindingConflicts::empty());
+
+        bindings.set_app_shortcuts(Vec::new());
+        assert_eq!(bindings.key_conflicts(DEFAULT_MODE, copy), BindingConflicts::empty());
+    }
+
+    #[test]
+    fn list_bindings() {
+        let mut bindings = resize_mode();
+        let id = bindings.bind_key(
+            "resize",
+            KeyCombo {
+                modifiers: KeyModifiers::empty(),
+                sym: KEY_R,
+            },
+        );
+
+        let list = bindings.list();
+        let modes = list.iter().map(|binding| binding.mode.as_deref()).collect::<Vec<_>>();
+        assert_eq!(modes, [Some(DEFAULT_MODE), Some("resize"), Some("resize")]);
+
+        assert!(list.iter().all(|binding| binding.owner == BindingOwner::Wm));
+        assert!(matches!(list[0].trigger, Trigger::Key(KeyCombo { sym: KEY_R, .. })));
+        assert_eq!(list[2].action, Some(Action::Trigger(id)));
+    }
+}
@@ -0,0 +1,301 @@
+//! Click gestures
+//!
+//! Double clicks, triple clicks and long presses are detected by the runtime from pointer buttons and touch
+//! points, so every wm handles them the same way and a wm does not need timers to tell a double click from two
+//! clicks.
+//!
+//! Presses are counted when the same button is pressed again within the interval without moving further than the
+//! distance. Touch points are counted like a single button, since each tap is usually a new touch point. A long
+//! press fires once a button or touch point is held for the long press duration without moving further than the
+//! distance. The underlying button and touch events are still sent as usual.
+
+use std::{collections::HashMap, time::Duration};
+
+use crate::{
+    host::aerugo::wm::types::{ClickConfig, ClickKind, DecorationRegion, Point},
+    Id,
+};
+
+pub const DEFAULT_CONFIG: ClickConfig = ClickConfig {
+    interval: 400,
+    distance: 4,
+    long_press: 500,
+};
+
+/// What was pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    Button(u32),
+    Touch(i32),
+}
+
+impl Source {
+    /// Whether presses of the sources are counted together.
+    fn repeats(self, other: Source) -> bool {
+        match (self, other) {
+            (Source::Button(a), Source::Button(b)) => a == b,
+            (Source::Touch(_), Source::Touch(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A button or touch point being pressed.
+#[derive(Debug, Clone, Copy)]
+pub struct Press {
+    pub source: Source,
+    pub time: u32,
+    pub position: Point,
+    /// The toplevel under the pointer or touch point.
+    pub focus: Option<Id>,
+    /// The region of the server side decorations which was pressed.
+    pub decoration: Option<DecorationRegion>,
+}
+
+#[derive(Debug)]
+struct LastPress {
+    source: Source,
+    time: u32,
+    position: Point,
+    count: u32,
+}
+
+#[derive(Debug)]
+struct Held {
+    press: Press,
+    timer: u32,
+}
+
+#[derive(Debug)]
+pub struct Clicks {
+    config: ClickConfig,
+    /// The last press of each seat, used to count clicks.
+    last: HashMap<Id, LastPress>,
+    /// Buttons and touch points which may become a long press.
+    held: HashMap<(Id, Source), Held>,
+}
+
+impl Default for Clicks {
+    fn default() -> Self {
+        Self {
+            config: DEFAULT_CONFIG,
+            last: HashMap::new(),
+            held: HashMap::new(),
+        }
+    }
+}
+
+impl Clicks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_config(&mut self, config: ClickConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> ClickConfig {
+        self.config
+    }
+
+    /// How long a press must be held to become a long press, or [`None`] if long presses are disabled.
+    pub fn long_press(&self) -> Option<Duration> {
+        (self.config.long_press > 0).then(|| Duration::from_millis(self.config.long_press.into()))
+    }
+
+    fn near(&self, a: Point, b: Point) -> bool {
+        (a.x - b.x).hypot(a.y - b.y) <= f64::from(self.config.distance)
+    }
+
+    /// A button or touch point was pressed.
+    ///
+    /// `timer` is the timer which fires the long press, if long presses are enabled. Returns the gesture if the
+    /// press completed a double or triple click.
+    pub fn press(&mut self, seat: Id, press: Press, timer: Option<u32>) -> Option<ClickKind> {
+        let count = match self.last.get(&seat) {
+            Some(last)
+                if last.source.repeats(press.source)
+                    && last.count < 3
+                    && press.time.wrapping_sub(last.time) <= self.config.interval
+                    && self.near(last.position, press.position) =>
+            {
+                last.count + 1
+            }
+            _ => 1,
+        };
+
+        self.last.insert(
+            seat,
+            LastPress {
+                source: press.source,
+                time: press.time,
+                position: press.position,
+                count,
+            },
+        );
+
+        if let Some(timer) = timer {
+            self.held.insert((seat, press.source), Held { press, timer });
+        }
+
+        match count {
+            2 => Some(ClickKind::DoubleClick),
+            3 => Some(ClickKind::TripleClick),
+            _ => None,
+        }
+    }
+
+    /// A button or touch point was released.
+    ///
+    /// Returns the timer of the long press which no longer fires.
+    pub fn release(&mut self, seat: Id, source: Source) -> Option<u32> {
+        self.held.remove(&(seat, source)).map(|held| held.timer)
+    }
+
+    /// The pointer of the seat, or a touch point if the source is given, moved.
+    ///
+    /// Returns the timers of the long presses which moved too far to fire.
+    pub fn motion(&mut self, seat: Id, source: Option<Source>, position: Point) -> Vec<u32> {
+        let mut cancelled = Vec::new();
+        let config = self.config;
+
+        self.held.retain(|&(held_seat, held_source), held| {
+            let moved = held_seat == seat
+                && match source {
+                    Some(source) => held_source == source,
+                    None => matches!(held_source, Source::Button(_)),
+                };
+
+            let far = (held.press.position.x - position.x).hypot(held.press.position.y - position.y)
+                > f64::from(config.distance);
+
+            if moved && far {
+                cancelled.push(held.timer);
+                return false;
+            }
+
+            true
+        });
+
+        cancelled
+    }
+
+    /// The touch session of the seat was cancelled.
+    ///
+    /// Returns the timers of the long presses which no longer fire.
+    pub fn touch_cancel(&mut self, seat: Id) -> Vec<u32> {
+        let mut cancelled = Vec::new();
+
+        self.held.retain(|&(held_seat, source), held| {
+            if held_seat == seat && matches!(source, Source::Touch(_)) {
+                cancelled.push(held.timer);
+                return false;
+            }
+
+            true
+        });
+
+        cancelled
+    }
+
+    /// A timer expired, which fires the long press it belongs to.
+    ///
+    /// Returns the seat and the press which became a long press.
+    pub fn timer_expired(&mut self, timer: u32) -> Option<(Id, Press)> {
+        let key = *self
+            .held
+            .iter()
+            .find(|(_, held)| held.timer == timer)
+            .map(|(key, _)| key)?;
+
+        let (seat, _) = key;
+        let held = self.held.remove(&key)?;
+
+        // The press after a long press starts counting again.
+        self.last.remove(&seat);
+        Some((seat, held.press))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use crate::{
+        host::aerugo::wm::types::{ClickKind, Point},
+        Id, IdType,
+    };
+
+    use super::{Clicks, Press, Source};
+
+    fn seat() -> Id {
+        Id(NonZeroU32::new(1).unwrap(), IdType::Seat)
+    }
+
+    fn press(source: Source, time: u32, x: f64) -> Press {
+        Press {
+            source,
+            time,
+            position: Point { x, y: 0.0 },
+            focus: None,
+            decoration: None,
+        }
+    }
+
+    #[test]
+    fn multiple_clicks() {
+        let left = Source::Button(0x110);
+        let mut clicks = Clicks::new();
+
+        assert_eq!(clicks.press(seat(), press(left, 0, 0.0), None), None);
+        assert_eq!(
+            clicks.press(seat(), press(left, 300, 2.0), None),
+            Some(ClickKind::DoubleClick)
+        );
+        assert_eq!(
+            clicks.press(seat(), press(left, 600, 2.0), None),
+            Some(ClickKind::TripleClick)
+        );
+
+        // A fourth press starts counting again.
+        assert_eq!(clicks.press(seat(), press(left, 700, 2.0), None), None);
+
+        // Too slow, too far and a different button do not count.
+        assert_eq!(clicks.press(seat(), press(left, 1200, 2.0), None), None);
+        assert_eq!(clicks.press(seat(), press(left, 1300, 20.0), None), None);
+        assert_eq!(
+            clicks.press(seat(), press(Source::Button(0x111), 1400, 20.0), None),
+            None
+        );
+
+        // Each tap is a new touch point.
+        assert_eq!(clicks.press(seat(), press(Source::Touch(0), 2000, 0.0), None), None);
+        assert_eq!(
+            clicks.press(seat(), press(Source::Touch(1), 2100, 1.0), None),
+            Some(ClickKind::DoubleClick)
+        );
+    }
+
+    #[test]
+    fn long_press() {
+        let touch = Source::Touch(0);
+        let mut clicks = Clicks::new();
+
+        clicks.press(seat(), press(touch, 0, 0.0), Some(7));
+        assert!(clicks.motion(seat(), Some(touch), Point { x: 3.0, y: 0.0 }).is_empty());
+        assert!(clicks.motion(seat(), None, Point { x: 100.0, y: 0.0 }).is_empty());
+
+        let (fired_seat, fired) = clicks.timer_expired(7).unwrap();
+        assert_eq!(fired_seat, seat());
+        assert_eq!(fired.source, touch);
+        assert!(clicks.timer_expired(7).is_none());
+
+        // Releasing or moving away cancels the long press.
+        clicks.press(seat(), press(touch, 1000, 0.0), Some(8));
+        assert_eq!(clicks.release(seat(), touch), Some(8));
+
+        clicks.press(seat(), press(touch, 2000, 0.0), Some(9));
+        assert_eq!(clicks.motion(seat(), Some(touch), Point { x: 10.0, y: 0.0 }), [9]);
+        assert!(clicks.timer_expired(9).is_none());
+    }
+}
@@ -0,0 +1,194 @@
+//! Screen edge triggers
+//!
+//! Hot corners and docks revealed at a screen edge need to know when the pointer reaches an edge. Rather than
+//! having the wm check every pointer motion, the runtime matches motion against the edge triggers of the wm and
+//! only calls the wm when a trigger fires.
+//!
+//! A trigger fires when the pointer of a seat enters the area within the threshold of the edge or corner. The
+//! trigger does not fire again for the seat until the pointer has left the area, so pushing against the edge does
+//! not fire the trigger repeatedly.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    host::aerugo::wm::types::{Point, ScreenEdge},
+    placement::Rect,
+    Id,
+};
+
+#[derive(Debug)]
+struct EdgeTrigger {
+    output: Id,
+    edge: ScreenEdge,
+    threshold: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct EdgeTriggers {
+    triggers: HashMap<u32, EdgeTrigger>,
+    /// The triggers each seat's pointer is currently inside of.
+    entered: HashSet<(u32, Id)>,
+    /// The id of the next trigger.
+    next: u32,
+}
+
+impl EdgeTriggers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a trigger at the edge of the output.
+    pub fn add(&mut self, output: Id, edge: ScreenEdge, threshold: u32) -> u32 {
+        // Skip ids of triggers which still exist if the id wrapped around.
+        let mut id = self.next;
+        while self.triggers.contains_key(&id) {
+            id = id.wrapping_add(1);
+        }
+
+        self.next = id.wrapping_add(1);
+        self.triggers.insert(
+            id,
+            EdgeTrigger {
+                output,
+                edge,
+                threshold,
+            },
+        );
+
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.triggers.remove(&id);
+        self.entered.retain(|&(trigger, _)| trigger != id);
+    }
+
+    /// Remove the triggers of a disconnected output.
+    pub fn remove_output(&mut self, output: Id) {
+        let removed = self
+            .triggers
+            .iter()
+            .filter(|(_, trigger)| trigger.output == output)
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+
+        for id in removed {
+            self.remove(id);
+        }
+    }
+
+    /// The pointer of the seat moved to the position in the global compositor space.
+    ///
+    /// Returns the triggers which fired, ordered by id.
+    pub fn motion(&mut self, seat: Id, position: Point, outputs: impl Fn(Id) -> Option<Rect>) -> Vec<u32> {
+        let mut fired = Vec::new();
+
+        for (&id, trigger) in &self.triggers {
+            let inside = outputs(trigger.output)
+                .is_some_and(|output| in_area(output, trigger.edge, trigger.threshold, position));
+
+            if !inside {
+                self.entered.remove(&(id, seat));
+            } else if self.entered.insert((id, seat)) {
+                fired.push(id);
+            }
+        }
+
+        fired.sort_unstable();
+        fired
+    }
+}
+
+/// Whether the position is within the threshold of the edge of the output.
+fn in_area(output: Rect, edge: ScreenEdge, threshold: u32, position: Point) -> bool {
+    let left = f64::from(output.x);
+    let top = f64::from(output.y);
+    let right = left + f64::from(output.width);
+    let bottom = top + f64::from(output.height);
+
+    if !(left..right).contains(&position.x) || !(top..bottom).contains(&position.y) {
+        return false;
+    }
+
+    // The pixel at the edge is always inside the area, even with a threshold of 0.
+    let reach = f64::from(threshold) + 1.0;
+    let near_left = position.x < left + reach;
+    let near_right = position.x >= right - reach;
+    let near_top = position.y < top + reach;
+    let near_bottom = position.y >= bottom - reach;
+
+    match edge {
+        ScreenEdge::Top => near_top,
+        ScreenEdge::Bottom => near_bottom,
+        ScreenEdge::Left => near_left,
+        ScreenEdge::Right => near_right,
+        ScreenEdge::TopLeft => near_top && near_left,
+        ScreenEdge::TopRight => near_top && near_right,
+        ScreenEdge::BottomLeft => near_bottom && near_left,
+        ScreenEdge::BottomRight => near_bottom && near_right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use crate::{
+        host::aerugo::wm::types::{Point, ScreenEdge},
+        placement::Rect,
+        Id, IdType,
+    };
+
+    use super::{in_area, EdgeTriggers};
+
+    const OUTPUT: Rect = Rect {
+        x: 1920,
+        y: 0,
+        width: 1920,
+        height: 1080,
+    };
+
+    fn id(rep: u32, ty: IdType) -> Id {
+        Id(NonZeroU32::new(rep).unwrap(), ty)
+    }
+
+    #[test]
+    fn areas() {
+        let point = |x, y| Point { x, y };
+
+        assert!(in_area(OUTPUT, ScreenEdge::TopLeft, 0, point(1920.5, 0.0)));
+        assert!(!in_area(OUTPUT, ScreenEdge::TopLeft, 0, point(1921.0, 0.0)));
+        assert!(in_area(OUTPUT, ScreenEdge::TopLeft, 4, point(1924.0, 4.0)));
+        assert!(in_area(OUTPUT, ScreenEdge::Bottom, 0, point(3000.0, 1079.5)));
+        assert!(in_area(OUTPUT, ScreenEdge::Right, 2, point(3837.0, 500.0)));
+        assert!(!in_area(OUTPUT, ScreenEdge::Right, 2, point(3836.0, 500.0)));
+
+        // The left edge of this output is the right edge of the output beside it.
+        assert!(!in_area(OUTPUT, ScreenEdge::Left, 0, point(1919.5, 500.0)));
+    }
+
+    #[test]
+    fn fire_once_per_entry() {
+        let output = id(1, IdType::Output);
+        let seat = id(2, IdType::Seat);
+        let other_seat = id(3, IdType::Seat);
+        let outputs = |id: Id| (id == output).then_some(OUTPUT);
+
+        let mut triggers = EdgeTriggers::new();
+        let corner = triggers.add(output, ScreenEdge::TopLeft, 0);
+        let top = triggers.add(output, ScreenEdge::Top, 0);
+
+        let position = Point { x: 1920.0, y: 0.0 };
+        assert_eq!(triggers.motion(seat, position, outputs), [corner, top]);
+        assert!(triggers.motion(seat, position, outputs).is_empty());
+        assert_eq!(triggers.motion(other_seat, position, outputs), [corner, top]);
+
+        let away = Point { x: 2500.0, y: 0.0 };
+        assert!(triggers.motion(seat, away, outputs).is_empty());
+        assert_eq!(triggers.motion(seat, position, outputs), [corner]);
+
+        triggers.remove_output(output);
+        assert!(triggers.motion(seat, Point { x: 3000.0, y: 500.0 }, outputs).is_empty());
+        assert!(triggers.motion(seat, position, outputs).is_empty());
+    }
+}
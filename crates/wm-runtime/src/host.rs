@@ -2,26 +2,72 @@
 //!
 //! This crate implements the wm runtime used by Aerugo.
 
-use std::num::NonZeroU32;
+use std::{num::NonZeroU32, time::Duration};
 
 use wasmtime::component::Resource;
 
-use crate::{ConfigureUpdate, Id, IdError, IdType, WmRequest, WmState, WmToplevelConfigure};
+use crate::{
+    activation,
+    binding::{self, Action},
+    images, layout,
+    placement::{self, Placement, Rect},
+    sanitize_render, snapshot_size,
+    text::FontStack,
+    ConfigureUpdate, Id, IdError, IdType, NodeKind, SnapshotInfo, WmCursor, WmOutputConfigure, WmRequest, WmState,
+    WmToplevelConfigure, WmToplevelGroup, WmWorkspace, MAX_STORE_ENTRIES, MAX_STORE_KEY, MAX_STORE_SIZE,
+    MAX_STORE_VALUE,
+};
 
 use self::aerugo::wm::types::{
-    DecorationMode, Features, Focus, Geometry, Host, HostOutput, HostServer, HostSnapshot, HostToplevel,
-    HostToplevelConfigure, HostView, HostViewBuilder, Output, OutputId, ResizeEdge, Server, Size, Snapshot, Toplevel,
-    ToplevelConfigure, ToplevelId, ToplevelState, View, ViewBuilder,
+    BindingConflicts, BindingId, ClickConfig, Color, CursorShape, DecorationMode, Features, Focus, Font, Geometry,
+    Host, HostLayerSurface, HostNode, HostOutput, HostOutputConfigure, HostPopup, HostSeat, HostServer, HostSnapshot,
+    HostToplevel, HostToplevelConfigure, HostToplevelGroup, HostWorkspace, ImageSource, KeyCombo, LayerSurface,
+    LayerSurfaceId, LayerSurfaceState, LayoutChild, Node, Output, OutputConfigure, OutputEdge, OutputId, OutputMode,
+    OutputTransform, Point, PointerCombo, PointerContext, Popup, PopupId, PopupParent, Positioner, ProcessId,
+    RenderProperties, ResizeEdge, ScreenEdge, Seat, SeatCapabilities, SeatId, SelectionData, SelectionKind, Server,
+    ServerDecorations, Size, Snapshot, SplitDirection, Subpixel, SwitchKind, TabLayout, Texture, TimerId, Toplevel,
+    ToplevelConfigure, ToplevelGroup, ToplevelGroupId, ToplevelId, ToplevelState, TriggerId, VrrMode, WindowRules,
+    Workspace, WorkspaceId, XkbRules, ZoomFollow,
 };
 
 wasmtime::component::bindgen!(in "../../wm.wit");
 
+impl From<KeyCombo> for binding::KeyCombo {
+    fn from(value: KeyCombo) -> Self {
+        Self {
+            modifiers: value.modifiers,
+            sym: value.sym,
+        }
+    }
+}
+
 impl Host for WmState {}
 
 impl HostServer for WmState {
-    fn set_keyboard_focus(&mut self, server: Resource<Server>, _focus: Focus) -> wasmtime::Result<()> {
+    fn set_keyboard_focus(&mut self, server: Resource<Server>, focus: Focus) -> wasmtime::Result<()> {
         self.validate_id_server(&server)?;
-        todo!()
+
+        let focus = match focus {
+            Focus::None => None,
+            Focus::Toplevel(rep) => {
+                let toplevel =
+                    NonZeroU32::new(rep)
+                        .and_then(|rep| self.toplevels.get(&rep))
+                        .ok_or(IdError::InvalidId {
+                            rep,
+                            ty: IdType::Toplevel,
+                        })?;
+
+                Some(toplevel.id)
+            }
+        };
+
+        if let Some(id) = focus {
+            self.switcher.focused(id);
+        }
+
+        let _ = self.sender.send(WmRequest::KeyboardFocus(focus));
+        Ok(())
     }
 
     fn set_pointer_focus(&mut self, server: Resource<Server>, _focus: Focus) -> wasmtime::Result<()> {
@@ -29,6 +75,603 @@ impl HostServer for WmState {
         todo!()
     }
 
+    fn focus(&mut self, server: Resource<Server>, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let id = self.get_toplevel_res(&toplevel)?.id;
+        self.switcher.focused(id);
+
+        let _ = self.sender.send(WmRequest::KeyboardFocus(Some(id)));
+        Ok(())
+    }
+
+    fn clear_focus(&mut self, server: Resource<Server>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        let _ = self.sender.send(WmRequest::KeyboardFocus(None));
+        Ok(())
+    }
+
+    fn place_floating(
+        &mut self,
+        server: Resource<Server>,
+        toplevel: Resource<Toplevel>,
+        area: Geometry,
+        focused: Option<Resource<Toplevel>>,
+    ) -> wasmtime::Result<Geometry> {
+        self.validate_id_server(&server)?;
+
+        let focused = match focused {
+            Some(focused) => self.get_toplevel_res(&focused)?.geometry.map(Rect::from),
+            None => None,
+        };
+
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        let id = toplevel.id;
+        let app_id = toplevel.app_id.clone();
+        let size = toplevel
+            .geometry
+            .map(|geometry| Size {
+                width: geometry.width,
+                height: geometry.height,
+            })
+            .or(toplevel.min_size);
+        let parent = toplevel
+            .parent
+            .and_then(|parent| self.toplevels.get(&parent.rep()))
+            .and_then(|parent| parent.geometry)
+            .map(Rect::from);
+
+        // Only cascade toplevels from the same app.
+        let siblings = self
+            .toplevels
+            .values()
+            .filter(|sibling| sibling.id != id && app_id.is_some() && sibling.app_id == app_id)
+            .filter_map(|sibling| sibling.geometry)
+            .map(Rect::from)
+            .collect::<Vec<_>>();
+
+        let placement = Placement { size, parent, focused };
+        Ok(placement::place(placement, area.into(), &siblings).into())
+    }
+
+    fn split_area(
+        &mut self,
+        server: Resource<Server>,
+        area: Geometry,
+        direction: SplitDirection,
+        children: Vec<LayoutChild>,
+        gap: u32,
+    ) -> wasmtime::Result<Vec<Geometry>> {
+        self.validate_id_server(&server)?;
+
+        let rects = layout::split(area.into(), direction, &children, gap);
+        Ok(rects.into_iter().map(Into::into).collect())
+    }
+
+    fn adjust_ratios(
+        &mut self,
+        server: Resource<Server>,
+        ratios: Vec<f64>,
+        border: u32,
+        delta: f64,
+    ) -> wasmtime::Result<Vec<f64>> {
+        self.validate_id_server(&server)?;
+        Ok(layout::adjust_ratios(&ratios, border as usize, delta))
+    }
+
+    fn set_click_config(&mut self, server: Resource<Server>, config: ClickConfig) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        self.clicks.set_config(config);
+        Ok(())
+    }
+
+    fn start_move(&mut self, server: Resource<Server>, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let id = self.get_toplevel_res(&toplevel)?.id;
+
+        let _ = self.sender.send(WmRequest::StartMove(id));
+        Ok(())
+    }
+
+    fn start_resize(
+        &mut self,
+        server: Resource<Server>,
+        toplevel: Resource<Toplevel>,
+        edge: ResizeEdge,
+    ) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let id = self.get_toplevel_res(&toplevel)?.id;
+
+        let _ = self.sender.send(WmRequest::StartResize { toplevel: id, edge });
+        Ok(())
+    }
+
+    fn move_to_workspace(
+        &mut self,
+        server: Resource<Server>,
+        toplevel: Resource<Toplevel>,
+        workspace: Resource<Workspace>,
+    ) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let workspace = self.get_workspace_res(&workspace)?.id;
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        toplevel.workspace = Some(workspace);
+        let toplevel = toplevel.id;
+
+        let _ = self.sender.send(WmRequest::MoveToWorkspace { toplevel, workspace });
+        Ok(())
+    }
+
+    fn raise(&mut self, server: Resource<Server>, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let id = self.get_toplevel_res(&toplevel)?.id;
+
+        let _ = self.sender.send(WmRequest::Raise(id));
+        Ok(())
+    }
+
+    fn lower(&mut self, server: Resource<Server>, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let id = self.get_toplevel_res(&toplevel)?.id;
+
+        let _ = self.sender.send(WmRequest::Lower(id));
+        Ok(())
+    }
+
+    fn restack_above(
+        &mut self,
+        server: Resource<Server>,
+        toplevel: Resource<Toplevel>,
+        sibling: Resource<Toplevel>,
+    ) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let sibling = self.get_toplevel_res(&sibling)?.id;
+        let toplevel = self.get_toplevel_res(&toplevel)?.id;
+
+        let _ = self.sender.send(WmRequest::RestackAbove { toplevel, sibling });
+        Ok(())
+    }
+
+    fn kill_client(&mut self, server: Resource<Server>, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let toplevel = self.get_toplevel_res(&toplevel)?.id;
+
+        let _ = self.sender.send(WmRequest::KillClient(toplevel));
+        Ok(())
+    }
+
+    fn binding_mode(&mut self, server: Resource<Server>) -> wasmtime::Result<String> {
+        self.validate_id_server(&server)?;
+        Ok(self.bindings.active_mode().into())
+    }
+
+    fn set_binding_mode(&mut self, server: Resource<Server>, mode: String) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        if self.bindings.set_mode(&mode) {
+            let _ = self.sender.send(WmRequest::BindingModeChanged(mode));
+        }
+
+        Ok(())
+    }
+
+    fn bind_enter_mode(
+        &mut self,
+        server: Resource<Server>,
+        mode: String,
+        combo: KeyCombo,
+        target: String,
+    ) -> wasmtime::Result<BindingConflicts> {
+        self.validate_id_server(&server)?;
+        let combo = combo.into();
+        let conflicts = self.key_binding_conflicts(&mode, combo);
+        self.bindings.bind(&mode, combo, Action::EnterMode(target));
+        Ok(conflicts)
+    }
+
+    fn bind_exit_mode(
+        &mut self,
+        server: Resource<Server>,
+        mode: String,
+        combo: KeyCombo,
+    ) -> wasmtime::Result<BindingConflicts> {
+        self.validate_id_server(&server)?;
+        let combo = combo.into();
+        let conflicts = self.key_binding_conflicts(&mode, combo);
+        self.bindings.bind(&mode, combo, Action::ExitMode);
+        Ok(conflicts)
+    }
+
+    fn unbind(&mut self, server: Resource<Server>, mode: String, combo: KeyCombo) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        self.bindings.unbind(&mode, combo.into());
+        Ok(())
+    }
+
+    fn bind_key(
+        &mut self,
+        server: Resource<Server>,
+        mode: String,
+        combo: KeyCombo,
+    ) -> wasmtime::Result<(BindingId, BindingConflicts)> {
+        self.validate_id_server(&server)?;
+        let combo = combo.into();
+        let conflicts = self.key_binding_conflicts(&mode, combo);
+        Ok((self.bindings.bind_key(&mode, combo), conflicts))
+    }
+
+    fn unbind_key(&mut self, server: Resource<Server>, binding: BindingId) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        self.bindings.unbind_key(binding);
+        Ok(())
+    }
+
+    fn forward_unbound_keys(&mut self, server: Resource<Server>, forward: bool) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        self.forward_unbound_keys = forward;
+        Ok(())
+    }
+
+    fn set_keymap(&mut self, server: Resource<Server>, rules: XkbRules) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let _ = self.sender.send(WmRequest::SetKeymap(rules));
+        Ok(())
+    }
+
+    fn set_layout_index(&mut self, server: Resource<Server>, index: u32) -> wasmtime::Result<Result<(), String>> {
+        self.validate_id_server(&server)?;
+        Ok(self.select_layout(index))
+    }
+
+    fn keyboard_layouts(&mut self, server: Resource<Server>) -> wasmtime::Result<Vec<String>> {
+        self.validate_id_server(&server)?;
+        Ok(self.keyboard_layouts.clone())
+    }
+
+    fn switch_state(&mut self, server: Resource<Server>, kind: SwitchKind) -> wasmtime::Result<bool> {
+        self.validate_id_server(&server)?;
+        Ok(*self.switch_mut(kind))
+    }
+
+    fn is_session_locked(&mut self, server: Resource<Server>) -> wasmtime::Result<bool> {
+        self.validate_id_server(&server)?;
+        Ok(self.session_locked)
+    }
+
+    fn bind_pointer(
+        &mut self,
+        server: Resource<Server>,
+        mode: String,
+        combo: PointerCombo,
+        contexts: PointerContext,
+    ) -> wasmtime::Result<(BindingId, BindingConflicts)> {
+        self.validate_id_server(&server)?;
+        let conflicts = self.bindings.pointer_conflicts(&mode, &combo);
+        Ok((self.bindings.bind_pointer(&mode, combo, contexts), conflicts))
+    }
+
+    fn unbind_pointer(&mut self, server: Resource<Server>, binding: BindingId) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        self.bindings.unbind_pointer(binding);
+        Ok(())
+    }
+
+    fn enable_switcher(&mut self, server: Resource<Server>, combo: KeyCombo) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        self.switcher.set_combo(Some(combo.into()));
+        Ok(())
+    }
+
+    fn disable_switcher(&mut self, server: Resource<Server>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        if self.switcher.set_combo(None) {
+            let _ = self.sender.send(WmRequest::HideSwitcher);
+        }
+
+        Ok(())
+    }
+
+    fn usable_area(&mut self, server: Resource<Server>, output: Resource<Output>) -> wasmtime::Result<Geometry> {
+        self.validate_id_server(&server)?;
+        let output = self.get_output_res(&output)?.id;
+
+        // The output was validated above.
+        Ok(self.compute_usable_area(output).unwrap().into())
+    }
+
+    fn set_exclusive_zone(
+        &mut self,
+        server: Resource<Server>,
+        output: Resource<Output>,
+        edge: OutputEdge,
+        size: u32,
+    ) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let wm_output = self.get_output_res(&output)?;
+        wm_output.zones.set(edge, i32::try_from(size).unwrap_or(i32::MAX));
+        let output = wm_output.id;
+
+        // The wm changed the zone itself, so usable-area-changed is not called.
+        let area = self.compute_usable_area(output).unwrap();
+        self.outputs.get_mut(&output.rep()).unwrap().usable_area = area;
+        Ok(())
+    }
+
+    fn begin_transaction(&mut self, server: Resource<Server>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        if self.transaction_depth == 0 {
+            let _ = self.sender.send(WmRequest::BeginTransaction);
+        }
+
+        self.transaction_depth = self.transaction_depth.saturating_add(1);
+        Ok(())
+    }
+
+    fn commit_transaction(&mut self, server: Resource<Server>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        match self.transaction_depth {
+            0 => {}
+            1 => {
+                self.transaction_depth = 0;
+                let _ = self.sender.send(WmRequest::CommitTransaction);
+            }
+            _ => self.transaction_depth -= 1,
+        }
+
+        Ok(())
+    }
+
+    fn store_get(&mut self, server: Resource<Server>, key: String) -> wasmtime::Result<Option<String>> {
+        self.validate_id_server(&server)?;
+        Ok(self.store.get(&key).cloned())
+    }
+
+    fn store_set(
+        &mut self,
+        server: Resource<Server>,
+        key: String,
+        value: Option<String>,
+    ) -> wasmtime::Result<Result<(), String>> {
+        self.validate_id_server(&server)?;
+
+        if key.is_empty() || key.len() > MAX_STORE_KEY {
+            return Ok(Err(format!("key must be between 1 and {MAX_STORE_KEY} bytes")));
+        }
+
+        if let Some(value) = &value {
+            if value.len() > MAX_STORE_VALUE {
+                return Ok(Err(format!("value must be at most {MAX_STORE_VALUE} bytes")));
+            }
+
+            if !self.store.contains_key(&key) && self.store.len() >= MAX_STORE_ENTRIES {
+                return Ok(Err(format!("store may contain at most {MAX_STORE_ENTRIES} values")));
+            }
+
+            let size = self
+                .store
+                .iter()
+                .filter(|(stored, _)| **stored != key)
+                .map(|(key, value)| key.len() + value.len())
+                .sum::<usize>();
+
+            if size + key.len() + value.len() > MAX_STORE_SIZE {
+                return Ok(Err(format!("store would be larger than {MAX_STORE_SIZE} bytes")));
+            }
+        }
+
+        let changed = match &value {
+            Some(value) => self.store.insert(key.clone(), value.clone()).as_ref() != Some(value),
+            None => self.store.remove(&key).is_some(),
+        };
+
+        if changed {
+            let _ = self.sender.send(WmRequest::StoreSet { key, value });
+        }
+
+        Ok(Ok(()))
+    }
+
+    fn query_rules(&mut self, server: Resource<Server>, toplevel: Resource<Toplevel>) -> wasmtime::Result<WindowRules> {
+        self.validate_id_server(&server)?;
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.rules.clone())
+    }
+
+    fn set_selection(
+        &mut self,
+        server: Resource<Server>,
+        kind: SelectionKind,
+        data: Vec<SelectionData>,
+    ) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        let _ = self.sender.send(WmRequest::SetSelection { kind, data });
+        Ok(())
+    }
+
+    fn set_cursor_shape(&mut self, server: Resource<Server>, shape: CursorShape) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        let _ = self.sender.send(WmRequest::SetCursor(Some(WmCursor::Shape(shape))));
+        Ok(())
+    }
+
+    fn set_cursor_image(&mut self, server: Resource<Server>, image: Texture, hotspot: Point) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        let _ = self
+            .sender
+            .send(WmRequest::SetCursor(Some(WmCursor::Image { image, hotspot })));
+        Ok(())
+    }
+
+    fn set_cursor_visible(&mut self, server: Resource<Server>, visible: bool) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        let _ = self.sender.send(WmRequest::SetCursorVisible(visible));
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self, server: Resource<Server>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        let _ = self.sender.send(WmRequest::SetCursor(Some(WmCursor::Hidden)));
+        Ok(())
+    }
+
+    fn reset_cursor(&mut self, server: Resource<Server>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+
+        let _ = self.sender.send(WmRequest::SetCursor(None));
+        Ok(())
+    }
+
+    fn present(
+        &mut self,
+        server: Resource<Server>,
+        output: Resource<Output>,
+        root: Option<Resource<Node>>,
+    ) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let output = self.get_output_res(&output)?.id;
+        let root = match root {
+            Some(root) => Some(self.get_node_res(&root)?.id),
+            None => None,
+        };
+
+        let _ = self.sender.send(WmRequest::Present { output, root });
+        Ok(())
+    }
+
+    fn create_rect(&mut self, server: Resource<Server>, color: Color, size: Size) -> wasmtime::Result<Resource<Node>> {
+        self.validate_id_server(&server)?;
+        Ok(self.create_node(NodeKind::Solid { size, color })?)
+    }
+
+    fn create_text(
+        &mut self,
+        server: Resource<Server>,
+        text: String,
+        font: Font,
+        size: f32,
+        color: Color,
+    ) -> wasmtime::Result<Result<Texture, String>> {
+        self.validate_id_server(&server)?;
+
+        // Drawing happens on the wm runtime thread, so the wm receives the texture without a round trip to the
+        // display server.
+        let fonts = self.fonts.get_or_insert_with(FontStack::system);
+        Ok(fonts.draw(&text, font, size, &color).map_err(|err| err.to_string()))
+    }
+
+    fn load_image(
+        &mut self,
+        server: Resource<Server>,
+        source: ImageSource,
+    ) -> wasmtime::Result<Result<Texture, String>> {
+        self.validate_id_server(&server)?;
+        Ok(images::load(source).map_err(|err| err.to_string()))
+    }
+
+    fn request_frame(&mut self, server: Resource<Server>, output: Resource<Output>) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        let id = self.get_output_res(&output)?.id;
+
+        let _ = self.sender.send(WmRequest::RequestFrame(id));
+        Ok(())
+    }
+
+    fn set_timer(&mut self, server: Resource<Server>, duration: u32) -> wasmtime::Result<TimerId> {
+        self.validate_id_server(&server)?;
+        let timer = self.alloc_timer();
+
+        let _ = self.sender.send(WmRequest::SetTimer {
+            timer,
+            duration: Duration::from_millis(duration.into()),
+        });
+        Ok(timer)
+    }
+
+    fn cancel_timer(&mut self, server: Resource<Server>, timer: TimerId) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        self.cancel_timer(timer);
+        Ok(())
+    }
+
+    fn add_edge_trigger(
+        &mut self,
+        server: Resource<Server>,
+        output: Resource<Output>,
+        edge: ScreenEdge,
+        threshold: u32,
+    ) -> wasmtime::Result<TriggerId> {
+        self.validate_id_server(&server)?;
+        let output = self.get_output_res(&output)?.id;
+
+        Ok(self.edge_triggers.add(output, edge, threshold))
+    }
+
+    fn remove_edge_trigger(&mut self, server: Resource<Server>, trigger: TriggerId) -> wasmtime::Result<()> {
+        self.validate_id_server(&server)?;
+        self.edge_triggers.remove(trigger);
+        Ok(())
+    }
+
+    fn create_activation_token(
+        &mut self,
+        server: Resource<Server>,
+        app_id: Option<String>,
+    ) -> wasmtime::Result<String> {
+        self.validate_id_server(&server)?;
+        let token = activation::new_token()?;
+
+        let _ = self.sender.send(WmRequest::ActivationToken {
+            token: token.clone(),
+            app_id,
+        });
+        Ok(token)
+    }
+
+    fn spawn(
+        &mut self,
+        server: Resource<Server>,
+        command: String,
+        args: Vec<String>,
+        env: Vec<(String, String)>,
+    ) -> wasmtime::Result<Result<ProcessId, String>> {
+        self.validate_id_server(&server)?;
+
+        if command.is_empty() {
+            return Ok(Err("empty command".into()));
+        }
+
+        if let Some((name, _)) = env
+            .iter()
+            .find(|(name, _)| name.is_empty() || name.contains(['=', '\0']))
+        {
+            return Ok(Err(format!("invalid environment variable name: {name:?}")));
+        }
+
+        // Skip ids of processes which are still running if the id wrapped around.
+        let mut process = self.next_process;
+        while self.processes.contains(&process) {
+            process = process.wrapping_add(1);
+        }
+
+        self.next_process = process.wrapping_add(1);
+        self.processes.insert(process);
+
+        let _ = self.sender.send(WmRequest::Spawn {
+            process,
+            command,
+            args,
+            env,
+        });
+        Ok(Ok(process))
+    }
+
     fn drop(&mut self, server: Resource<Server>) -> wasmtime::Result<()> {
         // TODO: What should happen if the server is dropped?
         self.validate_id_server(&server)?;
@@ -36,45 +679,215 @@ impl HostServer for WmState {
     }
 }
 
-impl HostViewBuilder for WmState {
-    fn with_toplevel(
+impl HostNode for WmState {
+    fn group(&mut self) -> wasmtime::Result<Resource<Node>> {
+        Ok(self.create_node(NodeKind::Group)?)
+    }
+
+    fn from_toplevel(
         &mut self,
         toplevel: Resource<Toplevel>,
-        image: Resource<Snapshot>,
-    ) -> wasmtime::Result<Resource<ViewBuilder>> {
-        todo!()
+        snapshot: Resource<Snapshot>,
+    ) -> wasmtime::Result<Resource<Node>> {
+        let toplevel = self.get_toplevel_res(&toplevel)?.id;
+        let snapshot = self.get_snapshot_res(&snapshot)?.id;
+        Ok(self.create_node(NodeKind::Toplevel { toplevel, snapshot })?)
     }
 
-    fn build(&mut self, builder: Resource<ViewBuilder>) -> wasmtime::Result<Resource<View>> {
-        todo!()
+    fn solid(&mut self, size: Size, color: Color) -> wasmtime::Result<Resource<Node>> {
+        Ok(self.create_node(NodeKind::Solid { size, color })?)
     }
 
-    fn drop(&mut self, builder: Resource<ViewBuilder>) -> wasmtime::Result<()> {
-        todo!()
+    fn border(&mut self, size: Size, width: u32, color: Color) -> wasmtime::Result<Resource<Node>> {
+        Ok(self.create_node(NodeKind::Border { size, width, color })?)
     }
-}
 
-impl HostView for WmState {
-    fn drop(&mut self, node: Resource<View>) -> wasmtime::Result<()> {
-        todo!()
+    fn from_texture(&mut self, size: Size, texture: Texture) -> wasmtime::Result<Resource<Node>> {
+        Ok(self.create_node(NodeKind::Texture { size, texture })?)
+    }
+
+    fn from_snapshot(&mut self, size: Size, snapshot: Resource<Snapshot>) -> wasmtime::Result<Resource<Node>> {
+        let snapshot = self.get_snapshot_res(&snapshot)?.id;
+        Ok(self.create_node(NodeKind::Snapshot { size, snapshot })?)
+    }
+
+    fn add_child(&mut self, node: Resource<Node>, child: Resource<Node>) -> wasmtime::Result<bool> {
+        let parent = self.get_node_res(&node)?.id;
+        let child = self.get_node_res(&child)?;
+        let id = child.id;
+
+        if self.is_ancestor(id, parent) {
+            return Ok(false);
+        }
+
+        self.nodes.get_mut(&id.rep()).unwrap().parent = Some(parent);
+        let _ = self.sender.send(WmRequest::AddNodeChild { parent, child: id });
+        Ok(true)
+    }
+
+    fn detach(&mut self, node: Resource<Node>) -> wasmtime::Result<()> {
+        let node = self.get_node_res(&node)?;
+
+        if node.parent.take().is_some() {
+            let _ = self.sender.send(WmRequest::DetachNode(node.id));
+        }
+
+        Ok(())
+    }
+
+    fn place_above(&mut self, node: Resource<Node>, sibling: Resource<Node>) -> wasmtime::Result<()> {
+        let id = self.get_node_res(&node)?.id;
+        let sibling = self.get_node_res(&sibling)?;
+        let (sibling, parent) = (sibling.id, sibling.parent);
+
+        // The node cannot be placed next to itself or inside its own tree.
+        if self.is_ancestor(id, sibling) {
+            return Ok(());
+        }
+
+        self.nodes.get_mut(&id.rep()).unwrap().parent = parent;
+        let _ = self.sender.send(WmRequest::PlaceNodeAbove { node: id, sibling });
+        Ok(())
+    }
+
+    fn set_offset(&mut self, node: Resource<Node>, offset: Point) -> wasmtime::Result<()> {
+        let node = self.get_node_res(&node)?.id;
+
+        let _ = self.sender.send(WmRequest::SetNodeOffset { node, offset });
+        Ok(())
+    }
+
+    fn set_render_properties(&mut self, node: Resource<Node>, properties: RenderProperties) -> wasmtime::Result<()> {
+        let properties = sanitize_render(properties);
+        let node = self.get_node_res(&node)?.id;
+
+        let _ = self.sender.send(WmRequest::NodeRender { node, properties });
+        Ok(())
+    }
+
+    fn set_size(&mut self, node: Resource<Node>, size: Size) -> wasmtime::Result<()> {
+        let node = self.get_node_res(&node)?.id;
+
+        let _ = self.sender.send(WmRequest::SetNodeSize { node, size });
+        Ok(())
+    }
+
+    fn set_color(&mut self, node: Resource<Node>, color: Color) -> wasmtime::Result<()> {
+        let node = self.get_node_res(&node)?.id;
+
+        let _ = self.sender.send(WmRequest::SetNodeColor { node, color });
+        Ok(())
+    }
+
+    fn drop(&mut self, node: Resource<Node>) -> wasmtime::Result<()> {
+        let id = self.get_node_res(&node)?.id;
+        self.nodes.remove(&id.rep());
+        self.remove_id(id);
+        // TODO: Free the id once the id allocator supports freeing disjoint ids.
+
+        // The children stay alive as long as the wm holds them, but are no longer in the scene.
+        for child in self.nodes.values_mut() {
+            if child.parent == Some(id) {
+                child.parent = None;
+            }
+        }
+
+        let _ = self.sender.send(WmRequest::DestroyNode(id));
+        Ok(())
     }
 }
 
 impl HostOutput for WmState {
     fn id(&mut self, output: Resource<Output>) -> wasmtime::Result<OutputId> {
-        todo!()
+        let output = self.get_output_res(&output)?;
+        Ok(output.id.rep().get())
     }
 
     fn name(&mut self, output: Resource<Output>) -> wasmtime::Result<Option<String>> {
-        todo!()
+        let output = self.get_output_res(&output)?;
+        Ok(output.info.name.clone())
     }
 
     fn geometry(&mut self, output: Resource<Output>) -> wasmtime::Result<Geometry> {
-        todo!()
+        let output = self.get_output_res(&output)?;
+        Ok(output.geometry)
+    }
+
+    fn usable_area(&mut self, output: Resource<Output>) -> wasmtime::Result<Geometry> {
+        let output = self.get_output_res(&output)?;
+        Ok(output.usable_area.into())
     }
 
     fn refresh_rate(&mut self, output: Resource<Output>) -> wasmtime::Result<u32> {
-        todo!()
+        let output = self.get_output_res(&output)?;
+        Ok(output.info.refresh_rate)
+    }
+
+    fn scale(&mut self, output: Resource<Output>) -> wasmtime::Result<f64> {
+        let output = self.get_output_res(&output)?;
+        Ok(output.info.scale)
+    }
+
+    fn physical_size(&mut self, output: Resource<Output>) -> wasmtime::Result<Option<Size>> {
+        let output = self.get_output_res(&output)?;
+        Ok(output.info.physical_size)
+    }
+
+    fn make(&mut self, output: Resource<Output>) -> wasmtime::Result<Option<String>> {
+        let output = self.get_output_res(&output)?;
+        Ok(output.info.make.clone())
+    }
+
+    fn model(&mut self, output: Resource<Output>) -> wasmtime::Result<Option<String>> {
+        let output = self.get_output_res(&output)?;
+        Ok(output.info.model.clone())
+    }
+
+    fn serial(&mut self, output: Resource<Output>) -> wasmtime::Result<Option<String>> {
+        let output = self.get_output_res(&output)?;
+        Ok(output.info.serial.clone())
+    }
+
+    fn subpixel(&mut self, output: Resource<Output>) -> wasmtime::Result<Subpixel> {
+        let output = self.get_output_res(&output)?;
+        Ok(output.info.subpixel)
+    }
+
+    fn set_enabled(&mut self, output: Resource<Output>, enabled: bool) -> wasmtime::Result<()> {
+        let output = self.get_output_res(&output)?.id;
+        let _ = self.sender.send(WmRequest::SetOutputEnabled { output, enabled });
+        Ok(())
+    }
+
+    fn set_dpms(&mut self, output: Resource<Output>, on: bool) -> wasmtime::Result<()> {
+        let output = self.get_output_res(&output)?.id;
+        let _ = self.sender.send(WmRequest::SetOutputDpms { output, on });
+        Ok(())
+    }
+
+    fn supports_vrr(&mut self, output: Resource<Output>) -> wasmtime::Result<bool> {
+        let output = self.get_output_res(&output)?;
+        Ok(output.info.vrr_capable)
+    }
+
+    fn set_vrr(&mut self, output: Resource<Output>, mode: VrrMode) -> wasmtime::Result<()> {
+        let output = self.get_output_res(&output)?;
+
+        // Nothing would change, so the display server is not bothered.
+        if !output.info.vrr_capable {
+            return Ok(());
+        }
+
+        let output = output.id;
+        let enabled = matches!(mode, VrrMode::On);
+        let _ = self.sender.send(WmRequest::SetOutputVrr { output, enabled });
+        Ok(())
+    }
+
+    fn set_zoom(&mut self, output: Resource<Output>, level: f64, follow: ZoomFollow) -> wasmtime::Result<()> {
+        let output = self.get_output_res(&output)?.id;
+        let _ = self.sender.send(WmRequest::SetOutputZoom { output, level, follow });
+        Ok(())
     }
 
     fn drop(&mut self, output: Resource<Output>) -> wasmtime::Result<()> {
@@ -82,6 +895,67 @@ impl HostOutput for WmState {
     }
 }
 
+impl HostOutputConfigure for WmState {
+    fn new(&mut self, output: Resource<Output>) -> wasmtime::Result<Resource<OutputConfigure>> {
+        let output = self.get_output_res(&output)?.id;
+        let id = self.alloc_id(IdType::OutputConfigure)?;
+        self.output_configures.insert(
+            id.rep(),
+            WmOutputConfigure {
+                output,
+                configuration: Default::default(),
+            },
+        );
+
+        Ok(Resource::new_own(id.rep().get()))
+    }
+
+    fn mode(&mut self, configure: Resource<OutputConfigure>, mode: OutputMode) -> wasmtime::Result<()> {
+        let configure = self.get_output_configure_res(&configure)?;
+        configure.configuration.mode = Some(mode);
+        Ok(())
+    }
+
+    fn scale(&mut self, configure: Resource<OutputConfigure>, scale: f64) -> wasmtime::Result<()> {
+        let configure = self.get_output_configure_res(&configure)?;
+        configure.configuration.scale = Some(scale);
+        Ok(())
+    }
+
+    fn transform(&mut self, configure: Resource<OutputConfigure>, transform: OutputTransform) -> wasmtime::Result<()> {
+        let configure = self.get_output_configure_res(&configure)?;
+        configure.configuration.transform = Some(transform);
+        Ok(())
+    }
+
+    fn position(&mut self, configure: Resource<OutputConfigure>, x: i32, y: i32) -> wasmtime::Result<()> {
+        let configure = self.get_output_configure_res(&configure)?;
+        configure.configuration.position = Some((x, y));
+        Ok(())
+    }
+
+    fn submit(&mut self, configure: Resource<OutputConfigure>) -> wasmtime::Result<u32> {
+        let configure = self.get_output_configure_res(&configure)?;
+        let output = configure.output;
+        let configuration = std::mem::take(&mut configure.configuration);
+        let serial = self.next_serial();
+
+        let _ = self.sender.send(WmRequest::ConfigureOutput {
+            output,
+            serial,
+            configuration,
+        });
+        Ok(serial)
+    }
+
+    fn drop(&mut self, configure: Resource<OutputConfigure>) -> wasmtime::Result<()> {
+        let id = self.get_id(&configure, IdType::OutputConfigure)?;
+        self.output_configures.remove(&id.rep());
+        self.remove_id(id);
+        Ok(())
+    }
+}
+
 impl HostToplevel for WmState {
     fn features(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<Features> {
         let toplevel = self.get_toplevel_res(&toplevel)?;
@@ -118,6 +992,85 @@ impl HostToplevel for WmState {
         Ok(toplevel.geometry)
     }
 
+    fn workspace(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<Option<WorkspaceId>> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.workspace.map(Id::rep).map(Into::into))
+    }
+
+    fn render_properties(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<RenderProperties> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.render)
+    }
+
+    fn set_render_properties(
+        &mut self,
+        toplevel: Resource<Toplevel>,
+        properties: RenderProperties,
+    ) -> wasmtime::Result<()> {
+        let properties = sanitize_render(properties);
+
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        toplevel.render = properties;
+        let toplevel = toplevel.id;
+
+        let _ = self.sender.send(WmRequest::ToplevelRender { toplevel, properties });
+        Ok(())
+    }
+
+    fn snapshot(
+        &mut self,
+        toplevel: Resource<Toplevel>,
+        max_size: Option<Size>,
+    ) -> wasmtime::Result<Option<Resource<Snapshot>>> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+
+        let Some(geometry) = toplevel.geometry else {
+            return Ok(None);
+        };
+
+        let toplevel = toplevel.id;
+        let size = Size {
+            width: geometry.width,
+            height: geometry.height,
+        };
+        let size = snapshot_size(size, max_size);
+
+        let id = self.alloc_id(IdType::Snapshot)?;
+        self.snapshots.insert(id.rep(), SnapshotInfo { id, size, scale: 1.0 });
+
+        let _ = self.sender.send(WmRequest::SnapshotToplevel {
+            toplevel,
+            snapshot: id,
+            size,
+        });
+        Ok(Some(Resource::new_own(id.rep().get())))
+    }
+
+    fn server_decorations(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<Option<ServerDecorations>> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.server_decorations.clone())
+    }
+
+    fn set_server_decorations(
+        &mut self,
+        toplevel: Resource<Toplevel>,
+        decorations: Option<ServerDecorations>,
+    ) -> wasmtime::Result<()> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        toplevel.server_decorations = decorations.clone();
+        let toplevel = toplevel.id;
+
+        let _ = self
+            .sender
+            .send(WmRequest::ToplevelDecorations { toplevel, decorations });
+        Ok(())
+    }
+
+    fn restore_geometry(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<Option<Geometry>> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.restore_geometry)
+    }
+
     fn parent(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<Option<ToplevelId>> {
         let toplevel = self.get_toplevel_res(&toplevel)?;
         Ok(toplevel.parent.map(Id::rep).map(Into::into))
@@ -133,11 +1086,36 @@ impl HostToplevel for WmState {
         Ok(toplevel.decorations)
     }
 
+    fn preferred_decorations(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<Option<DecorationMode>> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.preferred_decorations)
+    }
+
     fn resize_edge(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<Option<ResizeEdge>> {
         let toplevel = self.get_toplevel_res(&toplevel)?;
         Ok(toplevel.resize_edge)
     }
 
+    fn is_unresponsive(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<bool> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.unresponsive)
+    }
+
+    fn is_playing_audio(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<bool> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.playing_audio)
+    }
+
+    fn is_scanned_out(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<bool> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.scanout)
+    }
+
+    fn prefers_variable_refresh(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<bool> {
+        let toplevel = self.get_toplevel_res(&toplevel)?;
+        Ok(toplevel.prefers_variable_refresh)
+    }
+
     fn request_close(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
         let toplevel = self.get_toplevel_res(&toplevel)?;
         let id = toplevel.id;
@@ -147,33 +1125,378 @@ impl HostToplevel for WmState {
     }
 
     fn drop(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
-        let toplevel = self.get_toplevel_res(&toplevel)?;
-        let id = toplevel.id;
-        // TODO: Remove id from this side.
+        // The toplevel may have already been closed. Otherwise the runtime keeps tracking it until it is closed,
+        // since the toplevel is mapped regardless of whether the wm holds it.
+        if let Ok(toplevel) = self.get_toplevel_res(&toplevel) {
+            let id = toplevel.id;
+            let _ = self.sender.send(WmRequest::ToplevelDrop(id));
+        }
+
+        Ok(())
+    }
+}
+
+impl HostPopup for WmState {
+    fn id(&mut self, popup: Resource<Popup>) -> wasmtime::Result<PopupId> {
+        let popup = self.get_popup_res(&popup)?;
+        Ok(popup.id.rep().get())
+    }
+
+    fn parent(&mut self, popup: Resource<Popup>) -> wasmtime::Result<PopupParent> {
+        let popup = self.get_popup_res(&popup)?;
+        Ok(popup.parent)
+    }
+
+    fn positioner(&mut self, popup: Resource<Popup>) -> wasmtime::Result<Positioner> {
+        let popup = self.get_popup_res(&popup)?;
+        Ok(popup.positioner)
+    }
+
+    fn geometry(&mut self, popup: Resource<Popup>) -> wasmtime::Result<Option<Geometry>> {
+        let popup = self.get_popup_res(&popup)?;
+        Ok(popup.geometry)
+    }
+
+    fn configure(
+        &mut self,
+        popup: Resource<Popup>,
+        geometry: Geometry,
+        reposition_token: Option<u32>,
+    ) -> wasmtime::Result<()> {
+        let popup = self.get_popup_res(&popup)?;
+        popup.geometry = Some(geometry);
+        let id = popup.id;
+
+        let _ = self.sender.send(WmRequest::PopupConfigure {
+            popup: id,
+            geometry,
+            reposition_token,
+        });
+        Ok(())
+    }
+
+    fn dismiss(&mut self, popup: Resource<Popup>) -> wasmtime::Result<()> {
+        let id = self.get_popup_res(&popup)?.id;
+
+        let _ = self.sender.send(WmRequest::PopupDismiss(id));
+        Ok(())
+    }
+
+    fn drop(&mut self, popup: Resource<Popup>) -> wasmtime::Result<()> {
+        // The popup may have already been closed.
+        if let Ok(popup) = self.get_popup_res(&popup) {
+            let id = popup.id;
+            let _ = self.sender.send(WmRequest::PopupDrop(id));
+        }
+
+        Ok(())
+    }
+}
+
+impl HostWorkspace for WmState {
+    fn new(&mut self, name: String, output: Option<Resource<Output>>) -> wasmtime::Result<Resource<Workspace>> {
+        let output = match output {
+            Some(output) => Some(self.get_output_res(&output)?.id),
+            None => None,
+        };
+
+        let id = self.alloc_id(IdType::Workspace)?;
+        self.workspaces.insert(
+            id.rep(),
+            WmWorkspace {
+                id,
+                name: name.clone(),
+                output,
+                active: false,
+            },
+        );
+
+        let _ = self.sender.send(WmRequest::CreateWorkspace {
+            workspace: id,
+            name,
+            output,
+        });
+        Ok(Resource::new_own(id.rep().get()))
+    }
+
+    fn id(&mut self, workspace: Resource<Workspace>) -> wasmtime::Result<WorkspaceId> {
+        let workspace = self.get_workspace_res(&workspace)?;
+        Ok(workspace.id.rep().get())
+    }
+
+    fn name(&mut self, workspace: Resource<Workspace>) -> wasmtime::Result<String> {
+        let workspace = self.get_workspace_res(&workspace)?;
+        Ok(workspace.name.clone())
+    }
+
+    fn rename(&mut self, workspace: Resource<Workspace>, name: String) -> wasmtime::Result<()> {
+        let workspace = self.get_workspace_res(&workspace)?;
+        workspace.name = name.clone();
+        let id = workspace.id;
+
+        let _ = self.sender.send(WmRequest::RenameWorkspace { workspace: id, name });
+        Ok(())
+    }
+
+    fn output(&mut self, workspace: Resource<Workspace>) -> wasmtime::Result<Option<OutputId>> {
+        let workspace = self.get_workspace_res(&workspace)?;
+        Ok(workspace.output.map(Id::rep).map(Into::into))
+    }
+
+    fn assign(&mut self, workspace: Resource<Workspace>, output: Resource<Output>) -> wasmtime::Result<()> {
+        let output = self.get_output_res(&output)?.id;
+        let workspace = self.get_workspace_res(&workspace)?;
+
+        if workspace.output != Some(output) {
+            workspace.output = Some(output);
+            workspace.active = false;
+        }
+
+        let id = workspace.id;
+        let _ = self.sender.send(WmRequest::AssignWorkspace { workspace: id, output });
+        Ok(())
+    }
+
+    fn active(&mut self, workspace: Resource<Workspace>) -> wasmtime::Result<bool> {
+        let workspace = self.get_workspace_res(&workspace)?;
+        Ok(workspace.active)
+    }
+
+    fn activate(&mut self, workspace: Resource<Workspace>) -> wasmtime::Result<()> {
+        let workspace = self.get_workspace_res(&workspace)?;
+        let id = workspace.id;
+
+        let Some(output) = workspace.output else {
+            return Ok(());
+        };
+
+        for workspace in self.workspaces.values_mut() {
+            if workspace.output == Some(output) {
+                workspace.active = workspace.id == id;
+            }
+        }
+
+        let _ = self.sender.send(WmRequest::ActivateWorkspace(id));
+        Ok(())
+    }
+
+    fn drop(&mut self, workspace: Resource<Workspace>) -> wasmtime::Result<()> {
+        let id = self.get_workspace_res(&workspace)?.id;
+        self.workspaces.remove(&id.rep());
+        self.remove_id(id);
+        // TODO: Free the id once the id allocator supports freeing disjoint ids.
+
+        for toplevel in self.toplevels.values_mut() {
+            if toplevel.workspace == Some(id) {
+                toplevel.workspace = None;
+            }
+        }
+
+        let _ = self.sender.send(WmRequest::DestroyWorkspace(id));
+        Ok(())
+    }
+}
+
+impl HostToplevelGroup for WmState {
+    fn new(&mut self) -> wasmtime::Result<Resource<ToplevelGroup>> {
+        let id = self.alloc_id(IdType::ToplevelGroup)?;
+        self.toplevel_groups.insert(
+            id.rep(),
+            WmToplevelGroup {
+                id,
+                members: Vec::new(),
+                visible: None,
+                tab_layout: None,
+            },
+        );
+
+        let _ = self.sender.send(WmRequest::CreateToplevelGroup(id));
+        Ok(Resource::new_own(id.rep().get()))
+    }
+
+    fn id(&mut self, group: Resource<ToplevelGroup>) -> wasmtime::Result<ToplevelGroupId> {
+        let group = self.get_toplevel_group_res(&group)?;
+        Ok(group.id.rep().get())
+    }
+
+    fn add(&mut self, group: Resource<ToplevelGroup>, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
+        let toplevel = self.get_toplevel_res(&toplevel)?.id;
+        let group = self.get_toplevel_group_res(&group)?.id;
+
+        if self.toplevel_groups[&group.rep()].members.contains(&toplevel) {
+            return Ok(());
+        }
+
+        self.remove_from_toplevel_group(toplevel);
+
+        let group_state = self.toplevel_groups.get_mut(&group.rep()).unwrap();
+        group_state.members.push(toplevel);
+        group_state.visible.get_or_insert(toplevel);
+
+        let _ = self.sender.send(WmRequest::AddToToplevelGroup { group, toplevel });
+        Ok(())
+    }
+
+    fn remove(&mut self, group: Resource<ToplevelGroup>, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
+        let toplevel = self.get_toplevel_res(&toplevel)?.id;
+        let group = self.get_toplevel_group_res(&group)?;
+
+        if !group.members.contains(&toplevel) {
+            return Ok(());
+        }
+
+        group.remove(toplevel);
+        let group = group.id;
+
+        let _ = self.sender.send(WmRequest::RemoveFromToplevelGroup { group, toplevel });
+        Ok(())
+    }
+
+    fn members(&mut self, group: Resource<ToplevelGroup>) -> wasmtime::Result<Vec<ToplevelId>> {
+        let group = self.get_toplevel_group_res(&group)?;
+        Ok(group.members.iter().map(|member| member.rep().get()).collect())
+    }
+
+    fn set_visible(&mut self, group: Resource<ToplevelGroup>, toplevel: Resource<Toplevel>) -> wasmtime::Result<()> {
+        let toplevel = self.get_toplevel_res(&toplevel)?.id;
+        let group = self.get_toplevel_group_res(&group)?;
+
+        if !group.members.contains(&toplevel) || group.visible == Some(toplevel) {
+            return Ok(());
+        }
+
+        group.visible = Some(toplevel);
+        let group = group.id;
+
+        let _ = self
+            .sender
+            .send(WmRequest::SetVisibleInToplevelGroup { group, toplevel });
+        Ok(())
+    }
+
+    fn visible(&mut self, group: Resource<ToplevelGroup>) -> wasmtime::Result<Option<ToplevelId>> {
+        let group = self.get_toplevel_group_res(&group)?;
+        Ok(group.visible.map(|visible| visible.rep().get()))
+    }
+
+    fn tab_layout(&mut self, group: Resource<ToplevelGroup>) -> wasmtime::Result<Option<TabLayout>> {
+        Ok(self.get_toplevel_group_res(&group)?.tab_layout)
+    }
+
+    fn set_tab_layout(&mut self, group: Resource<ToplevelGroup>, layout: Option<TabLayout>) -> wasmtime::Result<()> {
+        let group = self.get_toplevel_group_res(&group)?;
+
+        if group.tab_layout == layout {
+            return Ok(());
+        }
+
+        group.tab_layout = layout;
+        let group = group.id;
+
+        let _ = self.sender.send(WmRequest::SetToplevelGroupTabLayout { group, layout });
+        Ok(())
+    }
+
+    fn drop(&mut self, group: Resource<ToplevelGroup>) -> wasmtime::Result<()> {
+        let id = self.get_toplevel_group_res(&group)?.id;
+        self.toplevel_groups.remove(&id.rep());
+        self.remove_id(id);
+
+        let _ = self.sender.send(WmRequest::DestroyToplevelGroup(id));
+        Ok(())
+    }
+}
+
+impl HostLayerSurface for WmState {
+    fn id(&mut self, layer_surface: Resource<LayerSurface>) -> wasmtime::Result<LayerSurfaceId> {
+        let layer_surface = self.get_layer_surface_res(&layer_surface)?;
+        Ok(layer_surface.id.rep().get())
+    }
+
+    fn output(&mut self, layer_surface: Resource<LayerSurface>) -> wasmtime::Result<Option<OutputId>> {
+        let layer_surface = self.get_layer_surface_res(&layer_surface)?;
+        Ok(layer_surface.output.map(Id::rep).map(Into::into))
+    }
+
+    fn namespace(&mut self, layer_surface: Resource<LayerSurface>) -> wasmtime::Result<String> {
+        let layer_surface = self.get_layer_surface_res(&layer_surface)?;
+        Ok(layer_surface.namespace.clone())
+    }
+
+    fn state(&mut self, layer_surface: Resource<LayerSurface>) -> wasmtime::Result<LayerSurfaceState> {
+        let layer_surface = self.get_layer_surface_res(&layer_surface)?;
+        Ok(layer_surface.state)
+    }
+
+    fn configure(&mut self, layer_surface: Resource<LayerSurface>, size: Size) -> wasmtime::Result<u32> {
+        let id = self.get_layer_surface_res(&layer_surface)?.id;
+        let serial = self.next_serial();
+
+        let _ = self.sender.send(WmRequest::LayerSurfaceConfigure {
+            layer_surface: id,
+            size,
+            serial,
+        });
+        Ok(serial)
+    }
+
+    fn close(&mut self, layer_surface: Resource<LayerSurface>) -> wasmtime::Result<()> {
+        let id = self.get_layer_surface_res(&layer_surface)?.id;
+
+        let _ = self.sender.send(WmRequest::LayerSurfaceClose(id));
+        Ok(())
+    }
+
+    fn drop(&mut self, layer_surface: Resource<LayerSurface>) -> wasmtime::Result<()> {
+        // The layer surface may have already been closed.
+        if let Ok(layer_surface) = self.get_layer_surface_res(&layer_surface) {
+            let id = layer_surface.id;
+            let _ = self.sender.send(WmRequest::LayerSurfaceDrop(id));
+        }
 
-        let _ = self.sender.send(WmRequest::ToplevelDrop(id));
         Ok(())
     }
 }
 
 impl HostToplevelConfigure for WmState {
     fn new(&mut self, toplevel: Resource<Toplevel>) -> wasmtime::Result<Resource<ToplevelConfigure>> {
-        let toplevel = self.get_toplevel_res(&toplevel)?;
-        let configure = WmToplevelConfigure {
-            toplevel_id: toplevel.id,
-            decorations: Default::default(),
-            parent: Default::default(),
-            state: Default::default(),
-            size: Default::default(),
-            bounds: Default::default(),
-        };
+        let toplevel_id = self.get_toplevel_res(&toplevel)?.id;
+        let id = self.alloc_id(IdType::ToplevelConfigure)?;
+        self.toplevel_configures.insert(
+            id.rep(),
+            WmToplevelConfigure {
+                toplevel_id,
+                configuration: Default::default(),
+            },
+        );
 
-        Ok(Resource::new_own(todo!("Allocate owned id for toplevel configure")))
+        Ok(Resource::new_own(id.rep().get()))
     }
 
     fn submit(&mut self, configure: Resource<ToplevelConfigure>) -> wasmtime::Result<u32> {
-        let _configure = self.get_toplevel_configure(&configure)?;
-        todo!()
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        let toplevel = configure.toplevel_id;
+        let configuration = std::mem::take(&mut configure.configuration);
+        let serial = self.next_serial();
+
+        // The display server applies the decoration mode the wm chose and clients cannot change the parent the wm
+        // chose, so there is no need to wait for either.
+        let wm_toplevel = self.get_toplevel(toplevel)?;
+
+        if let Some(decorations) = configuration.decorations {
+            wm_toplevel.decorations = decorations;
+        }
+
+        if let ConfigureUpdate::Update(parent) = configuration.parent {
+            wm_toplevel.parent = parent;
+        }
+
+        let _ = self.sender.send(WmRequest::ConfigureToplevel {
+            toplevel,
+            serial,
+            configuration,
+        });
+        Ok(serial)
     }
 
     fn decorations(
@@ -181,8 +1504,8 @@ impl HostToplevelConfigure for WmState {
         configure: Resource<ToplevelConfigure>,
         decorations: DecorationMode,
     ) -> wasmtime::Result<()> {
-        let configure = self.get_toplevel_configure(&configure)?;
-        configure.decorations = Some(decorations);
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        configure.configuration.decorations = Some(decorations);
         Ok(())
     }
 
@@ -191,59 +1514,165 @@ impl HostToplevelConfigure for WmState {
         configure: Resource<ToplevelConfigure>,
         parent: Option<Resource<Toplevel>>,
     ) -> wasmtime::Result<()> {
-        let configure = self.get_toplevel_configure(&configure)?;
-
-        match parent {
-            Some(parent) => {
-                if parent.owned() {
-                    todo!("propagate error")
-                }
-
-                let parent_id = NonZeroU32::new(parent.rep()).ok_or(IdError::ZeroId)?;
-                configure.parent = ConfigureUpdate::Update(Some(Id(parent_id, IdType::Toplevel)));
-                Ok(())
-            }
+        let parent = match parent {
+            Some(parent) => Some(self.get_toplevel_res(&parent)?.id),
+            None => None,
+        };
 
-            None => {
-                configure.parent = ConfigureUpdate::Update(None);
-                Ok(())
-            }
-        }
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        configure.configuration.parent = ConfigureUpdate::Update(parent);
+        Ok(())
     }
 
     fn state(&mut self, configure: Resource<ToplevelConfigure>, states: ToplevelState) -> wasmtime::Result<()> {
-        let configure = self.get_toplevel_configure(&configure)?;
-        configure.state = Some(states);
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        configure.configuration.state = Some(states);
         Ok(())
     }
 
     fn size(&mut self, configure: Resource<ToplevelConfigure>, size: Option<Size>) -> wasmtime::Result<()> {
-        let configure = self.get_toplevel_configure(&configure)?;
-        configure.size = ConfigureUpdate::Update(size);
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        configure.configuration.size = ConfigureUpdate::Update(size);
         Ok(())
     }
 
+    fn restore(&mut self, configure: Resource<ToplevelConfigure>) -> wasmtime::Result<Option<Geometry>> {
+        let (toplevel_id, configured_state) = {
+            let configure = self.get_toplevel_configure_res(&configure)?;
+            (configure.toplevel_id, configure.configuration.state)
+        };
+        let toplevel = self.get_toplevel(toplevel_id)?;
+        // Keep any other states the wm already set in this configure.
+        let state =
+            configured_state.unwrap_or(toplevel.state) & !(ToplevelState::MAXIMIZED | ToplevelState::FULLSCREEN);
+        let restore = toplevel.restore_geometry.take();
+
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        configure.configuration.state = Some(state);
+
+        if let Some(geometry) = restore {
+            configure.configuration.size = ConfigureUpdate::Update(Some(Size {
+                width: geometry.width,
+                height: geometry.height,
+            }));
+        }
+
+        Ok(restore)
+    }
+
     fn bounds(&mut self, configure: Resource<ToplevelConfigure>, bounds: Option<Size>) -> wasmtime::Result<()> {
-        let configure = self.get_toplevel_configure(&configure)?;
-        configure.bounds = ConfigureUpdate::Update(bounds);
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        configure.configuration.bounds = ConfigureUpdate::Update(bounds);
+        Ok(())
+    }
+
+    fn clip(&mut self, configure: Resource<ToplevelConfigure>, clip: Option<Geometry>) -> wasmtime::Result<()> {
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        configure.configuration.clip = ConfigureUpdate::Update(clip);
+        Ok(())
+    }
+
+    fn corner_radius(&mut self, configure: Resource<ToplevelConfigure>, radius: u32) -> wasmtime::Result<()> {
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        configure.configuration.corner_radius = Some(radius);
+        Ok(())
+    }
+
+    fn fullscreen(
+        &mut self,
+        configure: Resource<ToplevelConfigure>,
+        output: Resource<Output>,
+        mode_hint: Option<OutputMode>,
+    ) -> wasmtime::Result<()> {
+        let output = self.get_output_res(&output)?;
+        let output_id = output.id;
+        let size = match &mode_hint {
+            Some(mode) => crate::fullscreen_size(mode, output.info.scale),
+            None => Size {
+                width: output.geometry.width,
+                height: output.geometry.height,
+            },
+        };
+
+        let (toplevel_id, configured_state) = {
+            let configure = self.get_toplevel_configure_res(&configure)?;
+            (configure.toplevel_id, configure.configuration.state)
+        };
+        // Keep any other states the wm already set in this configure.
+        let state = configured_state.unwrap_or(self.get_toplevel(toplevel_id)?.state) | ToplevelState::FULLSCREEN;
+
+        let configure = self.get_toplevel_configure_res(&configure)?;
+        configure.configuration.state = Some(state);
+        configure.configuration.size = ConfigureUpdate::Update(Some(size));
+        configure.configuration.fullscreen = Some((output_id, mode_hint));
         Ok(())
     }
 
     fn drop(&mut self, configure: Resource<ToplevelConfigure>) -> wasmtime::Result<()> {
-        todo!()
+        let id = self.get_id(&configure, IdType::ToplevelConfigure)?;
+        self.toplevel_configures.remove(&id.rep());
+        self.remove_id(id);
+        Ok(())
     }
 }
 
 impl HostSnapshot for WmState {
     fn size(&mut self, snapshot: Resource<Snapshot>) -> wasmtime::Result<Size> {
-        todo!()
+        Ok(self.get_snapshot_res(&snapshot)?.size)
     }
 
     fn scale(&mut self, snapshot: Resource<Snapshot>) -> wasmtime::Result<f32> {
-        todo!()
+        Ok(self.get_snapshot_res(&snapshot)?.scale)
     }
 
     fn drop(&mut self, snapshot: Resource<Snapshot>) -> wasmtime::Result<()> {
-        todo!()
+        let id = self.get_snapshot_res(&snapshot)?.id;
+        self.snapshots.remove(&id.rep());
+        self.remove_id(id);
+
+        let _ = self.sender.send(WmRequest::DestroySnapshot(id));
+        Ok(())
+    }
+}
+
+impl HostSeat for WmState {
+    fn id(&mut self, seat: Resource<Seat>) -> wasmtime::Result<SeatId> {
+        Ok(self.get_seat_res(&seat)?.id.rep().get())
+    }
+
+    fn name(&mut self, seat: Resource<Seat>) -> wasmtime::Result<String> {
+        Ok(self.get_seat_res(&seat)?.name.clone())
+    }
+
+    fn capabilities(&mut self, seat: Resource<Seat>) -> wasmtime::Result<SeatCapabilities> {
+        Ok(self.get_seat_res(&seat)?.capabilities)
+    }
+
+    fn pointer_position(&mut self, seat: Resource<Seat>) -> wasmtime::Result<Option<Point>> {
+        Ok(self.get_seat_res(&seat)?.pointer_position)
+    }
+
+    fn layout_index(&mut self, seat: Resource<Seat>) -> wasmtime::Result<u32> {
+        Ok(self.get_seat_res(&seat)?.layout)
+    }
+
+    fn warp_pointer(&mut self, seat: Resource<Seat>, position: Point) -> wasmtime::Result<()> {
+        let seat = self.get_seat_res(&seat)?;
+
+        if !seat.capabilities.contains(SeatCapabilities::POINTER) {
+            return Ok(());
+        }
+
+        // Update the position now so the wm sees the warp before the display server sends the motion.
+        seat.pointer_position = Some(position);
+        let seat = seat.id;
+
+        let _ = self.sender.send(WmRequest::WarpPointer { seat, position });
+        Ok(())
+    }
+
+    fn drop(&mut self, _seat: Resource<Seat>) -> wasmtime::Result<()> {
+        // The seat is owned by the display server and is removed with WmEvent::RemovedSeat.
+        Ok(())
     }
 }
@@ -0,0 +1,138 @@
+//! Images decoded by the display server
+//!
+//! Decoding images in wasm would mean every wm ships its own decoders. Instead the runtime decodes PNG and JPEG
+//! images, such as wallpapers and application icons, into textures the wm may use in nodes.
+
+use std::{
+    fmt, fs,
+    io::{self, Cursor},
+    path::Path,
+};
+
+use image::{io::Reader, ImageFormat, Limits, Rgba};
+
+use crate::host::aerugo::wm::types::{ImageSource, Texture};
+
+/// The largest width or height of an image.
+pub const MAX_IMAGE_SIZE: u32 = 16384;
+
+/// The largest image file.
+pub const MAX_IMAGE_FILE: u64 = 64 * 1024 * 1024;
+
+/// Why an image could not be loaded.
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+
+    /// The path of the image is not absolute.
+    RelativePath,
+
+    /// The image file is larger than [`MAX_IMAGE_FILE`].
+    FileTooLarge,
+
+    /// The image is not a PNG or JPEG image.
+    Unsupported,
+
+    Decode(image::ImageError),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(err) => write!(f, "failed to read image: {err}"),
+            ImageError::RelativePath => write!(f, "image path is not absolute"),
+            ImageError::FileTooLarge => write!(f, "image file is larger than {MAX_IMAGE_FILE} bytes"),
+            ImageError::Unsupported => write!(f, "image is not a PNG or JPEG image"),
+            ImageError::Decode(err) => write!(f, "failed to decode image: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// Load and decode an image.
+pub fn load(source: ImageSource) -> Result<Texture, ImageError> {
+    match source {
+        ImageSource::Path(path) => decode(&read(Path::new(&path))?),
+        ImageSource::Bytes(bytes) => decode(&bytes),
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, ImageError> {
+    if !path.is_absolute() {
+        return Err(ImageError::RelativePath);
+    }
+
+    if fs::metadata(path).map_err(ImageError::Io)?.len() > MAX_IMAGE_FILE {
+        return Err(ImageError::FileTooLarge);
+    }
+
+    fs::read(path).map_err(ImageError::Io)
+}
+
+/// Decode a PNG or JPEG image into a texture.
+pub fn decode(bytes: &[u8]) -> Result<Texture, ImageError> {
+    let mut reader = Reader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(ImageError::Io)?;
+
+    if !matches!(reader.format(), Some(ImageFormat::Png | ImageFormat::Jpeg)) {
+        return Err(ImageError::Unsupported);
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_SIZE);
+    limits.max_image_height = Some(MAX_IMAGE_SIZE);
+    reader.limits(limits);
+
+    let image = reader.decode().map_err(ImageError::Decode)?.into_rgba8();
+    let (width, height) = image.dimensions();
+    let pixels = image.pixels().flat_map(|&pixel| argb_premultiplied(pixel)).collect();
+
+    Ok(Texture { width, height, pixels })
+}
+
+/// Convert a pixel to premultiplied ARGB8888.
+fn argb_premultiplied(Rgba([r, g, b, a]): Rgba<u8>) -> [u8; 4] {
+    let premultiply = |channel: u8| ((u16::from(channel) * u16::from(a) + 127) / 255) as u8;
+
+    // ARGB8888 is little endian.
+    [premultiply(b), premultiply(g), premultiply(r), a]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageOutputFormat, Rgba, RgbaImage};
+
+    use super::{decode, load, ImageError};
+    use crate::host::aerugo::wm::types::ImageSource;
+
+    #[test]
+    fn decode_png() {
+        let mut png = Cursor::new(Vec::new());
+        let image = RgbaImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgba([255, 128, 0, 255]),
+            _ => Rgba([255, 255, 255, 128]),
+        });
+        image.write_to(&mut png, ImageOutputFormat::Png).unwrap();
+
+        let texture = decode(png.get_ref()).unwrap();
+        assert_eq!((texture.width, texture.height), (2, 1));
+        assert_eq!(texture.pixels, [0, 128, 255, 255, 128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn rejected() {
+        assert!(matches!(decode(b"GIF89a"), Err(ImageError::Unsupported)));
+        assert!(matches!(
+            load(ImageSource::Path("wallpaper.png".into())),
+            Err(ImageError::RelativePath)
+        ));
+        assert!(matches!(
+            load(ImageSource::Path("/nonexistent/wallpaper.png".into())),
+            Err(ImageError::Io(_))
+        ));
+    }
+}
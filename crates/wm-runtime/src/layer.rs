@@ -0,0 +1,243 @@
+//! Usable area of outputs with layer surfaces.
+//!
+//! Layer surfaces such as bars and docks may reserve an exclusive zone along an edge of the output. Toplevels
+//! should not be placed in the exclusive zone, so the wm is given the usable area of the output after every
+//! exclusive zone is removed.
+//!
+//! The wm may also reserve exclusive zones for its own UI, such as a bar drawn in the scene.
+//!
+//! An exclusive zone of a layer surface only applies if the layer surface is anchored to a single edge, or is anchored to an edge
+//! and both perpendicular edges. A surface anchored to opposite edges (or to no edges) does not reserve space.
+
+use crate::{
+    host::aerugo::wm::types::{Anchor, LayerSurfaceState, OutputEdge},
+    placement::Rect,
+};
+
+/// Exclusive zones the wm reserved for its own UI, such as a bar drawn in the scene.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WmZones {
+    pub top: i32,
+    pub bottom: i32,
+    pub left: i32,
+    pub right: i32,
+}
+
+impl WmZones {
+    /// Reserve the size along the edge, replacing the size reserved before.
+    pub fn set(&mut self, edge: OutputEdge, size: i32) {
+        let zone = match edge {
+            OutputEdge::Top => &mut self.top,
+            OutputEdge::Bottom => &mut self.bottom,
+            OutputEdge::Left => &mut self.left,
+            OutputEdge::Right => &mut self.right,
+        };
+
+        *zone = size;
+    }
+}
+
+fn exclusive_edge(anchor: Anchor) -> Option<OutputEdge> {
+    let horizontal = Anchor::LEFT | Anchor::RIGHT;
+    let vertical = Anchor::TOP | Anchor::BOTTOM;
+
+    // Being anchored to both perpendicular edges is the same as being anchored to neither.
+    let spans_horizontal = !anchor.intersects(horizontal) || anchor.contains(horizontal);
+    let spans_vertical = !anchor.intersects(vertical) || anchor.contains(vertical);
+
+    if anchor.contains(Anchor::TOP) && !anchor.contains(Anchor::BOTTOM) && spans_horizontal {
+        Some(OutputEdge::Top)
+    } else if anchor.contains(Anchor::BOTTOM) && !anchor.contains(Anchor::TOP) && spans_horizontal {
+        Some(OutputEdge::Bottom)
+    } else if anchor.contains(Anchor::LEFT) && !anchor.contains(Anchor::RIGHT) && spans_vertical {
+        Some(OutputEdge::Left)
+    } else if anchor.contains(Anchor::RIGHT) && !anchor.contains(Anchor::LEFT) && spans_vertical {
+        Some(OutputEdge::Right)
+    } else {
+        None
+    }
+}
+
+/// Remove the reserved size along the edge from the area.
+///
+/// The reserved size is clamped so the area never grows or becomes smaller than nothing.
+fn reserve(area: &mut Rect, edge: OutputEdge, size: i32) {
+    match edge {
+        OutputEdge::Top => {
+            let reserved = size.clamp(0, area.height.max(0));
+            area.y += reserved;
+            area.height -= reserved;
+        }
+        OutputEdge::Bottom => {
+            let reserved = size.clamp(0, area.height.max(0));
+            area.height -= reserved;
+        }
+        OutputEdge::Left => {
+            let reserved = size.clamp(0, area.width.max(0));
+            area.x += reserved;
+            area.width -= reserved;
+        }
+        OutputEdge::Right => {
+            let reserved = size.clamp(0, area.width.max(0));
+            area.width -= reserved;
+        }
+    }
+}
+
+/// Compute the usable area of an output.
+///
+/// `output` is the geometry of the output, `zones` are the exclusive zones reserved by the wm and `surfaces` are
+/// the states of the layer surfaces on the output. The zones of the wm are closest to the edges of the output.
+pub fn usable_area<'a>(
+    output: Rect,
+    zones: WmZones,
+    surfaces: impl IntoIterator<Item = &'a LayerSurfaceState>,
+) -> Rect {
+    let mut area = output;
+
+    for (edge, size) in [
+        (OutputEdge::Top, zones.top),
+        (OutputEdge::Bottom, zones.bottom),
+        (OutputEdge::Left, zones.left),
+        (OutputEdge::Right, zones.right),
+    ] {
+        if size > 0 {
+            reserve(&mut area, edge, size);
+        }
+    }
+
+    for state in surfaces {
+        if state.exclusive_zone <= 0 {
+            continue;
+        }
+
+        let Some(edge) = exclusive_edge(state.anchor) else {
+            continue;
+        };
+
+        let margin = match edge {
+            OutputEdge::Top => state.margin.top,
+            OutputEdge::Bottom => state.margin.bottom,
+            OutputEdge::Left => state.margin.left,
+            OutputEdge::Right => state.margin.right,
+        };
+
+        // A negative margin moves the surface off the edge, but never gives back space reserved by other zones.
+        reserve(&mut area, edge, state.exclusive_zone.saturating_add(margin));
+    }
+
+    area
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        host::aerugo::wm::types::{Anchor, KeyboardInteractivity, Layer, LayerSurfaceState, Margins, Size},
+        placement::Rect,
+    };
+
+    use super::{usable_area, WmZones};
+
+    const OUTPUT: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 1920,
+        height: 1080,
+    };
+
+    fn surface(anchor: Anchor, exclusive_zone: i32) -> LayerSurfaceState {
+        LayerSurfaceState {
+            layer: Layer::Top,
+            anchor,
+            exclusive_zone,
+            margin: Margins {
+                top: 0,
+                bottom: 0,
+                left: 0,
+                right: 0,
+            },
+            size: Size { width: 0, height: 0 },
+            keyboard_interactivity: KeyboardInteractivity::None,
+        }
+    }
+
+    #[test]
+    fn top_bar() {
+        let bar = surface(Anchor::TOP | Anchor::LEFT | Anchor::RIGHT, 30);
+        assert_eq!(
+            usable_area(OUTPUT, WmZones::default(), [&bar]),
+            Rect {
+                x: 0,
+                y: 30,
+                width: 1920,
+                height: 1050
+            }
+        );
+    }
+
+    #[test]
+    fn bar_and_dock_with_margin() {
+        let bar = surface(Anchor::TOP, 30);
+        let mut dock = surface(Anchor::LEFT | Anchor::TOP | Anchor::BOTTOM, 64);
+        dock.margin.left = 8;
+
+        assert_eq!(
+            usable_area(OUTPUT, WmZones::default(), [&bar, &dock]),
+            Rect {
+                x: 72,
+                y: 30,
+                width: 1848,
+                height: 1050
+            }
+        );
+    }
+
+    #[test]
+    fn no_exclusive_edge() {
+        let wallpaper = surface(Anchor::all(), 100);
+        let corner = surface(Anchor::TOP | Anchor::LEFT, 100);
+        let stretched = surface(Anchor::LEFT | Anchor::RIGHT, 100);
+        let negative = surface(Anchor::BOTTOM, -1);
+
+        assert_eq!(
+            usable_area(OUTPUT, WmZones::default(), [&wallpaper, &corner, &stretched, &negative]),
+            OUTPUT
+        );
+    }
+
+    #[test]
+    fn negative_margin_and_oversized_zones() {
+        let mut bar = surface(Anchor::TOP, 30);
+        bar.margin.top = -100;
+        let dock = surface(Anchor::LEFT, i32::MAX);
+        let zones = WmZones {
+            bottom: 2000,
+            ..WmZones::default()
+        };
+
+        let area = usable_area(OUTPUT, zones, [&bar, &dock]);
+        assert_eq!(area.width, 0);
+        assert_eq!(area.height, 0);
+        assert!(area.x >= OUTPUT.x && area.y >= OUTPUT.y);
+    }
+
+    #[test]
+    fn wm_zones() {
+        let bar = surface(Anchor::TOP, 30);
+        let zones = WmZones {
+            top: 24,
+            right: 48,
+            ..WmZones::default()
+        };
+
+        assert_eq!(
+            usable_area(OUTPUT, zones, [&bar]),
+            Rect {
+                x: 0,
+                y: 54,
+                width: 1872,
+                height: 1026
+            }
+        );
+    }
+}
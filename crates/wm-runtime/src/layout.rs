@@ -0,0 +1,277 @@
+//! Layout helpers for tiling wms.
+//!
+//! Splitting an area between toplevels is simple until toplevels have minimum and maximum sizes. The runtime
+//! provides the math so every tiling wm gets the same behavior:
+//!
+//! 1. The space along the split is shared by the weights of the children.
+//! 2. A child whose share is below its minimum size gets its minimum size, and the remaining space is shared by the
+//!    other children. Children above their maximum size are handled the same way.
+//! 3. If the minimum sizes do not fit, the space is shared by the minimum sizes instead.
+//!
+//! Children always fill the area across the split.
+
+use crate::{
+    host::aerugo::wm::types::{LayoutChild, Size, SplitDirection},
+    placement::Rect,
+};
+
+/// The smallest ratio a child may be shrunk to when adjusting ratios.
+pub const MIN_RATIO: f64 = 0.05;
+
+/// The length of the size along the split.
+fn along(size: Option<Size>, direction: SplitDirection) -> Option<f64> {
+    size.map(|size| match direction {
+        SplitDirection::Horizontal => f64::from(size.width),
+        SplitDirection::Vertical => f64::from(size.height),
+    })
+}
+
+/// A weight which may be used to share space, treating invalid weights as the default weight of 1.
+fn weight(child: &LayoutChild) -> f64 {
+    if child.weight.is_finite() && child.weight > 0.0 {
+        child.weight
+    } else {
+        1.0
+    }
+}
+
+/// Share the length between the children, respecting their minimum and maximum sizes.
+fn distribute(length: f64, children: &[LayoutChild], direction: SplitDirection) -> Vec<f64> {
+    let mins = children
+        .iter()
+        .map(|child| along(child.min_size, direction).unwrap_or(0.0))
+        .collect::<Vec<_>>();
+
+    // A maximum size of 0 means the size is unconstrained, and a maximum below the minimum is ignored.
+    let maxs = children
+        .iter()
+        .zip(&mins)
+        .map(|(child, &min)| {
+            along(child.max_size, direction)
+                .filter(|&max| max > 0.0 && max >= min)
+                .unwrap_or(f64::INFINITY)
+        })
+        .collect::<Vec<_>>();
+
+    let min_total = mins.iter().sum::<f64>();
+
+    if min_total > length {
+        return mins.iter().map(|min| min / min_total * length).collect();
+    }
+
+    let mut sizes = vec![None; children.len()];
+
+    loop {
+        let fixed = sizes.iter().flatten().sum::<f64>();
+        let remaining = (length - fixed).max(0.0);
+        let total_weight = children
+            .iter()
+            .zip(&sizes)
+            .filter(|(_, size)| size.is_none())
+            .map(|(child, _)| weight(child))
+            .sum::<f64>();
+
+        let share = |index: usize| remaining * weight(&children[index]) / total_weight;
+        let free = (0..children.len())
+            .filter(|&index| sizes[index].is_none())
+            .collect::<Vec<_>>();
+
+        // Fix the children below their minimum size first, since growing the others may push more children below
+        // their minimum.
+        let below = free
+            .iter()
+            .copied()
+            .filter(|&index| share(index) < mins[index])
+            .collect::<Vec<_>>();
+
+        if !below.is_empty() {
+            for index in below {
+                sizes[index] = Some(mins[index]);
+            }
+
+            continue;
+        }
+
+        let above = free
+            .iter()
+            .copied()
+            .filter(|&index| share(index) > maxs[index])
+            .collect::<Vec<_>>();
+
+        if !above.is_empty() {
+            for index in above {
+                sizes[index] = Some(maxs[index]);
+            }
+
+            continue;
+        }
+
+        for index in free {
+            sizes[index] = Some(share(index));
+        }
+
+        return sizes.into_iter().map(Option::unwrap).collect();
+    }
+}
+
+/// Split the area between the children, with a gap between each child.
+///
+/// Returns the geometry of each child in order. Positions are rounded so the children exactly cover the area when
+/// every child gets its share.
+pub fn split(area: Rect, direction: SplitDirection, children: &[LayoutChild], gap: u32) -> Vec<Rect> {
+    if children.is_empty() {
+        return Vec::new();
+    }
+
+    let gap = gap.min(i32::MAX as u32) as i32;
+    let (start, length) = match direction {
+        SplitDirection::Horizontal => (area.x, area.width),
+        SplitDirection::Vertical => (area.y, area.height),
+    };
+
+    let gaps = gap.saturating_mul(children.len() as i32 - 1);
+    let available = length.saturating_sub(gaps).max(0);
+    let sizes = distribute(f64::from(available), children, direction);
+
+    let mut rects = Vec::with_capacity(children.len());
+    let mut offset = 0.0;
+    let mut position = start;
+
+    for size in sizes {
+        // Round the edges rather than the sizes so rounding errors do not add up.
+        let begin = offset.round() as i32;
+        offset += size;
+        let child_length = offset.round() as i32 - begin;
+
+        rects.push(match direction {
+            SplitDirection::Horizontal => Rect {
+                x: position,
+                y: area.y,
+                width: child_length,
+                height: area.height,
+            },
+            SplitDirection::Vertical => Rect {
+                x: area.x,
+                y: position,
+                width: area.width,
+                height: child_length,
+            },
+        });
+
+        position = position.saturating_add(child_length).saturating_add(gap);
+    }
+
+    rects
+}
+
+/// Move the border after the child at the index by the delta, as a fraction of the whole split.
+///
+/// The ratios are normalized so they add up to 1. Neither child beside the border is shrunk below [`MIN_RATIO`].
+/// Returns the ratios unchanged, but normalized, if there is no child after the border.
+pub fn adjust_ratios(ratios: &[f64], border: usize, delta: f64) -> Vec<f64> {
+    let valid = |ratio: f64| if ratio.is_finite() && ratio > 0.0 { ratio } else { 0.0 };
+    let total = ratios.iter().copied().map(valid).sum::<f64>();
+
+    let mut ratios = if total > 0.0 {
+        ratios.iter().map(|&ratio| valid(ratio) / total).collect::<Vec<_>>()
+    } else {
+        vec![1.0 / ratios.len() as f64; ratios.len()]
+    };
+
+    if border + 1 >= ratios.len() || !delta.is_finite() {
+        return ratios;
+    }
+
+    let (before, after) = (ratios[border], ratios[border + 1]);
+
+    // Children which were already below the minimum are not shrunk further.
+    let delta = delta.clamp((MIN_RATIO - before).min(0.0), (after - MIN_RATIO).max(0.0));
+
+    ratios[border] = before + delta;
+    ratios[border + 1] = after - delta;
+    ratios
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        host::aerugo::wm::types::{LayoutChild, Size, SplitDirection},
+        placement::Rect,
+    };
+
+    use super::{adjust_ratios, split, MIN_RATIO};
+
+    const AREA: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 1000,
+        height: 600,
+    };
+
+    fn child(weight: f64, min_width: u32, max_width: u32) -> LayoutChild {
+        LayoutChild {
+            weight,
+            min_size: Some(Size {
+                width: min_width,
+                height: 0,
+            }),
+            max_size: Some(Size {
+                width: max_width,
+                height: 0,
+            }),
+        }
+    }
+
+    fn widths(rects: &[Rect]) -> Vec<i32> {
+        rects.iter().map(|rect| rect.width).collect()
+    }
+
+    #[test]
+    fn split_by_weight() {
+        let children = [child(1.0, 0, 0), child(1.0, 0, 0), child(1.0, 0, 0)];
+        let rects = split(AREA, SplitDirection::Horizontal, &children, 10);
+
+        assert_eq!(widths(&rects), [327, 326, 327]);
+        assert_eq!(rects[1].x, 337);
+        assert_eq!(rects[2].x + rects[2].width, AREA.width);
+        assert!(rects.iter().all(|rect| rect.height == AREA.height));
+
+        let rects = split(AREA, SplitDirection::Vertical, &[child(2.0, 0, 0), child(1.0, 0, 0)], 0);
+        assert_eq!(rects.iter().map(|rect| rect.height).collect::<Vec<_>>(), [400, 200]);
+    }
+
+    #[test]
+    fn min_and_max_sizes() {
+        // The first child would get 250, so it gets its minimum and the others share the rest.
+        let children = [child(1.0, 400, 0), child(1.0, 0, 0), child(1.0, 0, 0), child(1.0, 0, 0)];
+        assert_eq!(
+            widths(&split(AREA, SplitDirection::Horizontal, &children, 0)),
+            [400, 200, 200, 200]
+        );
+
+        let children = [child(1.0, 0, 200), child(1.0, 0, 0)];
+        assert_eq!(
+            widths(&split(AREA, SplitDirection::Horizontal, &children, 0)),
+            [200, 800]
+        );
+
+        // The minimum sizes do not fit, so the area is shared by the minimum sizes.
+        let children = [child(1.0, 1500, 0), child(1.0, 500, 0)];
+        assert_eq!(
+            widths(&split(AREA, SplitDirection::Horizontal, &children, 0)),
+            [750, 250]
+        );
+    }
+
+    #[test]
+    fn adjust() {
+        assert_eq!(adjust_ratios(&[1.0, 1.0], 0, 0.25), [0.75, 0.25]);
+
+        let clamped = adjust_ratios(&[1.0, 1.0], 0, 1.0);
+        assert!((clamped[1] - MIN_RATIO).abs() < 1e-9);
+        assert!((clamped[0] + clamped[1] - 1.0).abs() < 1e-9);
+
+        assert_eq!(adjust_ratios(&[2.0, 2.0], 1, 0.25), [0.5, 0.5]);
+        assert_eq!(adjust_ratios(&[0.0, 0.0], 0, f64::NAN), [0.5, 0.5]);
+    }
+}
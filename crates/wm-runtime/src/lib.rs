@@ -1,26 +1,59 @@
 //! Wasm WM runtime for the Aerugo.
 
+mod activation;
+mod binding;
+mod clicks;
+mod edge;
 mod host;
 mod id;
+mod images;
+mod layer;
+mod layout;
+mod placement;
 mod runner;
+mod supervisor;
+mod switcher;
+mod text;
+
+/// The types shared with the wm, generated from `wm.wit`.
+pub use host::aerugo::wm::types;
+
+/// Bindings as listed for IPC clients.
+pub use binding::{Action, BindingInfo, BindingOwner, KeyCombo, Trigger};
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{self, Display},
     num::NonZeroU32,
+    time::Duration,
 };
 
+use binding::Bindings;
 use calloop::{
     channel::{Channel, Sender},
     EventSource, Poll, PostAction, TokenFactory,
 };
+use clicks::Clicks;
+use edge::EdgeTriggers;
 use host::{
-    aerugo::wm::types::{DecorationMode, Features, Geometry, ResizeEdge, Server, Size, ToplevelState},
+    aerugo::wm::types::{
+        ActivationTokenInfo, AxisSource, BindingConflicts, ButtonState, Color, CursorShape, DecorationMode,
+        DecorationRegion, Features, Geometry, GestureKind, KeyFilter, KeyModifiers, KeyStatus, LayerSurfaceState,
+        OutputMode, OutputTransform, Point, PointerContext, PointerFilter, PopupParent, Positioner, ProcessStatus,
+        RenderProperties, ResizeEdge, SeatCapabilities, SelectionData, SelectionKind, Server, ServerDecorations, Size,
+        StateRequest, Subpixel, SwitchKind, TabLayout, Texture, ToplevelState, ToplevelUpdates, WindowRules, XkbRules,
+        ZoomFollow,
+    },
     exports::aerugo::wm::wm_types::WmTypes,
 };
+use id::IdAllocator;
+use layer::WmZones;
+use placement::Rect;
 use runner::WmRunner;
+use switcher::Switcher;
+use text::FontStack;
 use wasmtime::{
-    component::{Linker, Resource},
+    component::{Linker, Resource, ResourceAny},
     Config, Engine, Store,
 };
 
@@ -31,6 +64,13 @@ use wasmtime::{
 pub struct Id(NonZeroU32, IdType);
 
 impl Id {
+    /// An id of an object allocated by the display server.
+    ///
+    /// Returns [`None`] if the id is in the range the wm runtime allocates ids from.
+    pub fn server(rep: NonZeroU32, ty: IdType) -> Option<Self> {
+        (rep < RUNTIME_ID_START).then_some(Self(rep, ty))
+    }
+
     pub fn rep(self) -> NonZeroU32 {
         self.0
     }
@@ -40,6 +80,15 @@ impl Id {
     }
 }
 
+/// The first id allocated by the wm runtime.
+///
+/// Objects created by the wm (such as workspaces) are allocated ids from the top half of the id space so they
+/// never collide with ids allocated by the display server.
+const RUNTIME_ID_START: NonZeroU32 = match NonZeroU32::new(0x8000_0000) {
+    Some(id) => id,
+    None => unreachable!(),
+};
+
 /// The type of an id.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum IdType {
@@ -57,8 +106,29 @@ pub enum IdType {
     /// A snapshot is an object which references the contents of a surface for a given size and scale.
     Snapshot,
 
-    /// A view is a combination of a surface and a snapshot which can be presented.
-    View,
+    /// A node of the scene graph presented on outputs.
+    Node,
+
+    /// A popup.
+    Popup,
+
+    /// A layer surface.
+    LayerSurface,
+
+    /// An output configure.
+    OutputConfigure,
+
+    /// A toplevel configure.
+    ToplevelConfigure,
+
+    /// A workspace.
+    Workspace,
+
+    /// A group of toplevels of which only one member is visible.
+    ToplevelGroup,
+
+    /// A seat.
+    Seat,
 }
 
 /// An event sent to the wm runtime.
@@ -81,6 +151,21 @@ pub enum WmEvent {
         update: ToplevelUpdate,
     },
 
+    /// Notify the runtime of the window rules which apply to the toplevel.
+    ToplevelRules {
+        toplevel: Id,
+        rules: WindowRules,
+    },
+
+    /// Notify the runtime that a toplevel was committed.
+    CommittedToplevel {
+        toplevel: Id,
+        /// A snapshot of the toplevel surface, if the size changed.
+        snapshot: Option<SnapshotInfo>,
+        /// The subsurfaces of the toplevel, from bottom to top.
+        subsurfaces: Vec<SurfaceNodeInfo>,
+    },
+
     /// Notify the runtime that a configure has been acked.
     AckToplevel {
         toplevel: Id,
@@ -89,16 +174,350 @@ pub enum WmEvent {
 
     NewOutput {
         output: Id,
-        // TODO: Info
+        geometry: Geometry,
+        info: OutputInfo,
     },
 
-    /// TODO: Add to wit file
+    /// Notify the runtime that the geometry or properties of an output changed, such as after a mode set.
     UpdateOutput {
         output: Id,
-        // TODO: Info
+        geometry: Geometry,
+        info: OutputInfo,
     },
 
     DisconnectOutput(Id),
+
+    /// Notify the runtime that an output configure was applied or failed.
+    OutputConfigured {
+        output: Id,
+        serial: u32,
+        applied: bool,
+    },
+
+    /// Notify the runtime that the output is about to draw a frame.
+    Frame {
+        output: Id,
+        /// The presentation time of the previous frame in milliseconds.
+        time: u32,
+    },
+
+    /// Notify the runtime that a toplevel requested a change to its state.
+    RequestToplevelState {
+        toplevel: Id,
+        request: StateRequest,
+
+        /// The output requested by a fullscreen request.
+        output: Option<Id>,
+    },
+
+    /// Notify the runtime that the client of a toplevel stopped answering pings.
+    ToplevelUnresponsive(Id),
+
+    /// Notify the runtime that the client of an unresponsive toplevel answered a ping.
+    ToplevelResponsive(Id),
+
+    /// Notify the runtime that a toplevel started or stopped playing audio.
+    ToplevelAudio {
+        toplevel: Id,
+        playing: bool,
+    },
+
+    /// Notify the runtime that a fullscreen toplevel became eligible or stopped being eligible for direct scanout.
+    ScanoutStateChanged {
+        toplevel: Id,
+        scanout: bool,
+    },
+
+    /// Notify the runtime that a client requested the toplevel be activated with a valid token.
+    ActivationRequested {
+        toplevel: Id,
+        token: ActivationTokenInfo,
+    },
+
+    /// Notify the runtime that a new popup was created.
+    NewPopup {
+        popup: Id,
+
+        /// The parent of the popup, either a toplevel or a popup.
+        parent: Id,
+
+        positioner: Positioner,
+    },
+
+    /// Notify the runtime that a popup was dismissed or destroyed.
+    ClosedPopup(Id),
+
+    /// Notify the runtime that a popup requested to be repositioned.
+    RepositionPopup {
+        popup: Id,
+        positioner: Positioner,
+        token: u32,
+    },
+
+    /// Notify the runtime that a layer surface was mapped.
+    NewLayerSurface {
+        layer_surface: Id,
+
+        /// The output the layer surface was created on.
+        output: Option<Id>,
+
+        namespace: String,
+
+        state: LayerSurfaceState,
+    },
+
+    /// Notify the runtime that the state of a layer surface was committed.
+    UpdateLayerSurface {
+        layer_surface: Id,
+        state: LayerSurfaceState,
+    },
+
+    /// Notify the runtime that a layer surface configure has been acked.
+    AckLayerSurface {
+        layer_surface: Id,
+        serial: u32,
+    },
+
+    /// Notify the runtime that a layer surface was unmapped or destroyed.
+    ClosedLayerSurface(Id),
+
+    /// Notify the runtime that a client requested a workspace be activated.
+    RequestActivateWorkspace(Id),
+
+    /// Notify the runtime that a toplevel was dragged onto another workspace in the window switcher.
+    RequestMoveToWorkspace {
+        toplevel: Id,
+        workspace: Id,
+    },
+
+    /// Notify the runtime that a timer set by the wm expired.
+    TimerExpired(u32),
+
+    /// Notify the runtime that a process spawned by the wm exited or could not be started.
+    ProcessExited {
+        process: u32,
+        status: ProcessStatus,
+    },
+
+    /// Replace the wm with the wm module, handing the state of the current wm to the new wm.
+    Reload(Vec<u8>),
+
+    /// Notify the runtime that the clipboard or primary selection changed.
+    SelectionChanged {
+        kind: SelectionKind,
+        /// The mime types offered, or empty if the selection was cleared.
+        mime_types: Vec<String>,
+        /// The pid of the client which owns the selection.
+        owner: Option<i32>,
+    },
+
+    /// Set the shortcuts used by applications, which are reported as conflicts when the wm binds them.
+    SetAppShortcuts(Vec<KeyCombo>),
+
+    /// Request every binding for an IPC client.
+    ///
+    /// The runtime replies with [`WmRequest::Bindings`].
+    ListBindings,
+
+    /// Notify the runtime that a client started dragging data.
+    DragStarted {
+        /// A snapshot of the drag icon surface.
+        icon: Option<SnapshotInfo>,
+        mime_types: Vec<String>,
+    },
+
+    /// Notify the runtime that the pointer moved while dragging.
+    DragMotion {
+        position: Point,
+        /// The toplevel which is offered the data.
+        focus: Option<Id>,
+    },
+
+    /// Notify the runtime that the data was dropped.
+    DragDropped {
+        position: Point,
+        /// The toplevel the data was dropped on.
+        focus: Option<Id>,
+    },
+
+    /// Notify the runtime that the drag was cancelled.
+    DragCancelled,
+
+    /// Notify the runtime that a seat was created.
+    NewSeat {
+        seat: Id,
+        name: String,
+        capabilities: SeatCapabilities,
+    },
+
+    /// Notify the runtime that input devices were added to or removed from the seat.
+    SeatCapabilities {
+        seat: Id,
+        capabilities: SeatCapabilities,
+    },
+
+    /// Notify the runtime that the seat was removed.
+    RemovedSeat(Id),
+
+    /// A key was pressed or released.
+    ///
+    /// The serial is used to match the wm's [`WmRequest::KeyFilter`] to the event.
+    Key {
+        seat: Id,
+        serial: u32,
+        time: u32,
+        sym: u32,
+        compose: Option<String>,
+        status: KeyStatus,
+    },
+
+    /// The keyboard modifiers of the seat were updated.
+    KeyModifiers {
+        seat: Id,
+        modifiers: KeyModifiers,
+    },
+
+    /// Notify the runtime that the keymap changed, with the names of its layouts.
+    ///
+    /// The display server sends [`WmEvent::LayoutChanged`] for every seat afterwards.
+    KeymapChanged {
+        layouts: Vec<String>,
+    },
+
+    /// Notify the runtime that the active keyboard layout of the seat changed.
+    LayoutChanged {
+        seat: Id,
+        index: u32,
+    },
+
+    /// The pointer has moved.
+    ///
+    /// The serial is used to match the wm's [`WmRequest::PointerFilter`] to the event.
+    PointerMotion {
+        seat: Id,
+        serial: u32,
+        time: u32,
+        focus: Option<Id>,
+        position: Point,
+        /// The position in the global compositor space, returned when the wm queries the pointer position.
+        global: Point,
+    },
+
+    /// A pointer button was pressed or released.
+    PointerButton {
+        seat: Id,
+        serial: u32,
+        time: u32,
+        button: u32,
+        state: ButtonState,
+        /// The part of the scene under the pointer, used to match pointer bindings.
+        context: PointerContext,
+    },
+
+    /// The pointer moved over the server side decorations of a toplevel.
+    DecorationMotion {
+        seat: Id,
+        toplevel: Id,
+        time: u32,
+        /// The position relative to the top left corner of the decorations.
+        position: Point,
+        /// The position in the global compositor space, used to detect click gestures on the decorations.
+        global: Point,
+        region: DecorationRegion,
+    },
+
+    /// A pointer button was pressed or released over the server side decorations of a toplevel.
+    DecorationButton {
+        seat: Id,
+        toplevel: Id,
+        time: u32,
+        button: u32,
+        state: ButtonState,
+        position: Point,
+        region: DecorationRegion,
+    },
+
+    /// The pointer was scrolled.
+    PointerAxis {
+        seat: Id,
+        serial: u32,
+        time: u32,
+        horizontal: f64,
+        vertical: f64,
+        source: AxisSource,
+        /// The part of the scene under the pointer, used to match pointer bindings.
+        context: PointerContext,
+    },
+
+    /// A new touch point has appeared.
+    TouchDown {
+        seat: Id,
+        time: u32,
+        id: i32,
+        focus: Option<Id>,
+        position: Point,
+    },
+
+    /// A touch point has disappeared.
+    TouchUp {
+        seat: Id,
+        time: u32,
+        id: i32,
+    },
+
+    /// A touch point has moved.
+    TouchMotion {
+        seat: Id,
+        time: u32,
+        id: i32,
+        position: Point,
+    },
+
+    /// The touch session of the seat was cancelled.
+    TouchCancel(Id),
+
+    /// A touchpad gesture has begun.
+    ///
+    /// The serial is used to match the wm's [`WmRequest::PointerFilter`] to the event.
+    GestureBegin {
+        seat: Id,
+        serial: u32,
+        time: u32,
+        kind: GestureKind,
+        fingers: u32,
+    },
+
+    /// The fingers of the touchpad gesture have moved.
+    GestureUpdate {
+        seat: Id,
+        time: u32,
+        delta: Point,
+        scale: f64,
+        rotation: f64,
+    },
+
+    /// The touchpad gesture has ended.
+    GestureEnd {
+        seat: Id,
+        time: u32,
+        cancelled: bool,
+    },
+
+    /// A switch of an input device was toggled, such as the lid being closed.
+    ///
+    /// The display server only sends this when the state of the switch changes.
+    SwitchToggled {
+        seat: Id,
+        time: u32,
+        kind: SwitchKind,
+        on: bool,
+    },
+
+    /// A screen locker locked the session using `ext-session-lock-v1`.
+    SessionLocked,
+
+    /// The screen locker unlocked the session.
+    SessionUnlocked,
 }
 
 /// A request from the wm runtime.
@@ -107,13 +526,282 @@ pub enum WmRequest {
     /// The display server requested the wm runtime thread terminates.
     TerminateWm,
 
-    /// The wm runtime dropped the wm and it will no longer be used.
+    /// The wm was replaced by the wm module from [`WmEvent::Reload`].
     ///
-    /// TODO: Destruction semantics?
+    /// Timers, workspaces and toplevel groups of the previous wm were cancelled and destroyed before this request.
+    Reloaded,
+
+    /// The wm module from [`WmEvent::Reload`] could not be started, so the previous wm keeps running.
+    ReloadFailed(String),
+
+    /// The wm trapped or failed to handle an event.
+    ///
+    /// The display server should keep every toplevel mapped with a fallback layout and tell the user the wm
+    /// crashed. The wm runtime restarts the wm and sends [`WmRequest::Restarted`], unless the wm crashed too
+    /// often, in which case the wm stays stopped until a new wm is loaded with [`WmEvent::Reload`].
+    Crashed(String),
+
+    /// The wm was restarted after [`WmRequest::Crashed`].
+    Restarted,
+
+    /// The wm runtime dropped the toplevel and it will no longer be used.
     ToplevelDrop(Id),
 
     /// The wm runtime requested the toplevel with the specified id be closed.
     ToplevelRequestClose(Id),
+
+    /// The wm requested the client which owns the toplevel be disconnected.
+    KillClient(Id),
+
+    /// The wm configured the popup with the geometry relative to the parent.
+    PopupConfigure {
+        popup: Id,
+        geometry: Geometry,
+        reposition_token: Option<u32>,
+    },
+
+    /// The wm requested the popup be dismissed.
+    PopupDismiss(Id),
+
+    /// The wm runtime dropped the popup and it will no longer be used.
+    PopupDrop(Id),
+
+    /// The wm configured the layer surface with the size.
+    ///
+    /// The serial is allocated by the runtime and is sent back in [`WmEvent::AckLayerSurface`].
+    LayerSurfaceConfigure { layer_surface: Id, size: Size, serial: u32 },
+
+    /// The wm requested the layer surface be closed.
+    LayerSurfaceClose(Id),
+
+    /// The wm runtime dropped the layer surface and it will no longer be used.
+    LayerSurfaceDrop(Id),
+
+    /// The wm created a workspace.
+    CreateWorkspace {
+        workspace: Id,
+        name: String,
+        output: Option<Id>,
+    },
+
+    /// The wm renamed the workspace.
+    RenameWorkspace { workspace: Id, name: String },
+
+    /// The wm assigned the workspace to an output.
+    AssignWorkspace { workspace: Id, output: Id },
+
+    /// The wm activated the workspace on its output.
+    ActivateWorkspace(Id),
+
+    /// The wm dropped the workspace.
+    DestroyWorkspace(Id),
+
+    /// The wm created a toplevel group.
+    CreateToplevelGroup(Id),
+
+    /// The wm added the toplevel to the group, removing it from its previous group.
+    AddToToplevelGroup { group: Id, toplevel: Id },
+
+    /// The wm removed the toplevel from the group.
+    RemoveFromToplevelGroup { group: Id, toplevel: Id },
+
+    /// The wm made the toplevel the visible member of the group.
+    SetVisibleInToplevelGroup { group: Id, toplevel: Id },
+
+    /// The wm set how the tab bar of the group is arranged, or removed the tab bar.
+    SetToplevelGroupTabLayout { group: Id, layout: Option<TabLayout> },
+
+    /// The wm dropped the toplevel group, so its hidden members are shown.
+    DestroyToplevelGroup(Id),
+
+    /// Every binding with its owner, in reply to [`WmEvent::ListBindings`].
+    Bindings(Vec<BindingInfo>),
+
+    /// The active binding mode changed, either by a binding or by the wm.
+    BindingModeChanged(String),
+
+    /// The wm captured the last buffer committed by the toplevel, scaled down to the size.
+    SnapshotToplevel { toplevel: Id, snapshot: Id, size: Size },
+
+    /// The wm dropped the snapshot, so the backing storage may be destroyed.
+    DestroySnapshot(Id),
+
+    /// The wm created a scene node.
+    CreateNode { node: Id, kind: NodeKind },
+
+    /// The wm added a child to a scene node, above the other children.
+    AddNodeChild { parent: Id, child: Id },
+
+    /// The wm removed the scene node from its parent.
+    DetachNode(Id),
+
+    /// The wm placed the scene node directly above a sibling.
+    PlaceNodeAbove { node: Id, sibling: Id },
+
+    /// The wm moved the scene node relative to its parent.
+    SetNodeOffset { node: Id, offset: Point },
+
+    /// The wm set the properties used when compositing the scene node.
+    NodeRender { node: Id, properties: RenderProperties },
+
+    /// The wm resized a solid color, border or texture node.
+    SetNodeSize { node: Id, size: Size },
+
+    /// The wm changed the color of a solid color or border node.
+    SetNodeColor { node: Id, color: Color },
+
+    /// The wm dropped the scene node, removing it and its children from the scene.
+    DestroyNode(Id),
+
+    /// The wm presented the tree of the scene node on the output, or nothing if the node is [`None`].
+    Present { output: Id, root: Option<Id> },
+
+    /// The wm moved the toplevel to the workspace.
+    MoveToWorkspace { toplevel: Id, workspace: Id },
+
+    /// The wm set the keyboard focus.
+    ///
+    /// If the id is [`None`], the keyboard focus is cleared.
+    KeyboardFocus(Option<Id>),
+
+    /// The wm set the properties used when compositing the toplevel.
+    ToplevelRender { toplevel: Id, properties: RenderProperties },
+
+    /// The wm set the server side decorations drawn around the toplevel.
+    ToplevelDecorations {
+        toplevel: Id,
+        decorations: Option<ServerDecorations>,
+    },
+
+    /// The wm raised the toplevel above every other toplevel.
+    Raise(Id),
+
+    /// The wm lowered the toplevel below every other toplevel.
+    Lower(Id),
+
+    /// The wm placed the toplevel directly above the sibling.
+    RestackAbove { toplevel: Id, sibling: Id },
+
+    /// The wm requested an interactive move of the toplevel.
+    StartMove(Id),
+
+    /// The wm requested an interactive resize of the toplevel.
+    StartResize { toplevel: Id, edge: ResizeEdge },
+
+    /// The wm or a binding has decided whether the key event with the serial should be forwarded to the client.
+    KeyFilter { serial: u32, filter: KeyFilter },
+
+    /// The wm has decided whether the pointer event with the serial should be forwarded to the client.
+    PointerFilter { serial: u32, filter: PointerFilter },
+
+    /// The wm submitted a configure for the output.
+    ///
+    /// The display server replies with [`WmEvent::OutputConfigured`] using the serial.
+    ConfigureOutput {
+        output: Id,
+        serial: u32,
+        configuration: OutputConfiguration,
+    },
+
+    /// The wm submitted a configure for the toplevel.
+    ///
+    /// The display server replies with [`WmEvent::AckToplevel`] using the serial once the client acked the
+    /// configure.
+    ConfigureToplevel {
+        toplevel: Id,
+        serial: u32,
+        configuration: ToplevelConfiguration,
+    },
+
+    /// The wm requested the output draws a frame.
+    RequestFrame(Id),
+
+    /// The wm enabled or disabled the output.
+    SetOutputEnabled { output: Id, enabled: bool },
+
+    /// The wm turned the display of the output on or off.
+    SetOutputDpms { output: Id, on: bool },
+
+    /// The wm enabled or disabled variable refresh on the output.
+    SetOutputVrr { output: Id, enabled: bool },
+
+    /// The wm magnified the output.
+    SetOutputZoom { output: Id, level: f64, follow: ZoomFollow },
+
+    /// The wm set a timer.
+    ///
+    /// The display server sends [`WmEvent::TimerExpired`] with the id once the duration has passed.
+    SetTimer { timer: u32, duration: Duration },
+
+    /// The wm cancelled the timer.
+    CancelTimer(u32),
+
+    /// The wm changed a value in its persistent store, or removed the value if the value is [`None`].
+    ///
+    /// The display server should write the store to disk.
+    StoreSet { key: String, value: Option<String> },
+
+    /// The wm spawned a process.
+    ///
+    /// The display server sends [`WmEvent::ProcessExited`] with the id once the process exits.
+    Spawn {
+        process: u32,
+        command: String,
+        args: Vec<String>,
+        env: Vec<(String, String)>,
+    },
+
+    /// The wm created an activation token for a process it spawns.
+    ///
+    /// The display server should accept the token from clients activating a toplevel, and send
+    /// [`WmEvent::ActivationRequested`] when the token is used.
+    ActivationToken { token: String, app_id: Option<String> },
+
+    /// The wm set the cursor, or [`None`] to show the cursor set by clients again.
+    SetCursor(Option<WmCursor>),
+
+    /// The wm hid or showed the cursor regardless of the cursor set by clients or the wm.
+    SetCursorVisible(bool),
+
+    /// The wm moved the pointer of the seat to the position in the global compositor space.
+    ///
+    /// The display server moves the pointer as if the pointer moved to the position, so the pointer focus is
+    /// updated and the wm receives [`WmEvent::PointerMotion`]. No relative motion is sent to clients, since the
+    /// pointer did not move physically.
+    WarpPointer { seat: Id, position: Point },
+
+    /// The wm set the clipboard or primary selection.
+    ///
+    /// The selection is cleared if the data is empty.
+    SetSelection {
+        kind: SelectionKind,
+        data: Vec<SelectionData>,
+    },
+
+    /// Show the window switcher or update the selection of the window switcher.
+    ///
+    /// The toplevels are ordered from most to least recently focused. The display server draws a thumbnail of
+    /// each toplevel and outlines the selected toplevel.
+    ShowSwitcher { toplevels: Vec<Id>, selected: usize },
+
+    /// Hide the window switcher.
+    HideSwitcher,
+
+    /// The wm set the keymap of every keyboard.
+    ///
+    /// The display server sends [`WmEvent::KeymapChanged`] once the keymap is compiled.
+    SetKeymap(XkbRules),
+
+    /// The wm set the active keyboard layout of every seat.
+    SetLayoutIndex(u32),
+
+    /// The wm began a transaction.
+    ///
+    /// The configures sent until [`WmRequest::CommitTransaction`] are presented together.
+    BeginTransaction,
+
+    /// The wm committed the open transaction.
+    CommitTransaction,
 }
 
 /// A message from the wm runtime.
@@ -124,6 +812,147 @@ pub enum RuntimeMessage {
     Closed,
 }
 
+/// Changes to the configuration of an output. Properties which are [`None`] are not changed.
+#[derive(Debug, Clone, Default)]
+pub struct OutputConfiguration {
+    pub mode: Option<OutputMode>,
+    pub scale: Option<f64>,
+    pub transform: Option<OutputTransform>,
+    pub position: Option<(i32, i32)>,
+}
+
+/// Changes to the state of a toplevel. Properties which are [`None`] or [`ConfigureUpdate::None`] are not changed.
+#[derive(Debug, Clone, Default)]
+pub struct ToplevelConfiguration {
+    pub decorations: Option<DecorationMode>,
+    /// The toplevel the wm treats the toplevel as a child of. Only the wm runtime keeps the parent, since the
+    /// display server cannot change the parent of an xdg toplevel.
+    pub parent: ConfigureUpdate<Id>,
+    pub state: Option<ToplevelState>,
+    pub size: ConfigureUpdate<Size>,
+    pub bounds: ConfigureUpdate<Size>,
+    pub clip: ConfigureUpdate<Geometry>,
+    pub corner_radius: Option<u32>,
+    /// The output the toplevel is made fullscreen on and the hint for the mode of the output.
+    pub fullscreen: Option<(Id, Option<OutputMode>)>,
+}
+
+/// A cursor set by the wm.
+#[derive(Debug, Clone)]
+pub enum WmCursor {
+    /// A cursor from the cursor theme.
+    Shape(CursorShape),
+
+    /// An image uploaded by the wm.
+    Image { image: Texture, hotspot: Point },
+
+    /// The cursor is hidden.
+    Hidden,
+}
+
+/// What a scene node draws.
+#[derive(Debug, Clone)]
+pub enum NodeKind {
+    /// Nothing, the node only groups its children.
+    Group,
+
+    /// The surface tree of a toplevel.
+    Toplevel { toplevel: Id, snapshot: Id },
+
+    /// A color with premultiplied alpha filling the size.
+    Solid { size: Size, color: Color },
+
+    /// A border of the width along the inside of the size.
+    Border { size: Size, width: u32, color: Color },
+
+    /// A texture stretched to the size.
+    Texture { size: Size, texture: Texture },
+
+    /// A snapshot stretched to the size.
+    Snapshot { size: Size, snapshot: Id },
+}
+
+/// A snapshot created by the display server.
+#[derive(Debug, Clone)]
+pub struct SnapshotInfo {
+    pub id: Id,
+    pub size: Size,
+    pub scale: f32,
+}
+
+/// The size of a snapshot of a toplevel with the size.
+///
+/// The toplevel is scaled down to fit the maximum size while keeping the aspect ratio and is never scaled up.
+fn snapshot_size(size: Size, max_size: Option<Size>) -> Size {
+    let Some(max_size) = max_size else {
+        return size;
+    };
+
+    let scale = (f64::from(max_size.width) / f64::from(size.width.max(1)))
+        .min(f64::from(max_size.height) / f64::from(size.height.max(1)))
+        .min(1.0);
+
+    Size {
+        width: ((f64::from(size.width) * scale).round() as u32).max(1),
+        height: ((f64::from(size.height) * scale).round() as u32).max(1),
+    }
+}
+
+/// Render properties set by the wm, with values which cannot be drawn replaced.
+///
+/// The opacity is clamped between 0.0 and 1.0 and a negative scale is clamped to 0.0. A NaN or infinite value is
+/// replaced by the value of [`DEFAULT_RENDER`], so a wm dividing by zero during an animation draws the toplevel
+/// unchanged.
+fn sanitize_render(properties: RenderProperties) -> RenderProperties {
+    let finite_or = |value: f32, default: f32| if value.is_finite() { value } else { default };
+
+    RenderProperties {
+        opacity: finite_or(properties.opacity, DEFAULT_RENDER.opacity).clamp(0.0, 1.0),
+        scale: finite_or(properties.scale, DEFAULT_RENDER.scale).max(0.0),
+        rotation: finite_or(properties.rotation, DEFAULT_RENDER.rotation),
+        ..properties
+    }
+}
+
+/// The size of a toplevel which is fullscreen on an output with the mode and scale.
+fn fullscreen_size(mode: &OutputMode, scale: f64) -> Size {
+    Size {
+        width: (f64::from(mode.size.width) / scale).round() as u32,
+        height: (f64::from(mode.size.height) / scale).round() as u32,
+    }
+}
+
+/// A subsurface of a toplevel.
+#[derive(Debug, Clone)]
+pub struct SurfaceNodeInfo {
+    pub snapshot: Option<SnapshotInfo>,
+    /// The position relative to the toplevel.
+    pub offset: Point,
+    /// The index of the parent subsurface, or [`None`] if the parent is the toplevel.
+    pub parent: Option<u32>,
+    /// Whether the subsurface is drawn below the toplevel.
+    pub below: bool,
+}
+
+/// Properties of an output.
+#[derive(Debug, Clone)]
+pub struct OutputInfo {
+    /// The name of the connector, such as `DP-1`.
+    pub name: Option<String>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    /// The physical size in millimeters, if known.
+    pub physical_size: Option<Size>,
+    /// The refresh rate in millihertz.
+    pub refresh_rate: u32,
+    /// The scale factor, which may be fractional.
+    pub scale: f64,
+    pub subpixel: Subpixel,
+    /// Whether the output supports variable refresh.
+    pub vrr_capable: bool,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ToplevelUpdate {
     pub app_id: Option<String>,
@@ -133,8 +962,14 @@ pub struct ToplevelUpdate {
     pub geometry: ConfigureUpdate<Geometry>,
     pub parent: ConfigureUpdate<Id>,
     pub state: Option<ToplevelState>,
-    pub decorations: Option<DecorationMode>,
+    /// The decoration mode preferred by the client, from xdg-decoration.
+    pub decorations: ConfigureUpdate<DecorationMode>,
+    /// The edge the client requested an interactive resize by, from xdg_toplevel.resize.
     pub resize_edge: ConfigureUpdate<ResizeEdge>,
+    /// Whether the client requested an interactive move, from xdg_toplevel.move.
+    pub request_move: bool,
+    /// Whether the toplevel prefers variable refresh, from the content type of the toplevel.
+    pub prefers_variable_refresh: Option<bool>,
 }
 
 /// The WM runtime.
@@ -200,8 +1035,23 @@ impl EventSource for WmRuntime {
     }
 }
 
+/// The longest key of a value in the persistent store of the wm.
+pub const MAX_STORE_KEY: usize = 256;
+
+/// The longest value in the persistent store of the wm.
+pub const MAX_STORE_VALUE: usize = 64 * 1024;
+
+/// The most values in the persistent store of the wm.
+pub const MAX_STORE_ENTRIES: usize = 1024;
+
+/// The largest total size of the keys and values in the persistent store of the wm.
+pub const MAX_STORE_SIZE: usize = 1024 * 1024;
+
 impl WmRuntime {
-    pub fn new(bytes: &[u8]) -> wasmtime::Result<WmRuntime> {
+    /// Start the wm.
+    ///
+    /// The store contains the values the wm previously stored, which the display server loads from disk.
+    pub fn new(bytes: &[u8], store: HashMap<String, String>) -> wasmtime::Result<WmRuntime> {
         let (event_sender, event_channel) = calloop::channel::channel();
         let (req_sender, req_channel) = calloop::channel::channel();
 
@@ -213,55 +1063,72 @@ impl WmRuntime {
 
         let engine = Engine::new(&config)?;
 
-        let mut store = Store::new(
-            &engine,
-            WmState {
-                sender: req_sender,
-                ids: Vec::new(),
-                toplevels: HashMap::new(),
-            },
-        );
+        let mut store = Store::new(&engine, WmState::new(req_sender, store));
+        let (wm, funcs) = instantiate(&mut store, bytes)?;
 
-        let component = wasmtime::component::Component::new(&engine, bytes)?;
-        let linker = Linker::new(&engine);
+        let runtime = WmRuntime {
+            channel: req_channel,
+            sender: event_sender,
+        };
 
-        // TODO: Tune the fuel amount
-        store.add_fuel(10000).unwrap();
+        // Start the wm thread.
+        WmRunner::new(event_channel, store, wm, funcs, bytes.to_vec()).run()?;
+
+        Ok(runtime)
+    }
+
+    /// Replace the wm with a new version of the wm without losing the session.
+    ///
+    /// The state of the running wm is handed to the new wm, which then takes over every toplevel. The display
+    /// server receives [`WmRequest::Reloaded`] once the new wm is running, or [`WmRequest::ReloadFailed`] if the
+    /// previous wm keeps running.
+    pub fn reload(&self, bytes: Vec<u8>) {
+        let _ = self.sender.send(WmEvent::Reload(bytes));
+    }
+
+    /// A sender of events to the wm.
+    ///
+    /// The runtime is inserted into the event loop to receive requests, so the display server keeps the sender to
+    /// tell the wm about changes.
+    pub fn sender(&self) -> Sender<WmEvent> {
+        self.sender.clone()
+    }
+}
 
-        let (aerugo_wm, instance) = host::AerugoWm::instantiate(&mut store, &component, &linker)?;
-        let info = aerugo_wm
-            .aerugo_wm_wm_types()
-            .call_get_info(&mut store)?
-            .expect("Handle string error");
+/// Instantiate a wm module and create the wm.
+fn instantiate(store: &mut Store<WmState>, bytes: &[u8]) -> wasmtime::Result<(ResourceAny, WmTypes)> {
+    let engine = store.engine().clone();
+    let component = wasmtime::component::Component::new(&engine, bytes)?;
+    let linker = Linker::new(&engine);
 
-        // TODO: Validate info
+    // TODO: Tune the fuel amount
+    store.add_fuel(10000).unwrap();
 
-        // Allocate the server (id 0).
-        let server = Resource::new_own(0);
+    let (aerugo_wm, instance) = host::AerugoWm::instantiate(&mut *store, &component, &linker)?;
+    let info = aerugo_wm
+        .aerugo_wm_wm_types()
+        .call_get_info(&mut *store)?
+        .map_err(wasmtime::Error::msg)?;
 
-        // Initialize the wm on this thread.
-        let wm = aerugo_wm
-            .aerugo_wm_wm_types()
-            .call_create_wm(&mut store, server)?
-            .expect("Handle string error");
+    // TODO: Validate info
 
-        let mut exports = instance.exports(&mut store);
-        let mut export_wm = exports.instance("wm").expect("Handle missing wm export");
-        let funcs = WmTypes::new(&mut export_wm)?;
+    // Allocate the server (id 0).
+    let server = Resource::new_own(0);
 
-        // Rust wants us to explicitly drop exports for some reason...
-        drop(exports);
+    // Initialize the wm on this thread.
+    let wm = aerugo_wm
+        .aerugo_wm_wm_types()
+        .call_create_wm(&mut *store, server)?
+        .map_err(wasmtime::Error::msg)?;
 
-        let runtime = WmRuntime {
-            channel: req_channel,
-            sender: event_sender,
-        };
+    let mut exports = instance.exports(&mut *store);
+    let mut export_wm = exports.instance("wm").expect("Handle missing wm export");
+    let funcs = WmTypes::new(&mut export_wm)?;
 
-        // Start the wm thread.
-        WmRunner::new(event_channel, store, wm, funcs).run()?;
+    // Rust wants us to explicitly drop exports for some reason...
+    drop(exports);
 
-        Ok(runtime)
-    }
+    Ok((wm, funcs))
 }
 
 #[derive(Debug)]
@@ -289,7 +1156,13 @@ impl From<IdError> for Error {
 pub enum IdError {
     ZeroId,
 
-    InvalidId { rep: u32, ty: IdType },
+    InvalidId {
+        rep: u32,
+        ty: IdType,
+    },
+
+    /// The wm runtime has no ids left to allocate.
+    Exhausted,
 }
 
 impl Display for IdError {
@@ -297,6 +1170,7 @@ impl Display for IdError {
         match self {
             IdError::ZeroId => write!(f, "zero id"),
             IdError::InvalidId { rep, ty } => write!(f, "invalid id: Id {{ rep: {rep}, ty: {ty:?} }}"),
+            IdError::Exhausted => write!(f, "ids exhausted"),
         }
     }
 }
@@ -306,25 +1180,168 @@ impl std::error::Error for IdError {}
 #[derive(Debug)]
 struct WmState {
     sender: Sender<WmRequest>,
-    ids: Vec<Option<IdType>>,
+    ids: HashMap<NonZeroU32, IdType>,
+    /// Allocator for ids of objects created by the wm.
+    allocator: IdAllocator,
     toplevels: HashMap<NonZeroU32, WmToplevel>,
+    popups: HashMap<NonZeroU32, WmPopup>,
+    layer_surfaces: HashMap<NonZeroU32, WmLayerSurface>,
+    outputs: HashMap<NonZeroU32, WmOutput>,
+    output_configures: HashMap<NonZeroU32, WmOutputConfigure>,
+    toplevel_configures: HashMap<NonZeroU32, WmToplevelConfigure>,
+    workspaces: HashMap<NonZeroU32, WmWorkspace>,
+    toplevel_groups: HashMap<NonZeroU32, WmToplevelGroup>,
+    snapshots: HashMap<NonZeroU32, SnapshotInfo>,
+    nodes: HashMap<NonZeroU32, WmNode>,
+    seats: HashMap<NonZeroU32, WmSeat>,
+    /// The last serial allocated by the runtime.
+    serial: u32,
+    bindings: Bindings,
+    /// Whether keys which are not bound are forwarded to the client without calling the wm.
+    forward_unbound_keys: bool,
+    /// The names of the layouts of the keymap.
+    keyboard_layouts: Vec<String>,
+    /// Whether the lid is closed.
+    lid_closed: bool,
+    /// Whether a convertible laptop is folded into a tablet.
+    tablet_mode: bool,
+    /// Whether a screen locker locked the session.
+    session_locked: bool,
+    /// How many transactions are nested in the open transaction.
+    transaction_depth: u32,
+    switcher: Switcher,
+    /// Timers which have not expired or been cancelled, including the timers of long presses.
+    timers: HashSet<u32>,
+    /// The id of the next timer.
+    next_timer: u32,
+    edge_triggers: EdgeTriggers,
+    clicks: Clicks,
+    /// Processes spawned by the wm which have not exited.
+    processes: HashSet<u32>,
+    /// The id of the next process.
+    next_process: u32,
+    /// Values the wm persists across restarts.
+    store: HashMap<String, String>,
+    /// The fonts used to draw text, loaded when the wm first draws text.
+    fonts: Option<FontStack>,
 }
 
 impl WmState {
+    fn new(sender: Sender<WmRequest>, store: HashMap<String, String>) -> Self {
+        Self {
+            sender,
+            ids: HashMap::new(),
+            allocator: IdAllocator::new(RUNTIME_ID_START, NonZeroU32::MAX),
+            toplevels: HashMap::new(),
+            popups: HashMap::new(),
+            layer_surfaces: HashMap::new(),
+            outputs: HashMap::new(),
+            output_configures: HashMap::new(),
+            toplevel_configures: HashMap::new(),
+            workspaces: HashMap::new(),
+            toplevel_groups: HashMap::new(),
+            snapshots: HashMap::new(),
+            nodes: HashMap::new(),
+            serial: 0,
+            bindings: Bindings::new(),
+            forward_unbound_keys: false,
+            keyboard_layouts: Vec::new(),
+            lid_closed: false,
+            tablet_mode: false,
+            session_locked: false,
+            transaction_depth: 0,
+            seats: HashMap::new(),
+            switcher: Switcher::new(),
+            timers: HashSet::new(),
+            next_timer: 0,
+            edge_triggers: EdgeTriggers::new(),
+            clicks: Clicks::new(),
+            processes: HashSet::new(),
+            next_process: 0,
+            store,
+            fonts: None,
+        }
+    }
+
     fn get_id<T: 'static>(&self, resource: &Resource<T>, ty: IdType) -> Result<Id, Error> {
         let rep = NonZeroU32::new(resource.rep()).ok_or(IdError::ZeroId)?;
 
-        if self
-            .ids
-            .get(rep.get() as usize)
-            .ok_or(IdError::InvalidId { rep: rep.get(), ty })?
-            .filter(|&info| info == ty)
-            .is_none()
-        {
+        if self.ids.get(&rep) != Some(&ty) {
             return Err(Error::Id(IdError::InvalidId { rep: rep.get(), ty }));
         }
 
-        Ok(Id(rep, IdType::Toplevel))
+        Ok(Id(rep, ty))
+    }
+
+    /// Register the id so resources referring to the id can be validated.
+    fn insert_id(&mut self, id: Id) {
+        self.ids.insert(id.rep(), id.ty());
+    }
+
+    fn remove_id(&mut self, id: Id) {
+        self.ids.remove(&id.rep());
+    }
+
+    /// Allocate the id of a timer.
+    fn alloc_timer(&mut self) -> u32 {
+        // Skip ids of timers which are still pending if the id wrapped around.
+        let mut timer = self.next_timer;
+        while self.timers.contains(&timer) {
+            timer = timer.wrapping_add(1);
+        }
+
+        self.next_timer = timer.wrapping_add(1);
+        self.timers.insert(timer);
+        timer
+    }
+
+    /// Cancel a timer which has not expired.
+    fn cancel_timer(&mut self, timer: u32) {
+        if self.timers.remove(&timer) {
+            let _ = self.sender.send(WmRequest::CancelTimer(timer));
+        }
+    }
+
+    /// The edge triggers entered by moving the pointer of the seat to the global position.
+    fn edge_motion(&mut self, seat: Id, global: Point) -> Vec<u32> {
+        let outputs = &self.outputs;
+        self.edge_triggers.motion(seat, global, |output| {
+            outputs.get(&output.rep()).map(|output| Rect::from(output.geometry))
+        })
+    }
+
+    /// Allocate an id for an object created by the wm.
+    fn alloc_id(&mut self, ty: IdType) -> Result<Id, Error> {
+        let rep = self.allocator.alloc().map_err(|_| IdError::Exhausted)?;
+        let id = Id(rep, ty);
+        self.insert_id(id);
+        Ok(id)
+    }
+
+    /// Compute the usable area of the output from the exclusive zones of the wm and of the layer surfaces on the
+    /// output.
+    fn compute_usable_area(&self, output: Id) -> Option<Rect> {
+        let wm_output = self.outputs.get(&output.rep())?;
+        let surfaces = self
+            .layer_surfaces
+            .values()
+            .filter(|surface| surface.output == Some(output))
+            .map(|surface| &surface.state);
+
+        Some(layer::usable_area(wm_output.geometry.into(), wm_output.zones, surfaces))
+    }
+
+    /// Send the selection of the window switcher to the display server, or hide the switcher if it was closed.
+    fn update_switcher(&self) {
+        let request = match self.switcher.selection() {
+            Some((toplevels, selected)) => WmRequest::ShowSwitcher {
+                toplevels: toplevels.to_vec(),
+                selected,
+            },
+            None => WmRequest::HideSwitcher,
+        };
+
+        let _ = self.sender.send(request);
     }
 
     fn validate_id_server(&self, resource: &Resource<Server>) -> Result<(), Error> {
@@ -347,12 +1364,228 @@ impl WmState {
     fn get_toplevel(&mut self, id: Id) -> Result<&mut WmToplevel, Error> {
         self.toplevels.get_mut(&id.rep()).ok_or(Error::Id(IdError::InvalidId {
             rep: id.rep().get(),
-            ty: IdType::View,
+            ty: IdType::Toplevel,
+        }))
+    }
+
+    fn get_popup_res<T: 'static>(&mut self, resource: &Resource<T>) -> Result<&mut WmPopup, Error> {
+        let id = self.get_id(resource, IdType::Popup)?;
+
+        self.popups.get_mut(&id.rep()).ok_or(Error::Id(IdError::InvalidId {
+            rep: id.rep().get(),
+            ty: IdType::Popup,
+        }))
+    }
+
+    fn get_layer_surface_res<T: 'static>(&mut self, resource: &Resource<T>) -> Result<&mut WmLayerSurface, Error> {
+        let id = self.get_id(resource, IdType::LayerSurface)?;
+
+        self.layer_surfaces
+            .get_mut(&id.rep())
+            .ok_or(Error::Id(IdError::InvalidId {
+                rep: id.rep().get(),
+                ty: IdType::LayerSurface,
+            }))
+    }
+
+    fn get_output_res<T: 'static>(&mut self, resource: &Resource<T>) -> Result<&mut WmOutput, Error> {
+        let id = self.get_id(resource, IdType::Output)?;
+
+        self.outputs.get_mut(&id.rep()).ok_or(Error::Id(IdError::InvalidId {
+            rep: id.rep().get(),
+            ty: IdType::Output,
+        }))
+    }
+
+    fn get_output_configure_res<T: 'static>(
+        &mut self,
+        resource: &Resource<T>,
+    ) -> Result<&mut WmOutputConfigure, Error> {
+        let id = self.get_id(resource, IdType::OutputConfigure)?;
+
+        self.output_configures
+            .get_mut(&id.rep())
+            .ok_or(Error::Id(IdError::InvalidId {
+                rep: id.rep().get(),
+                ty: IdType::OutputConfigure,
+            }))
+    }
+
+    fn get_workspace_res<T: 'static>(&mut self, resource: &Resource<T>) -> Result<&mut WmWorkspace, Error> {
+        let id = self.get_id(resource, IdType::Workspace)?;
+
+        self.workspaces.get_mut(&id.rep()).ok_or(Error::Id(IdError::InvalidId {
+            rep: id.rep().get(),
+            ty: IdType::Workspace,
+        }))
+    }
+
+    fn get_toplevel_group_res<T: 'static>(&mut self, resource: &Resource<T>) -> Result<&mut WmToplevelGroup, Error> {
+        let id = self.get_id(resource, IdType::ToplevelGroup)?;
+
+        self.toplevel_groups
+            .get_mut(&id.rep())
+            .ok_or(Error::Id(IdError::InvalidId {
+                rep: id.rep().get(),
+                ty: IdType::ToplevelGroup,
+            }))
+    }
+
+    /// Remove the toplevel from the group it is in, if any.
+    fn remove_from_toplevel_group(&mut self, toplevel: Id) {
+        for group in self.toplevel_groups.values_mut() {
+            group.remove(toplevel);
+        }
+    }
+
+    /// Make the member of a pressed tab in the tab bar above the toplevel visible.
+    fn press_tab(&mut self, toplevel: Id, tab: u32) {
+        let Some(group) = self
+            .toplevel_groups
+            .values_mut()
+            .find(|group| group.tab_layout.is_some() && group.visible == Some(toplevel))
+        else {
+            return;
+        };
+
+        let Some(&member) = group.members.get(tab as usize) else {
+            return;
+        };
+
+        if group.visible == Some(member) {
+            return;
+        }
+
+        group.visible = Some(member);
+        let _ = self.sender.send(WmRequest::SetVisibleInToplevelGroup {
+            group: group.id,
+            toplevel: member,
+        });
+    }
+
+    /// Set the active keyboard layout of every seat.
+    fn select_layout(&mut self, index: u32) -> Result<(), String> {
+        if index as usize >= self.keyboard_layouts.len() {
+            return Err(format!(
+                "the keymap has {} layouts, so there is no layout {index}",
+                self.keyboard_layouts.len()
+            ));
+        }
+
+        let _ = self.sender.send(WmRequest::SetLayoutIndex(index));
+        Ok(())
+    }
+
+    /// Whether the switch is on.
+    fn switch_mut(&mut self, kind: SwitchKind) -> &mut bool {
+        match kind {
+            SwitchKind::Lid => &mut self.lid_closed,
+            SwitchKind::TabletMode => &mut self.tablet_mode,
+        }
+    }
+
+    fn get_snapshot_res<T: 'static>(&mut self, resource: &Resource<T>) -> Result<&mut SnapshotInfo, Error> {
+        let id = self.get_id(resource, IdType::Snapshot)?;
+
+        self.snapshots.get_mut(&id.rep()).ok_or(Error::Id(IdError::InvalidId {
+            rep: id.rep().get(),
+            ty: IdType::Snapshot,
+        }))
+    }
+
+    fn get_node_res<T: 'static>(&mut self, resource: &Resource<T>) -> Result<&mut WmNode, Error> {
+        let id = self.get_id(resource, IdType::Node)?;
+
+        self.nodes.get_mut(&id.rep()).ok_or(Error::Id(IdError::InvalidId {
+            rep: id.rep().get(),
+            ty: IdType::Node,
+        }))
+    }
+
+    /// Create a scene node which is not in the scene until the wm adds the node to a parent or presents it.
+    fn create_node<T: 'static>(&mut self, kind: NodeKind) -> Result<Resource<T>, Error> {
+        let id = self.alloc_id(IdType::Node)?;
+        self.nodes.insert(id.rep(), WmNode { id, parent: None });
+
+        let _ = self.sender.send(WmRequest::CreateNode { node: id, kind });
+        Ok(Resource::new_own(id.rep().get()))
+    }
+
+    /// Whether the node is the other node or one of its ancestors.
+    fn is_ancestor(&self, node: Id, of: Id) -> bool {
+        let mut next = Some(of);
+
+        while let Some(current) = next {
+            if current == node {
+                return true;
+            }
+
+            next = self.nodes.get(&current.rep()).and_then(|node| node.parent);
+        }
+
+        false
+    }
+
+    fn get_seat_res<T: 'static>(&mut self, resource: &Resource<T>) -> Result<&mut WmSeat, Error> {
+        let id = self.get_id(resource, IdType::Seat)?;
+        self.get_seat(id)
+    }
+
+    fn get_seat(&mut self, id: Id) -> Result<&mut WmSeat, Error> {
+        self.seats.get_mut(&id.rep()).ok_or(Error::Id(IdError::InvalidId {
+            rep: id.rep().get(),
+            ty: IdType::Seat,
         }))
     }
 
-    fn get_toplevel_configure<T: 'static>(&self, _resource: &Resource<T>) -> Result<&mut WmToplevelConfigure, Error> {
-        todo!()
+    /// The conflicts of binding the key combo in the mode, including the combo of the switcher.
+    fn key_binding_conflicts(&self, mode: &str, combo: KeyCombo) -> BindingConflicts {
+        let mut conflicts = self.bindings.key_conflicts(mode, combo);
+
+        if self
+            .switcher
+            .combo()
+            .is_some_and(|switcher| switcher.matches(combo.modifiers, combo.sym))
+        {
+            conflicts |= BindingConflicts::SWITCHER;
+        }
+
+        conflicts
+    }
+
+    /// Every binding with its owner, including the combo of the switcher.
+    fn list_bindings(&self) -> Vec<BindingInfo> {
+        let mut bindings = self.bindings.list();
+
+        if let Some(combo) = self.switcher.combo() {
+            bindings.push(BindingInfo {
+                mode: None,
+                trigger: Trigger::Key(combo),
+                action: None,
+                owner: BindingOwner::Switcher,
+            });
+        }
+
+        bindings
+    }
+
+    fn next_serial(&mut self) -> u32 {
+        self.serial = self.serial.wrapping_add(1);
+        self.serial
+    }
+
+    fn get_toplevel_configure_res<T: 'static>(
+        &mut self,
+        resource: &Resource<T>,
+    ) -> Result<&mut WmToplevelConfigure, Error> {
+        let id = self.get_id(resource, IdType::ToplevelConfigure)?;
+
+        self.toplevel_configures
+            .get_mut(&id.rep())
+            .ok_or(Error::Id(IdError::InvalidId {
+                rep: id.rep().get(),
+                ty: IdType::ToplevelConfigure,
+            }))
     }
 }
 
@@ -367,10 +1600,219 @@ struct WmToplevel {
     min_size: Option<Size>,
     max_size: Option<Size>,
     geometry: Option<Geometry>,
+    /// The geometry before the toplevel was maximized or made fullscreen.
+    restore_geometry: Option<Geometry>,
     parent: Option<Id>,
     state: ToplevelState,
     decorations: DecorationMode,
+    /// The decoration mode the client prefers, if the client has stated a preference.
+    preferred_decorations: Option<DecorationMode>,
     resize_edge: Option<ResizeEdge>,
+    workspace: Option<Id>,
+    render: RenderProperties,
+    rules: WindowRules,
+    server_decorations: Option<ServerDecorations>,
+    /// Whether the client stopped answering pings or acking configures.
+    unresponsive: bool,
+    /// Whether an audio stream of the toplevel is playing.
+    playing_audio: bool,
+    prefers_variable_refresh: bool,
+    /// Whether the toplevel is eligible for direct scanout.
+    scanout: bool,
+}
+
+impl WmToplevel {
+    /// Apply an update from the display server, returning which properties changed.
+    ///
+    /// The parent must already have been checked to be a known toplevel.
+    fn apply_update(&mut self, update: ToplevelUpdate) -> ToplevelUpdates {
+        let mut updates = ToplevelUpdates::empty();
+        let previous_geometry = self.geometry;
+
+        if let Some(app_id) = update.app_id.filter(|app_id| self.app_id.as_ref() != Some(app_id)) {
+            updates |= ToplevelUpdates::APP_ID;
+            self.app_id = Some(app_id);
+        }
+
+        if let Some(title) = update.title.filter(|title| self.title.as_ref() != Some(title)) {
+            updates |= ToplevelUpdates::TITLE;
+            self.title = Some(title);
+        }
+
+        if let ConfigureUpdate::Update(min_size) = update.min_size {
+            updates |= ToplevelUpdates::MIN_SIZE;
+            self.min_size = min_size;
+        }
+
+        if let ConfigureUpdate::Update(max_size) = update.max_size {
+            updates |= ToplevelUpdates::MAX_SIZE;
+            self.max_size = max_size;
+        }
+
+        if let ConfigureUpdate::Update(geometry) = update.geometry {
+            updates |= ToplevelUpdates::GEOMETRY;
+            self.geometry = geometry;
+        }
+
+        if let ConfigureUpdate::Update(parent) = update.parent {
+            if self.parent != parent {
+                updates |= ToplevelUpdates::PARENT;
+                self.parent = parent;
+            }
+        }
+
+        if let Some(state) = update.state {
+            let restorable = ToplevelState::MAXIMIZED | ToplevelState::FULLSCREEN;
+
+            // Save the geometry when the toplevel is first maximized or made fullscreen. Switching between
+            // maximized and fullscreen keeps the original geometry.
+            if !self.state.intersects(restorable) && state.intersects(restorable) && previous_geometry.is_some() {
+                self.restore_geometry = previous_geometry;
+                updates |= ToplevelUpdates::RESTORE_GEOMETRY;
+            }
+
+            self.state = state;
+        }
+
+        if let ConfigureUpdate::Update(decorations) = update.decorations {
+            if self.preferred_decorations != decorations {
+                updates |= ToplevelUpdates::DECORATIONS;
+                self.preferred_decorations = decorations;
+            }
+        }
+
+        if let ConfigureUpdate::Update(edge) = update.resize_edge {
+            updates |= ToplevelUpdates::REQUEST_RESIZE;
+            self.resize_edge = edge;
+        }
+
+        if update.request_move {
+            updates |= ToplevelUpdates::REQUEST_MOVE;
+        }
+
+        if let Some(prefers) = update.prefers_variable_refresh {
+            if self.prefers_variable_refresh != prefers {
+                updates |= ToplevelUpdates::VARIABLE_REFRESH;
+                self.prefers_variable_refresh = prefers;
+            }
+        }
+
+        updates
+    }
+}
+
+/// Render properties which composite the toplevel unchanged.
+const DEFAULT_RENDER: RenderProperties = RenderProperties {
+    opacity: 1.0,
+    scale: 1.0,
+    rotation: 0.0,
+    translate_x: 0,
+    translate_y: 0,
+    clip: None,
+    corner_radius: 0,
+};
+
+/// Window rules which do not change the toplevel.
+const NO_RULES: WindowRules = WindowRules {
+    workspace: None,
+    floating: None,
+    size: None,
+    opacity: None,
+};
+
+/// Popup wm runtime state.
+#[derive(Debug)]
+struct WmPopup {
+    id: Id,
+    parent: PopupParent,
+    positioner: Positioner,
+    geometry: Option<Geometry>,
+}
+
+/// Layer surface wm runtime state.
+#[derive(Debug)]
+struct WmLayerSurface {
+    id: Id,
+    output: Option<Id>,
+    namespace: String,
+    state: LayerSurfaceState,
+}
+
+/// Workspace wm runtime state.
+#[derive(Debug)]
+struct WmWorkspace {
+    id: Id,
+    name: String,
+    output: Option<Id>,
+    active: bool,
+}
+
+/// Toplevel group wm runtime state.
+///
+/// The display server tracks the same membership to hide the members which are not visible.
+#[derive(Debug)]
+struct WmToplevelGroup {
+    id: Id,
+    members: Vec<Id>,
+    visible: Option<Id>,
+    tab_layout: Option<TabLayout>,
+}
+
+impl WmToplevelGroup {
+    /// Remove a member, making the member after it visible if it was the visible member.
+    fn remove(&mut self, toplevel: Id) {
+        let Some(index) = self.members.iter().position(|&member| member == toplevel) else {
+            return;
+        };
+
+        self.members.remove(index);
+
+        if self.visible == Some(toplevel) {
+            self.visible = self.members.get(index).or(self.members.last()).copied();
+        }
+    }
+}
+
+/// Output wm runtime state.
+#[derive(Debug)]
+struct WmOutput {
+    id: Id,
+    geometry: Geometry,
+    info: OutputInfo,
+    /// The usable area last sent to the wm.
+    usable_area: Rect,
+    /// Exclusive zones the wm reserved on the output.
+    zones: WmZones,
+}
+
+/// Scene node wm runtime state.
+#[derive(Debug)]
+struct WmNode {
+    id: Id,
+    parent: Option<Id>,
+}
+
+/// Seat wm runtime state.
+#[derive(Debug)]
+struct WmSeat {
+    id: Id,
+    name: String,
+    capabilities: SeatCapabilities,
+    /// The current keyboard modifiers of the seat, used to match bindings.
+    modifiers: KeyModifiers,
+    /// The toplevel under the pointer of the seat.
+    pointer_focus: Option<Id>,
+    /// The position of the pointer in the global compositor space, if the pointer has moved.
+    pointer_position: Option<Point>,
+    /// The index of the active keyboard layout of the seat.
+    layout: u32,
+}
+
+/// Output configure wm runtime state.
+#[derive(Debug)]
+struct WmOutputConfigure {
+    output: Id,
+    configuration: OutputConfiguration,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -386,19 +1828,61 @@ impl<T> ConfigureUpdate<T> {
     }
 }
 
+/// Toplevel configure wm runtime state.
 #[derive(Debug)]
 struct WmToplevelConfigure {
     toplevel_id: Id,
-    decorations: Option<DecorationMode>,
-    parent: ConfigureUpdate<Id>,
-    state: Option<ToplevelState>,
-    size: ConfigureUpdate<Size>,
-    bounds: ConfigureUpdate<Size>,
+    configuration: ToplevelConfiguration,
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Id, WmEvent, WmRequest};
+    use std::num::NonZeroU32;
+
+    use wasmtime::component::Resource;
+
+    use crate::{
+        fullscreen_size,
+        host::aerugo::wm::types::{
+            Anchor, DecorationMode, Features, Geometry, HostToplevelConfigure, KeyboardInteractivity, Layer,
+            LayerSurfaceState, Margins, OutputMode, ResizeEdge, Size, Subpixel, TabLayout, ToplevelState,
+            ToplevelUpdates,
+        },
+        placement::Rect,
+        sanitize_render, snapshot_size, ConfigureUpdate, Id, IdType, OutputInfo, ToplevelUpdate, WmEvent,
+        WmLayerSurface, WmNode, WmOutput, WmRequest, WmState, WmToplevel, WmToplevelGroup, DEFAULT_RENDER, NO_RULES,
+    };
+
+    fn toplevel(id: u32) -> Id {
+        Id(NonZeroU32::new(id).unwrap(), IdType::Toplevel)
+    }
+
+    fn wm_toplevel() -> WmToplevel {
+        WmToplevel {
+            id: toplevel(1),
+            initial_commit: false,
+            features: Features::empty(),
+            app_id: None,
+            title: None,
+            min_size: None,
+            max_size: None,
+            geometry: None,
+            restore_geometry: None,
+            parent: None,
+            state: ToplevelState::empty(),
+            decorations: DecorationMode::ClientSide,
+            preferred_decorations: None,
+            resize_edge: None,
+            workspace: None,
+            render: DEFAULT_RENDER,
+            rules: NO_RULES,
+            server_decorations: None,
+            unresponsive: false,
+            playing_audio: false,
+            prefers_variable_refresh: false,
+            scanout: false,
+        }
+    }
 
     fn assert_send<T: Send>() {}
 
@@ -416,4 +1900,281 @@ mod tests {
     fn is_request_send() {
         assert_send::<WmRequest>();
     }
+
+    #[test]
+    fn toplevel_metadata_updates() {
+        let mut toplevel = wm_toplevel();
+
+        let updates = toplevel.apply_update(ToplevelUpdate {
+            app_id: Some("org.example.editor".into()),
+            title: Some("notes.txt".into()),
+            parent: ConfigureUpdate::Update(Some(self::toplevel(2))),
+            decorations: ConfigureUpdate::Update(Some(DecorationMode::ServerSide)),
+            ..ToplevelUpdate::default()
+        });
+        assert_eq!(
+            updates,
+            ToplevelUpdates::APP_ID | ToplevelUpdates::TITLE | ToplevelUpdates::PARENT | ToplevelUpdates::DECORATIONS
+        );
+        assert_eq!(toplevel.app_id.as_deref(), Some("org.example.editor"));
+        assert_eq!(toplevel.parent, Some(self::toplevel(2)));
+        assert_eq!(toplevel.preferred_decorations, Some(DecorationMode::ServerSide));
+
+        // Only properties which changed are reported.
+        let updates = toplevel.apply_update(ToplevelUpdate {
+            app_id: Some("org.example.editor".into()),
+            title: Some("notes.txt - modified".into()),
+            parent: ConfigureUpdate::Update(None),
+            ..ToplevelUpdate::default()
+        });
+        assert_eq!(updates, ToplevelUpdates::TITLE | ToplevelUpdates::PARENT);
+        assert_eq!(toplevel.parent, None);
+
+        // The client no longer prefers a decoration mode.
+        let updates = toplevel.apply_update(ToplevelUpdate {
+            decorations: ConfigureUpdate::Update(None),
+            ..ToplevelUpdate::default()
+        });
+        assert_eq!(updates, ToplevelUpdates::DECORATIONS);
+        assert_eq!(toplevel.preferred_decorations, None);
+
+        let update = || ToplevelUpdate {
+            prefers_variable_refresh: Some(true),
+            ..ToplevelUpdate::default()
+        };
+        assert_eq!(toplevel.apply_update(update()), ToplevelUpdates::VARIABLE_REFRESH);
+        assert!(toplevel.apply_update(update()).is_empty());
+
+        // Interactive move and resize requests are reported every time.
+        let update = || ToplevelUpdate {
+            request_move: true,
+            ..ToplevelUpdate::default()
+        };
+        assert_eq!(toplevel.apply_update(update()), ToplevelUpdates::REQUEST_MOVE);
+        assert_eq!(toplevel.apply_update(update()), ToplevelUpdates::REQUEST_MOVE);
+
+        let updates = toplevel.apply_update(ToplevelUpdate {
+            resize_edge: ConfigureUpdate::Update(Some(ResizeEdge::BottomRight)),
+            ..ToplevelUpdate::default()
+        });
+        assert_eq!(updates, ToplevelUpdates::REQUEST_RESIZE);
+        assert_eq!(toplevel.resize_edge, Some(ResizeEdge::BottomRight));
+    }
+
+    #[test]
+    fn usable_area_of_output() {
+        let (sender, _channel) = calloop::channel::channel();
+        let mut wm = WmState::new(sender, Default::default());
+        let output = |rep| Id(NonZeroU32::new(rep).unwrap(), IdType::Output);
+        let geometry = Geometry {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+
+        for rep in [1, 2] {
+            wm.outputs.insert(
+                output(rep).rep(),
+                WmOutput {
+                    id: output(rep),
+                    geometry,
+                    info: OutputInfo {
+                        name: None,
+                        make: None,
+                        model: None,
+                        serial: None,
+                        physical_size: None,
+                        refresh_rate: 60_000,
+                        scale: 1.0,
+                        subpixel: Subpixel::Unknown,
+                        vrr_capable: false,
+                    },
+                    usable_area: geometry.into(),
+                    zones: WmZones::default(),
+                },
+            );
+        }
+
+        // A bar on the first output.
+        let bar = Id(NonZeroU32::new(3).unwrap(), IdType::LayerSurface);
+        wm.layer_surfaces.insert(
+            bar.rep(),
+            WmLayerSurface {
+                id: bar,
+                output: Some(output(1)),
+                namespace: "bar".into(),
+                state: LayerSurfaceState {
+                    layer: Layer::Top,
+                    anchor: Anchor::TOP | Anchor::LEFT | Anchor::RIGHT,
+                    exclusive_zone: 30,
+                    margin: Margins {
+                        top: 0,
+                        bottom: 0,
+                        left: 0,
+                        right: 0,
+                    },
+                    size: Size { width: 0, height: 30 },
+                    keyboard_interactivity: KeyboardInteractivity::None,
+                },
+            },
+        );
+
+        assert_eq!(
+            wm.compute_usable_area(output(1)),
+            Some(Rect {
+                x: 0,
+                y: 30,
+                width: 1920,
+                height: 1050,
+            })
+        );
+        assert_eq!(wm.compute_usable_area(output(2)), Some(geometry.into()));
+        assert_eq!(wm.compute_usable_area(output(4)), None);
+    }
+
+    #[test]
+    fn node_cycles() {
+        let (sender, _channel) = calloop::channel::channel();
+        let mut wm = WmState::new(sender, Default::default());
+        let node = |rep| Id(NonZeroU32::new(rep).unwrap(), IdType::Node);
+
+        // A workspace group holding a toplevel group.
+        for (rep, parent) in [(1, None), (2, Some(node(1))), (3, Some(node(2))), (4, None)] {
+            wm.nodes.insert(node(rep).rep(), WmNode { id: node(rep), parent });
+        }
+
+        assert!(wm.is_ancestor(node(1), node(3)));
+        assert!(wm.is_ancestor(node(3), node(3)));
+        assert!(!wm.is_ancestor(node(3), node(1)));
+        assert!(!wm.is_ancestor(node(4), node(3)));
+    }
+
+    #[test]
+    fn snapshot_scaled_to_fit() {
+        let size = |width, height| Size { width, height };
+        let scaled = |width, height, max_size: Option<Size>| {
+            let size = snapshot_size(size(width, height), max_size);
+            (size.width, size.height)
+        };
+
+        assert_eq!(scaled(1920, 1080, None), (1920, 1080));
+        assert_eq!(scaled(1920, 1080, Some(size(320, 320))), (320, 180));
+        assert_eq!(scaled(100, 50, Some(size(320, 320))), (100, 50));
+        assert_eq!(scaled(4000, 10, Some(size(100, 100))), (100, 1));
+    }
+
+    #[test]
+    fn render_properties_sanitized() {
+        let sanitized = sanitize_render(crate::RenderProperties {
+            opacity: f32::NAN,
+            scale: -2.0,
+            rotation: f32::INFINITY,
+            translate_x: 10,
+            ..DEFAULT_RENDER
+        });
+
+        assert_eq!(sanitized.opacity, 1.0);
+        assert_eq!(sanitized.scale, 0.0);
+        assert_eq!(sanitized.rotation, 0.0);
+        assert_eq!(sanitized.translate_x, 10);
+
+        let sanitized = sanitize_render(crate::RenderProperties {
+            opacity: 1.5,
+            scale: f32::NAN,
+            ..DEFAULT_RENDER
+        });
+
+        assert_eq!(sanitized.opacity, 1.0);
+        assert_eq!(sanitized.scale, 1.0);
+    }
+
+    #[test]
+    fn fullscreen_size_of_mode() {
+        let mode = OutputMode {
+            size: Size {
+                width: 2560,
+                height: 1440,
+            },
+            refresh: 144_000,
+        };
+
+        let size = fullscreen_size(&mode, 1.0);
+        assert_eq!((size.width, size.height), (2560, 1440));
+
+        let size = fullscreen_size(&mode, 1.5);
+        assert_eq!((size.width, size.height), (1707, 960));
+    }
+
+    #[test]
+    fn press_tab() {
+        let (sender, _channel) = calloop::channel::channel();
+        let mut wm = WmState::new(sender, Default::default());
+        let group = Id(NonZeroU32::new(10).unwrap(), IdType::ToplevelGroup);
+
+        wm.toplevel_groups.insert(
+            group.rep(),
+            WmToplevelGroup {
+                id: group,
+                members: vec![toplevel(1), toplevel(2), toplevel(3)],
+                visible: Some(toplevel(1)),
+                tab_layout: None,
+            },
+        );
+
+        // Groups without a tab bar have no tabs to press.
+        wm.press_tab(toplevel(1), 2);
+        assert_eq!(wm.toplevel_groups[&group.rep()].visible, Some(toplevel(1)));
+
+        wm.toplevel_groups.get_mut(&group.rep()).unwrap().tab_layout = Some(TabLayout::Tabbed);
+        wm.press_tab(toplevel(1), 2);
+        assert_eq!(wm.toplevel_groups[&group.rep()].visible, Some(toplevel(3)));
+
+        // Only the visible member has a tab bar.
+        wm.press_tab(toplevel(1), 0);
+        wm.press_tab(toplevel(3), 5);
+        assert_eq!(wm.toplevel_groups[&group.rep()].visible, Some(toplevel(3)));
+    }
+
+    #[test]
+    fn submit_toplevel_configure() {
+        let (sender, _channel) = calloop::channel::channel();
+        let mut wm = WmState::new(sender, Default::default());
+        wm.insert_id(toplevel(1));
+        wm.toplevels.insert(toplevel(1).rep(), wm_toplevel());
+
+        let configure = HostToplevelConfigure::new(&mut wm, Resource::new_borrow(1)).unwrap();
+        let rep = configure.rep();
+        wm.decorations(Resource::new_borrow(rep), DecorationMode::ServerSide)
+            .unwrap();
+        wm.state(Resource::new_borrow(rep), ToplevelState::MAXIMIZED).unwrap();
+
+        let serial = wm.submit(Resource::new_borrow(rep)).unwrap();
+        assert_eq!(wm.toplevels[&toplevel(1).rep()].decorations, DecorationMode::ServerSide);
+
+        // The configure may be submitted again with the properties set since the last submit.
+        let configure = wm.get_toplevel_configure_res(&Resource::<()>::new_borrow(rep)).unwrap();
+        assert!(configure.configuration.decorations.is_none());
+        assert!(configure.configuration.state.is_none());
+        assert_ne!(wm.submit(Resource::new_borrow(rep)).unwrap(), serial);
+
+        HostToplevelConfigure::drop(&mut wm, Resource::new_own(rep)).unwrap();
+        assert!(wm.submit(Resource::new_borrow(rep)).is_err());
+
+        // Configures cannot be created for ids which are not toplevels.
+        assert!(HostToplevelConfigure::new(&mut wm, Resource::new_borrow(rep)).is_err());
+    }
+
+    #[test]
+    fn select_layout() {
+        let (sender, _channel) = calloop::channel::channel();
+        let mut wm = WmState::new(sender, Default::default());
+
+        // The keymap is not known yet.
+        assert!(wm.select_layout(0).is_err());
+
+        wm.keyboard_layouts = vec!["English (US)".into(), "German".into()];
+        assert!(wm.select_layout(1).is_ok());
+        assert!(wm.select_layout(2).is_err());
+    }
 }
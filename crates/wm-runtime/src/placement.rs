@@ -0,0 +1,245 @@
+//! Initial placement of floating toplevels.
+//!
+//! Placement is provided by the host so every wm does not need to reimplement the same heuristics:
+//!
+//! 1. Dialogs (toplevels with a parent) are centered on the parent.
+//! 2. Other toplevels are centered in the area.
+//! 3. If another toplevel with the same app id is already at that position, the toplevel is cascaded.
+//! 4. If the toplevel would cover the focused toplevel, it is moved beside the focused toplevel if there is room.
+//!
+//! The result is always kept inside the area.
+
+use crate::host::aerugo::wm::types::{Geometry, Size};
+
+/// Offset between cascaded toplevels.
+const CASCADE_STEP: i32 = 32;
+
+/// Size used when the toplevel has no geometry or minimum size.
+const DEFAULT_SIZE: Size = Size {
+    width: 640,
+    height: 480,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    fn overlaps(&self, other: &Rect) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+
+    fn center_in(width: i32, height: i32, within: &Rect) -> Rect {
+        Rect {
+            x: within.x + (within.width - width) / 2,
+            y: within.y + (within.height - height) / 2,
+            width,
+            height,
+        }
+    }
+
+    /// Move the rectangle so it is inside the area.
+    ///
+    /// If the rectangle is larger than the area, the rectangle is aligned to the top left of the area.
+    fn clamp_to(mut self, area: &Rect) -> Rect {
+        self.width = self.width.min(area.width);
+        self.height = self.height.min(area.height);
+        self.x = self.x.clamp(area.x, area.x + area.width - self.width);
+        self.y = self.y.clamp(area.y, area.y + area.height - self.height);
+        self
+    }
+}
+
+impl From<Geometry> for Rect {
+    fn from(geometry: Geometry) -> Self {
+        Rect {
+            x: geometry.x,
+            y: geometry.y,
+            width: geometry.width.min(i32::MAX as u32) as i32,
+            height: geometry.height.min(i32::MAX as u32) as i32,
+        }
+    }
+}
+
+impl From<Rect> for Geometry {
+    fn from(rect: Rect) -> Self {
+        Geometry {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width.max(0) as u32,
+            height: rect.height.max(0) as u32,
+        }
+    }
+}
+
+/// Information about the toplevel being placed.
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    /// The size of the toplevel.
+    pub size: Option<Size>,
+
+    /// The geometry of the parent toplevel.
+    pub parent: Option<Rect>,
+
+    /// The geometry of the focused toplevel.
+    pub focused: Option<Rect>,
+}
+
+/// Place a toplevel inside the area.
+///
+/// `siblings` are the geometries of other toplevels with the same app id.
+pub fn place(placement: Placement, area: Rect, siblings: &[Rect]) -> Rect {
+    let size = placement.size.unwrap_or(DEFAULT_SIZE);
+    let width = (size.width.min(i32::MAX as u32) as i32).min(area.width);
+    let height = (size.height.min(i32::MAX as u32) as i32).min(area.height);
+
+    // Dialogs are centered on the parent and are not cascaded.
+    if let Some(parent) = placement.parent {
+        return Rect::center_in(width, height, &parent).clamp_to(&area);
+    }
+
+    let mut rect = Rect::center_in(width, height, &area);
+
+    // Cascade from the center until there is no sibling at the same position. If the cascade leaves the area,
+    // start again from the top left of the area.
+    while siblings
+        .iter()
+        .any(|sibling| sibling.x == rect.x && sibling.y == rect.y)
+    {
+        rect.x += CASCADE_STEP;
+        rect.y += CASCADE_STEP;
+
+        if rect.x + rect.width > area.x + area.width || rect.y + rect.height > area.y + area.height {
+            rect.x = area.x;
+            rect.y = area.y;
+
+            // Every position was taken, give up.
+            if siblings
+                .iter()
+                .any(|sibling| sibling.x == rect.x && sibling.y == rect.y)
+            {
+                break;
+            }
+        }
+    }
+
+    if let Some(focused) = placement.focused.filter(|focused| focused.overlaps(&rect)) {
+        let right = focused.x + focused.width;
+        let left = focused.x - rect.width;
+
+        if right + rect.width <= area.x + area.width {
+            rect.x = right;
+        } else if left >= area.x {
+            rect.x = left;
+        }
+    }
+
+    rect.clamp_to(&area)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::host::aerugo::wm::types::Size;
+
+    use super::{place, Placement, Rect, CASCADE_STEP};
+
+    const AREA: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 1920,
+        height: 1080,
+    };
+
+    fn placement(width: u32, height: u32) -> Placement {
+        Placement {
+            size: Some(Size { width, height }),
+            parent: None,
+            focused: None,
+        }
+    }
+
+    #[test]
+    fn centered() {
+        let rect = place(placement(800, 600), AREA, &[]);
+        assert_eq!(
+            rect,
+            Rect {
+                x: 560,
+                y: 240,
+                width: 800,
+                height: 600
+            }
+        );
+    }
+
+    #[test]
+    fn larger_than_area() {
+        let rect = place(placement(4000, 3000), AREA, &[]);
+        assert_eq!(rect, AREA);
+    }
+
+    #[test]
+    fn cascade() {
+        let first = place(placement(800, 600), AREA, &[]);
+        let second = place(placement(800, 600), AREA, &[first]);
+        assert_eq!(second.x, first.x + CASCADE_STEP);
+        assert_eq!(second.y, first.y + CASCADE_STEP);
+
+        let third = place(placement(800, 600), AREA, &[first, second]);
+        assert_eq!(third.x, second.x + CASCADE_STEP);
+    }
+
+    #[test]
+    fn dialog_centered_on_parent() {
+        let parent = Rect {
+            x: 100,
+            y: 100,
+            width: 600,
+            height: 400,
+        };
+        let rect = place(
+            Placement {
+                parent: Some(parent),
+                ..placement(200, 100)
+            },
+            AREA,
+            &[],
+        );
+        assert_eq!(
+            rect,
+            Rect {
+                x: 300,
+                y: 250,
+                width: 200,
+                height: 100
+            }
+        );
+    }
+
+    #[test]
+    fn avoids_focused() {
+        let focused = Rect {
+            x: 500,
+            y: 200,
+            width: 700,
+            height: 700,
+        };
+        let rect = place(
+            Placement {
+                focused: Some(focused),
+                ..placement(400, 300)
+            },
+            AREA,
+            &[],
+        );
+        assert_eq!(rect.x, 1200);
+        assert!(!rect.overlaps(&focused));
+    }
+}
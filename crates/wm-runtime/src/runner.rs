@@ -1,4 +1,4 @@
-use std::{fmt, io, thread};
+use std::{collections::HashSet, fmt, io, mem, thread, time::Instant};
 
 use calloop::channel::Channel;
 use wasmtime::{
@@ -7,11 +7,25 @@ use wasmtime::{
 };
 
 use crate::{
+    binding::{Bindings, KeyMatch, PointerMatch},
+    clicks::{Clicks, Press, Source},
+    edge::EdgeTriggers,
     host::{
-        aerugo::wm::types::{DecorationMode, Features, ToplevelUpdates},
+        aerugo::wm::types::{
+            ActivationTokenInfo, AxisSource, ButtonState, ClickGesture, ClickKind, ClickSource, DecorationMode,
+            DecorationRegion, Features, Focus, Geometry, GestureKind, KeyFilter, KeyModifiers, KeyStatus,
+            LayerSurfaceState, Point, PointerContext, PointerFilter, PopupParent, Positioner, ProcessStatus,
+            SeatCapabilities, Snapshot, SurfaceNode, SwitchKind, ToplevelUpdates, WindowRules,
+        },
         exports::aerugo::wm::wm_types::WmTypes,
     },
-    ConfigureUpdate, Id, ToplevelUpdate, WmEvent, WmState, WmToplevel,
+    layer::WmZones,
+    placement::Rect,
+    supervisor::Supervisor,
+    switcher::SwitcherKey,
+    ConfigureUpdate, Error, Id, IdError, IdType, OutputInfo, SnapshotInfo, SurfaceNodeInfo, ToplevelUpdate, WmEvent,
+    WmLayerSurface, WmNode, WmOutput, WmPopup, WmRequest, WmSeat, WmState, WmToplevel, WmToplevelGroup, WmWorkspace,
+    DEFAULT_RENDER, NO_RULES,
 };
 
 pub struct WmRunner {
@@ -19,6 +33,11 @@ pub struct WmRunner {
     store: Store<WmState>,
     wm: ResourceAny,
     funcs: WmTypes,
+    /// The wm module, used to restart the wm after it crashed.
+    bytes: Vec<u8>,
+    supervisor: Supervisor,
+    /// Whether the wm crashed and could not be restarted.
+    crashed: bool,
 }
 
 impl fmt::Debug for WmRunner {
@@ -27,17 +46,28 @@ impl fmt::Debug for WmRunner {
             .field("channel", &self.channel)
             .field("store", &self.store)
             .field("wm", &self.wm)
+            .field("supervisor", &self.supervisor)
+            .field("crashed", &self.crashed)
             .finish_non_exhaustive()
     }
 }
 
 impl WmRunner {
-    pub(super) fn new(channel: Channel<WmEvent>, store: Store<WmState>, wm: ResourceAny, funcs: WmTypes) -> Self {
+    pub(super) fn new(
+        channel: Channel<WmEvent>,
+        store: Store<WmState>,
+        wm: ResourceAny,
+        funcs: WmTypes,
+        bytes: Vec<u8>,
+    ) -> Self {
         Self {
             channel,
             store,
             wm,
             funcs,
+            bytes,
+            supervisor: Supervisor::default(),
+            crashed: false,
         }
     }
 
@@ -47,6 +77,12 @@ impl WmRunner {
                 // Since this is run on a separate thread, we want to manually poll and suspend the thread if no
                 // wm events are pending.
                 match self.channel.recv() {
+                    Ok(event) if self.crashed => {
+                        if let Err(err) = self.dispatch_crashed(event) {
+                            tracing::error!(?err, "Failed to track objects while the wm is stopped");
+                        }
+                    }
+
                     Ok(event) => {
                         // Dispatch the event on the runtime.
                         // Add some fuel for while dispatching.
@@ -54,13 +90,248 @@ impl WmRunner {
                             WmEvent::NewToplevel { toplevel, features } => self.new_toplevel(toplevel, features),
                             WmEvent::ClosedToplevel(id) => self.closed_toplevel(id),
                             WmEvent::UpdateToplevel { toplevel, update } => self.update_toplevel(toplevel, update),
-                            WmEvent::AckToplevel { toplevel, serial } => todo!(),
-                            WmEvent::NewOutput { output } => todo!(),
-                            WmEvent::UpdateOutput { output } => todo!(),
-                            WmEvent::DisconnectOutput(_) => todo!(),
+                            WmEvent::ToplevelRules { toplevel, rules } => self.toplevel_rules(toplevel, rules),
+                            WmEvent::CommittedToplevel {
+                                toplevel,
+                                snapshot,
+                                subsurfaces,
+                            } => self.committed_toplevel(toplevel, snapshot, subsurfaces),
+                            WmEvent::AckToplevel { toplevel, serial } => self.funcs.wm().call_ack_toplevel(
+                                &mut self.store,
+                                self.wm,
+                                toplevel.rep().get(),
+                                serial,
+                            ),
+                            WmEvent::RequestToplevelState {
+                                toplevel,
+                                request,
+                                output,
+                            } => self.funcs.wm().call_request_state(
+                                &mut self.store,
+                                self.wm,
+                                toplevel.rep().get(),
+                                request,
+                                output.map(|output| output.rep().get()),
+                            ),
+                            WmEvent::ToplevelUnresponsive(toplevel) => self.set_unresponsive(toplevel, true),
+                            WmEvent::ToplevelResponsive(toplevel) => self.set_unresponsive(toplevel, false),
+                            WmEvent::ToplevelAudio { toplevel, playing } => self.set_playing_audio(toplevel, playing),
+                            WmEvent::ScanoutStateChanged { toplevel, scanout } => self.set_scanout(toplevel, scanout),
+                            WmEvent::ActivationRequested { toplevel, token } => {
+                                self.activation_requested(toplevel, token)
+                            }
+                            WmEvent::NewOutput { output, geometry, info } => self.new_output(output, geometry, info),
+                            WmEvent::UpdateOutput { output, geometry, info } => {
+                                self.update_output(output, geometry, info)
+                            }
+                            WmEvent::DisconnectOutput(id) => self.disconnect_output(id),
+                            WmEvent::OutputConfigured {
+                                output,
+                                serial,
+                                applied,
+                            } => self.funcs.wm().call_output_configured(
+                                &mut self.store,
+                                self.wm,
+                                output.rep().get(),
+                                serial,
+                                applied,
+                            ),
+                            WmEvent::Frame { output, time } => {
+                                self.funcs
+                                    .wm()
+                                    .call_frame(&mut self.store, self.wm, output.rep().get(), time)
+                            }
+                            WmEvent::NewPopup {
+                                popup,
+                                parent,
+                                positioner,
+                            } => self.new_popup(popup, parent, positioner),
+                            WmEvent::ClosedPopup(id) => self.closed_popup(id),
+                            WmEvent::RepositionPopup {
+                                popup,
+                                positioner,
+                                token,
+                            } => self.reposition_popup(popup, positioner, token),
+                            WmEvent::NewLayerSurface {
+                                layer_surface,
+                                output,
+                                namespace,
+                                state,
+                            } => self.new_layer_surface(layer_surface, output, namespace, state),
+                            WmEvent::UpdateLayerSurface { layer_surface, state } => {
+                                self.update_layer_surface(layer_surface, state)
+                            }
+                            WmEvent::AckLayerSurface { layer_surface, serial } => self
+                                .funcs
+                                .wm()
+                                .call_ack_layer_surface(&mut self.store, self.wm, layer_surface.rep().get(), serial),
+                            WmEvent::ClosedLayerSurface(id) => self.closed_layer_surface(id),
+                            WmEvent::RequestActivateWorkspace(id) => self.funcs.wm().call_request_activate_workspace(
+                                &mut self.store,
+                                self.wm,
+                                id.rep().get(),
+                            ),
+                            WmEvent::RequestMoveToWorkspace { toplevel, workspace } => {
+                                self.funcs.wm().call_request_move_to_workspace(
+                                    &mut self.store,
+                                    self.wm,
+                                    toplevel.rep().get(),
+                                    workspace.rep().get(),
+                                )
+                            }
+                            WmEvent::TimerExpired(timer) => self.timer_expired(timer),
+                            WmEvent::ProcessExited { process, status } => self.process_exited(process, status),
+                            WmEvent::Reload(bytes) => self.reload(&bytes),
+                            WmEvent::SelectionChanged {
+                                kind,
+                                mime_types,
+                                owner,
+                            } => self.funcs.wm().call_selection_changed(
+                                &mut self.store,
+                                self.wm,
+                                kind,
+                                &mime_types,
+                                owner,
+                            ),
+                            WmEvent::SetAppShortcuts(shortcuts) => {
+                                self.store.data_mut().bindings.set_app_shortcuts(shortcuts);
+                                Ok(())
+                            }
+                            WmEvent::ListBindings => {
+                                let state = self.store.data();
+                                let _ = state.sender.send(WmRequest::Bindings(state.list_bindings()));
+                                Ok(())
+                            }
+                            WmEvent::DragStarted { icon, mime_types } => self.drag_started(icon, mime_types),
+                            WmEvent::DragMotion { position, focus } => self.funcs.wm().call_drag_motion(
+                                &mut self.store,
+                                self.wm,
+                                position,
+                                focus_from_id(focus),
+                            ),
+                            WmEvent::DragDropped { position, focus } => self.funcs.wm().call_drag_dropped(
+                                &mut self.store,
+                                self.wm,
+                                position,
+                                focus_from_id(focus),
+                            ),
+                            WmEvent::DragCancelled => self.funcs.wm().call_drag_cancelled(&mut self.store, self.wm),
+                            WmEvent::NewSeat {
+                                seat,
+                                name,
+                                capabilities,
+                            } => self.new_seat(seat, name, capabilities),
+                            WmEvent::SeatCapabilities { seat, capabilities } => {
+                                self.seat_capabilities(seat, capabilities)
+                            }
+                            WmEvent::RemovedSeat(id) => self.removed_seat(id),
+                            WmEvent::Key {
+                                seat,
+                                serial,
+                                time,
+                                sym,
+                                compose,
+                                status,
+                            } => self.key(seat, serial, time, sym, compose, status),
+                            WmEvent::KeyModifiers { seat, modifiers } => self.key_modifiers(seat, modifiers),
+                            WmEvent::KeymapChanged { layouts } => self.keymap_changed(layouts),
+                            WmEvent::LayoutChanged { seat, index } => self.layout_changed(seat, index),
+                            WmEvent::PointerMotion {
+                                seat,
+                                serial,
+                                time,
+                                focus,
+                                position,
+                                global,
+                            } => self.pointer_motion(seat, serial, time, focus, position, global),
+                            WmEvent::PointerButton {
+                                seat,
+                                serial,
+                                time,
+                                button,
+                                state,
+                                context,
+                            } => self.pointer_button(seat, serial, time, button, state, context),
+                            WmEvent::DecorationMotion {
+                                seat,
+                                toplevel,
+                                time,
+                                position,
+                                global,
+                                region,
+                            } => self.decoration_motion(seat, toplevel, time, position, global, region),
+                            WmEvent::DecorationButton {
+                                seat,
+                                toplevel,
+                                time,
+                                button,
+                                state,
+                                position,
+                                region,
+                            } => self.decoration_button(seat, toplevel, time, button, state, position, region),
+                            WmEvent::PointerAxis {
+                                seat,
+                                serial,
+                                time,
+                                horizontal,
+                                vertical,
+                                source,
+                                context,
+                            } => self.pointer_axis(seat, serial, time, horizontal, vertical, source, context),
+                            WmEvent::TouchDown {
+                                seat,
+                                time,
+                                id,
+                                focus,
+                                position,
+                            } => self.touch_down(seat, time, id, focus, position),
+                            WmEvent::TouchUp { seat, time, id } => self.touch_up(seat, time, id),
+                            WmEvent::TouchMotion {
+                                seat,
+                                time,
+                                id,
+                                position,
+                            } => self.touch_motion(seat, time, id, position),
+                            WmEvent::TouchCancel(seat) => self.touch_cancel(seat),
+                            WmEvent::GestureBegin {
+                                seat,
+                                serial,
+                                time,
+                                kind,
+                                fingers,
+                            } => self.gesture_begin(seat, serial, time, kind, fingers),
+                            WmEvent::GestureUpdate {
+                                seat,
+                                time,
+                                delta,
+                                scale,
+                                rotation,
+                            } => self.funcs.wm().call_gesture_update(
+                                &mut self.store,
+                                self.wm,
+                                seat.rep().get(),
+                                time,
+                                delta,
+                                scale,
+                                rotation,
+                            ),
+                            WmEvent::GestureEnd { seat, time, cancelled } => self.funcs.wm().call_gesture_end(
+                                &mut self.store,
+                                self.wm,
+                                seat.rep().get(),
+                                time,
+                                cancelled,
+                            ),
+                            WmEvent::SwitchToggled { seat, time, kind, on } => {
+                                self.switch_toggled(seat, time, kind, on)
+                            }
+                            WmEvent::SessionLocked => self.set_session_locked(true),
+                            WmEvent::SessionUnlocked => self.set_session_locked(false),
                         };
 
-                        result.expect("handle error");
+                        if let Err(err) = result {
+                            self.wm_crashed(err);
+                        }
                     }
 
                     // The other end was closed.
@@ -72,89 +343,1496 @@ impl WmRunner {
         Ok(())
     }
 
-    // TODO: Somehow communicate all the initial state
+    /// The wm is told about the toplevel once the display server sends the initial state with the first update.
     fn new_toplevel(&mut self, id: Id, features: Features) -> wasmtime::Result<()> {
+        self.store.data_mut().insert_id(id);
         self.store.data_mut().toplevels.insert(
             id.rep(),
             WmToplevel {
                 id,
-                initial_commit: false,
+                initial_commit: true,
                 features,
                 app_id: Default::default(),
                 title: Default::default(),
                 min_size: Default::default(),
                 max_size: Default::default(),
                 geometry: Default::default(),
+                restore_geometry: Default::default(),
                 parent: Default::default(),
                 state: Default::default(),
                 decorations: DecorationMode::ClientSide,
+                preferred_decorations: None,
                 resize_edge: Default::default(),
+                workspace: Default::default(),
+                render: DEFAULT_RENDER,
+                rules: NO_RULES,
+                server_decorations: None,
+                unresponsive: false,
+                playing_audio: false,
+                prefers_variable_refresh: false,
+                scanout: false,
             },
         );
+        self.store.data_mut().switcher.added(id);
 
         Ok(())
     }
 
+    fn set_unresponsive(&mut self, id: Id, unresponsive: bool) -> wasmtime::Result<()> {
+        if !self.track_unresponsive(id, unresponsive) {
+            return Ok(());
+        }
+
+        let wm = self.funcs.wm();
+
+        if unresponsive {
+            wm.call_toplevel_unresponsive(&mut self.store, self.wm, id.rep().get())
+        } else {
+            wm.call_toplevel_responsive(&mut self.store, self.wm, id.rep().get())
+        }
+    }
+
+    /// Returns [`true`] if the toplevel became unresponsive or responsive again.
+    fn track_unresponsive(&mut self, id: Id, unresponsive: bool) -> bool {
+        let Some(toplevel) = self.store.data_mut().toplevels.get_mut(&id.rep()) else {
+            return false;
+        };
+
+        mem::replace(&mut toplevel.unresponsive, unresponsive) != unresponsive
+    }
+
+    fn set_playing_audio(&mut self, id: Id, playing: bool) -> wasmtime::Result<()> {
+        if !self.track_playing_audio(id, playing) {
+            return Ok(());
+        }
+
+        let wm = self.funcs.wm();
+        wm.call_toplevel_audio(&mut self.store, self.wm, id.rep().get(), playing)
+    }
+
+    /// Returns [`true`] if the toplevel started or stopped playing audio.
+    fn track_playing_audio(&mut self, id: Id, playing: bool) -> bool {
+        let Some(toplevel) = self.store.data_mut().toplevels.get_mut(&id.rep()) else {
+            return false;
+        };
+
+        mem::replace(&mut toplevel.playing_audio, playing) != playing
+    }
+
+    fn set_scanout(&mut self, id: Id, scanout: bool) -> wasmtime::Result<()> {
+        if !self.track_scanout(id, scanout) {
+            return Ok(());
+        }
+
+        let wm = self.funcs.wm();
+        wm.call_scanout_state_changed(&mut self.store, self.wm, id.rep().get(), scanout)
+    }
+
+    /// Returns [`true`] if the toplevel became eligible or stopped being eligible for direct scanout.
+    fn track_scanout(&mut self, id: Id, scanout: bool) -> bool {
+        let Some(toplevel) = self.store.data_mut().toplevels.get_mut(&id.rep()) else {
+            return false;
+        };
+
+        mem::replace(&mut toplevel.scanout, scanout) != scanout
+    }
+
+    fn activation_requested(&mut self, id: Id, token: ActivationTokenInfo) -> wasmtime::Result<()> {
+        // The wm is not told about the toplevel until the initial commit.
+        if self.store.data_mut().get_toplevel(id)?.initial_commit {
+            return Ok(());
+        }
+
+        self.funcs
+            .wm()
+            .call_activation_requested(&mut self.store, self.wm, id.rep().get(), &token)
+    }
+
     fn closed_toplevel(&mut self, id: Id) -> wasmtime::Result<()> {
+        self.track_closed_toplevel(id);
+
         self.funcs
             .wm()
             .call_closed_toplevel(&mut self.store, self.wm, id.rep().get())
     }
 
+    fn track_closed_toplevel(&mut self, id: Id) {
+        let state = self.store.data_mut();
+
+        let switcher_open = state.switcher.is_open();
+        state.switcher.removed(id);
+        state.remove_from_toplevel_group(id);
+
+        if switcher_open {
+            state.update_switcher();
+        }
+    }
+
     fn update_toplevel(&mut self, id: Id, update: ToplevelUpdate) -> wasmtime::Result<()> {
-        let mut updates = ToplevelUpdates::default();
+        let (initial_commit, updates) = self.track_update_toplevel(id, update)?;
+
+        if initial_commit {
+            let toplevel = Resource::new_own(id.rep().get());
+            self.funcs.wm().call_new_toplevel(&mut self.store, self.wm, toplevel)
+        } else {
+            self.funcs
+                .wm()
+                .call_update_toplevel(&mut self.store, self.wm, id.rep().get(), updates)
+        }
+    }
+
+    /// Returns whether this was the initial commit of the toplevel and which properties changed.
+    fn track_update_toplevel(&mut self, id: Id, update: ToplevelUpdate) -> wasmtime::Result<(bool, ToplevelUpdates)> {
         let wm = self.store.data_mut();
 
         // Check if the parent being set is valid before borrowing the toplevel data.
+        if let ConfigureUpdate::Update(Some(parent)) = update.parent {
+            wm.get_toplevel(parent)?;
+        }
 
         let toplevel = wm.get_toplevel(id)?;
+        let updates = toplevel.apply_update(update);
+
+        Ok((mem::take(&mut toplevel.initial_commit), updates))
+    }
+
+    fn committed_toplevel(
+        &mut self,
+        id: Id,
+        snapshot: Option<SnapshotInfo>,
+        subsurfaces: Vec<SurfaceNodeInfo>,
+    ) -> wasmtime::Result<()> {
+        let wm = self.store.data_mut();
+        wm.get_toplevel(id)?;
+
+        let mut own = |snapshot: SnapshotInfo| -> Resource<Snapshot> {
+            let rep = snapshot.id.rep();
+            wm.insert_id(snapshot.id);
+            wm.snapshots.insert(rep, snapshot);
+            Resource::new_own(rep.get())
+        };
+
+        let snapshot = snapshot.map(&mut own);
+        let subsurfaces = subsurfaces
+            .into_iter()
+            .map(|node| SurfaceNode {
+                snapshot: node.snapshot.map(&mut own),
+                offset: node.offset,
+                parent: node.parent,
+                below: node.below,
+            })
+            .collect::<Vec<_>>();
+
+        self.funcs
+            .wm()
+            .call_committed_toplevel(&mut self.store, self.wm, id.rep().get(), snapshot, &subsurfaces)
+    }
+
+    fn toplevel_rules(&mut self, id: Id, rules: WindowRules) -> wasmtime::Result<()> {
+        let toplevel = self.store.data_mut().get_toplevel(id)?;
+        toplevel.rules = rules;
+
+        // The wm queries the rules when it is told about the new toplevel.
+        if toplevel.initial_commit {
+            return Ok(());
+        }
+
+        self.funcs
+            .wm()
+            .call_update_toplevel(&mut self.store, self.wm, id.rep().get(), ToplevelUpdates::RULES)
+    }
+
+    fn new_popup(&mut self, id: Id, parent: Id, positioner: Positioner) -> wasmtime::Result<()> {
+        self.track_new_popup(id, parent, positioner)?;
+
+        let popup = Resource::new_own(id.rep().get());
+        self.funcs.wm().call_new_popup(&mut self.store, self.wm, popup)
+    }
+
+    fn track_new_popup(&mut self, id: Id, parent: Id, positioner: Positioner) -> wasmtime::Result<()> {
+        let parent = match parent.ty() {
+            IdType::Toplevel => PopupParent::Toplevel(parent.rep().get()),
+            IdType::Popup => PopupParent::Popup(parent.rep().get()),
+            ty => {
+                return Err(Error::Id(IdError::InvalidId {
+                    rep: parent.rep().get(),
+                    ty,
+                })
+                .into())
+            }
+        };
+
+        let wm = self.store.data_mut();
+        wm.insert_id(id);
+        wm.popups.insert(
+            id.rep(),
+            WmPopup {
+                id,
+                parent,
+                positioner,
+                geometry: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn closed_popup(&mut self, id: Id) -> wasmtime::Result<()> {
+        self.track_closed_popup(id);
+
+        self.funcs
+            .wm()
+            .call_closed_popup(&mut self.store, self.wm, id.rep().get())
+    }
+
+    fn track_closed_popup(&mut self, id: Id) {
+        let wm = self.store.data_mut();
+        wm.popups.remove(&id.rep());
+        wm.remove_id(id);
+    }
+
+    fn reposition_popup(&mut self, id: Id, positioner: Positioner, token: u32) -> wasmtime::Result<()> {
+        let Some(popup) = self.store.data_mut().popups.get_mut(&id.rep()) else {
+            return Err(Error::Id(IdError::InvalidId {
+                rep: id.rep().get(),
+                ty: IdType::Popup,
+            })
+            .into());
+        };
+
+        popup.positioner = positioner;
+
+        self.funcs
+            .wm()
+            .call_popup_repositioned(&mut self.store, self.wm, id.rep().get(), token)
+    }
+
+    fn new_output(&mut self, id: Id, geometry: Geometry, info: OutputInfo) -> wasmtime::Result<()> {
+        self.track_new_output(id, geometry, info);
+
+        let output = Resource::new_own(id.rep().get());
+        self.funcs.wm().call_new_output(&mut self.store, self.wm, output)
+    }
+
+    fn track_new_output(&mut self, id: Id, geometry: Geometry, info: OutputInfo) {
+        let wm = self.store.data_mut();
+        wm.insert_id(id);
+        wm.outputs.insert(
+            id.rep(),
+            WmOutput {
+                id,
+                geometry,
+                info,
+                usable_area: geometry.into(),
+                zones: WmZones::default(),
+            },
+        );
+    }
+
+    fn update_output(&mut self, id: Id, geometry: Geometry, info: OutputInfo) -> wasmtime::Result<()> {
+        let scale = info.scale;
+
+        if self.track_update_output(id, geometry, info) {
+            self.funcs
+                .wm()
+                .call_scale_changed(&mut self.store, self.wm, id.rep().get(), scale)?;
+        }
+
+        // The usable area follows the geometry of the output.
+        self.update_usable_area(Some(id))
+    }
+
+    /// Returns [`true`] if the scale of the output changed.
+    fn track_update_output(&mut self, id: Id, geometry: Geometry, info: OutputInfo) -> bool {
+        let Some(output) = self.store.data_mut().outputs.get_mut(&id.rep()) else {
+            return false;
+        };
+
+        let scale_changed = output.info.scale != info.scale;
+        output.geometry = geometry;
+        output.info = info;
+        scale_changed
+    }
+
+    fn disconnect_output(&mut self, id: Id) -> wasmtime::Result<()> {
+        self.track_disconnect_output(id);
+
+        self.funcs
+            .wm()
+            .call_disconnect_output(&mut self.store, self.wm, id.rep().get())
+    }
+
+    fn track_disconnect_output(&mut self, id: Id) {
+        let wm = self.store.data_mut();
+        wm.outputs.remove(&id.rep());
+        wm.remove_id(id);
+        wm.edge_triggers.remove_output(id);
 
-        if (toplevel.app_id != update.app_id) && update.app_id.is_some() {
-            updates |= ToplevelUpdates::APP_ID;
+        // Workspaces on the output are kept so the wm can assign them to another output.
+        for workspace in wm.workspaces.values_mut() {
+            if workspace.output == Some(id) {
+                workspace.output = None;
+                workspace.active = false;
+            }
         }
+    }
+
+    fn new_layer_surface(
+        &mut self,
+        id: Id,
+        output: Option<Id>,
+        namespace: String,
+        state: LayerSurfaceState,
+    ) -> wasmtime::Result<()> {
+        self.track_new_layer_surface(id, output, namespace, state);
+
+        let layer_surface = Resource::new_own(id.rep().get());
+        self.funcs
+            .wm()
+            .call_new_layer_surface(&mut self.store, self.wm, layer_surface)?;
+
+        self.update_usable_area(output)
+    }
+
+    fn track_new_layer_surface(&mut self, id: Id, output: Option<Id>, namespace: String, state: LayerSurfaceState) {
+        let wm = self.store.data_mut();
+        wm.insert_id(id);
+        wm.layer_surfaces.insert(
+            id.rep(),
+            WmLayerSurface {
+                id,
+                output,
+                namespace,
+                state,
+            },
+        );
+    }
 
-        if (toplevel.title != update.title) && update.title.is_some() {
-            updates |= ToplevelUpdates::TITLE;
+    fn update_layer_surface(&mut self, id: Id, state: LayerSurfaceState) -> wasmtime::Result<()> {
+        let exclusive_zone = state.exclusive_zone;
+
+        let Some(output) = self.track_update_layer_surface(id, state)? else {
+            return Ok(());
+        };
+
+        self.funcs
+            .wm()
+            .call_layer_surface_exclusive_zone(&mut self.store, self.wm, id.rep().get(), exclusive_zone)?;
+
+        self.update_usable_area(output)
+    }
+
+    /// Returns the output of the layer surface if the exclusive zone of the layer surface changed.
+    fn track_update_layer_surface(&mut self, id: Id, state: LayerSurfaceState) -> wasmtime::Result<Option<Option<Id>>> {
+        let Some(layer_surface) = self.store.data_mut().layer_surfaces.get_mut(&id.rep()) else {
+            return Err(Error::Id(IdError::InvalidId {
+                rep: id.rep().get(),
+                ty: IdType::LayerSurface,
+            })
+            .into());
+        };
+
+        let output = layer_surface.output;
+        let previous = std::mem::replace(&mut layer_surface.state, state);
+        let margins = |state: &LayerSurfaceState| {
+            let margin = state.margin;
+            (margin.top, margin.bottom, margin.left, margin.right)
+        };
+
+        // Anchoring to a different edge or changing the margin also moves the exclusive zone.
+        let moved = previous.exclusive_zone != state.exclusive_zone
+            || previous.anchor != state.anchor
+            || margins(&previous) != margins(&state);
+
+        Ok(moved.then_some(output))
+    }
+
+    fn closed_layer_surface(&mut self, id: Id) -> wasmtime::Result<()> {
+        let output = self.track_closed_layer_surface(id);
+
+        self.funcs
+            .wm()
+            .call_closed_layer_surface(&mut self.store, self.wm, id.rep().get())?;
+
+        self.update_usable_area(output)
+    }
+
+    /// Returns the output the layer surface was on.
+    fn track_closed_layer_surface(&mut self, id: Id) -> Option<Id> {
+        let wm = self.store.data_mut();
+        let output = wm.layer_surfaces.remove(&id.rep()).and_then(|surface| surface.output);
+        wm.remove_id(id);
+        output
+    }
+
+    /// Tell the wm if the usable area of the output changed.
+    fn update_usable_area(&mut self, output: Option<Id>) -> wasmtime::Result<()> {
+        let Some((output, area)) = self.track_usable_area(output) else {
+            return Ok(());
+        };
+
+        self.funcs
+            .wm()
+            .call_usable_area_changed(&mut self.store, self.wm, output.rep().get(), area.into())
+    }
+
+    /// Returns the output and its usable area if the usable area changed.
+    fn track_usable_area(&mut self, output: Option<Id>) -> Option<(Id, Rect)> {
+        let output = output?;
+        let wm = self.store.data_mut();
+        let area = wm.compute_usable_area(output)?;
+        let wm_output = wm.outputs.get_mut(&output.rep())?;
+
+        (mem::replace(&mut wm_output.usable_area, area) != area).then_some((output, area))
+    }
+
+    fn timer_expired(&mut self, timer: u32) -> wasmtime::Result<()> {
+        let data = self.store.data_mut();
+
+        // The timer may have been cancelled after the display server sent the event.
+        if !data.timers.remove(&timer) {
+            return Ok(());
         }
 
-        if let ConfigureUpdate::Update(min_size) = update.min_size {
-            updates |= ToplevelUpdates::MIN_SIZE;
-            toplevel.min_size = min_size;
+        if let Some((seat, press)) = data.clicks.timer_expired(timer) {
+            let time = press.time.wrapping_add(data.clicks.config().long_press);
+            return self.click_gesture(seat, time, ClickKind::LongPress, press);
         }
 
-        if let ConfigureUpdate::Update(max_size) = update.max_size {
-            updates |= ToplevelUpdates::MAX_SIZE;
-            toplevel.max_size = max_size;
+        self.funcs.wm().call_timer_expired(&mut self.store, self.wm, timer)
+    }
+
+    fn process_exited(&mut self, process: u32, status: ProcessStatus) -> wasmtime::Result<()> {
+        if !self.track_process_exited(process) {
+            return Ok(());
         }
 
-        if let ConfigureUpdate::Update(geometry) = update.geometry {
-            updates |= ToplevelUpdates::GEOMETRY;
-            toplevel.geometry = geometry;
+        self.funcs
+            .wm()
+            .call_process_exited(&mut self.store, self.wm, process, &status)
+    }
+
+    /// Returns [`true`] if the process was spawned by the current wm.
+    fn track_process_exited(&mut self, process: u32) -> bool {
+        self.store.data_mut().processes.remove(&process)
+    }
+
+    /// Replace the wm with a new wm module.
+    ///
+    /// The new wm restores the state serialized by the current wm and is then told about every output, seat,
+    /// toplevel, layer surface and popup as if they were just created. Bindings, timers, workspaces and processes
+    /// belong to the current wm and are not handed over. If the new wm fails to start, the current wm keeps running,
+    /// although requests the new wm made before failing are not undone.
+    fn reload(&mut self, bytes: &[u8]) -> wasmtime::Result<()> {
+        // A crashed wm cannot serialize its state, so the new wm starts over.
+        let state = match self.crashed {
+            true => None,
+            false => Some(self.funcs.wm().call_serialize_state(&mut self.store, self.wm)?),
+        };
+
+        match self.replace_wm(bytes, state.as_deref()) {
+            Ok(()) => {
+                self.bytes = bytes.to_vec();
+                self.crashed = false;
+                self.supervisor.reset();
+
+                let _ = self.store.data().sender.send(WmRequest::Reloaded);
+            }
+
+            Err(err) => {
+                tracing::error!(?err, "Failed to reload the wm");
+                let _ = self.store.data().sender.send(WmRequest::ReloadFailed(err.to_string()));
+            }
         }
 
-        if let ConfigureUpdate::Update(parent) = update.parent {
-            todo!()
+        Ok(())
+    }
+
+    /// The wm trapped or failed to handle an event.
+    ///
+    /// The wm is restarted with the same module unless it crashed too often.
+    fn wm_crashed(&mut self, err: wasmtime::Error) {
+        tracing::error!(?err, "The wm crashed");
+
+        // The wm will never commit the transaction it left open.
+        if mem::take(&mut self.store.data_mut().transaction_depth) != 0 {
+            let _ = self.store.data().sender.send(WmRequest::CommitTransaction);
         }
 
-        if let Some(state) = update.state {
-            // TODO
+        let _ = self.store.data().sender.send(WmRequest::Crashed(err.to_string()));
+
+        if !self.supervisor.crashed(Instant::now()) {
+            tracing::error!("The wm crashed too often and will not be restarted");
+            self.crashed = true;
+            return;
         }
 
-        if let Some(decorations) = update.decorations {}
+        let bytes = mem::take(&mut self.bytes);
+        let result = self.replace_wm(&bytes, None);
+        self.bytes = bytes;
+
+        match result {
+            Ok(()) => {
+                tracing::info!("Restarted the wm");
+                self.crashed = false;
+                let _ = self.store.data().sender.send(WmRequest::Restarted);
+            }
 
-        if let ConfigureUpdate::Update(edge) = update.resize_edge {
-            updates |= ToplevelUpdates::REQUEST_RESIZE;
+            Err(err) => {
+                tracing::error!(?err, "Failed to restart the wm");
+                self.crashed = true;
+            }
         }
+    }
 
-        if toplevel.initial_commit {
-            toplevel.initial_commit = false;
-            let toplevel = Resource::new_own(toplevel.id.rep().get());
+    /// Track objects while the wm is stopped, so the next wm is told about them.
+    ///
+    /// This is the only place events are handled while the wm is stopped, so the handlers used while the wm is
+    /// running always call the wm.
+    fn dispatch_crashed(&mut self, event: WmEvent) -> wasmtime::Result<()> {
+        match event {
+            WmEvent::NewToplevel { toplevel, features } => self.new_toplevel(toplevel, features),
+            WmEvent::ClosedToplevel(id) => {
+                self.track_closed_toplevel(id);
+                Ok(())
+            }
+            WmEvent::UpdateToplevel { toplevel, update } => self.track_update_toplevel(toplevel, update).map(|_| ()),
+            WmEvent::ToplevelUnresponsive(toplevel) => {
+                self.track_unresponsive(toplevel, true);
+                Ok(())
+            }
+            WmEvent::ToplevelResponsive(toplevel) => {
+                self.track_unresponsive(toplevel, false);
+                Ok(())
+            }
+            WmEvent::ToplevelAudio { toplevel, playing } => {
+                self.track_playing_audio(toplevel, playing);
+                Ok(())
+            }
+            WmEvent::ScanoutStateChanged { toplevel, scanout } => {
+                self.track_scanout(toplevel, scanout);
+                Ok(())
+            }
+            WmEvent::NewOutput { output, geometry, info } => {
+                self.track_new_output(output, geometry, info);
+                Ok(())
+            }
+            WmEvent::UpdateOutput { output, geometry, info } => {
+                self.track_update_output(output, geometry, info);
+                self.track_usable_area(Some(output));
+                Ok(())
+            }
+            WmEvent::DisconnectOutput(id) => {
+                self.track_disconnect_output(id);
+                Ok(())
+            }
+            WmEvent::NewPopup {
+                popup,
+                parent,
+                positioner,
+            } => self.track_new_popup(popup, parent, positioner),
+            WmEvent::ClosedPopup(id) => {
+                self.track_closed_popup(id);
+                Ok(())
+            }
+            WmEvent::NewLayerSurface {
+                layer_surface,
+                output,
+                namespace,
+                state,
+            } => {
+                self.track_new_layer_surface(layer_surface, output, namespace, state);
+                self.track_usable_area(output);
+                Ok(())
+            }
+            WmEvent::UpdateLayerSurface { layer_surface, state } => {
+                if let Some(output) = self.track_update_layer_surface(layer_surface, state)? {
+                    self.track_usable_area(output);
+                }
 
-            self.funcs.wm().call_new_toplevel(&mut self.store, self.wm, toplevel)
-        } else {
+                Ok(())
+            }
+            WmEvent::ClosedLayerSurface(id) => {
+                let output = self.track_closed_layer_surface(id);
+                self.track_usable_area(output);
+                Ok(())
+            }
+            WmEvent::ProcessExited { process, .. } => {
+                self.track_process_exited(process);
+                Ok(())
+            }
+            WmEvent::NewSeat {
+                seat,
+                name,
+                capabilities,
+            } => {
+                self.track_new_seat(seat, name, capabilities);
+                Ok(())
+            }
+            WmEvent::RemovedSeat(id) => {
+                self.track_removed_seat(id);
+                Ok(())
+            }
+            WmEvent::KeymapChanged { layouts } => self.keymap_changed(layouts),
+            WmEvent::LayoutChanged { seat, index } => self.track_layout_changed(seat, index),
+            WmEvent::SwitchToggled { kind, on, .. } => {
+                *self.store.data_mut().switch_mut(kind) = on;
+                Ok(())
+            }
+            WmEvent::SessionLocked => {
+                self.store.data_mut().session_locked = true;
+                Ok(())
+            }
+            WmEvent::SessionUnlocked => {
+                self.store.data_mut().session_locked = false;
+                Ok(())
+            }
+            WmEvent::Reload(bytes) => self.reload(&bytes),
+
+            // No wm will see the snapshots, so the display server may destroy them right away.
+            WmEvent::CommittedToplevel {
+                snapshot, subsurfaces, ..
+            } => {
+                let sender = &self.store.data().sender;
+                let snapshots = snapshot
+                    .into_iter()
+                    .chain(subsurfaces.into_iter().filter_map(|node| node.snapshot));
+
+                for snapshot in snapshots {
+                    let _ = sender.send(WmRequest::DestroySnapshot(snapshot.id));
+                }
+
+                Ok(())
+            }
+
+            // Gestures are sent to clients while no wm can consume them.
+            WmEvent::GestureBegin { serial, .. } => {
+                let filter = PointerFilter::Forward;
+                let _ = self
+                    .store
+                    .data()
+                    .sender
+                    .send(WmRequest::PointerFilter { serial, filter });
+                Ok(())
+            }
+
+            // Input and requests for the wm are dropped while the display server uses the fallback layout.
+            _ => Ok(()),
+        }
+    }
+
+    /// Start a wm module in place of the current wm.
+    ///
+    /// If the new wm fails to start, the current wm and its objects are left in place.
+    fn replace_wm(&mut self, bytes: &[u8], state: Option<&[u8]>) -> wasmtime::Result<()> {
+        // Move the runtime state to a store for the new wm.
+        let placeholder = WmState::new(self.store.data().sender.clone(), Default::default());
+        let data = mem::replace(self.store.data_mut(), placeholder);
+        let mut store = Store::new(self.store.engine(), data);
+        let previous = PreviousWm::take(store.data_mut());
+
+        match Self::start_wm(&mut store, bytes, state) {
+            Ok((wm, funcs)) => {
+                previous.destroy(store.data_mut());
+                self.store = store;
+                self.wm = wm;
+                self.funcs = funcs;
+                Ok(())
+            }
+
+            Err(err) => {
+                let mut data = store.into_data();
+                previous.restore(&mut data);
+                *self.store.data_mut() = data;
+                Err(err)
+            }
+        }
+    }
+
+    fn start_wm(
+        store: &mut Store<WmState>,
+        bytes: &[u8],
+        state: Option<&[u8]>,
+    ) -> wasmtime::Result<(ResourceAny, WmTypes)> {
+        let (wm, funcs) = crate::instantiate(store, bytes)?;
+
+        if let Some(state) = state {
+            funcs
+                .wm()
+                .call_restore_state(&mut *store, wm, state)?
+                .map_err(wasmtime::Error::msg)?;
+        }
+
+        // Sort the objects so parents, which are created first, are announced before their popups.
+        let sorted = |mut reps: Vec<u32>| {
+            reps.sort_unstable();
+            reps
+        };
+
+        let data = store.data();
+        let outputs = sorted(data.outputs.keys().map(|rep| rep.get()).collect());
+        let seats = sorted(data.seats.keys().map(|rep| rep.get()).collect());
+        let toplevels = sorted(
+            data.toplevels
+                .values()
+                .filter(|toplevel| !toplevel.initial_commit)
+                .map(|toplevel| toplevel.id.rep().get())
+                .collect(),
+        );
+        let layer_surfaces = sorted(data.layer_surfaces.keys().map(|rep| rep.get()).collect());
+        let popups = sorted(data.popups.keys().map(|rep| rep.get()).collect());
+
+        for rep in outputs {
+            funcs.wm().call_new_output(&mut *store, wm, Resource::new_own(rep))?;
+        }
+
+        for rep in seats {
+            funcs.wm().call_new_seat(&mut *store, wm, Resource::new_own(rep))?;
+        }
+
+        for rep in toplevels {
+            funcs.wm().call_new_toplevel(&mut *store, wm, Resource::new_own(rep))?;
+        }
+
+        for rep in layer_surfaces {
+            funcs
+                .wm()
+                .call_new_layer_surface(&mut *store, wm, Resource::new_own(rep))?;
+        }
+
+        for rep in popups {
+            funcs.wm().call_new_popup(&mut *store, wm, Resource::new_own(rep))?;
+        }
+
+        Ok((wm, funcs))
+    }
+
+    fn drag_started(&mut self, icon: Option<SnapshotInfo>, mime_types: Vec<String>) -> wasmtime::Result<()> {
+        let icon = icon.map(|icon| {
+            let id = icon.id;
+            let wm = self.store.data_mut();
+            wm.insert_id(id);
+            wm.snapshots.insert(id.rep(), icon);
+            Resource::new_own(id.rep().get())
+        });
+
+        self.funcs
+            .wm()
+            .call_drag_started(&mut self.store, self.wm, icon, &mime_types)
+    }
+
+    fn new_seat(&mut self, id: Id, name: String, capabilities: SeatCapabilities) -> wasmtime::Result<()> {
+        self.track_new_seat(id, name, capabilities);
+
+        let seat = Resource::new_own(id.rep().get());
+        self.funcs.wm().call_new_seat(&mut self.store, self.wm, seat)
+    }
+
+    fn track_new_seat(&mut self, id: Id, name: String, capabilities: SeatCapabilities) {
+        let wm = self.store.data_mut();
+        wm.insert_id(id);
+        wm.seats.insert(
+            id.rep(),
+            WmSeat {
+                id,
+                name,
+                capabilities,
+                modifiers: KeyModifiers::empty(),
+                pointer_focus: None,
+                pointer_position: None,
+                layout: 0,
+            },
+        );
+    }
+
+    fn seat_capabilities(&mut self, id: Id, capabilities: SeatCapabilities) -> wasmtime::Result<()> {
+        let seat = self.store.data_mut().get_seat(id)?;
+
+        if seat.capabilities == capabilities {
+            return Ok(());
+        }
+
+        seat.capabilities = capabilities;
+
+        // A seat which lost its keyboard or pointer no longer has modifiers or a pointer focus.
+        if !capabilities.contains(SeatCapabilities::KEYBOARD) {
+            seat.modifiers = KeyModifiers::empty();
+        }
+
+        if !capabilities.contains(SeatCapabilities::POINTER) {
+            seat.pointer_focus = None;
+        }
+
+        self.funcs
+            .wm()
+            .call_seat_capabilities_changed(&mut self.store, self.wm, id.rep().get(), capabilities)
+    }
+
+    fn removed_seat(&mut self, id: Id) -> wasmtime::Result<()> {
+        self.track_removed_seat(id);
+
+        self.funcs
+            .wm()
+            .call_removed_seat(&mut self.store, self.wm, id.rep().get())
+    }
+
+    fn track_removed_seat(&mut self, id: Id) {
+        let wm = self.store.data_mut();
+        wm.seats.remove(&id.rep());
+        wm.remove_id(id);
+    }
+
+    fn key(
+        &mut self,
+        seat: Id,
+        serial: u32,
+        time: u32,
+        sym: u32,
+        compose: Option<String>,
+        status: KeyStatus,
+    ) -> wasmtime::Result<()> {
+        let modifiers = self.store.data_mut().get_seat(seat)?.modifiers;
+        let state = self.store.data_mut();
+
+        // The switcher takes every key while it is open.
+        match state.switcher.key(modifiers, sym, status) {
+            SwitcherKey::Ignored => (),
+            SwitcherKey::Consumed => {
+                state.update_switcher();
+                let _ = state.sender.send(WmRequest::KeyFilter {
+                    serial,
+                    filter: KeyFilter::Drop,
+                });
+                return Ok(());
+            }
+            SwitcherKey::Cancelled => {
+                let _ = state.sender.send(WmRequest::HideSwitcher);
+                let _ = state.sender.send(WmRequest::KeyFilter {
+                    serial,
+                    filter: KeyFilter::Drop,
+                });
+                return Ok(());
+            }
+        }
+
+        let forward_unbound_keys = state.forward_unbound_keys;
+
+        let filter = match state.bindings.key(modifiers, sym, status) {
+            // The wm only uses bindings, so unbound keys go straight to the client.
+            KeyMatch::Unbound if forward_unbound_keys => KeyFilter::Forward,
+            KeyMatch::Unbound => self.funcs.wm().call_key(
+                &mut self.store,
+                self.wm,
+                seat.rep().get(),
+                time,
+                sym,
+                compose.as_deref(),
+                status,
+            )?,
+            KeyMatch::Bound => KeyFilter::Drop,
+            KeyMatch::Triggered(binding) => {
+                self.funcs
+                    .wm()
+                    .call_binding_triggered(&mut self.store, self.wm, seat.rep().get(), binding)?;
+                KeyFilter::Drop
+            }
+            KeyMatch::ModeChanged(mode) => {
+                self.funcs
+                    .wm()
+                    .call_binding_mode_changed(&mut self.store, self.wm, &mode)?;
+                let _ = self.store.data().sender.send(WmRequest::BindingModeChanged(mode));
+                KeyFilter::Drop
+            }
+        };
+
+        let _ = self.store.data().sender.send(WmRequest::KeyFilter { serial, filter });
+        Ok(())
+    }
+
+    fn keymap_changed(&mut self, layouts: Vec<String>) -> wasmtime::Result<()> {
+        self.store.data_mut().keyboard_layouts = layouts;
+        Ok(())
+    }
+
+    fn layout_changed(&mut self, seat: Id, index: u32) -> wasmtime::Result<()> {
+        self.track_layout_changed(seat, index)?;
+
+        self.funcs
+            .wm()
+            .call_layout_changed(&mut self.store, self.wm, seat.rep().get(), index)
+    }
+
+    fn track_layout_changed(&mut self, seat: Id, index: u32) -> wasmtime::Result<()> {
+        self.store.data_mut().get_seat(seat)?.layout = index;
+        Ok(())
+    }
+
+    fn switch_toggled(&mut self, seat: Id, time: u32, kind: SwitchKind, on: bool) -> wasmtime::Result<()> {
+        *self.store.data_mut().switch_mut(kind) = on;
+
+        self.funcs
+            .wm()
+            .call_switch_toggled(&mut self.store, self.wm, seat.rep().get(), time, kind, on)
+    }
+
+    fn set_session_locked(&mut self, locked: bool) -> wasmtime::Result<()> {
+        self.store.data_mut().session_locked = locked;
+
+        match locked {
+            true => self.funcs.wm().call_session_locked(&mut self.store, self.wm),
+            false => self.funcs.wm().call_session_unlocked(&mut self.store, self.wm),
+        }
+    }
+
+    fn key_modifiers(&mut self, seat: Id, modifiers: KeyModifiers) -> wasmtime::Result<()> {
+        self.store.data_mut().get_seat(seat)?.modifiers = modifiers;
+        let state = self.store.data_mut();
+
+        let was_open = state.switcher.is_open();
+        let selected = state.switcher.modifiers(modifiers);
+
+        if was_open && !state.switcher.is_open() {
+            let _ = state.sender.send(WmRequest::HideSwitcher);
+        }
+
+        self.funcs
+            .wm()
+            .call_key_modifiers(&mut self.store, self.wm, seat.rep().get(), modifiers)?;
+
+        if let Some(toplevel) = selected {
             self.funcs
                 .wm()
-                .call_update_toplevel(&mut self.store, self.wm, id.rep().get(), updates)
+                .call_switcher_selected(&mut self.store, self.wm, toplevel.rep().get())?;
+        }
+
+        Ok(())
+    }
+
+    fn pointer_motion(
+        &mut self,
+        seat: Id,
+        serial: u32,
+        time: u32,
+        focus: Option<Id>,
+        position: Point,
+        global: Point,
+    ) -> wasmtime::Result<()> {
+        let data = self.store.data_mut();
+        let wm_seat = data.get_seat(seat)?;
+        wm_seat.pointer_focus = focus;
+        wm_seat.pointer_position = Some(global);
+
+        for timer in data.clicks.motion(seat, None, global) {
+            data.cancel_timer(timer);
+        }
+
+        let triggered = data.edge_motion(seat, global);
+
+        let filter = self.funcs.wm().call_pointer_motion(
+            &mut self.store,
+            self.wm,
+            seat.rep().get(),
+            time,
+            focus_from_id(focus),
+            position,
+        )?;
+        let _ = self
+            .store
+            .data()
+            .sender
+            .send(WmRequest::PointerFilter { serial, filter });
+
+        self.edges_triggered(seat, triggered)
+    }
+
+    fn edges_triggered(&mut self, seat: Id, triggered: Vec<u32>) -> wasmtime::Result<()> {
+        for trigger in triggered {
+            self.funcs
+                .wm()
+                .call_edge_triggered(&mut self.store, self.wm, seat.rep().get(), trigger)?;
+        }
+
+        Ok(())
+    }
+
+    fn pointer_button(
+        &mut self,
+        seat: Id,
+        serial: u32,
+        time: u32,
+        button: u32,
+        state: ButtonState,
+        context: PointerContext,
+    ) -> wasmtime::Result<()> {
+        let modifiers = self.store.data_mut().get_seat(seat)?.modifiers;
+        let data = self.store.data_mut();
+
+        let filter = match data.bindings.pointer_button(modifiers, button, state, context) {
+            PointerMatch::Unbound => {
+                self.funcs
+                    .wm()
+                    .call_pointer_button(&mut self.store, self.wm, seat.rep().get(), time, button, state)?
+            }
+            PointerMatch::Bound => PointerFilter::Drop,
+            PointerMatch::Triggered(binding) => {
+                self.pointer_binding(seat, binding)?;
+                PointerFilter::Drop
+            }
+        };
+
+        let _ = self
+            .store
+            .data()
+            .sender
+            .send(WmRequest::PointerFilter { serial, filter });
+
+        self.pointer_click(seat, time, button, state, None)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn decoration_motion(
+        &mut self,
+        seat: Id,
+        toplevel: Id,
+        time: u32,
+        position: Point,
+        global: Point,
+        region: DecorationRegion,
+    ) -> wasmtime::Result<()> {
+        let data = self.store.data_mut();
+        let wm_seat = data.get_seat(seat)?;
+        // The decorations are part of the toplevel, so pointer bindings over them act on the toplevel.
+        wm_seat.pointer_focus = Some(toplevel);
+        // Presses on the decorations are counted at the global position like presses on surfaces.
+        wm_seat.pointer_position = Some(global);
+
+        for timer in data.clicks.motion(seat, None, global) {
+            data.cancel_timer(timer);
         }
+
+        // The title bar of a maximized toplevel touches the top edge of the output.
+        let triggered = data.edge_motion(seat, global);
+
+        self.funcs.wm().call_decoration_motion(
+            &mut self.store,
+            self.wm,
+            seat.rep().get(),
+            toplevel.rep().get(),
+            time,
+            position,
+            region,
+        )?;
+
+        self.edges_triggered(seat, triggered)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn decoration_button(
+        &mut self,
+        seat: Id,
+        toplevel: Id,
+        time: u32,
+        button: u32,
+        state: ButtonState,
+        position: Point,
+        region: DecorationRegion,
+    ) -> wasmtime::Result<()> {
+        let data = self.store.data_mut();
+        let wm_seat = data.get_seat(seat)?;
+        wm_seat.pointer_focus = Some(toplevel);
+        let modifiers = wm_seat.modifiers;
+
+        let context = match region {
+            DecorationRegion::TitleBar | DecorationRegion::Tab(_) => PointerContext::TITLEBAR,
+            DecorationRegion::Border(_) => PointerContext::BORDER,
+        };
+
+        // A bound button is not sent to the wm as a press of the decorations, like a bound button over a surface.
+        match data.bindings.pointer_button(modifiers, button, state, context) {
+            PointerMatch::Unbound => (),
+            PointerMatch::Bound => return Ok(()),
+            PointerMatch::Triggered(binding) => return self.pointer_binding(seat, binding),
+        }
+
+        if let (ButtonState::Pressed, DecorationRegion::Tab(tab)) = (state, region) {
+            self.store.data_mut().press_tab(toplevel, tab);
+        }
+
+        self.funcs.wm().call_decoration_button(
+            &mut self.store,
+            self.wm,
+            seat.rep().get(),
+            toplevel.rep().get(),
+            time,
+            button,
+            state,
+            position,
+            region,
+        )?;
+
+        self.pointer_click(seat, time, button, state, Some((toplevel, region)))
+    }
+
+    /// Detect click gestures from a pointer button, which may be on the decorations of a toplevel.
+    fn pointer_click(
+        &mut self,
+        seat: Id,
+        time: u32,
+        button: u32,
+        state: ButtonState,
+        decoration: Option<(Id, DecorationRegion)>,
+    ) -> wasmtime::Result<()> {
+        let wm_seat = self.store.data_mut().get_seat(seat)?;
+
+        match state {
+            ButtonState::Pressed => {
+                let press = Press {
+                    source: Source::Button(button),
+                    time,
+                    position: wm_seat.pointer_position.unwrap_or(Point { x: 0.0, y: 0.0 }),
+                    focus: decoration.map(|(toplevel, _)| toplevel).or(wm_seat.pointer_focus),
+                    decoration: decoration.map(|(_, region)| region),
+                };
+
+                self.click_pressed(seat, press)
+            }
+
+            ButtonState::Released => {
+                self.click_released(seat, Source::Button(button));
+                Ok(())
+            }
+        }
+    }
+
+    /// A button or touch point was pressed, which may complete a click gesture or start a long press.
+    fn click_pressed(&mut self, seat: Id, press: Press) -> wasmtime::Result<()> {
+        let data = self.store.data_mut();
+
+        let timer = data.clicks.long_press().map(|duration| {
+            let timer = data.alloc_timer();
+            let _ = data.sender.send(WmRequest::SetTimer { timer, duration });
+            timer
+        });
+
+        match data.clicks.press(seat, press, timer) {
+            Some(kind) => self.click_gesture(seat, press.time, kind, press),
+            None => Ok(()),
+        }
+    }
+
+    fn click_released(&mut self, seat: Id, source: Source) {
+        let data = self.store.data_mut();
+
+        if let Some(timer) = data.clicks.release(seat, source) {
+            data.cancel_timer(timer);
+        }
+    }
+
+    fn click_gesture(&mut self, seat: Id, time: u32, kind: ClickKind, press: Press) -> wasmtime::Result<()> {
+        let source = match press.source {
+            Source::Button(button) => ClickSource::Button(button),
+            Source::Touch(id) => ClickSource::Touch(id),
+        };
+
+        let gesture = ClickGesture {
+            kind,
+            source,
+            focus: focus_from_id(press.focus),
+            position: press.position,
+            decoration: press.decoration,
+        };
+
+        self.funcs
+            .wm()
+            .call_click_gesture(&mut self.store, self.wm, seat.rep().get(), time, gesture)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn pointer_axis(
+        &mut self,
+        seat: Id,
+        serial: u32,
+        time: u32,
+        horizontal: f64,
+        vertical: f64,
+        source: AxisSource,
+        context: PointerContext,
+    ) -> wasmtime::Result<()> {
+        let modifiers = self.store.data_mut().get_seat(seat)?.modifiers;
+        let data = self.store.data_mut();
+
+        let filter = match data.bindings.pointer_axis(modifiers, horizontal, vertical, context) {
+            PointerMatch::Unbound | PointerMatch::Bound => self.funcs.wm().call_pointer_axis(
+                &mut self.store,
+                self.wm,
+                seat.rep().get(),
+                time,
+                horizontal,
+                vertical,
+                source,
+            )?,
+            PointerMatch::Triggered(binding) => {
+                self.pointer_binding(seat, binding)?;
+                PointerFilter::Drop
+            }
+        };
+
+        let _ = self
+            .store
+            .data()
+            .sender
+            .send(WmRequest::PointerFilter { serial, filter });
+        Ok(())
+    }
+
+    fn gesture_begin(
+        &mut self,
+        seat: Id,
+        serial: u32,
+        time: u32,
+        kind: GestureKind,
+        fingers: u32,
+    ) -> wasmtime::Result<()> {
+        let filter =
+            self.funcs
+                .wm()
+                .call_gesture_begin(&mut self.store, self.wm, seat.rep().get(), time, kind, fingers)?;
+
+        let _ = self
+            .store
+            .data()
+            .sender
+            .send(WmRequest::PointerFilter { serial, filter });
+        Ok(())
+    }
+
+    fn pointer_binding(&mut self, seat: Id, binding: u32) -> wasmtime::Result<()> {
+        let toplevel = self
+            .store
+            .data_mut()
+            .get_seat(seat)?
+            .pointer_focus
+            .map(|id| id.rep().get());
+
+        self.funcs
+            .wm()
+            .call_pointer_binding(&mut self.store, self.wm, seat.rep().get(), binding, toplevel)
+    }
+
+    fn touch_down(&mut self, seat: Id, time: u32, id: i32, focus: Option<Id>, position: Point) -> wasmtime::Result<()> {
+        self.funcs.wm().call_touch_down(
+            &mut self.store,
+            self.wm,
+            seat.rep().get(),
+            time,
+            id,
+            focus_from_id(focus),
+            position,
+        )?;
+
+        let press = Press {
+            source: Source::Touch(id),
+            time,
+            position,
+            focus,
+            decoration: None,
+        };
+
+        self.click_pressed(seat, press)
+    }
+
+    fn touch_up(&mut self, seat: Id, time: u32, id: i32) -> wasmtime::Result<()> {
+        self.click_released(seat, Source::Touch(id));
+
+        self.funcs
+            .wm()
+            .call_touch_up(&mut self.store, self.wm, seat.rep().get(), time, id)
+    }
+
+    fn touch_motion(&mut self, seat: Id, time: u32, id: i32, position: Point) -> wasmtime::Result<()> {
+        let data = self.store.data_mut();
+
+        for timer in data.clicks.motion(seat, Some(Source::Touch(id)), position) {
+            data.cancel_timer(timer);
+        }
+
+        self.funcs
+            .wm()
+            .call_touch_motion(&mut self.store, self.wm, seat.rep().get(), time, id, position)
+    }
+
+    fn touch_cancel(&mut self, seat: Id) -> wasmtime::Result<()> {
+        let data = self.store.data_mut();
+
+        for timer in data.clicks.touch_cancel(seat) {
+            data.cancel_timer(timer);
+        }
+
+        self.funcs
+            .wm()
+            .call_touch_cancel(&mut self.store, self.wm, seat.rep().get())
+    }
+}
+
+fn focus_from_id(id: Option<Id>) -> Focus {
+    match id {
+        Some(id) => Focus::Toplevel(id.rep().get()),
+        None => Focus::None,
+    }
+}
+
+/// Objects owned by the wm which is being replaced.
+struct PreviousWm {
+    bindings: Bindings,
+    timers: HashSet<u32>,
+    edge_triggers: EdgeTriggers,
+    clicks: Clicks,
+    processes: HashSet<u32>,
+    workspaces: Vec<WmWorkspace>,
+    toplevel_groups: Vec<WmToplevelGroup>,
+    nodes: Vec<WmNode>,
+}
+
+impl PreviousWm {
+    fn take(state: &mut WmState) -> Self {
+        Self {
+            bindings: mem::replace(&mut state.bindings, Bindings::new()),
+            timers: mem::take(&mut state.timers),
+            edge_triggers: mem::take(&mut state.edge_triggers),
+            clicks: mem::take(&mut state.clicks),
+            processes: mem::take(&mut state.processes),
+            workspaces: state.workspaces.drain().map(|(_, workspace)| workspace).collect(),
+            toplevel_groups: state.toplevel_groups.drain().map(|(_, group)| group).collect(),
+            nodes: state.nodes.drain().map(|(_, node)| node).collect(),
+        }
+    }
+
+    /// Give the objects back to the previous wm after the new wm failed to start.
+    fn restore(self, state: &mut WmState) {
+        state.bindings = self.bindings;
+        state.timers = self.timers;
+        state.edge_triggers = self.edge_triggers;
+        state.clicks = self.clicks;
+        state.processes = self.processes;
+        state.workspaces = self
+            .workspaces
+            .into_iter()
+            .map(|workspace| (workspace.id.rep(), workspace))
+            .collect();
+        state.toplevel_groups = self
+            .toplevel_groups
+            .into_iter()
+            .map(|group| (group.id.rep(), group))
+            .collect();
+        state.nodes = self.nodes.into_iter().map(|node| (node.id.rep(), node)).collect();
+    }
+
+    /// Cancel the timers and destroy the workspaces, toplevel groups and scene nodes of the previous wm.
+    ///
+    /// Processes keep running, but the new wm is not told when they exit. Toplevels on a destroyed workspace are
+    /// no longer on a workspace, so the new wm places them again.
+    fn destroy(self, state: &mut WmState) {
+        for timer in self.timers {
+            let _ = state.sender.send(WmRequest::CancelTimer(timer));
+        }
+
+        for workspace in self.workspaces {
+            state.remove_id(workspace.id);
+
+            for toplevel in state.toplevels.values_mut() {
+                if toplevel.workspace == Some(workspace.id) {
+                    toplevel.workspace = None;
+                }
+            }
+
+            let _ = state.sender.send(WmRequest::DestroyWorkspace(workspace.id));
+        }
+
+        for group in self.toplevel_groups {
+            state.remove_id(group.id);
+            let _ = state.sender.send(WmRequest::DestroyToplevelGroup(group.id));
+        }
+
+        for node in self.nodes {
+            state.remove_id(node.id);
+            let _ = state.sender.send(WmRequest::DestroyNode(node.id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use crate::{
+        host::aerugo::wm::types::{DecorationMode, Features, ToplevelState},
+        Id, IdType, WmRequest, WmState, WmToplevel, WmWorkspace, DEFAULT_RENDER, NO_RULES,
+    };
+
+    use super::PreviousWm;
+
+    fn toplevel(rep: u32, workspace: Option<Id>) -> WmToplevel {
+        WmToplevel {
+            id: Id(NonZeroU32::new(rep).unwrap(), IdType::Toplevel),
+            initial_commit: false,
+            features: Features::empty(),
+            app_id: None,
+            title: None,
+            min_size: None,
+            max_size: None,
+            geometry: None,
+            restore_geometry: None,
+            parent: None,
+            state: ToplevelState::empty(),
+            decorations: DecorationMode::ClientSide,
+            preferred_decorations: None,
+            resize_edge: None,
+            workspace,
+            render: DEFAULT_RENDER,
+            rules: NO_RULES,
+            server_decorations: None,
+            unresponsive: false,
+            playing_audio: false,
+            prefers_variable_refresh: false,
+            scanout: false,
+        }
+    }
+
+    #[test]
+    fn reload_clears_destroyed_workspaces() {
+        let (sender, channel) = calloop::channel::channel();
+        let mut wm = WmState::new(sender, Default::default());
+        let workspace = Id(NonZeroU32::new(0x8000_0001).unwrap(), IdType::Workspace);
+
+        wm.insert_id(workspace);
+        wm.workspaces.insert(
+            workspace.rep(),
+            WmWorkspace {
+                id: workspace,
+                name: "1".into(),
+                output: None,
+                active: true,
+            },
+        );
+
+        for toplevel in [
+            toplevel(1, Some(workspace)),
+            toplevel(2, Some(workspace)),
+            toplevel(3, None),
+        ] {
+            wm.insert_id(toplevel.id);
+            wm.toplevels.insert(toplevel.id.rep(), toplevel);
+        }
+
+        // The new wm started, so the workspaces of the previous wm are destroyed.
+        PreviousWm::take(&mut wm).destroy(&mut wm);
+
+        assert!(wm.workspaces.is_empty());
+        assert!(wm.toplevels.values().all(|toplevel| toplevel.workspace.is_none()));
+        assert!(!wm.ids.contains_key(&workspace.rep()));
+        assert!(matches!(
+            channel.try_recv(),
+            Ok(WmRequest::DestroyWorkspace(id)) if id == workspace
+        ));
     }
 }
@@ -0,0 +1,72 @@
+//! Restarting crashed wms.
+//!
+//! A wm which traps is restarted with the same module. The state of a crashed wm cannot be serialized, so the
+//! restarted wm starts over and is told about every existing object. A wm which crashes again right after starting
+//! would be restarted forever, so the wm is only restarted a few times in a short window. After that the wm stays
+//! stopped until a new wm is loaded.
+
+use std::time::{Duration, Instant};
+
+/// The most restarts in [`RESTART_WINDOW`].
+pub const MAX_RESTARTS: usize = 3;
+
+/// The window in which crashes are counted.
+pub const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    /// When the wm crashed within the window.
+    crashes: Vec<Instant>,
+}
+
+impl Supervisor {
+    /// The wm crashed.
+    ///
+    /// Returns whether the wm may be restarted.
+    pub fn crashed(&mut self, now: Instant) -> bool {
+        self.crashes
+            .retain(|&crash| now.saturating_duration_since(crash) < RESTART_WINDOW);
+        self.crashes.push(now);
+        self.crashes.len() <= MAX_RESTARTS
+    }
+
+    /// A new wm was loaded, so crashes of the previous wm no longer count.
+    pub fn reset(&mut self) {
+        self.crashes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Supervisor, MAX_RESTARTS, RESTART_WINDOW};
+
+    #[test]
+    fn crash_loop_stops_restarts() {
+        let start = Instant::now();
+        let mut supervisor = Supervisor::default();
+
+        for crash in 0..MAX_RESTARTS {
+            assert!(supervisor.crashed(start + Duration::from_secs(crash as u64)));
+        }
+
+        assert!(!supervisor.crashed(start + Duration::from_secs(10)));
+
+        // Loading a new wm allows restarts again.
+        supervisor.reset();
+        assert!(supervisor.crashed(start + Duration::from_secs(11)));
+    }
+
+    #[test]
+    fn old_crashes_are_forgotten() {
+        let start = Instant::now();
+        let mut supervisor = Supervisor::default();
+
+        for crash in 0..MAX_RESTARTS {
+            assert!(supervisor.crashed(start + RESTART_WINDOW * crash as u32));
+        }
+
+        assert!(supervisor.crashed(start + RESTART_WINDOW * MAX_RESTARTS as u32));
+    }
+}
@@ -0,0 +1,301 @@
+//! Window switcher
+//!
+//! The runtime provides a built-in window switcher (alt-tab) so that small wms do not need to build their own
+//! overlay. Toplevels are ordered by how recently the wm focused them.
+//!
+//! While the modifiers of the switcher's key combo are held, pressing the key selects the next toplevel, or the
+//! previous toplevel if shift is also held. Releasing the modifiers closes the switcher and the wm is told which
+//! toplevel was selected. The wm decides whether to focus the selected toplevel. Pressing escape closes the
+//! switcher without selecting a toplevel.
+//!
+//! The release of a key is consumed only if its press was consumed, even if the switcher closed in between, so
+//! clients never see a release without a press or a press without a release.
+//!
+//! The display server draws the switcher while it is open.
+
+use crate::{
+    binding::{modifiers_match, KeyCombo},
+    host::aerugo::wm::types::{KeyModifiers, KeyStatus},
+    Id,
+};
+
+const KEY_ESCAPE: u32 = 0xff1b;
+
+/// The result of sending a key to the switcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitcherKey {
+    /// The key is not used by the switcher.
+    Ignored,
+
+    /// The switcher is open and the selection may have changed.
+    Consumed,
+
+    /// The switcher was closed without selecting a toplevel.
+    Cancelled,
+}
+
+#[derive(Debug, Default)]
+pub struct Switcher {
+    combo: Option<KeyCombo>,
+    /// The toplevels, with the most recently focused toplevel first.
+    mru: Vec<Id>,
+    /// The selected index into `mru` while the switcher is open.
+    selected: Option<usize>,
+    /// The keys whose press was consumed and which have not been released yet.
+    pressed: Vec<u32>,
+}
+
+impl Switcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable the switcher using the key combo, or disable the switcher.
+    ///
+    /// Returns [`true`] if the switcher was open and has been closed.
+    pub fn set_combo(&mut self, combo: Option<KeyCombo>) -> bool {
+        self.combo = combo;
+
+        match combo {
+            Some(_) => false,
+            None => self.selected.take().is_some(),
+        }
+    }
+
+    /// The key combo which opens the switcher, or [`None`] if the switcher is disabled.
+    pub fn combo(&self) -> Option<KeyCombo> {
+        self.combo
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.selected.is_some()
+    }
+
+    /// The toplevels in most recently focused order and the selected index while the switcher is open.
+    pub fn selection(&self) -> Option<(&[Id], usize)> {
+        self.selected.map(|selected| (self.mru.as_slice(), selected))
+    }
+
+    /// A toplevel was created. New toplevels are the least recently focused.
+    pub fn added(&mut self, id: Id) {
+        if !self.mru.contains(&id) {
+            self.mru.push(id);
+        }
+    }
+
+    /// The wm focused the toplevel.
+    pub fn focused(&mut self, id: Id) {
+        self.mru.retain(|&toplevel| toplevel != id);
+        self.mru.insert(0, id);
+    }
+
+    /// A toplevel was closed.
+    ///
+    /// The switcher is closed if no toplevels remain.
+    pub fn removed(&mut self, id: Id) {
+        let Some(index) = self.mru.iter().position(|&toplevel| toplevel == id) else {
+            return;
+        };
+
+        self.mru.remove(index);
+
+        if let Some(selected) = self.selected {
+            self.selected = match self.mru.len() {
+                0 => None,
+                _ if index < selected => Some(selected - 1),
+                len => Some(selected.min(len - 1)),
+            };
+        }
+    }
+
+    /// Handle a key event.
+    pub fn key(&mut self, modifiers: KeyModifiers, sym: u32, status: KeyStatus) -> SwitcherKey {
+        if !matches!(status, KeyStatus::Press) {
+            return match self.pressed.iter().position(|&key| key == sym) {
+                Some(index) => {
+                    self.pressed.swap_remove(index);
+                    SwitcherKey::Consumed
+                }
+                None => SwitcherKey::Ignored,
+            };
+        }
+
+        let result = self.press(modifiers, sym);
+
+        if result != SwitcherKey::Ignored && !self.pressed.contains(&sym) {
+            self.pressed.push(sym);
+        }
+
+        result
+    }
+
+    fn press(&mut self, modifiers: KeyModifiers, sym: u32) -> SwitcherKey {
+        let Some(combo) = self.combo else {
+            return SwitcherKey::Ignored;
+        };
+
+        let backwards = modifiers.contains(KeyModifiers::SHIFT) && !combo.modifiers.contains(KeyModifiers::SHIFT);
+
+        match self.selected {
+            // Every key is consumed while the switcher is open.
+            Some(_) if sym == KEY_ESCAPE => {
+                self.selected = None;
+                SwitcherKey::Cancelled
+            }
+            Some(selected) => {
+                if sym == combo.sym {
+                    self.selected = Some(self.step(selected, backwards));
+                }
+
+                SwitcherKey::Consumed
+            }
+
+            None => {
+                let shift = match backwards {
+                    true => KeyModifiers::SHIFT,
+                    false => KeyModifiers::empty(),
+                };
+
+                if sym != combo.sym || !modifiers_match(combo.modifiers, modifiers & !shift) || self.mru.is_empty() {
+                    return SwitcherKey::Ignored;
+                }
+
+                // The most recently focused toplevel is usually the focused toplevel, so start at the next one.
+                self.selected = Some(self.step(0, backwards));
+                SwitcherKey::Consumed
+            }
+        }
+    }
+
+    /// Handle a change of the keyboard modifiers.
+    ///
+    /// If the modifiers of the combo were released, the switcher is closed and the selected toplevel is returned.
+    pub fn modifiers(&mut self, modifiers: KeyModifiers) -> Option<Id> {
+        let combo = self.combo?;
+
+        if self.selected.is_none() || modifiers.contains(combo.modifiers) {
+            return None;
+        }
+
+        self.selected
+            .take()
+            .and_then(|selected| self.mru.get(selected).copied())
+    }
+
+    fn step(&self, selected: usize, backwards: bool) -> usize {
+        let len = self.mru.len();
+
+        match backwards {
+            true => (selected + len - 1) % len,
+            false => (selected + 1) % len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use crate::{
+        binding::KeyCombo,
+        host::aerugo::wm::types::{KeyModifiers, KeyStatus},
+        Id, IdType,
+    };
+
+    use super::{Switcher, SwitcherKey, KEY_ESCAPE};
+
+    const KEY_TAB: u32 = 0xff09;
+    const ALT: KeyModifiers = KeyModifiers::ALT;
+
+    fn toplevel(id: u32) -> Id {
+        Id(NonZeroU32::new(id).unwrap(), IdType::Toplevel)
+    }
+
+    fn switcher() -> Switcher {
+        let mut switcher = Switcher::new();
+        switcher.set_combo(Some(KeyCombo {
+            modifiers: ALT,
+            sym: KEY_TAB,
+        }));
+
+        for id in 1..=3 {
+            switcher.added(toplevel(id));
+        }
+
+        // Focus order: 3, 2, 1
+        switcher.focused(toplevel(2));
+        switcher.focused(toplevel(3));
+        switcher
+    }
+
+    #[test]
+    fn alt_tab_selects_previous_focus() {
+        let mut switcher = switcher();
+
+        assert_eq!(switcher.key(ALT, KEY_TAB, KeyStatus::Press), SwitcherKey::Consumed);
+        assert_eq!(switcher.key(ALT, KEY_TAB, KeyStatus::Release), SwitcherKey::Consumed);
+        assert_eq!(switcher.selection().map(|(_, selected)| selected), Some(1));
+
+        // Holding alt while releasing other modifiers does not close the switcher.
+        assert_eq!(switcher.modifiers(ALT), None);
+        assert_eq!(switcher.modifiers(KeyModifiers::empty()), Some(toplevel(2)));
+        assert!(!switcher.is_open());
+    }
+
+    #[test]
+    fn cycle_and_cancel() {
+        let mut switcher = switcher();
+
+        // Shift cycles backwards, wrapping to the least recently focused toplevel.
+        switcher.key(ALT | KeyModifiers::SHIFT, KEY_TAB, KeyStatus::Press);
+        assert_eq!(switcher.selection().map(|(_, selected)| selected), Some(2));
+        switcher.key(ALT, KEY_TAB, KeyStatus::Press);
+        assert_eq!(switcher.selection().map(|(_, selected)| selected), Some(0));
+
+        assert_eq!(switcher.key(ALT, KEY_ESCAPE, KeyStatus::Press), SwitcherKey::Cancelled);
+        assert_eq!(switcher.modifiers(KeyModifiers::empty()), None);
+
+        // Tab without alt is not used by the switcher.
+        assert_eq!(
+            switcher.key(KeyModifiers::empty(), KEY_TAB, KeyStatus::Press),
+            SwitcherKey::Ignored
+        );
+    }
+
+    #[test]
+    fn releases_paired_with_presses() {
+        const KEY_A: u32 = 0x61;
+        let mut switcher = switcher();
+
+        // A key held before the switcher opened is released in the client.
+        assert_eq!(switcher.key(ALT, KEY_A, KeyStatus::Press), SwitcherKey::Ignored);
+        switcher.key(ALT, KEY_TAB, KeyStatus::Press);
+        assert_eq!(switcher.key(ALT, KEY_A, KeyStatus::Release), SwitcherKey::Ignored);
+
+        // Alt is released before tab, which closes the switcher, but the release of tab is still consumed.
+        assert_eq!(switcher.modifiers(KeyModifiers::empty()), Some(toplevel(2)));
+        assert_eq!(
+            switcher.key(KeyModifiers::empty(), KEY_TAB, KeyStatus::Release),
+            SwitcherKey::Consumed
+        );
+        assert_eq!(
+            switcher.key(KeyModifiers::empty(), KEY_TAB, KeyStatus::Release),
+            SwitcherKey::Ignored
+        );
+    }
+
+    #[test]
+    fn closing_toplevels_while_open() {
+        let mut switcher = switcher();
+
+        switcher.key(ALT, KEY_TAB, KeyStatus::Press);
+        switcher.key(ALT, KEY_TAB, KeyStatus::Press);
+        // Order is 3, 2, 1 and toplevel 1 is selected.
+        switcher.removed(toplevel(3));
+        assert_eq!(switcher.selection().map(|(_, selected)| selected), Some(1));
+        switcher.removed(toplevel(1));
+        assert_eq!(switcher.selection().map(|(_, selected)| selected), Some(0));
+        switcher.removed(toplevel(2));
+        assert!(!switcher.is_open());
+    }
+}
@@ -0,0 +1,325 @@
+//! Text drawn by the display server
+//!
+//! Shaping and rasterizing text in wasm would mean every wm ships a font renderer and its own fonts. Instead the
+//! runtime draws text with fonts installed on the system, and the wm receives a texture it may use in a node or a
+//! title bar.
+//!
+//! Text is laid out on a single line with kerning. A character missing in the requested font is drawn with the
+//! first other font which has the character.
+
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use ab_glyph::{point, Font as _, FontArc, ScaleFont as _};
+
+use crate::host::aerugo::wm::types::{Color, Font, FontFamily, Texture};
+
+/// The largest font size in pixels.
+pub const MAX_TEXT_SIZE: f32 = 512.0;
+
+/// The widest texture of text in pixels.
+pub const MAX_TEXT_WIDTH: u32 = 8192;
+
+/// How deep font directories are searched.
+const MAX_FONT_DIR_DEPTH: usize = 4;
+
+/// Font files searched for in the font directories, in order of preference.
+const FONT_FILES: &[(FontFamily, bool, &str)] = &[
+    (FontFamily::SansSerif, false, "DejaVuSans.ttf"),
+    (FontFamily::SansSerif, true, "DejaVuSans-Bold.ttf"),
+    (FontFamily::Monospace, false, "DejaVuSansMono.ttf"),
+    (FontFamily::Monospace, true, "DejaVuSansMono-Bold.ttf"),
+    (FontFamily::SansSerif, false, "NotoSans-Regular.ttf"),
+    (FontFamily::SansSerif, true, "NotoSans-Bold.ttf"),
+    (FontFamily::Monospace, false, "NotoSansMono-Regular.ttf"),
+    (FontFamily::Monospace, true, "NotoSansMono-Bold.ttf"),
+    (FontFamily::SansSerif, false, "LiberationSans-Regular.ttf"),
+    (FontFamily::SansSerif, true, "LiberationSans-Bold.ttf"),
+    (FontFamily::Monospace, false, "LiberationMono-Regular.ttf"),
+    (FontFamily::Monospace, true, "LiberationMono-Bold.ttf"),
+];
+
+/// Why text could not be drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextError {
+    /// The font size is not between 0 and [`MAX_TEXT_SIZE`].
+    Size(f32),
+
+    /// The text is wider than [`MAX_TEXT_WIDTH`].
+    TooWide,
+
+    /// No fonts were found on the system.
+    NoFonts,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::Size(size) => write!(f, "invalid font size {size}"),
+            TextError::TooWide => write!(f, "text is wider than {MAX_TEXT_WIDTH} pixels"),
+            TextError::NoFonts => write!(f, "no fonts are installed"),
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+struct Face {
+    family: FontFamily,
+    bold: bool,
+    font: FontArc,
+}
+
+/// The fonts used to draw text.
+#[derive(Default)]
+pub struct FontStack {
+    faces: Vec<Face>,
+}
+
+impl fmt::Debug for FontStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FontStack").field("faces", &self.faces.len()).finish()
+    }
+}
+
+impl FontStack {
+    /// Load the fonts installed in the font directories of the system and the user.
+    pub fn system() -> Self {
+        let mut found = Vec::new();
+
+        for dir in font_dirs() {
+            find_fonts(&dir, 0, &mut found);
+        }
+
+        // The same font may be installed in several directories.
+        found.sort_by_key(|&(index, _)| index);
+        found.dedup_by_key(|&mut (index, _)| index);
+
+        let faces = found
+            .into_iter()
+            .filter_map(|(index, path)| {
+                let (family, bold, _) = FONT_FILES[index];
+                let font = fs::read(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|bytes| FontArc::try_from_vec(bytes).map_err(|err| err.to_string()));
+
+                match font {
+                    Ok(font) => Some(Face { family, bold, font }),
+                    Err(err) => {
+                        tracing::warn!(?path, %err, "Failed to load font");
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        tracing::debug!(fonts = faces.len(), "Loaded fonts");
+        Self { faces }
+    }
+
+    /// Draw a line of text.
+    ///
+    /// The size is the height of a line in pixels.
+    pub fn draw(&self, text: &str, font: Font, size: f32, color: &Color) -> Result<Texture, TextError> {
+        // Negated so NaN is rejected.
+        if !(size > 0.0 && size <= MAX_TEXT_SIZE) {
+            return Err(TextError::Size(size));
+        }
+
+        let fonts = self.fonts(font);
+        let Some(primary) = fonts.first() else {
+            return Err(TextError::NoFonts);
+        };
+
+        let primary = primary.as_scaled(size);
+        let ascent = primary.ascent();
+        let height = primary.height().ceil() as u32;
+
+        let mut glyphs = Vec::new();
+        let mut x = 0.0f32;
+        let mut previous = None;
+
+        for c in text.chars().filter(|c| !c.is_control()) {
+            let Some((index, id)) = fonts.iter().enumerate().find_map(|(index, font)| {
+                let id = font.glyph_id(c);
+                (id.0 != 0).then_some((index, id))
+            }) else {
+                continue;
+            };
+
+            let scaled = fonts[index].as_scaled(size);
+
+            // Kerning is only known between glyphs of the same font.
+            if let Some((previous_index, previous_id)) = previous {
+                if previous_index == index {
+                    x += scaled.kern(previous_id, id);
+                }
+            }
+
+            glyphs.push((index, id.with_scale_and_position(size, point(x, ascent))));
+            x += scaled.h_advance(id);
+            previous = Some((index, id));
+
+            if x > MAX_TEXT_WIDTH as f32 {
+                return Err(TextError::TooWide);
+            }
+        }
+
+        let width = x.max(0.0).ceil() as u32;
+        let mut coverage = vec![0.0f32; width as usize * height as usize];
+
+        for (index, glyph) in glyphs {
+            // Whitespace has no outline.
+            let Some(outlined) = fonts[index].outline_glyph(glyph) else {
+                continue;
+            };
+
+            let bounds = outlined.px_bounds();
+
+            outlined.draw(|glyph_x, glyph_y, glyph_coverage| {
+                let x = bounds.min.x as i64 + i64::from(glyph_x);
+                let y = bounds.min.y as i64 + i64::from(glyph_y);
+
+                if (0..i64::from(width)).contains(&x) && (0..i64::from(height)).contains(&y) {
+                    let pixel = &mut coverage[y as usize * width as usize + x as usize];
+                    *pixel = (*pixel + glyph_coverage).min(1.0);
+                }
+            });
+        }
+
+        let pixels = coverage
+            .into_iter()
+            .flat_map(|coverage| pixel(color, coverage))
+            .collect();
+
+        Ok(Texture { width, height, pixels })
+    }
+
+    /// The fonts to draw the text with, starting with the requested font.
+    fn fonts(&self, font: Font) -> Vec<&FontArc> {
+        let mut faces = self.faces.iter().collect::<Vec<_>>();
+
+        // The sort is stable, so fonts of the same rank stay in order of preference.
+        faces.sort_by_key(|face| (face.family != font.family, face.bold != font.bold));
+        faces.into_iter().map(|face| &face.font).collect()
+    }
+}
+
+/// A premultiplied ARGB8888 pixel of the color with the coverage.
+fn pixel(color: &Color, coverage: f32) -> [u8; 4] {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * coverage * 255.0).round() as u8;
+
+    // ARGB8888 is little endian.
+    [channel(color.b), channel(color.g), channel(color.r), channel(color.a)]
+}
+
+/// The `fonts` directories of `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`.
+fn font_dirs() -> Vec<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+
+    let data_dirs = env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+
+    data_home
+        .into_iter()
+        .chain(data_dirs.split(':').map(PathBuf::from))
+        .map(|dir| dir.join("fonts"))
+        .collect()
+}
+
+/// Find the known font files in the directory and its subdirectories.
+fn find_fonts(dir: &Path, depth: usize, found: &mut Vec<(usize, PathBuf)>) {
+    if depth > MAX_FONT_DIR_DEPTH {
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        match entry.file_type() {
+            Ok(ty) if ty.is_dir() => find_fonts(&path, depth + 1, found),
+
+            Ok(_) => {
+                let name = entry.file_name();
+                let index = FONT_FILES.iter().position(|&(_, _, file)| name == file);
+
+                if let Some(index) = index {
+                    found.push((index, path));
+                }
+            }
+
+            Err(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pixel, FontStack, TextError};
+    use crate::host::aerugo::wm::types::{Color, Font, FontFamily};
+
+    const WHITE: Color = Color {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    const SANS: Font = Font {
+        family: FontFamily::SansSerif,
+        bold: false,
+    };
+
+    #[test]
+    fn premultiplied_pixel() {
+        let orange = Color {
+            r: 1.0,
+            g: 0.5,
+            b: 0.0,
+            a: 1.0,
+        };
+
+        assert_eq!(pixel(&orange, 1.0), [0, 128, 255, 255]);
+        assert_eq!(pixel(&orange, 0.5), [0, 64, 128, 128]);
+        assert_eq!(pixel(&orange, 0.0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn draw() {
+        let fonts = FontStack::default();
+        assert_eq!(fonts.draw("Title", SANS, 16.0, &WHITE).unwrap_err(), TextError::NoFonts);
+
+        let fonts = FontStack::system();
+        assert_eq!(
+            fonts.draw("Title", SANS, f32::NAN, &WHITE).unwrap_err().to_string(),
+            "invalid font size NaN"
+        );
+
+        // Drawing needs fonts installed on the system.
+        if fonts.faces.is_empty() {
+            return;
+        }
+
+        let texture = fonts.draw("Title", SANS, 16.0, &WHITE).unwrap();
+        assert!(texture.width > 0 && texture.height >= 16);
+        assert_eq!(texture.pixels.len(), (texture.width * texture.height * 4) as usize);
+        assert!(texture.pixels.chunks(4).any(|pixel| pixel[3] == 255));
+
+        let empty = fonts.draw("", SANS, 16.0, &WHITE).unwrap();
+        assert_eq!((empty.width, empty.pixels.len()), (0, 0));
+
+        let long = "W".repeat(2000);
+        assert_eq!(fonts.draw(&long, SANS, 64.0, &WHITE).unwrap_err(), TextError::TooWide);
+    }
+}
@@ -1,8 +1,12 @@
 use std::collections::HashMap;
 
 use aerugo::wm::types::{
-    KeyFilter, KeyModifiers, KeyStatus, Output, OutputId, Server, Snapshot, Toplevel, ToplevelConfigure, ToplevelId,
-    ToplevelUpdates,
+    ActivationTokenInfo, AxisSource, BindingId, ButtonState, ClickGesture, ClickKind, Color, DecorationFill,
+    DecorationMode, DecorationRegion, Focus, Geometry, GestureKind, KeyFilter, KeyModifiers, KeyStatus, LayerSurface,
+    LayerSurfaceId, Output, OutputId, Point, PointerFilter, Popup, PopupId, Positioner, PositionerEdge, ProcessId,
+    ProcessStatus, Seat, SeatCapabilities, SeatId, SelectionKind, Server, ServerDecorations, Snapshot, StateRequest,
+    SurfaceNode, SwitchKind, TimerId, Toplevel, ToplevelConfigure, ToplevelId, ToplevelState, ToplevelUpdates,
+    TriggerId, Workspace, WorkspaceId,
 };
 use exports::aerugo::wm::wm_types::{Guest, GuestWm, WmInfo};
 use wit_bindgen::{rt::string::String, Resource};
@@ -11,6 +15,18 @@ use xkeysym::KeyCode;
 pub struct Wm {
     /// All known toplevels.
     toplevels: HashMap<ToplevelId, Toplevel>,
+
+    /// All known popups.
+    popups: HashMap<PopupId, Popup>,
+
+    /// All known layer surfaces.
+    layer_surfaces: HashMap<LayerSurfaceId, LayerSurface>,
+
+    /// All workspaces created by the wm.
+    workspaces: HashMap<WorkspaceId, Workspace>,
+
+    /// All known seats.
+    seats: HashMap<SeatId, Seat>,
 }
 
 impl Wm {
@@ -22,7 +38,9 @@ impl Wm {
         let id = toplevel.id();
         let toplevel = self.toplevels.entry(id).or_insert(toplevel);
 
-        let _configure = ToplevelConfigure::new(toplevel);
+        // The toplevel is shown once the client acks the initial configure. The minimal wm lets the client choose
+        // the size.
+        ToplevelConfigure::new(toplevel).submit();
     }
 
     fn closed_toplevel(&mut self, toplevel: ToplevelId) {
@@ -30,26 +48,210 @@ impl Wm {
         self.toplevels.remove(&toplevel);
     }
 
-    fn update_toplevel(&mut self, _toplevel: ToplevelId, _updates: ToplevelUpdates) {
-        todo!()
+    fn update_toplevel(&mut self, toplevel: ToplevelId, updates: ToplevelUpdates) {
+        let Some(toplevel) = self.toplevels.get(&toplevel) else {
+            return;
+        };
+
+        // Window rules would match the app id and title here. The minimal wm only honors the decoration mode the
+        // toplevel prefers.
+        if updates.contains(ToplevelUpdates::DECORATIONS) {
+            let decorations = toplevel.preferred_decorations().unwrap_or(DecorationMode::ClientSide);
+
+            // The display server draws nothing around server side decorated toplevels unless the wm declares the
+            // decorations.
+            toplevel.set_server_decorations(match decorations {
+                DecorationMode::ServerSide => Some(title_bar()),
+                DecorationMode::ClientSide => None,
+            });
+
+            let configure = ToplevelConfigure::new(toplevel);
+            configure.decorations(decorations);
+            configure.submit();
+        }
     }
 
     fn ack_toplevel(&mut self, _toplevel: ToplevelId, _serial: u32) {
-        todo!()
+        // The minimal wm does not wait for configures to be acked.
     }
 
-    fn committed_toplevel(&mut self, _toplevel: ToplevelId, _snapshot: Option<Snapshot>) {
+    fn committed_toplevel(
+        &mut self,
+        _toplevel: ToplevelId,
+        _snapshot: Option<Snapshot>,
+        _subsurfaces: Vec<SurfaceNode>,
+    ) {
         todo!()
     }
 
-    fn key(&mut self, _time: u32, _key_code: KeyCode, _compose: Option<String>, _status: KeyStatus) -> KeyFilter {
+    fn request_state(&mut self, toplevel: ToplevelId, request: StateRequest, _output: Option<OutputId>) {
+        let Some(toplevel) = self.toplevels.get(&toplevel) else {
+            return;
+        };
+
+        let configure = ToplevelConfigure::new(toplevel);
+
+        match request {
+            StateRequest::Maximize => configure.state(toplevel.state() | ToplevelState::MAXIMIZED),
+            StateRequest::Fullscreen => configure.state(toplevel.state() | ToplevelState::FULLSCREEN),
+            StateRequest::Unmaximize | StateRequest::Unfullscreen => {
+                configure.restore();
+            }
+            // The minimal wm cannot hide toplevels, but still responds with a configure.
+            StateRequest::Minimize => (),
+        }
+
+        configure.submit();
+    }
+
+    fn toplevel_unresponsive(&mut self, _toplevel: ToplevelId) {
+        // The minimal wm does not draw anything over toplevels.
+    }
+
+    fn toplevel_responsive(&mut self, _toplevel: ToplevelId) {}
+
+    fn toplevel_audio(&mut self, _toplevel: ToplevelId, _playing: bool) {}
+
+    fn scanout_state_changed(&mut self, _toplevel: ToplevelId, _scanout: bool) {}
+
+    fn activation_requested(&mut self, _toplevel: ToplevelId, _token: ActivationTokenInfo) {
+        // The minimal wm does not change the focus on its own.
+    }
+
+    fn new_popup(&mut self, popup: Popup) {
+        // The minimal wm does not apply any constraint adjustments.
+        popup.configure(popup_geometry(popup.positioner()), None);
+        self.popups.insert(popup.id(), popup);
+    }
+
+    fn closed_popup(&mut self, popup: PopupId) {
+        self.popups.remove(&popup);
+    }
+
+    fn popup_repositioned(&mut self, popup: PopupId, token: u32) {
+        if let Some(popup) = self.popups.get(&popup) {
+            popup.configure(popup_geometry(popup.positioner()), Some(token));
+        }
+    }
+
+    fn new_layer_surface(&mut self, layer_surface: LayerSurface) {
+        // The minimal wm gives the layer surface the size it asked for and does not stretch it between edges.
+        layer_surface.configure(layer_surface.state().size);
+        self.layer_surfaces.insert(layer_surface.id(), layer_surface);
+    }
+
+    fn closed_layer_surface(&mut self, layer_surface: LayerSurfaceId) {
+        self.layer_surfaces.remove(&layer_surface);
+    }
+
+    fn ack_layer_surface(&mut self, _layer_surface: LayerSurfaceId, _serial: u32) {}
+
+    fn layer_surface_exclusive_zone(&mut self, _layer_surface: LayerSurfaceId, _exclusive_zone: i32) {
+        // The minimal wm does not tile, so there is nothing to rearrange.
+    }
+
+    fn usable_area_changed(&mut self, _output: OutputId, _area: Geometry) {}
+
+    fn new_seat(&mut self, seat: Seat) {
+        self.seats.insert(seat.id(), seat);
+    }
+
+    fn removed_seat(&mut self, seat: SeatId) {
+        self.seats.remove(&seat);
+    }
+
+    fn seat_capabilities_changed(&mut self, _seat: SeatId, _capabilities: SeatCapabilities) {}
+
+    fn key(
+        &mut self,
+        _seat: SeatId,
+        _time: u32,
+        _key_code: KeyCode,
+        _compose: Option<String>,
+        _status: KeyStatus,
+    ) -> KeyFilter {
         todo!()
     }
 
-    fn key_modifiers(&mut self, __modifiers: KeyModifiers) {
+    fn key_modifiers(&mut self, _seat: SeatId, __modifiers: KeyModifiers) {
         todo!()
     }
 
+    fn layout_changed(&mut self, _seat: SeatId, _index: u32) {}
+
+    fn pointer_binding(&mut self, _seat: SeatId, _binding: BindingId, _toplevel: Option<ToplevelId>) {}
+
+    fn binding_mode_changed(&mut self, _mode: String) {}
+
+    fn binding_triggered(&mut self, _seat: SeatId, _binding: BindingId) {}
+
+    fn switcher_selected(&mut self, _toplevel: ToplevelId) {}
+
+    fn pointer_motion(&mut self, _seat: SeatId, _time: u32, _focus: Focus, _position: Point) -> PointerFilter {
+        PointerFilter::Forward
+    }
+
+    fn pointer_button(&mut self, _seat: SeatId, _time: u32, _button: u32, _state: ButtonState) -> PointerFilter {
+        PointerFilter::Forward
+    }
+
+    fn pointer_axis(
+        &mut self,
+        _seat: SeatId,
+        _time: u32,
+        _horizontal: f64,
+        _vertical: f64,
+        _source: AxisSource,
+    ) -> PointerFilter {
+        PointerFilter::Forward
+    }
+
+    fn touch_down(&mut self, _seat: SeatId, _time: u32, _id: i32, _focus: Focus, _position: Point) {}
+
+    fn touch_up(&mut self, _seat: SeatId, _time: u32, _id: i32) {}
+
+    fn touch_motion(&mut self, _seat: SeatId, _time: u32, _id: i32, _position: Point) {}
+
+    fn touch_cancel(&mut self, _seat: SeatId) {}
+
+    fn click_gesture(&mut self, _seat: SeatId, _time: u32, gesture: ClickGesture) {
+        // Double clicking the title bar toggles whether the toplevel is maximized.
+        let (ClickKind::DoubleClick, Some(DecorationRegion::TitleBar), Focus::Toplevel(id)) =
+            (gesture.kind, gesture.decoration, gesture.focus)
+        else {
+            return;
+        };
+
+        let Some(toplevel) = self.toplevels.get(&id) else {
+            return;
+        };
+
+        let configure = ToplevelConfigure::new(toplevel);
+
+        if toplevel.state().contains(ToplevelState::MAXIMIZED) {
+            configure.restore();
+        } else {
+            configure.state(toplevel.state() | ToplevelState::MAXIMIZED);
+        }
+
+        configure.submit();
+    }
+
+    fn gesture_begin(&mut self, _seat: SeatId, _time: u32, _kind: GestureKind, _fingers: u32) -> PointerFilter {
+        // The minimal wm does not use gestures, so clients receive them.
+        PointerFilter::Forward
+    }
+
+    fn gesture_update(&mut self, _seat: SeatId, _time: u32, _delta: Point, _scale: f64, _rotation: f64) {}
+
+    fn gesture_end(&mut self, _seat: SeatId, _time: u32, _cancelled: bool) {}
+
+    fn switch_toggled(&mut self, _seat: SeatId, _time: u32, _kind: SwitchKind, _on: bool) {}
+
+    fn session_locked(&mut self) {}
+
+    fn session_unlocked(&mut self) {}
+
     fn new_output(&mut self, __output: Output) {
         todo!()
     }
@@ -57,6 +259,131 @@ impl Wm {
     fn disconnect_output(&mut self, __output: OutputId) {
         todo!()
     }
+
+    fn frame(&mut self, _output: OutputId, _time: u32) {}
+
+    fn output_configured(&mut self, _output: OutputId, _serial: u32, _applied: bool) {}
+
+    fn scale_changed(&mut self, _output: OutputId, _scale: f64) {}
+
+    fn request_activate_workspace(&mut self, workspace: WorkspaceId) {
+        if let Some(workspace) = self.workspaces.get(&workspace) {
+            workspace.activate();
+        }
+    }
+
+    fn request_move_to_workspace(&mut self, _toplevel: ToplevelId, _workspace: WorkspaceId) {
+        // The minimal wm does not keep the server around to move the toplevel, so the request is ignored.
+    }
+
+    fn timer_expired(&mut self, _timer: TimerId) {}
+
+    fn edge_triggered(&mut self, _seat: SeatId, _trigger: TriggerId) {}
+
+    fn process_exited(&mut self, _process: ProcessId, _status: ProcessStatus) {}
+
+    fn serialize_state(&mut self) -> Vec<u8> {
+        // The minimal wm has no state worth keeping, the toplevels are announced to the new wm again.
+        Vec::new()
+    }
+
+    fn restore_state(&mut self, _state: Vec<u8>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn selection_changed(&mut self, _kind: SelectionKind, _mime_types: Vec<String>, _owner: Option<i32>) {
+        // The minimal wm does not manage the clipboard.
+    }
+
+    fn drag_started(&mut self, _icon: Option<Snapshot>, _mime_types: Vec<String>) {
+        // The minimal wm does not present a scene, so the drag icon is not drawn.
+    }
+
+    fn drag_motion(&mut self, _position: Point, _focus: Focus) {}
+
+    fn drag_dropped(&mut self, _position: Point, _focus: Focus) {}
+
+    fn drag_cancelled(&mut self) {}
+
+    fn decoration_motion(
+        &mut self,
+        _seat: SeatId,
+        _toplevel: ToplevelId,
+        _time: u32,
+        _position: Point,
+        _region: DecorationRegion,
+    ) {
+    }
+
+    fn decoration_button(
+        &mut self,
+        _seat: SeatId,
+        _toplevel: ToplevelId,
+        _time: u32,
+        _button: u32,
+        _state: ButtonState,
+        _position: Point,
+        _region: DecorationRegion,
+    ) {
+        // The minimal wm does not draw server side decorations.
+    }
+}
+
+/// A plain gray title bar and border for server side decorated toplevels.
+fn title_bar() -> ServerDecorations {
+    let gray = |value| Color {
+        r: value,
+        g: value,
+        b: value,
+        a: 1.0,
+    };
+
+    ServerDecorations {
+        title_bar_height: 24,
+        border_width: 1,
+        title_bar: DecorationFill::Color(gray(0.25)),
+        border: gray(0.15),
+    }
+}
+
+/// Place the popup at the anchor point, extending in the direction of the gravity.
+fn popup_geometry(positioner: Positioner) -> Geometry {
+    let rect = positioner.anchor_rect;
+    let (width, height) = (rect.width as i32, rect.height as i32);
+
+    let (anchor_x, anchor_y) = match positioner.anchor {
+        PositionerEdge::None => (rect.x + width / 2, rect.y + height / 2),
+        PositionerEdge::Top => (rect.x + width / 2, rect.y),
+        PositionerEdge::Bottom => (rect.x + width / 2, rect.y + height),
+        PositionerEdge::Left => (rect.x, rect.y + height / 2),
+        PositionerEdge::Right => (rect.x + width, rect.y + height / 2),
+        PositionerEdge::TopLeft => (rect.x, rect.y),
+        PositionerEdge::BottomLeft => (rect.x, rect.y + height),
+        PositionerEdge::TopRight => (rect.x + width, rect.y),
+        PositionerEdge::BottomRight => (rect.x + width, rect.y + height),
+    };
+
+    let size = positioner.size;
+    let (popup_width, popup_height) = (size.width as i32, size.height as i32);
+
+    let (x, y) = match positioner.gravity {
+        PositionerEdge::None => (anchor_x - popup_width / 2, anchor_y - popup_height / 2),
+        PositionerEdge::Top => (anchor_x - popup_width / 2, anchor_y - popup_height),
+        PositionerEdge::Bottom => (anchor_x - popup_width / 2, anchor_y),
+        PositionerEdge::Left => (anchor_x - popup_width, anchor_y - popup_height / 2),
+        PositionerEdge::Right => (anchor_x, anchor_y - popup_height / 2),
+        PositionerEdge::TopLeft => (anchor_x - popup_width, anchor_y - popup_height),
+        PositionerEdge::BottomLeft => (anchor_x - popup_width, anchor_y),
+        PositionerEdge::TopRight => (anchor_x, anchor_y - popup_height),
+        PositionerEdge::BottomRight => (anchor_x, anchor_y),
+    };
+
+    Geometry {
+        x: x + positioner.offset_x,
+        y: y + positioner.offset_y,
+        width: size.width,
+        height: size.height,
+    }
 }
 
 wit_bindgen::generate!({
@@ -105,16 +432,171 @@ impl GuestWm for WmImpl {
         self.0.borrow_mut().ack_toplevel(toplevel, serial);
     }
 
-    fn committed_toplevel(&self, toplevel: ToplevelId, snapshot: Option<Snapshot>) {
-        self.0.borrow_mut().committed_toplevel(toplevel, snapshot)
+    fn committed_toplevel(&self, toplevel: ToplevelId, snapshot: Option<Snapshot>, subsurfaces: Vec<SurfaceNode>) {
+        self.0.borrow_mut().committed_toplevel(toplevel, snapshot, subsurfaces)
+    }
+
+    fn request_state(&self, toplevel: ToplevelId, request: StateRequest, output: Option<OutputId>) {
+        self.0.borrow_mut().request_state(toplevel, request, output);
+    }
+
+    fn toplevel_unresponsive(&self, toplevel: ToplevelId) {
+        self.0.borrow_mut().toplevel_unresponsive(toplevel);
+    }
+
+    fn toplevel_responsive(&self, toplevel: ToplevelId) {
+        self.0.borrow_mut().toplevel_responsive(toplevel);
+    }
+
+    fn toplevel_audio(&self, toplevel: ToplevelId, playing: bool) {
+        self.0.borrow_mut().toplevel_audio(toplevel, playing);
+    }
+
+    fn scanout_state_changed(&self, toplevel: ToplevelId, scanout: bool) {
+        self.0.borrow_mut().scanout_state_changed(toplevel, scanout);
+    }
+
+    fn activation_requested(&self, toplevel: ToplevelId, token: ActivationTokenInfo) {
+        self.0.borrow_mut().activation_requested(toplevel, token);
+    }
+
+    fn new_popup(&self, popup: Popup) {
+        self.0.borrow_mut().new_popup(popup);
+    }
+
+    fn closed_popup(&self, popup: PopupId) {
+        self.0.borrow_mut().closed_popup(popup);
+    }
+
+    fn popup_repositioned(&self, popup: PopupId, token: u32) {
+        self.0.borrow_mut().popup_repositioned(popup, token);
+    }
+
+    fn new_layer_surface(&self, layer_surface: LayerSurface) {
+        self.0.borrow_mut().new_layer_surface(layer_surface);
+    }
+
+    fn closed_layer_surface(&self, layer_surface: LayerSurfaceId) {
+        self.0.borrow_mut().closed_layer_surface(layer_surface);
+    }
+
+    fn ack_layer_surface(&self, layer_surface: LayerSurfaceId, serial: u32) {
+        self.0.borrow_mut().ack_layer_surface(layer_surface, serial);
+    }
+
+    fn layer_surface_exclusive_zone(&self, layer_surface: LayerSurfaceId, exclusive_zone: i32) {
+        self.0
+            .borrow_mut()
+            .layer_surface_exclusive_zone(layer_surface, exclusive_zone);
+    }
+
+    fn usable_area_changed(&self, output: OutputId, area: Geometry) {
+        self.0.borrow_mut().usable_area_changed(output, area);
+    }
+
+    fn new_seat(&self, seat: Seat) {
+        self.0.borrow_mut().new_seat(seat);
+    }
+
+    fn removed_seat(&self, seat: SeatId) {
+        self.0.borrow_mut().removed_seat(seat);
+    }
+
+    fn seat_capabilities_changed(&self, seat: SeatId, capabilities: SeatCapabilities) {
+        self.0.borrow_mut().seat_capabilities_changed(seat, capabilities);
+    }
+
+    fn key(&self, seat: SeatId, time: u32, sym: u32, compose: Option<String>, status: KeyStatus) -> KeyFilter {
+        self.0.borrow_mut().key(seat, time, KeyCode::from(sym), compose, status)
+    }
+
+    fn key_modifiers(&self, seat: SeatId, modifiers: KeyModifiers) {
+        self.0.borrow_mut().key_modifiers(seat, modifiers)
+    }
+
+    fn layout_changed(&self, seat: SeatId, index: u32) {
+        self.0.borrow_mut().layout_changed(seat, index);
+    }
+
+    fn pointer_binding(&self, seat: SeatId, binding: BindingId, toplevel: Option<ToplevelId>) {
+        self.0.borrow_mut().pointer_binding(seat, binding, toplevel)
+    }
+
+    fn binding_mode_changed(&self, mode: String) {
+        self.0.borrow_mut().binding_mode_changed(mode)
+    }
+
+    fn binding_triggered(&self, seat: SeatId, binding: BindingId) {
+        self.0.borrow_mut().binding_triggered(seat, binding)
+    }
+
+    fn switcher_selected(&self, toplevel: ToplevelId) {
+        self.0.borrow_mut().switcher_selected(toplevel)
+    }
+
+    fn pointer_motion(&self, seat: SeatId, time: u32, focus: Focus, position: Point) -> PointerFilter {
+        self.0.borrow_mut().pointer_motion(seat, time, focus, position)
+    }
+
+    fn pointer_button(&self, seat: SeatId, time: u32, button: u32, state: ButtonState) -> PointerFilter {
+        self.0.borrow_mut().pointer_button(seat, time, button, state)
+    }
+
+    fn pointer_axis(
+        &self,
+        seat: SeatId,
+        time: u32,
+        horizontal: f64,
+        vertical: f64,
+        source: AxisSource,
+    ) -> PointerFilter {
+        self.0
+            .borrow_mut()
+            .pointer_axis(seat, time, horizontal, vertical, source)
     }
 
-    fn key(&self, time: u32, sym: u32, compose: Option<String>, status: KeyStatus) -> KeyFilter {
-        self.0.borrow_mut().key(time, KeyCode::from(sym), compose, status)
+    fn touch_down(&self, seat: SeatId, time: u32, id: i32, focus: Focus, position: Point) {
+        self.0.borrow_mut().touch_down(seat, time, id, focus, position);
     }
 
-    fn key_modifiers(&self, modifiers: KeyModifiers) {
-        self.0.borrow_mut().key_modifiers(modifiers)
+    fn touch_up(&self, seat: SeatId, time: u32, id: i32) {
+        self.0.borrow_mut().touch_up(seat, time, id);
+    }
+
+    fn touch_motion(&self, seat: SeatId, time: u32, id: i32, position: Point) {
+        self.0.borrow_mut().touch_motion(seat, time, id, position);
+    }
+
+    fn touch_cancel(&self, seat: SeatId) {
+        self.0.borrow_mut().touch_cancel(seat);
+    }
+
+    fn click_gesture(&self, seat: SeatId, time: u32, gesture: ClickGesture) {
+        self.0.borrow_mut().click_gesture(seat, time, gesture);
+    }
+
+    fn gesture_begin(&self, seat: SeatId, time: u32, kind: GestureKind, fingers: u32) -> PointerFilter {
+        self.0.borrow_mut().gesture_begin(seat, time, kind, fingers)
+    }
+
+    fn gesture_update(&self, seat: SeatId, time: u32, delta: Point, scale: f64, rotation: f64) {
+        self.0.borrow_mut().gesture_update(seat, time, delta, scale, rotation);
+    }
+
+    fn gesture_end(&self, seat: SeatId, time: u32, cancelled: bool) {
+        self.0.borrow_mut().gesture_end(seat, time, cancelled);
+    }
+
+    fn switch_toggled(&self, seat: SeatId, time: u32, kind: SwitchKind, on: bool) {
+        self.0.borrow_mut().switch_toggled(seat, time, kind, on);
+    }
+
+    fn session_locked(&self) {
+        self.0.borrow_mut().session_locked();
+    }
+
+    fn session_unlocked(&self) {
+        self.0.borrow_mut().session_unlocked();
     }
 
     fn new_output(&self, output: Output) {
@@ -124,4 +606,92 @@ impl GuestWm for WmImpl {
     fn disconnect_output(&self, output: OutputId) {
         self.0.borrow_mut().disconnect_output(output);
     }
+
+    fn frame(&self, output: OutputId, time: u32) {
+        self.0.borrow_mut().frame(output, time)
+    }
+
+    fn output_configured(&self, output: OutputId, serial: u32, applied: bool) {
+        self.0.borrow_mut().output_configured(output, serial, applied)
+    }
+
+    fn scale_changed(&self, output: OutputId, scale: f64) {
+        self.0.borrow_mut().scale_changed(output, scale)
+    }
+
+    fn request_activate_workspace(&self, workspace: WorkspaceId) {
+        self.0.borrow_mut().request_activate_workspace(workspace);
+    }
+
+    fn request_move_to_workspace(&self, toplevel: ToplevelId, workspace: WorkspaceId) {
+        self.0.borrow_mut().request_move_to_workspace(toplevel, workspace);
+    }
+
+    fn timer_expired(&self, timer: TimerId) {
+        self.0.borrow_mut().timer_expired(timer);
+    }
+
+    fn edge_triggered(&self, seat: SeatId, trigger: TriggerId) {
+        self.0.borrow_mut().edge_triggered(seat, trigger);
+    }
+
+    fn process_exited(&self, process: ProcessId, status: ProcessStatus) {
+        self.0.borrow_mut().process_exited(process, status);
+    }
+
+    fn serialize_state(&self) -> Vec<u8> {
+        self.0.borrow_mut().serialize_state()
+    }
+
+    fn restore_state(&self, state: Vec<u8>) -> Result<(), String> {
+        self.0.borrow_mut().restore_state(state)
+    }
+
+    fn selection_changed(&self, kind: SelectionKind, mime_types: Vec<String>, owner: Option<i32>) {
+        self.0.borrow_mut().selection_changed(kind, mime_types, owner)
+    }
+
+    fn drag_started(&self, icon: Option<Snapshot>, mime_types: Vec<String>) {
+        self.0.borrow_mut().drag_started(icon, mime_types)
+    }
+
+    fn drag_motion(&self, position: Point, focus: Focus) {
+        self.0.borrow_mut().drag_motion(position, focus)
+    }
+
+    fn drag_dropped(&self, position: Point, focus: Focus) {
+        self.0.borrow_mut().drag_dropped(position, focus)
+    }
+
+    fn drag_cancelled(&self) {
+        self.0.borrow_mut().drag_cancelled()
+    }
+
+    fn decoration_motion(
+        &self,
+        seat: SeatId,
+        toplevel: ToplevelId,
+        time: u32,
+        position: Point,
+        region: DecorationRegion,
+    ) {
+        self.0
+            .borrow_mut()
+            .decoration_motion(seat, toplevel, time, position, region)
+    }
+
+    fn decoration_button(
+        &self,
+        seat: SeatId,
+        toplevel: ToplevelId,
+        time: u32,
+        button: u32,
+        state: ButtonState,
+        position: Point,
+        region: DecorationRegion,
+    ) {
+        self.0
+            .borrow_mut()
+            .decoration_button(seat, toplevel, time, button, state, position, region)
+    }
 }